@@ -533,7 +533,10 @@ object ProtoUtil {
     payment = dd.payment.map { case DeployCode(code, args) => ipc.DeployCode(code, args) },
     gasLimit = dd.gasLimit,
     gasPrice = dd.gasPrice,
-    nonce = dd.nonce
+    nonce = dd.nonce,
+    // Deploys are authorized by the key of the user who signed them.
+    authorizationKeys = Seq(dd.user).filterNot(_.isEmpty),
+    deployHash = protoHash(dd)
   )
 
   def dependenciesHashesOf(b: BlockMessage): List[BlockHash] = {
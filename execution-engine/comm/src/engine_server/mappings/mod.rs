@@ -1,8 +1,16 @@
 mod uint;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::{TryFrom, TryInto};
 
+use common::bytesrepr::ToBytes;
+use common::value::abi::{Abi, AbiType, Arg, EntryPoint};
+use common::value::account::{
+    ActionThresholds, AssociatedKeys, KeyLimits, PublicKey, PurseId, Weight,
+};
+use common::value::U512;
+use execution_engine::approval::Approval;
+use execution_engine::auction::EraValidators;
 use execution_engine::engine::{Error as EngineError, ExecutionResult, RootNotFound};
 use execution_engine::execution::Error as ExecutionError;
 use ipc;
@@ -11,11 +19,6 @@ use storage::{
     global_state, history, history::CommitResult, op, transform, transform::TypeMismatch,
 };
 
-/// Helper method for turning instances of Value into Transform::Write.
-fn transform_write(v: common::value::Value) -> Result<transform::Transform, ParsingError> {
-    Ok(transform::Transform::Write(v))
-}
-
 #[derive(Debug)]
 pub struct ParsingError(pub String);
 
@@ -52,46 +55,23 @@ impl TryFrom<&super::ipc::Transform> for transform::Transform {
                 other => parse_error(format!("Through some impossibility a RustBigInt was turned into a non-uint value type: ${:?}", other))
             }
         } else if tr.has_write() {
-            let v = tr.get_write().get_value();
-            if v.has_integer() {
-                transform_write(common::value::Value::Int32(v.get_integer()))
-            } else if v.has_big_int() {
-                let b = v.get_big_int();
-                let v = b.try_into()?;
-                transform_write(v)
-            } else if v.has_byte_arr() {
-                let v: Vec<u8> = Vec::from(v.get_byte_arr());
-                transform_write(common::value::Value::ByteArray(v))
-            } else if v.has_int_list() {
-                let list = v.get_int_list().list.clone();
-                transform_write(common::value::Value::ListInt32(list))
-            } else if v.has_string_val() {
-                transform_write(common::value::Value::String(v.get_string_val().to_string()))
-            } else if v.has_account() {
-                let mut pub_key = [0u8; 32];
-                let uref_map: URefMap = v.get_account().get_known_urefs().try_into()?;
-                pub_key.clone_from_slice(&v.get_account().pub_key);
-                let account =
-                    common::value::Account::new(pub_key, v.get_account().nonce as u64, uref_map.0);
-                transform_write(common::value::Value::Account(account))
-            } else if v.has_contract() {
-                let ipc_contr = v.get_contract();
-                let contr_body = ipc_contr.get_body().to_vec();
-                let known_urefs: URefMap = ipc_contr.get_known_urefs().try_into()?;
-                transform_write(common::value::Contract::new(contr_body, known_urefs.0).into())
-            } else if v.has_string_list() {
-                let list = v.get_string_list().list.to_vec();
-                transform_write(common::value::Value::ListString(list))
-            } else if v.has_named_key() {
-                let nk = v.get_named_key();
-                let name = nk.get_name().to_string();
-                let key = nk.get_key().try_into()?;
-                transform_write(common::value::Value::NamedKey(name, key))
+            tr.get_write()
+                .get_value()
+                .try_into()
+                .map(transform::Transform::Write)
+        } else if tr.has_failure() {
+            let failure = tr.get_failure();
+            if failure.has_type_mismatch() {
+                let type_mismatch = failure.get_type_mismatch();
+                let error = TypeMismatch::new(
+                    type_mismatch.get_expected().to_string(),
+                    type_mismatch.get_found().to_string(),
+                );
+                Ok(transform::Transform::Failure(error.into()))
+            } else if failure.has_overflow() {
+                Ok(transform::Transform::Failure(transform::Error::Overflow))
             } else {
-                parse_error(format!(
-                    "TransformEntry write contained unknown value: {:?}",
-                    v
-                ))
+                parse_error("TransformFailure couldn't be parsed to known Error.".to_owned())
             }
         } else {
             parse_error("TransformEntry couldn't be parsed to known Transform.".to_owned())
@@ -99,6 +79,94 @@ impl TryFrom<&super::ipc::Transform> for transform::Transform {
     }
 }
 
+impl TryFrom<&super::ipc::Value> for common::value::Value {
+    type Error = ParsingError;
+    fn try_from(v: &super::ipc::Value) -> Result<common::value::Value, ParsingError> {
+        if v.has_integer() {
+            Ok(common::value::Value::Int32(v.get_integer()))
+        } else if v.has_big_int() {
+            v.get_big_int().try_into()
+        } else if v.has_byte_arr() {
+            let v: Vec<u8> = Vec::from(v.get_byte_arr());
+            Ok(common::value::Value::ByteArray(v))
+        } else if v.has_int_list() {
+            let list = v.get_int_list().list.clone();
+            Ok(common::value::Value::ListInt32(list))
+        } else if v.has_string_val() {
+            Ok(common::value::Value::String(v.get_string_val().to_string()))
+        } else if v.has_account() {
+            let mut pub_key = [0u8; 32];
+            let uref_map: URefMap = v.get_account().get_known_urefs().try_into()?;
+            pub_key.clone_from_slice(&v.get_account().pub_key);
+            let ipc_keys = v.get_account().get_associated_keys();
+            let associated_keys = if ipc_keys.is_empty() {
+                // The node sends accounts without their keys, which leaves
+                // them authorized by their own key.
+                AssociatedKeys::new(PublicKey::new(pub_key), Weight::new(1))
+            } else {
+                AssociatedKeysWrapper::try_from(ipc_keys)?.0
+            };
+            let purse_id = match v.get_account().get_purse_id().try_into()? {
+                common::key::Key::URef(addr, rights) => PurseId::new(addr, rights),
+                other => return parse_error(format!("Purse id has to be a URef, got {:?}", other)),
+            };
+            let action_thresholds: ActionThresholds =
+                v.get_account().get_action_thresholds().try_into()?;
+            let account = common::value::Account::new(
+                pub_key,
+                v.get_account().nonce as u64,
+                uref_map.0,
+                purse_id,
+                associated_keys,
+                action_thresholds,
+            );
+            Ok(common::value::Value::Account(account))
+        } else if v.has_contract() {
+            let ipc_contr = v.get_contract();
+            let contr_body = ipc_contr.get_body().to_vec();
+            let known_urefs: URefMap = ipc_contr.get_known_urefs().try_into()?;
+            let protocol_version = ipc_contr.get_protocol_version();
+            let contract =
+                common::value::Contract::new(contr_body, known_urefs.0, protocol_version);
+            if ipc_contr.has_abi() {
+                Ok(contract.with_abi(ipc_contr.get_abi().into()).into())
+            } else {
+                Ok(contract.into())
+            }
+        } else if v.has_contract_package() {
+            let ipc_package = v.get_contract_package();
+            let access_key = ipc_package.get_access_key().try_into()?;
+            let mut package = common::value::ContractPackage::new(access_key);
+            for version in ipc_package.get_versions() {
+                let contract_key = version.get_contract_key().try_into()?;
+                let number = package.add_version(version.get_major(), contract_key);
+                if version.get_disabled() {
+                    package.disable_version(number);
+                }
+            }
+            for group in ipc_package.get_groups() {
+                for key in group.get_keys() {
+                    package.add_group_key(group.get_name().to_string(), key.try_into()?);
+                }
+            }
+            if ipc_package.get_locked() {
+                package.lock();
+            }
+            Ok(common::value::Value::ContractPackage(package))
+        } else if v.has_string_list() {
+            let list = v.get_string_list().list.to_vec();
+            Ok(common::value::Value::ListString(list))
+        } else if v.has_named_key() {
+            let nk = v.get_named_key();
+            let name = nk.get_name().to_string();
+            let key = nk.get_key().try_into()?;
+            Ok(common::value::Value::NamedKey(name, key))
+        } else {
+            parse_error(format!("ipc Value couldn't be parsed to any Value: {:?}", v))
+        }
+    }
+}
+
 impl From<common::value::Value> for super::ipc::Value {
     fn from(v: common::value::Value) -> Self {
         let mut tv = super::ipc::Value::new();
@@ -138,18 +206,57 @@ impl From<common::value::Value> for super::ipc::Value {
                 let mut acc = super::ipc::Account::new();
                 acc.set_pub_key(account.pub_key().to_vec());
                 acc.set_nonce(account.nonce());
+                let associated_keys: Vec<super::ipc::Account_AssociatedKey> =
+                    account.associated_keys().into();
+                acc.set_associated_keys(protobuf::RepeatedField::from_vec(associated_keys));
+                acc.set_purse_id((&common::key::Key::from(account.purse_id())).into());
+                acc.set_action_thresholds(account.action_thresholds().into());
                 let urefs = URefMap(account.get_urefs_lookup()).into();
                 acc.set_known_urefs(protobuf::RepeatedField::from_vec(urefs));
                 tv.set_account(acc);
             }
             common::value::Value::Contract(contract) => {
-                let (bytes, known_urefs) = contract.destructure();
+                let (bytes, known_urefs, protocol_version, abi) = contract.destructure();
                 let mut contr = super::ipc::Contract::new();
                 let urefs = URefMap(known_urefs).into();
                 contr.set_body(bytes);
+                contr.set_protocol_version(protocol_version);
+                if let Some(abi) = abi {
+                    contr.set_abi((&abi).into());
+                }
                 contr.set_known_urefs(protobuf::RepeatedField::from_vec(urefs));
                 tv.set_contract(contr);
             }
+            common::value::Value::ContractPackage(package) => {
+                let mut ipc_package = super::ipc::ContractPackage::new();
+                ipc_package.set_access_key((&package.access_key()).into());
+                let versions: Vec<super::ipc::ContractPackage_Version> = package
+                    .versions()
+                    .iter()
+                    .map(|version| {
+                        let mut ipc_version = super::ipc::ContractPackage_Version::new();
+                        ipc_version.set_major(version.major());
+                        ipc_version.set_contract_key((&version.contract_key()).into());
+                        ipc_version.set_disabled(version.is_disabled());
+                        ipc_version
+                    })
+                    .collect();
+                ipc_package.set_versions(protobuf::RepeatedField::from_vec(versions));
+                let groups: Vec<super::ipc::ContractPackage_Group> = package
+                    .groups()
+                    .iter()
+                    .map(|(name, keys)| {
+                        let mut group = super::ipc::ContractPackage_Group::new();
+                        group.set_name(name.clone());
+                        let keys: Vec<super::ipc::Key> = keys.iter().map(Into::into).collect();
+                        group.set_keys(protobuf::RepeatedField::from_vec(keys));
+                        group
+                    })
+                    .collect();
+                ipc_package.set_groups(protobuf::RepeatedField::from_vec(groups));
+                ipc_package.set_locked(package.is_locked());
+                tv.set_contract_package(ipc_package);
+            }
         };
         tv
     }
@@ -246,6 +353,226 @@ impl From<URefMap> for Vec<super::ipc::NamedKey> {
     }
 }
 
+// newtype because trait impl have to be defined in the crate of the type.
+pub struct AssociatedKeysWrapper(AssociatedKeys);
+
+impl TryFrom<&[super::ipc::Account_AssociatedKey]> for AssociatedKeysWrapper {
+    type Error = ParsingError;
+    fn try_from(from: &[super::ipc::Account_AssociatedKey]) -> Result<Self, ParsingError> {
+        let mut keys = from.iter().map(|ipc_key| {
+            let pub_key: PublicKey = public_key_from_ipc(ipc_key.get_pub_key())?;
+            let weight = weight_from_ipc(ipc_key.get_weight())?;
+            Ok((pub_key, weight))
+        });
+        let (first_key, first_weight) = match keys.next() {
+            Some(first) => first?,
+            None => return parse_error("Account has no associated keys.".to_owned()),
+        };
+        let mut associated_keys = AssociatedKeys::new(first_key, first_weight);
+        // The number of keys is checked against the configured limits by
+        // `effects_from_ipc`.
+        let limits = KeyLimits::default().with_max_keys(usize::max_value());
+        for key in keys {
+            let (pub_key, weight) = key?;
+            if let Err(error) = associated_keys.add_key(pub_key, weight, &limits) {
+                return parse_error(format!("Invalid associated key {:?}: {}", pub_key, error));
+            }
+        }
+        Ok(AssociatedKeysWrapper(associated_keys))
+    }
+}
+
+impl From<&AssociatedKeys> for Vec<super::ipc::Account_AssociatedKey> {
+    fn from(associated_keys: &AssociatedKeys) -> Vec<super::ipc::Account_AssociatedKey> {
+        associated_keys
+            .iter()
+            .map(|(pub_key, weight)| {
+                let mut ipc_key = super::ipc::Account_AssociatedKey::new();
+                ipc_key.set_pub_key(pub_key.value().to_vec());
+                ipc_key.set_weight(u32::from(weight.value()));
+                ipc_key
+            })
+            .collect()
+    }
+}
+
+fn weight_from_ipc(weight: u32) -> Result<Weight, ParsingError> {
+    if weight > u32::from(u8::max_value()) {
+        parse_error(format!("Weight {} out of range", weight))
+    } else {
+        Ok(Weight::new(weight as u8))
+    }
+}
+
+impl TryFrom<&super::ipc::Account_ActionThresholds> for ActionThresholds {
+    type Error = ParsingError;
+    fn try_from(from: &super::ipc::Account_ActionThresholds) -> Result<Self, ParsingError> {
+        let deployment = weight_from_ipc(from.get_deployment_threshold())?;
+        let key_management = weight_from_ipc(from.get_key_management_threshold())?;
+        Ok(ActionThresholds::new(deployment, key_management))
+    }
+}
+
+impl From<&ActionThresholds> for super::ipc::Account_ActionThresholds {
+    fn from(action_thresholds: &ActionThresholds) -> super::ipc::Account_ActionThresholds {
+        let mut ipc_thresholds = super::ipc::Account_ActionThresholds::new();
+        ipc_thresholds.set_deployment_threshold(u32::from(action_thresholds.deployment().value()));
+        ipc_thresholds.set_key_management_threshold(u32::from(
+            action_thresholds.key_management().value(),
+        ));
+        ipc_thresholds
+    }
+}
+
+impl From<AbiType> for super::ipc::AbiType {
+    fn from(abi_type: AbiType) -> super::ipc::AbiType {
+        match abi_type {
+            AbiType::Unit => super::ipc::AbiType::UNIT,
+            AbiType::Int32 => super::ipc::AbiType::INT32,
+            AbiType::UInt128 => super::ipc::AbiType::UINT128,
+            AbiType::UInt256 => super::ipc::AbiType::UINT256,
+            AbiType::UInt512 => super::ipc::AbiType::UINT512,
+            AbiType::ByteArray => super::ipc::AbiType::BYTE_ARRAY,
+            AbiType::ListInt32 => super::ipc::AbiType::LIST_INT32,
+            AbiType::String => super::ipc::AbiType::STRING,
+            AbiType::ListString => super::ipc::AbiType::LIST_STRING,
+            AbiType::Key => super::ipc::AbiType::KEY,
+        }
+    }
+}
+
+impl From<super::ipc::AbiType> for AbiType {
+    fn from(abi_type: super::ipc::AbiType) -> AbiType {
+        match abi_type {
+            super::ipc::AbiType::UNIT => AbiType::Unit,
+            super::ipc::AbiType::INT32 => AbiType::Int32,
+            super::ipc::AbiType::UINT128 => AbiType::UInt128,
+            super::ipc::AbiType::UINT256 => AbiType::UInt256,
+            super::ipc::AbiType::UINT512 => AbiType::UInt512,
+            super::ipc::AbiType::BYTE_ARRAY => AbiType::ByteArray,
+            super::ipc::AbiType::LIST_INT32 => AbiType::ListInt32,
+            super::ipc::AbiType::STRING => AbiType::String,
+            super::ipc::AbiType::LIST_STRING => AbiType::ListString,
+            super::ipc::AbiType::KEY => AbiType::Key,
+        }
+    }
+}
+
+impl From<&Abi> for super::ipc::Abi {
+    fn from(abi: &Abi) -> super::ipc::Abi {
+        let entry_points: Vec<super::ipc::Abi_EntryPoint> = abi
+            .entry_points()
+            .iter()
+            .map(|entry_point| {
+                let args: Vec<super::ipc::Abi_Arg> = entry_point
+                    .args()
+                    .iter()
+                    .map(|arg| {
+                        let mut ipc_arg = super::ipc::Abi_Arg::new();
+                        ipc_arg.set_name(arg.name().to_string());
+                        ipc_arg.set_arg_type(arg.arg_type().into());
+                        ipc_arg
+                    })
+                    .collect();
+                let mut ipc_entry_point = super::ipc::Abi_EntryPoint::new();
+                ipc_entry_point.set_name(entry_point.name().to_string());
+                ipc_entry_point.set_args(protobuf::RepeatedField::from_vec(args));
+                ipc_entry_point.set_ret(entry_point.ret().into());
+                ipc_entry_point.set_groups(protobuf::RepeatedField::from_vec(
+                    entry_point.groups().to_vec(),
+                ));
+                ipc_entry_point
+            })
+            .collect();
+        let mut ipc_abi = super::ipc::Abi::new();
+        ipc_abi.set_entry_points(protobuf::RepeatedField::from_vec(entry_points));
+        ipc_abi
+    }
+}
+
+impl From<&super::ipc::Abi> for Abi {
+    fn from(ipc_abi: &super::ipc::Abi) -> Abi {
+        let entry_points = ipc_abi
+            .get_entry_points()
+            .iter()
+            .map(|entry_point| {
+                let args = entry_point
+                    .get_args()
+                    .iter()
+                    .map(|arg| Arg::new(arg.get_name().to_string(), arg.get_arg_type().into()))
+                    .collect();
+                EntryPoint::new(
+                    entry_point.get_name().to_string(),
+                    args,
+                    entry_point.get_ret().into(),
+                )
+                .with_groups(entry_point.get_groups().to_vec())
+            })
+            .collect();
+        Abi::new(entry_points)
+    }
+}
+
+/// Parses 32 bytes long public key sent over gRPC.
+pub fn public_key_from_ipc(bytes: &[u8]) -> Result<PublicKey, ParsingError> {
+    if bytes.len() != 32 {
+        return parse_error(format!(
+            "Public key has to be 32 bytes long, got {}",
+            bytes.len()
+        ));
+    }
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(bytes);
+    Ok(PublicKey::new(arr))
+}
+
+/// Parses 20 bytes long account address sent over gRPC.
+pub fn account_address_from_ipc(bytes: &[u8]) -> Result<[u8; 20], ParsingError> {
+    if bytes.len() != 20 {
+        return parse_error(format!(
+            "Account address has to be 20 bytes long, got {}",
+            bytes.len()
+        ));
+    }
+    let mut arr = [0u8; 20];
+    arr.copy_from_slice(bytes);
+    Ok(arr)
+}
+
+/// Parses 32 bytes long deploy hash sent over gRPC.
+pub fn deploy_hash_from_ipc(bytes: &[u8]) -> Result<[u8; 32], ParsingError> {
+    if bytes.len() != 32 {
+        return parse_error(format!(
+            "Deploy hash has to be 32 bytes long, got {}",
+            bytes.len()
+        ));
+    }
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(bytes);
+    Ok(arr)
+}
+
+pub fn transfer_addr_from_ipc(bytes: &[u8]) -> Result<[u8; 32], ParsingError> {
+    if bytes.len() != 32 {
+        return parse_error(format!(
+            "Transfer address has to be 32 bytes long, got {}",
+            bytes.len()
+        ));
+    }
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(bytes);
+    Ok(arr)
+}
+
+/// Parses an approval sent over gRPC. Its signature is only checked when the
+/// deploy is run.
+pub fn approval_from_ipc(approval: &ipc::Approval) -> Result<Approval, ParsingError> {
+    Ok(Approval {
+        public_key: public_key_from_ipc(approval.get_public_key())?,
+        signature: approval.get_signature().to_vec(),
+    })
+}
+
 impl From<&common::key::Key> for super::ipc::Key {
     fn from(key: &common::key::Key) -> super::ipc::Key {
         let mut k = super::ipc::Key::new();
@@ -268,6 +595,21 @@ impl From<&common::key::Key> for super::ipc::Key {
                 key_uref.set_uref(uref.to_vec());
                 k.set_uref(key_uref);
             }
+            common::key::Key::Local(hash) => {
+                let mut key_local = super::ipc::KeyLocal::new();
+                key_local.set_hash(hash.to_vec());
+                k.set_local(key_local);
+            }
+            common::key::Key::DeployInfo(deploy_hash) => {
+                let mut key_deploy_info = super::ipc::KeyDeployInfo::new();
+                key_deploy_info.set_deploy_hash(deploy_hash.to_vec());
+                k.set_deploy_info(key_deploy_info);
+            }
+            common::key::Key::Transfer(addr) => {
+                let mut key_transfer = super::ipc::KeyTransfer::new();
+                key_transfer.set_addr(addr.to_vec());
+                k.set_transfer(key_transfer);
+            }
         }
         k
     }
@@ -293,6 +635,16 @@ impl TryFrom<&super::ipc::Key> for common::key::Key {
                 arr,
                 common::key::AccessRights::READ_ADD_WRITE,
             ))
+        } else if ipc_key.has_local() {
+            let mut arr = [0u8; 32];
+            arr.clone_from_slice(&ipc_key.get_local().hash);
+            Ok(common::key::Key::Local(arr))
+        } else if ipc_key.has_deploy_info() {
+            let deploy_hash = deploy_hash_from_ipc(&ipc_key.get_deploy_info().deploy_hash)?;
+            Ok(common::key::Key::DeployInfo(deploy_hash))
+        } else if ipc_key.has_transfer() {
+            let addr = transfer_addr_from_ipc(&ipc_key.get_transfer().addr)?;
+            Ok(common::key::Key::Transfer(addr))
         } else {
             parse_error(format!(
                 "ipc Key couldn't be parsed to any Key: {:?}",
@@ -315,6 +667,37 @@ impl From<op::Op> for super::ipc::Op {
     }
 }
 
+impl TryFrom<&super::ipc::Op> for op::Op {
+    type Error = ParsingError;
+    fn try_from(ipc_op: &super::ipc::Op) -> Result<Self, ParsingError> {
+        if ipc_op.has_read() {
+            Ok(op::Op::Read)
+        } else if ipc_op.has_write() {
+            Ok(op::Op::Write)
+        } else if ipc_op.has_add() {
+            Ok(op::Op::Add)
+        } else if ipc_op.has_noop() {
+            Ok(op::Op::NoOp)
+        } else {
+            parse_error("ipc Op couldn't be parsed to any Op.".to_owned())
+        }
+    }
+}
+
+/// Transforms gRPC OpEntry into domain tuple of (Key, Op).
+impl TryFrom<&super::ipc::OpEntry> for (common::key::Key, op::Op) {
+    type Error = ParsingError;
+    fn try_from(from: &super::ipc::OpEntry) -> Result<Self, ParsingError> {
+        if !from.has_key() {
+            return parse_error("No key field in OpEntry".to_owned());
+        }
+        if !from.has_operation() {
+            return parse_error("No operation field in OpEntry".to_owned());
+        }
+        Ok((from.get_key().try_into()?, from.get_operation().try_into()?))
+    }
+}
+
 /// Transforms gRPC TransformEntry into domain tuple of (Key, Transform).
 impl TryFrom<&super::ipc::TransformEntry> for (common::key::Key, transform::Transform) {
     type Error = ParsingError;
@@ -333,6 +716,31 @@ impl TryFrom<&super::ipc::TransformEntry> for (common::key::Key, transform::Tran
     }
 }
 
+/// Parses the effects of a commit, refusing accounts with more associated
+/// keys than `key_limits` allow.
+pub fn effects_from_ipc(
+    entries: &[super::ipc::TransformEntry],
+    key_limits: &KeyLimits,
+) -> Result<HashMap<common::key::Key, transform::Transform>, ParsingError> {
+    let mut effects = HashMap::new();
+    for entry in entries {
+        let (key, operation): (common::key::Key, transform::Transform) = entry.try_into()?;
+        if let transform::Transform::Write(common::value::Value::Account(account)) = &operation {
+            let count = account.associated_keys().len();
+            if count > key_limits.max_keys() {
+                return parse_error(format!(
+                    "Account {:?} has {} associated keys, more than {}",
+                    key,
+                    count,
+                    key_limits.max_keys()
+                ));
+            }
+        }
+        effects.insert(key, operation);
+    }
+    Ok(effects)
+}
+
 impl From<global_state::ExecutionEffect> for super::ipc::ExecutionEffect {
     fn from(ee: global_state::ExecutionEffect) -> super::ipc::ExecutionEffect {
         let mut eff = super::ipc::ExecutionEffect::new();
@@ -358,12 +766,111 @@ impl From<global_state::ExecutionEffect> for super::ipc::ExecutionEffect {
                     tr_entry
                 })
                 .collect();
+        let ipc_events: Vec<super::ipc::Event> =
+            ee.2.into_iter()
+                .map(|event| {
+                    let mut ipc_event = super::ipc::Event::new();
+                    ipc_event.set_topic(event.topic);
+                    ipc_event.set_data(event.data.into());
+                    ipc_event
+                })
+                .collect();
         eff.set_op_map(protobuf::RepeatedField::from_vec(ipc_ops));
         eff.set_transform_map(protobuf::RepeatedField::from_vec(ipc_tran));
+        eff.set_events(protobuf::RepeatedField::from_vec(ipc_events));
         eff
     }
 }
 
+impl TryFrom<&super::ipc::ExecutionEffect> for global_state::ExecutionEffect {
+    type Error = ParsingError;
+    fn try_from(
+        ipc_effect: &super::ipc::ExecutionEffect,
+    ) -> Result<global_state::ExecutionEffect, ParsingError> {
+        let ops = ipc_effect
+            .get_op_map()
+            .iter()
+            .map(TryInto::try_into)
+            .collect::<Result<HashMap<common::key::Key, op::Op>, ParsingError>>()?;
+        let transforms = ipc_effect
+            .get_transform_map()
+            .iter()
+            .map(TryInto::try_into)
+            .collect::<Result<HashMap<common::key::Key, transform::Transform>, ParsingError>>()?;
+        let events = ipc_effect
+            .get_events()
+            .iter()
+            .map(|ipc_event| {
+                ipc_event
+                    .get_data()
+                    .try_into()
+                    .map(|data| global_state::Event {
+                        topic: ipc_event.get_topic().to_string(),
+                        data,
+                    })
+            })
+            .collect::<Result<Vec<global_state::Event>, ParsingError>>()?;
+        Ok(global_state::ExecutionEffect(ops, transforms, events))
+    }
+}
+
+impl From<&BTreeMap<common::key::Key, U512>> for ipc::BalancesResponse_Balances {
+    fn from(balances: &BTreeMap<common::key::Key, U512>) -> ipc::BalancesResponse_Balances {
+        let balances = balances
+            .iter()
+            .map(|(purse, balance)| {
+                let mut purse_balance = ipc::PurseBalance::new();
+                purse_balance.set_purse(purse.into());
+                purse_balance.set_balance((*balance).into());
+                purse_balance
+            })
+            .collect();
+        let mut result = ipc::BalancesResponse_Balances::new();
+        result.set_balances(protobuf::RepeatedField::from_vec(balances));
+        result
+    }
+}
+
+fn validator_weights(
+    weights: &BTreeMap<PublicKey, U512>,
+) -> protobuf::RepeatedField<ipc::ValidatorWeight> {
+    let validator_weights = weights
+        .iter()
+        .map(|(public_key, weight)| {
+            let mut validator_weight = ipc::ValidatorWeight::new();
+            validator_weight.set_public_key(public_key.value().to_vec());
+            validator_weight.set_weight((*weight).into());
+            validator_weight
+        })
+        .collect();
+    protobuf::RepeatedField::from_vec(validator_weights)
+}
+
+impl From<&BTreeMap<PublicKey, U512>> for ipc::BondedValidatorsResponse_Validators {
+    fn from(weights: &BTreeMap<PublicKey, U512>) -> ipc::BondedValidatorsResponse_Validators {
+        let mut result = ipc::BondedValidatorsResponse_Validators::new();
+        result.set_validators(validator_weights(weights));
+        result
+    }
+}
+
+impl From<&EraValidators> for ipc::EraValidatorsResponse_Eras {
+    fn from(era_validators: &EraValidators) -> ipc::EraValidatorsResponse_Eras {
+        let eras = era_validators
+            .iter()
+            .map(|(era_id, weights)| {
+                let mut era = ipc::EraValidators::new();
+                era.set_era_id(*era_id);
+                era.set_validators(validator_weights(weights));
+                era
+            })
+            .collect();
+        let mut result = ipc::EraValidatorsResponse_Eras::new();
+        result.set_eras(protobuf::RepeatedField::from_vec(eras));
+        result
+    }
+}
+
 impl From<RootNotFound> for ipc::RootNotFound {
     fn from(err: RootNotFound) -> ipc::RootNotFound {
         let RootNotFound(missing_root_hash) = err;
@@ -385,22 +892,27 @@ impl From<TypeMismatch> for ipc::TypeMismatch {
 
 impl From<ExecutionResult> for ipc::DeployResult {
     fn from(er: ExecutionResult) -> ipc::DeployResult {
-        match er {
-            ExecutionResult {
-                result: Ok(effects),
-                cost,
-            } => {
+        let ExecutionResult {
+            result,
+            cost,
+            payment_cost,
+            fee_effect,
+            transfers,
+            journal,
+        } = er;
+        // Clients are told the costs as plain numbers of gas.
+        let (cost, payment_cost) = (cost.as_u64(), payment_cost.as_u64());
+        let mut deploy_result = match result {
+            Ok(effects) => {
                 let mut ipc_ee = effects.into();
                 let mut deploy_result = ipc::DeployResult::new();
                 deploy_result.set_effects(ipc_ee);
                 deploy_result.set_cost(cost);
                 deploy_result
             }
-            ExecutionResult {
-                result: Err(err),
-                cost,
-            } => {
-                match err {
+            Err(err) => {
+                let code = err.code();
+                let mut deploy_result = match err {
                     // TODO(mateusz.gorski): Fix error model for the storage errors.
                     // We don't have separate IPC messages for storage errors
                     // so for the time being they are all reported as "wasm errors".
@@ -423,6 +935,16 @@ impl From<ExecutionResult> for ipc::DeployResult {
                             deploy_result.set_cost(cost);
                             deploy_result
                         }
+                        ExecutionError::Revert(status) => {
+                            let mut deploy_result = ipc::DeployResult::new();
+                            let mut deploy_error = ipc::DeployError::new();
+                            let mut revert_error = ipc::RevertError::new();
+                            revert_error.set_status(status);
+                            deploy_error.set_revertErr(revert_error);
+                            deploy_result.set_error(deploy_error);
+                            deploy_result.set_cost(cost);
+                            deploy_result
+                        }
                         ExecutionError::KeyNotFound(key) => {
                             let msg = format!("Key {:?} not found.", key);
                             wasm_error(msg)
@@ -435,10 +957,53 @@ impl From<ExecutionResult> for ipc::DeployResult {
                             err
                         }
                     },
+                    EngineError::EmptyAuthorizationKeys
+                    | EngineError::AccountNotFound(_)
+                    | EngineError::UnknownAuthorizationKey(_)
+                    | EngineError::InsufficientPayment
+                    | EngineError::GenesisError(_)
+                    | EngineError::UpgradeError(_) => {
+                        let mut err = wasm_error(err.to_string());
+                        err.set_cost(cost);
+                        err
+                    }
+                    EngineError::PaymentError(error) => {
+                        let mut err = wasm_error(format!("Payment code failed: {:?}", error));
+                        err.set_cost(cost);
+                        err
+                    }
+                    EngineError::Precondition(error) => {
+                        let mut deploy_result = ipc::DeployResult::new();
+                        let mut deploy_error = ipc::DeployError::new();
+                        let mut precondition_error = ipc::PreconditionError::new();
+                        precondition_error.set_message(error.to_string());
+                        deploy_error.set_preconditionErr(precondition_error);
+                        deploy_result.set_error(deploy_error);
+                        deploy_result.set_cost(cost);
+                        deploy_result
+                    }
                     EngineError::Unreachable => panic!("Reached unreachable."),
-                }
+                };
+                deploy_result.mut_error().set_code(code.into());
+                deploy_result
             }
+        };
+        deploy_result.set_payment_cost(payment_cost);
+        // The node commits the effects of deploys, so a failed session which
+        // is charged for reports the fees as its effects and its error apart.
+        if let Some(fee_effect) = fee_effect {
+            let session_error = deploy_result.take_error();
+            deploy_result.set_effects(fee_effect.into());
+            deploy_result.set_session_error(session_error);
         }
+        let transfers = transfers.iter().map(|addr| addr.to_vec()).collect();
+        deploy_result.set_transfers(transfers);
+        // Journals only fail to serialize if they don't fit in memory, in
+        // which case the node goes without.
+        if let Some(Ok(journal)) = journal.map(|journal| journal.to_bytes()) {
+            deploy_result.set_journal(journal);
+        }
+        deploy_result
     }
 }
 
@@ -494,7 +1059,7 @@ where
     }
 }
 
-fn wasm_error(msg: String) -> ipc::DeployResult {
+pub fn wasm_error(msg: String) -> ipc::DeployResult {
     let mut deploy_result = ipc::DeployResult::new();
     let mut deploy_error = ipc::DeployError::new();
     let mut err = ipc::WasmError::new();
@@ -506,14 +1071,27 @@ fn wasm_error(msg: String) -> ipc::DeployResult {
 
 #[cfg(test)]
 mod tests {
-    use super::wasm_error;
-    use common::key::Key;
+    use super::{account_address_from_ipc, deploy_hash_from_ipc, effects_from_ipc, wasm_error};
+    use common::bytesrepr::deserialize;
+    use common::key::{AccessRights, Key};
+    use common::value::abi::{Abi, AbiType, Arg, EntryPoint};
+    use common::value::account::{
+        ActionThresholds, AssociatedKeys, KeyLimits, PublicKey, PurseId, Weight,
+    };
+    use common::value::{Account, Contract, ContractPackage, Value, U512};
+    use execution_engine::approval::ApprovalError;
+    use execution_engine::deploy_info::PreconditionError;
     use execution_engine::engine::{Error as EngineError, ExecutionResult, RootNotFound};
+    use execution_engine::error_code::ErrorCode;
+    use execution_engine::execution::Error as ExecutionError;
+    use execution_engine::journal::{DeployJournal, JournalEntry};
+    use shared::gas::Gas;
     use shared::newtypes::Blake2bHash;
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
     use std::convert::TryInto;
-    use storage::global_state::ExecutionEffect;
-    use storage::transform::Transform;
+    use storage::global_state::{Event, ExecutionEffect};
+    use storage::op::Op;
+    use storage::transform::{Error as TransformError, Transform, TypeMismatch};
 
     // Test that wasm_error function actually returns DeployResult with result set to WasmError
     #[test]
@@ -528,6 +1106,20 @@ mod tests {
         assert_eq!(ipc_error_msg, error_msg);
     }
 
+    #[test]
+    fn deploy_hash_must_be_32_bytes_long() {
+        assert_eq!(deploy_hash_from_ipc(&[7u8; 32]).ok(), Some([7u8; 32]));
+        assert!(deploy_hash_from_ipc(&[]).is_err());
+        assert!(deploy_hash_from_ipc(&[7u8; 33]).is_err());
+    }
+
+    #[test]
+    fn account_address_must_be_20_bytes_long() {
+        assert_eq!(account_address_from_ipc(&[7u8; 20]).ok(), Some([7u8; 20]));
+        assert!(account_address_from_ipc(&[]).is_err());
+        assert!(account_address_from_ipc(&[7u8; 32]).is_err());
+    }
+
     #[test]
     fn deploy_result_to_ipc_missing_root() {
         let root_hash: Blake2bHash = [1u8; 32].into();
@@ -544,9 +1136,10 @@ mod tests {
             tmp_map
         };
         let execution_effect: ExecutionEffect =
-            ExecutionEffect(HashMap::new(), input_transforms.clone());
+            ExecutionEffect(HashMap::new(), input_transforms.clone(), Vec::new());
         let cost: u64 = 123;
-        let execution_result: ExecutionResult = ExecutionResult::success(execution_effect, cost);
+        let execution_result: ExecutionResult =
+            ExecutionResult::success(execution_effect, Gas::from(cost));
         let mut ipc_deploy_result: super::ipc::DeployResult = execution_result.into();
         assert_eq!(ipc_deploy_result.get_cost(), cost);
 
@@ -563,8 +1156,176 @@ mod tests {
         assert_eq!(&input_transforms, &ipc_transforms);
     }
 
+    #[test]
+    fn deploy_result_to_ipc_commits_fees_of_failed_sessions() {
+        let fee_transforms: HashMap<Key, Transform> = {
+            let mut tmp_map = HashMap::new();
+            let rewards_purse = Key::URef([2u8; 32], AccessRights::READ_ADD_WRITE);
+            tmp_map.insert(rewards_purse, Transform::AddUInt512(U512::from(30)));
+            tmp_map
+        };
+        let fee_effect = ExecutionEffect(HashMap::new(), fee_transforms.clone(), Vec::new());
+        let execution_result =
+            ExecutionResult::failure(ExecutionError::GasLimit.into(), Gas::from(30))
+                .with_payment_cost(Gas::from(10))
+                .with_fee_effect(fee_effect);
+        let mut ipc_deploy_result: super::ipc::DeployResult = execution_result.into();
+        assert!(ipc_deploy_result.get_session_error().has_gasErr());
+        assert_eq!(ipc_deploy_result.get_cost(), 30);
+        assert_eq!(ipc_deploy_result.get_payment_cost(), 10);
+
+        let ipc_transforms: HashMap<Key, Transform> = ipc_deploy_result
+            .take_effects()
+            .take_transform_map()
+            .into_vec()
+            .iter()
+            .map(|e| e.try_into())
+            .collect::<Result<HashMap<Key, Transform>, _>>()
+            .unwrap();
+        assert_eq!(&fee_transforms, &ipc_transforms);
+    }
+
+    #[test]
+    fn contract_package_to_ipc_roundtrip() {
+        let mut package = ContractPackage::new(Key::URef([1u8; 32], AccessRights::READ_ADD_WRITE));
+        package.add_version(1, Key::Hash([2u8; 32]));
+        package.add_version(2, Key::Hash([3u8; 32]));
+        package.disable_version(1);
+        package.add_group_key("minters".to_string(), Key::URef([5u8; 32], AccessRights::READ));
+        package.lock();
+        let input_transforms: HashMap<Key, Transform> = {
+            let mut tmp_map = HashMap::new();
+            tmp_map.insert(
+                Key::Hash([4u8; 32]),
+                Transform::Write(Value::ContractPackage(package)),
+            );
+            tmp_map
+        };
+        let execution_effect: ExecutionEffect =
+            ExecutionEffect(HashMap::new(), input_transforms.clone(), Vec::new());
+        let execution_result: ExecutionResult =
+            ExecutionResult::success(execution_effect, Gas::default());
+        let mut ipc_deploy_result: super::ipc::DeployResult = execution_result.into();
+
+        let ipc_transforms: HashMap<Key, Transform> = ipc_deploy_result
+            .take_effects()
+            .take_transform_map()
+            .into_vec()
+            .iter()
+            .map(|e| e.try_into())
+            .collect::<Result<HashMap<Key, Transform>, _>>()
+            .unwrap();
+        assert_eq!(&input_transforms, &ipc_transforms);
+    }
+
+    #[test]
+    fn accounts_without_keys_are_authorized_by_their_own_key() {
+        let purse_id = PurseId::new([8u8; 32], AccessRights::READ_ADD_WRITE);
+        let account = Account::create([7u8; 32], 0, BTreeMap::new(), purse_id);
+        let mut ipc_value: super::ipc::Value = Value::Account(account.clone()).into();
+        ipc_value.mut_account().clear_associated_keys();
+        let value: Value = (&ipc_value).try_into().unwrap();
+        assert_eq!(value, Value::Account(account));
+    }
+
+    #[test]
+    fn committed_accounts_are_held_to_the_key_limits() {
+        let public_key = PublicKey::new([7u8; 32]);
+        let mut keys = AssociatedKeys::new(public_key, Weight::new(1));
+        keys.add_key(PublicKey::new([9u8; 32]), Weight::new(1), &KeyLimits::default())
+            .unwrap();
+        let purse_id = PurseId::new([8u8; 32], AccessRights::READ_ADD_WRITE);
+        let thresholds = ActionThresholds::default();
+        let account = Account::new([7u8; 32], 0, BTreeMap::new(), purse_id, keys, thresholds);
+        let mut entry = super::ipc::TransformEntry::new();
+        entry.set_key((&Key::Account([7u8; 20])).into());
+        entry.set_transform(Transform::Write(Value::Account(account)).into());
+        let entries = vec![entry];
+
+        let limits = KeyLimits::default();
+        assert_eq!(effects_from_ipc(&entries, &limits).unwrap().len(), 1);
+        let limits = KeyLimits::default().with_max_keys(1);
+        assert!(effects_from_ipc(&entries, &limits).is_err());
+    }
+
+    #[test]
+    fn contracts_without_a_protocol_version_can_be_called() {
+        let mut ipc_contract = super::ipc::Contract::new();
+        ipc_contract.set_body(vec![1u8, 2, 3]);
+        let mut ipc_value = super::ipc::Value::new();
+        ipc_value.set_contract(ipc_contract);
+        match (&ipc_value).try_into() {
+            Ok(Value::Contract(contract)) => assert!(contract.is_compatible()),
+            other => panic!("Expected a contract, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn contract_abi_to_ipc_roundtrip() {
+        let abi = Abi::new(vec![EntryPoint::new(
+            "transfer".to_string(),
+            vec![
+                Arg::new("target".to_string(), AbiType::Key),
+                Arg::new("amount".to_string(), AbiType::UInt512),
+            ],
+            AbiType::Unit,
+        )
+        .with_groups(vec!["owners".to_string()])]);
+        let contract = Contract::new(vec![1u8, 2, 3], BTreeMap::new(), 1).with_abi(abi);
+        let ipc_value: super::ipc::Value = Value::Contract(contract.clone()).into();
+        let mut ipc_transform = super::ipc::Transform::new();
+        let mut ipc_write = super::ipc::TransformWrite::new();
+        ipc_write.set_value(ipc_value);
+        ipc_transform.set_write(ipc_write);
+        let transform: Transform = (&ipc_transform).try_into().unwrap();
+        assert_eq!(transform, Transform::Write(Value::Contract(contract)));
+    }
+
+    #[test]
+    fn events_are_part_of_ipc_effects() {
+        let event = Event {
+            topic: "transfer".to_string(),
+            data: Value::Int32(10),
+        };
+        let execution_effect = ExecutionEffect(HashMap::new(), HashMap::new(), vec![event]);
+        let ipc_effect: super::ipc::ExecutionEffect = execution_effect.into();
+        let ipc_events = ipc_effect.get_events();
+        assert_eq!(ipc_events.len(), 1);
+        assert_eq!(ipc_events[0].get_topic(), "transfer");
+        let expected_data: super::ipc::Value = Value::Int32(10).into();
+        assert_eq!(ipc_events[0].get_data(), &expected_data);
+    }
+
+    #[test]
+    fn execution_effect_to_ipc_roundtrip() {
+        let a = Key::Hash([1u8; 32]);
+        let b = Key::URef([2u8; 32], AccessRights::READ_ADD_WRITE);
+        let mut ops = HashMap::new();
+        ops.insert(a, Op::Write);
+        ops.insert(b, Op::Add);
+        let mut transforms = HashMap::new();
+        transforms.insert(
+            a,
+            Transform::Failure(TransformError::TypeMismatch(TypeMismatch::new(
+                "Int32".to_string(),
+                "String".to_string(),
+            ))),
+        );
+        transforms.insert(b, Transform::Failure(TransformError::Overflow));
+        let events = vec![Event {
+            topic: "transfer".to_string(),
+            data: Value::String("done".to_string()),
+        }];
+        let ipc_effect: super::ipc::ExecutionEffect =
+            ExecutionEffect(ops.clone(), transforms.clone(), events.clone()).into();
+        let effect: ExecutionEffect = (&ipc_effect).try_into().unwrap();
+        assert_eq!(effect.0, ops);
+        assert_eq!(effect.1, transforms);
+        assert_eq!(effect.2, events);
+    }
+
     fn into_execution_failure<E: Into<EngineError>>(error: E, cost: u64) -> ExecutionResult {
-        ExecutionResult::failure(error.into(), cost)
+        ExecutionResult::failure(error.into(), Gas::from(cost))
     }
 
     fn test_cost<E: Into<EngineError>>(expected_cost: u64, err: E) -> u64 {
@@ -604,4 +1365,90 @@ mod tests {
             execution_engine::execution::Error::ForgedReference(Key::Account([1u8; 20]));
         assert_eq!(test_cost(cost, forged_ref_error), cost);
     }
+
+    #[test]
+    fn revert_status_is_reported() {
+        let cost: u64 = 100;
+        let execution_failure =
+            into_execution_failure(execution_engine::execution::Error::Revert(65537), cost);
+        let mut ipc_deploy_result: super::ipc::DeployResult = execution_failure.into();
+        assert_eq!(ipc_deploy_result.get_cost(), cost);
+        let mut ipc_error = ipc_deploy_result.take_error();
+        assert_eq!(ipc_error.take_revertErr().get_status(), 65537);
+    }
+
+    #[test]
+    fn error_codes_are_reported() {
+        let code = |error: EngineError| {
+            let ipc_deploy_result: super::ipc::DeployResult =
+                into_execution_failure(error, 0).into();
+            ipc_deploy_result.get_error().get_code()
+        };
+        assert_eq!(code(ExecutionError::GasLimit.into()), u32::from(ErrorCode::OutOfGas));
+        assert_eq!(code(ExecutionError::Revert(1).into()), u32::from(ErrorCode::Revert));
+        assert_eq!(
+            code(ExecutionError::KeyNotFound(Key::Hash([1u8; 32])).into()),
+            u32::from(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(code(EngineError::InsufficientPayment), u32::from(ErrorCode::Payment));
+        let missing_approval = ApprovalError::MissingApproval(PublicKey::new([1u8; 32]));
+        assert_eq!(
+            code(EngineError::Approval(missing_approval)),
+            u32::from(ErrorCode::InvalidSignature)
+        );
+        assert_eq!(
+            code(EngineError::Approval(ApprovalError::InvalidBodyHash([0u8; 32]))),
+            u32::from(ErrorCode::InvalidDeployHash)
+        );
+    }
+
+    #[test]
+    fn precondition_error_is_reported() {
+        let error = PreconditionError::MissingDependency([2u8; 32]);
+        let message = error.to_string();
+        let execution_failure = into_execution_failure(EngineError::Precondition(error), 0);
+        let mut ipc_deploy_result: super::ipc::DeployResult = execution_failure.into();
+        let mut ipc_error = ipc_deploy_result.take_error();
+        assert_eq!(ipc_error.take_preconditionErr().get_message(), message);
+    }
+
+    #[test]
+    fn record_keys_roundtrip() {
+        for key in &[Key::DeployInfo([7u8; 32]), Key::Transfer([8u8; 32])] {
+            let ipc_key: super::ipc::Key = key.into();
+            let parsed: Key = (&ipc_key).try_into().expect("Key should be parsed.");
+            assert_eq!(&parsed, key);
+        }
+    }
+
+    #[test]
+    fn deploy_result_to_ipc_lists_transfers() {
+        let mut transforms = HashMap::new();
+        for addr in &[[9u8; 32], [8u8; 32]] {
+            let record = Transform::Write(Value::ByteArray(vec![1]));
+            transforms.insert(Key::Transfer(*addr), record);
+        }
+        let effect = ExecutionEffect(HashMap::new(), transforms, Vec::new());
+        let execution_result = ExecutionResult::success(effect, Gas::from(10));
+        let ipc_deploy_result: super::ipc::DeployResult = execution_result.into();
+        assert_eq!(
+            ipc_deploy_result.get_transfers().to_vec(),
+            vec![vec![8u8; 32], vec![9u8; 32]]
+        );
+        assert!(ipc_deploy_result.get_journal().is_empty());
+    }
+
+    #[test]
+    fn deploy_result_to_ipc_carries_the_journal() {
+        let journal = DeployJournal {
+            deploy_hash: [1u8; 32],
+            prestate_hash: [2u8; 32].into(),
+            block_height: 3,
+            entries: vec![JournalEntry::Gas(Gas::from(4)), JournalEntry::HostCall(5)],
+        };
+        let failure = ExecutionResult::failure(EngineError::InsufficientPayment, Gas::from(10));
+        let execution_result = failure.with_journal(journal.clone());
+        let ipc_deploy_result: super::ipc::DeployResult = execution_result.into();
+        assert_eq!(deserialize(ipc_deploy_result.get_journal()), Ok(journal));
+    }
 }
@@ -1,17 +1,21 @@
 use std::marker::{Send, Sync};
 
+use common::bytesrepr::{self, ToBytes};
 use common::key::Key;
-use execution_engine::engine::{EngineState, Error as EngineError};
+use common::value::account::PublicKey;
+use execution_engine::approval::Approval;
+use execution_engine::deploy_info::DeployPreconditions;
+use execution_engine::engine::{Deploy, DeployCode, EngineState, Error as EngineError};
 use execution_engine::execution::{Executor, WasmiExecutor};
+use execution_engine::module_cache::CachingPreprocessor;
 use execution_engine::trackingcopy::QueryResult;
 use ipc::*;
 use ipc_grpc::ExecutionEngineService;
+use shared::gas::Gas;
 use shared::newtypes::Blake2bHash;
-use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Debug;
 use storage::history::*;
-use storage::transform::Transform;
 use wasm_prep::{Preprocessor, WasmiPreprocessor};
 
 pub mod ipc;
@@ -26,9 +30,9 @@ use mappings::*;
 // This way core won't depend on comm (outer layer) leading to cleaner design.
 impl<H> ipc_grpc::ExecutionEngineService for EngineState<H>
 where
-    H: History,
+    H: History + Send + Sync,
     EngineError: From<H::Error>,
-    H::Error: Into<execution_engine::execution::Error> + Debug,
+    H::Error: Into<execution_engine::execution::Error> + From<bytesrepr::Error> + Debug,
 {
     fn query(
         &self,
@@ -45,44 +49,26 @@ where
             }
             Ok(key) => {
                 let path = p.get_path();
-                match self.tracking_copy(state_hash) {
-                    Err(storage_error) => {
-                        let mut result = ipc::QueryResponse::new();
-                        let error = format!("Error during checkout out Trie: {:?}", storage_error);
-                        result.set_failure(error);
-                        grpc::SingleResponse::completed(result)
-                    }
-                    Ok(None) => {
-                        let mut result = ipc::QueryResponse::new();
-                        let error = format!("Root not found: {:?}", state_hash);
-                        result.set_failure(error);
-                        grpc::SingleResponse::completed(result)
+                let mut result = ipc::QueryResponse::new();
+                match self.query(state_hash, key, path) {
+                    Err(error) => result.set_failure(format!("{:?}", error)),
+                    Ok(None) => result.set_failure(format!("Root not found: {:?}", state_hash)),
+                    Ok(Some((QueryResult::ValueNotFound(full_path), _))) => {
+                        result.set_failure(format!("Value not found: {:?}", full_path))
                     }
-                    Ok(Some(mut tc)) => {
-                        let response = match tc.query(key, path) {
-                            Err(err) => {
-                                let mut result = ipc::QueryResponse::new();
-                                let error = format!("{:?}", err);
-                                result.set_failure(error);
-                                result
-                            }
-
-                            Ok(QueryResult::ValueNotFound(full_path)) => {
-                                let mut result = ipc::QueryResponse::new();
-                                let error = format!("Value not found: {:?}", full_path);
-                                result.set_failure(error);
-                                result
-                            }
-
-                            Ok(QueryResult::Success(value)) => {
-                                let mut result = ipc::QueryResponse::new();
+                    Ok(Some((QueryResult::Success(value), proofs))) => {
+                        let proofs: Result<Vec<Vec<u8>>, _> =
+                            proofs.iter().map(ToBytes::to_bytes).collect();
+                        match proofs {
+                            Ok(proofs) => {
                                 result.set_success(value.into());
-                                result
+                                result.set_proofs(protobuf::RepeatedField::from_vec(proofs));
                             }
-                        };
-                        grpc::SingleResponse::completed(response)
+                            Err(error) => result.set_failure(format!("{:?}", error)),
+                        }
                     }
                 }
+                grpc::SingleResponse::completed(result)
             }
         }
     }
@@ -93,12 +79,21 @@ where
         p: ipc::ExecRequest,
     ) -> grpc::SingleResponse<ipc::ExecResponse> {
         let executor = WasmiExecutor;
-        let preprocessor = WasmiPreprocessor;
+        let preprocessor = CachingPreprocessor::new(WasmiPreprocessor, self.module_cache());
         // TODO: don't unwrap
         let prestate_hash: Blake2bHash = p.get_parent_state_hash().try_into().unwrap();
+        let block_height = p.get_block_height();
+        let block_time = p.get_block_time();
         let deploys = p.get_deploys();
-        let deploys_result: Result<Vec<DeployResult>, RootNotFound> =
-            run_deploys(&self, &executor, &preprocessor, prestate_hash, deploys);
+        let deploys_result: Result<Vec<DeployResult>, RootNotFound> = run_deploys(
+            &self,
+            &executor,
+            &preprocessor,
+            prestate_hash,
+            block_height,
+            block_time,
+            deploys,
+        );
         match deploys_result {
             Ok(deploy_results) => {
                 let mut exec_response = ipc::ExecResponse::new();
@@ -115,6 +110,42 @@ where
         }
     }
 
+    fn speculative_exec(
+        &self,
+        _o: ::grpc::RequestOptions,
+        p: ipc::SpeculativeExecRequest,
+    ) -> grpc::SingleResponse<ipc::SpeculativeExecResponse> {
+        let mut response = ipc::SpeculativeExecResponse::new();
+        // A malformed hash can't name any state the deploy could be run against.
+        let state_hash: Blake2bHash = match p.get_state_hash().try_into() {
+            Ok(state_hash) => state_hash,
+            Err(_) => {
+                let mut root = ipc::RootNotFound::new();
+                root.set_hash(p.get_state_hash().to_vec());
+                response.set_missing_state(root);
+                return grpc::SingleResponse::completed(response);
+            }
+        };
+        let deploy = p.get_deploy();
+        let result = match parse_deploy(deploy) {
+            Err(ParsingError(err_msg)) => Ok(wasm_error(err_msg)),
+            Ok(parsed) => self
+                .speculative_exec(
+                    state_hash,
+                    &engine_deploy(deploy, &parsed, p.get_block_time()),
+                    p.get_block_height(),
+                    &WasmiExecutor,
+                    &CachingPreprocessor::new(WasmiPreprocessor, self.module_cache()),
+                )
+                .map(Into::into),
+        };
+        match result {
+            Ok(deploy_result) => response.set_success(deploy_result),
+            Err(error) => response.set_missing_state(error.into()),
+        }
+        grpc::SingleResponse::completed(response)
+    }
+
     fn commit(
         &self,
         _o: ::grpc::RequestOptions,
@@ -122,8 +153,7 @@ where
     ) -> grpc::SingleResponse<ipc::CommitResponse> {
         // TODO: don't unwrap
         let prestate_hash: Blake2bHash = p.get_prestate_hash().try_into().unwrap();
-        let effects_result: Result<HashMap<Key, Transform>, ParsingError> =
-            p.get_effects().iter().map(TryInto::try_into).collect();
+        let effects_result = effects_from_ipc(p.get_effects(), &self.config().key_limits());
         match effects_result {
             Err(ParsingError(error_message)) => {
                 let mut res = ipc::CommitResponse::new();
@@ -167,6 +197,142 @@ where
             }
         }
     }
+
+    fn balances(
+        &self,
+        _o: ::grpc::RequestOptions,
+        p: ipc::BalancesRequest,
+    ) -> grpc::SingleResponse<ipc::BalancesResponse> {
+        let mut response = ipc::BalancesResponse::new();
+        let mut root = ipc::RootNotFound::new();
+        root.set_hash(p.get_state_hash().to_vec());
+        match p.get_state_hash().try_into() {
+            Err(_) => response.set_missing_state(root),
+            Ok(state_hash) => match self.balances(state_hash) {
+                Err(error) => response.set_failure(format!("{:?}", error)),
+                Ok(None) => response.set_missing_state(root),
+                Ok(Some(balances)) => response.set_success((&balances).into()),
+            },
+        }
+        grpc::SingleResponse::completed(response)
+    }
+
+    fn bonded_validators(
+        &self,
+        _o: ::grpc::RequestOptions,
+        p: ipc::BondedValidatorsRequest,
+    ) -> grpc::SingleResponse<ipc::BondedValidatorsResponse> {
+        let mut response = ipc::BondedValidatorsResponse::new();
+        let mut root = ipc::RootNotFound::new();
+        root.set_hash(p.get_state_hash().to_vec());
+        match p.get_state_hash().try_into() {
+            Err(_) => response.set_missing_state(root),
+            Ok(state_hash) => match self.bonded_validators(state_hash) {
+                Err(error) => response.set_failure(format!("{:?}", error)),
+                Ok(None) => response.set_missing_state(root),
+                Ok(Some(weights)) => response.set_success((&weights).into()),
+            },
+        }
+        grpc::SingleResponse::completed(response)
+    }
+
+    fn era_validators(
+        &self,
+        _o: ::grpc::RequestOptions,
+        p: ipc::EraValidatorsRequest,
+    ) -> grpc::SingleResponse<ipc::EraValidatorsResponse> {
+        let mut response = ipc::EraValidatorsResponse::new();
+        let mut root = ipc::RootNotFound::new();
+        root.set_hash(p.get_state_hash().to_vec());
+        match p.get_state_hash().try_into() {
+            Err(_) => response.set_missing_state(root),
+            Ok(state_hash) => match self.era_validators(state_hash) {
+                Err(error) => response.set_failure(format!("{:?}", error)),
+                Ok(None) => response.set_missing_state(root),
+                Ok(Some(era_validators)) => response.set_success((&era_validators).into()),
+            },
+        }
+        grpc::SingleResponse::completed(response)
+    }
+}
+
+/// Parts of a deploy which have to be parsed before it's run.
+struct ParsedDeploy {
+    address: [u8; 20],
+    authorization_keys: Vec<PublicKey>,
+    deploy_hash: [u8; 32],
+    body_hash: [u8; 32],
+    approvals: Vec<Approval>,
+    dependencies: Vec<[u8; 32]>,
+}
+
+fn parse_deploy(deploy: &ipc::Deploy) -> Result<ParsedDeploy, ParsingError> {
+    let address = account_address_from_ipc(deploy.get_address())?;
+    let authorization_keys = deploy
+        .get_authorization_keys()
+        .iter()
+        .map(|key| public_key_from_ipc(key))
+        .collect::<Result<Vec<PublicKey>, ParsingError>>()?;
+    let deploy_hash = deploy_hash_from_ipc(deploy.get_deploy_hash())?;
+    // Nodes which leave the verification of approvals to themselves don't
+    // have to send the body hash.
+    let body_hash = match deploy.get_body_hash() {
+        [] => [0u8; 32],
+        body_hash => deploy_hash_from_ipc(body_hash)?,
+    };
+    let approvals = deploy
+        .get_approvals()
+        .iter()
+        .map(approval_from_ipc)
+        .collect::<Result<Vec<Approval>, ParsingError>>()?;
+    let dependencies = deploy
+        .get_dependencies()
+        .iter()
+        .map(|hash| deploy_hash_from_ipc(hash))
+        .collect::<Result<Vec<[u8; 32]>, ParsingError>>()?;
+    Ok(ParsedDeploy {
+        address,
+        authorization_keys,
+        deploy_hash,
+        body_hash,
+        approvals,
+        dependencies,
+    })
+}
+
+fn engine_deploy<'a>(
+    deploy: &'a ipc::Deploy,
+    parsed: &'a ParsedDeploy,
+    block_time: u64,
+) -> Deploy<'a> {
+    let session_contract = deploy.get_session();
+    let payment_contract = deploy.get_payment();
+    Deploy {
+        session: DeployCode {
+            module_bytes: &session_contract.code,
+            args: &session_contract.args,
+            named_args: &session_contract.named_args,
+        },
+        payment: DeployCode {
+            module_bytes: &payment_contract.code,
+            args: &payment_contract.args,
+            named_args: &payment_contract.named_args,
+        },
+        address: parsed.address,
+        authorization_keys: &parsed.authorization_keys,
+        timestamp: deploy.timestamp,
+        nonce: deploy.nonce,
+        deploy_hash: parsed.deploy_hash,
+        body_hash: parsed.body_hash,
+        approvals: &parsed.approvals,
+        preconditions: DeployPreconditions {
+            ttl_millis: deploy.ttl_millis,
+            dependencies: &parsed.dependencies,
+            block_time,
+        },
+        gas_limit: Gas::from(deploy.gas_limit),
+        gas_price: deploy.gas_price,
+    }
 }
 
 fn run_deploys<A, H, E, P>(
@@ -174,50 +340,51 @@ fn run_deploys<A, H, E, P>(
     executor: &E,
     preprocessor: &P,
     prestate_hash: Blake2bHash,
+    block_height: u64,
+    block_time: u64,
     deploys: &[ipc::Deploy],
 ) -> Result<Vec<DeployResult>, RootNotFound>
 where
-    H: History,
-    E: Executor<A>,
-    P: Preprocessor<A>,
+    H: History + Send + Sync,
+    E: Executor<A> + Sync,
+    P: Preprocessor<A> + Sync,
     EngineError: From<H::Error>,
     H::Error: Into<execution_engine::execution::Error>,
 {
+    let parsed_deploys: Vec<Result<ParsedDeploy, ParsingError>> =
+        deploys.iter().map(parse_deploy).collect();
+    // Deploys which can't be parsed fail without being run.
+    let engine_deploys: Vec<Deploy> = deploys
+        .iter()
+        .zip(&parsed_deploys)
+        .filter_map(|(deploy, parsed)| {
+            let parsed = parsed.as_ref().ok()?;
+            Some(engine_deploy(deploy, parsed, block_time))
+        })
+        .collect();
     // We want to treat RootNotFound error differently b/c it should short-circuit
     // the execution of ALL deploys within the block. This is because all of them share
     // the same prestate and all of them would fail.
-    // Iterator (Result<_, _> + collect()) will short circuit the execution
-    // when run_deploy returns Err.
-    deploys
-        .iter()
-        .map(|deploy| {
-            let session_contract = deploy.get_session();
-            let module_bytes = &session_contract.code;
-            let args = &session_contract.args;
-            let address: [u8; 20] = {
-                let mut tmp = [0u8; 20];
-                tmp.copy_from_slice(&deploy.address);
-                tmp
-            };
-            let timestamp = deploy.timestamp;
-            let nonce = deploy.nonce;
-            let gas_limit = deploy.gas_limit as u64;
-            engine_state
-                .run_deploy(
-                    module_bytes,
-                    args,
-                    address,
-                    timestamp,
-                    nonce,
-                    prestate_hash,
-                    gas_limit,
-                    executor,
-                    preprocessor,
-                )
-                .map(Into::into)
-                .map_err(Into::into)
+    let mut results = engine_state
+        .run_deploys(
+            &engine_deploys,
+            block_height,
+            prestate_hash,
+            executor,
+            preprocessor,
+        )?
+        .into_iter();
+    let deploy_results = parsed_deploys
+        .into_iter()
+        .map(|parsed| match parsed {
+            Ok(_) => results
+                .next()
+                .expect("Every parsed deploy should have a result.")
+                .into(),
+            Err(ParsingError(err_msg)) => wasm_error(err_msg),
         })
-        .collect()
+        .collect();
+    Ok(deploy_results)
 }
 
 // Helper method which returns single DeployResult that is set to be a WasmError.
@@ -236,3 +403,139 @@ pub fn new<E: ExecutionEngineService + Sync + Send + 'static>(
     server.add_service(ipc_grpc::ExecutionEngineServiceServer::new_service_def(e));
     server
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ipc;
+    use super::{engine_deploy, parse_deploy};
+    use common::key::AccessRights;
+    use common::value::account::{PublicKey, PurseId};
+    use common::value::{Value, U512};
+    use execution_engine::engine::{EngineConfig, EngineState};
+    use execution_engine::genesis::{GenesisAccount, GenesisConfig};
+    use execution_engine::mint;
+    use grpc::RequestOptions;
+    use ipc_grpc::ExecutionEngineService;
+    use storage::global_state::in_memory::InMemoryGlobalState;
+
+    /// A deploy with the fields the node sets, as in
+    /// `ProtoUtil.deployDataToEEDeploy`.
+    fn node_deploy() -> ipc::Deploy {
+        let mut session = ipc::DeployCode::new();
+        session.set_code(vec![0u8, 97, 115, 109]);
+        let mut deploy = ipc::Deploy::new();
+        deploy.set_address(vec![7u8; 20]);
+        deploy.set_timestamp(1000);
+        deploy.set_session(session);
+        deploy.set_payment(ipc::DeployCode::new());
+        deploy.set_gas_limit(100);
+        deploy.set_gas_price(1);
+        deploy.set_nonce(1);
+        deploy.set_authorization_keys(protobuf::RepeatedField::from_vec(vec![vec![5u8; 32]]));
+        deploy.set_deploy_hash(vec![3u8; 32]);
+        deploy
+    }
+
+    #[test]
+    fn deploys_sent_by_the_node_are_parsed() {
+        let deploy = node_deploy();
+        let parsed = parse_deploy(&deploy).expect("should parse");
+        let engine_deploy = engine_deploy(&deploy, &parsed, 0);
+        assert_eq!(engine_deploy.address, [7u8; 20]);
+        assert_eq!(engine_deploy.deploy_hash, [3u8; 32]);
+        assert_eq!(engine_deploy.authorization_keys, &[PublicKey::new([5u8; 32])]);
+        assert_eq!(engine_deploy.timestamp, 1000);
+        assert_eq!(engine_deploy.nonce, 1);
+    }
+
+    #[test]
+    fn deploys_with_malformed_addresses_are_rejected() {
+        let mut deploy = node_deploy();
+        deploy.set_address(vec![7u8; 32]);
+        assert!(parse_deploy(&deploy).is_err());
+    }
+
+    #[test]
+    fn balances_are_sent_with_their_purses() {
+        let addr = [4u8; 32];
+        let purse = PurseId::new(addr, AccessRights::READ_ADD_WRITE);
+        let pairs = [
+            (mint::balance_key(purse), Value::UInt512(U512::from(10))),
+            (mint::purse_record_key(addr), Value::ByteArray(addr.to_vec())),
+        ];
+        let (global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let engine_state = EngineState::new(global_state);
+        let balances_at = |state_hash: Vec<u8>| {
+            let mut request = ipc::BalancesRequest::new();
+            request.set_state_hash(state_hash);
+            ExecutionEngineService::balances(&engine_state, RequestOptions::new(), request)
+                .wait_drop_metadata()
+                .expect("should respond")
+        };
+
+        let response = balances_at(root_hash.to_vec());
+        let balances = response.get_success().get_balances();
+        assert_eq!(balances.len(), 1);
+        let key: ipc::Key = (&mint::balance_key(purse)).into();
+        assert_eq!(balances[0].get_purse(), &key);
+        assert_eq!(balances[0].get_balance().get_value(), "10");
+        assert!(balances_at(vec![1u8; 32]).has_missing_state());
+        assert!(balances_at(vec![1u8; 3]).has_missing_state());
+    }
+
+    #[test]
+    fn bonded_validators_are_sent_with_their_weights() {
+        let validator = PublicKey::new([3u8; 32]);
+        let accounts = vec![GenesisAccount::new(validator, U512::from(100), U512::from(50))];
+        let genesis = GenesisConfig::new("test-chain", accounts, Default::default());
+        let (engine_state, genesis_hash) =
+            EngineState::in_memory_genesis(&genesis, EngineConfig::new())
+                .expect("Genesis should be committed.");
+        let validators_at = |state_hash: Vec<u8>| {
+            let mut request = ipc::BondedValidatorsRequest::new();
+            request.set_state_hash(state_hash);
+            ExecutionEngineService::bonded_validators(&engine_state, RequestOptions::new(), request)
+                .wait_drop_metadata()
+                .expect("should respond")
+        };
+
+        let response = validators_at(genesis_hash.to_vec());
+        let validators = response.get_success().get_validators();
+        assert_eq!(validators.len(), 1);
+        assert_eq!(validators[0].get_public_key(), &[3u8; 32]);
+        assert_eq!(validators[0].get_weight().get_value(), "50");
+        assert!(validators_at(vec![1u8; 32]).has_missing_state());
+        assert!(validators_at(vec![1u8; 3]).has_missing_state());
+    }
+
+    #[test]
+    fn era_validators_are_sent_by_era() {
+        let validator = PublicKey::new([3u8; 32]);
+        let accounts = vec![GenesisAccount::new(validator, U512::from(100), U512::from(50))];
+        let genesis =
+            GenesisConfig::new("test-chain", accounts, Default::default()).with_auction_delay(1);
+        let (engine_state, genesis_hash) =
+            EngineState::in_memory_genesis(&genesis, EngineConfig::new())
+                .expect("Genesis should be committed.");
+        let eras_at = |state_hash: Vec<u8>| {
+            let mut request = ipc::EraValidatorsRequest::new();
+            request.set_state_hash(state_hash);
+            ExecutionEngineService::era_validators(&engine_state, RequestOptions::new(), request)
+                .wait_drop_metadata()
+                .expect("should respond")
+        };
+
+        // Genesis seats the validator in the first era and the one after it.
+        let response = eras_at(genesis_hash.to_vec());
+        let eras = response.get_success().get_eras();
+        assert_eq!(eras.iter().map(|era| era.get_era_id()).collect::<Vec<_>>(), vec![0, 1]);
+        for era in eras {
+            let validators = era.get_validators();
+            assert_eq!(validators.len(), 1);
+            assert_eq!(validators[0].get_public_key(), &[3u8; 32]);
+            assert_eq!(validators[0].get_weight().get_value(), "50");
+        }
+        assert!(eras_at(vec![1u8; 32]).has_missing_state());
+        assert!(eras_at(vec![1u8; 3]).has_missing_state());
+    }
+}
@@ -9,15 +9,130 @@ extern crate wabt;
 extern crate wasm_prep;
 
 pub mod engine_server;
+mod metrics;
 
-use clap::{App, Arg};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use clap::{App, Arg, ArgMatches};
+use common::bytesrepr;
+use common::value::account::KeyLimits;
 use engine_server::*;
-use execution_engine::engine::EngineState;
+use execution_engine::engine::{EngineConfig, EngineState, Error as EngineError, RefundRatio};
+use execution_engine::execution;
 use storage::global_state::inmem::InMemHist;
+use storage::global_state::lmdb::LmdbGlobalState;
+use storage::global_state::migration::{MigrationError, MigrationReport, Migrator};
+use storage::global_state::rocksdb::RocksDbGlobalState;
+use storage::history::trie_store::compression::Compression;
+use storage::history::trie_store::lmdb::LmdbEnvironment;
+use storage::history::History;
+use storage::metrics::{self as store_metrics, StoreCounters, StoreMetrics};
 
 fn main() {
     let matches = App::new("Execution engine server")
         .arg(Arg::with_name("socket").required(true).help("Socket file"))
+        .arg(
+            Arg::with_name("contract-debug")
+                .long("contract-debug")
+                .help("Prints messages passed by contracts to the print host function"),
+        )
+        .arg(
+            Arg::with_name("use-payment-code")
+                .long("use-payment-code")
+                .help("Runs the payment code of deploys to buy the gas for their session code"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .takes_value(true)
+                .default_value("1")
+                .help("Number of threads the deploys of a block are executed on"),
+        )
+        .arg(
+            Arg::with_name("refund-ratio")
+                .long("refund-ratio")
+                .takes_value(true)
+                .default_value("1/1")
+                .help("Share of their unused payment refunded to deploys, as a fraction like 1/2"),
+        )
+        .arg(
+            Arg::with_name("module-cache-size")
+                .long("module-cache-size")
+                .takes_value(true)
+                .default_value("64")
+                .help("Number of preprocessed Wasm modules kept for deploys sending the same code"),
+        )
+        .arg(
+            Arg::with_name("max-deploy-size")
+                .long("max-deploy-size")
+                .takes_value(true)
+                .help("Largest deploy accepted, in bytes of code and arguments"),
+        )
+        .arg(
+            Arg::with_name("max-payment-cost")
+                .long("max-payment-cost")
+                .takes_value(true)
+                .help("Gas the payment code of a deploy can use"),
+        )
+        .arg(
+            Arg::with_name("block-gas-limit")
+                .long("block-gas-limit")
+                .takes_value(true)
+                .help("Gas the deploys of a block can use together"),
+        )
+        .arg(
+            Arg::with_name("max-associated-keys")
+                .long("max-associated-keys")
+                .takes_value(true)
+                .help("Number of keys an account can associate with itself"),
+        )
+        .arg(
+            Arg::with_name("journal")
+                .long("journal")
+                .help("Journals the execution of deploys, so that it can be replayed and audited"),
+        )
+        .arg(
+            Arg::with_name("storage")
+                .long("storage")
+                .takes_value(true)
+                .possible_values(&["memory", "lmdb", "rocksdb"])
+                .default_value("memory")
+                .help("Backend the global state is kept in"),
+        )
+        .arg(
+            Arg::with_name("data-dir")
+                .long("data-dir")
+                .takes_value(true)
+                .required_ifs(&[("storage", "lmdb"), ("storage", "rocksdb")])
+                .help("Directory of the global state, for the lmdb and rocksdb backends"),
+        )
+        .arg(
+            Arg::with_name("compression-level")
+                .long("compression-level")
+                .takes_value(true)
+                .help("Level the lmdb and rocksdb backends compress the tries they write at"),
+        )
+        .arg(
+            Arg::with_name("compression-threshold")
+                .long("compression-threshold")
+                .takes_value(true)
+                .requires("compression-level")
+                .help("Size in bytes below which tries aren't compressed"),
+        )
+        .arg(
+            Arg::with_name("migrations-dry-run")
+                .long("migrations-dry-run")
+                .help("Only reports the migrations the global state needs, then exits"),
+        )
+        .arg(
+            Arg::with_name("metrics-address")
+                .long("metrics-address")
+                .takes_value(true)
+                .help("Address serving the storage metrics to Prometheus, like 127.0.0.1:9100"),
+        )
         .get_matches();
     let socket = matches
         .value_of("socket")
@@ -26,10 +141,98 @@ fn main() {
     if socket_path.exists() {
         std::fs::remove_file(socket_path).expect("Remove old socket file.");
     }
+    let execution_threads: usize = matches
+        .value_of("threads")
+        .and_then(|threads| threads.parse().ok())
+        .expect("threads has to be a number");
+    let refund_ratio = matches
+        .value_of("refund-ratio")
+        .and_then(parse_refund_ratio)
+        .expect("refund-ratio has to be a ratio between 0 and 1");
+    let module_cache_capacity: usize = matches
+        .value_of("module-cache-size")
+        .and_then(|size| size.parse().ok())
+        .expect("module-cache-size has to be a number");
+
+    let engine_config = EngineConfig::new()
+        .with_contract_debug(matches.is_present("contract-debug"))
+        .with_use_payment_code(matches.is_present("use-payment-code"))
+        .with_refund_ratio(refund_ratio)
+        .with_execution_threads(execution_threads)
+        .with_module_cache_capacity(module_cache_capacity)
+        .with_journaling(matches.is_present("journal"));
+    let engine_config = match parse_number(&matches, "max-deploy-size") {
+        Some(max_deploy_size) => engine_config.with_max_deploy_size(max_deploy_size),
+        None => engine_config,
+    };
+    let engine_config = match parse_number(&matches, "max-payment-cost") {
+        Some(max_payment_cost) => engine_config.with_max_payment_cost(max_payment_cost),
+        None => engine_config,
+    };
+    let engine_config = match parse_number(&matches, "block-gas-limit") {
+        Some(block_gas_limit) => engine_config.with_block_gas_limit(block_gas_limit),
+        None => engine_config,
+    };
+    let engine_config = match parse_number(&matches, "max-associated-keys") {
+        Some(max_keys) => {
+            engine_config.with_key_limits(KeyLimits::default().with_max_keys(max_keys))
+        }
+        None => engine_config,
+    };
+    let store_metrics: Arc<dyn StoreMetrics> = match matches.value_of("metrics-address") {
+        Some(address) => {
+            let counters = Arc::new(StoreCounters::new());
+            metrics::serve(address, Arc::clone(&counters)).expect("Serve metrics");
+            println!("Metrics are served on: {}", address);
+            counters
+        }
+        None => store_metrics::no_metrics(),
+    };
+    let compression = compression(&matches);
+    let migrator = migrator(&matches);
+    match matches.value_of("storage") {
+        Some("lmdb") => {
+            let data_dir = data_dir(&matches);
+            let environment =
+                Arc::new(LmdbEnvironment::new(&data_dir).expect("Open LMDB environment"));
+            let mut state = LmdbGlobalState::new(environment).expect("Open global state");
+            finish_migration(state.migrate(&migrator));
+            let state = state.with_metrics(store_metrics);
+            let state = match compression {
+                Some(compression) => state.with_compression(compression),
+                None => state,
+            };
+            serve(socket, state, engine_config)
+        }
+        Some("rocksdb") => {
+            let data_dir = data_dir(&matches);
+            let mut state = RocksDbGlobalState::open(&data_dir).expect("Open global state");
+            finish_migration(state.migrate(&migrator));
+            let state = state.with_metrics(store_metrics);
+            let state = match compression {
+                Some(compression) => state.with_compression(compression),
+                None => state,
+            };
+            serve(socket, state, engine_config)
+        }
+        _ => {
+            let init_state = storage::global_state::mocked_account([48u8; 20]);
+            let state = InMemHist::new_initialized(&([0u8; 32].into()), init_state)
+                .with_metrics(store_metrics);
+            serve(socket, state, engine_config)
+        }
+    }
+}
 
-    let init_state = storage::global_state::mocked_account([48u8; 20]);
-    let engine_state =
-        EngineState::new(InMemHist::new_initialized(&([0u8; 32].into()), init_state));
+/// Serves the engine over the global state `state` on `socket` until the
+/// process is stopped.
+fn serve<H>(socket: &str, state: H, engine_config: EngineConfig) -> !
+where
+    H: History + Send + Sync + 'static,
+    EngineError: From<H::Error>,
+    H::Error: Into<execution::Error> + From<bytesrepr::Error> + Debug,
+{
+    let engine_state = EngineState::with_config(state, engine_config);
     let server_builder = engine_server::new(socket, engine_state);
     let _server = server_builder.build().expect("Start server");
 
@@ -40,3 +243,84 @@ fn main() {
         std::thread::park();
     }
 }
+
+/// The `data-dir` argument, created if it doesn't exist yet.
+fn data_dir(matches: &ArgMatches) -> PathBuf {
+    let data_dir = PathBuf::from(
+        matches
+            .value_of("data-dir")
+            .expect("data-dir is required by the persistent backends"),
+    );
+    std::fs::create_dir_all(&data_dir).expect("Create data directory");
+    data_dir
+}
+
+/// Migrator bringing the global state to the format of this build, printing
+/// its progress.
+fn migrator(matches: &ArgMatches) -> Migrator {
+    Migrator::new()
+        .with_dry_run(matches.is_present("migrations-dry-run"))
+        .with_progress(Box::new(|report: &MigrationReport| {
+            println!(
+                "Migration to format version {}: {} of {} tries",
+                report.to_version, report.tries_migrated, report.tries_total
+            );
+        }))
+}
+
+/// Reports how the migration of the global state went. The server exits
+/// after a dry run, and panics if the migration failed.
+fn finish_migration(result: Result<MigrationReport, MigrationError>) {
+    let report = result.unwrap_or_else(|error| panic!("Migrate the global state: {}", error));
+    if report.dry_run {
+        println!(
+            "Global state is at format version {}: {} of {} tries would be rewritten for {}",
+            report.from_version, report.tries_rewritten, report.tries_total, report.to_version
+        );
+        std::process::exit(0);
+    }
+    if report.from_version != report.to_version {
+        println!(
+            "Global state migrated from format version {} to {}, {} of {} tries rewritten",
+            report.from_version, report.to_version, report.tries_rewritten, report.tries_total
+        );
+    }
+}
+
+/// The compression of tries configured by the arguments, if any.
+#[cfg(feature = "compression")]
+fn compression(matches: &ArgMatches) -> Option<Compression> {
+    let level = parse_number(matches, "compression-level")?;
+    let threshold = parse_number(matches, "compression-threshold")
+        .unwrap_or(storage::history::trie_store::compression::DEFAULT_THRESHOLD);
+    Some(
+        Compression::zstd()
+            .with_level(level)
+            .with_threshold(threshold),
+    )
+}
+
+#[cfg(not(feature = "compression"))]
+fn compression(matches: &ArgMatches) -> Option<Compression> {
+    if matches.is_present("compression-level") {
+        panic!("compression-level needs the server to be built with the compression feature");
+    }
+    None
+}
+
+/// Value of the optional argument `name`, which has to be a number if it's
+/// given.
+fn parse_number<T: FromStr>(matches: &ArgMatches, name: &str) -> Option<T> {
+    matches.value_of(name).map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has to be a number", name))
+    })
+}
+
+fn parse_refund_ratio(ratio: &str) -> Option<RefundRatio> {
+    let mut parts = ratio.splitn(2, '/');
+    let numerator = parts.next()?.trim().parse().ok()?;
+    let denominator = parts.next()?.trim().parse().ok()?;
+    RefundRatio::new(numerator, denominator)
+}
@@ -0,0 +1,129 @@
+//! Reports the metrics of the global state over HTTP, in the text format
+//! Prometheus scrapes.
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use storage::metrics::{StoreCounters, StoreCounts};
+
+/// Renders `counts` in the Prometheus text format.
+pub fn render(counts: &StoreCounts) -> String {
+    let mut text = String::new();
+    let counters = [
+        (
+            "storage_tries_read_total",
+            "Tries read from the trie store.",
+            counts.tries_read,
+        ),
+        (
+            "storage_trie_read_bytes_total",
+            "Bytes of the tries read from the trie store.",
+            counts.trie_bytes_read,
+        ),
+        (
+            "storage_tries_written_total",
+            "Tries written to the trie store.",
+            counts.tries_written,
+        ),
+        (
+            "storage_trie_written_bytes_total",
+            "Bytes of the tries written to the trie store.",
+            counts.trie_bytes_written,
+        ),
+        (
+            "storage_corruptions_detected_total",
+            "Records read which didn't match their key or checksum.",
+            counts.corruptions_detected,
+        ),
+    ];
+    for (name, help, value) in counters.iter() {
+        writeln!(text, "# HELP {} {}", name, help).unwrap();
+        writeln!(text, "# TYPE {} counter", name).unwrap();
+        writeln!(text, "{} {}", name, value).unwrap();
+    }
+    let name = "storage_operation_duration_seconds";
+    writeln!(text, "# HELP {} Time the operations of the global state took.", name).unwrap();
+    writeln!(text, "# TYPE {} summary", name).unwrap();
+    for (operation, operation_counts) in &counts.operations {
+        let labels = format!("{{operation=\"{}\"}}", operation.name());
+        let seconds = as_seconds(operation_counts.total_duration);
+        writeln!(text, "{}_sum{} {}", name, labels, seconds).unwrap();
+        writeln!(text, "{}_count{} {}", name, labels, operation_counts.count).unwrap();
+    }
+    text
+}
+
+fn as_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// Serves the metrics counted by `counters` on `address` from a thread of its
+/// own, whatever path is requested.
+pub fn serve(address: &str, counters: Arc<StoreCounters>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            // A scrape failing only fails that scrape.
+            let _ = stream.and_then(|stream| respond(stream, &counters));
+        }
+    });
+    Ok(())
+}
+
+fn respond(stream: TcpStream, counters: &StoreCounters) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    // The request is read up to the blank line ending its headers.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim_end().is_empty() {
+        line.clear();
+    }
+    let body = render(&counters.counts());
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use storage::metrics::{StoreCounters, StoreMetrics, StoreOperation};
+
+    use super::render;
+
+    #[test]
+    fn metrics_are_rendered_for_prometheus() {
+        let counters = StoreCounters::new();
+        counters.trie_read(100);
+        counters.trie_written(40);
+        counters.trie_written(60);
+        counters.operation(StoreOperation::Commit, Duration::from_millis(1500));
+        let text = render(&counters.counts());
+        let lines: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "storage_tries_read_total 1",
+                "storage_trie_read_bytes_total 100",
+                "storage_tries_written_total 2",
+                "storage_trie_written_bytes_total 100",
+                "storage_corruptions_detected_total 0",
+                "storage_operation_duration_seconds_sum{operation=\"commit\"} 1.5",
+                "storage_operation_duration_seconds_count{operation=\"commit\"} 1",
+            ]
+        );
+        assert!(text.contains("# TYPE storage_operation_duration_seconds summary\n"));
+    }
+}
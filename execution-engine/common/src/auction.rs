@@ -0,0 +1,90 @@
+//! Types shared by the auction system contract and its callers.
+//!
+//! Accounts bid tokens for a seat in the validator set of the eras to come.
+//! The bid of every bidder is stored under a named key of the auction
+//! contract called `b_` followed by the hex-encoded public key of the bidder.
+
+use crate::pos::{hex_name, key_from_hex};
+use crate::value::account::PublicKey;
+use alloc::string::String;
+use core::convert::TryFrom;
+use failure::Fail;
+
+/// Prefix of the names under which the auction contract keeps the bids.
+pub const BID_KEY_PREFIX: &str = "b_";
+
+/// Name of the named key of the auction contract holding the bid of `bidder`.
+pub fn bid_key_name(bidder: PublicKey) -> String {
+    hex_name(BID_KEY_PREFIX, bidder)
+}
+
+/// Bidder whose bid is kept under `name`, if it's the name of a bid.
+pub fn bidder_from_bid_key_name(name: &str) -> Option<PublicKey> {
+    if !name.starts_with(BID_KEY_PREFIX) {
+        return None;
+    }
+    key_from_hex(&name[BID_KEY_PREFIX.len()..])
+}
+
+/// Reasons why bidding or withdrawing a bid can fail. Discriminants are the
+/// codes returned by the host functions and must not change.
+#[repr(i32)]
+#[derive(Fail, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AuctionError {
+    #[fail(display = "Source purse doesn't hold enough tokens")]
+    InsufficientFunds = 1,
+    #[fail(display = "Bidder has no bid")]
+    NoBid = 2,
+    #[fail(display = "Withdrawing more than the bid")]
+    WithdrawTooLarge = 3,
+}
+
+impl From<AuctionError> for i32 {
+    fn from(error: AuctionError) -> i32 {
+        error as i32
+    }
+}
+
+impl TryFrom<i32> for AuctionError {
+    type Error = ();
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(AuctionError::InsufficientFunds),
+            2 => Ok(AuctionError::NoBid),
+            3 => Ok(AuctionError::WithdrawTooLarge),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bid_key_name, bidder_from_bid_key_name, AuctionError};
+    use crate::pos::stake_key_name;
+    use crate::value::account::PublicKey;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn bid_key_names_roundtrip() {
+        let bidder = PublicKey::new([0x5a; 32]);
+        let name = bid_key_name(bidder);
+        assert!(name.starts_with("b_5a5a"));
+        assert_eq!(bidder_from_bid_key_name(&name), Some(bidder));
+        assert_eq!(bidder_from_bid_key_name(&stake_key_name(bidder)), None);
+        assert_eq!(bidder_from_bid_key_name("bids_purse"), None);
+    }
+
+    #[test]
+    fn error_codes_roundtrip() {
+        let errors = [
+            AuctionError::InsufficientFunds,
+            AuctionError::NoBid,
+            AuctionError::WithdrawTooLarge,
+        ];
+        for error in &errors {
+            assert_eq!(AuctionError::try_from(i32::from(*error)), Ok(*error));
+        }
+        assert_eq!(AuctionError::try_from(0), Err(()));
+    }
+}
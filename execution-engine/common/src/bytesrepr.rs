@@ -420,6 +420,22 @@ where
     }
 }
 
+impl<T1: ToBytes, T2: ToBytes> ToBytes for (T1, T2) {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut result = self.0.to_bytes()?;
+        result.append(&mut self.1.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl<T1: FromBytes, T2: FromBytes> FromBytes for (T1, T2) {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (t1, rem): (T1, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (t2, rem): (T2, &[u8]) = FromBytes::from_bytes(rem)?;
+        Ok(((t1, t2), rem))
+    }
+}
+
 impl ToBytes for &str {
     fn to_bytes(&self) -> Result<Vec<u8>, Error> {
         if self.len() >= u32::max_value() as usize - U32_SIZE {
@@ -434,6 +450,30 @@ impl ToBytes for &str {
     }
 }
 
+/// Serializes a slice of arbitrary serializable items, prefixed by its length.
+pub fn vec_to_bytes<T: ToBytes>(items: &[T]) -> Result<Vec<u8>, Error> {
+    if items.len() >= u32::max_value() as usize - U32_SIZE {
+        return Err(Error::OutOfMemoryError);
+    }
+    let mut result = (items.len() as u32).to_bytes()?;
+    for item in items {
+        result.append(&mut item.to_bytes()?);
+    }
+    Ok(result)
+}
+
+/// Counterpart of `vec_to_bytes`.
+pub fn vec_from_bytes<T: FromBytes>(bytes: &[u8]) -> Result<(Vec<T>, &[u8]), Error> {
+    let (size, mut stream): (u32, &[u8]) = FromBytes::from_bytes(bytes)?;
+    let mut result = Vec::new();
+    for _ in 0..size {
+        let (item, rem): (T, &[u8]) = FromBytes::from_bytes(stream)?;
+        result.push(item);
+        stream = rem;
+    }
+    Ok((result, stream))
+}
+
 #[cfg(test)]
 mod proptests {
     // Bring the macros and other important things into scope.
@@ -504,6 +544,11 @@ mod proptests {
             assert!(test_serialization_roundtrip(&o));
         }
 
+        #[test]
+        fn test_pair(pair in (key_arb(), value_arb())) {
+            assert!(test_serialization_roundtrip(&pair));
+        }
+
         #[test]
         fn test_unit(unit in Just(())) {
             assert!(test_serialization_roundtrip(&unit));
@@ -543,6 +588,16 @@ mod proptests {
         fn test_access_rights(access_right in access_rights_arb()) {
             assert!(test_serialization_roundtrip(&access_right))
         }
+
+        #[test]
+        fn test_version_selector(selector in version_selector_arb()) {
+            assert!(test_serialization_roundtrip(&selector))
+        }
+
+        #[test]
+        fn test_phase(phase in phase_arb()) {
+            assert!(test_serialization_roundtrip(&phase))
+        }
     }
 
 }
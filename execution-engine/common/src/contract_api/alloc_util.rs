@@ -1,3 +1,4 @@
+use super::unwrap_or_revert::UnwrapOrRevert;
 use crate::bytesrepr::ToBytes;
 use alloc::alloc::{Alloc, Global};
 use alloc::vec::Vec;
@@ -16,14 +17,14 @@ pub fn alloc_bytes(n: usize) -> *mut u8 {
 // &str, but the compiler complains if I try to use the polymorphic
 // version with T = str.
 pub fn str_ref_to_ptr(t: &str) -> (*const u8, usize, Vec<u8>) {
-    let bytes = t.to_bytes().expect("Unable to serialize string");
+    let bytes = t.to_bytes().unwrap_or_revert();
     let ptr = bytes.as_ptr();
     let size = bytes.len();
     (ptr, size, bytes)
 }
 
 pub fn to_ptr<T: ToBytes>(t: &T) -> (*const u8, usize, Vec<u8>) {
-    let bytes = t.to_bytes().expect("Unable to serialize data");
+    let bytes = t.to_bytes().unwrap_or_revert();
     let ptr = bytes.as_ptr();
     let size = bytes.len();
     (ptr, size, bytes)
@@ -0,0 +1,40 @@
+//! Bidding for a seat in the validator set with the auction system contract.
+//!
+//! Bids are made by the account the deploy runs under. At the end of every
+//! era the highest bids, counting the tokens delegated to the bidders, win
+//! the seats of a later era and are bonded with the Proof-of-Stake contract.
+
+use super::alloc_util::to_ptr;
+use super::unknown_code;
+use crate::auction::AuctionError;
+use crate::ext_ffi;
+use crate::value::account::PurseId;
+use crate::value::U512;
+use core::convert::TryFrom;
+
+fn auction_result(code: i32) -> Result<(), AuctionError> {
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(AuctionError::try_from(code).unwrap_or_else(|_| unknown_code(code)))
+    }
+}
+
+/// Moves `amount` of tokens from `purse` to the bids purse of the auction
+/// contract, adding them to the bid of the account. The caller needs write
+/// rights to `purse`.
+pub fn add_bid(amount: U512, purse: PurseId) -> Result<(), AuctionError> {
+    let (amount_ptr, amount_size, _bytes) = to_ptr(&amount);
+    let (purse_ptr, purse_size, _bytes2) = to_ptr(&purse);
+    let code = unsafe { ext_ffi::add_bid(amount_ptr, amount_size, purse_ptr, purse_size) };
+    auction_result(code)
+}
+
+/// Takes `amount` of tokens off the bid of the account and pays them out to
+/// its main purse right away. Bids which won a seat are bonded already, and
+/// are unbonded with `pos::unbond` instead.
+pub fn withdraw_bid(amount: U512) -> Result<(), AuctionError> {
+    let (amount_ptr, amount_size, _bytes) = to_ptr(&amount);
+    let code = unsafe { ext_ffi::withdraw_bid(amount_ptr, amount_size) };
+    auction_result(code)
+}
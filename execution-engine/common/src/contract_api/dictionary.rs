@@ -0,0 +1,64 @@
+//! Key-value collections of unbounded size.
+//!
+//! The items of a dictionary are stored under local keys derived from a seed
+//! URef and the item's key, so they don't take up space in the named keys of
+//! the contract. The seed controls access: reading items requires it to be
+//! readable and putting them requires it to be writeable, so a dictionary is
+//! shared with other contexts by passing them a version of the seed.
+
+use super::alloc_util::to_ptr;
+use super::read_host_buffer;
+use super::unwrap_or_revert::UnwrapOrRevert;
+use crate::bytesrepr::deserialize;
+use crate::ext_ffi;
+use crate::key::Key;
+use crate::value::{StoredType, TypeMismatch, Value};
+use alloc::string::String;
+use core::convert::TryInto;
+
+/// Creates a new dictionary and stores its seed URef under `name` in the
+/// named keys of the current context. Returns the seed.
+pub fn new_dictionary(name: &str) -> Key {
+    let seed: Key = super::new_uref(String::from(name)).into();
+    super::add_uref(name, &seed);
+    seed
+}
+
+/// Stores `value` under `key` in the dictionary seeded by `seed_uref`,
+/// replacing the value stored there so far.
+pub fn dictionary_put<T: Into<Value>>(seed_uref: Key, key: &str, value: T) {
+    let (seed_ptr, seed_size, _bytes) = to_ptr(&seed_uref);
+    let value: Value = value.into();
+    let (value_ptr, value_size, _bytes2) = to_ptr(&value);
+    unsafe {
+        ext_ffi::dictionary_put(
+            seed_ptr,
+            seed_size,
+            key.as_ptr(),
+            key.len(),
+            value_ptr,
+            value_size,
+        )
+    };
+}
+
+/// Reads the value stored under `key` in the dictionary seeded by
+/// `seed_uref`, if there is one. Fails if the value isn't a `T`.
+pub fn dictionary_get<T: StoredType>(
+    seed_uref: Key,
+    key: &str,
+) -> Result<Option<T>, TypeMismatch> {
+    let (seed_ptr, seed_size, _bytes) = to_ptr(&seed_uref);
+    let value_size =
+        unsafe { ext_ffi::dictionary_get(seed_ptr, seed_size, key.as_ptr(), key.len()) };
+    if value_size == 0 {
+        return Ok(None);
+    }
+    let value_bytes = read_host_buffer(value_size);
+    let value: Value = deserialize(&value_bytes).unwrap_or_revert();
+    let found = value.type_string();
+    value
+        .try_into()
+        .map(Some)
+        .map_err(|_| TypeMismatch::new(T::type_string(), found))
+}
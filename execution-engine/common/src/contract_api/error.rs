@@ -0,0 +1,272 @@
+//! Status codes a contract can stop its execution with.
+//!
+//! A code is passed to the host through `contract_api::revert` as a `u32` and
+//! is reported back in the deploy's execution result. Codes up to
+//! `RESERVED_ERROR_MAX` belong to the system; contracts define their own
+//! errors with `ApiError::User`.
+
+use crate::bytesrepr;
+use crate::value::account::{KeyManagementError, TransferError};
+use crate::value::TypeMismatch;
+use alloc::string::String;
+use core::u16;
+
+/// Largest code reserved for the errors defined by the system.
+pub const RESERVED_ERROR_MAX: u32 = u16::MAX as u32;
+
+/// Offset added to the codes of `ApiError::User`.
+const USER_ERROR_OFFSET: u32 = RESERVED_ERROR_MAX + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiError {
+    /// A required argument was not passed to the contract.
+    MissingArgument,
+    /// An argument couldn't be deserialized into the expected type.
+    InvalidArgument,
+    /// Bytes couldn't be deserialized.
+    Deserialize,
+    /// A value couldn't be read from the global state.
+    Read,
+    /// A named key wasn't found.
+    MissingKey,
+    /// A key was of a different variant than expected.
+    UnexpectedKeyVariant,
+    /// A value was of a different type than expected.
+    UnexpectedValueVariant,
+    /// A contract to call wasn't found.
+    ContractNotFound,
+    /// A purse doesn't exist or isn't accessible.
+    InvalidPurse,
+    /// A transfer couldn't be made.
+    Transfer,
+    /// The caller doesn't have the access rights required by an operation.
+    NoAccessRights,
+    /// A contract called with a gas allowance ran out of it.
+    CalleeOutOfGas,
+    /// A value couldn't be serialized.
+    Serialize,
+    /// An optional value which was required was missing.
+    None,
+    /// The keys or action thresholds of an account couldn't be changed.
+    KeyManagement,
+    /// A code unknown to this version of the crate.
+    Unhandled(u32),
+    /// An error defined by the contract.
+    User(u16),
+}
+
+impl From<ApiError> for u32 {
+    fn from(error: ApiError) -> u32 {
+        match error {
+            ApiError::MissingArgument => 1,
+            ApiError::InvalidArgument => 2,
+            ApiError::Deserialize => 3,
+            ApiError::Read => 4,
+            ApiError::MissingKey => 5,
+            ApiError::UnexpectedKeyVariant => 6,
+            ApiError::UnexpectedValueVariant => 7,
+            ApiError::ContractNotFound => 8,
+            ApiError::InvalidPurse => 9,
+            ApiError::Transfer => 10,
+            ApiError::NoAccessRights => 11,
+            ApiError::CalleeOutOfGas => 12,
+            ApiError::Serialize => 13,
+            ApiError::None => 14,
+            ApiError::KeyManagement => 15,
+            ApiError::Unhandled(code) => code,
+            ApiError::User(code) => USER_ERROR_OFFSET + u32::from(code),
+        }
+    }
+}
+
+impl From<u32> for ApiError {
+    fn from(code: u32) -> ApiError {
+        match code {
+            1 => ApiError::MissingArgument,
+            2 => ApiError::InvalidArgument,
+            3 => ApiError::Deserialize,
+            4 => ApiError::Read,
+            5 => ApiError::MissingKey,
+            6 => ApiError::UnexpectedKeyVariant,
+            7 => ApiError::UnexpectedValueVariant,
+            8 => ApiError::ContractNotFound,
+            9 => ApiError::InvalidPurse,
+            10 => ApiError::Transfer,
+            11 => ApiError::NoAccessRights,
+            12 => ApiError::CalleeOutOfGas,
+            13 => ApiError::Serialize,
+            14 => ApiError::None,
+            15 => ApiError::KeyManagement,
+            code if code >= USER_ERROR_OFFSET && code - USER_ERROR_OFFSET <= RESERVED_ERROR_MAX => {
+                ApiError::User((code - USER_ERROR_OFFSET) as u16)
+            }
+            code => ApiError::Unhandled(code),
+        }
+    }
+}
+
+impl From<bytesrepr::Error> for ApiError {
+    fn from(error: bytesrepr::Error) -> ApiError {
+        match error {
+            bytesrepr::Error::OutOfMemoryError => ApiError::Serialize,
+            _ => ApiError::Deserialize,
+        }
+    }
+}
+
+impl From<TypeMismatch> for ApiError {
+    fn from(_error: TypeMismatch) -> ApiError {
+        ApiError::UnexpectedValueVariant
+    }
+}
+
+/// Reasons why `contract_api::get_arg` can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgError {
+    /// No argument was passed at the given index.
+    Missing(u32),
+    /// The argument at `index` isn't a serialized value of the requested
+    /// type. Arguments carry no type information of their own, so `error`
+    /// is the failure reported while deserializing them.
+    InvalidType { index: u32, error: bytesrepr::Error },
+    /// No argument was passed under the given name.
+    MissingNamed(String),
+    /// Like `InvalidType`, for the argument passed under `name`.
+    InvalidNamedType {
+        name: String,
+        error: bytesrepr::Error,
+    },
+}
+
+impl From<ArgError> for ApiError {
+    fn from(error: ArgError) -> ApiError {
+        match error {
+            ArgError::Missing(_) | ArgError::MissingNamed(_) => ApiError::MissingArgument,
+            ArgError::InvalidType { .. } | ArgError::InvalidNamedType { .. } => {
+                ApiError::InvalidArgument
+            }
+        }
+    }
+}
+
+/// Reasons why the functions of `contract_api` accessing the global state,
+/// calling contracts, transferring tokens or managing keys can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContractApiError {
+    /// Bytes couldn't be serialized or the host returned bytes which
+    /// couldn't be deserialized.
+    Bytesrepr(bytesrepr::Error),
+    /// The value under a pointer isn't of the type the pointer is typed with.
+    TypeMismatch(TypeMismatch),
+    /// The current context has no named key with the given name.
+    MissingKey(String),
+    /// A called contract returned a value of another type than expected,
+    /// named by the string.
+    UnexpectedReturnType(String),
+    /// A contract called with a gas allowance ran out of it.
+    CalleeOutOfGas,
+    Transfer(TransferError),
+    KeyManagement(KeyManagementError),
+    /// The host returned a status code this crate doesn't know about.
+    UnknownCode(i32),
+}
+
+impl From<bytesrepr::Error> for ContractApiError {
+    fn from(error: bytesrepr::Error) -> ContractApiError {
+        ContractApiError::Bytesrepr(error)
+    }
+}
+
+impl From<TypeMismatch> for ContractApiError {
+    fn from(error: TypeMismatch) -> ContractApiError {
+        ContractApiError::TypeMismatch(error)
+    }
+}
+
+impl From<TransferError> for ContractApiError {
+    fn from(error: TransferError) -> ContractApiError {
+        ContractApiError::Transfer(error)
+    }
+}
+
+impl From<KeyManagementError> for ContractApiError {
+    fn from(error: KeyManagementError) -> ContractApiError {
+        ContractApiError::KeyManagement(error)
+    }
+}
+
+impl From<ContractApiError> for ApiError {
+    fn from(error: ContractApiError) -> ApiError {
+        match error {
+            ContractApiError::Bytesrepr(error) => error.into(),
+            ContractApiError::TypeMismatch(_) | ContractApiError::UnexpectedReturnType(_) => {
+                ApiError::UnexpectedValueVariant
+            }
+            ContractApiError::MissingKey(_) => ApiError::MissingKey,
+            ContractApiError::CalleeOutOfGas => ApiError::CalleeOutOfGas,
+            ContractApiError::Transfer(_) => ApiError::Transfer,
+            ContractApiError::KeyManagement(_) => ApiError::KeyManagement,
+            ContractApiError::UnknownCode(code) => ApiError::Unhandled(code as u32),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiError, ArgError, ContractApiError, RESERVED_ERROR_MAX};
+    use crate::bytesrepr;
+    use crate::value::account::TransferError;
+    use crate::value::TypeMismatch;
+    use alloc::string::String;
+
+    #[test]
+    fn user_errors_are_outside_of_reserved_range() {
+        assert_eq!(u32::from(ApiError::User(0)), RESERVED_ERROR_MAX + 1);
+        assert_eq!(ApiError::from(RESERVED_ERROR_MAX + 1), ApiError::User(0));
+        assert_eq!(
+            ApiError::from(u32::from(ApiError::User(u16::max_value()))),
+            ApiError::User(u16::max_value())
+        );
+    }
+
+    #[test]
+    fn arg_errors_map_to_reserved_codes() {
+        assert_eq!(u32::from(ApiError::from(ArgError::Missing(3))), 1);
+        let invalid_type = ArgError::InvalidType {
+            index: 0,
+            error: bytesrepr::Error::EarlyEndOfStream,
+        };
+        assert_eq!(u32::from(ApiError::from(invalid_type)), 2);
+    }
+
+    #[test]
+    fn bytesrepr_errors_map_to_reserved_codes() {
+        assert_eq!(ApiError::from(bytesrepr::Error::OutOfMemoryError), ApiError::Serialize);
+        assert_eq!(ApiError::from(bytesrepr::Error::LeftOverBytes), ApiError::Deserialize);
+    }
+
+    #[test]
+    fn contract_api_errors_map_to_reserved_codes() {
+        let type_mismatch = TypeMismatch::new(String::from("Int32"), String::from("String"));
+        assert_eq!(
+            ApiError::from(ContractApiError::from(type_mismatch)),
+            ApiError::UnexpectedValueVariant
+        );
+        assert_eq!(
+            ApiError::from(ContractApiError::MissingKey(String::from("counter"))),
+            ApiError::MissingKey
+        );
+        assert_eq!(
+            ApiError::from(ContractApiError::from(TransferError::InsufficientFunds)),
+            ApiError::Transfer
+        );
+        assert_eq!(ApiError::from(ContractApiError::UnknownCode(99)), ApiError::Unhandled(99));
+    }
+
+    #[test]
+    fn codes_roundtrip() {
+        for code in 0..=(2 * RESERVED_ERROR_MAX + 2) {
+            assert_eq!(u32::from(ApiError::from(code)), code);
+        }
+    }
+}
@@ -1,54 +1,141 @@
 mod alloc_util;
 pub mod argsparser;
+pub mod auction;
+pub mod dictionary;
+pub mod error;
 pub mod pointers;
+pub mod pos;
+pub mod unwrap_or_revert;
 
 use self::alloc_util::*;
+use self::error::{ApiError, ArgError, ContractApiError};
 use self::pointers::*;
-use crate::bytesrepr::{deserialize, FromBytes, ToBytes};
+use self::unwrap_or_revert::UnwrapOrRevert;
+use crate::bytesrepr::{deserialize, vec_from_bytes, vec_to_bytes, FromBytes, ToBytes};
+use crate::crypto::{
+    RecoveryId, Secp256k1PublicKey, Secp256k1Signature, Signature, SECP256K1_PUBLIC_KEY_LENGTH,
+};
 use crate::ext_ffi;
-use crate::key::{Key, UREF_SIZE};
-use crate::value::{Contract, Value};
+use crate::key::{AccessError, AccessRights, Key, UREF_SIZE};
+use crate::phase::{Phase, PHASE_SIZE};
+use crate::protocol_version::{ProtocolVersion, PROTOCOL_VERSION_SIZE};
+use crate::system_contracts::SystemContractId;
+use crate::value::account::{
+    ActionType, KeyManagementError, PublicKey, PurseId, TransferError, TransferredTo, Weight,
+    PUBLIC_KEY_SIZE,
+};
+use crate::value::abi::Abi;
+use crate::value::contract::PROTOCOL_VERSION;
+use crate::value::contract_package::VersionSelector;
+use crate::value::{Contract, StoredType, TypeMismatch, Value, U512};
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::vec::Vec;
 use argsparser::ArgsParser;
 use core::convert::{TryFrom, TryInto};
 
-/// Read value under the key in the global state
-pub fn read<T>(u_ptr: UPointer<T>) -> T
-where
-    T: TryFrom<Value>,
-{
+/// Read value under the key in the global state. Fails if the value isn't of
+/// the type the pointer is typed with.
+pub fn read<T: StoredType>(u_ptr: UPointer<T>) -> Result<T, ContractApiError> {
     let key: Key = u_ptr.into();
-    let value = read_untyped(&key);
+    let value = read_untyped(&key)?;
+    let found = value.type_string();
     value
         .try_into()
-        .map_err(|_| "T could not be derived from Value")
-        .unwrap()
+        .map_err(|_| TypeMismatch::new(T::type_string(), found).into())
+}
+
+/// Like `read`, reverting if the value can't be read.
+pub fn read_or_revert<T: StoredType>(u_ptr: UPointer<T>) -> T {
+    read(u_ptr).unwrap_or_revert()
+}
+
+/// Copies the result of the last host function call out of the host buffer.
+/// Host functions with results of variable size return the size first, so
+/// that exactly `size` bytes can be allocated for them.
+fn read_host_buffer(size: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(size);
+    unsafe {
+        let copied = ext_ffi::read_host_buffer(bytes.as_mut_ptr(), size);
+        bytes.set_len(copied);
+    }
+    bytes
 }
 
-fn read_untyped(key: &Key) -> Value {
+fn read_untyped(key: &Key) -> Result<Value, ContractApiError> {
     // Note: _bytes is necessary to keep the Vec<u8> in scope. If _bytes is
     //      dropped then key_ptr becomes invalid.
 
     let (key_ptr, key_size, _bytes) = to_ptr(key);
     let value_size = unsafe { ext_ffi::read_value(key_ptr, key_size) };
-    let value_ptr = alloc_bytes(value_size);
-    let value_bytes = unsafe {
-        ext_ffi::get_read(value_ptr);
-        Vec::from_raw_parts(value_ptr, value_size, value_size)
-    };
-    deserialize(&value_bytes).unwrap()
+    let value_bytes = read_host_buffer(value_size);
+    Ok(deserialize(&value_bytes)?)
 }
 
-/// Write the value under the key in the global state
-pub fn write<T>(u_ptr: UPointer<T>, t: T)
-where
-    Value: From<T>,
-{
+/// Reads the values stored under all of `keys` with a single call to the
+/// host. The results are in the order of the keys; missing values are `None`.
+pub fn read_multi(keys: &[Key]) -> Vec<Option<Value>> {
+    let keys: Vec<Key> = keys.to_vec();
+    let (keys_ptr, keys_size, _bytes) = to_ptr(&keys);
+    let values_size = unsafe { ext_ffi::read_multi(keys_ptr, keys_size) };
+    let values_bytes = read_host_buffer(values_size);
+    let (values, _rem): (Vec<Option<Value>>, &[u8]) =
+        vec_from_bytes(&values_bytes).unwrap_or_revert();
+    values
+}
+
+/// Writes all of `entries` with a single call to the host. Either all the
+/// values are written or, if one of the keys isn't writeable, none are.
+pub fn write_multi(entries: &[(Key, Value)]) {
+    let bytes = vec_to_bytes(entries).unwrap_or_revert();
+    unsafe { ext_ffi::write_multi(bytes.as_ptr(), bytes.len()) }
+}
+
+/// Reads the value stored under `key_bytes` in the local storage of the
+/// current context, if there is one.
+pub fn read_local(key_bytes: &[u8]) -> Option<Value> {
+    let value_size = unsafe { ext_ffi::read_local(key_bytes.as_ptr(), key_bytes.len()) };
+    if value_size == 0 {
+        return None;
+    }
+    let value_bytes = read_host_buffer(value_size);
+    Some(deserialize(&value_bytes).unwrap_or_revert())
+}
+
+/// Writes `value` under `key_bytes` to the local storage of the current
+/// context. Local storage can't be reached from other contexts, so unlike
+/// with `write` no URef has to be created and shared.
+pub fn write_local(key_bytes: &[u8], value: &Value) {
+    let (value_ptr, value_size, _bytes) = to_ptr(value);
+    unsafe { ext_ffi::write_local(key_bytes.as_ptr(), key_bytes.len(), value_ptr, value_size) };
+}
+
+/// Write the value under the key in the global state. If the pointer can be
+/// read through, the value stored so far is checked to be of type `T` first
+/// and nothing is written when it isn't.
+pub fn write<T: StoredType>(u_ptr: UPointer<T>, t: T) -> Result<(), ContractApiError> {
+    let key = check_stored_type(u_ptr)?;
+    write_untyped(&key, &t.into());
+    Ok(())
+}
+
+/// Like `write`, reverting if the value can't be written.
+pub fn write_or_revert<T: StoredType>(u_ptr: UPointer<T>, t: T) {
+    write(u_ptr, t).unwrap_or_revert()
+}
+
+/// Checks that the value under `u_ptr` is a `T` if the pointer can be read
+/// through, and returns its key.
+fn check_stored_type<T: StoredType>(u_ptr: UPointer<T>) -> Result<Key, ContractApiError> {
+    let is_readable = u_ptr.rights().is_readable();
     let key = u_ptr.into();
-    let value = t.into();
-    write_untyped(&key, &value)
+    if is_readable {
+        let found = read_untyped(&key)?.type_string();
+        if found != T::type_string() {
+            return Err(TypeMismatch::new(T::type_string(), found).into());
+        }
+    }
+    Ok(key)
 }
 
 fn write_untyped(key: &Key, value: &Value) {
@@ -59,14 +146,18 @@ fn write_untyped(key: &Key, value: &Value) {
     }
 }
 
-/// Add the given value to the one currently under the key in the global state
-pub fn add<T>(u_ptr: UPointer<T>, t: T)
-where
-    Value: From<T>,
-{
-    let key = u_ptr.into();
-    let value = t.into();
-    add_untyped(&key, &value)
+/// Add the given value to the one currently under the key in the global state.
+/// Like with `write`, the value stored so far is checked to be of type `T`
+/// first if the pointer can be read through.
+pub fn add<T: StoredType>(u_ptr: UPointer<T>, t: T) -> Result<(), ContractApiError> {
+    let key = check_stored_type(u_ptr)?;
+    add_untyped(&key, &t.into());
+    Ok(())
+}
+
+/// Like `add`, reverting if the value can't be added.
+pub fn add_or_revert<T: StoredType>(u_ptr: UPointer<T>, t: T) {
+    add(u_ptr, t).unwrap_or_revert()
 }
 
 fn add_untyped(key: &Key, value: &Value) {
@@ -91,22 +182,35 @@ where
         ext_ffi::new_uref(key_ptr, value_ptr, value_size); // new_uref creates a URef with ReadWrite access writes
         Vec::from_raw_parts(key_ptr, UREF_SIZE, UREF_SIZE)
     };
-    let key: Key = deserialize(&bytes).unwrap();
+    let key: Key = deserialize(&bytes).unwrap_or_revert();
     if let Key::URef(id, access_rights) = key {
         UPointer::new(id, access_rights)
     } else {
-        panic!("URef FFI did not return a URef!");
+        revert(ApiError::UnexpectedKeyVariant)
     }
 }
 
+/// Like `new_uref`, but the current context is only granted `rights` to the
+/// new URef, e.g. to create a value nobody can change after initializing it.
+pub fn new_uref_with_rights<T>(init: T, rights: AccessRights) -> UPointer<T>
+where
+    Value: From<T>,
+{
+    let key_ptr = alloc_bytes(UREF_SIZE);
+    let value: Value = init.into();
+    let (value_ptr, value_size, _bytes2) = to_ptr(&value);
+    let bytes = unsafe {
+        ext_ffi::new_uref_with_rights(key_ptr, value_ptr, value_size, rights.bits().into());
+        Vec::from_raw_parts(key_ptr, UREF_SIZE, UREF_SIZE)
+    };
+    let key: Key = deserialize(&bytes).unwrap_or_revert();
+    key.to_u_ptr().unwrap_or_revert_with(ApiError::UnexpectedKeyVariant)
+}
+
 fn fn_bytes_by_name(name: &str) -> Vec<u8> {
     let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
     let fn_size = unsafe { ext_ffi::serialize_function(name_ptr, name_size) };
-    let fn_ptr = alloc_bytes(fn_size);
-    unsafe {
-        ext_ffi::get_function(fn_ptr);
-        Vec::from_raw_parts(fn_ptr, fn_size, fn_size)
-    }
+    read_host_buffer(fn_size)
 }
 
 // TODO: fn_by_name, fn_bytes_by_name and ext_ffi::serialize_function should be removed.
@@ -119,7 +223,7 @@ fn fn_bytes_by_name(name: &str) -> Vec<u8> {
 /// an unforgable reference.
 pub fn fn_by_name(name: &str, known_urefs: BTreeMap<String, Key>) -> Contract {
     let bytes = fn_bytes_by_name(name);
-    Contract::new(bytes, known_urefs)
+    Contract::new(bytes, known_urefs, PROTOCOL_VERSION)
 }
 
 /// Gets the serialized bytes of an exported function (see `fn_by_name`), then
@@ -136,38 +240,244 @@ pub fn store_function(name: &str, known_urefs: BTreeMap<String, Key>) -> Contrac
     ContractPointer::Hash(tmp)
 }
 
+/// Stores an exported function (see `fn_by_name`) under a hash of the
+/// contract's content. Unlike `store_function`, the address doesn't depend on
+/// the deploy, so storing the same function with the same `known_urefs` again
+/// returns the same key.
+pub fn store_function_at_hash(name: &str, known_urefs: BTreeMap<String, Key>) -> ContractPointer {
+    let (fn_ptr, fn_size, _bytes1) = str_ref_to_ptr(name);
+    let (urefs_ptr, urefs_size, _bytes2) = to_ptr(&known_urefs);
+    let mut tmp = [0u8; 32];
+    let tmp_ptr = tmp.as_mut_ptr();
+    unsafe {
+        ext_ffi::store_function_at_hash(fn_ptr, fn_size, urefs_ptr, urefs_size, tmp_ptr);
+    }
+    ContractPointer::Hash(tmp)
+}
+
+/// Replaces the code of the contract stored under `uref` with the exported
+/// function `name` (see `fn_by_name`). The contract keeps its named keys.
+/// The URef has to carry write rights.
+pub fn upgrade_contract_at_uref(name: &str, uref: UPointer<Contract>) {
+    let (name_ptr, name_size, _bytes1) = str_ref_to_ptr(name);
+    let key: Key = uref.into();
+    let (key_ptr, key_size, _bytes2) = to_ptr(&key);
+    unsafe { ext_ffi::upgrade_contract_at_uref(name_ptr, name_size, key_ptr, key_size) }
+}
+
+/// Stores the functions exported under `names` from the current module as a
+/// library. Instead of bundling the library's code, other contracts can import
+/// its functions from the Wasm module `lib:<hex encoded hash>`, where the hash
+/// is the one returned here; the host links them when the contract is run.
+/// Libraries run with their own memory and can't call host functions, so their
+/// functions should only take and return numbers.
+pub fn store_library(names: &[&str]) -> ContractPointer {
+    let names: Vec<String> = names.iter().map(|name| String::from(*name)).collect();
+    let (names_ptr, names_size, _bytes) = to_ptr(&names);
+    let mut tmp = [0u8; 32];
+    let tmp_ptr = tmp.as_mut_ptr();
+    unsafe {
+        ext_ffi::store_library(names_ptr, names_size, tmp_ptr);
+    }
+    ContractPointer::Hash(tmp)
+}
+
+/// Same as `store_function`, but also stores `abi` alongside the function so
+/// that clients can learn how to call it from the global state.
+pub fn store_function_with_abi(
+    name: &str,
+    known_urefs: BTreeMap<String, Key>,
+    abi: &Abi,
+) -> ContractPointer {
+    let (fn_ptr, fn_size, _bytes1) = str_ref_to_ptr(name);
+    let (urefs_ptr, urefs_size, _bytes2) = to_ptr(&known_urefs);
+    let (abi_ptr, abi_size, _bytes3) = to_ptr(abi);
+    let mut tmp = [0u8; 32];
+    let tmp_ptr = tmp.as_mut_ptr();
+    unsafe {
+        ext_ffi::store_function_with_abi(
+            fn_ptr, fn_size, urefs_ptr, urefs_size, abi_ptr, abi_size, tmp_ptr,
+        );
+    }
+    ContractPointer::Hash(tmp)
+}
+
 /// Finds function by the name and stores it at the unforgable name.
 pub fn store_function_at(name: &str, known_urefs: BTreeMap<String, Key>, uref: UPointer<Contract>) {
     let contract = fn_by_name(name, known_urefs);
-    write(uref, contract);
+    write_untyped(&uref.into(), &Value::Contract(contract));
+}
+
+/// Creates an empty contract package and returns a pointer to it together with
+/// the key required to add new versions. Calling the package runs its latest
+/// version.
+pub fn create_contract_package() -> (ContractPointer, Key) {
+    let mut package_hash = [0u8; 32];
+    let access_key_ptr = alloc_bytes(UREF_SIZE);
+    let access_key_bytes = unsafe {
+        ext_ffi::create_contract_package(package_hash.as_mut_ptr(), access_key_ptr);
+        Vec::from_raw_parts(access_key_ptr, UREF_SIZE, UREF_SIZE)
+    };
+    let access_key: Key = deserialize(&access_key_bytes).unwrap_or_revert();
+    (ContractPointer::Hash(package_hash), access_key)
+}
+
+/// Adds `contract` (e.g. the result of `store_function`) as the newest version
+/// of `package`, released under the `major` version. Returns the number of the
+/// new version; versions are numbered from 1. Contracts stored under URefs
+/// can't be added, as they could be upgraded in place.
+pub fn add_contract_version(
+    package: ContractPointer,
+    access_key: Key,
+    major: u32,
+    contract: ContractPointer,
+) -> u32 {
+    let package_key: Key = package.into();
+    let contract_key: Key = contract.into();
+    let (package_key_ptr, package_key_size, _bytes1) = to_ptr(&package_key);
+    let (access_key_ptr, access_key_size, _bytes2) = to_ptr(&access_key);
+    let (contract_key_ptr, contract_key_size, _bytes3) = to_ptr(&contract_key);
+    unsafe {
+        ext_ffi::add_contract_version(
+            package_key_ptr,
+            package_key_size,
+            access_key_ptr,
+            access_key_size,
+            major,
+            contract_key_ptr,
+            contract_key_size,
+        )
+    }
+}
+
+/// Disables the given version of `package`, so that it can't be called anymore.
+/// Calling the package then runs the newest version which isn't disabled.
+pub fn disable_contract_version(package: ContractPointer, access_key: Key, version: u32) {
+    let package_key: Key = package.into();
+    let (package_key_ptr, package_key_size, _bytes1) = to_ptr(&package_key);
+    let (access_key_ptr, access_key_size, _bytes2) = to_ptr(&access_key);
+    unsafe {
+        ext_ffi::disable_contract_version(
+            package_key_ptr,
+            package_key_size,
+            access_key_ptr,
+            access_key_size,
+            version,
+        );
+    }
+}
+
+/// Locks `package` for good. Afterwards no versions can be added and its user
+/// groups can't be changed.
+pub fn lock_contract_package(package: ContractPointer, access_key: Key) {
+    let package_key: Key = package.into();
+    let (package_key_ptr, package_key_size, _bytes1) = to_ptr(&package_key);
+    let (access_key_ptr, access_key_size, _bytes2) = to_ptr(&access_key);
+    unsafe {
+        ext_ffi::lock_contract_package(
+            package_key_ptr,
+            package_key_size,
+            access_key_ptr,
+            access_key_size,
+        );
+    }
+}
+
+/// Adds a new URef to the user group `group` of `package`, creating the group
+/// if needed, and returns it. Calling an entry point which lists the group in
+/// its ABI requires passing one of the group's URefs in `extra_urefs` of
+/// `call_contract`.
+pub fn create_contract_user_group(package: ContractPointer, access_key: Key, group: &str) -> Key {
+    let package_key: Key = package.into();
+    let (package_key_ptr, package_key_size, _bytes1) = to_ptr(&package_key);
+    let (access_key_ptr, access_key_size, _bytes2) = to_ptr(&access_key);
+    let (group_ptr, group_size, _bytes3) = str_ref_to_ptr(group);
+    let dest_ptr = alloc_bytes(UREF_SIZE);
+    let key_bytes = unsafe {
+        ext_ffi::create_contract_user_group(
+            package_key_ptr,
+            package_key_size,
+            access_key_ptr,
+            access_key_size,
+            group_ptr,
+            group_size,
+            dest_ptr,
+        );
+        Vec::from_raw_parts(dest_ptr, UREF_SIZE, UREF_SIZE)
+    };
+    deserialize(&key_bytes).unwrap_or_revert()
+}
+
+/// Removes the user group `group` from `package`, revoking access of all the
+/// URefs created for it.
+pub fn remove_contract_user_group(package: ContractPointer, access_key: Key, group: &str) {
+    let package_key: Key = package.into();
+    let (package_key_ptr, package_key_size, _bytes1) = to_ptr(&package_key);
+    let (access_key_ptr, access_key_size, _bytes2) = to_ptr(&access_key);
+    let (group_ptr, group_size, _bytes3) = str_ref_to_ptr(group);
+    unsafe {
+        ext_ffi::remove_contract_user_group(
+            package_key_ptr,
+            package_key_size,
+            access_key_ptr,
+            access_key_size,
+            group_ptr,
+            group_size,
+        );
+    }
 }
 
 /// Return the i-th argument passed to the host for the current module
 /// invokation. Note that this is only relevent to contracts stored on-chain
 /// since a contract deployed directly is not invoked with any arguments.
-pub fn get_arg<T: FromBytes>(i: u32) -> T {
+/// Fails if there is no i-th argument or if it isn't a serialized `T`; the
+/// error can be passed on to `revert` with `.into()`.
+pub fn get_arg<T: FromBytes>(i: u32) -> Result<T, ArgError> {
     let arg_size = unsafe { ext_ffi::load_arg(i) };
-    let dest_ptr = alloc_bytes(arg_size);
-    let arg_bytes = unsafe {
-        ext_ffi::get_arg(dest_ptr);
-        Vec::from_raw_parts(dest_ptr, arg_size, arg_size)
-    };
-    // TODO: better error handling (i.e. pass the `Result` on)
-    deserialize(&arg_bytes).unwrap()
+    if arg_size < 0 {
+        return Err(ArgError::Missing(i));
+    }
+    deserialize(&read_host_buffer(arg_size as usize))
+        .map_err(|error| ArgError::InvalidType { index: i, error })
+}
+
+/// Return the argument passed to the current module invokation under `name`.
+/// Only deploys can pass named arguments; stored contracts called by other
+/// contracts receive positional arguments only.
+pub fn get_named_arg<T: FromBytes>(name: &str) -> Result<T, ArgError> {
+    let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
+    let arg_size = unsafe { ext_ffi::load_named_arg(name_ptr, name_size) };
+    if arg_size < 0 {
+        return Err(ArgError::MissingNamed(String::from(name)));
+    }
+    deserialize(&read_host_buffer(arg_size as usize)).map_err(|error| {
+        ArgError::InvalidNamedType {
+            name: String::from(name),
+            error,
+        }
+    })
 }
 
 /// Return the unforgable reference known by the current module under the given name.
 /// This either comes from the known_urefs of the account or contract,
-/// depending on whether the current module is a sub-call or not.
-pub fn get_uref(name: &str) -> Key {
+/// depending on whether the current module is a sub-call or not. Fails with
+/// `ContractApiError::MissingKey` if there is no such name.
+pub fn get_uref(name: &str) -> Result<Key, ContractApiError> {
+    if !has_uref(name) {
+        return Err(ContractApiError::MissingKey(String::from(name)));
+    }
     let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
     let dest_ptr = alloc_bytes(UREF_SIZE);
     let uref_bytes = unsafe {
         ext_ffi::get_uref(name_ptr, name_size, dest_ptr);
         Vec::from_raw_parts(dest_ptr, UREF_SIZE, UREF_SIZE)
     };
-    // TODO: better error handling (i.e. pass the `Result` on)
-    deserialize(&uref_bytes).unwrap()
+    Ok(deserialize(&uref_bytes)?)
+}
+
+/// Like `get_uref`, reverting if there is no such name.
+pub fn get_uref_or_revert(name: &str) -> Key {
+    get_uref(name).unwrap_or_revert()
 }
 
 /// Check if the given name corresponds to a known unforgable reference
@@ -177,6 +487,63 @@ pub fn has_uref(name: &str) -> bool {
     result == 0
 }
 
+/// Checks whether the current context can use `key`. Returns `false` for a
+/// URef which was forged or which carries more rights than were granted to
+/// the context. Keys of other variants are always valid.
+pub fn is_valid(key: &Key) -> bool {
+    let (key_ptr, key_size, _bytes) = to_ptr(key);
+    let result = unsafe { ext_ffi::is_valid(key_ptr, key_size) };
+    result != 0
+}
+
+/// Checks whether the current context can use `key` with `required_rights`
+/// before, e.g., storing a key received from another contract. Fails with
+/// `AccessError::ForgedReference` if the key is a forged URef or carries
+/// more rights than were granted, and with `AccessError::InsufficientRights`
+/// if it doesn't allow the requested reads, writes or additions.
+pub fn validate_key(key: &Key, required_rights: AccessRights) -> Result<(), AccessError> {
+    let (key_ptr, key_size, _bytes) = to_ptr(key);
+    let code = unsafe { ext_ffi::validate_key(key_ptr, key_size, required_rights.bits().into()) };
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(AccessError::try_from(code).unwrap_or_else(|_| unknown_code(code)))
+    }
+}
+
+/// Removes the key known under `name` from the named keys of the current
+/// context. The change is written back to the account running the session
+/// code or to the stored contract. Does nothing if there is no such key.
+pub fn remove_uref(name: &str) {
+    let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
+    unsafe { ext_ffi::remove_uref(name_ptr, name_size) };
+}
+
+/// Number of named keys requested at once by `list_named_keys`.
+const NAMED_KEYS_PAGE_SIZE: u32 = 100;
+
+/// Returns all the named keys of the current context. Large maps are fetched
+/// from the host in pages, see `list_named_keys_page`.
+pub fn list_named_keys() -> BTreeMap<String, Key> {
+    let mut named_keys = BTreeMap::new();
+    loop {
+        let mut page = list_named_keys_page(named_keys.len() as u32, NAMED_KEYS_PAGE_SIZE);
+        if page.is_empty() {
+            return named_keys;
+        }
+        named_keys.append(&mut page);
+    }
+}
+
+/// Returns up to `limit` named keys of the current context, skipping the
+/// first `offset` ones in the order of their names. The host may return fewer
+/// keys than requested; an empty map means there are no more keys.
+pub fn list_named_keys_page(offset: u32, limit: u32) -> BTreeMap<String, Key> {
+    let page_size = unsafe { ext_ffi::list_named_keys(offset, limit) };
+    let page_bytes = read_host_buffer(page_size);
+    deserialize(&page_bytes).unwrap_or_revert()
+}
+
 /// Add the given key to the known_urefs map under the given name
 pub fn add_uref(name: &str, key: &Key) {
     let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
@@ -184,42 +551,514 @@ pub fn add_uref(name: &str, key: &Key) {
     unsafe { ext_ffi::add_uref(name_ptr, name_size, key_ptr, key_size) };
 }
 
+/// Stores `key` under `name` in the named keys of the currently executing
+/// contract. Unlike `add_uref`, this can't be used from session code and it
+/// replaces any key already known under `name`.
+pub fn put_key(name: &str, key: &Key) {
+    let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
+    let (key_ptr, key_size, _bytes2) = to_ptr(key);
+    unsafe { ext_ffi::put_key(name_ptr, name_size, key_ptr, key_size) };
+}
+
+/// Removes the key known under `name` from the named keys of the currently
+/// executing contract.
+pub fn remove_key(name: &str) {
+    let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
+    unsafe { ext_ffi::remove_key(name_ptr, name_size) };
+}
+
+/// Returns the key known under `name` to the currently executing contract, if
+/// there is one.
+pub fn get_key(name: &str) -> Option<Key> {
+    let (name_ptr, name_size, _bytes) = str_ref_to_ptr(name);
+    let dest_ptr = alloc_bytes(UREF_SIZE);
+    let key_bytes = unsafe {
+        let key_size = ext_ffi::get_key(name_ptr, name_size, dest_ptr);
+        Vec::from_raw_parts(dest_ptr, key_size, UREF_SIZE)
+    };
+    if key_bytes.is_empty() {
+        None
+    } else {
+        Some(deserialize(&key_bytes).unwrap_or_revert())
+    }
+}
+
+/// Returns a pointer to the system contract `id`, which the engine was told
+/// about when it was started.
+pub fn get_system_contract(id: SystemContractId) -> ContractPointer {
+    let dest_ptr = alloc_bytes(UREF_SIZE);
+    let key_bytes = unsafe {
+        let key_size = ext_ffi::get_system_contract(id.into(), dest_ptr);
+        Vec::from_raw_parts(dest_ptr, key_size, UREF_SIZE)
+    };
+    let key: Key = deserialize(&key_bytes).unwrap_or_revert();
+    key.to_c_ptr().unwrap_or_revert_with(ApiError::UnexpectedKeyVariant)
+}
+
+/// Returns the public key of the account which initiated the deploy.
+/// Note that this is always the deploying account, even if the current
+/// module was invoked by another contract.
+pub fn get_caller() -> PublicKey {
+    let dest_ptr = alloc_bytes(PUBLIC_KEY_SIZE);
+    let key_bytes = unsafe {
+        ext_ffi::get_caller(dest_ptr);
+        Vec::from_raw_parts(dest_ptr, PUBLIC_KEY_SIZE, PUBLIC_KEY_SIZE)
+    };
+    deserialize(&key_bytes).unwrap_or_revert()
+}
+
+/// Returns the phase of the deploy execution in which the current code runs.
+pub fn get_phase() -> Phase {
+    let dest_ptr = alloc_bytes(PHASE_SIZE);
+    let phase_bytes = unsafe {
+        ext_ffi::get_phase(dest_ptr);
+        Vec::from_raw_parts(dest_ptr, PHASE_SIZE, PHASE_SIZE)
+    };
+    deserialize(&phase_bytes).unwrap_or_revert()
+}
+
+/// Returns the hash of the deploy being executed. It's the same in every
+/// contract called by the deploy, so it can be used to record that the
+/// deploy was already processed.
+pub fn get_deploy_hash() -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    unsafe {
+        ext_ffi::get_deploy_hash(hash.as_mut_ptr());
+    }
+    hash
+}
+
+/// Returns the version of the protocol the deploy runs under.
+pub fn get_protocol_version() -> ProtocolVersion {
+    let dest_ptr = alloc_bytes(PROTOCOL_VERSION_SIZE);
+    let version_bytes = unsafe {
+        ext_ffi::get_protocol_version(dest_ptr);
+        Vec::from_raw_parts(dest_ptr, PROTOCOL_VERSION_SIZE, PROTOCOL_VERSION_SIZE)
+    };
+    deserialize(&version_bytes).unwrap_or_revert()
+}
+
+/// Returns the gas the current code can still spend. Gas is charged for
+/// whole blocks of instructions at once, so the value is an approximation;
+/// long-running contracts can use it to stop early and save their progress.
+pub fn gas_remaining() -> u64 {
+    unsafe { ext_ffi::gas_remaining() }
+}
+
+/// Returns the height of the block the deploy is executed in, for logic which
+/// follows the progress of the chain rather than time.
+pub fn get_block_height() -> u64 {
+    unsafe { ext_ffi::get_block_height() }
+}
+
+/// Returns the number of contract calls the current code is nested in;
+/// session code runs at depth 0. Calls nested deeper than the engine allows
+/// fail the deploy, so recursive contracts can use it to stop in time.
+pub fn call_stack_depth() -> u32 {
+    unsafe { ext_ffi::call_stack_depth() }
+}
+
+/// Replaces the main purse of the deploying account with a new one and moves
+/// the whole balance into it. Useful when a reference to the old purse leaked.
+/// Requires the deploy to be signed with keys meeting the account's key
+/// management threshold. Returns the new purse.
+pub fn rotate_main_purse() -> PurseId {
+    let dest_ptr = alloc_bytes(UREF_SIZE);
+    let purse_bytes = unsafe {
+        ext_ffi::rotate_main_purse(dest_ptr);
+        Vec::from_raw_parts(dest_ptr, UREF_SIZE, UREF_SIZE)
+    };
+    deserialize(&purse_bytes).unwrap_or_revert()
+}
+
+/// Reverts when the host returns a status code this crate doesn't know about.
+fn unknown_code(code: i32) -> ! {
+    revert(ApiError::Unhandled(code as u32))
+}
+
+fn key_management_result(code: i32) -> Result<(), ContractApiError> {
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(KeyManagementError::try_from(code)
+            .map(ContractApiError::from)
+            .unwrap_or_else(|_| ContractApiError::UnknownCode(code)))
+    }
+}
+
+/// Transfers `amount` of tokens from the main purse of the deploying account
+/// to the main purse of the account owned by `target`, creating that account
+/// if it doesn't exist yet. Only allowed from the account's context.
+pub fn transfer_to_account(
+    target: PublicKey,
+    amount: U512,
+) -> Result<TransferredTo, ContractApiError> {
+    let (target_ptr, target_size, _bytes) = to_ptr(&target);
+    let (amount_ptr, amount_size, _bytes2) = to_ptr(&amount);
+    let code =
+        unsafe { ext_ffi::transfer_to_account(target_ptr, target_size, amount_ptr, amount_size) };
+    match TransferredTo::try_from(code) {
+        Ok(transferred_to) => Ok(transferred_to),
+        Err(()) => Err(transfer_error(code)),
+    }
+}
+
+/// Like `transfer_to_account`, reverting if the transfer can't be made.
+pub fn transfer_to_account_or_revert(target: PublicKey, amount: U512) -> TransferredTo {
+    transfer_to_account(target, amount).unwrap_or_revert()
+}
+
+/// Returns the Blake2b-256 digest of `data`. The host charges gas for every
+/// hashed byte, which is still much cheaper than hashing in Wasm.
+pub fn blake2b256(data: &[u8]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    unsafe {
+        ext_ffi::blake2b(data.as_ptr(), data.len(), digest.as_mut_ptr());
+    }
+    digest
+}
+
+/// Checks that `signature` is a valid Ed25519 signature of `message` made by
+/// the secret key belonging to `public_key`.
+pub fn verify_ed25519(message: &[u8], signature: &Signature, public_key: &PublicKey) -> bool {
+    let public_key = public_key.value();
+    let result = unsafe {
+        ext_ffi::verify_ed25519(
+            message.as_ptr(),
+            message.len(),
+            signature.as_bytes().as_ptr(),
+            public_key.as_ptr(),
+        )
+    };
+    result != 0
+}
+
+/// Checks that `signature` is a valid secp256k1 signature of the 32 byte
+/// `digest` made by the secret key belonging to `public_key`.
+pub fn verify_secp256k1(
+    digest: &[u8; 32],
+    signature: &Secp256k1Signature,
+    public_key: &Secp256k1PublicKey,
+) -> bool {
+    let result = unsafe {
+        ext_ffi::verify_secp256k1(
+            digest.as_ptr(),
+            signature.as_bytes().as_ptr(),
+            public_key.as_bytes().as_ptr(),
+        )
+    };
+    result != 0
+}
+
+/// Recovers the public key which made the secp256k1 `signature` of the 32
+/// byte `digest`. Returns `None` if no key could have made the signature.
+pub fn recover_secp256k1(
+    digest: &[u8; 32],
+    signature: &Secp256k1Signature,
+    recovery_id: RecoveryId,
+) -> Option<Secp256k1PublicKey> {
+    let mut public_key = [0u8; SECP256K1_PUBLIC_KEY_LENGTH];
+    let result = unsafe {
+        ext_ffi::recover_secp256k1(
+            digest.as_ptr(),
+            signature.as_bytes().as_ptr(),
+            u32::from(recovery_id.value()),
+            public_key.as_mut_ptr(),
+        )
+    };
+    if result != 0 {
+        Some(Secp256k1PublicKey::new(public_key))
+    } else {
+        None
+    }
+}
+
+/// Logs `message` if the engine runs with contract debugging enabled and does
+/// nothing otherwise. Meant for tracing contracts in test environments.
+pub fn print(message: &str) {
+    let (message_ptr, message_size, _bytes) = str_ref_to_ptr(message);
+    unsafe { ext_ffi::print(message_ptr, message_size) };
+}
+
+/// Emits an event which off-chain consumers can find in the results of the
+/// deploy. Events are not stored in the global state. The deploy fails if
+/// the event is too large or it has emitted too many events.
+pub fn emit_event(topic: &str, data: &Value) {
+    let (topic_ptr, topic_size, _bytes) = str_ref_to_ptr(topic);
+    let (data_ptr, data_size, _bytes2) = to_ptr(data);
+    unsafe { ext_ffi::emit_event(topic_ptr, topic_size, data_ptr, data_size) };
+}
+
+/// Returns 32 random bytes which every validator agrees on. Every call returns
+/// different bytes, but they are derived from the deploy and its phase, so
+/// anyone who knows the deploy can predict them. Don't use them as secrets.
+pub fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    unsafe {
+        ext_ffi::random_bytes(bytes.as_mut_ptr());
+    }
+    bytes
+}
+
+/// Returns the main purse of the deploying account. Only available to
+/// session code; stored contracts get purses passed to them instead.
+pub fn main_purse() -> PurseId {
+    let dest_ptr = alloc_bytes(UREF_SIZE);
+    let purse_bytes = unsafe {
+        ext_ffi::get_main_purse(dest_ptr);
+        Vec::from_raw_parts(dest_ptr, UREF_SIZE, UREF_SIZE)
+    };
+    deserialize(&purse_bytes).unwrap_or_revert()
+}
+
+/// Returns the purse the payment code pays the deploy's gas into. Only
+/// available to payment code; the reference carries add rights only.
+pub fn payment_purse() -> PurseId {
+    let dest_ptr = alloc_bytes(UREF_SIZE);
+    let purse_bytes = unsafe {
+        ext_ffi::get_payment_purse(dest_ptr);
+        Vec::from_raw_parts(dest_ptr, UREF_SIZE, UREF_SIZE)
+    };
+    deserialize(&purse_bytes).unwrap_or_revert()
+}
+
+/// Creates a new, empty purse. The returned reference carries full rights,
+/// so it should only be handed out after attenuating it with
+/// `PurseId::with_access_rights`.
+pub fn create_purse() -> PurseId {
+    let dest_ptr = alloc_bytes(UREF_SIZE);
+    let purse_bytes = unsafe {
+        ext_ffi::create_purse(dest_ptr);
+        Vec::from_raw_parts(dest_ptr, UREF_SIZE, UREF_SIZE)
+    };
+    deserialize(&purse_bytes).unwrap_or_revert()
+}
+
+/// Transfers `amount` of tokens from `source` to `target`. The caller has to
+/// hold `source` with write rights and `target` with add rights.
+pub fn transfer_from_purse_to_purse(
+    source: PurseId,
+    target: PurseId,
+    amount: U512,
+) -> Result<(), ContractApiError> {
+    let (source_ptr, source_size, _bytes) = to_ptr(&source);
+    let (target_ptr, target_size, _bytes2) = to_ptr(&target);
+    let (amount_ptr, amount_size, _bytes3) = to_ptr(&amount);
+    let code = unsafe {
+        ext_ffi::transfer_from_purse_to_purse(
+            source_ptr,
+            source_size,
+            target_ptr,
+            target_size,
+            amount_ptr,
+            amount_size,
+        )
+    };
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(transfer_error(code))
+    }
+}
+
+/// Like `transfer_from_purse_to_purse`, reverting if the transfer can't be
+/// made.
+pub fn transfer_from_purse_to_purse_or_revert(source: PurseId, target: PurseId, amount: U512) {
+    transfer_from_purse_to_purse(source, target, amount).unwrap_or_revert()
+}
+
+fn transfer_error(code: i32) -> ContractApiError {
+    TransferError::try_from(code)
+        .map(ContractApiError::from)
+        .unwrap_or_else(|_| ContractApiError::UnknownCode(code))
+}
+
+/// Associates `public_key` with the deploying account. Requires the deploy to
+/// be signed with keys meeting the account's key management threshold.
+pub fn add_associated_key(public_key: PublicKey, weight: Weight) -> Result<(), ContractApiError> {
+    let (public_key_ptr, public_key_size, _bytes) = to_ptr(&public_key);
+    let result = unsafe {
+        ext_ffi::add_associated_key(public_key_ptr, public_key_size, u32::from(weight.value()))
+    };
+    key_management_result(result)
+}
+
+/// Like `add_associated_key`, reverting if the key can't be associated.
+pub fn add_associated_key_or_revert(public_key: PublicKey, weight: Weight) {
+    add_associated_key(public_key, weight).unwrap_or_revert()
+}
+
+/// Removes `public_key` from the keys associated with the deploying account.
+/// Fails if the remaining keys couldn't meet the account's action thresholds.
+pub fn remove_associated_key(public_key: PublicKey) -> Result<(), ContractApiError> {
+    let (public_key_ptr, public_key_size, _bytes) = to_ptr(&public_key);
+    let result = unsafe { ext_ffi::remove_associated_key(public_key_ptr, public_key_size) };
+    key_management_result(result)
+}
+
+/// Like `remove_associated_key`, reverting if the key can't be removed.
+pub fn remove_associated_key_or_revert(public_key: PublicKey) {
+    remove_associated_key(public_key).unwrap_or_revert()
+}
+
+/// Sets the deploying account's threshold for `action_type`.
+pub fn set_action_threshold(
+    action_type: ActionType,
+    threshold: Weight,
+) -> Result<(), ContractApiError> {
+    let result = unsafe {
+        ext_ffi::set_action_threshold(action_type as u32, u32::from(threshold.value()))
+    };
+    key_management_result(result)
+}
+
+/// Like `set_action_threshold`, reverting if the threshold can't be set.
+pub fn set_action_threshold_or_revert(action_type: ActionType, threshold: Weight) {
+    set_action_threshold(action_type, threshold).unwrap_or_revert()
+}
+
 /// Return `t` to the host, terminating the currently running module.
 /// Note this function is only relevent to contracts stored on chain which
 /// return a value to their caller. The return value of a directly deployed
 /// contract is never looked at.
 #[allow(clippy::ptr_arg)]
-pub fn ret<T: ToBytes>(t: &T, extra_urefs: &Vec<Key>) -> ! {
-    let (ptr, size, _bytes) = to_ptr(t);
+pub fn ret<T>(t: T, extra_urefs: &Vec<Key>) -> !
+where
+    Value: From<T>,
+{
+    let value: Value = t.into();
+    let (ptr, size, _bytes) = to_ptr(&value);
     let (urefs_ptr, urefs_size, _bytes2) = to_ptr(extra_urefs);
     unsafe {
         ext_ffi::ret(ptr, size, urefs_ptr, urefs_size);
     }
 }
 
+/// Stops the execution of the currently running module with the given error.
+/// All changes made by the deploy are discarded and the error code is reported
+/// in the deploy's execution result.
+pub fn revert(error: ApiError) -> ! {
+    unsafe {
+        ext_ffi::revert(error.into());
+    }
+}
+
 /// Call the given contract, passing the given (serialized) arguments to
 /// the host in order to have them available to the called contract during its
 /// execution. The value returned from the contract call (see `ret` above) is
-/// returned from this function.
+/// returned from this function. Fails with
+/// `ContractApiError::UnexpectedReturnType` if the value isn't a `T`.
 #[allow(clippy::ptr_arg)]
-pub fn call_contract<A: ArgsParser, T: FromBytes>(
+pub fn call_contract<A: ArgsParser, T: TryFrom<Value>>(
     c_ptr: ContractPointer,
     args: &A,
     extra_urefs: &Vec<Key>,
-) -> T {
+) -> Result<T, ContractApiError> {
     let contract_key: Key = c_ptr.into();
     let (key_ptr, key_size, _bytes1) = to_ptr(&contract_key);
-    let (args_ptr, args_size, _bytes2) = ArgsParser::parse(args).map(|args| to_ptr(&args)).unwrap();
+    let (args_ptr, args_size, _bytes2) = to_ptr(&ArgsParser::parse(args)?);
     let (urefs_ptr, urefs_size, _bytes3) = to_ptr(extra_urefs);
     let res_size = unsafe {
         ext_ffi::call_contract(
             key_ptr, key_size, args_ptr, args_size, urefs_ptr, urefs_size,
         )
     };
-    let res_ptr = alloc_bytes(res_size);
-    let res_bytes = unsafe {
-        ext_ffi::get_call_result(res_ptr);
-        Vec::from_raw_parts(res_ptr, res_size, res_size)
+    call_result(res_size)
+}
+
+/// Like `call_contract`, reverting if the call fails.
+#[allow(clippy::ptr_arg)]
+pub fn call_contract_or_revert<A: ArgsParser, T: TryFrom<Value>>(
+    c_ptr: ContractPointer,
+    args: &A,
+    extra_urefs: &Vec<Key>,
+) -> T {
+    call_contract(c_ptr, args, extra_urefs).unwrap_or_revert()
+}
+
+/// Like `call_contract`, but when `c_ptr` points at a contract package, runs
+/// the version picked by `selector` instead of the latest one.
+#[allow(clippy::ptr_arg)]
+pub fn call_versioned_contract<A: ArgsParser, T: TryFrom<Value>>(
+    c_ptr: ContractPointer,
+    selector: VersionSelector,
+    args: &A,
+    extra_urefs: &Vec<Key>,
+) -> Result<T, ContractApiError> {
+    let contract_key: Key = c_ptr.into();
+    let (key_ptr, key_size, _bytes1) = to_ptr(&contract_key);
+    let (selector_ptr, selector_size, _bytes2) = to_ptr(&selector);
+    let (args_ptr, args_size, _bytes3) = to_ptr(&ArgsParser::parse(args)?);
+    let (urefs_ptr, urefs_size, _bytes4) = to_ptr(extra_urefs);
+    let res_size = unsafe {
+        ext_ffi::call_versioned_contract(
+            key_ptr,
+            key_size,
+            selector_ptr,
+            selector_size,
+            args_ptr,
+            args_size,
+            urefs_ptr,
+            urefs_size,
+        )
+    };
+    call_result(res_size)
+}
+
+/// Like `call_versioned_contract`, reverting if the call fails.
+#[allow(clippy::ptr_arg)]
+pub fn call_versioned_contract_or_revert<A: ArgsParser, T: TryFrom<Value>>(
+    c_ptr: ContractPointer,
+    selector: VersionSelector,
+    args: &A,
+    extra_urefs: &Vec<Key>,
+) -> T {
+    call_versioned_contract(c_ptr, selector, args, extra_urefs).unwrap_or_revert()
+}
+
+/// Like `call_contract`, but the callee can spend at most `gas_allowance` of
+/// the gas left to the deploy. If it runs out of it, the changes it made are
+/// discarded and `ContractApiError::CalleeOutOfGas` is returned instead of the
+/// whole deploy failing. The gas used by the callee is charged either way. A
+/// value of another type than `T` is reported as
+/// `ContractApiError::UnexpectedReturnType`.
+#[allow(clippy::ptr_arg)]
+pub fn call_contract_with_gas<A: ArgsParser, T: TryFrom<Value>>(
+    c_ptr: ContractPointer,
+    args: &A,
+    extra_urefs: &Vec<Key>,
+    gas_allowance: u64,
+) -> Result<T, ContractApiError> {
+    let contract_key: Key = c_ptr.into();
+    let (key_ptr, key_size, _bytes1) = to_ptr(&contract_key);
+    let (args_ptr, args_size, _bytes2) = to_ptr(&ArgsParser::parse(args)?);
+    let (urefs_ptr, urefs_size, _bytes3) = to_ptr(extra_urefs);
+    let res_size = unsafe {
+        ext_ffi::call_contract_with_gas(
+            key_ptr,
+            key_size,
+            args_ptr,
+            args_size,
+            urefs_ptr,
+            urefs_size,
+            gas_allowance,
+        )
     };
-    deserialize(&res_bytes).unwrap()
+    if res_size < 0 {
+        return Err(ContractApiError::CalleeOutOfGas);
+    }
+    call_result(res_size as usize)
+}
+
+/// Reads the `res_size` bytes long result of the last contract call and
+/// converts it to `T`. Fails with `ContractApiError::UnexpectedReturnType`
+/// if the called contract returned a value of another type.
+fn call_result<T: TryFrom<Value>>(res_size: usize) -> Result<T, ContractApiError> {
+    let res_bytes = read_host_buffer(res_size);
+    // The host only lets contracts return serialized `Value`s.
+    let value: Value = deserialize(&res_bytes)?;
+    let found = value.type_string();
+    value
+        .try_into()
+        .map_err(|_| ContractApiError::UnexpectedReturnType(found))
 }
@@ -15,6 +15,22 @@ impl<T> UPointer<T> {
     pub fn new(id: [u8; 32], rights: AccessRights) -> UPointer<T> {
         UPointer(id, rights, PhantomData)
     }
+
+    pub fn rights(&self) -> AccessRights {
+        self.1
+    }
+
+    /// The same pointer with only the right to read through it, which can be
+    /// handed out without letting others change the value.
+    pub fn to_read_only(self) -> UPointer<T> {
+        UPointer(self.0, self.1 & AccessRights::READ, PhantomData)
+    }
+
+    /// The same pointer with only the right to add to the value, e.g. to let
+    /// others deposit into a purse.
+    pub fn to_add_only(self) -> UPointer<T> {
+        UPointer(self.0, self.1 & AccessRights::ADD, PhantomData)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -37,3 +53,16 @@ impl From<ContractPointer> for Key {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::UPointer;
+    use crate::key::{AccessRights, Key};
+
+    #[test]
+    fn pointers_are_attenuated() {
+        let u_ptr: UPointer<i32> = UPointer::new([1u8; 32], AccessRights::READ_ADD_WRITE);
+        assert_eq!(Key::from(u_ptr.to_read_only()), Key::URef([1u8; 32], AccessRights::READ));
+        assert_eq!(Key::from(u_ptr.to_add_only()), Key::URef([1u8; 32], AccessRights::ADD));
+    }
+}
@@ -0,0 +1,69 @@
+//! Bonding, unbonding and delegating with the Proof-of-Stake system contract.
+//!
+//! Tokens are bonded as the stake of the account the deploy runs under, or
+//! delegated by it to a bonded validator, adding to the weight of the
+//! validator. Requests wait for the delays configured in the engine before
+//! they take effect.
+
+use super::alloc_util::to_ptr;
+use super::unknown_code;
+use crate::ext_ffi;
+use crate::pos::PosError;
+use crate::value::account::{PublicKey, PurseId};
+use crate::value::U512;
+use core::convert::TryFrom;
+
+fn pos_result(code: i32) -> Result<(), PosError> {
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(PosError::try_from(code).unwrap_or_else(|_| unknown_code(code)))
+    }
+}
+
+/// Moves `amount` of tokens from `purse` to the bonding purse of the PoS
+/// contract. The caller needs write rights to `purse`.
+pub fn bond(amount: U512, purse: PurseId) -> Result<(), PosError> {
+    let (amount_ptr, amount_size, _bytes) = to_ptr(&amount);
+    let (purse_ptr, purse_size, _bytes2) = to_ptr(&purse);
+    let code = unsafe { ext_ffi::bond(amount_ptr, amount_size, purse_ptr, purse_size) };
+    pos_result(code)
+}
+
+/// Takes `amount` of tokens off the stake of the account. They are paid out
+/// to its main purse once the unbonding delay passes.
+pub fn unbond(amount: U512) -> Result<(), PosError> {
+    let (amount_ptr, amount_size, _bytes) = to_ptr(&amount);
+    let code = unsafe { ext_ffi::unbond(amount_ptr, amount_size) };
+    pos_result(code)
+}
+
+/// Moves `amount` of tokens from `purse` to the bonding purse of the PoS
+/// contract and delegates them to `validator`, which has to be bonded. The
+/// caller needs write rights to `purse`.
+pub fn delegate(validator: PublicKey, amount: U512, purse: PurseId) -> Result<(), PosError> {
+    let (validator_ptr, validator_size, _bytes) = to_ptr(&validator);
+    let (amount_ptr, amount_size, _bytes2) = to_ptr(&amount);
+    let (purse_ptr, purse_size, _bytes3) = to_ptr(&purse);
+    let code = unsafe {
+        ext_ffi::delegate(
+            validator_ptr,
+            validator_size,
+            amount_ptr,
+            amount_size,
+            purse_ptr,
+            purse_size,
+        )
+    };
+    pos_result(code)
+}
+
+/// Takes back `amount` of the tokens the account delegated to `validator`.
+/// They are paid out to its main purse once the unbonding delay passes.
+pub fn undelegate(validator: PublicKey, amount: U512) -> Result<(), PosError> {
+    let (validator_ptr, validator_size, _bytes) = to_ptr(&validator);
+    let (amount_ptr, amount_size, _bytes2) = to_ptr(&amount);
+    let code =
+        unsafe { ext_ffi::undelegate(validator_ptr, validator_size, amount_ptr, amount_size) };
+    pos_result(code)
+}
@@ -0,0 +1,37 @@
+//! Turns failures into reverts with a proper error code.
+//!
+//! Fallible functions of `contract_api` return a `Result`. Contracts which
+//! can't handle an error can stop with `unwrap_or_revert` instead of panicking,
+//! which would only be reported as an opaque trap.
+
+use super::error::ApiError;
+use super::revert;
+
+pub trait UnwrapOrRevert<T> {
+    /// Unwraps the value, or reverts with the error converted into an
+    /// `ApiError`. `None` reverts with `ApiError::None`.
+    fn unwrap_or_revert(self) -> T;
+
+    /// Unwraps the value, or reverts with `error`.
+    fn unwrap_or_revert_with<E: Into<ApiError>>(self, error: E) -> T;
+}
+
+impl<T, E: Into<ApiError>> UnwrapOrRevert<T> for Result<T, E> {
+    fn unwrap_or_revert(self) -> T {
+        self.unwrap_or_else(|error| revert(error.into()))
+    }
+
+    fn unwrap_or_revert_with<F: Into<ApiError>>(self, error: F) -> T {
+        self.unwrap_or_else(|_| revert(error.into()))
+    }
+}
+
+impl<T> UnwrapOrRevert<T> for Option<T> {
+    fn unwrap_or_revert(self) -> T {
+        self.unwrap_or_else(|| revert(ApiError::None))
+    }
+
+    fn unwrap_or_revert_with<E: Into<ApiError>>(self, error: E) -> T {
+        self.unwrap_or_else(|| revert(error.into()))
+    }
+}
@@ -0,0 +1,104 @@
+//! Types taken by the signature verification functions of `contract_api`.
+
+use core::fmt;
+
+pub use crate::value::account::PublicKey;
+
+/// Length of an Ed25519 signature.
+pub const ED25519_SIGNATURE_LENGTH: usize = 64;
+
+/// Length of a secp256k1 signature: `r` followed by `s`, both big endian.
+pub const SECP256K1_SIGNATURE_LENGTH: usize = 64;
+
+/// Length of a secp256k1 public key in the uncompressed SEC1 encoding.
+pub const SECP256K1_PUBLIC_KEY_LENGTH: usize = 65;
+
+// Arrays longer than 32 elements don't implement these traits themselves.
+macro_rules! impl_byte_array_traits {
+    ($name:ident, $length:expr) => {
+        impl $name {
+            pub fn new(bytes: [u8; $length]) -> $name {
+                $name(bytes)
+            }
+
+            pub fn as_bytes(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl From<[u8; $length]> for $name {
+            fn from(bytes: [u8; $length]) -> $name {
+                $name(bytes)
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &$name) -> bool {
+                self.0[..] == other.0[..]
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({:?})", stringify!($name), &self.0[..])
+            }
+        }
+    };
+}
+
+/// Ed25519 signature, made by the secret key belonging to a `PublicKey`.
+#[derive(Clone, Copy)]
+pub struct Signature([u8; ED25519_SIGNATURE_LENGTH]);
+
+impl_byte_array_traits!(Signature, ED25519_SIGNATURE_LENGTH);
+
+/// secp256k1 signature without the recovery id.
+#[derive(Clone, Copy)]
+pub struct Secp256k1Signature([u8; SECP256K1_SIGNATURE_LENGTH]);
+
+impl_byte_array_traits!(Secp256k1Signature, SECP256K1_SIGNATURE_LENGTH);
+
+/// secp256k1 public key.
+#[derive(Clone, Copy)]
+pub struct Secp256k1PublicKey([u8; SECP256K1_PUBLIC_KEY_LENGTH]);
+
+impl_byte_array_traits!(Secp256k1PublicKey, SECP256K1_PUBLIC_KEY_LENGTH);
+
+/// Tells which of the candidate public keys made a secp256k1 signature.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RecoveryId(u8);
+
+impl RecoveryId {
+    /// Returns `None` unless `id` is in the `0..=3` range.
+    pub fn new(id: u8) -> Option<RecoveryId> {
+        if id <= 3 {
+            Some(RecoveryId(id))
+        } else {
+            None
+        }
+    }
+
+    /// Converts the `v` value of an Ethereum signature, which is the recovery
+    /// id offset by 27.
+    pub fn from_ethereum_v(v: u8) -> Option<RecoveryId> {
+        v.checked_sub(27).and_then(RecoveryId::new)
+    }
+
+    pub fn value(self) -> u8 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecoveryId;
+
+    #[test]
+    fn recovery_id_from_ethereum_v() {
+        assert_eq!(RecoveryId::from_ethereum_v(28), RecoveryId::new(1));
+        assert_eq!(RecoveryId::from_ethereum_v(1), None);
+        assert_eq!(RecoveryId::from_ethereum_v(31), None);
+    }
+}
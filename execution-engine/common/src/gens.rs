@@ -1,4 +1,10 @@
 use crate::key::*;
+use crate::phase::Phase;
+use crate::value::abi::{Abi, AbiType, Arg, EntryPoint};
+use crate::value::account::{
+    ActionThresholds, AssociatedKeys, KeyLimits, PublicKey, PurseId, Weight,
+};
+use crate::value::contract_package::VersionSelector;
 use crate::value::*;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
@@ -42,24 +48,143 @@ pub fn key_arb() -> impl Strategy<Value = Key> {
         u8_slice_20().prop_map(Key::Account),
         u8_slice_32().prop_map(Key::Hash),
         access_rights_arb()
-            .prop_flat_map(|right| { u8_slice_32().prop_map(move |addr| Key::URef(addr, right)) })
+            .prop_flat_map(|right| { u8_slice_32().prop_map(move |addr| Key::URef(addr, right)) }),
+        u8_slice_32().prop_map(Key::Local),
+        u8_slice_32().prop_map(Key::DeployInfo),
+        u8_slice_32().prop_map(Key::Transfer),
     ]
 }
 
+pub fn associated_keys_arb(size: usize) -> impl Strategy<Value = AssociatedKeys> {
+    (u8_slice_32(), any::<u8>()).prop_flat_map(move |(key, weight)| {
+        btree_map(u8_slice_32(), any::<u8>(), size).prop_map(move |keys| {
+            let mut associated_keys = AssociatedKeys::new(PublicKey::new(key), Weight::new(weight));
+            let limits = KeyLimits::new(size + 1, Weight::new(0), Weight::new(u8::max_value()));
+            keys.into_iter().for_each(|(k, w)| {
+                let _ = associated_keys.add_key(PublicKey::new(k), Weight::new(w), &limits);
+            });
+            associated_keys
+        })
+    })
+}
+
+pub fn purse_id_arb() -> impl Strategy<Value = PurseId> {
+    (u8_slice_32(), access_rights_arb()).prop_map(|(addr, rights)| PurseId::new(addr, rights))
+}
+
+pub fn action_thresholds_arb() -> impl Strategy<Value = ActionThresholds> {
+    (any::<u8>(), any::<u8>()).prop_map(|(deployment, key_management)| {
+        ActionThresholds::new(Weight::new(deployment), Weight::new(key_management))
+    })
+}
+
 pub fn account_arb() -> impl Strategy<Value = Account> {
     u8_slice_32().prop_flat_map(|b| {
         any::<u64>().prop_flat_map(move |u64arb| {
-            uref_map_arb(3).prop_map(move |urefs| Account::new(b, u64arb, urefs))
+            uref_map_arb(3).prop_flat_map(move |urefs| {
+                (
+                    purse_id_arb(),
+                    associated_keys_arb(3),
+                    action_thresholds_arb(),
+                )
+                    .prop_map(move |(purse_id, associated_keys, action_thresholds)| {
+                        Account::new(
+                            b,
+                            u64arb,
+                            urefs.clone(),
+                            purse_id,
+                            associated_keys,
+                            action_thresholds,
+                        )
+                    })
+            })
         })
     })
 }
 
+pub fn abi_type_arb() -> impl Strategy<Value = AbiType> {
+    prop_oneof![
+        Just(AbiType::Unit),
+        Just(AbiType::Int32),
+        Just(AbiType::UInt128),
+        Just(AbiType::UInt256),
+        Just(AbiType::UInt512),
+        Just(AbiType::ByteArray),
+        Just(AbiType::ListInt32),
+        Just(AbiType::String),
+        Just(AbiType::ListString),
+        Just(AbiType::Key),
+    ]
+}
+
+pub fn abi_arb() -> impl Strategy<Value = Abi> {
+    let arg = ("\\PC*", abi_type_arb()).prop_map(|(name, arg_type)| Arg::new(name, arg_type));
+    let entry_point = ("\\PC*", vec(arg, 0..5), abi_type_arb(), vec("\\PC*", 0..3)).prop_map(
+        |(name, args, ret, groups)| EntryPoint::new(name, args, ret).with_groups(groups),
+    );
+    vec(entry_point, 0..5).prop_map(Abi::new)
+}
+
 pub fn contract_arb() -> impl Strategy<Value = Contract> {
     uref_map_arb(20).prop_flat_map(|urefs| {
-        vec(any::<u8>(), 1..1000).prop_map(move |body| Contract::new(body, urefs.clone()))
+        vec(any::<u8>(), 1..1000).prop_flat_map(move |body| {
+            let urefs = urefs.clone();
+            (any::<u64>(), proptest::option::of(abi_arb())).prop_map(
+                move |(protocol_version, abi)| {
+                    let contract = Contract::new(body.clone(), urefs.clone(), protocol_version);
+                    match abi {
+                        Some(abi) => contract.with_abi(abi),
+                        None => contract,
+                    }
+                },
+            )
+        })
     })
 }
 
+pub fn phase_arb() -> impl Strategy<Value = Phase> {
+    prop_oneof![
+        Just(Phase::System),
+        Just(Phase::Payment),
+        Just(Phase::Session),
+        Just(Phase::FinalizePayment),
+    ]
+}
+
+pub fn version_selector_arb() -> impl Strategy<Value = VersionSelector> {
+    prop_oneof![
+        Just(VersionSelector::Latest),
+        any::<u32>().prop_map(VersionSelector::Exact),
+        any::<u32>().prop_map(VersionSelector::Major),
+    ]
+}
+
+pub fn contract_package_arb() -> impl Strategy<Value = ContractPackage> {
+    (
+        u8_slice_32(),
+        access_rights_arb(),
+        vec((any::<u32>(), key_arb(), any::<bool>()), 0..20),
+        btree_map("\\PC*", vec(key_arb(), 1..5), 0..5),
+        any::<bool>(),
+    )
+        .prop_map(|(addr, rights, versions, groups, locked)| {
+            let mut package = ContractPackage::new(Key::URef(addr, rights));
+            versions.into_iter().for_each(|(major, key, disabled)| {
+                let version = package.add_version(major, key);
+                if disabled {
+                    package.disable_version(version);
+                }
+            });
+            groups.into_iter().for_each(|(name, keys)| {
+                keys.into_iter().for_each(|key| package.add_group_key(name.clone(), key));
+            });
+            if locked {
+                package.lock();
+            }
+            package
+        })
+}
+
 pub fn u128_arb() -> impl Strategy<Value = U128> {
     vec(any::<u8>(), 0..16).prop_map(|b| U128::from_little_endian(b.as_slice()))
 }
@@ -82,6 +207,7 @@ pub fn value_arb() -> impl Strategy<Value = Value> {
         ("\\PC*", key_arb()).prop_map(|(n, k)| Value::NamedKey(n, k)),
         account_arb().prop_map(Value::Account),
         contract_arb().prop_map(Value::Contract),
+        contract_package_arb().prop_map(Value::ContractPackage),
         u128_arb().prop_map(Value::UInt128),
         u256_arb().prop_map(Value::UInt256),
         u512_arb().prop_map(Value::UInt512)
@@ -2,6 +2,8 @@ use super::alloc::vec::Vec;
 use super::bytesrepr::{Error, FromBytes, ToBytes, N32, U32_SIZE};
 use crate::contract_api::pointers::*;
 use bitflags;
+use core::convert::TryFrom;
+use failure::Fail;
 
 bitflags! {
     #[allow(clippy::derive_hash_xor_eq)]
@@ -30,6 +32,36 @@ impl AccessRights {
     }
 }
 
+/// Reasons why a key can't be used by the current context with the rights
+/// it asks for. Discriminants are the codes returned by the host functions
+/// and must not change.
+#[repr(i32)]
+#[derive(Fail, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AccessError {
+    #[fail(display = "URef is unknown or carries more rights than were granted")]
+    ForgedReference = 1,
+    #[fail(display = "Key doesn't allow the required access")]
+    InsufficientRights = 2,
+}
+
+impl From<AccessError> for i32 {
+    fn from(error: AccessError) -> i32 {
+        error as i32
+    }
+}
+
+impl TryFrom<i32> for AccessError {
+    type Error = ();
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(AccessError::ForgedReference),
+            2 => Ok(AccessError::InsufficientRights),
+            _ => Err(()),
+        }
+    }
+}
+
 pub const KEY_SIZE: usize = 32;
 
 #[repr(C)]
@@ -38,6 +70,16 @@ pub enum Key {
     Account([u8; 20]),
     Hash([u8; 32]),
     URef([u8; 32], AccessRights), //TODO: more bytes?
+    /// Local storage of a contract or an account. The address is derived by
+    /// the host from the context it is used in, so a context can't reach the
+    /// local storage of another one.
+    Local([u8; 32]),
+    /// Record of the execution of the deploy with the given hash, written by
+    /// the engine.
+    DeployInfo([u8; 32]),
+    /// Record of a native transfer made by a deploy, written by the engine
+    /// under an address derived from the hash of the deploy.
+    Transfer([u8; 32]),
 }
 
 use Key::*;
@@ -59,9 +101,14 @@ impl Key {
     }
 }
 
-const ACCOUNT_ID: u8 = 0;
-const HASH_ID: u8 = 1;
-const UREF_ID: u8 = 2;
+// The bytes of every key start with the tag of its kind, so the keys of a
+// kind can be listed by their tag.
+pub const ACCOUNT_ID: u8 = 0;
+pub const HASH_ID: u8 = 1;
+pub const UREF_ID: u8 = 2;
+pub const LOCAL_ID: u8 = 3;
+pub const DEPLOY_INFO_ID: u8 = 4;
+pub const TRANSFER_ID: u8 = 5;
 const KEY_ID_SIZE: usize = 1; // u8 used to determine the ID
 const ACCESS_RIGHTS_SIZE: usize = 1; // u8 used to tag AccessRights
 pub const UREF_SIZE: usize = U32_SIZE + N32 + KEY_ID_SIZE + ACCESS_RIGHTS_SIZE;
@@ -106,6 +153,24 @@ impl ToBytes for Key {
                 result.append(&mut access_rights.to_bytes()?);
                 Ok(result)
             }
+            Local(hash) => {
+                let mut result = Vec::with_capacity(37);
+                result.push(LOCAL_ID);
+                result.append(&mut hash.to_bytes()?);
+                Ok(result)
+            }
+            DeployInfo(hash) => {
+                let mut result = Vec::with_capacity(37);
+                result.push(DEPLOY_INFO_ID);
+                result.append(&mut hash.to_bytes()?);
+                Ok(result)
+            }
+            Transfer(addr) => {
+                let mut result = Vec::with_capacity(37);
+                result.push(TRANSFER_ID);
+                result.append(&mut addr.to_bytes()?);
+                Ok(result)
+            }
         }
     }
 }
@@ -133,6 +198,18 @@ impl FromBytes for Key {
                 let (access_right, rem2): (AccessRights, &[u8]) = FromBytes::from_bytes(rem)?;
                 Ok((URef(rf, access_right), rem2))
             }
+            LOCAL_ID => {
+                let (hash, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(rest)?;
+                Ok((Local(hash), rem))
+            }
+            DEPLOY_INFO_ID => {
+                let (hash, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(rest)?;
+                Ok((DeployInfo(hash), rem))
+            }
+            TRANSFER_ID => {
+                let (addr, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(rest)?;
+                Ok((Transfer(addr), rem))
+            }
             _ => Err(Error::FormattingError),
         }
     }
@@ -175,6 +252,9 @@ impl AsRef<[u8]> for Key {
             Account(a) => a,
             Hash(h) => h,
             URef(u, ..) => u,
+            Local(h) => h,
+            DeployInfo(h) => h,
+            Transfer(a) => a,
         }
     }
 }
@@ -182,7 +262,8 @@ impl AsRef<[u8]> for Key {
 #[allow(clippy::unnecessary_operation)]
 #[cfg(test)]
 mod tests {
-    use crate::key::AccessRights;
+    use crate::key::{AccessError, AccessRights};
+    use core::convert::TryFrom;
 
     fn test_readable(right: AccessRights, is_true: bool) {
         assert_eq!(right.is_readable(), is_true)
@@ -228,4 +309,12 @@ mod tests {
         test_addable(AccessRights::WRITE, false);
         test_addable(AccessRights::READ_ADD_WRITE, true);
     }
+
+    #[test]
+    fn access_error_codes_roundtrip() {
+        for error in &[AccessError::ForgedReference, AccessError::InsufficientRights] {
+            assert_eq!(AccessError::try_from(i32::from(*error)), Ok(*error));
+        }
+        assert_eq!(AccessError::try_from(0), Err(()));
+    }
 }
@@ -25,13 +25,21 @@ extern crate proptest;
 #[global_allocator]
 pub static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+pub mod auction;
 pub mod bytesrepr;
 pub mod contract_api;
+pub mod crypto;
 #[cfg(any(test, feature = "gens"))]
 pub mod gens;
 pub mod key;
+pub mod phase;
+pub mod pos;
+pub mod protocol_version;
+pub mod runtime_args;
+pub mod system_contracts;
 #[cfg(any(test, feature = "gens"))]
 pub mod test_utils;
+pub mod trie_merkle_proof;
 pub mod value;
 
 mod ext_ffi {
@@ -51,7 +59,23 @@ mod ext_ffi {
             extra_urefs_size: usize,
             hash_ptr: *const u8,
         );
-        pub fn load_arg(i: u32) -> usize;
+        pub fn store_function_at_hash(
+            value_ptr: *const u8,
+            value_size: usize,
+            extra_urefs_ptr: *const u8,
+            extra_urefs_size: usize,
+            hash_ptr: *const u8,
+        );
+        pub fn store_function_with_abi(
+            value_ptr: *const u8,
+            value_size: usize,
+            extra_urefs_ptr: *const u8,
+            extra_urefs_size: usize,
+            abi_ptr: *const u8,
+            abi_size: usize,
+            hash_ptr: *const u8,
+        );
+        pub fn load_arg(i: u32) -> i32;
         pub fn get_arg(dest: *mut u8); //can only be called after `load_arg`
         pub fn ret(
             value_ptr: *const u8,
@@ -69,10 +93,199 @@ mod ext_ffi {
             extra_urefs_ptr: *const u8,
             extra_urefs_size: usize,
         ) -> usize;
+        pub fn call_versioned_contract(
+            key_ptr: *const u8,
+            key_size: usize,
+            selector_ptr: *const u8,
+            selector_size: usize,
+            args_ptr: *const u8,
+            args_size: usize,
+            extra_urefs_ptr: *const u8,
+            extra_urefs_size: usize,
+        ) -> usize;
         pub fn get_call_result(res_ptr: *mut u8); //can only be called after `call_contract`
         pub fn get_uref(name_ptr: *const u8, name_size: usize, dest: *mut u8);
         pub fn has_uref_name(name_ptr: *const u8, name_size: usize) -> i32;
         pub fn add_uref(name_ptr: *const u8, name_size: usize, key_ptr: *const u8, key_size: usize);
+        pub fn get_caller(dest_ptr: *mut u8);
+        pub fn rotate_main_purse(dest_ptr: *mut u8);
+        pub fn add_associated_key(public_key_ptr: *const u8, public_key_size: usize, weight: u32)
+            -> i32;
+        pub fn remove_associated_key(public_key_ptr: *const u8, public_key_size: usize) -> i32;
+        pub fn set_action_threshold(action_type: u32, threshold: u32) -> i32;
+        pub fn create_contract_package(package_hash_ptr: *mut u8, access_key_ptr: *mut u8);
+        pub fn add_contract_version(
+            package_key_ptr: *const u8,
+            package_key_size: usize,
+            access_key_ptr: *const u8,
+            access_key_size: usize,
+            major: u32,
+            contract_key_ptr: *const u8,
+            contract_key_size: usize,
+        ) -> u32;
+        pub fn lock_contract_package(
+            package_key_ptr: *const u8,
+            package_key_size: usize,
+            access_key_ptr: *const u8,
+            access_key_size: usize,
+        );
+        pub fn create_contract_user_group(
+            package_key_ptr: *const u8,
+            package_key_size: usize,
+            access_key_ptr: *const u8,
+            access_key_size: usize,
+            group_name_ptr: *const u8,
+            group_name_size: usize,
+            dest_ptr: *mut u8,
+        );
+        pub fn remove_contract_user_group(
+            package_key_ptr: *const u8,
+            package_key_size: usize,
+            access_key_ptr: *const u8,
+            access_key_size: usize,
+            group_name_ptr: *const u8,
+            group_name_size: usize,
+        );
+        pub fn put_key(name_ptr: *const u8, name_size: usize, key_ptr: *const u8, key_size: usize);
+        pub fn remove_key(name_ptr: *const u8, name_size: usize);
+        pub fn get_key(name_ptr: *const u8, name_size: usize, dest_ptr: *mut u8) -> usize;
+        pub fn store_library(names_ptr: *const u8, names_size: usize, hash_ptr: *mut u8);
+        pub fn get_phase(dest_ptr: *mut u8);
+        pub fn revert(status: u32) -> !;
+        pub fn transfer_to_account(
+            target_ptr: *const u8,
+            target_size: usize,
+            amount_ptr: *const u8,
+            amount_size: usize,
+        ) -> i32;
+        pub fn create_purse(dest_ptr: *mut u8);
+        pub fn get_main_purse(dest_ptr: *mut u8);
+        pub fn blake2b(in_ptr: *const u8, in_size: usize, out_ptr: *mut u8);
+        pub fn verify_ed25519(
+            message_ptr: *const u8,
+            message_size: usize,
+            signature_ptr: *const u8,
+            public_key_ptr: *const u8,
+        ) -> i32;
+        pub fn verify_secp256k1(
+            digest_ptr: *const u8,
+            signature_ptr: *const u8,
+            public_key_ptr: *const u8,
+        ) -> i32;
+        pub fn recover_secp256k1(
+            digest_ptr: *const u8,
+            signature_ptr: *const u8,
+            recovery_id: u32,
+            dest_ptr: *mut u8,
+        ) -> i32;
+        pub fn random_bytes(dest_ptr: *mut u8);
+        pub fn emit_event(
+            topic_ptr: *const u8,
+            topic_size: usize,
+            data_ptr: *const u8,
+            data_size: usize,
+        );
+        pub fn print(message_ptr: *const u8, message_size: usize);
+        pub fn load_named_arg(name_ptr: *const u8, name_size: usize) -> i32;
+        pub fn list_named_keys(offset: u32, limit: u32) -> usize; // then `read_host_buffer`
+        pub fn remove_uref(name_ptr: *const u8, name_size: usize);
+        pub fn is_valid(key_ptr: *const u8, key_size: usize) -> i32;
+        pub fn read_local(key_ptr: *const u8, key_size: usize) -> usize; // then `read_host_buffer`
+        pub fn write_local(
+            key_ptr: *const u8,
+            key_size: usize,
+            value_ptr: *const u8,
+            value_size: usize,
+        );
+        pub fn get_system_contract(id: u32, dest_ptr: *mut u8) -> usize;
+        pub fn call_contract_with_gas(
+            key_ptr: *const u8,
+            key_size: usize,
+            args_ptr: *const u8,
+            args_size: usize,
+            extra_urefs_ptr: *const u8,
+            extra_urefs_size: usize,
+            gas_allowance: u64,
+        ) -> i32;
+        pub fn upgrade_contract_at_uref(
+            name_ptr: *const u8,
+            name_size: usize,
+            key_ptr: *const u8,
+            key_size: usize,
+        );
+        pub fn gas_remaining() -> u64;
+        pub fn read_multi(keys_ptr: *const u8, keys_size: usize) -> usize;
+        pub fn write_multi(entries_ptr: *const u8, entries_size: usize);
+        pub fn read_host_buffer(dest_ptr: *mut u8, dest_size: usize) -> usize;
+        pub fn new_uref_with_rights(
+            key_ptr: *mut u8,
+            value_ptr: *const u8,
+            value_size: usize,
+            rights: u32,
+        );
+        pub fn get_deploy_hash(dest_ptr: *mut u8);
+        pub fn get_protocol_version(dest_ptr: *mut u8);
+        pub fn dictionary_get(
+            seed_ptr: *const u8,
+            seed_size: usize,
+            key_ptr: *const u8,
+            key_size: usize,
+        ) -> usize; // then `read_host_buffer`
+        pub fn dictionary_put(
+            seed_ptr: *const u8,
+            seed_size: usize,
+            key_ptr: *const u8,
+            key_size: usize,
+            value_ptr: *const u8,
+            value_size: usize,
+        );
+        pub fn call_stack_depth() -> u32;
+        pub fn validate_key(key_ptr: *const u8, key_size: usize, rights: u32) -> i32;
+        pub fn get_block_height() -> u64;
+        pub fn get_payment_purse(dest_ptr: *mut u8);
+        pub fn transfer_from_purse_to_purse(
+            source_ptr: *const u8,
+            source_size: usize,
+            target_ptr: *const u8,
+            target_size: usize,
+            amount_ptr: *const u8,
+            amount_size: usize,
+        ) -> i32;
+        pub fn disable_contract_version(
+            package_key_ptr: *const u8,
+            package_key_size: usize,
+            access_key_ptr: *const u8,
+            access_key_size: usize,
+            version: u32,
+        );
+        pub fn bond(
+            amount_ptr: *const u8,
+            amount_size: usize,
+            purse_ptr: *const u8,
+            purse_size: usize,
+        ) -> i32;
+        pub fn unbond(amount_ptr: *const u8, amount_size: usize) -> i32;
+        pub fn delegate(
+            validator_ptr: *const u8,
+            validator_size: usize,
+            amount_ptr: *const u8,
+            amount_size: usize,
+            purse_ptr: *const u8,
+            purse_size: usize,
+        ) -> i32;
+        pub fn undelegate(
+            validator_ptr: *const u8,
+            validator_size: usize,
+            amount_ptr: *const u8,
+            amount_size: usize,
+        ) -> i32;
+        pub fn add_bid(
+            amount_ptr: *const u8,
+            amount_size: usize,
+            purse_ptr: *const u8,
+            purse_size: usize,
+        ) -> i32;
+        pub fn withdraw_bid(amount_ptr: *const u8, amount_size: usize) -> i32;
     }
 }
 
@@ -0,0 +1,49 @@
+use crate::bytesrepr::{Error, FromBytes, ToBytes};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// Size of a serialized `Phase`.
+pub const PHASE_SIZE: usize = 1;
+
+/// Stage of the execution of a deploy. Payment and session code are often
+/// built from the same Wasm module and use the phase to tell which part of
+/// the deploy they are running.
+#[repr(u8)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Phase {
+    /// Code run by the system itself, outside of any deploy.
+    System = 0,
+    /// Payment code, which buys the gas for the rest of the deploy.
+    Payment = 1,
+    /// Session code of the deploy.
+    Session = 2,
+    /// Settlement of the payment after the session code has finished.
+    FinalizePayment = 3,
+}
+
+impl TryFrom<u8> for Phase {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Phase, Error> {
+        match value {
+            0 => Ok(Phase::System),
+            1 => Ok(Phase::Payment),
+            2 => Ok(Phase::Session),
+            3 => Ok(Phase::FinalizePayment),
+            _ => Err(Error::FormattingError),
+        }
+    }
+}
+
+impl ToBytes for Phase {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        (*self as u8).to_bytes()
+    }
+}
+
+impl FromBytes for Phase {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (id, rem): (u8, &[u8]) = FromBytes::from_bytes(bytes)?;
+        Ok((Phase::try_from(id)?, rem))
+    }
+}
@@ -0,0 +1,164 @@
+//! Types shared by the Proof-of-Stake system contract and its callers.
+//!
+//! The stake of every bonded validator is stored under a named key of the
+//! PoS contract called `v_` followed by the hex-encoded public key of the
+//! validator, so the node can find the stakes by querying the contract.
+//!
+//! Tokens delegated to a validator are kept in a dictionary seeded by the URef
+//! under the named key `d_` followed by the hex-encoded public key of the
+//! validator. Its items are named after the hex-encoded public keys of the
+//! delegators, the item called `total` holds the sum of the delegations and
+//! the item called `delegators` lists the names of the other items.
+
+use crate::value::account::PublicKey;
+use alloc::string::String;
+use core::convert::TryFrom;
+use core::fmt::Write;
+use failure::Fail;
+
+/// Prefix of the names under which the PoS contract keeps the stakes.
+pub const STAKE_KEY_PREFIX: &str = "v_";
+
+/// Prefix of the names under which the PoS contract keeps the seeds of the
+/// dictionaries of delegations.
+pub const DELEGATIONS_KEY_PREFIX: &str = "d_";
+
+/// Item of a dictionary of delegations holding their sum.
+pub const DELEGATED_TOTAL_ITEM: &str = "total";
+
+/// Item of a dictionary of delegations listing the delegators.
+pub const DELEGATORS_ITEM: &str = "delegators";
+
+pub(crate) fn hex_name(prefix: &str, key: PublicKey) -> String {
+    let mut name = String::from(prefix);
+    for byte in key.value().iter() {
+        let _ = write!(name, "{:02x}", byte);
+    }
+    name
+}
+
+/// Name of the named key of the PoS contract holding the stake of `validator`.
+pub fn stake_key_name(validator: PublicKey) -> String {
+    hex_name(STAKE_KEY_PREFIX, validator)
+}
+
+/// Name of the named key of the PoS contract holding the seed of the
+/// dictionary of the delegations to `validator`.
+pub fn delegations_key_name(validator: PublicKey) -> String {
+    hex_name(DELEGATIONS_KEY_PREFIX, validator)
+}
+
+/// Item of a dictionary of delegations holding the tokens delegated by
+/// `delegator`.
+pub fn delegation_item_name(delegator: PublicKey) -> String {
+    hex_name("", delegator)
+}
+
+/// Delegator whose delegation is kept under the item `name`, if it's the
+/// name of a delegation.
+pub fn delegator_from_item_name(name: &str) -> Option<PublicKey> {
+    key_from_hex(name)
+}
+
+/// Validator whose stake is kept under `name`, if it's the name of a stake.
+pub fn validator_from_stake_key_name(name: &str) -> Option<PublicKey> {
+    if !name.starts_with(STAKE_KEY_PREFIX) {
+        return None;
+    }
+    key_from_hex(&name[STAKE_KEY_PREFIX.len()..])
+}
+
+pub(crate) fn key_from_hex(hex: &str) -> Option<PublicKey> {
+    let hex = hex.as_bytes();
+    if hex.len() != 64 || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (byte, digits) in key.iter_mut().zip(hex.chunks(2)) {
+        let digits = core::str::from_utf8(digits).ok()?;
+        *byte = u8::from_str_radix(digits, 16).ok()?;
+    }
+    Some(PublicKey::new(key))
+}
+
+/// Reasons why bonding, unbonding or delegating can fail. Discriminants are the codes
+/// returned by the host functions and must not change.
+#[repr(i32)]
+#[derive(Fail, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PosError {
+    #[fail(display = "Source purse doesn't hold enough tokens")]
+    InsufficientFunds = 1,
+    #[fail(display = "Validator isn't bonded")]
+    NotBonded = 2,
+    #[fail(display = "Unbonding more than the validator has bonded")]
+    UnbondTooLarge = 3,
+    #[fail(display = "Validator was slashed")]
+    Slashed = 4,
+    #[fail(display = "Delegator has no tokens delegated to the validator")]
+    NotDelegated = 5,
+    #[fail(display = "Undelegating more than the delegator has delegated")]
+    UndelegateTooLarge = 6,
+}
+
+impl From<PosError> for i32 {
+    fn from(error: PosError) -> i32 {
+        error as i32
+    }
+}
+
+impl TryFrom<i32> for PosError {
+    type Error = ();
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(PosError::InsufficientFunds),
+            2 => Ok(PosError::NotBonded),
+            3 => Ok(PosError::UnbondTooLarge),
+            4 => Ok(PosError::Slashed),
+            5 => Ok(PosError::NotDelegated),
+            6 => Ok(PosError::UndelegateTooLarge),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        delegation_item_name, delegations_key_name, delegator_from_item_name, stake_key_name,
+        validator_from_stake_key_name, PosError, DELEGATED_TOTAL_ITEM,
+    };
+    use crate::value::account::PublicKey;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn stake_key_names_roundtrip() {
+        let validator = PublicKey::new([0xa5; 32]);
+        let name = stake_key_name(validator);
+        assert_eq!(name.len(), 2 + 64);
+        assert!(name.starts_with("v_a5a5"));
+        assert_eq!(validator_from_stake_key_name(&name), Some(validator));
+        assert_eq!(validator_from_stake_key_name("bonding_purse"), None);
+        assert_eq!(validator_from_stake_key_name("v_a5"), None);
+        assert_eq!(validator_from_stake_key_name(&delegations_key_name(validator)), None);
+        assert_eq!(delegation_item_name(validator), name[2..]);
+        assert_eq!(delegator_from_item_name(&name[2..]), Some(validator));
+        assert_eq!(delegator_from_item_name(DELEGATED_TOTAL_ITEM), None);
+    }
+
+    #[test]
+    fn error_codes_roundtrip() {
+        let errors = [
+            PosError::InsufficientFunds,
+            PosError::NotBonded,
+            PosError::UnbondTooLarge,
+            PosError::Slashed,
+            PosError::NotDelegated,
+            PosError::UndelegateTooLarge,
+        ];
+        for error in &errors {
+            assert_eq!(PosError::try_from(i32::from(*error)), Ok(*error));
+        }
+        assert_eq!(PosError::try_from(0), Err(()));
+    }
+}
@@ -0,0 +1,58 @@
+//! Version of the protocol the engine runs deploys under.
+//!
+//! Contracts read it with `contract_api::get_protocol_version` to find out
+//! which host features are available to them.
+
+use crate::bytesrepr::{Error, FromBytes, ToBytes, U64_SIZE};
+use alloc::vec::Vec;
+
+/// Size of a serialized `ProtocolVersion`.
+pub const PROTOCOL_VERSION_SIZE: usize = U64_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion(u64);
+
+impl ProtocolVersion {
+    pub fn new(version: u64) -> ProtocolVersion {
+        ProtocolVersion(version)
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for ProtocolVersion {
+    fn from(version: u64) -> ProtocolVersion {
+        ProtocolVersion(version)
+    }
+}
+
+impl ToBytes for ProtocolVersion {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.0.to_bytes()
+    }
+}
+
+impl FromBytes for ProtocolVersion {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (version, rem): (u64, &[u8]) = FromBytes::from_bytes(bytes)?;
+        Ok((ProtocolVersion(version), rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProtocolVersion, PROTOCOL_VERSION_SIZE};
+    use crate::bytesrepr::{deserialize, ToBytes};
+
+    #[test]
+    fn protocol_version_roundtrip() {
+        let version = ProtocolVersion::new(3);
+        let bytes = version.to_bytes().expect("should serialize");
+        assert_eq!(bytes.len(), PROTOCOL_VERSION_SIZE);
+        let decoded: ProtocolVersion = deserialize(&bytes).expect("should deserialize");
+        assert_eq!(decoded, version);
+        assert!(ProtocolVersion::new(2) < decoded);
+    }
+}
@@ -0,0 +1,71 @@
+//! Arguments passed to a contract by name.
+//!
+//! A deploy can carry a `RuntimeArgs` map next to its positional arguments.
+//! The contract looks the values up with `contract_api::get_named_arg`.
+
+use crate::bytesrepr::{Error, FromBytes, ToBytes};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Serialized argument values keyed by their names.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct RuntimeArgs(BTreeMap<String, Vec<u8>>);
+
+impl RuntimeArgs {
+    pub fn new() -> RuntimeArgs {
+        Default::default()
+    }
+
+    /// Serializes `value` and stores it under `name`, replacing an earlier
+    /// value with the same name.
+    pub fn insert<T: ToBytes>(&mut self, name: &str, value: &T) -> Result<(), Error> {
+        self.0.insert(String::from(name), value.to_bytes()?);
+        Ok(())
+    }
+
+    /// Serialized value of the argument called `name`.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.0.get(name).map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl ToBytes for RuntimeArgs {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.0.to_bytes()
+    }
+}
+
+impl FromBytes for RuntimeArgs {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (args, rem): (BTreeMap<String, Vec<u8>>, &[u8]) = FromBytes::from_bytes(bytes)?;
+        Ok((RuntimeArgs(args), rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuntimeArgs;
+    use crate::bytesrepr::{deserialize, ToBytes};
+    use alloc::string::String;
+
+    #[test]
+    fn named_args_roundtrip() {
+        let mut args = RuntimeArgs::new();
+        args.insert("amount", &10u64).expect("should serialize");
+        args.insert("name", &String::from("alice")).expect("should serialize");
+        let bytes = args.to_bytes().expect("should serialize");
+        let decoded: RuntimeArgs = deserialize(&bytes).expect("should deserialize");
+        assert_eq!(decoded, args);
+        assert_eq!(decoded.get("amount"), Some(&10u64.to_le_bytes()[..]));
+        assert_eq!(decoded.get("missing"), None);
+    }
+}
@@ -0,0 +1,54 @@
+//! Identifiers of the contracts provided by the system.
+//!
+//! The engine is told where each system contract is stored when it's started;
+//! user contracts find them with `contract_api::get_system_contract`.
+
+use core::convert::TryFrom;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SystemContractId {
+    Mint = 0,
+    ProofOfStake = 1,
+    StandardPayment = 2,
+    Auction = 3,
+}
+
+impl From<SystemContractId> for u32 {
+    fn from(id: SystemContractId) -> u32 {
+        id as u32
+    }
+}
+
+impl TryFrom<u32> for SystemContractId {
+    type Error = ();
+
+    fn try_from(id: u32) -> Result<Self, Self::Error> {
+        match id {
+            0 => Ok(SystemContractId::Mint),
+            1 => Ok(SystemContractId::ProofOfStake),
+            2 => Ok(SystemContractId::StandardPayment),
+            3 => Ok(SystemContractId::Auction),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SystemContractId;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn ids_roundtrip() {
+        for id in &[
+            SystemContractId::Mint,
+            SystemContractId::ProofOfStake,
+            SystemContractId::StandardPayment,
+            SystemContractId::Auction,
+        ] {
+            assert_eq!(SystemContractId::try_from(u32::from(*id)), Ok(*id));
+        }
+        assert_eq!(SystemContractId::try_from(4), Err(()));
+    }
+}
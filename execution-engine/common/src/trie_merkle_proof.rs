@@ -0,0 +1,385 @@
+//! Proofs that a value is stored under a key in a global state, which can be
+//! checked against the root hash of the state without access to it.
+//!
+//! The global state is kept as a Merkle trie: every trie is stored at the
+//! blake2b hash of its serialization, and the nodes and extensions point to
+//! the tries under them by these hashes. A proof holds the key and the value
+//! of a leaf and, for every trie on the path from the root down to the leaf,
+//! whatever it takes to serialize the trie apart from its pointer to the trie
+//! below. Hashing the tries back up the path has to give the root hash.
+//!
+//! Hashing is left to the caller, so that contracts can validate proofs with
+//! the `blake2b` host function.
+
+use alloc::vec::Vec;
+
+use failure::Fail;
+
+use crate::bytesrepr::{self, FromBytes, ToBytes};
+
+const LEAF_TAG: u32 = 0;
+const NODE_TAG: u32 = 1;
+const EXTENSION_TAG: u32 = 2;
+
+const RADIX: usize = 256;
+
+const NODE_STEP_TAG: u8 = 0;
+const EXTENSION_STEP_TAG: u8 = 1;
+
+/// A pointer of a trie to the trie under it, serialized the same way as the
+/// pointers of the global state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pointer {
+    LeafPointer([u8; 32]),
+    NodePointer([u8; 32]),
+}
+
+impl Pointer {
+    pub fn hash(&self) -> &[u8; 32] {
+        match self {
+            Pointer::LeafPointer(hash) => hash,
+            Pointer::NodePointer(hash) => hash,
+        }
+    }
+
+    fn tag(&self) -> u32 {
+        match self {
+            Pointer::LeafPointer(_) => 0,
+            Pointer::NodePointer(_) => 1,
+        }
+    }
+}
+
+impl ToBytes for Pointer {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.tag().to_bytes()?;
+        result.append(&mut self.hash().to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for Pointer {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (tag, rem): (u32, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (hash, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(rem)?;
+        match tag {
+            0 => Ok((Pointer::LeafPointer(hash), rem)),
+            1 => Ok((Pointer::NodePointer(hash), rem)),
+            _ => Err(bytesrepr::Error::FormattingError),
+        }
+    }
+}
+
+/// A trie on the path of a proof, without its pointer to the trie below.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrieMerkleProofStep {
+    /// A node, with its pointers other than the one at `hole_index`, which
+    /// is the pointer to the trie below.
+    Node {
+        hole_index: u8,
+        indexed_pointers_with_hole: Vec<(u8, Pointer)>,
+    },
+    /// An extension, whose pointer is the pointer to the trie below.
+    Extension { affix: Vec<u8> },
+}
+
+impl TrieMerkleProofStep {
+    /// The serialization of the trie of this step, pointing to `below`.
+    fn trie_bytes(&self, below: Pointer) -> Result<Vec<u8>, bytesrepr::Error> {
+        match self {
+            TrieMerkleProofStep::Node {
+                hole_index,
+                indexed_pointers_with_hole,
+            } => {
+                let mut pointers: Vec<Option<Pointer>> = vec![None; RADIX];
+                for (index, pointer) in indexed_pointers_with_hole {
+                    pointers[*index as usize] = Some(*pointer);
+                }
+                pointers[*hole_index as usize] = Some(below);
+                let mut result = NODE_TAG.to_bytes()?;
+                result.append(&mut (RADIX as u32).to_bytes()?);
+                for pointer in pointers {
+                    result.append(&mut pointer.to_bytes()?);
+                }
+                Ok(result)
+            }
+            TrieMerkleProofStep::Extension { affix } => {
+                let mut result = EXTENSION_TAG.to_bytes()?;
+                result.append(&mut affix.to_bytes()?);
+                result.append(&mut below.to_bytes()?);
+                Ok(result)
+            }
+        }
+    }
+}
+
+impl ToBytes for TrieMerkleProofStep {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        match self {
+            TrieMerkleProofStep::Node {
+                hole_index,
+                indexed_pointers_with_hole,
+            } => {
+                let mut result = NODE_STEP_TAG.to_bytes()?;
+                result.append(&mut hole_index.to_bytes()?);
+                result.append(&mut bytesrepr::vec_to_bytes(indexed_pointers_with_hole)?);
+                Ok(result)
+            }
+            TrieMerkleProofStep::Extension { affix } => {
+                let mut result = EXTENSION_STEP_TAG.to_bytes()?;
+                result.append(&mut affix.to_bytes()?);
+                Ok(result)
+            }
+        }
+    }
+}
+
+impl FromBytes for TrieMerkleProofStep {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (tag, rem): (u8, &[u8]) = FromBytes::from_bytes(bytes)?;
+        match tag {
+            NODE_STEP_TAG => {
+                let (hole_index, rem): (u8, &[u8]) = FromBytes::from_bytes(rem)?;
+                let (indexed_pointers_with_hole, rem) = bytesrepr::vec_from_bytes(rem)?;
+                let step = TrieMerkleProofStep::Node {
+                    hole_index,
+                    indexed_pointers_with_hole,
+                };
+                Ok((step, rem))
+            }
+            EXTENSION_STEP_TAG => {
+                let (affix, rem): (Vec<u8>, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((TrieMerkleProofStep::Extension { affix }, rem))
+            }
+            _ => Err(bytesrepr::Error::FormattingError),
+        }
+    }
+}
+
+/// A proof that `value` is stored under `key`. The steps start at the trie
+/// pointing to the leaf and end at the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrieMerkleProof<K, V> {
+    key: K,
+    value: V,
+    proof_steps: Vec<TrieMerkleProofStep>,
+}
+
+impl<K, V> TrieMerkleProof<K, V>
+where
+    K: ToBytes,
+    V: ToBytes,
+{
+    pub fn new(key: K, value: V, proof_steps: Vec<TrieMerkleProofStep>) -> Self {
+        TrieMerkleProof {
+            key,
+            value,
+            proof_steps,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub fn proof_steps(&self) -> &[TrieMerkleProofStep] {
+        &self.proof_steps
+    }
+
+    /// The root hash of the state the proof is of, hashing the tries on its
+    /// path with `hash`.
+    pub fn compute_state_hash<H>(&self, hash: H) -> Result<[u8; 32], bytesrepr::Error>
+    where
+        H: Fn(&[u8]) -> [u8; 32],
+    {
+        let mut leaf = LEAF_TAG.to_bytes()?;
+        leaf.append(&mut self.key.to_bytes()?);
+        leaf.append(&mut self.value.to_bytes()?);
+        let mut pointer = Pointer::LeafPointer(hash(&leaf));
+        for step in &self.proof_steps {
+            pointer = Pointer::NodePointer(hash(&step.trie_bytes(pointer)?));
+        }
+        Ok(*pointer.hash())
+    }
+}
+
+impl<K: ToBytes, V: ToBytes> ToBytes for TrieMerkleProof<K, V> {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.key.to_bytes()?;
+        result.append(&mut self.value.to_bytes()?);
+        result.append(&mut bytesrepr::vec_to_bytes(&self.proof_steps)?);
+        Ok(result)
+    }
+}
+
+impl<K: FromBytes, V: FromBytes> FromBytes for TrieMerkleProof<K, V> {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (key, rem): (K, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (value, rem): (V, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (proof_steps, rem) = bytesrepr::vec_from_bytes(rem)?;
+        let proof = TrieMerkleProof {
+            key,
+            value,
+            proof_steps,
+        };
+        Ok((proof, rem))
+    }
+}
+
+#[derive(Fail, Debug, PartialEq, Eq, Clone)]
+pub enum ValidationError {
+    #[fail(display = "The proof is of another key")]
+    KeyMismatch,
+
+    #[fail(display = "The steps of the proof don't follow the path of the key")]
+    PathMismatch,
+
+    #[fail(display = "The proof is of another state")]
+    RootHashMismatch,
+
+    #[fail(display = "{}", _0)]
+    BytesRepr(bytesrepr::Error),
+}
+
+impl From<bytesrepr::Error> for ValidationError {
+    fn from(error: bytesrepr::Error) -> Self {
+        ValidationError::BytesRepr(error)
+    }
+}
+
+/// Checks that `proof` proves a value stored under `key` in the state whose
+/// root hash is `root_hash`, hashing with `hash`.
+pub fn validate_proof<K, V, H>(
+    root_hash: &[u8; 32],
+    key: &K,
+    proof: &TrieMerkleProof<K, V>,
+    hash: H,
+) -> Result<(), ValidationError>
+where
+    K: ToBytes + PartialEq,
+    V: ToBytes,
+    H: Fn(&[u8]) -> [u8; 32],
+{
+    if proof.key() != key {
+        return Err(ValidationError::KeyMismatch);
+    }
+    // Otherwise the leaf could be put anywhere in the trie.
+    let path = key.to_bytes()?;
+    let mut depth = 0;
+    for step in proof.proof_steps().iter().rev() {
+        let consumed = match step {
+            TrieMerkleProofStep::Node { hole_index, .. } => match path.get(depth) {
+                Some(index) if index == hole_index => 1,
+                _ => return Err(ValidationError::PathMismatch),
+            },
+            TrieMerkleProofStep::Extension { affix } => match path.get(depth..) {
+                Some(rest) if rest.starts_with(affix) => affix.len(),
+                _ => return Err(ValidationError::PathMismatch),
+            },
+        };
+        depth += consumed;
+    }
+    if proof.compute_state_hash(hash)? != *root_hash {
+        return Err(ValidationError::RootHashMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::bytesrepr::ToBytes;
+    use crate::key::Key;
+    use crate::test_utils::test_serialization_roundtrip;
+    use crate::value::Value;
+
+    use super::{
+        validate_proof, Pointer, TrieMerkleProof, TrieMerkleProofStep, ValidationError, LEAF_TAG,
+    };
+
+    /// Not a cryptographic hash, but it tells the tries of the tests apart.
+    fn hash(bytes: &[u8]) -> [u8; 32] {
+        let mut result = [0u8; 32];
+        for (i, byte) in bytes.iter().enumerate() {
+            result[i % 32] = result[i % 32].wrapping_mul(31).wrapping_add(*byte);
+        }
+        result
+    }
+
+    const KEY: Key = Key::Account([3u8; 20]);
+
+    /// A proof of `Value::Int32(1)` under `KEY` below a node and an extension
+    /// with the rest of the path.
+    fn proof() -> TrieMerkleProof<Key, Value> {
+        let path = KEY.to_bytes().unwrap();
+        let node = TrieMerkleProofStep::Node {
+            hole_index: path[0],
+            indexed_pointers_with_hole: vec![(7, Pointer::LeafPointer([7u8; 32]))],
+        };
+        let extension = TrieMerkleProofStep::Extension {
+            affix: path[1..].to_vec(),
+        };
+        TrieMerkleProof::new(KEY, Value::Int32(1), vec![extension, node])
+    }
+
+    fn root_hash() -> [u8; 32] {
+        proof().compute_state_hash(hash).unwrap()
+    }
+
+    #[test]
+    fn proofs_are_serialized() {
+        assert!(test_serialization_roundtrip(&proof()));
+    }
+
+    #[test]
+    fn proofs_of_the_state_are_valid() {
+        assert_eq!(validate_proof(&root_hash(), &KEY, &proof(), hash), Ok(()));
+    }
+
+    #[test]
+    fn proofs_without_steps_hash_the_leaf() {
+        let proof = TrieMerkleProof::new(KEY, Value::Int32(1), Vec::new());
+        let mut leaf = LEAF_TAG.to_bytes().unwrap();
+        leaf.append(&mut KEY.to_bytes().unwrap());
+        leaf.append(&mut Value::Int32(1).to_bytes().unwrap());
+        assert_eq!(validate_proof(&hash(&leaf), &KEY, &proof, hash), Ok(()));
+    }
+
+    #[test]
+    fn proofs_of_other_values_are_invalid() {
+        let mut other = proof();
+        other.value = Value::Int32(2);
+        assert_eq!(
+            validate_proof(&root_hash(), &KEY, &other, hash),
+            Err(ValidationError::RootHashMismatch)
+        );
+    }
+
+    #[test]
+    fn proofs_of_other_keys_are_invalid() {
+        let other_key = Key::Account([4u8; 20]);
+        assert_eq!(
+            validate_proof(&root_hash(), &other_key, &proof(), hash),
+            Err(ValidationError::KeyMismatch)
+        );
+    }
+
+    #[test]
+    fn proofs_off_the_path_of_the_key_are_invalid() {
+        let mut other = proof();
+        other.proof_steps[1] = TrieMerkleProofStep::Node {
+            hole_index: 7,
+            indexed_pointers_with_hole: Vec::new(),
+        };
+        let root_hash = other.compute_state_hash(hash).unwrap();
+        assert_eq!(
+            validate_proof(&root_hash, &KEY, &other, hash),
+            Err(ValidationError::PathMismatch)
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Descriptions of the interface of stored contracts.
+//!
+//! An `Abi` is stored together with a `Contract` and lets clients discover how
+//! to call the contract (which entry points it has and what arguments they
+//! take) straight from the global state.
+
+use crate::bytesrepr::{vec_from_bytes, vec_to_bytes, Error, FromBytes, ToBytes};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// Maximal size of a serialized `Abi` which can be stored with a contract.
+pub const MAX_ABI_SIZE: usize = 8 * 1024;
+
+/// Type of an argument or of a return value of an entry point.
+#[repr(u8)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AbiType {
+    Unit = 0,
+    Int32 = 1,
+    UInt128 = 2,
+    UInt256 = 3,
+    UInt512 = 4,
+    ByteArray = 5,
+    ListInt32 = 6,
+    String = 7,
+    ListString = 8,
+    Key = 9,
+}
+
+impl TryFrom<u8> for AbiType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<AbiType, Error> {
+        match value {
+            0 => Ok(AbiType::Unit),
+            1 => Ok(AbiType::Int32),
+            2 => Ok(AbiType::UInt128),
+            3 => Ok(AbiType::UInt256),
+            4 => Ok(AbiType::UInt512),
+            5 => Ok(AbiType::ByteArray),
+            6 => Ok(AbiType::ListInt32),
+            7 => Ok(AbiType::String),
+            8 => Ok(AbiType::ListString),
+            9 => Ok(AbiType::Key),
+            _ => Err(Error::FormattingError),
+        }
+    }
+}
+
+impl ToBytes for AbiType {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        (*self as u8).to_bytes()
+    }
+}
+
+impl FromBytes for AbiType {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (id, rem): (u8, &[u8]) = FromBytes::from_bytes(bytes)?;
+        Ok((AbiType::try_from(id)?, rem))
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Arg {
+    name: String,
+    arg_type: AbiType,
+}
+
+impl Arg {
+    pub fn new(name: String, arg_type: AbiType) -> Self {
+        Arg { name, arg_type }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arg_type(&self) -> AbiType {
+        self.arg_type
+    }
+}
+
+impl ToBytes for Arg {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut result = self.name.to_bytes()?;
+        result.append(&mut self.arg_type.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for Arg {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (name, rem1): (String, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (arg_type, rem2): (AbiType, &[u8]) = FromBytes::from_bytes(rem1)?;
+        Ok((Arg { name, arg_type }, rem2))
+    }
+}
+
+/// An entry point of a contract. If it lists any user groups, callers of a
+/// package running the contract have to present a URef of one of them.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct EntryPoint {
+    name: String,
+    args: Vec<Arg>,
+    ret: AbiType,
+    groups: Vec<String>,
+}
+
+impl EntryPoint {
+    pub fn new(name: String, args: Vec<Arg>, ret: AbiType) -> Self {
+        EntryPoint {
+            name,
+            args,
+            ret,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Restricts the entry point to members of the given user groups.
+    pub fn with_groups(mut self, groups: Vec<String>) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Arguments in the order they have to be passed.
+    pub fn args(&self) -> &[Arg] {
+        &self.args
+    }
+
+    pub fn ret(&self) -> AbiType {
+        self.ret
+    }
+
+    /// Names of the user groups allowed to call the entry point. Empty if
+    /// anyone can call it.
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+}
+
+impl ToBytes for EntryPoint {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut result = self.name.to_bytes()?;
+        result.append(&mut vec_to_bytes(&self.args)?);
+        result.append(&mut self.ret.to_bytes()?);
+        result.append(&mut self.groups.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for EntryPoint {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (name, rem1): (String, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (args, rem2): (Vec<Arg>, &[u8]) = vec_from_bytes(rem1)?;
+        let (ret, rem3): (AbiType, &[u8]) = FromBytes::from_bytes(rem2)?;
+        let (groups, rem4): (Vec<String>, &[u8]) = FromBytes::from_bytes(rem3)?;
+        let entry_point = EntryPoint {
+            name,
+            args,
+            ret,
+            groups,
+        };
+        Ok((entry_point, rem4))
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Abi {
+    entry_points: Vec<EntryPoint>,
+}
+
+impl Abi {
+    pub fn new(entry_points: Vec<EntryPoint>) -> Self {
+        Abi { entry_points }
+    }
+
+    pub fn entry_points(&self) -> &[EntryPoint] {
+        &self.entry_points
+    }
+
+    pub fn entry_point(&self, name: &str) -> Option<&EntryPoint> {
+        self.entry_points.iter().find(|entry_point| entry_point.name == name)
+    }
+}
+
+impl ToBytes for Abi {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        vec_to_bytes(&self.entry_points)
+    }
+}
+
+impl FromBytes for Abi {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (entry_points, rem): (Vec<EntryPoint>, &[u8]) = vec_from_bytes(bytes)?;
+        Ok((Abi { entry_points }, rem))
+    }
+}
@@ -1,29 +1,509 @@
-use crate::bytesrepr::{Error, FromBytes, ToBytes, U64_SIZE};
-use crate::key::{Key, KEY_SIZE, UREF_SIZE};
+use crate::bytesrepr::{Error, FromBytes, ToBytes, N32, U32_SIZE, U64_SIZE, U8_SIZE};
+use crate::key::{AccessRights, Key, KEY_SIZE, UREF_SIZE};
 use alloc::collections::btree_map::BTreeMap;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::convert::TryFrom;
+use failure::Fail;
+
+/// Default maximum number of keys that can be associated with an account.
+pub const DEFAULT_MAX_KEYS: usize = 10;
+
+/// Address of the system account, which the engine acts as when it changes
+/// the state on its own behalf. No deploy can be run under it.
+pub const SYSTEM_ACCOUNT: [u8; 20] = [0u8; 20];
+
+/// Errors which can occur while managing associated keys and action thresholds
+/// of an account. Discriminants are the codes returned by the host functions
+/// and must not change.
+#[repr(i32)]
+#[derive(Fail, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KeyManagementError {
+    #[fail(display = "Maximum number of associated keys reached")]
+    MaxKeysLimit = 1,
+    #[fail(display = "Key is already associated with the account")]
+    DuplicateKey = 2,
+    #[fail(display = "Key is not associated with the account")]
+    MissingKey = 3,
+    #[fail(display = "Weight out of allowed range")]
+    WeightOutOfRange = 4,
+    #[fail(display = "Action thresholds can't be met by associated keys")]
+    ThresholdViolation = 5,
+    #[fail(display = "Deploy isn't authorized to manage keys")]
+    PermissionDenied = 6,
+}
+
+impl From<KeyManagementError> for i32 {
+    fn from(error: KeyManagementError) -> i32 {
+        error as i32
+    }
+}
+
+impl TryFrom<i32> for KeyManagementError {
+    type Error = ();
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(KeyManagementError::MaxKeysLimit),
+            2 => Ok(KeyManagementError::DuplicateKey),
+            3 => Ok(KeyManagementError::MissingKey),
+            4 => Ok(KeyManagementError::WeightOutOfRange),
+            5 => Ok(KeyManagementError::ThresholdViolation),
+            6 => Ok(KeyManagementError::PermissionDenied),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Account a transfer ended up in. Discriminants are the codes returned by
+/// the host functions on success.
+#[repr(i32)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransferredTo {
+    ExistingAccount = 0,
+    NewAccount = 1,
+}
+
+/// Errors which can occur while transferring tokens. Discriminants are the
+/// codes returned by the host functions and don't overlap with the codes of
+/// `TransferredTo`.
+#[repr(i32)]
+#[derive(Fail, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransferError {
+    #[fail(display = "Source purse doesn't hold enough tokens")]
+    InsufficientFunds = 2,
+    #[fail(display = "Tokens can only be transferred from an account's context")]
+    InvalidContext = 3,
+}
+
+pub type TransferResult = Result<TransferredTo, TransferError>;
+
+impl From<TransferredTo> for i32 {
+    fn from(transferred_to: TransferredTo) -> i32 {
+        transferred_to as i32
+    }
+}
+
+impl From<TransferError> for i32 {
+    fn from(error: TransferError) -> i32 {
+        error as i32
+    }
+}
+
+impl TryFrom<i32> for TransferredTo {
+    type Error = ();
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(TransferredTo::ExistingAccount),
+            1 => Ok(TransferredTo::NewAccount),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TryFrom<i32> for TransferError {
+    type Error = ();
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            2 => Ok(TransferError::InsufficientFunds),
+            3 => Ok(TransferError::InvalidContext),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Bounds enforced when associating keys with an account.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct KeyLimits {
+    max_keys: usize,
+    min_weight: Weight,
+    max_weight: Weight,
+}
+
+impl KeyLimits {
+    pub fn new(max_keys: usize, min_weight: Weight, max_weight: Weight) -> KeyLimits {
+        KeyLimits {
+            max_keys,
+            min_weight,
+            max_weight,
+        }
+    }
+
+    /// The limits, allowing at most `max_keys` keys per account.
+    pub fn with_max_keys(mut self, max_keys: usize) -> KeyLimits {
+        self.max_keys = max_keys;
+        self
+    }
+
+    pub fn max_keys(&self) -> usize {
+        self.max_keys
+    }
+
+    fn check_weight(&self, weight: Weight) -> Result<(), KeyManagementError> {
+        if weight < self.min_weight || weight > self.max_weight {
+            Err(KeyManagementError::WeightOutOfRange)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for KeyLimits {
+    fn default() -> Self {
+        KeyLimits::new(DEFAULT_MAX_KEYS, Weight::new(1), Weight::new(u8::max_value()))
+    }
+}
+
+/// Size of a serialized `PublicKey` (length prefix followed by 32 bytes).
+pub const PUBLIC_KEY_SIZE: usize = U32_SIZE + N32;
+
+/// Public key of one of the keys allowed to act on behalf of an account.
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct PublicKey([u8; 32]);
+
+impl PublicKey {
+    pub fn new(key: [u8; 32]) -> PublicKey {
+        PublicKey(key)
+    }
+
+    pub fn value(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl From<[u8; 32]> for PublicKey {
+    fn from(key: [u8; 32]) -> PublicKey {
+        PublicKey(key)
+    }
+}
+
+impl ToBytes for PublicKey {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.0.to_bytes()
+    }
+}
+
+impl FromBytes for PublicKey {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (key, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(bytes)?;
+        Ok((PublicKey(key), rem))
+    }
+}
+
+/// Weight of an associated key.
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Weight(u8);
+
+impl Weight {
+    pub fn new(weight: u8) -> Weight {
+        Weight(weight)
+    }
+
+    pub fn value(self) -> u8 {
+        self.0
+    }
+}
+
+impl ToBytes for Weight {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.0.to_bytes()
+    }
+}
+
+impl FromBytes for Weight {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (weight, rem): (u8, &[u8]) = FromBytes::from_bytes(bytes)?;
+        Ok((Weight(weight), rem))
+    }
+}
+
+/// Kinds of actions guarded by action thresholds.
+#[repr(u32)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ActionType {
+    Deployment = 0,
+    KeyManagement = 1,
+}
+
+impl TryFrom<u32> for ActionType {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ActionType::Deployment),
+            1 => Ok(ActionType::KeyManagement),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Minimal total weight of the authorization keys that is required
+/// to perform particular kinds of actions on behalf of an account.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ActionThresholds {
+    deployment: Weight,
+    key_management: Weight,
+}
+
+impl ActionThresholds {
+    pub fn new(deployment: Weight, key_management: Weight) -> ActionThresholds {
+        ActionThresholds {
+            deployment,
+            key_management,
+        }
+    }
+
+    pub fn deployment(&self) -> &Weight {
+        &self.deployment
+    }
+
+    pub fn key_management(&self) -> &Weight {
+        &self.key_management
+    }
+
+    pub fn get(&self, action_type: ActionType) -> &Weight {
+        match action_type {
+            ActionType::Deployment => &self.deployment,
+            ActionType::KeyManagement => &self.key_management,
+        }
+    }
+
+    /// Sets threshold for `action_type`. Deployment threshold can't
+    /// exceed the key management one.
+    pub fn set(
+        &mut self,
+        action_type: ActionType,
+        threshold: Weight,
+    ) -> Result<(), KeyManagementError> {
+        let (deployment, key_management) = match action_type {
+            ActionType::Deployment => (threshold, self.key_management),
+            ActionType::KeyManagement => (self.deployment, threshold),
+        };
+        if deployment > key_management {
+            return Err(KeyManagementError::ThresholdViolation);
+        }
+        self.deployment = deployment;
+        self.key_management = key_management;
+        Ok(())
+    }
+
+    fn max(&self) -> Weight {
+        core::cmp::max(self.deployment, self.key_management)
+    }
+}
+
+impl Default for ActionThresholds {
+    fn default() -> Self {
+        ActionThresholds::new(Weight::new(1), Weight::new(1))
+    }
+}
+
+impl ToBytes for ActionThresholds {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::with_capacity(2 * U8_SIZE);
+        result.append(&mut self.deployment.to_bytes()?);
+        result.append(&mut self.key_management.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for ActionThresholds {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (deployment, rem1): (Weight, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (key_management, rem2): (Weight, &[u8]) = FromBytes::from_bytes(rem1)?;
+        Ok((ActionThresholds::new(deployment, key_management), rem2))
+    }
+}
+
+/// Unforgeable reference to a purse. The balance of the purse
+/// is stored in the global state under this reference.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct PurseId([u8; 32], AccessRights);
+
+impl PurseId {
+    pub fn new(addr: [u8; 32], access_rights: AccessRights) -> PurseId {
+        PurseId(addr, access_rights)
+    }
+
+    pub fn addr(&self) -> [u8; 32] {
+        self.0
+    }
+
+    pub fn access_rights(&self) -> AccessRights {
+        self.1
+    }
+
+    /// Returns a reference to the same purse carrying `access_rights`.
+    pub fn with_access_rights(self, access_rights: AccessRights) -> PurseId {
+        PurseId(self.0, access_rights)
+    }
+}
+
+impl From<PurseId> for Key {
+    fn from(purse_id: PurseId) -> Key {
+        Key::URef(purse_id.0, purse_id.1)
+    }
+}
+
+impl ToBytes for PurseId {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Key::from(*self).to_bytes()
+    }
+}
+
+impl FromBytes for PurseId {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (key, rem): (Key, &[u8]) = FromBytes::from_bytes(bytes)?;
+        match key {
+            Key::URef(addr, access_rights) => Ok((PurseId(addr, access_rights), rem)),
+            _ => Err(Error::FormattingError),
+        }
+    }
+}
+
+/// Set of keys (together with their weights) that are allowed to
+/// authorize deploys on behalf of an account.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AssociatedKeys(BTreeMap<PublicKey, Weight>);
+
+impl AssociatedKeys {
+    pub fn new(key: PublicKey, weight: Weight) -> AssociatedKeys {
+        let mut keys = BTreeMap::new();
+        keys.insert(key, weight);
+        AssociatedKeys(keys)
+    }
+
+    /// Adds new key to the set, respecting `limits`.
+    /// On error the set is left unchanged.
+    pub fn add_key(
+        &mut self,
+        key: PublicKey,
+        weight: Weight,
+        limits: &KeyLimits,
+    ) -> Result<(), KeyManagementError> {
+        limits.check_weight(weight)?;
+        if self.0.contains_key(&key) {
+            Err(KeyManagementError::DuplicateKey)
+        } else if self.0.len() >= limits.max_keys() {
+            Err(KeyManagementError::MaxKeysLimit)
+        } else {
+            self.0.insert(key, weight);
+            Ok(())
+        }
+    }
+
+    pub fn remove_key(&mut self, key: &PublicKey) -> Result<Weight, KeyManagementError> {
+        self.0.remove(key).ok_or(KeyManagementError::MissingKey)
+    }
+
+    pub fn get(&self, key: &PublicKey) -> Option<&Weight> {
+        self.0.get(key)
+    }
+
+    pub fn contains_key(&self, key: &PublicKey) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PublicKey, &Weight)> {
+        self.0.iter()
+    }
+
+    /// Sums the weights of all associated keys. Saturates at `u8::max_value()`.
+    pub fn full_weight(&self) -> Weight {
+        self.total_weight(self.0.keys())
+    }
+
+    /// Sums the weights of those `keys` which are associated with the account.
+    /// Saturates at `u8::max_value()`.
+    pub fn total_weight<'a, I: IntoIterator<Item = &'a PublicKey>>(&self, keys: I) -> Weight {
+        let total = keys
+            .into_iter()
+            .filter_map(|key| self.0.get(key))
+            .fold(0u8, |acc, weight| acc.saturating_add(weight.value()));
+        Weight::new(total)
+    }
+}
+
+impl ToBytes for AssociatedKeys {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.0.to_bytes()
+    }
+}
+
+impl FromBytes for AssociatedKeys {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (keys, rem): (BTreeMap<PublicKey, Weight>, &[u8]) = FromBytes::from_bytes(bytes)?;
+        Ok((AssociatedKeys(keys), rem))
+    }
+}
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Account {
     public_key: [u8; 32],
     nonce: u64,
     known_urefs: BTreeMap<String, Key>,
+    purse_id: PurseId,
+    associated_keys: AssociatedKeys,
+    action_thresholds: ActionThresholds,
 }
 
 impl Account {
-    pub fn new(public_key: [u8; 32], nonce: u64, known_urefs: BTreeMap<String, Key>) -> Self {
+    pub fn new(
+        public_key: [u8; 32],
+        nonce: u64,
+        known_urefs: BTreeMap<String, Key>,
+        purse_id: PurseId,
+        associated_keys: AssociatedKeys,
+        action_thresholds: ActionThresholds,
+    ) -> Self {
         Account {
             public_key,
             nonce,
             known_urefs,
+            purse_id,
+            associated_keys,
+            action_thresholds,
         }
     }
 
+    /// Creates an account whose only associated key is its own `public_key`
+    /// and which uses default action thresholds.
+    pub fn create(
+        public_key: [u8; 32],
+        nonce: u64,
+        known_urefs: BTreeMap<String, Key>,
+        purse_id: PurseId,
+    ) -> Self {
+        let associated_keys = AssociatedKeys::new(PublicKey::new(public_key), Weight::new(1));
+        Account::new(
+            public_key,
+            nonce,
+            known_urefs,
+            purse_id,
+            associated_keys,
+            ActionThresholds::default(),
+        )
+    }
+
     pub fn insert_urefs(&mut self, keys: &mut BTreeMap<String, Key>) {
         self.known_urefs.append(keys);
     }
 
+    /// Removes the key known under `name`, returning it if there was one.
+    pub fn remove_uref(&mut self, name: &str) -> Option<Key> {
+        self.known_urefs.remove(name)
+    }
+
     pub fn urefs_lookup(&self) -> &BTreeMap<String, Key> {
         &self.known_urefs
     }
@@ -36,21 +516,84 @@ impl Account {
         &self.public_key
     }
 
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::new(self.public_key)
+    }
+
     pub fn nonce(&self) -> u64 {
         self.nonce
     }
+
+    pub fn purse_id(&self) -> PurseId {
+        self.purse_id
+    }
+
+    /// Replaces main purse of the account.
+    pub fn set_purse_id(&mut self, purse_id: PurseId) {
+        self.purse_id = purse_id;
+    }
+
+    pub fn associated_keys(&self) -> &AssociatedKeys {
+        &self.associated_keys
+    }
+
+    pub fn action_thresholds(&self) -> &ActionThresholds {
+        &self.action_thresholds
+    }
+
+    pub fn add_associated_key(
+        &mut self,
+        key: PublicKey,
+        weight: Weight,
+        limits: &KeyLimits,
+    ) -> Result<(), KeyManagementError> {
+        self.associated_keys.add_key(key, weight, limits)
+    }
+
+    /// Removes an associated key unless the remaining keys wouldn't be able
+    /// to meet the action thresholds anymore.
+    pub fn remove_associated_key(&mut self, key: &PublicKey) -> Result<(), KeyManagementError> {
+        let mut associated_keys = self.associated_keys.clone();
+        associated_keys.remove_key(key)?;
+        if associated_keys.full_weight() < self.action_thresholds.max() {
+            return Err(KeyManagementError::ThresholdViolation);
+        }
+        self.associated_keys = associated_keys;
+        Ok(())
+    }
+
+    /// Sets the threshold of `action_type` unless it couldn't be met by
+    /// the associated keys.
+    pub fn set_action_threshold(
+        &mut self,
+        action_type: ActionType,
+        threshold: Weight,
+    ) -> Result<(), KeyManagementError> {
+        if threshold > self.associated_keys.full_weight() {
+            return Err(KeyManagementError::ThresholdViolation);
+        }
+        self.action_thresholds.set(action_type, threshold)
+    }
 }
 
 impl ToBytes for Account {
     fn to_bytes(&self) -> Result<Vec<u8>, Error> {
-        if UREF_SIZE * self.known_urefs.len() >= u32::max_value() as usize - KEY_SIZE - U64_SIZE {
+        let associated_keys_size = (PUBLIC_KEY_SIZE + U8_SIZE) * self.associated_keys.len();
+        let fixed_size = KEY_SIZE + U64_SIZE + UREF_SIZE + U32_SIZE + 2 * U8_SIZE;
+        if UREF_SIZE * self.known_urefs.len() + associated_keys_size
+            >= u32::max_value() as usize - fixed_size
+        {
             return Err(Error::OutOfMemoryError);
         }
-        let mut result: Vec<u8> =
-            Vec::with_capacity(KEY_SIZE + U64_SIZE + UREF_SIZE * self.known_urefs.len());
+        let mut result: Vec<u8> = Vec::with_capacity(
+            fixed_size + UREF_SIZE * self.known_urefs.len() + associated_keys_size,
+        );
         result.extend(&self.public_key.to_bytes()?);
         result.append(&mut self.nonce.to_bytes()?);
         result.append(&mut self.known_urefs.to_bytes()?);
+        result.append(&mut self.purse_id.to_bytes()?);
+        result.append(&mut self.associated_keys.to_bytes()?);
+        result.append(&mut self.action_thresholds.to_bytes()?);
         Ok(result)
     }
 }
@@ -60,13 +603,138 @@ impl FromBytes for Account {
         let (public_key, rem1): ([u8; 32], &[u8]) = FromBytes::from_bytes(bytes)?;
         let (nonce, rem2): (u64, &[u8]) = FromBytes::from_bytes(rem1)?;
         let (known_urefs, rem3): (BTreeMap<String, Key>, &[u8]) = FromBytes::from_bytes(rem2)?;
+        let (purse_id, rem4): (PurseId, &[u8]) = FromBytes::from_bytes(rem3)?;
+        let (associated_keys, rem5): (AssociatedKeys, &[u8]) = FromBytes::from_bytes(rem4)?;
+        let (action_thresholds, rem6): (ActionThresholds, &[u8]) = FromBytes::from_bytes(rem5)?;
         Ok((
             Account {
                 public_key,
                 nonce,
                 known_urefs,
+                purse_id,
+                associated_keys,
+                action_thresholds,
             },
-            rem3,
+            rem6,
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Account, ActionThresholds, ActionType, AssociatedKeys, KeyLimits, KeyManagementError,
+        PublicKey, PurseId, Weight,
+    };
+    use crate::key::AccessRights;
+    use alloc::collections::btree_map::BTreeMap;
+    use core::convert::TryFrom;
+
+    fn account(weight: u8, thresholds: (u8, u8)) -> Account {
+        Account::new(
+            [1u8; 32],
+            0,
+            BTreeMap::new(),
+            PurseId::new([0u8; 32], AccessRights::READ_ADD_WRITE),
+            AssociatedKeys::new(PublicKey::new([1u8; 32]), Weight::new(weight)),
+            ActionThresholds::new(Weight::new(thresholds.0), Weight::new(thresholds.1)),
+        )
+    }
+
+    #[test]
+    fn associated_keys_add_duplicate() {
+        let key = PublicKey::new([1u8; 32]);
+        let limits = KeyLimits::default();
+        let mut keys = AssociatedKeys::new(key, Weight::new(1));
+        assert_eq!(
+            keys.add_key(key, Weight::new(2), &limits),
+            Err(KeyManagementError::DuplicateKey)
+        );
+        assert_eq!(keys.get(&key), Some(&Weight::new(1)));
+        assert!(keys
+            .add_key(PublicKey::new([2u8; 32]), Weight::new(2), &limits)
+            .is_ok());
+        assert_eq!(keys.len(), 2);
+    }
+
+    #[test]
+    fn associated_keys_max_keys_limit() {
+        let limits = KeyLimits::new(2, Weight::new(1), Weight::new(10));
+        let mut keys = AssociatedKeys::new(PublicKey::new([1u8; 32]), Weight::new(1));
+        assert!(keys
+            .add_key(PublicKey::new([2u8; 32]), Weight::new(1), &limits)
+            .is_ok());
+        assert_eq!(
+            keys.add_key(PublicKey::new([3u8; 32]), Weight::new(1), &limits),
+            Err(KeyManagementError::MaxKeysLimit)
+        );
+        assert_eq!(keys.len(), 2);
+    }
+
+    #[test]
+    fn associated_keys_weight_out_of_range() {
+        let limits = KeyLimits::new(10, Weight::new(1), Weight::new(10));
+        let mut keys = AssociatedKeys::new(PublicKey::new([1u8; 32]), Weight::new(1));
+        assert_eq!(
+            keys.add_key(PublicKey::new([2u8; 32]), Weight::new(0), &limits),
+            Err(KeyManagementError::WeightOutOfRange)
+        );
+        assert_eq!(
+            keys.add_key(PublicKey::new([2u8; 32]), Weight::new(11), &limits),
+            Err(KeyManagementError::WeightOutOfRange)
+        );
+    }
+
+    #[test]
+    fn remove_key_violating_threshold() {
+        let mut account = account(1, (1, 1));
+        assert_eq!(
+            account.remove_associated_key(&PublicKey::new([1u8; 32])),
+            Err(KeyManagementError::ThresholdViolation)
+        );
+        assert_eq!(
+            account.remove_associated_key(&PublicKey::new([2u8; 32])),
+            Err(KeyManagementError::MissingKey)
+        );
+    }
+
+    #[test]
+    fn set_threshold_violation() {
+        let mut account = account(2, (1, 1));
+        assert_eq!(
+            account.set_action_threshold(ActionType::KeyManagement, Weight::new(3)),
+            Err(KeyManagementError::ThresholdViolation)
+        );
+        assert_eq!(
+            account.set_action_threshold(ActionType::Deployment, Weight::new(2)),
+            Err(KeyManagementError::ThresholdViolation)
+        );
+        assert!(account
+            .set_action_threshold(ActionType::KeyManagement, Weight::new(2))
+            .is_ok());
+        assert!(account
+            .set_action_threshold(ActionType::Deployment, Weight::new(2))
+            .is_ok());
+    }
+
+    #[test]
+    fn key_management_error_codes_roundtrip() {
+        for code in 1..=6 {
+            let error = KeyManagementError::try_from(code).expect("should be a valid code");
+            assert_eq!(i32::from(error), code);
+        }
+        assert!(KeyManagementError::try_from(0).is_err());
+    }
+
+    #[test]
+    fn associated_keys_total_weight() {
+        let key_1 = PublicKey::new([1u8; 32]);
+        let key_2 = PublicKey::new([2u8; 32]);
+        let unknown_key = PublicKey::new([3u8; 32]);
+        let mut keys = AssociatedKeys::new(key_1, Weight::new(200));
+        keys.add_key(key_2, Weight::new(100));
+        assert_eq!(keys.total_weight(&[key_1]), Weight::new(200));
+        assert_eq!(keys.total_weight(&[key_2, unknown_key]), Weight::new(100));
+        assert_eq!(keys.total_weight(&[key_1, key_2]), Weight::new(255));
+    }
+}
@@ -1,52 +1,96 @@
-use crate::bytesrepr::{Error, FromBytes, ToBytes, U32_SIZE};
+use crate::bytesrepr::{Error, FromBytes, ToBytes, U32_SIZE, U64_SIZE};
 use crate::key::{Key, UREF_SIZE};
+use crate::value::abi::Abi;
 use alloc::collections::btree_map::BTreeMap;
 use alloc::string::String;
 use alloc::vec::Vec;
 
+/// Major version of the host function ABI. Contracts record the version they
+/// were built against and the host refuses to run contracts whose version
+/// isn't compatible with its own.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// Version of the contracts which don't record one, like those the node sends
+/// without it. They're taken to be built against the current version.
+pub const UNVERSIONED: u64 = 0;
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Contract {
     bytes: Vec<u8>,
     known_urefs: BTreeMap<String, Key>,
+    protocol_version: u64,
+    abi: Option<Abi>,
 }
 
 impl Contract {
-    pub fn new(bytes: Vec<u8>, known_urefs: BTreeMap<String, Key>) -> Self {
-        Contract { bytes, known_urefs }
+    pub fn new(bytes: Vec<u8>, known_urefs: BTreeMap<String, Key>, protocol_version: u64) -> Self {
+        Contract {
+            bytes,
+            known_urefs,
+            protocol_version,
+            abi: None,
+        }
+    }
+
+    /// Attaches a description of the contract's interface.
+    pub fn with_abi(mut self, abi: Abi) -> Self {
+        self.abi = Some(abi);
+        self
     }
 
     pub fn insert_urefs(&mut self, keys: &mut BTreeMap<String, Key>) {
         self.known_urefs.append(keys);
     }
 
+    /// Removes the key known under `name`, returning it if there was one.
+    pub fn remove_uref(&mut self, name: &str) -> Option<Key> {
+        self.known_urefs.remove(name)
+    }
+
     pub fn urefs_lookup(&self) -> &BTreeMap<String, Key> {
         &self.known_urefs
     }
 
-    pub fn destructure(self) -> (Vec<u8>, BTreeMap<String, Key>) {
-        (self.bytes, self.known_urefs)
+    pub fn destructure(self) -> (Vec<u8>, BTreeMap<String, Key>, u64, Option<Abi>) {
+        (self.bytes, self.known_urefs, self.protocol_version, self.abi)
     }
 
     pub fn bytes(&self) -> &[u8] {
         &self.bytes
     }
+
+    pub fn protocol_version(&self) -> u64 {
+        self.protocol_version
+    }
+
+    /// Whether the contract can run under [`PROTOCOL_VERSION`].
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION || self.protocol_version == UNVERSIONED
+    }
+
+    pub fn abi(&self) -> Option<&Abi> {
+        self.abi.as_ref()
+    }
 }
 
 impl ToBytes for Contract {
     fn to_bytes(&self) -> Result<Vec<u8>, Error> {
         if self.bytes.len() + UREF_SIZE * self.known_urefs.len()
-            >= u32::max_value() as usize - U32_SIZE * 2
+            >= u32::max_value() as usize - U32_SIZE * 2 - U64_SIZE
         {
             return Err(Error::OutOfMemoryError);
         }
         let size: usize = U32_SIZE +                           //size for length of bytes
                     self.bytes.len() +                  //size for elements of bytes
                     U32_SIZE +                                 //size for length of known_urefs
-                    UREF_SIZE * self.known_urefs.len(); //size for known_urefs elements
+                    UREF_SIZE * self.known_urefs.len() + //size for known_urefs elements
+                    U64_SIZE;                                  //size for protocol_version
 
         let mut result = Vec::with_capacity(size);
         result.append(&mut self.bytes.to_bytes()?);
         result.append(&mut self.known_urefs.to_bytes()?);
+        result.append(&mut self.protocol_version.to_bytes()?);
+        result.append(&mut self.abi.to_bytes()?);
         Ok(result)
     }
 }
@@ -55,6 +99,16 @@ impl FromBytes for Contract {
     fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
         let (bytes, rem1): (Vec<u8>, &[u8]) = FromBytes::from_bytes(bytes)?;
         let (known_urefs, rem2): (BTreeMap<String, Key>, &[u8]) = FromBytes::from_bytes(rem1)?;
-        Ok((Contract { bytes, known_urefs }, rem2))
+        let (protocol_version, rem3): (u64, &[u8]) = FromBytes::from_bytes(rem2)?;
+        let (abi, rem4): (Option<Abi>, &[u8]) = FromBytes::from_bytes(rem3)?;
+        Ok((
+            Contract {
+                bytes,
+                known_urefs,
+                protocol_version,
+                abi,
+            },
+            rem4,
+        ))
     }
 }
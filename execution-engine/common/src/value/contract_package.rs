@@ -0,0 +1,386 @@
+use crate::bytesrepr::{vec_from_bytes, vec_to_bytes, Error, FromBytes, ToBytes};
+use crate::key::Key;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const SELECTOR_LATEST_TAG: u8 = 0;
+const SELECTOR_EXACT_TAG: u8 = 1;
+const SELECTOR_MAJOR_TAG: u8 = 2;
+
+/// Chooses which version of a package gets called.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum VersionSelector {
+    /// The newest version of the package.
+    Latest,
+    /// The version with the given number.
+    Exact(u32),
+    /// The newest version released under the given major version.
+    Major(u32),
+}
+
+impl ToBytes for VersionSelector {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        match self {
+            VersionSelector::Latest => Ok(vec![SELECTOR_LATEST_TAG]),
+            VersionSelector::Exact(version) => {
+                let mut result = vec![SELECTOR_EXACT_TAG];
+                result.append(&mut version.to_bytes()?);
+                Ok(result)
+            }
+            VersionSelector::Major(major) => {
+                let mut result = vec![SELECTOR_MAJOR_TAG];
+                result.append(&mut major.to_bytes()?);
+                Ok(result)
+            }
+        }
+    }
+}
+
+impl FromBytes for VersionSelector {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (tag, rem): (u8, &[u8]) = FromBytes::from_bytes(bytes)?;
+        match tag {
+            SELECTOR_LATEST_TAG => Ok((VersionSelector::Latest, rem)),
+            SELECTOR_EXACT_TAG => {
+                let (version, rem): (u32, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((VersionSelector::Exact(version), rem))
+            }
+            SELECTOR_MAJOR_TAG => {
+                let (major, rem): (u32, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((VersionSelector::Major(major), rem))
+            }
+            _ => Err(Error::FormattingError),
+        }
+    }
+}
+
+/// A single version of a package: the stored contract together with the
+/// major version it was released under. A version can be disabled, after which
+/// it can't be called anymore.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ContractVersion {
+    major: u32,
+    contract_key: Key,
+    disabled: bool,
+}
+
+impl ContractVersion {
+    pub fn new(major: u32, contract_key: Key) -> Self {
+        ContractVersion {
+            major,
+            contract_key,
+            disabled: false,
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    pub fn contract_key(&self) -> Key {
+        self.contract_key
+    }
+}
+
+impl ToBytes for ContractVersion {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut result = self.major.to_bytes()?;
+        result.append(&mut self.contract_key.to_bytes()?);
+        result.append(&mut (self.disabled as u8).to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for ContractVersion {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (major, rem1): (u32, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (contract_key, rem2): (Key, &[u8]) = FromBytes::from_bytes(rem1)?;
+        let (disabled, rem3): (u8, &[u8]) = FromBytes::from_bytes(rem2)?;
+        let disabled = match disabled {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::FormattingError),
+        };
+        Ok((
+            ContractVersion {
+                major,
+                contract_key,
+                disabled,
+            },
+            rem3,
+        ))
+    }
+}
+
+/// A stable handle to an upgradable contract.
+///
+/// The package lives under its own key and owns an ordered list of contract
+/// versions, each being the key of a stored `Contract` released under some
+/// major version. Calling the package runs its latest version unless the
+/// caller selects another one with a `VersionSelector`. New versions can
+/// only be added by whoever holds the package's access key.
+///
+/// A package may also define user groups, each being a named set of URefs.
+/// An entry point listing some groups in the ABI of the called version can
+/// only be called by callers presenting a URef belonging to one of them.
+///
+/// A package can be locked, after which neither its versions nor its groups
+/// can ever change again.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ContractPackage {
+    access_key: Key,
+    versions: Vec<ContractVersion>,
+    groups: BTreeMap<String, Vec<Key>>,
+    locked: bool,
+}
+
+impl ContractPackage {
+    /// Creates a package without any versions.
+    pub fn new(access_key: Key) -> Self {
+        ContractPackage {
+            access_key,
+            versions: Vec::new(),
+            groups: BTreeMap::new(),
+            locked: false,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Makes the package immutable. There is no way to unlock it.
+    pub fn lock(&mut self) {
+        self.locked = true;
+    }
+
+    pub fn access_key(&self) -> Key {
+        self.access_key
+    }
+
+    /// All the versions, oldest first.
+    pub fn versions(&self) -> &[ContractVersion] {
+        &self.versions
+    }
+
+    /// Key of the contract with the given version, even if the version is
+    /// disabled. Versions are numbered from 1.
+    pub fn get_version(&self, version: u32) -> Option<Key> {
+        if version == 0 {
+            return None;
+        }
+        self.versions
+            .get(version as usize - 1)
+            .map(ContractVersion::contract_key)
+    }
+
+    /// Key of the newest version which isn't disabled.
+    pub fn latest(&self) -> Option<Key> {
+        self.versions
+            .iter()
+            .rev()
+            .find(|version| !version.disabled)
+            .map(ContractVersion::contract_key)
+    }
+
+    /// Key of the newest contract released under the given major version
+    /// which isn't disabled.
+    pub fn latest_of_major(&self, major: u32) -> Option<Key> {
+        self.versions
+            .iter()
+            .rev()
+            .find(|version| version.major == major && !version.disabled)
+            .map(ContractVersion::contract_key)
+    }
+
+    /// Key of the contract picked by `selector`, if there is one. Disabled
+    /// versions are skipped, unless picked with `VersionSelector::Exact`.
+    pub fn resolve(&self, selector: VersionSelector) -> Option<Key> {
+        match selector {
+            VersionSelector::Latest => self.latest(),
+            VersionSelector::Exact(version) => self.get_version(version),
+            VersionSelector::Major(major) => self.latest_of_major(major),
+        }
+    }
+
+    /// Appends `contract_key` as the newest version released under `major`
+    /// and returns its number.
+    pub fn add_version(&mut self, major: u32, contract_key: Key) -> u32 {
+        self.versions.push(ContractVersion::new(major, contract_key));
+        self.versions.len() as u32
+    }
+
+    pub fn is_version_disabled(&self, version: u32) -> bool {
+        version > 0
+            && self
+                .versions
+                .get(version as usize - 1)
+                .map_or(false, ContractVersion::is_disabled)
+    }
+
+    /// Takes the given version out of service. Returns `false` if there is no
+    /// such version.
+    pub fn disable_version(&mut self, version: u32) -> bool {
+        if version == 0 {
+            return false;
+        }
+        match self.versions.get_mut(version as usize - 1) {
+            Some(contract_version) => {
+                contract_version.disabled = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn groups(&self) -> &BTreeMap<String, Vec<Key>> {
+        &self.groups
+    }
+
+    /// Adds `key` to the group `name`, creating the group if necessary.
+    pub fn add_group_key(&mut self, name: String, key: Key) {
+        let keys = self.groups.entry(name).or_insert_with(Vec::new);
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    /// Removes the group `name`. Returns `false` if there was no such group.
+    pub fn remove_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Checks whether a caller presenting `keys` may call an entry point
+    /// restricted to `groups`. An entry point without groups can be called by
+    /// anyone. Group membership is decided by the address of a URef,
+    /// regardless of the access rights it was presented with.
+    pub fn is_callable_with(&self, groups: &[String], keys: &[Key]) -> bool {
+        if groups.is_empty() {
+            return true;
+        }
+        keys.iter().any(|presented| match presented {
+            Key::URef(addr, _) => groups
+                .iter()
+                .filter_map(|name| self.groups.get(name))
+                .flatten()
+                .any(|member| match member {
+                    Key::URef(member_addr, _) => member_addr == addr,
+                    _ => false,
+                }),
+            _ => false,
+        })
+    }
+}
+
+impl ToBytes for ContractPackage {
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut result = self.access_key.to_bytes()?;
+        result.append(&mut vec_to_bytes(&self.versions)?);
+        result.append(&mut self.groups.to_bytes()?);
+        result.append(&mut (self.locked as u8).to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for ContractPackage {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (access_key, rem1): (Key, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (versions, rem2): (Vec<ContractVersion>, &[u8]) = vec_from_bytes(rem1)?;
+        let (groups, rem3): (BTreeMap<String, Vec<Key>>, &[u8]) = FromBytes::from_bytes(rem2)?;
+        let (locked, rem4): (u8, &[u8]) = FromBytes::from_bytes(rem3)?;
+        let locked = match locked {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::FormattingError),
+        };
+        Ok((
+            ContractPackage {
+                access_key,
+                versions,
+                groups,
+                locked,
+            },
+            rem4,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContractPackage, VersionSelector};
+    use crate::key::{AccessRights, Key};
+    use alloc::string::ToString;
+
+    #[test]
+    fn versions_are_numbered_from_one() {
+        let mut package = ContractPackage::new(Key::URef([1u8; 32], AccessRights::READ_ADD_WRITE));
+        assert_eq!(package.latest(), None);
+        assert_eq!(package.add_version(1, Key::Hash([2u8; 32])), 1);
+        assert_eq!(package.add_version(1, Key::Hash([3u8; 32])), 2);
+        assert_eq!(package.get_version(0), None);
+        assert_eq!(package.get_version(1), Some(Key::Hash([2u8; 32])));
+        assert_eq!(package.get_version(3), None);
+        assert_eq!(package.latest(), Some(Key::Hash([3u8; 32])));
+    }
+
+    #[test]
+    fn selectors_resolve_versions() {
+        let mut package = ContractPackage::new(Key::URef([1u8; 32], AccessRights::READ_ADD_WRITE));
+        assert_eq!(package.resolve(VersionSelector::Latest), None);
+        package.add_version(1, Key::Hash([2u8; 32]));
+        package.add_version(1, Key::Hash([3u8; 32]));
+        package.add_version(2, Key::Hash([4u8; 32]));
+        assert_eq!(package.resolve(VersionSelector::Latest), Some(Key::Hash([4u8; 32])));
+        assert_eq!(package.resolve(VersionSelector::Exact(1)), Some(Key::Hash([2u8; 32])));
+        assert_eq!(package.resolve(VersionSelector::Exact(4)), None);
+        assert_eq!(package.resolve(VersionSelector::Major(1)), Some(Key::Hash([3u8; 32])));
+        assert_eq!(package.resolve(VersionSelector::Major(2)), Some(Key::Hash([4u8; 32])));
+        assert_eq!(package.resolve(VersionSelector::Major(3)), None);
+    }
+
+    #[test]
+    fn disabled_versions_are_skipped() {
+        let mut package = ContractPackage::new(Key::URef([1u8; 32], AccessRights::READ_ADD_WRITE));
+        package.add_version(1, Key::Hash([2u8; 32]));
+        package.add_version(1, Key::Hash([3u8; 32]));
+        assert!(!package.disable_version(3));
+        assert!(package.disable_version(2));
+        assert!(package.is_version_disabled(2));
+        assert!(!package.is_version_disabled(1));
+        assert_eq!(package.latest(), Some(Key::Hash([2u8; 32])));
+        assert_eq!(package.resolve(VersionSelector::Major(1)), Some(Key::Hash([2u8; 32])));
+        assert_eq!(package.resolve(VersionSelector::Exact(2)), Some(Key::Hash([3u8; 32])));
+
+        assert!(package.disable_version(1));
+        assert_eq!(package.latest(), None);
+    }
+
+    #[test]
+    fn groups_restrict_callers() {
+        let mut package = ContractPackage::new(Key::URef([1u8; 32], AccessRights::READ_ADD_WRITE));
+        let member = Key::URef([2u8; 32], AccessRights::READ_ADD_WRITE);
+        let stranger = Key::URef([3u8; 32], AccessRights::READ_ADD_WRITE);
+        let minters = vec!["minters".to_string()];
+        let burners = vec!["burners".to_string()];
+        assert!(package.is_callable_with(&[], &[]));
+        assert!(!package.is_callable_with(&minters, &[]));
+
+        package.add_group_key("minters".to_string(), member);
+        package.add_group_key("burners".to_string(), stranger);
+        assert!(package.is_callable_with(&[], &[]));
+        assert!(!package.is_callable_with(&minters, &[]));
+        assert!(!package.is_callable_with(&minters, &[stranger]));
+        assert!(package.is_callable_with(&minters, &[stranger, member]));
+        assert!(package.is_callable_with(&minters, &[Key::URef([2u8; 32], AccessRights::READ)]));
+        assert!(!package.is_callable_with(&burners, &[member]));
+
+        assert!(package.remove_group("minters"));
+        assert!(!package.remove_group("minters"));
+        assert!(!package.is_callable_with(&minters, &[member]));
+    }
+}
@@ -1,5 +1,7 @@
+pub mod abi;
 pub mod account;
 pub mod contract;
+pub mod contract_package;
 pub mod uint;
 
 use crate::bytesrepr::{
@@ -14,6 +16,7 @@ use core::mem::size_of;
 
 pub use self::account::Account;
 pub use self::contract::Contract;
+pub use self::contract_package::ContractPackage;
 pub use self::uint::{U128, U256, U512};
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -29,6 +32,7 @@ pub enum Value {
     NamedKey(String, Key),
     Account(account::Account),
     Contract(contract::Contract),
+    ContractPackage(contract_package::ContractPackage),
 }
 
 const INT32_ID: u8 = 0;
@@ -42,6 +46,7 @@ const LISTSTRING_ID: u8 = 7;
 const U128_ID: u8 = 8;
 const U256_ID: u8 = 9;
 const U512_ID: u8 = 10;
+const CONTRACT_PACKAGE_ID: u8 = 11;
 
 use self::Value::*;
 
@@ -111,6 +116,9 @@ impl ToBytes for Value {
                 Ok(result)
             }
             Contract(c) => Ok(iter::once(CONTRACT_ID).chain(c.to_bytes()?).collect()),
+            ContractPackage(p) => Ok(iter::once(CONTRACT_PACKAGE_ID)
+                .chain(p.to_bytes()?)
+                .collect()),
             NamedKey(n, k) => {
                 if n.len() + UREF_SIZE >= u32::max_value() as usize - U32_SIZE - U8_SIZE {
                     return Err(Error::OutOfMemoryError);
@@ -179,6 +187,11 @@ impl FromBytes for Value {
                 let (c, rem): (contract::Contract, &[u8]) = FromBytes::from_bytes(rest)?;
                 Ok((Contract(c), rem))
             }
+            CONTRACT_PACKAGE_ID => {
+                let (p, rem): (contract_package::ContractPackage, &[u8]) =
+                    FromBytes::from_bytes(rest)?;
+                Ok((ContractPackage(p), rem))
+            }
             NAMEDKEY_ID => {
                 let (name, rem1): (String, &[u8]) = FromBytes::from_bytes(rest)?;
                 let (key, rem2): (Key, &[u8]) = FromBytes::from_bytes(rem1)?;
@@ -205,6 +218,7 @@ impl Value {
             ByteArray(_) => String::from("ByteArray"),
             Account(_) => String::from("Account"),
             Contract(_) => String::from("Contract"),
+            ContractPackage(_) => String::from("ContractPackage"),
             NamedKey(_, _) => String::from("NamedKey"),
             ListString(_) => String::from("List[String]"),
         }
@@ -218,8 +232,33 @@ impl Value {
     }
 }
 
+/// Types which are stored in the global state as one variant of `Value`.
+pub trait StoredType: Into<Value> + TryFrom<Value> {
+    /// Name of the variant, as returned by `Value::type_string`.
+    fn type_string() -> String;
+}
+
+/// A value found in the global state isn't of the type it was expected to be.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct TypeMismatch {
+    pub expected: String,
+    pub found: String,
+}
+
+impl TypeMismatch {
+    pub fn new(expected: String, found: String) -> TypeMismatch {
+        TypeMismatch { expected, found }
+    }
+}
+
 macro_rules! from_try_from_impl {
-    ($type:ty, $variant:ident) => {
+    ($type:ty, $variant:ident, $type_string:expr) => {
+        impl StoredType for $type {
+            fn type_string() -> String {
+                String::from($type_string)
+            }
+        }
+
         impl From<$type> for Value {
             fn from(x: $type) -> Self {
                 Value::$variant(x)
@@ -240,16 +279,17 @@ macro_rules! from_try_from_impl {
     };
 }
 
-from_try_from_impl!(i32, Int32);
-from_try_from_impl!(U128, UInt128);
-from_try_from_impl!(U256, UInt256);
-from_try_from_impl!(U512, UInt512);
-from_try_from_impl!(Vec<u8>, ByteArray);
-from_try_from_impl!(Vec<i32>, ListInt32);
-from_try_from_impl!(Vec<String>, ListString);
-from_try_from_impl!(String, String);
-from_try_from_impl!(account::Account, Account);
-from_try_from_impl!(contract::Contract, Contract);
+from_try_from_impl!(i32, Int32, "Int32");
+from_try_from_impl!(U128, UInt128, "UInt128");
+from_try_from_impl!(U256, UInt256, "UInt256");
+from_try_from_impl!(U512, UInt512, "UInt512");
+from_try_from_impl!(Vec<u8>, ByteArray, "ByteArray");
+from_try_from_impl!(Vec<i32>, ListInt32, "List[Int32]");
+from_try_from_impl!(Vec<String>, ListString, "List[String]");
+from_try_from_impl!(String, String, "String");
+from_try_from_impl!(account::Account, Account, "Account");
+from_try_from_impl!(contract::Contract, Contract, "Contract");
+from_try_from_impl!(contract_package::ContractPackage, ContractPackage, "ContractPackage");
 
 impl From<(String, Key)> for Value {
     fn from(tuple: (String, Key)) -> Self {
@@ -257,6 +297,12 @@ impl From<(String, Key)> for Value {
     }
 }
 
+impl StoredType for (String, Key) {
+    fn type_string() -> String {
+        String::from("NamedKey")
+    }
+}
+
 impl TryFrom<Value> for (String, Key) {
     type Error = ();
 
@@ -268,3 +314,19 @@ impl TryFrom<Value> for (String, Key) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{StoredType, Value, U512};
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn stored_type_names_match_values() {
+        assert_eq!(i32::type_string(), Value::Int32(0).type_string());
+        assert_eq!(U512::type_string(), Value::UInt512(U512::zero()).type_string());
+        assert_eq!(Vec::<i32>::type_string(), Value::ListInt32(Vec::new()).type_string());
+        assert_eq!(Vec::<String>::type_string(), Value::ListString(Vec::new()).type_string());
+        assert_eq!(String::type_string(), Value::String(String::new()).type_string());
+    }
+}
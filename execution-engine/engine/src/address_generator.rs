@@ -0,0 +1,101 @@
+//! Addresses of the URefs, purses and contracts created by deploys.
+//!
+//! Every validator has to create the same addresses when it runs a deploy,
+//! so they are derived from nothing but the deploy: they form a hash chain
+//! seeded by the deploy hash and the phase being run, each address being the
+//! hash of the one before it. Contracts called by the deploy keep drawing
+//! from the chain of their caller. Transfer records are stored under a chain
+//! of their own, so recording transfers doesn't move the other addresses, and
+//! so are the bytes contracts draw with `random_bytes`.
+
+use common::phase::Phase;
+use shared::newtypes::Blake2bHash;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressGenerator {
+    last: [u8; 32],
+}
+
+impl AddressGenerator {
+    pub fn new(deploy_hash: [u8; 32], phase: Phase) -> AddressGenerator {
+        let mut seed = deploy_hash.to_vec();
+        seed.push(phase as u8);
+        AddressGenerator { last: hash(&seed) }
+    }
+
+    /// Chain of the addresses of the transfers made by the deploy.
+    pub fn for_transfers(deploy_hash: [u8; 32], phase: Phase) -> AddressGenerator {
+        let mut seed = deploy_hash.to_vec();
+        seed.push(phase as u8);
+        seed.extend_from_slice(b"transfer");
+        AddressGenerator { last: hash(&seed) }
+    }
+
+    /// Chain of the bytes drawn by the deploy with `random_bytes`.
+    pub fn for_random_bytes(deploy_hash: [u8; 32], phase: Phase) -> AddressGenerator {
+        let mut seed = deploy_hash.to_vec();
+        seed.push(phase as u8);
+        seed.extend_from_slice(b"random");
+        AddressGenerator { last: hash(&seed) }
+    }
+
+    /// Next address of the chain.
+    pub fn create_address(&mut self) -> [u8; 32] {
+        self.last = hash(&self.last);
+        self.last
+    }
+}
+
+fn hash(data: &[u8]) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    result.copy_from_slice(&Blake2bHash::new(data).to_vec());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use common::phase::Phase;
+
+    use super::AddressGenerator;
+
+    const DEPLOY_HASH: [u8; 32] = [1u8; 32];
+
+    fn addresses(deploy_hash: [u8; 32], phase: Phase) -> Vec<[u8; 32]> {
+        let mut generator = AddressGenerator::new(deploy_hash, phase);
+        (0..100).map(|_| generator.create_address()).collect()
+    }
+
+    #[test]
+    fn addresses_are_deterministic() {
+        assert_eq!(
+            addresses(DEPLOY_HASH, Phase::Session),
+            addresses(DEPLOY_HASH, Phase::Session)
+        );
+    }
+
+    #[test]
+    fn addresses_are_unique() {
+        let mut seen = HashSet::new();
+        for (deploy_hash, phase) in &[
+            (DEPLOY_HASH, Phase::Session),
+            (DEPLOY_HASH, Phase::Payment),
+            ([2u8; 32], Phase::Session),
+        ] {
+            for address in addresses(*deploy_hash, *phase) {
+                assert!(seen.insert(address), "Address {:?} was repeated", address);
+            }
+        }
+        for mut generator in vec![
+            AddressGenerator::for_transfers(DEPLOY_HASH, Phase::Session),
+            AddressGenerator::for_random_bytes(DEPLOY_HASH, Phase::Session),
+            AddressGenerator::for_random_bytes(DEPLOY_HASH, Phase::Payment),
+        ] {
+            for _ in 0..100 {
+                let address = generator.create_address();
+                assert!(seen.insert(address), "Address {:?} was repeated", address);
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Approvals of deploys and their verification.
+//!
+//! The node verifies the signatures of the deploys it sends to the engine, so
+//! the engine only verifies them itself when it's configured to, as it should
+//! be when it's driven by tests or tools directly. Every authorization key of
+//! a deploy has to approve it with an Ed25519 signature of its deploy hash.
+//! Both the body hash declared by the deploy and its deploy hash are
+//! recomputed from the deploy first, so a signature can't approve any other
+//! code or header.
+
+use common::value::account::PublicKey;
+use engine::{Deploy, DeployCode};
+use shared::newtypes::Blake2bHash;
+
+/// Signature of a deploy hash by one of the keys of the deploying account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Approval {
+    pub public_key: PublicKey,
+    /// Ed25519 signature, 64 bytes long.
+    pub signature: Vec<u8>,
+}
+
+/// Reasons why the approvals of a deploy aren't accepted.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum ApprovalError {
+    #[fail(display = "Deploy body hash {:?} isn't the hash of its code", _0)]
+    InvalidBodyHash([u8; 32]),
+    #[fail(display = "Deploy hash {:?} isn't the hash of its header", _0)]
+    InvalidDeployHash([u8; 32]),
+    #[fail(display = "Authorization key {:?} didn't approve the deploy", _0)]
+    MissingApproval(PublicKey),
+    #[fail(display = "Approval by {:?} has an invalid signature", _0)]
+    InvalidSignature(PublicKey),
+}
+
+/// Hash of the code and the arguments of both phases of a deploy. Each part
+/// is prefixed by its length, so moving bytes between parts changes the hash.
+pub fn body_hash(session: &DeployCode, payment: &DeployCode) -> [u8; 32] {
+    let mut body = Vec::new();
+    for code in &[session, payment] {
+        for part in &[code.module_bytes, code.args, code.named_args] {
+            body.extend_from_slice(&(part.len() as u64).to_le_bytes());
+            body.extend_from_slice(part);
+        }
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Blake2bHash::new(&body).to_vec());
+    hash
+}
+
+/// Hash of the header of `deploy` carrying `body_hash`: the account address,
+/// the timestamp, the nonce, the gas price, the TTL, the dependencies and the
+/// body hash. This is the hash approvals sign.
+pub fn deploy_hash(deploy: &Deploy, body_hash: &[u8; 32]) -> [u8; 32] {
+    let preconditions = &deploy.preconditions;
+    let mut header = deploy.address.to_vec();
+    for number in &[
+        deploy.timestamp,
+        deploy.nonce,
+        deploy.gas_price,
+        preconditions.ttl_millis,
+        preconditions.dependencies.len() as u64,
+    ] {
+        header.extend_from_slice(&number.to_le_bytes());
+    }
+    for dependency in preconditions.dependencies {
+        header.extend_from_slice(dependency);
+    }
+    header.extend_from_slice(body_hash);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Blake2bHash::new(&header).to_vec());
+    hash
+}
+
+fn is_valid_signature(approval: &Approval, deploy_hash: &[u8; 32]) -> bool {
+    match (
+        ed25519_dalek::PublicKey::from_bytes(&approval.public_key.value()),
+        ed25519_dalek::Signature::from_bytes(&approval.signature),
+    ) {
+        (Ok(public_key), Ok(signature)) => public_key.verify(deploy_hash, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+/// Checks the body hash and the deploy hash of `deploy` and that each of its
+/// authorization keys approved it. Approvals by other keys are verified as
+/// well.
+pub fn verify_approvals(deploy: &Deploy) -> Result<(), ApprovalError> {
+    let body_hash = body_hash(&deploy.session, &deploy.payment);
+    if body_hash != deploy.body_hash {
+        return Err(ApprovalError::InvalidBodyHash(deploy.body_hash));
+    }
+    if deploy_hash(deploy, &body_hash) != deploy.deploy_hash {
+        return Err(ApprovalError::InvalidDeployHash(deploy.deploy_hash));
+    }
+    if let Some(approval) = deploy
+        .approvals
+        .iter()
+        .find(|approval| !is_valid_signature(approval, &deploy.deploy_hash))
+    {
+        return Err(ApprovalError::InvalidSignature(approval.public_key));
+    }
+    match deploy.authorization_keys.iter().find(|key| {
+        !deploy
+            .approvals
+            .iter()
+            .any(|approval| approval.public_key == **key)
+    }) {
+        Some(key) => Err(ApprovalError::MissingApproval(*key)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::value::account::PublicKey;
+    use shared::gas::Gas;
+
+    use super::{body_hash, deploy_hash, verify_approvals, Approval, ApprovalError};
+    use deploy_info::DeployPreconditions;
+    use engine::{Deploy, DeployCode};
+
+    fn approval(secret: [u8; 32], message: &[u8]) -> Approval {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&secret).expect("valid secret key");
+        let public: ed25519_dalek::PublicKey = (&secret).into();
+        let public_key = PublicKey::new(public.to_bytes());
+        let keypair = ed25519_dalek::Keypair { secret, public };
+        Approval {
+            public_key,
+            signature: keypair.sign(message).to_bytes().to_vec(),
+        }
+    }
+
+    // A deploy with consistent hashes. They don't depend on the authorization
+    // keys or the approvals.
+    fn deploy<'a>(authorization_keys: &'a [PublicKey], approvals: &'a [Approval]) -> Deploy<'a> {
+        let session = DeployCode::standard_payment(&[1, 2]);
+        let payment = DeployCode::standard_payment(&[]);
+        let body_hash = body_hash(&session, &payment);
+        let deploy = Deploy {
+            session,
+            payment,
+            address: [1u8; 20],
+            authorization_keys,
+            timestamp: 0,
+            nonce: 1,
+            deploy_hash: [0u8; 32],
+            body_hash,
+            approvals,
+            preconditions: DeployPreconditions::default(),
+            gas_limit: Gas::from(1_000),
+            gas_price: 1,
+        };
+        Deploy {
+            deploy_hash: deploy_hash(&deploy, &body_hash),
+            ..deploy
+        }
+    }
+
+    fn signed_hash() -> [u8; 32] {
+        deploy(&[], &[]).deploy_hash
+    }
+
+    #[test]
+    fn body_hash_covers_every_part_of_the_code() {
+        let code = |args: &'static [u8]| DeployCode::standard_payment(args);
+        let hash = body_hash(&code(&[1, 2]), &code(&[]));
+        assert_eq!(hash, body_hash(&code(&[1, 2]), &code(&[])));
+        assert_ne!(hash, body_hash(&code(&[1]), &code(&[2])));
+        assert_ne!(hash, body_hash(&code(&[]), &code(&[1, 2])));
+    }
+
+    #[test]
+    fn authorization_keys_have_to_approve_the_deploy() {
+        let first = approval([7u8; 32], &signed_hash());
+        let second = approval([8u8; 32], &signed_hash());
+        let keys = [first.public_key, second.public_key];
+        let approvals = [first.clone(), second.clone()];
+        assert_eq!(verify_approvals(&deploy(&keys, &approvals)), Ok(()));
+        assert_eq!(
+            verify_approvals(&deploy(&keys, &approvals[..1])),
+            Err(ApprovalError::MissingApproval(second.public_key))
+        );
+    }
+
+    #[test]
+    fn signatures_of_other_messages_are_rejected() {
+        let forged = approval([7u8; 32], &[4u8; 32]);
+        let truncated = Approval {
+            signature: vec![0u8; 10],
+            ..approval([8u8; 32], &signed_hash())
+        };
+        for approval in &[forged, truncated] {
+            let keys = [approval.public_key];
+            let approvals = [approval.clone()];
+            assert_eq!(
+                verify_approvals(&deploy(&keys, &approvals)),
+                Err(ApprovalError::InvalidSignature(approval.public_key))
+            );
+        }
+    }
+
+    #[test]
+    fn tampered_deploys_are_rejected() {
+        let approval = approval([7u8; 32], &signed_hash());
+        let keys = [approval.public_key];
+        let approvals = [approval];
+        let approved = deploy(&keys, &approvals);
+
+        let session = DeployCode::standard_payment(&[3]);
+        let tampered = Deploy {
+            session,
+            ..approved
+        };
+        assert_eq!(
+            verify_approvals(&tampered),
+            Err(ApprovalError::InvalidBodyHash(approved.body_hash))
+        );
+        // Updating the body hash to match the new body doesn't help, as the
+        // approved deploy hash covers the old one.
+        let tampered = Deploy {
+            body_hash: body_hash(&session, &approved.payment),
+            ..tampered
+        };
+        assert_eq!(
+            verify_approvals(&tampered),
+            Err(ApprovalError::InvalidDeployHash(approved.deploy_hash))
+        );
+        let tampered = Deploy {
+            nonce: 2,
+            ..approved
+        };
+        assert_eq!(
+            verify_approvals(&tampered),
+            Err(ApprovalError::InvalidDeployHash(approved.deploy_hash))
+        );
+    }
+}
@@ -0,0 +1,466 @@
+//! Native implementation of the auction system contract.
+//!
+//! The contract lives under the key registered for `SystemContractId::Auction`.
+//! Accounts bid for a seat in the validator set by moving tokens to the bids
+//! purse of the contract, and can withdraw them as long as they didn't win.
+//! At the end of every era the candidates are ranked by their bids plus their
+//! weights in the PoS contract, so that the tokens delegated to them count as
+//! well, and the best ones win the seats of the era `auction_delay` eras after
+//! the next one. The bids of the winners are bonded as their stakes.
+//!
+//! The validator sets of the current and upcoming eras are kept in the local
+//! storage of the contract, along with the weights they were seated with.
+
+use std::collections::BTreeMap;
+
+use common::auction::{bid_key_name, bidder_from_bid_key_name, AuctionError};
+use common::bytesrepr::{deserialize, ToBytes};
+use common::key::Key;
+use common::value::account::{PublicKey, PurseId};
+use common::value::contract::PROTOCOL_VERSION;
+use common::value::{Contract, Value, U512};
+use execution::Error;
+use mint;
+use pos::{self, local_key, named_key, read_contract, QueueDelays};
+use storage::global_state::StateReader;
+use storage::transform::TypeMismatch;
+use trackingcopy::TrackingCopy;
+
+pub const BIDS_PURSE_KEY: &str = "bids_purse";
+pub const ERA_VALIDATORS_KEY: &str = "era_validators";
+
+/// Number of validators seated in every era unless configured otherwise.
+pub const DEFAULT_VALIDATOR_SLOTS: usize = 100;
+
+/// Number of eras between the next era and the one an auction fills unless
+/// configured otherwise.
+pub const DEFAULT_AUCTION_DELAY: u64 = 1;
+
+/// Validators seated in each era, with their weights, by era id.
+pub type EraValidators = BTreeMap<u64, BTreeMap<PublicKey, U512>>;
+
+/// How the validator sets are picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuctionSettings {
+    /// Size of the validator set of an era.
+    pub validator_slots: usize,
+    /// Number of eras between the one following an auction and the one whose
+    /// validators it picks, so that they're known in advance.
+    pub auction_delay: u64,
+}
+
+impl Default for AuctionSettings {
+    fn default() -> Self {
+        AuctionSettings {
+            validator_slots: DEFAULT_VALIDATOR_SLOTS,
+            auction_delay: DEFAULT_AUCTION_DELAY,
+        }
+    }
+}
+
+/// Stores an auction contract without any bids under `contract_key`, with an
+/// empty bids purse at `bids_purse_addr`.
+pub fn install<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    bids_purse_addr: [u8; 32],
+) -> Result<(), Error> {
+    let bids_purse = mint::create_purse(state, bids_purse_addr);
+    let mut named_keys = BTreeMap::new();
+    named_keys.insert(BIDS_PURSE_KEY.to_string(), bids_purse.into());
+    let era_validators_key = local_key(contract_key, ERA_VALIDATORS_KEY)?;
+    state.write(
+        era_validators_key,
+        Value::ByteArray(EraValidators::new().to_bytes()?),
+    );
+    named_keys.insert(ERA_VALIDATORS_KEY.to_string(), era_validators_key);
+    let contract = Contract::new(Vec::new(), named_keys, PROTOCOL_VERSION);
+    state.write(contract_key, Value::Contract(contract));
+    Ok(())
+}
+
+fn bids_purse(contract: &Contract) -> Result<PurseId, Error> {
+    match named_key(contract, BIDS_PURSE_KEY)? {
+        Key::URef(addr, rights) => Ok(PurseId::new(addr, rights)),
+        _ => Err(Error::URefNotFound(BIDS_PURSE_KEY.to_string())),
+    }
+}
+
+fn bid<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+    bidder: PublicKey,
+) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    let bid_key = match contract.urefs_lookup().get(&bid_key_name(bidder)) {
+        Some(key) => *key,
+        None => return Ok(U512::zero()),
+    };
+    match state.read(bid_key).map_err(Into::into)? {
+        Some(Value::UInt512(bid)) => Ok(bid),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "UInt512".to_string(),
+            other.type_string(),
+        ))),
+        None => Err(Error::KeyNotFound(bid_key)),
+    }
+}
+
+// Bidders whose bid drops to zero are removed from the named keys.
+fn set_bid<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    contract: &mut Contract,
+    bidder: PublicKey,
+    bid: U512,
+) -> Result<(), Error> {
+    let name = bid_key_name(bidder);
+    let bid_key = local_key(contract_key, &name)?;
+    state.write(bid_key, Value::UInt512(bid));
+    if bid.is_zero() {
+        contract.remove_uref(&name);
+    } else {
+        let mut named_keys = BTreeMap::new();
+        named_keys.insert(name, bid_key);
+        contract.insert_urefs(&mut named_keys);
+    }
+    Ok(())
+}
+
+fn read_era_validators<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+) -> Result<EraValidators, Error>
+where
+    R::Error: Into<Error>,
+{
+    let era_validators_key = named_key(contract, ERA_VALIDATORS_KEY)?;
+    match state.read(era_validators_key).map_err(Into::into)? {
+        Some(Value::ByteArray(bytes)) => Ok(deserialize(&bytes)?),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "ByteArray".to_string(),
+            other.type_string(),
+        ))),
+        None => Err(Error::KeyNotFound(era_validators_key)),
+    }
+}
+
+fn write_era_validators<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+    era_validators: &EraValidators,
+) -> Result<(), Error> {
+    let era_validators_key = named_key(contract, ERA_VALIDATORS_KEY)?;
+    state.write(
+        era_validators_key,
+        Value::ByteArray(era_validators.to_bytes()?),
+    );
+    Ok(())
+}
+
+/// Moves `amount` of tokens from `source` to the bids purse and adds them to
+/// the bid of `bidder`.
+pub fn add_bid<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    bidder: PublicKey,
+    source: PurseId,
+    amount: U512,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let mut contract = read_contract(state, contract_key)?;
+    match mint::transfer(state, source, bids_purse(&contract)?, amount) {
+        Ok(()) => (),
+        Err(Error::InsufficientFunds) => {
+            return Err(Error::Auction(AuctionError::InsufficientFunds));
+        }
+        Err(error) => return Err(error),
+    }
+    let bid = bid(state, &contract, bidder)?
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    set_bid(state, contract_key, &mut contract, bidder, bid)?;
+    state.write(contract_key, Value::Contract(contract));
+    Ok(())
+}
+
+/// Takes `amount` of tokens off the bid of `bidder` and pays them out to
+/// `target` right away.
+pub fn withdraw_bid<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    bidder: PublicKey,
+    target: PurseId,
+    amount: U512,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let mut contract = read_contract(state, contract_key)?;
+    let bid = bid(state, &contract, bidder)?;
+    if bid.is_zero() {
+        return Err(Error::Auction(AuctionError::NoBid));
+    }
+    if amount > bid {
+        return Err(Error::Auction(AuctionError::WithdrawTooLarge));
+    }
+    mint::transfer(state, bids_purse(&contract)?, target, amount)?;
+    set_bid(state, contract_key, &mut contract, bidder, bid - amount)?;
+    state.write(contract_key, Value::Contract(contract));
+    Ok(())
+}
+
+/// Bids which haven't won a seat yet, by bidder.
+pub fn bids<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+) -> Result<BTreeMap<PublicKey, U512>, Error>
+where
+    R::Error: Into<Error>,
+{
+    let contract = read_contract(state, contract_key)?;
+    let mut bids = BTreeMap::new();
+    for name in contract.urefs_lookup().keys() {
+        if let Some(bidder) = bidder_from_bid_key_name(name) {
+            bids.insert(bidder, bid(state, &contract, bidder)?);
+        }
+    }
+    Ok(bids)
+}
+
+/// Seats `validators` in the eras which are filled before the first auction:
+/// the first one and the `auction_delay` eras after it.
+pub fn seat_genesis_validators<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    validators: &BTreeMap<PublicKey, U512>,
+    settings: AuctionSettings,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let contract = read_contract(state, contract_key)?;
+    let era_validators = (0..=settings.auction_delay)
+        .map(|era_id| (era_id, validators.clone()))
+        .collect();
+    write_era_validators(state, &contract, &era_validators)
+}
+
+/// Runs the auction at the end of the era `era_id`, at `height`. The
+/// candidates which weren't slashed in the PoS contract at `pos_contract` are
+/// ranked by their bids plus their weights, ties going to the lowest public
+/// key, and the first `validator_slots` of them are seated, their bids being
+/// bonded. Sets of the eras which ended are dropped. Returns the new set.
+#[allow(clippy::too_many_arguments)]
+pub fn run_auction<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    pos_contract: Key,
+    era_id: u64,
+    height: u64,
+    settings: AuctionSettings,
+    delays: QueueDelays,
+) -> Result<BTreeMap<PublicKey, U512>, Error>
+where
+    R::Error: Into<Error>,
+{
+    let bids = bids(state, contract_key)?;
+    let slashed = {
+        let pos = read_contract(state, pos_contract)?;
+        pos::slashed_validators(state, &pos)?
+    };
+    let mut scores = pos::weights(state, pos_contract)?;
+    for (bidder, bid) in &bids {
+        *scores.entry(*bidder).or_insert_with(U512::zero) += *bid;
+    }
+    let mut candidates: Vec<(PublicKey, U512)> = scores
+        .into_iter()
+        .filter(|(candidate, _)| !slashed.contains(candidate))
+        .collect();
+    // The sort is stable, so candidates with equal scores stay in key order.
+    candidates.sort_by(|(_, a), (_, b)| b.cmp(a));
+    candidates.truncate(settings.validator_slots);
+    let winners: BTreeMap<PublicKey, U512> = candidates.into_iter().collect();
+
+    let mut contract = read_contract(state, contract_key)?;
+    let bids_purse = bids_purse(&contract)?;
+    for winner in winners.keys() {
+        if let Some(bid) = bids.get(winner) {
+            pos::bond(state, pos_contract, *winner, bids_purse, *bid, height, delays)?;
+            set_bid(state, contract_key, &mut contract, *winner, U512::zero())?;
+        }
+    }
+    let mut era_validators = read_era_validators(state, &contract)?;
+    era_validators = era_validators.split_off(&(era_id + 1));
+    era_validators.insert(era_id + 1 + settings.auction_delay, winners.clone());
+    write_era_validators(state, &contract, &era_validators)?;
+    state.write(contract_key, Value::Contract(contract));
+    Ok(winners)
+}
+
+/// Validator sets of the current and upcoming eras, by era id.
+pub fn era_validators<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+) -> Result<EraValidators, Error>
+where
+    R::Error: Into<Error>,
+{
+    let contract = read_contract(state, contract_key)?;
+    read_era_validators(state, &contract)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use common::auction::AuctionError;
+    use common::key::Key;
+    use common::value::account::{PublicKey, PurseId};
+    use common::value::{Value, U512};
+    use storage::global_state::inmem::InMemGS;
+
+    use super::{era_validators, install, run_auction, AuctionSettings};
+    use execution::Error;
+    use mint;
+    use pos::{self, QueueDelays};
+    use system::{SystemContext, SystemFlow};
+    use trackingcopy::TrackingCopy;
+
+    const MINT_KEY: Key = Key::Hash([6u8; 32]);
+    const AUCTION_KEY: Key = Key::Hash([8u8; 32]);
+    const POS_KEY: Key = Key::Hash([7u8; 32]);
+
+    type TestTrackingCopy = TrackingCopy<InMemGS<Key, Value>>;
+
+    fn purse(tc: &mut TestTrackingCopy, addr: [u8; 32], balance: u64) -> PurseId {
+        let purse = mint::create_purse(tc, addr);
+        mint::mint(tc, purse, U512::from(balance)).expect("should mint");
+        purse
+    }
+
+    fn key(byte: u8) -> PublicKey {
+        PublicKey::new([byte; 32])
+    }
+
+    /// Auction contract and PoS contract with validator 1 bonded with a stake
+    /// of 100.
+    fn setup() -> TestTrackingCopy {
+        let mut tc = TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        mint::install(&mut tc, MINT_KEY);
+        pos::install(&mut tc, POS_KEY, [2u8; 32]).expect("should install PoS");
+        install(&mut tc, AUCTION_KEY, [3u8; 32]).expect("should install auction");
+        let source = purse(&mut tc, [11u8; 32], 100);
+        let delays = QueueDelays::default();
+        pos::bond(&mut tc, POS_KEY, key(1), source, U512::from(100), 0, delays)
+            .expect("should bond");
+        tc
+    }
+
+    fn add_bid(tc: &mut TestTrackingCopy, bidder: u8, amount: u64) -> PurseId {
+        let source = purse(tc, [20 + bidder; 32], amount);
+        super::add_bid(tc, AUCTION_KEY, key(bidder), source, U512::from(amount))
+            .expect("should bid");
+        source
+    }
+
+    fn auction(tc: &mut TestTrackingCopy, era_id: u64, validator_slots: usize) -> Vec<PublicKey> {
+        let settings = AuctionSettings {
+            validator_slots,
+            auction_delay: 1,
+        };
+        let delays = QueueDelays::default();
+        let winners = run_auction(tc, AUCTION_KEY, POS_KEY, era_id, 0, settings, delays)
+            .expect("should run auction");
+        winners.keys().cloned().collect()
+    }
+
+    #[test]
+    fn bids_can_be_withdrawn() {
+        let mut tc = setup();
+        let purse = add_bid(&mut tc, 2, 50);
+        let withdraw = |tc: &mut TestTrackingCopy, bidder: u8, amount: u64| {
+            super::withdraw_bid(tc, AUCTION_KEY, key(bidder), purse, U512::from(amount))
+        };
+        assert_matches!(
+            withdraw(&mut tc, 2, 51),
+            Err(Error::Auction(AuctionError::WithdrawTooLarge))
+        );
+        assert_matches!(
+            withdraw(&mut tc, 3, 1),
+            Err(Error::Auction(AuctionError::NoBid))
+        );
+        withdraw(&mut tc, 2, 20).expect("should withdraw");
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::from(20));
+        let bids = super::bids(&mut tc, AUCTION_KEY).unwrap();
+        assert_eq!(bids.get(&key(2)), Some(&U512::from(30)));
+        withdraw(&mut tc, 2, 30).expect("should withdraw");
+        assert!(super::bids(&mut tc, AUCTION_KEY).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bids_need_funds() {
+        let mut tc = setup();
+        let source = purse(&mut tc, [4u8; 32], 10);
+        assert_matches!(
+            super::add_bid(&mut tc, AUCTION_KEY, key(2), source, U512::from(11)),
+            Err(Error::Auction(AuctionError::InsufficientFunds))
+        );
+    }
+
+    #[test]
+    fn best_candidates_win_and_get_their_bids_bonded() {
+        let mut tc = setup();
+        add_bid(&mut tc, 2, 150);
+        add_bid(&mut tc, 3, 100);
+        add_bid(&mut tc, 4, 50);
+        // Validator 1 and bidder 3 tie, and 1 has the lower key.
+        assert_eq!(auction(&mut tc, 0, 2), vec![key(1), key(2)]);
+        let stakes = pos::stakes(&mut tc, POS_KEY).unwrap();
+        assert_eq!(stakes.get(&key(2)), Some(&U512::from(150)));
+        let bids = super::bids(&mut tc, AUCTION_KEY).unwrap();
+        assert_eq!(bids.keys().cloned().collect::<Vec<_>>(), vec![key(3), key(4)]);
+        let era_validators = era_validators(&mut tc, AUCTION_KEY).unwrap();
+        assert_eq!(era_validators.keys().cloned().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(era_validators[&2].get(&key(2)), Some(&U512::from(150)));
+    }
+
+    #[test]
+    fn delegations_count_towards_the_score() {
+        let mut tc = setup();
+        add_bid(&mut tc, 2, 150);
+        let source = purse(&mut tc, [5u8; 32], 60);
+        let delays = QueueDelays::default();
+        let amount = U512::from(60);
+        pos::delegate(&mut tc, POS_KEY, key(5), key(1), source, amount, 0, delays)
+            .expect("should delegate");
+        assert_eq!(auction(&mut tc, 0, 1), vec![key(1)]);
+        let era_validators = era_validators(&mut tc, AUCTION_KEY).unwrap();
+        assert_eq!(era_validators[&2].get(&key(1)), Some(&U512::from(160)));
+    }
+
+    #[test]
+    fn slashed_validators_are_not_seated() {
+        let mut tc = setup();
+        add_bid(&mut tc, 2, 10);
+        let context = SystemContext::new(SystemFlow::EraEnd);
+        let mut equivocators = BTreeSet::new();
+        equivocators.insert(key(1));
+        pos::slash(&context, &mut tc, POS_KEY, &equivocators, None).expect("should slash");
+        assert_eq!(auction(&mut tc, 0, 2), vec![key(2)]);
+    }
+
+    #[test]
+    fn sets_of_ended_eras_are_dropped() {
+        let mut tc = setup();
+        for era_id in 0..4 {
+            auction(&mut tc, era_id, 1);
+        }
+        let era_validators = era_validators(&mut tc, AUCTION_KEY).unwrap();
+        assert_eq!(era_validators.keys().cloned().collect::<Vec<_>>(), vec![4, 5]);
+    }
+}
@@ -0,0 +1,293 @@
+//! Records of executed deploys and the preconditions checked before a deploy
+//! is executed.
+//!
+//! Every deploy the engine executes leaves a `DeployInfo` under
+//! `Key::DeployInfo` with its hash, written when the deploy is finalized, so
+//! that clients can get a receipt of its cost and transfers. Deploys can
+//! depend on other deploys, which have to be recorded in the prestate, and
+//! can't be executed twice.
+
+use common::bytesrepr::{self, deserialize, vec_from_bytes, vec_to_bytes, FromBytes, ToBytes};
+use common::key::Key;
+use common::value::account::PurseId;
+use common::value::{Value, U512};
+use execution::Error;
+use shared::gas::Gas;
+use storage::global_state::StateReader;
+use trackingcopy::TrackingCopy;
+use transfer::{account_addr, TransferAddr};
+
+/// Record of an executed deploy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeployInfo {
+    pub deploy_hash: [u8; 32],
+    /// Addresses of the transfer records the deploy wrote, in ascending order.
+    pub transfers: Vec<TransferAddr>,
+    /// Account the deploy was executed under.
+    pub from: [u8; 20],
+    /// Main purse of the account, which paid for the deploy.
+    pub source_purse: PurseId,
+    /// Gas the deploy was charged for.
+    pub gas: Gas,
+    /// Height of the block the deploy was executed in.
+    pub block_height: u64,
+}
+
+impl ToBytes for DeployInfo {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.deploy_hash.to_bytes()?;
+        result.append(&mut vec_to_bytes(&self.transfers)?);
+        result.append(&mut self.from.to_vec().to_bytes()?);
+        result.append(&mut self.source_purse.to_bytes()?);
+        result.append(&mut self.gas.value().to_bytes()?);
+        result.append(&mut self.block_height.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for DeployInfo {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (deploy_hash, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (transfers, rem): (Vec<TransferAddr>, &[u8]) = vec_from_bytes(rem)?;
+        let (from, rem): (Vec<u8>, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (source_purse, rem): (PurseId, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (gas, rem): (U512, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (block_height, rem): (u64, &[u8]) = FromBytes::from_bytes(rem)?;
+        Ok((
+            DeployInfo {
+                deploy_hash,
+                transfers,
+                from: account_addr(&from)?,
+                source_purse,
+                gas: Gas::new(gas),
+                block_height,
+            },
+            rem,
+        ))
+    }
+}
+
+/// Reads the record of the deploy with `deploy_hash`, if it was executed.
+pub fn read_deploy_info<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    deploy_hash: [u8; 32],
+) -> Result<Option<DeployInfo>, Error>
+where
+    R::Error: Into<Error>,
+{
+    match state.read(Key::DeployInfo(deploy_hash)).map_err(Into::into)? {
+        Some(Value::ByteArray(bytes)) => Ok(Some(deserialize(&bytes)?)),
+        Some(_) | None => Ok(None),
+    }
+}
+
+pub fn write_deploy_info<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    deploy_hash: [u8; 32],
+    deploy_info: &DeployInfo,
+) -> Result<(), Error> {
+    state.write(
+        Key::DeployInfo(deploy_hash),
+        Value::ByteArray(deploy_info.to_bytes()?),
+    );
+    Ok(())
+}
+
+/// What a deploy requires to be executed, besides authorization and payment.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeployPreconditions<'a> {
+    /// Milliseconds after the timestamp of the deploy it stays valid for.
+    /// Deploys with no TTL don't expire.
+    pub ttl_millis: u64,
+    /// Hashes of the deploys which have to be executed before this one.
+    pub dependencies: &'a [[u8; 32]],
+    /// Time of the block the deploy is executed in, in milliseconds. The
+    /// timestamp and the TTL of the deploy aren't checked if it's zero.
+    pub block_time: u64,
+}
+
+/// Reasons why a deploy isn't executed.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum PreconditionError {
+    #[fail(display = "Deploy expired at {}, block time is {}", expiry, block_time)]
+    Expired { expiry: u64, block_time: u64 },
+    #[fail(display = "Deploy timestamp {} is after block time {}", timestamp, block_time)]
+    FutureTimestamp { timestamp: u64, block_time: u64 },
+    #[fail(display = "Dependency {:?} hasn't been executed", _0)]
+    MissingDependency([u8; 32]),
+    #[fail(display = "Deploy {:?} has already been executed", _0)]
+    AlreadyExecuted([u8; 32]),
+    #[fail(display = "Deploy of {} bytes exceeds the limit of {} bytes", size, max_size)]
+    DeployTooLarge { size: usize, max_size: usize },
+    /// The deploy could use more gas than its block has left.
+    #[fail(display = "Deploy may use {} gas, block has {} left", max_cost, remaining)]
+    BlockGasLimitExceeded { max_cost: Gas, remaining: Gas },
+}
+
+impl<'a> DeployPreconditions<'a> {
+    /// Checks the preconditions of the deploy with `deploy_hash` and
+    /// `timestamp` against the block time and the records in `state`.
+    pub fn check<R: StateReader<Key, Value>>(
+        &self,
+        state: &mut TrackingCopy<R>,
+        deploy_hash: [u8; 32],
+        timestamp: u64,
+    ) -> Result<Result<(), PreconditionError>, Error>
+    where
+        R::Error: Into<Error>,
+    {
+        if self.block_time != 0 {
+            if timestamp > self.block_time {
+                return Ok(Err(PreconditionError::FutureTimestamp {
+                    timestamp,
+                    block_time: self.block_time,
+                }));
+            }
+            let expiry = timestamp.saturating_add(self.ttl_millis);
+            if self.ttl_millis != 0 && expiry < self.block_time {
+                return Ok(Err(PreconditionError::Expired {
+                    expiry,
+                    block_time: self.block_time,
+                }));
+            }
+        }
+        if read_deploy_info(state, deploy_hash)?.is_some() {
+            return Ok(Err(PreconditionError::AlreadyExecuted(deploy_hash)));
+        }
+        for dependency in self.dependencies {
+            if read_deploy_info(state, *dependency)?.is_none() {
+                return Ok(Err(PreconditionError::MissingDependency(*dependency)));
+            }
+        }
+        Ok(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use common::bytesrepr::{deserialize, vec_to_bytes, ToBytes};
+    use common::key::{AccessRights, Key};
+    use common::value::account::PurseId;
+    use common::value::Value;
+    use shared::gas::Gas;
+    use storage::global_state::inmem::InMemGS;
+
+    use super::{
+        read_deploy_info, write_deploy_info, DeployInfo, DeployPreconditions, PreconditionError,
+    };
+    use trackingcopy::TrackingCopy;
+
+    const DEPLOY_HASH: [u8; 32] = [1u8; 32];
+    const DEPENDENCY: [u8; 32] = [2u8; 32];
+
+    fn deploy_info(deploy_hash: [u8; 32], transfers: Vec<[u8; 32]>) -> DeployInfo {
+        DeployInfo {
+            deploy_hash,
+            transfers,
+            from: [3u8; 20],
+            source_purse: PurseId::new([4u8; 32], AccessRights::READ_ADD_WRITE),
+            gas: Gas::from(1_234),
+            block_height: 7,
+        }
+    }
+
+    fn check(
+        preconditions: DeployPreconditions,
+        executed: &[[u8; 32]],
+    ) -> Result<(), PreconditionError> {
+        let mut tc: TrackingCopy<InMemGS<Key, Value>> =
+            TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        for deploy_hash in executed {
+            write_deploy_info(&mut tc, *deploy_hash, &deploy_info(*deploy_hash, vec![])).unwrap();
+        }
+        preconditions
+            .check(&mut tc, DEPLOY_HASH, 1_000)
+            .expect("Preconditions should be checked.")
+    }
+
+    #[test]
+    fn deploy_info_roundtrips() {
+        for transfers in vec![vec![], vec![[5u8; 32], [6u8; 32]]] {
+            let deploy_info = deploy_info(DEPLOY_HASH, transfers);
+            let bytes = deploy_info.to_bytes().unwrap();
+            assert_eq!(deserialize::<DeployInfo>(&bytes), Ok(deploy_info));
+        }
+    }
+
+    #[test]
+    fn deploy_info_with_a_malformed_account_is_rejected() {
+        let deploy_info = deploy_info(DEPLOY_HASH, vec![]);
+        let mut bytes = deploy_info.deploy_hash.to_bytes().unwrap();
+        bytes.append(&mut vec_to_bytes(&deploy_info.transfers).unwrap());
+        // Account addresses are 20 bytes long.
+        bytes.append(&mut vec![3u8; 19].to_bytes().unwrap());
+        bytes.append(&mut deploy_info.source_purse.to_bytes().unwrap());
+        bytes.append(&mut deploy_info.gas.value().to_bytes().unwrap());
+        bytes.append(&mut deploy_info.block_height.to_bytes().unwrap());
+        assert!(deserialize::<DeployInfo>(&bytes).is_err());
+    }
+
+    #[test]
+    fn deploy_info_is_read_back_under_its_hash() {
+        let mut tc: TrackingCopy<InMemGS<Key, Value>> =
+            TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        let deploy_info = deploy_info(DEPLOY_HASH, vec![[5u8; 32]]);
+        write_deploy_info(&mut tc, DEPLOY_HASH, &deploy_info).unwrap();
+        assert_eq!(read_deploy_info(&mut tc, DEPLOY_HASH).unwrap(), Some(deploy_info));
+        assert_eq!(read_deploy_info(&mut tc, DEPENDENCY).unwrap(), None);
+        // Values which aren't records don't count as executed deploys.
+        tc.write(Key::DeployInfo(DEPENDENCY), Value::Int32(1));
+        assert_eq!(read_deploy_info(&mut tc, DEPENDENCY).unwrap(), None);
+    }
+
+    #[test]
+    fn timestamps_are_checked_against_block_time() {
+        let preconditions = |ttl_millis, block_time| DeployPreconditions {
+            ttl_millis,
+            dependencies: &[],
+            block_time,
+        };
+        assert_eq!(check(preconditions(100, 1_100), &[]), Ok(()));
+        assert_eq!(
+            check(preconditions(100, 1_101), &[]),
+            Err(PreconditionError::Expired {
+                expiry: 1_100,
+                block_time: 1_101
+            })
+        );
+        assert_eq!(
+            check(preconditions(100, 999), &[]),
+            Err(PreconditionError::FutureTimestamp {
+                timestamp: 1_000,
+                block_time: 999
+            })
+        );
+        // Neither a missing TTL nor a missing block time expire deploys.
+        assert_eq!(check(preconditions(0, 5_000), &[]), Ok(()));
+        assert_eq!(check(preconditions(100, 0), &[]), Ok(()));
+    }
+
+    #[test]
+    fn dependencies_have_to_be_executed_first() {
+        let dependencies = [DEPENDENCY];
+        let preconditions = DeployPreconditions {
+            dependencies: &dependencies,
+            ..Default::default()
+        };
+        assert_eq!(
+            check(preconditions, &[]),
+            Err(PreconditionError::MissingDependency(DEPENDENCY))
+        );
+        assert_eq!(check(preconditions, &[DEPENDENCY]), Ok(()));
+    }
+
+    #[test]
+    fn deploys_are_executed_once() {
+        assert_eq!(
+            check(DeployPreconditions::default(), &[DEPLOY_HASH]),
+            Err(PreconditionError::AlreadyExecuted(DEPLOY_HASH))
+        );
+    }
+}
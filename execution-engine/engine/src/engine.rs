@@ -1,48 +1,559 @@
+use approval::{self, Approval, ApprovalError};
+use auction::{self, AuctionSettings, EraValidators};
+use common::bytesrepr;
 use common::key::Key;
-use execution::{self, Executor};
+use common::phase::Phase;
+use common::system_contracts::SystemContractId;
+use common::trie_merkle_proof::TrieMerkleProof;
+use common::value::account::{KeyLimits, PublicKey, PurseId};
+use common::value::{Account, Value, U512};
+use deploy_info::{
+    read_deploy_info, write_deploy_info, DeployInfo, DeployPreconditions, PreconditionError,
+};
+use error_code::ErrorCode;
+use execution::{self, account_address, Executor};
 use failure::Fail;
-use parking_lot::Mutex;
+use genesis::{GenesisConfig, GenesisResult};
+use journal::{DeployJournal, Replay};
+use mint;
+use module_cache::{ModuleCache, DEFAULT_MODULE_CACHE_CAPACITY};
+use pos::{self, QueueDelays};
+use rent;
+use scheduler::{self, BlockState, ReadSet, RecordingReader};
+use standard_payment::{self, STANDARD_PAYMENT_COST};
+use step::{EraEndData, StepError, StepResult};
+use parity_wasm::elements::Module;
+use parking_lot::RwLock;
+use shared::gas::Gas;
+use shared::motes::Motes;
 use shared::newtypes::Blake2bHash;
-use std::collections::HashMap;
-use storage::global_state::ExecutionEffect;
+use shared::ratio::Ratio;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use storage::global_state::in_memory::InMemoryGlobalState;
+use storage::global_state::{ExecutionEffect, StateReader};
 use storage::history::*;
-use storage::transform::Transform;
-use trackingcopy::TrackingCopy;
+use storage::transform::{Transform, TypeMismatch};
+use system::{SystemContext, SystemFlow, SYSTEM_ACCOUNT};
+use trackingcopy::{QueryResult, TrackingCopy};
+use transfer::{self, Transfer, TransferAddr};
+use upgrade::{self, ProtocolData, UpgradeConfig, UpgradeError, UpgradeResult};
+use vm::host_function_costs::HostFunctionCosts;
+use vm::storage_costs::StorageCosts;
 use vm::wasm_costs::WasmCosts;
 use wasm_prep::Preprocessor;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct RootNotFound(pub Blake2bHash);
 
+/// Depth of nested contract calls allowed unless configured otherwise.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 32;
+
+/// Gas the payment code of a deploy can use unless configured otherwise.
+pub const DEFAULT_MAX_PAYMENT_COST: u64 = 10_000_000;
+
+/// Largest deploy accepted unless configured otherwise, in bytes of code and
+/// arguments.
+pub const DEFAULT_MAX_DEPLOY_SIZE: usize = 1024 * 1024;
+
+/// Gas the deploys of a block can use together unless configured otherwise.
+pub const DEFAULT_BLOCK_GAS_LIMIT: u64 = 10_000_000_000;
+
+/// Number of motes a unit of gas costs for deploys which don't set a gas price.
+pub const CONV_RATE: u64 = 1;
+
+/// Share of the unused payment of a deploy which is refunded to the deploying
+/// account. The rest of it goes to the rewards purse along with the fee, or is
+/// burned with it if no rewards purse is configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefundRatio {
+    numerator: u64,
+    denominator: u64,
+}
+
+impl RefundRatio {
+    /// Ratio of `numerator` to `denominator`, unless it's above one or the
+    /// denominator is zero.
+    pub fn new(numerator: u64, denominator: u64) -> Option<RefundRatio> {
+        if denominator == 0 || numerator > denominator {
+            return None;
+        }
+        Some(RefundRatio {
+            numerator,
+            denominator,
+        })
+    }
+
+    /// Part of `motes` which is refunded, rounded down.
+    pub fn refund(&self, motes: Motes) -> Motes {
+        Motes::new(motes.value() * U512::from(self.numerator) / U512::from(self.denominator))
+    }
+}
+
+/// Everything unused is refunded.
+impl Default for RefundRatio {
+    fn default() -> Self {
+        RefundRatio {
+            numerator: 1,
+            denominator: 1,
+        }
+    }
+}
+
+/// Settings of the engine which are picked when it's started.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    contract_debug: bool,
+    system_contracts: BTreeMap<SystemContractId, Key>,
+    max_call_depth: usize,
+    wasm_costs: WasmCosts,
+    host_function_costs: HostFunctionCosts,
+    storage_costs: StorageCosts,
+    storage_credits: bool,
+    use_payment_code: bool,
+    verify_approvals: bool,
+    max_payment_cost: u64,
+    max_deploy_size: usize,
+    block_gas_limit: u64,
+    rewards_purse: Option<PurseId>,
+    refund_ratio: RefundRatio,
+    pos_delays: QueueDelays,
+    round_seigniorage_rate: Ratio,
+    auction_settings: AuctionSettings,
+    execution_threads: usize,
+    module_cache_capacity: usize,
+    journaling: bool,
+    key_limits: KeyLimits,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            contract_debug: false,
+            system_contracts: BTreeMap::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            wasm_costs: WasmCosts::default(),
+            host_function_costs: HostFunctionCosts::default(),
+            storage_costs: StorageCosts::default(),
+            storage_credits: false,
+            use_payment_code: false,
+            verify_approvals: false,
+            max_payment_cost: DEFAULT_MAX_PAYMENT_COST,
+            max_deploy_size: DEFAULT_MAX_DEPLOY_SIZE,
+            block_gas_limit: DEFAULT_BLOCK_GAS_LIMIT,
+            rewards_purse: None,
+            refund_ratio: RefundRatio::default(),
+            pos_delays: QueueDelays::default(),
+            round_seigniorage_rate: Ratio::default(),
+            auction_settings: AuctionSettings::default(),
+            execution_threads: 1,
+            module_cache_capacity: DEFAULT_MODULE_CACHE_CAPACITY,
+            journaling: false,
+            key_limits: KeyLimits::default(),
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn new() -> EngineConfig {
+        Default::default()
+    }
+
+    /// Makes the `print` host function log the messages of contracts. Meant for
+    /// test environments, it doesn't change the results of deploys.
+    pub fn with_contract_debug(mut self, contract_debug: bool) -> EngineConfig {
+        self.contract_debug = contract_debug;
+        self
+    }
+
+    pub fn contract_debug(&self) -> bool {
+        self.contract_debug
+    }
+
+    /// Registers the key the system contract `id` is stored under, so that
+    /// contracts can find it with `get_system_contract`.
+    pub fn with_system_contract(mut self, id: SystemContractId, key: Key) -> EngineConfig {
+        self.system_contracts.insert(id, key);
+        self
+    }
+
+    pub fn system_contracts(&self) -> &BTreeMap<SystemContractId, Key> {
+        &self.system_contracts
+    }
+
+    /// Limits how deeply contract calls can be nested. Session code runs at
+    /// depth 0 and every call made from it adds one.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> EngineConfig {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    /// Sets the gas costs of Wasm instructions, which are metered by the code
+    /// injected into every deploy when it's preprocessed. States recording
+    /// protocol data use the costs found there instead.
+    pub fn with_wasm_costs(mut self, wasm_costs: WasmCosts) -> EngineConfig {
+        self.wasm_costs = wasm_costs;
+        self
+    }
+
+    pub fn wasm_costs(&self) -> &WasmCosts {
+        &self.wasm_costs
+    }
+
+    /// Sets the gas charged for calls to host functions, on top of the Wasm
+    /// instructions making them. Like the Wasm costs, they're overridden by
+    /// the ones recorded in the protocol data of the state.
+    pub fn with_host_function_costs(
+        mut self,
+        host_function_costs: HostFunctionCosts,
+    ) -> EngineConfig {
+        self.host_function_costs = host_function_costs;
+        self
+    }
+
+    pub fn host_function_costs(&self) -> &HostFunctionCosts {
+        &self.host_function_costs
+    }
+
+    /// Sets the gas charged for every byte deploys add to the state, which is
+    /// overridden by the storage costs recorded in the protocol data.
+    pub fn with_storage_costs(mut self, storage_costs: StorageCosts) -> EngineConfig {
+        self.storage_costs = storage_costs;
+        self
+    }
+
+    pub fn storage_costs(&self) -> StorageCosts {
+        self.storage_costs
+    }
+
+    /// Records the storage credit of the values deploys grow, which pruning
+    /// can expire them by.
+    pub fn with_storage_credits(mut self, storage_credits: bool) -> EngineConfig {
+        self.storage_credits = storage_credits;
+        self
+    }
+
+    pub fn storage_credits(&self) -> bool {
+        self.storage_credits
+    }
+
+    /// Limits the height of the Wasm stack a deploy can use, as measured by
+    /// the instrumentation injected when it's preprocessed. Code going
+    /// deeper traps instead of overflowing the stack of the engine.
+    pub fn with_max_stack_height(mut self, max_stack_height: u32) -> EngineConfig {
+        self.wasm_costs.max_stack_height = max_stack_height;
+        self
+    }
+
+    /// Limits the memory of deploys, in 64kb pages: the size they can start
+    /// with and the size they can grow it to. Modules exceeding either are
+    /// rejected when they're preprocessed.
+    pub fn with_memory_limits(
+        mut self,
+        max_initial_pages: u32,
+        max_grow_pages: u32,
+    ) -> EngineConfig {
+        self.wasm_costs.max_initial_pages = max_initial_pages;
+        self.wasm_costs.max_grow_pages = max_grow_pages;
+        self
+    }
+
+    /// Runs deploys in two phases: their payment code buys the gas for their
+    /// session code, and a share of what they don't use is refunded. Otherwise
+    /// only the session code is run, on the gas limit of the deploy, and
+    /// nothing is charged for it.
+    pub fn with_use_payment_code(mut self, use_payment_code: bool) -> EngineConfig {
+        self.use_payment_code = use_payment_code;
+        self
+    }
+
+    pub fn use_payment_code(&self) -> bool {
+        self.use_payment_code
+    }
+
+    /// Verifies the body hash and the approvals of deploys before running
+    /// them. The node verifies them already, so only engines driven directly
+    /// need this.
+    pub fn with_verify_approvals(mut self, verify_approvals: bool) -> EngineConfig {
+        self.verify_approvals = verify_approvals;
+        self
+    }
+
+    pub fn verify_approvals(&self) -> bool {
+        self.verify_approvals
+    }
+
+    /// Limits the gas the payment code of a deploy can use.
+    pub fn with_max_payment_cost(mut self, max_payment_cost: u64) -> EngineConfig {
+        self.max_payment_cost = max_payment_cost;
+        self
+    }
+
+    pub fn max_payment_cost(&self) -> u64 {
+        self.max_payment_cost
+    }
+
+    /// Rejects deploys whose code and arguments take more than
+    /// `max_deploy_size` bytes, before anything of them is run.
+    pub fn with_max_deploy_size(mut self, max_deploy_size: usize) -> EngineConfig {
+        self.max_deploy_size = max_deploy_size;
+        self
+    }
+
+    pub fn max_deploy_size(&self) -> usize {
+        self.max_deploy_size
+    }
+
+    /// Limits the gas the deploys of a block can use together. Deploys which
+    /// could use more than their block has left aren't run.
+    pub fn with_block_gas_limit(mut self, block_gas_limit: u64) -> EngineConfig {
+        self.block_gas_limit = block_gas_limit;
+        self
+    }
+
+    pub fn block_gas_limit(&self) -> u64 {
+        self.block_gas_limit
+    }
+
+    /// Sets the purse the fees of deploys are transferred to. Without one the
+    /// fees are burned.
+    pub fn with_rewards_purse(mut self, rewards_purse: PurseId) -> EngineConfig {
+        self.rewards_purse = Some(rewards_purse);
+        self
+    }
+
+    pub fn rewards_purse(&self) -> Option<PurseId> {
+        self.rewards_purse
+    }
+
+    /// Sets the share of their unused payment deploys get back.
+    pub fn with_refund_ratio(mut self, refund_ratio: RefundRatio) -> EngineConfig {
+        self.refund_ratio = refund_ratio;
+        self
+    }
+
+    pub fn refund_ratio(&self) -> RefundRatio {
+        self.refund_ratio
+    }
+
+    /// Sets the number of blocks bonding requests wait before the tokens are
+    /// added to the stake of the validator.
+    pub fn with_bonding_delay(mut self, bonding_delay: u64) -> EngineConfig {
+        self.pos_delays.bonding = bonding_delay;
+        self
+    }
+
+    /// Sets the number of blocks unbonding requests wait before the tokens are
+    /// paid out to the validator.
+    pub fn with_unbonding_delay(mut self, unbonding_delay: u64) -> EngineConfig {
+        self.pos_delays.unbonding = unbonding_delay;
+        self
+    }
+
+    pub fn pos_delays(&self) -> QueueDelays {
+        self.pos_delays
+    }
+
+    /// Sets the share of the total supply minted as rewards for every round
+    /// of an era, compounded over the rounds. Nothing is minted by default.
+    pub fn with_round_seigniorage_rate(mut self, round_seigniorage_rate: Ratio) -> EngineConfig {
+        self.round_seigniorage_rate = round_seigniorage_rate;
+        self
+    }
+
+    pub fn round_seigniorage_rate(&self) -> Ratio {
+        self.round_seigniorage_rate
+    }
+
+    /// Sets the number of validators the auction seats in every era.
+    pub fn with_validator_slots(mut self, validator_slots: usize) -> EngineConfig {
+        self.auction_settings.validator_slots = validator_slots;
+        self
+    }
+
+    /// Sets the number of eras between the one following an auction and the
+    /// one whose validators it picks.
+    pub fn with_auction_delay(mut self, auction_delay: u64) -> EngineConfig {
+        self.auction_settings.auction_delay = auction_delay;
+        self
+    }
+
+    pub fn auction_settings(&self) -> AuctionSettings {
+        self.auction_settings
+    }
+
+    /// Sets the number of threads the deploys of a block are run on. Whatever
+    /// the number, the results are the ones of running them in order.
+    pub fn with_execution_threads(mut self, execution_threads: usize) -> EngineConfig {
+        self.execution_threads = execution_threads;
+        self
+    }
+
+    pub fn execution_threads(&self) -> usize {
+        self.execution_threads
+    }
+
+    /// Sets the number of preprocessed modules the engine keeps, so that
+    /// deploys sending the same code again don't have it preprocessed again.
+    pub fn with_module_cache_capacity(mut self, module_cache_capacity: usize) -> EngineConfig {
+        self.module_cache_capacity = module_cache_capacity;
+        self
+    }
+
+    pub fn module_cache_capacity(&self) -> usize {
+        self.module_cache_capacity
+    }
+
+    /// Journals the execution of every deploy, at the cost of keeping every
+    /// value deploys load and write in memory until they're done.
+    pub fn with_journaling(mut self, journaling: bool) -> EngineConfig {
+        self.journaling = journaling;
+        self
+    }
+
+    pub fn journaling(&self) -> bool {
+        self.journaling
+    }
+
+    /// Sets how many keys accounts can associate with themselves, and with
+    /// which weights.
+    pub fn with_key_limits(mut self, key_limits: KeyLimits) -> EngineConfig {
+        self.key_limits = key_limits;
+        self
+    }
+
+    pub fn key_limits(&self) -> KeyLimits {
+        self.key_limits
+    }
+}
+
+/// Wasm code of one of the phases of a deploy with the arguments it's called with.
+/// Payment code without module bytes stands for the standard payment.
+#[derive(Debug, Clone, Copy)]
+pub struct DeployCode<'a> {
+    pub module_bytes: &'a [u8],
+    pub args: &'a [u8],
+    pub named_args: &'a [u8],
+}
+
+impl<'a> DeployCode<'a> {
+    /// Payment code which runs the standard payment with `args`.
+    pub fn standard_payment(args: &'a [u8]) -> DeployCode<'a> {
+        DeployCode {
+            module_bytes: &[],
+            args,
+            named_args: &[],
+        }
+    }
+
+    pub fn is_standard_payment(&self) -> bool {
+        self.module_bytes.is_empty()
+    }
+
+    /// Bytes taken by the code and its arguments.
+    pub fn size(&self) -> usize {
+        self.module_bytes.len() + self.args.len() + self.named_args.len()
+    }
+}
+
+/// Deploy of a block with everything it's run with besides the state.
+#[derive(Debug, Clone, Copy)]
+pub struct Deploy<'a> {
+    pub session: DeployCode<'a>,
+    pub payment: DeployCode<'a>,
+    pub address: [u8; 20],
+    pub authorization_keys: &'a [PublicKey],
+    pub timestamp: u64,
+    pub nonce: u64,
+    pub deploy_hash: [u8; 32],
+    /// Hash of the code and the arguments, see `approval::body_hash`.
+    pub body_hash: [u8; 32],
+    /// Signatures of the deploy hash, only checked if the config asks for it.
+    pub approvals: &'a [Approval],
+    pub preconditions: DeployPreconditions<'a>,
+    pub gas_limit: Gas,
+    /// Motes paid for a unit of gas. Zero stands for `CONV_RATE`.
+    pub gas_price: u64,
+}
+
+impl<'a> Deploy<'a> {
+    /// Bytes taken by the code and the arguments of both phases.
+    pub fn size(&self) -> usize {
+        self.session.size() + self.payment.size()
+    }
+}
+
 pub struct EngineState<H>
 where
     H: History,
 {
     // Tracks the "state" of the blockchain (or is an interface to it).
     // I think it should be constrained with a lifetime parameter.
-    state: Mutex<H>,
-    wasm_costs: WasmCosts,
+    // Checkouts and commits share the lock, so queries don't wait for
+    // commits; only checkpoints and pruning take it alone.
+    state: RwLock<H>,
+    config: EngineConfig,
+    module_cache: ModuleCache<Module>,
 }
 
 pub struct ExecutionResult {
     pub result: Result<ExecutionEffect, Error>,
-    pub cost: u64,
+    pub cost: Gas,
+    /// Part of `cost` used by the payment code.
+    pub payment_cost: Gas,
+    /// Effects of charging the deploying account for a deploy whose session
+    /// code failed. They have to be committed even though `result` is an error.
+    pub fee_effect: Option<ExecutionEffect>,
+    /// Addresses of the transfer records written by the deploy, which are
+    /// committed with its effects.
+    pub transfers: Vec<TransferAddr>,
+    /// Journal of the execution, if the engine journals deploys.
+    pub journal: Option<DeployJournal>,
 }
 
 impl ExecutionResult {
-    pub fn failure(error: Error, cost: u64) -> ExecutionResult {
+    pub fn failure(error: Error, cost: Gas) -> ExecutionResult {
         ExecutionResult {
             result: Err(error),
             cost,
+            payment_cost: Gas::default(),
+            fee_effect: None,
+            transfers: Vec::new(),
+            journal: None,
         }
     }
 
-    pub fn success(effect: ExecutionEffect, cost: u64) -> ExecutionResult {
+    pub fn success(effect: ExecutionEffect, cost: Gas) -> ExecutionResult {
         ExecutionResult {
+            transfers: transfer::transfer_addrs(&effect),
             result: Ok(effect),
             cost,
+            payment_cost: Gas::default(),
+            fee_effect: None,
+            journal: None,
         }
     }
+
+    pub fn with_payment_cost(mut self, payment_cost: Gas) -> ExecutionResult {
+        self.payment_cost = payment_cost;
+        self
+    }
+
+    pub fn with_fee_effect(mut self, fee_effect: ExecutionEffect) -> ExecutionResult {
+        self.transfers = transfer::transfer_addrs(&fee_effect);
+        self.fee_effect = Some(fee_effect);
+        self
+    }
+
+    pub fn with_journal(mut self, journal: DeployJournal) -> ExecutionResult {
+        self.journal = Some(journal);
+        self
+    }
 }
 
 #[derive(Fail, Debug)]
@@ -53,10 +564,54 @@ pub enum Error {
     ExecError(::execution::Error),
     #[fail(display = "Storage error")]
     StorageError(storage::error::Error),
+    #[fail(display = "Authorization key set is empty")]
+    EmptyAuthorizationKeys,
+    #[fail(display = "Account {:?} not found", _0)]
+    AccountNotFound(Key),
+    #[fail(display = "Key {:?} is not associated with the account", _0)]
+    UnknownAuthorizationKey(PublicKey),
+    #[fail(display = "Payment code failed: {}", _0)]
+    PaymentError(Box<Error>),
+    #[fail(display = "Payment doesn't cover the gas used by the payment code")]
+    InsufficientPayment,
+    #[fail(display = "Genesis failed: {}", _0)]
+    GenesisError(String),
+    #[fail(display = "Upgrade failed: {:?}", _0)]
+    UpgradeError(UpgradeError),
+    #[fail(display = "Step failed: {:?}", _0)]
+    StepError(StepError),
+    #[fail(display = "{}", _0)]
+    Precondition(PreconditionError),
+    #[fail(display = "{}", _0)]
+    Approval(ApprovalError),
     #[fail(display = "Unreachable")]
     Unreachable,
 }
 
+impl Error {
+    /// Stable code of the kind of the error, which is what clients are told
+    /// besides the message.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::PreprocessingError(_) => ErrorCode::Preprocessing,
+            Error::ExecError(error) => error.code(),
+            Error::StorageError(_) => ErrorCode::Storage,
+            Error::EmptyAuthorizationKeys
+            | Error::AccountNotFound(_)
+            | Error::UnknownAuthorizationKey(_) => ErrorCode::Authorization,
+            Error::PaymentError(_) | Error::InsufficientPayment => ErrorCode::Payment,
+            Error::Precondition(_) => ErrorCode::Precondition,
+            Error::Approval(ApprovalError::InvalidBodyHash(_))
+            | Error::Approval(ApprovalError::InvalidDeployHash(_)) => ErrorCode::InvalidDeployHash,
+            Error::Approval(_) => ErrorCode::InvalidSignature,
+            Error::GenesisError(_)
+            | Error::UpgradeError(_)
+            | Error::StepError(_)
+            | Error::Unreachable => ErrorCode::Internal,
+        }
+    }
+}
+
 impl From<wasm_prep::PreprocessingError> for Error {
     fn from(error: wasm_prep::PreprocessingError) -> Self {
         match error {
@@ -79,6 +634,31 @@ impl From<wasm_prep::PreprocessingError> for Error {
                 Error::PreprocessingError(String::from("Wasm contract error: Stack limiter error."))
 
             }
+            wasm_prep::PreprocessingError::ModuleTooLarge(size) => {
+                Error::PreprocessingError(format!(
+                    "Wasm module is {} bytes long, the limit is {} bytes.",
+                    size,
+                    wasm_prep::MAX_MODULE_SIZE
+                ))
+            }
+            wasm_prep::PreprocessingError::StartSectionPresent => {
+                Error::PreprocessingError(String::from("Wasm modules can't have a start function."))
+            }
+            wasm_prep::PreprocessingError::FloatingPoint => Error::PreprocessingError(
+                String::from("Wasm modules can't use floating-point numbers."),
+            ),
+            wasm_prep::PreprocessingError::InitialMemoryTooLarge { pages, limit } => {
+                Error::PreprocessingError(format!(
+                    "Wasm module starts with {} pages of memory, the limit is {} pages.",
+                    pages, limit
+                ))
+            }
+            wasm_prep::PreprocessingError::MaximumMemoryTooLarge { pages, limit } => {
+                Error::PreprocessingError(format!(
+                    "Wasm module can grow its memory to {} pages, the limit is {} pages.",
+                    pages, limit
+                ))
+            }
         }
     }
 }
@@ -101,59 +681,925 @@ impl From<!> for Error {
     }
 }
 
+/// Main purse of the account at `address`.
+fn main_purse<R: StateReader<Key, Value>>(
+    tc: &mut TrackingCopy<R>,
+    address: [u8; 20],
+) -> Result<PurseId, execution::Error>
+where
+    R::Error: Into<execution::Error>,
+{
+    let account_key = Key::Account(address);
+    match tc.read(account_key).map_err(Into::into)? {
+        Some(Value::Account(account)) => Ok(account.purse_id()),
+        Some(other) => Err(execution::Error::TypeMismatch(TypeMismatch::new(
+            "Account".to_string(),
+            other.type_string(),
+        ))),
+        None => Err(execution::Error::KeyNotFound(account_key)),
+    }
+}
+
 impl<H> EngineState<H>
 where
     H: History,
     H::Error: Into<execution::Error>,
 {
     pub fn new(state: H) -> EngineState<H> {
+        EngineState::with_config(state, EngineConfig::new())
+    }
+
+    pub fn with_config(state: H, config: EngineConfig) -> EngineState<H> {
         EngineState {
-            state: Mutex::new(state),
-            wasm_costs: WasmCosts::new(),
+            state: RwLock::new(state),
+            module_cache: ModuleCache::new(config.module_cache_capacity()),
+            config,
         }
     }
 
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Modules preprocessed for the deploys run by the engine. Deploys are
+    /// only looked up there if they're run with a `CachingPreprocessor`
+    /// using it.
+    pub fn module_cache(&self) -> &ModuleCache<Module> {
+        &self.module_cache
+    }
+
     pub fn tracking_copy(
         &self,
         hash: Blake2bHash,
     ) -> Result<Option<TrackingCopy<H::Reader>>, Error> {
-        match self.state.lock().checkout(hash).map_err(Into::into)? {
+        match self.state.read().checkout(hash).map_err(Into::into)? {
             Some(tc) => Ok(Some(TrackingCopy::new(tc))),
             None => Ok(None),
         }
     }
 
+    /// Deletes what the global state keeps of every state but
+    /// `retained_roots`, in transactions deleting at most `batch_size`
+    /// entries each. Nothing is committed meanwhile, but no deploys may be
+    /// running on the other states, as their tries are gone.
+    pub fn prune(
+        &self,
+        retained_roots: &[Blake2bHash],
+        batch_size: usize,
+    ) -> Result<PruneResult, Error> {
+        let result = self
+            .state
+            .write()
+            .prune(retained_roots, batch_size)
+            .map_err(Into::into)?;
+        Ok(result)
+    }
+
+    /// Checks that the deploy is authorized by a non-empty set of keys, all
+    /// of which are associated with the deploying account. Duplicates are
+    /// dropped, so the returned set is what later authorization checks
+    /// should operate on. Deploys can't be sent from the system account,
+    /// whatever keys they carry.
+    pub fn validate_authorization_keys<R: StateReader<Key, Value>>(
+        &self,
+        address: [u8; 20],
+        authorization_keys: &[PublicKey],
+        tc: &mut TrackingCopy<R>,
+    ) -> Result<BTreeSet<PublicKey>, Error>
+    where
+        R::Error: Into<execution::Error>,
+    {
+        if address == SYSTEM_ACCOUNT {
+            return Err(execution::Error::SystemContext.into());
+        }
+        let authorization_keys: BTreeSet<PublicKey> =
+            authorization_keys.iter().cloned().collect();
+        if authorization_keys.is_empty() {
+            return Err(Error::EmptyAuthorizationKeys);
+        }
+        let account_key = Key::Account(address);
+        let account = match tc
+            .get(&account_key)
+            .map_err(Into::<execution::Error>::into)?
+        {
+            Some(Value::Account(account)) => account,
+            Some(other) => {
+                return Err(execution::Error::TypeMismatch(TypeMismatch::new(
+                    "Account".to_string(),
+                    other.type_string(),
+                ))
+                .into())
+            }
+            None => return Err(Error::AccountNotFound(account_key)),
+        };
+        match authorization_keys
+            .iter()
+            .find(|key| !account.associated_keys().contains_key(key))
+        {
+            Some(unknown_key) => Err(Error::UnknownAuthorizationKey(*unknown_key)),
+            None => Ok(authorization_keys),
+        }
+    }
+
     // TODO run_deploy should perform preprocessing and validation of the deploy.
-    // It should validate the signatures, ocaps etc.
+    // It should validate the ocaps etc.
     #[allow(clippy::too_many_arguments)]
     pub fn run_deploy<A, P: Preprocessor<A>, E: Executor<A>>(
         &self,
-        module_bytes: &[u8],
-        args: &[u8],
+        session: DeployCode,
+        payment: DeployCode,
         address: [u8; 20],
+        authorization_keys: &[PublicKey],
         timestamp: u64,
         nonce: u64,
+        deploy_hash: [u8; 32],
+        body_hash: [u8; 32],
+        approvals: &[Approval],
+        block_height: u64,
+        preconditions: DeployPreconditions,
         prestate_hash: Blake2bHash,
-        gas_limit: u64,
+        gas_limit: Gas,
+        gas_price: u64,
         executor: &E,
         preprocessor: &P,
     ) -> Result<ExecutionResult, RootNotFound> {
-        match preprocessor.preprocess(module_bytes, &self.wasm_costs) {
-            Err(error) => Ok(ExecutionResult::failure(error.into(), 0)),
-            Ok(module) => match self.tracking_copy(prestate_hash) {
-                Err(error) => Ok(ExecutionResult::failure(error, 0)),
-                Ok(checkout_result) => match checkout_result {
-                    None => Err(RootNotFound(prestate_hash)),
-                    Some(mut tc) => {
-                        match executor
-                            .exec(module, args, address, timestamp, nonce, gas_limit, &mut tc)
-                        {
-                            (Ok(ee), cost) => Ok(ExecutionResult::success(ee, cost)),
-                            (Err(error), cost) => Ok(ExecutionResult::failure(error.into(), cost)),
-                        }
+        let deploy = Deploy {
+            session,
+            payment,
+            address,
+            authorization_keys,
+            timestamp,
+            nonce,
+            deploy_hash,
+            body_hash,
+            approvals,
+            preconditions,
+            gas_limit,
+            gas_price,
+        };
+        self.run_deploy_on_state(&deploy, block_height, prestate_hash, executor, preprocessor)
+    }
+
+    /// Runs `deploy` on the state `state_hash` to estimate its cost and to
+    /// preview its effects, the same way it would be run in a block. The
+    /// tracking copy it runs on is dropped afterwards and nothing is committed.
+    pub fn speculative_exec<A, P: Preprocessor<A>, E: Executor<A>>(
+        &self,
+        state_hash: Blake2bHash,
+        deploy: &Deploy,
+        block_height: u64,
+        executor: &E,
+        preprocessor: &P,
+    ) -> Result<ExecutionResult, RootNotFound> {
+        self.run_deploy_on_state(deploy, block_height, state_hash, executor, preprocessor)
+    }
+
+    /// Runs the deploys of a block. The result is the one of running them
+    /// one after another, each on the state left by the deploys before it,
+    /// but deploys which don't depend on each other run concurrently on as
+    /// many threads as the config allows. The states deploys are run again on
+    /// are held by a checkpoint of the global state, which is rolled back
+    /// afterwards, so running a block leaves no state behind.
+    pub fn run_deploys<A, P, E>(
+        &self,
+        deploys: &[Deploy],
+        block_height: u64,
+        prestate_hash: Blake2bHash,
+        executor: &E,
+        preprocessor: &P,
+    ) -> Result<Vec<ExecutionResult>, RootNotFound>
+    where
+        H: Send + Sync,
+        P: Preprocessor<A> + Sync,
+        E: Executor<A> + Sync,
+    {
+        let run_on_prestate = |index: usize| {
+            let read_set = RefCell::new(ReadSet::default());
+            let checkout = self.state.read().checkout(prestate_hash);
+            let result = match checkout {
+                Err(error) => {
+                    let error = Error::ExecError(error.into());
+                    Ok(ExecutionResult::failure(error, Gas::default()))
+                }
+                Ok(None) => Err(RootNotFound(prestate_hash)),
+                Ok(Some(reader)) => {
+                    let tc = TrackingCopy::new(RecordingReader::new(reader, &read_set));
+                    Ok(self.execute_deploy(
+                        &deploys[index],
+                        block_height,
+                        prestate_hash,
+                        tc,
+                        executor,
+                        preprocessor,
+                    ))
+                }
+            };
+            (result, read_set.into_inner())
+        };
+        let threads = self.config.execution_threads();
+        let speculative_results =
+            scheduler::map_concurrently(deploys.len(), threads, run_on_prestate);
+
+        let checkpoint = self.state.write().checkpoint();
+        let run_in_order = || -> Result<Vec<ExecutionResult>, RootNotFound> {
+            let mut block_state = BlockState::new(prestate_hash, checkpoint);
+            let mut remaining_gas = Gas::from(self.config.block_gas_limit());
+            let mut results = Vec::with_capacity(deploys.len());
+            for (deploy, (result, read_set)) in deploys.iter().zip(speculative_results) {
+                if let Err(error) = self.check_gas_bound(deploy, remaining_gas) {
+                    // The deploy isn't part of the block, whatever it did on the
+                    // prestate.
+                    results.push(ExecutionResult::failure(error, Gas::default()));
+                    continue;
+                }
+                let result = if block_state.is_changed(&read_set) {
+                    // The deploy read keys changed by an earlier deploy, so it's
+                    // run again on the state left by the earlier deploys.
+                    match block_state.commit(self)? {
+                        Ok(state_hash) => self.run_deploy_on_state(
+                            deploy,
+                            block_height,
+                            state_hash,
+                            executor,
+                            preprocessor,
+                        )?,
+                        Err(error) => ExecutionResult::failure(error, Gas::default()),
                     }
-                },
-            },
+                } else {
+                    result?
+                };
+                remaining_gas = remaining_gas.saturating_sub(result.cost);
+                block_state.record(&result);
+                results.push(result);
+            }
+            Ok(results)
+        };
+        let results = run_in_order();
+        self.state.write().rollback(checkpoint);
+        results
+    }
+
+    fn run_deploy_on_state<A, P: Preprocessor<A>, E: Executor<A>>(
+        &self,
+        deploy: &Deploy,
+        block_height: u64,
+        state_hash: Blake2bHash,
+        executor: &E,
+        preprocessor: &P,
+    ) -> Result<ExecutionResult, RootNotFound> {
+        match self.tracking_copy(state_hash) {
+            Err(error) => Ok(ExecutionResult::failure(error, Gas::default())),
+            Ok(None) => Err(RootNotFound(state_hash)),
+            Ok(Some(tc)) => Ok(self.execute_deploy(
+                deploy,
+                block_height,
+                state_hash,
+                tc,
+                executor,
+                preprocessor,
+            )),
+        }
+    }
+
+    /// Runs `deploy` on the state `tc` was checked out from, journaling its
+    /// execution if the config says so.
+    fn execute_deploy<R, A, P, E>(
+        &self,
+        deploy: &Deploy,
+        block_height: u64,
+        prestate_hash: Blake2bHash,
+        mut tc: TrackingCopy<R>,
+        executor: &E,
+        preprocessor: &P,
+    ) -> ExecutionResult
+    where
+        R: StateReader<Key, Value>,
+        R::Error: Into<execution::Error>,
+        P: Preprocessor<A>,
+        E: Executor<A>,
+    {
+        let journaling = self.config.journaling();
+        if journaling {
+            tc.enable_journal();
+        }
+        let result = self.execute_deploy_on(
+            deploy,
+            block_height,
+            prestate_hash,
+            &mut tc,
+            executor,
+            preprocessor,
+        );
+        if !journaling {
+            return result;
+        }
+        result.with_journal(DeployJournal {
+            deploy_hash: deploy.deploy_hash,
+            prestate_hash,
+            block_height,
+            entries: tc.take_journal(),
+        })
+    }
+
+    fn execute_deploy_on<R, A, P, E>(
+        &self,
+        deploy: &Deploy,
+        block_height: u64,
+        prestate_hash: Blake2bHash,
+        tc: &mut TrackingCopy<R>,
+        executor: &E,
+        preprocessor: &P,
+    ) -> ExecutionResult
+    where
+        R: StateReader<Key, Value>,
+        R::Error: Into<execution::Error>,
+        P: Preprocessor<A>,
+        E: Executor<A>,
+    {
+        let Deploy {
+            session,
+            payment,
+            address,
+            authorization_keys,
+            timestamp,
+            deploy_hash,
+            preconditions,
+            gas_limit,
+            gas_price,
+            ..
+        } = *deploy;
+        // Deploys failing these checks are rejected before any gas is spent.
+        let rejected = |error: Error| ExecutionResult::failure(error, Gas::default());
+        let max_deploy_size = self.config.max_deploy_size();
+        if deploy.size() > max_deploy_size {
+            let error = PreconditionError::DeployTooLarge {
+                size: deploy.size(),
+                max_size: max_deploy_size,
+            };
+            return rejected(Error::Precondition(error));
+        }
+        if self.config.verify_approvals() {
+            if let Err(error) = approval::verify_approvals(deploy) {
+                return rejected(Error::Approval(error));
+            }
+        }
+        let block_gas_limit = Gas::from(self.config.block_gas_limit());
+        if let Err(error) = self.check_gas_bound(deploy, block_gas_limit) {
+            return rejected(error);
+        }
+        let authorization_keys =
+            match self.validate_authorization_keys(address, authorization_keys, tc) {
+                Ok(keys) => keys,
+                Err(error) => return rejected(error),
+            };
+        match preconditions.check(tc, deploy_hash, timestamp) {
+            Ok(Ok(())) => (),
+            Ok(Err(error)) => return rejected(Error::Precondition(error)),
+            Err(error) => return rejected(error.into()),
+        }
+        // Executed deploys are recorded when they're finalized, so that they
+        // can't be replayed, along with what they cost and transferred.
+        let source_purse = match main_purse(tc, address) {
+            Ok(purse) => purse,
+            Err(error) => return rejected(error.into()),
+        };
+        let deploy_info = |tc: &TrackingCopy<R>, gas: Gas| DeployInfo {
+            deploy_hash,
+            transfers: transfer::transfer_addrs(&tc.effect()),
+            from: address,
+            source_purse,
+            gas,
+            block_height,
+        };
+        // Deploys are charged the costs recorded in their prestate, so that
+        // upgrades can change them.
+        let protocol_data = match self.protocol_data(prestate_hash) {
+            Ok(protocol_data) => protocol_data,
+            Err(error) => return rejected(error),
+        };
+        let (wasm_costs, host_function_costs, storage_costs) = match protocol_data {
+            Some(protocol_data) => (
+                protocol_data.wasm_costs,
+                protocol_data.host_function_costs,
+                protocol_data.storage_costs,
+            ),
+            None => (
+                self.config.wasm_costs().clone(),
+                self.config.host_function_costs().clone(),
+                self.config.storage_costs(),
+            ),
+        };
+        // The session pays for the bytes it adds to the state along with its
+        // gas, and fails if it can't. Deploys growing values can also leave
+        // the credit they paid for them.
+        let charge_storage = |session_cost: Gas,
+                              gas_limit: Gas,
+                              start: &rent::ChangedSizes,
+                              tc: &mut TrackingCopy<R>|
+         -> Result<Gas, Error> {
+            let sizes = rent::ChangedSizes::measure(tc)?;
+            let growth = sizes.growth_since(start);
+            let storage_cost = Gas::from(storage_costs.bytes_cost(rent::net_bytes(&growth)));
+            if session_cost.saturating_add(storage_cost) > gas_limit {
+                return Err(execution::Error::GasLimit.into());
+            }
+            if self.config.storage_credits() {
+                rent::add_credits(tc, &growth, &sizes, storage_costs, block_height)?;
+            }
+            Ok(storage_cost)
+        };
+        let config = self
+            .config
+            .clone()
+            .with_host_function_costs(host_function_costs);
+        let exec = |code: DeployCode,
+                    gas_limit: Gas,
+                    phase: Phase,
+                    tc: &mut TrackingCopy<R>|
+         -> (Result<ExecutionEffect, Error>, Gas) {
+            match preprocessor.preprocess(code.module_bytes, &wasm_costs) {
+                Err(error) => (Err(error.into()), Gas::default()),
+                Ok(module) => {
+                    let (result, cost) = executor.exec(
+                        module,
+                        code.args,
+                        code.named_args,
+                        address,
+                        authorization_keys.clone(),
+                        deploy_hash,
+                        block_height,
+                        gas_limit,
+                        phase,
+                        &config,
+                        tc,
+                    );
+                    (result.map_err(Into::into), cost)
+                }
+            }
+        };
+
+        if !self.config.use_payment_code() {
+            let (result, session_cost) = exec(session, gas_limit, Phase::Session, tc);
+            let result = result.and_then(|exec_effect| {
+                let start = rent::ChangedSizes::default();
+                let storage_cost = charge_storage(session_cost, gas_limit, &start, tc)?;
+                let cost = session_cost.saturating_add(storage_cost);
+                let deploy_info = deploy_info(tc, cost);
+                write_deploy_info(tc, deploy_hash, &deploy_info)?;
+                let mut effect = tc.effect();
+                effect.2 = exec_effect.2;
+                Ok((effect, cost))
+            });
+            return match result {
+                Ok((effect, cost)) => ExecutionResult::success(effect, cost),
+                Err(error) => ExecutionResult::failure(error, session_cost),
+            };
+        }
+
+        // The payment code pays into a purse of the deploy, which buys the gas
+        // for the whole deploy. A deploy failing to pay is not charged.
+        let payment_purse_addr = mint::payment_purse(deploy_hash).addr();
+        let payment_purse = mint::create_purse(tc, payment_purse_addr);
+        let max_payment_cost = Gas::from(self.config.max_payment_cost());
+        let (payment_result, payment_cost) = if payment.is_standard_payment() {
+            let result = self.standard_payment(address, payment.args, payment_purse, tc);
+            (result.map(|()| Vec::new()), Gas::from(STANDARD_PAYMENT_COST))
+        } else {
+            let (result, cost) = exec(payment, max_payment_cost, Phase::Payment, tc);
+            (result.map(|effect| effect.2), cost)
+        };
+        let payment_failure = |error: Error| -> ExecutionResult {
+            ExecutionResult::failure(error, payment_cost).with_payment_cost(payment_cost)
+        };
+        let payment_events = match payment_result {
+            Ok(events) => events,
+            Err(error) => return payment_failure(Error::PaymentError(Box::new(error))),
+        };
+        let gas_price = if gas_price == 0 { CONV_RATE } else { gas_price };
+        let purchased_gas = match mint::balance(tc, payment_purse) {
+            // The price isn't zero, so the payment buys some gas.
+            Ok(balance) => Gas::from_motes(Motes::new(balance), gas_price).unwrap_or_default(),
+            Err(error) => return payment_failure(error.into()),
+        };
+        if purchased_gas < payment_cost {
+            return payment_failure(Error::InsufficientPayment);
+        }
+
+        // A failed session is rolled back, but the deploy still pays for it.
+        let session_gas_limit = cmp::min(gas_limit, purchased_gas.saturating_sub(payment_cost));
+        let start = match rent::ChangedSizes::measure(tc) {
+            Ok(start) => start,
+            Err(error) => return payment_failure(error.into()),
+        };
+        let checkpoint = tc.checkpoint();
+        let (session_result, session_cost) = exec(session, session_gas_limit, Phase::Session, tc);
+        let session_result = session_result.and_then(|effect| {
+            let storage_cost = charge_storage(session_cost, session_gas_limit, &start, tc)?;
+            Ok((effect.2, storage_cost))
+        });
+        let (session_events, storage_cost, session_error) = match session_result {
+            Ok((events, storage_cost)) => (events, storage_cost, None),
+            Err(error) => {
+                tc.rollback(checkpoint);
+                (Vec::new(), Gas::default(), Some(error))
+            }
+        };
+
+        let cost = payment_cost
+            .saturating_add(session_cost)
+            .saturating_add(storage_cost);
+        let finalized = self.finalize_payment(address, payment_purse, cost, gas_price, tc);
+        if let Err(error) = finalized {
+            let result = ExecutionResult::failure(error.into(), cost);
+            return result.with_payment_cost(payment_cost);
+        }
+        let deploy_info = deploy_info(tc, cost);
+        if let Err(error) = write_deploy_info(tc, deploy_hash, &deploy_info) {
+            let result = ExecutionResult::failure(error.into(), cost);
+            return result.with_payment_cost(payment_cost);
+        }
+        let mut effect = tc.effect();
+        effect.2 = payment_events;
+        let result = match session_error {
+            None => {
+                effect.2.extend(session_events);
+                ExecutionResult::success(effect, cost)
+            }
+            Some(error) => ExecutionResult::failure(error, cost).with_fee_effect(effect),
+        };
+        result.with_payment_cost(payment_cost)
+    }
+
+    /// Most gas `deploy` can use: its gas limit, and what its payment code can
+    /// use if deploys pay for themselves.
+    fn max_deploy_cost(&self, deploy: &Deploy) -> Gas {
+        if !self.config.use_payment_code() {
+            return deploy.gas_limit;
+        }
+        let max_payment_cost = if deploy.payment.is_standard_payment() {
+            STANDARD_PAYMENT_COST
+        } else {
+            self.config.max_payment_cost()
+        };
+        deploy.gas_limit.saturating_add(Gas::from(max_payment_cost))
+    }
+
+    /// Checks that `deploy` can't use more than `remaining_gas`.
+    fn check_gas_bound(&self, deploy: &Deploy, remaining_gas: Gas) -> Result<(), Error> {
+        let max_cost = self.max_deploy_cost(deploy);
+        if max_cost > remaining_gas {
+            let error = PreconditionError::BlockGasLimitExceeded {
+                max_cost,
+                remaining: remaining_gas,
+            };
+            return Err(Error::Precondition(error));
+        }
+        Ok(())
+    }
+
+    /// Pays for a deploy with the standard payment registered in the config.
+    fn standard_payment<R: StateReader<Key, Value>>(
+        &self,
+        address: [u8; 20],
+        args: &[u8],
+        payment_purse: PurseId,
+        tc: &mut TrackingCopy<R>,
+    ) -> Result<(), Error>
+    where
+        R::Error: Into<execution::Error>,
+    {
+        let id = SystemContractId::StandardPayment;
+        let contract_key = match self.config.system_contracts().get(&id) {
+            Some(key) => *key,
+            None => return Err(execution::Error::SystemContractNotFound(id.into()).into()),
+        };
+        let main_purse = main_purse(tc, address)?;
+        standard_payment::pay(tc, contract_key, main_purse, payment_purse, args)?;
+        Ok(())
+    }
+
+    /// Settles the payment of a deploy which used `cost` gas at `gas_price`.
+    /// The configured share of the unused payment is refunded to the main
+    /// purse of the deploying account, and the fee and the rest of the payment
+    /// move from the payment purse to the rewards purse. Engines without a
+    /// rewards purse burn them instead. The deploy doesn't hold the payment
+    /// purse, so this runs in the system context.
+    fn finalize_payment<R: StateReader<Key, Value>>(
+        &self,
+        address: [u8; 20],
+        payment_purse: PurseId,
+        cost: Gas,
+        gas_price: u64,
+        tc: &mut TrackingCopy<R>,
+    ) -> Result<(), execution::Error>
+    where
+        R::Error: Into<execution::Error>,
+    {
+        let fee = Motes::from_gas(cost, gas_price).ok_or(execution::Error::Overflow)?;
+        let balance = Motes::new(mint::balance(tc, payment_purse)?);
+        // The payment bought at least the gas used, so it covers the fee.
+        let unused = balance.checked_sub(fee).ok_or(execution::Error::InsufficientFunds)?;
+        let refund = self.config.refund_ratio().refund(unused);
+        let main_purse = main_purse(tc, address)?;
+        let context = SystemContext::new(SystemFlow::FinalizePayment);
+        context.transfer(tc, payment_purse, main_purse, refund.value())?;
+        let rewards = balance.value() - refund.value();
+        match self.config.rewards_purse() {
+            Some(rewards_purse) => context.transfer(tc, payment_purse, rewards_purse, rewards),
+            None => context.burn(tc, payment_purse, rewards),
+        }
+    }
+
+    /// Weights of the validators bonded in the PoS contract at `state_hash`,
+    /// which include the tokens delegated to them. Returns `None` if the
+    /// state isn't found.
+    pub fn bonded_validators(
+        &self,
+        state_hash: Blake2bHash,
+    ) -> Result<Option<BTreeMap<PublicKey, U512>>, Error> {
+        let id = SystemContractId::ProofOfStake;
+        let pos_contract = match self.config.system_contracts().get(&id) {
+            Some(key) => *key,
+            None => return Err(execution::Error::SystemContractNotFound(id.into()).into()),
+        };
+        match self.tracking_copy(state_hash)? {
+            Some(mut tc) => Ok(Some(pos::weights(&mut tc, pos_contract)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Balances of the purses the mint created, in the state at `state_hash`,
+    /// by the keys they're stored under. Returns `None` if the state isn't
+    /// found or can't list keys.
+    pub fn balances(&self, state_hash: Blake2bHash) -> Result<Option<BTreeMap<Key, U512>>, Error>
+    where
+        H::Error: From<bytesrepr::Error>,
+    {
+        match self.tracking_copy(state_hash)? {
+            Some(mut tc) => Ok(mint::balances(&mut tc)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Validator sets the auction contract at `state_hash` picked for the
+    /// current and upcoming eras, with their weights, by era id. Returns
+    /// `None` if the state isn't found.
+    pub fn era_validators(&self, state_hash: Blake2bHash) -> Result<Option<EraValidators>, Error> {
+        let id = SystemContractId::Auction;
+        let auction_contract = match self.config.system_contracts().get(&id) {
+            Some(key) => *key,
+            None => return Err(execution::Error::SystemContractNotFound(id.into()).into()),
+        };
+        match self.tracking_copy(state_hash)? {
+            Some(mut tc) => Ok(Some(auction::era_validators(&mut tc, auction_contract)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Creates the initial global state of a chain on top of the empty state
+    /// at `empty_root_hash`. The system contracts and the system account are
+    /// installed and the accounts are created with their stakes already
+    /// bonded, the validators among them being seated until the first
+    /// auction picks others. Engines running the chain have to be configured with
+    /// `GenesisConfig::engine_config`.
+    pub fn commit_genesis(
+        &self,
+        empty_root_hash: Blake2bHash,
+        genesis: &GenesisConfig,
+    ) -> Result<GenesisResult, Error> {
+        if let Some(public_key) = genesis.duplicate_accounts().first() {
+            let msg = format!("Account {:?} is listed more than once", public_key);
+            return Err(Error::GenesisError(msg));
+        }
+        let mut tc = match self.tracking_copy(empty_root_hash)? {
+            Some(tc) => tc,
+            None => {
+                let msg = format!("Root {:?} not found", empty_root_hash);
+                return Err(Error::GenesisError(msg));
+            }
+        };
+        let context = SystemContext::new(SystemFlow::Genesis);
+        mint::install(&mut tc, genesis.system_contract_key(SystemContractId::Mint));
+        let pos_key = genesis.system_contract_key(SystemContractId::ProofOfStake);
+        pos::install(&mut tc, pos_key, genesis.derive_addr(b"bonding_purse"))?;
+        let standard_payment_key = genesis.system_contract_key(SystemContractId::StandardPayment);
+        standard_payment::install(&mut tc, standard_payment_key);
+        let auction_key = genesis.system_contract_key(SystemContractId::Auction);
+        auction::install(&mut tc, auction_key, genesis.derive_addr(b"bids_purse"))?;
+        upgrade::write_protocol_data(&context, &mut tc, &genesis.protocol_data())?;
+        // The system account holds no funds, and its all-zeros key is nobody's.
+        let system_purse = mint::create_purse(&mut tc, genesis.derive_addr(b"system_purse"));
+        let system_account = Account::create([0u8; 32], 0, BTreeMap::new(), system_purse);
+        tc.write(context.account(), Value::Account(system_account));
+
+        for account in genesis.accounts() {
+            let public_key = account.public_key;
+            let mut purse_seed = b"main_purse".to_vec();
+            purse_seed.extend_from_slice(&public_key.value());
+            let purse = mint::create_purse(&mut tc, genesis.derive_addr(&purse_seed));
+            // The stake is bonded out of the main purse.
+            let balance = account.balance + account.bonded_amount;
+            context.mint(&mut tc, purse, balance)?;
+            let account_key = Key::Account(account_address(public_key));
+            let new_account = Account::create(public_key.value(), 0, BTreeMap::new(), purse);
+            tc.write(account_key, Value::Account(new_account));
+            if !account.bonded_amount.is_zero() {
+                let amount = account.bonded_amount;
+                let delays = QueueDelays::default();
+                pos::bond(&mut tc, pos_key, public_key, purse, amount, 0, delays)?;
+            }
+        }
+        let validators = pos::weights(&mut tc, pos_key)?;
+        let settings = genesis.auction_settings();
+        auction::seat_genesis_validators(&mut tc, auction_key, &validators, settings)?;
+
+        let effect = tc.effect();
+        let commit_result = self
+            .apply_effect(empty_root_hash, effect.1.clone())
+            .map_err(Into::<execution::Error>::into)?;
+        match commit_result {
+            CommitResult::Success(post_state_hash) => Ok(GenesisResult {
+                post_state_hash,
+                effect,
+            }),
+            _ => Err(Error::GenesisError(String::from("Effects couldn't be committed"))),
+        }
+    }
+
+    /// Protocol data recorded in the state at `state_hash`. States which
+    /// weren't created by genesis have none.
+    pub fn protocol_data(&self, state_hash: Blake2bHash) -> Result<Option<ProtocolData>, Error> {
+        match self.tracking_copy(state_hash)? {
+            Some(mut tc) => Ok(upgrade::read_protocol_data(&mut tc)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Record of the transfer at `addr` in the state at `state_hash`. There's
+    /// none if the state isn't found either.
+    pub fn transfer(
+        &self,
+        state_hash: Blake2bHash,
+        addr: TransferAddr,
+    ) -> Result<Option<Transfer>, Error> {
+        match self.tracking_copy(state_hash)? {
+            Some(mut tc) => Ok(transfer::read_transfer(&mut tc, addr)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Record of the deploy with `deploy_hash` in the state at `state_hash`,
+    /// if it was executed.
+    pub fn deploy_info(
+        &self,
+        state_hash: Blake2bHash,
+        deploy_hash: [u8; 32],
+    ) -> Result<Option<DeployInfo>, Error> {
+        match self.tracking_copy(state_hash)? {
+            Some(mut tc) => Ok(read_deploy_info(&mut tc, deploy_hash)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Value at the end of `path` in the state at `state_hash`, starting
+    /// from the value of `base_key` and following the named keys of the
+    /// accounts and contracts on the way, with proofs of the values read.
+    /// There's none if the state isn't found.
+    pub fn query(
+        &self,
+        state_hash: Blake2bHash,
+        base_key: Key,
+        path: &[String],
+    ) -> Result<Option<(QueryResult, Vec<TrieMerkleProof<Key, Value>>)>, Error> {
+        match self.tracking_copy(state_hash)? {
+            Some(mut tc) => {
+                let result = tc.query_with_proofs(base_key, path).map_err(Into::into)?;
+                Ok(Some(result))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Keys whose values differ between the states at `from` and `to`, with
+    /// their values in both, to see what a block changed. There's none if
+    /// one of the states isn't found.
+    pub fn diff(
+        &self,
+        from: Blake2bHash,
+        to: Blake2bHash,
+    ) -> Result<Option<Vec<(Key, Option<Value>, Option<Value>)>>, Error> {
+        let changes = self.state.read().diff(from, to).map_err(Into::into)?;
+        Ok(changes)
+    }
+
+    /// Keys starting with the bytes `prefix` in the state at `state_hash`,
+    /// with their values, in the order of their bytes, to look into a state
+    /// without knowing its keys: all the accounts for the tag of accounts,
+    /// say. There's none if the state isn't found or can't list its keys.
+    pub fn keys_with_prefix(
+        &self,
+        state_hash: Blake2bHash,
+        prefix: &[u8],
+    ) -> Result<Option<Vec<(Key, Value)>>, Error> {
+        let reader = match self.state.read().checkout(state_hash).map_err(Into::into)? {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+        let pairs = reader.keys_with_prefix(prefix).map_err(Into::into)?;
+        Ok(pairs)
+    }
+
+    /// Executes `deploy` again on the values its `journal` loaded from the
+    /// prestate, without the global state, and compares the journal of the
+    /// replay to the recorded one. Costs are still looked up in the prestate
+    /// if the engine has it, like they are for any deploy.
+    pub fn replay<A, P: Preprocessor<A>, E: Executor<A>>(
+        &self,
+        deploy: &Deploy,
+        journal: &DeployJournal,
+        executor: &E,
+        preprocessor: &P,
+    ) -> Replay {
+        let mut tc = TrackingCopy::new(journal.prestate());
+        tc.enable_journal();
+        self.execute_deploy_on(
+            deploy,
+            journal.block_height,
+            journal.prestate_hash,
+            &mut tc,
+            executor,
+            preprocessor,
+        );
+        let replayed = DeployJournal {
+            deploy_hash: deploy.deploy_hash,
+            prestate_hash: journal.prestate_hash,
+            block_height: journal.block_height,
+            entries: tc.take_journal(),
+        };
+        Replay::new(journal, replayed)
+    }
+
+    /// Applies `upgrade` to the state at `pre_state_hash`, recording the new
+    /// protocol data in it.
+    pub fn commit_upgrade(
+        &self,
+        pre_state_hash: Blake2bHash,
+        upgrade: &UpgradeConfig,
+    ) -> Result<UpgradeResult, Error> {
+        let mut tc = match self.tracking_copy(pre_state_hash)? {
+            Some(tc) => tc,
+            None => return Err(Error::UpgradeError(UpgradeError::RootNotFound(pre_state_hash))),
+        };
+        let context = SystemContext::new(SystemFlow::Upgrade);
+        let protocol_data = upgrade.apply(&context, &mut tc).map_err(Error::UpgradeError)?;
+        let effect = tc.effect();
+        let commit_result = self
+            .apply_effect(pre_state_hash, effect.1.clone())
+            .map_err(Into::<execution::Error>::into)?;
+        match commit_result {
+            CommitResult::Success(post_state_hash) => Ok(UpgradeResult {
+                post_state_hash,
+                protocol_data,
+                effect,
+            }),
+            _ => Err(Error::UpgradeError(UpgradeError::CommitFailed)),
+        }
+    }
+
+    /// Runs the step ending an era on the state at `state_root`: equivocators
+    /// are slashed, the seigniorage of the era is minted into the rewards
+    /// purse, the rewards collected there are bonded to the validators and
+    /// their delegators and the queues of the PoS contract are settled at the
+    /// height the era ended. If an auction contract is configured, the
+    /// validators of an upcoming era are picked last.
+    pub fn step(&self, state_root: Blake2bHash, era_end: &EraEndData) -> Result<StepResult, Error>
+    where
+        H::Error: From<bytesrepr::Error>,
+    {
+        let id = SystemContractId::ProofOfStake;
+        let pos_contract = match self.config.system_contracts().get(&id) {
+            Some(key) => *key,
+            None => return Err(execution::Error::SystemContractNotFound(id.into()).into()),
+        };
+        let mut tc = match self.tracking_copy(state_root)? {
+            Some(tc) => tc,
+            None => return Err(Error::StepError(StepError::RootNotFound(state_root))),
+        };
+        let context = SystemContext::new(SystemFlow::EraEnd);
+        let totals = era_end
+            .apply(&context, &mut tc, pos_contract, &self.config)
+            .map_err(Error::StepError)?;
+        let effect = tc.effect();
+        let commit_result = self
+            .apply_effect(state_root, effect.1.clone())
+            .map_err(Into::<execution::Error>::into)?;
+        match commit_result {
+            CommitResult::Success(post_state_hash) => Ok(StepResult {
+                post_state_hash,
+                totals,
+                effect,
+            }),
+            _ => Err(Error::StepError(StepError::CommitFailed)),
         }
     }
 
@@ -162,6 +1608,416 @@ where
         prestate_hash: Blake2bHash,
         effects: HashMap<Key, Transform>,
     ) -> Result<CommitResult, H::Error> {
-        self.state.lock().commit(prestate_hash, effects)
+        self.state.read().commit(prestate_hash, effects)
+    }
+
+    /// Applies `effects` like [`apply_effect`](EngineState::apply_effect),
+    /// holding the new state in `checkpoint` until it's committed.
+    pub fn apply_effect_at(
+        &self,
+        checkpoint: CheckpointId,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, H::Error> {
+        self.state.write().commit_at(checkpoint, prestate_hash, effects)
+    }
+}
+
+impl EngineState<InMemoryGlobalState> {
+    /// Engine on a global state kept in memory, configured for the chain
+    /// started by `genesis`, with the post state hash of its genesis. Tests
+    /// can run deploys on it without a database on disk.
+    pub fn in_memory_genesis(
+        genesis: &GenesisConfig,
+        config: EngineConfig,
+    ) -> Result<(EngineState<InMemoryGlobalState>, Blake2bHash), Error> {
+        let global_state = InMemoryGlobalState::empty()?;
+        let empty_root_hash = global_state.empty_root_hash();
+        let engine_state = EngineState::with_config(global_state, genesis.engine_config(config));
+        let post_state_hash = engine_state
+            .commit_genesis(empty_root_hash, genesis)?
+            .post_state_hash;
+        Ok((engine_state, post_state_hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EngineConfig, EngineState, Error, RefundRatio};
+    use common::key::{AccessRights, Key, ACCOUNT_ID};
+    use common::system_contracts::SystemContractId;
+    use common::value::account::{PublicKey, PurseId};
+    use common::value::contract::PROTOCOL_VERSION;
+    use common::value::{Contract, Value, U512};
+    use execution::{self, account_address};
+    use genesis::{GenesisAccount, GenesisConfig};
+    use mint;
+    use pos;
+    use shared::gas::Gas;
+    use shared::motes::Motes;
+    use shared::newtypes::Blake2bHash;
+    use std::collections::BTreeSet;
+    use storage::global_state::in_memory::InMemoryGlobalState;
+    use storage::global_state::inmem::InMemHist;
+    use storage::global_state::mocked_account;
+    use storage::history::{CommitResult, PruneResult};
+    use storage::transform::Transform;
+    use step::{EraEndData, StepError};
+    use system::SYSTEM_ACCOUNT;
+    use trackingcopy::QueryResult;
+    use upgrade::{UpgradeConfig, UpgradeError};
+    use vm::wasm_costs::WasmCosts;
+
+    const ADDRESS: [u8; 20] = [1u8; 20];
+    // Public key of the account created by `mocked_account`.
+    const PUBLIC_KEY: [u8; 32] = [48u8; 32];
+
+    fn validate(address: [u8; 20], keys: &[PublicKey]) -> Result<BTreeSet<PublicKey>, Error> {
+        let root_hash: Blake2bHash = [0u8; 32].into();
+        let hist = InMemHist::new_initialized(&root_hash, mocked_account(ADDRESS));
+        let engine_state = EngineState::new(hist);
+        let mut tc = engine_state
+            .tracking_copy(root_hash)
+            .expect("Checkout should not fail.")
+            .expect("Root hash should exist.");
+        engine_state.validate_authorization_keys(address, keys, &mut tc)
+    }
+
+    #[test]
+    fn authorization_keys_are_deduplicated() {
+        let key = PublicKey::new(PUBLIC_KEY);
+        assert_eq!(validate(ADDRESS, &[key, key]).expect("should be valid").len(), 1);
+    }
+
+    #[test]
+    fn empty_authorization_keys_are_rejected() {
+        assert_matches!(validate(ADDRESS, &[]), Err(Error::EmptyAuthorizationKeys));
+    }
+
+    #[test]
+    fn unknown_authorization_key_is_rejected() {
+        let unknown_key = PublicKey::new([2u8; 32]);
+        let keys = [PublicKey::new(PUBLIC_KEY), unknown_key];
+        match validate(ADDRESS, &keys) {
+            Err(Error::UnknownAuthorizationKey(key)) => assert_eq!(key, unknown_key),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_account_is_rejected() {
+        let keys = [PublicKey::new(PUBLIC_KEY)];
+        assert_matches!(validate([2u8; 20], &keys), Err(Error::AccountNotFound(_)));
+    }
+
+    #[test]
+    fn refund_ratios_are_at_most_one() {
+        assert_eq!(RefundRatio::new(1, 0), None);
+        assert_eq!(RefundRatio::new(3, 2), None);
+        let half = RefundRatio::new(1, 2).expect("Ratio should be valid.");
+        let motes = |value: u64| Motes::new(U512::from(value));
+        assert_eq!(half.refund(motes(7)), motes(3));
+        let none = RefundRatio::new(0, 1).expect("Ratio should be valid.");
+        assert_eq!(none.refund(motes(7)), motes(0));
+        assert_eq!(RefundRatio::default().refund(motes(7)), motes(7));
+    }
+
+    #[test]
+    fn payments_are_burned_without_a_rewards_purse() {
+        let payer = PublicKey::new([3u8; 32]);
+        let accounts = vec![GenesisAccount::new(payer, U512::from(100), U512::zero())];
+        let half = RefundRatio::new(1, 2).expect("Ratio should be valid.");
+        let config = EngineConfig::new().with_refund_ratio(half);
+        let (engine_state, genesis_hash) =
+            EngineState::in_memory_genesis(&genesis_config(accounts), config)
+                .expect("Genesis should be committed.");
+        let mut tc = engine_state
+            .tracking_copy(genesis_hash)
+            .expect("Checkout should not fail.")
+            .expect("Genesis hash should exist.");
+        let payment_purse = PurseId::new([9u8; 32], AccessRights::READ_ADD_WRITE);
+        mint::create_purse(&mut tc, payment_purse.addr());
+        mint::mint(&mut tc, payment_purse, U512::from(30)).expect("Payment should be minted.");
+        let total_supply = mint::total_supply(&mut tc).unwrap();
+
+        // A fee of 10 leaves 20 unused, half of which is refunded.
+        engine_state
+            .finalize_payment(account_address(payer), payment_purse, Gas::from(10), 1, &mut tc)
+            .expect("Payment should be finalized.");
+        let main_purse = match tc.read(Key::Account(account_address(payer))) {
+            Ok(Some(Value::Account(account))) => account.purse_id(),
+            other => panic!("Expected the payer's account, got {:?}", other),
+        };
+        assert_eq!(mint::balance(&mut tc, main_purse).unwrap(), U512::from(110));
+        assert_eq!(mint::balance(&mut tc, payment_purse).unwrap(), U512::zero());
+        assert_eq!(mint::total_supply(&mut tc).unwrap(), total_supply - U512::from(20));
+    }
+
+    fn genesis_config(accounts: Vec<GenesisAccount>) -> GenesisConfig {
+        GenesisConfig::new("test-chain", accounts, WasmCosts::default())
+    }
+
+    type TestEngineState = EngineState<InMemoryGlobalState>;
+
+    fn commit_genesis(genesis: &GenesisConfig) -> (TestEngineState, Blake2bHash) {
+        EngineState::in_memory_genesis(genesis, EngineConfig::new())
+            .expect("Genesis should be committed.")
+    }
+
+    #[test]
+    fn genesis_creates_accounts_and_bonds_stakes() {
+        let validator = PublicKey::new([3u8; 32]);
+        let accounts = vec![
+            GenesisAccount::new(validator, U512::from(100), U512::from(50)),
+            GenesisAccount::new(PublicKey::new([4u8; 32]), U512::from(10), U512::zero()),
+        ];
+        let genesis = genesis_config(accounts);
+        let (engine_state, post_state_hash) = commit_genesis(&genesis);
+        let mut tc = engine_state
+            .tracking_copy(post_state_hash)
+            .expect("Checkout should not fail.")
+            .expect("Post state hash should exist.");
+        let account_key = Key::Account(account_address(validator));
+        let purse = match tc.read(account_key) {
+            Ok(Some(Value::Account(account))) => account.purse_id(),
+            other => panic!("Expected an account, got {:?}", other),
+        };
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::from(100));
+
+        let pos_key = genesis.system_contract_key(SystemContractId::ProofOfStake);
+        let stakes = pos::stakes(&mut tc, pos_key).expect("Stakes should be read.");
+        assert_eq!(stakes.len(), 1);
+        assert_eq!(stakes.get(&validator), Some(&U512::from(50)));
+        // Everything the accounts start with is minted.
+        assert_eq!(mint::total_supply(&mut tc).unwrap(), U512::from(160));
+    }
+
+    #[test]
+    fn genesis_post_state_hash_is_deterministic() {
+        let accounts = vec![GenesisAccount::new(
+            PublicKey::new([3u8; 32]),
+            U512::from(100),
+            U512::from(50),
+        )];
+        let (_, first_hash) = commit_genesis(&genesis_config(accounts.clone()));
+        let (_, second_hash) = commit_genesis(&genesis_config(accounts));
+        assert_eq!(first_hash, second_hash);
+    }
+
+    #[test]
+    fn upgrades_record_new_protocol_data() {
+        let genesis = genesis_config(Vec::new());
+        let (engine_state, genesis_hash) = commit_genesis(&genesis);
+        let protocol_data = engine_state
+            .protocol_data(genesis_hash)
+            .expect("Protocol data should be read.");
+        assert_eq!(protocol_data, Some(genesis.protocol_data()));
+
+        let wasm_costs = WasmCosts {
+            regular: 3,
+            ..WasmCosts::default()
+        };
+        let upgrade = UpgradeConfig::new(2).with_wasm_costs(wasm_costs.clone());
+        let result = engine_state
+            .commit_upgrade(genesis_hash, &upgrade)
+            .expect("Upgrade should be committed.");
+        let protocol_data = engine_state
+            .protocol_data(result.post_state_hash)
+            .expect("Protocol data should be read.")
+            .expect("Protocol data should exist.");
+        assert_eq!(protocol_data.protocol_version, 2);
+        assert_eq!(protocol_data.wasm_costs, wasm_costs);
+        assert_matches!(
+            engine_state.commit_upgrade(result.post_state_hash, &upgrade),
+            Err(Error::UpgradeError(UpgradeError::InvalidActivationVersion { .. }))
+        );
+    }
+
+    #[test]
+    fn pruning_keeps_the_retained_states() {
+        let genesis = genesis_config(Vec::new());
+        let (engine_state, genesis_hash) = commit_genesis(&genesis);
+        let upgrade = UpgradeConfig::new(2);
+        let result = engine_state
+            .commit_upgrade(genesis_hash, &upgrade)
+            .expect("Upgrade should be committed.");
+        let missing = Blake2bHash::new(&[9u8]);
+        assert_eq!(
+            engine_state.prune(&[missing], 1).expect("Pruning should not fail."),
+            PruneResult::RootNotFound(missing)
+        );
+
+        let retained = [result.post_state_hash];
+        assert_matches!(
+            engine_state.prune(&retained, 1),
+            Ok(PruneResult::Pruned(deleted)) if deleted > 0
+        );
+        assert!(engine_state.tracking_copy(genesis_hash).unwrap().is_none());
+        let protocol_data = engine_state
+            .protocol_data(result.post_state_hash)
+            .expect("Protocol data should be read.")
+            .expect("Protocol data should exist.");
+        assert_eq!(protocol_data.protocol_version, 2);
+    }
+
+    #[test]
+    fn queries_follow_the_named_keys_on_the_path() {
+        let state_key = Key::Hash([1u8; 32]);
+        let contract_key = Key::Hash([2u8; 32]);
+        let named_keys = vec![("state".to_owned(), state_key)].into_iter().collect();
+        let contract = Contract::new(vec![0u8], named_keys, PROTOCOL_VERSION);
+        let pairs = [
+            (state_key, Value::Int32(1)),
+            (contract_key, contract.into()),
+        ];
+        let (global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let engine_state = EngineState::new(global_state);
+
+        let path = vec!["state".to_owned()];
+        let (result, proofs) = engine_state
+            .query(root_hash, contract_key, &path)
+            .expect("Query should not fail.")
+            .expect("Root hash should exist.");
+        assert_matches!(result, QueryResult::Success(Value::Int32(1)));
+        assert_eq!(proofs.len(), 2);
+
+        let path = vec!["state".to_owned(), "counter".to_owned()];
+        let (result, _) = engine_state
+            .query(root_hash, contract_key, &path)
+            .expect("Query should not fail.")
+            .expect("Root hash should exist.");
+        assert_matches!(
+            result,
+            QueryResult::ValueNotFound(ref message) if message.contains("counter")
+        );
+
+        let missing = Blake2bHash::new(&[9u8]);
+        assert!(engine_state
+            .query(missing, contract_key, &path)
+            .expect("Query should not fail.")
+            .is_none());
+    }
+
+    #[test]
+    fn diffs_show_what_a_commit_changed() {
+        let key = Key::Hash([1u8; 32]);
+        let (global_state, root_hash) =
+            InMemoryGlobalState::from_pairs(&[(key, Value::Int32(1))]).unwrap();
+        let engine_state = EngineState::new(global_state);
+        let effects = vec![(key, Transform::AddInt32(2))].into_iter().collect();
+        let post_state_hash = match engine_state.apply_effect(root_hash, effects).unwrap() {
+            CommitResult::Success(post_state_hash) => post_state_hash,
+            _ => panic!("Commit should succeed."),
+        };
+
+        let changes = engine_state
+            .diff(root_hash, post_state_hash)
+            .expect("Diff should not fail.")
+            .expect("Both states should exist.");
+        assert_eq!(changes, vec![(key, Some(Value::Int32(1)), Some(Value::Int32(3)))]);
+        let missing = Blake2bHash::new(&[9u8]);
+        assert!(engine_state
+            .diff(root_hash, missing)
+            .expect("Diff should not fail.")
+            .is_none());
+    }
+
+    #[test]
+    fn keys_with_prefix_list_the_accounts() {
+        let pairs = [
+            (Key::Account([1u8; 20]), Value::Int32(1)),
+            (Key::Account([2u8; 20]), Value::Int32(2)),
+            (Key::Hash([1u8; 32]), Value::Int32(3)),
+        ];
+        let (global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let engine_state = EngineState::new(global_state);
+
+        let accounts = engine_state
+            .keys_with_prefix(root_hash, &[ACCOUNT_ID])
+            .expect("Listing should not fail.")
+            .expect("The state should exist.");
+        assert_eq!(accounts, pairs[..2].to_vec());
+        let missing = Blake2bHash::new(&[9u8]);
+        assert!(engine_state
+            .keys_with_prefix(missing, &[ACCOUNT_ID])
+            .expect("Listing should not fail.")
+            .is_none());
+    }
+
+    #[test]
+    fn genesis_creates_the_system_account_which_cannot_deploy() {
+        let (engine_state, post_state_hash) = commit_genesis(&genesis_config(Vec::new()));
+        let mut tc = engine_state
+            .tracking_copy(post_state_hash)
+            .expect("Checkout should not fail.")
+            .expect("Post state hash should exist.");
+        let account = match tc.read(Key::Account(SYSTEM_ACCOUNT)) {
+            Ok(Some(Value::Account(account))) => account,
+            other => panic!("Expected the system account, got {:?}", other),
+        };
+        assert_eq!(mint::balance(&mut tc, account.purse_id()).unwrap(), U512::zero());
+        // Even the key associated with it doesn't authorize deploys.
+        let keys = [account.public_key()];
+        assert_matches!(
+            engine_state.validate_authorization_keys(SYSTEM_ACCOUNT, &keys, &mut tc),
+            Err(Error::ExecError(execution::Error::SystemContext))
+        );
+    }
+
+    #[test]
+    fn steps_bond_the_rewards_of_the_era_and_seat_the_next_validators() {
+        let validator = PublicKey::new([3u8; 32]);
+        let accounts = vec![GenesisAccount::new(validator, U512::from(100), U512::from(50))];
+        let genesis = genesis_config(accounts);
+        let rewards_purse = PurseId::new([9u8; 32], AccessRights::READ_ADD_WRITE);
+        let config = EngineConfig::new().with_rewards_purse(rewards_purse);
+        let (engine_state, genesis_hash) = EngineState::in_memory_genesis(&genesis, config)
+            .expect("Genesis should be committed.");
+        // Fees collected during the era, which are part of the total supply.
+        let mut tc = engine_state
+            .tracking_copy(genesis_hash)
+            .expect("Checkout should not fail.")
+            .expect("Genesis hash should exist.");
+        mint::create_purse(&mut tc, rewards_purse.addr());
+        mint::mint(&mut tc, rewards_purse, U512::from(20)).expect("Fees should be minted.");
+        let fees_hash = match engine_state.apply_effect(genesis_hash, tc.effect().1) {
+            Ok(CommitResult::Success(hash)) => hash,
+            _ => panic!("Fees should be committed"),
+        };
+
+        let result = engine_state
+            .step(fees_hash, &EraEndData::default())
+            .expect("Step should be committed.");
+        assert_eq!(result.totals.rewards, U512::from(20));
+        let stakes = engine_state
+            .bonded_validators(result.post_state_hash)
+            .expect("Stakes should be read.")
+            .expect("Post state hash should exist.");
+        assert_eq!(stakes.get(&validator), Some(&U512::from(70)));
+        // Genesis seated the validator in the first two eras, and the auction
+        // run by the step in the third one.
+        let era_validators = engine_state
+            .era_validators(result.post_state_hash)
+            .expect("Era validators should be read.")
+            .expect("Post state hash should exist.");
+        assert_eq!(era_validators.keys().cloned().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(era_validators[&1].get(&validator), Some(&U512::from(50)));
+        assert_eq!(era_validators[&2].get(&validator), Some(&U512::from(70)));
+        assert_matches!(
+            engine_state.step([1u8; 32].into(), &EraEndData::default()),
+            Err(Error::StepError(StepError::RootNotFound(_)))
+        );
+    }
+
+    #[test]
+    fn genesis_rejects_duplicate_accounts() {
+        let account = GenesisAccount::new(PublicKey::new([3u8; 32]), U512::from(1), U512::zero());
+        let genesis = genesis_config(vec![account.clone(), account]);
+        let root_hash: Blake2bHash = [0u8; 32].into();
+        let engine_state = EngineState::new(InMemHist::new(&root_hash));
+        assert_matches!(
+            engine_state.commit_genesis(root_hash, &genesis),
+            Err(Error::GenesisError(_))
+        );
     }
 }
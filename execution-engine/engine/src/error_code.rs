@@ -0,0 +1,115 @@
+//! Stable codes of the kinds of errors deploys fail with.
+//!
+//! Errors of the engine carry whatever detail is at hand where they happen,
+//! which changes as the engine does. Clients get the kind of the error as one
+//! of these codes instead, so that they can tell an out-of-gas deploy from a
+//! reverted one without parsing messages.
+
+use std::convert::TryFrom;
+
+use common::bytesrepr::{self, FromBytes, ToBytes};
+
+/// Kind of the error a deploy failed with. Discriminants are sent to clients
+/// and must not change.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The Wasm module of the deploy couldn't be deserialized or instrumented.
+    Preprocessing = 1,
+    /// An import or an export of the module couldn't be resolved.
+    Resolver = 2,
+    /// The interpreter trapped while running the code of the deploy.
+    Trap = 3,
+    /// The contract reverted with a status.
+    Revert = 4,
+    OutOfGas = 5,
+    /// Global state couldn't be read or a value read from it couldn't be
+    /// deserialized.
+    Storage = 6,
+    /// A value had another type than the one it was used as.
+    TypeMismatch = 7,
+    /// A key or a named key wasn't found.
+    KeyNotFound = 8,
+    /// The deploy isn't authorized by keys of its account.
+    Authorization = 9,
+    /// The payment code failed or didn't pay enough.
+    Payment = 10,
+    /// The TTL or the dependencies of the deploy don't allow running it.
+    Precondition = 11,
+    /// A host function rejected what the contract asked for.
+    Execution = 12,
+    /// An error which deploys can't cause.
+    Internal = 13,
+    /// The body hash of the deploy isn't the hash of its code.
+    InvalidDeployHash = 14,
+    /// An approval of the deploy has an invalid signature, or one of its
+    /// authorization keys didn't approve it.
+    InvalidSignature = 15,
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> u32 {
+        code as u32
+    }
+}
+
+impl TryFrom<u32> for ErrorCode {
+    type Error = ();
+
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(ErrorCode::Preprocessing),
+            2 => Ok(ErrorCode::Resolver),
+            3 => Ok(ErrorCode::Trap),
+            4 => Ok(ErrorCode::Revert),
+            5 => Ok(ErrorCode::OutOfGas),
+            6 => Ok(ErrorCode::Storage),
+            7 => Ok(ErrorCode::TypeMismatch),
+            8 => Ok(ErrorCode::KeyNotFound),
+            9 => Ok(ErrorCode::Authorization),
+            10 => Ok(ErrorCode::Payment),
+            11 => Ok(ErrorCode::Precondition),
+            12 => Ok(ErrorCode::Execution),
+            13 => Ok(ErrorCode::Internal),
+            14 => Ok(ErrorCode::InvalidDeployHash),
+            15 => Ok(ErrorCode::InvalidSignature),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToBytes for ErrorCode {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        u32::from(*self).to_bytes()
+    }
+}
+
+impl FromBytes for ErrorCode {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (code, rem): (u32, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let code = ErrorCode::try_from(code).map_err(|()| bytesrepr::Error::FormattingError)?;
+        Ok((code, rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use common::bytesrepr::{deserialize, ToBytes};
+
+    use super::ErrorCode;
+
+    #[test]
+    fn codes_roundtrip() {
+        for code in 1..=15 {
+            let error_code = ErrorCode::try_from(code).expect("Code should be known.");
+            assert_eq!(u32::from(error_code), code);
+            let bytes = error_code.to_bytes().unwrap();
+            assert_eq!(deserialize::<ErrorCode>(&bytes), Ok(error_code));
+        }
+        assert_eq!(ErrorCode::try_from(0), Err(()));
+        assert_eq!(ErrorCode::try_from(16), Err(()));
+        assert!(deserialize::<ErrorCode>(&16u32.to_bytes().unwrap()).is_err());
+    }
+}
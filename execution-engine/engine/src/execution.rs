@@ -2,12 +2,41 @@ extern crate blake2;
 
 use self::blake2::digest::{Input, VariableOutput};
 use self::blake2::VarBlake2b;
-use common::bytesrepr::{deserialize, Error as BytesReprError, ToBytes};
-use common::key::{AccessRights, Key};
-use common::value::{Account, Value};
-use storage::global_state::{ExecutionEffect, StateReader};
+use common::bytesrepr::{
+    deserialize, vec_from_bytes, vec_to_bytes, Error as BytesReprError, FromBytes, ToBytes, N32,
+};
+use common::crypto::{
+    ED25519_SIGNATURE_LENGTH, SECP256K1_PUBLIC_KEY_LENGTH, SECP256K1_SIGNATURE_LENGTH,
+};
+use common::key::{AccessError, AccessRights, Key};
+use common::phase::Phase;
+use common::auction::AuctionError;
+use common::pos::PosError;
+use common::protocol_version::ProtocolVersion;
+use common::runtime_args::RuntimeArgs;
+use common::system_contracts::SystemContractId;
+use common::value::account::{
+    ActionType, KeyLimits, KeyManagementError, PublicKey, PurseId, TransferError, TransferredTo,
+    Weight,
+};
+use common::value::abi::{Abi, EntryPoint, MAX_ABI_SIZE};
+use common::value::contract::PROTOCOL_VERSION;
+use common::value::contract_package::VersionSelector;
+use common::value::{Account, Contract, ContractPackage, Value, U512};
+use engine::{EngineConfig, DEFAULT_MAX_CALL_DEPTH};
+use error_code::ErrorCode;
+use journal::JournalEntry;
+use storage::global_state::stored_value::StoredValue;
+use storage::global_state::{Event, ExecutionEffect, StateReader};
 use storage::transform::TypeMismatch;
+use auction;
+use mint;
+use pos;
+use system::is_system_context;
 use trackingcopy::{AddResult, TrackingCopy};
+use transfer::{self, Transfer};
+use vm::host_function_costs::{HostFunctionCost, HostFunctionCosts};
+use wasm_prep::PreprocessingError;
 use wasmi::memory_units::Pages;
 use wasmi::{
     Error as InterpreterError, Externals, FuncInstance, FuncRef, HostError, ImportsBuilder,
@@ -15,15 +44,17 @@ use wasmi::{
     RuntimeArgs, RuntimeValue, Signature, Trap, ValueType,
 };
 
+use address_generator::AddressGenerator;
 use argsparser::Args;
 use itertools::Itertools;
 use parity_wasm::elements::{Error as ParityWasmError, Module};
-use rand::{RngCore, SeedableRng};
-use rand_chacha::ChaChaRng;
+use shared::gas::Gas;
+use shared::newtypes::Blake2bHash;
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
-use std::iter::IntoIterator;
+use std::iter::{self, IntoIterator};
 
 #[derive(Debug)]
 pub enum Error {
@@ -36,13 +67,36 @@ pub enum Error {
     InvalidAccess { required: AccessRights },
     ForgedReference(Key),
     NoImportedMemory,
-    ArgIndexOutOfBounds(usize),
     URefNotFound(String),
     FunctionNotFound(String),
     ParityWasm(ParityWasmError),
+    WasmOptimizer,
     GasLimit,
     Ret(Vec<Key>),
-    Rng(rand::Error),
+    InsufficientFunds,
+    KeyManagementThreshold,
+    InvalidActionType(u32),
+    IncompatibleProtocolVersion { expected: u64, actual: u64 },
+    GroupNotFound(String),
+    AbiTooLarge(usize),
+    LockedContractPackage(Key),
+    UnauthorizedCall(Key),
+    ContractVersionNotFound(Key, VersionSelector),
+    InvalidContract(PreprocessingError),
+    DisabledContractVersion(Key, u32),
+    UpgradableContractVersion(Key),
+    Revert(u32),
+    EventTooLarge(usize),
+    TooManyEvents,
+    SystemContractNotFound(u32),
+    CalleeGasLimit,
+    HostBufferTooSmall { required: usize, available: usize },
+    InvalidAccessRights(u32),
+    CallDepthExceeded(usize),
+    InvalidPhase(Phase),
+    SystemContext,
+    Pos(PosError),
+    Auction(AuctionError),
     Unreachable,
 }
 
@@ -52,6 +106,68 @@ impl fmt::Display for Error {
     }
 }
 
+impl Error {
+    /// Stable code of the kind of the error. Traps raised by host functions
+    /// get the code of the error the host function failed with.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Interpreter(error) => {
+                let host_error = error.as_host_error();
+                if let Some(error) = host_error.and_then(|error| error.downcast_ref::<Error>()) {
+                    return error.code();
+                }
+                if host_error
+                    .and_then(|error| error.downcast_ref::<storage::error::Error>())
+                    .is_some()
+                {
+                    return ErrorCode::Storage;
+                }
+                match error {
+                    InterpreterError::Instantiation(_) => ErrorCode::Resolver,
+                    _ => ErrorCode::Trap,
+                }
+            }
+            Error::ParityWasm(_)
+            | Error::WasmOptimizer
+            | Error::InvalidContract(_)
+            | Error::NoImportedMemory => ErrorCode::Preprocessing,
+            Error::FunctionNotFound(_) => ErrorCode::Resolver,
+            Error::Revert(_) => ErrorCode::Revert,
+            Error::GasLimit => ErrorCode::OutOfGas,
+            Error::Storage(_) | Error::BytesRepr(_) => ErrorCode::Storage,
+            Error::TypeMismatch(_) => ErrorCode::TypeMismatch,
+            Error::KeyNotFound(_) | Error::URefNotFound(_) => ErrorCode::KeyNotFound,
+            Error::SystemContext => ErrorCode::Authorization,
+            Error::Overflow
+            | Error::InvalidAccess { .. }
+            | Error::ForgedReference(_)
+            | Error::Ret(_)
+            | Error::InsufficientFunds
+            | Error::KeyManagementThreshold
+            | Error::InvalidActionType(_)
+            | Error::IncompatibleProtocolVersion { .. }
+            | Error::GroupNotFound(_)
+            | Error::AbiTooLarge(_)
+            | Error::LockedContractPackage(_)
+            | Error::UnauthorizedCall(_)
+            | Error::ContractVersionNotFound(..)
+            | Error::DisabledContractVersion(..)
+            | Error::UpgradableContractVersion(_)
+            | Error::EventTooLarge(_)
+            | Error::TooManyEvents
+            | Error::SystemContractNotFound(_)
+            | Error::CalleeGasLimit
+            | Error::HostBufferTooSmall { .. }
+            | Error::InvalidAccessRights(_)
+            | Error::CallDepthExceeded(_)
+            | Error::InvalidPhase(_)
+            | Error::Pos(_)
+            | Error::Auction(_) => ErrorCode::Execution,
+            Error::Unreachable => ErrorCode::Internal,
+        }
+    }
+}
+
 impl From<ParityWasmError> for Error {
     fn from(e: ParityWasmError) -> Self {
         Error::ParityWasm(e)
@@ -60,7 +176,18 @@ impl From<ParityWasmError> for Error {
 
 impl From<InterpreterError> for Error {
     fn from(e: InterpreterError) -> Self {
-        Error::Interpreter(e)
+        // A contract reverting deep down in a chain of calls is reported with its
+        // status rather than as a trap nested in the traps of its callers.
+        // The same goes for running out of gas.
+        let nested_error = match e
+            .as_host_error()
+            .and_then(|host_error| host_error.downcast_ref::<Error>())
+        {
+            Some(Error::Revert(status)) => Some(Error::Revert(*status)),
+            Some(Error::GasLimit) => Some(Error::GasLimit),
+            _ => None,
+        };
+        nested_error.unwrap_or_else(|| Error::Interpreter(e))
     }
 }
 
@@ -96,7 +223,16 @@ pub struct RuntimeContext<'a> {
     // Key pointing to the entity we are currently running
     //(could point at an account or contract in the global state)
     base_key: Key,
-    gas_limit: u64,
+    // Keys which signed the deploy
+    authorization_keys: BTreeSet<PublicKey>,
+    gas_limit: Gas,
+    // Stage of the deploy execution the code runs in
+    phase: Phase,
+    // Hash of the deploy being executed
+    deploy_hash: [u8; 32],
+    protocol_version: ProtocolVersion,
+    // Height of the block the deploy is executed in
+    block_height: u64,
 }
 
 impl<'a> RuntimeContext<'a> {
@@ -104,17 +240,62 @@ impl<'a> RuntimeContext<'a> {
         uref_lookup: &'a mut BTreeMap<String, Key>,
         account: &'a Account,
         base_key: Key,
-        gas_limit: u64,
+        authorization_keys: BTreeSet<PublicKey>,
+        gas_limit: Gas,
+        phase: Phase,
     ) -> Self {
         RuntimeContext {
             uref_lookup,
             known_urefs: HashMap::new(),
             account,
             base_key,
+            authorization_keys,
             gas_limit,
+            phase,
+            deploy_hash: [0u8; 32],
+            protocol_version: ProtocolVersion::new(PROTOCOL_VERSION),
+            block_height: 0,
         }
     }
 
+    /// Sets the hash of the deploy the context runs in.
+    pub fn with_deploy_hash(mut self, deploy_hash: [u8; 32]) -> Self {
+        self.deploy_hash = deploy_hash;
+        self
+    }
+
+    /// Sets the height of the block the context runs in.
+    pub fn with_block_height(mut self, block_height: u64) -> Self {
+        self.block_height = block_height;
+        self
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn deploy_hash(&self) -> [u8; 32] {
+        self.deploy_hash
+    }
+
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    pub fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
+    /// Checks whether keys that signed the deploy carry enough weight
+    /// to manage keys and purses of the deploying account.
+    pub fn can_manage_keys(&self) -> bool {
+        let total_weight = self
+            .account
+            .associated_keys()
+            .total_weight(&self.authorization_keys);
+        total_weight >= *self.account.action_thresholds().key_management()
+    }
+
     pub fn insert_named_uref(&mut self, name: String, key: Key) {
         self.insert_uref(key);
         self.uref_lookup.insert(name, key);
@@ -158,6 +339,10 @@ impl<'a> RuntimeContext<'a> {
                 .values()
                 .try_for_each(|key| self.validate_key(key))
                 .map(|_| Value::Contract(contract)),
+            Value::ContractPackage(package) => iter::once(&package.access_key())
+                .chain(package.groups().values().flatten())
+                .try_for_each(|key| self.validate_key(key))
+                .map(|_| Value::ContractPackage(package)),
         }
     }
 
@@ -167,20 +352,99 @@ impl<'a> RuntimeContext<'a> {
     fn validate_key(&self, key: &Key) -> Result<(), Error> {
         match key {
             Key::URef(raw_addr, new_rights) => {
-                self.known_urefs
+                // A known address alone isn't enough: the rights it's presented
+                // with have to be covered by rights granted to the context, or
+                // it could claim e.g. write rights to a URef it can only read.
+                let is_granted = self
+                    .known_urefs
                     .get(raw_addr) // Check if we `key` is known
                     .map(|known_rights| {
                         known_rights
                             .iter()
                             .any(|right| *right & *new_rights == *new_rights)
                     }) // are we allowed to use it this way?
-                    .map(|_| ()) // at this point we know it's valid to use `key`
-                    .ok_or_else(|| Error::ForgedReference(*key)) // otherwise `key` is forged
+                    .unwrap_or(false);
+                if is_granted {
+                    Ok(())
+                } else {
+                    Err(Error::ForgedReference(*key)) // otherwise `key` is forged
+                }
             }
             _ => Ok(()),
         }
     }
 
+    // Tests whether reading from the `key` is valid.
+    // For Accounts it's valid to read when the operation is done on the current context's key.
+    // For Contracts it's always valid.
+    // For URefs it's valid if the access rights of the URef allow for reading.
+    pub fn is_readable(&self, key: &Key) -> bool {
+        match key {
+            Key::Account(_) => &self.base_key == key,
+            Key::Hash(_) => true,
+            Key::URef(_, rights) => rights.is_readable(),
+            // Local keys are only accessed through `read_local` and `write_local`,
+            // or the dictionary host functions.
+            Key::Local(_) => false,
+            // Records of executed deploys and of transfers are public.
+            Key::DeployInfo(_) | Key::Transfer(_) => true,
+        }
+    }
+
+    /// Tests whether addition to `key` is valid.
+    /// Addition to account key is valid iff it is being made from the context of the account.
+    /// Addition to contract key is valid iff it is being made from the context of the contract.
+    /// Additions to unforgeable key is valid as long as key itself is addable
+    pub fn is_addable(&self, key: &Key) -> bool {
+        match key {
+            Key::Account(_) | Key::Hash(_) => &self.base_key == key,
+            Key::URef(_, rights) => rights.is_addable(),
+            Key::Local(_) | Key::DeployInfo(_) | Key::Transfer(_) => false,
+        }
+    }
+
+    // Test whether writing to `key` is valid.
+    // For Accounts and Hashes it's always invalid.
+    // For URefs it depends on the access rights that uref has.
+    pub fn is_writeable(&self, key: &Key) -> bool {
+        match key {
+            Key::Account(_)
+            | Key::Hash(_)
+            | Key::Local(_)
+            | Key::DeployInfo(_)
+            | Key::Transfer(_) => false,
+            Key::URef(_, rights) => rights.is_writeable(),
+        }
+    }
+
+    /// Checks that the current context can use `key` with the `required`
+    /// rights: a URef has to be known to the context with at least the rights
+    /// it carries, and the key has to allow reading, writing or adding to it
+    /// as required. Every access of a host function to the global state on
+    /// behalf of a contract goes through this check.
+    pub fn validate_access(&self, key: &Key, required: AccessRights) -> Result<(), Error> {
+        self.validate_key(key)?;
+        if (required.is_readable() && !self.is_readable(key))
+            || (required.is_writeable() && !self.is_writeable(key))
+            || (required.is_addable() && !self.is_addable(key))
+        {
+            return Err(Error::InvalidAccess { required });
+        }
+        Ok(())
+    }
+
+    pub fn validate_readable(&self, key: &Key) -> Result<(), Error> {
+        self.validate_access(key, AccessRights::READ)
+    }
+
+    pub fn validate_writeable(&self, key: &Key) -> Result<(), Error> {
+        self.validate_access(key, AccessRights::WRITE)
+    }
+
+    pub fn validate_addable(&self, key: &Key) -> Result<(), Error> {
+        self.validate_access(key, AccessRights::ADD)
+    }
+
     pub fn deserialize_key(&self, bytes: &[u8]) -> Result<Key, Error> {
         let key: Key = deserialize(bytes)?;
         self.validate_key(&key).map(|_| key)
@@ -200,10 +464,25 @@ pub struct Runtime<'a, R: StateReader<Key, Value>> {
     module: Module,
     result: Vec<u8>,
     host_buf: Vec<u8>,
-    fn_store_id: u32,
-    gas_counter: u64,
+    gas_counter: Gas,
     context: RuntimeContext<'a>,
-    rng: ChaChaRng,
+    address_generator: AddressGenerator,
+    transfer_address_generator: AddressGenerator,
+    // Chain the bytes of `random_bytes` are drawn from, across all contract calls.
+    random_generator: AddressGenerator,
+    // Events emitted by the deploy so far, handed over to contracts it calls.
+    events: Vec<Event>,
+    contract_debug: bool,
+    named_args: RuntimeArgs,
+    system_contracts: BTreeMap<SystemContractId, Key>,
+    host_function_costs: HostFunctionCosts,
+    // Cost of the host function being called, charged for the bytes it reads.
+    host_function_cost: HostFunctionCost,
+    // Number of contract calls the current code is nested in.
+    call_depth: usize,
+    max_call_depth: usize,
+    pos_delays: pos::QueueDelays,
+    key_limits: KeyLimits,
 }
 
 /// Rename function called `name` in the `module` to `call`.
@@ -226,18 +505,18 @@ impl<'a, R: StateReader<Key, Value>> Runtime<'a, R>
 where
     R::Error: Into<Error>,
 {
-    #[allow(clippy::too_many_arguments)]
     pub fn new(
         args: Vec<Vec<u8>>,
         memory: MemoryRef,
         state: &'a mut TrackingCopy<R>,
         module: Module,
-        account_addr: [u8; 20],
-        nonce: u64,
-        timestamp: u64,
         context: RuntimeContext<'a>,
     ) -> Self {
-        let rng = create_rng(&account_addr, timestamp, nonce);
+        let address_generator = AddressGenerator::new(context.deploy_hash(), context.phase());
+        let transfer_address_generator =
+            AddressGenerator::for_transfers(context.deploy_hash(), context.phase());
+        let random_generator =
+            AddressGenerator::for_random_bytes(context.deploy_hash(), context.phase());
         Runtime {
             args,
             memory,
@@ -245,10 +524,21 @@ where
             module,
             result: Vec::new(),
             host_buf: Vec::new(),
-            fn_store_id: 0,
-            gas_counter: 0,
+            gas_counter: Gas::default(),
             context,
-            rng,
+            address_generator,
+            transfer_address_generator,
+            random_generator,
+            events: Vec::new(),
+            contract_debug: false,
+            named_args: RuntimeArgs::new(),
+            system_contracts: BTreeMap::new(),
+            host_function_costs: HostFunctionCosts::default(),
+            host_function_cost: HostFunctionCost::default(),
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            pos_delays: pos::QueueDelays::default(),
+            key_limits: KeyLimits::default(),
         }
     }
 
@@ -258,17 +548,36 @@ where
     /// Intuition about the return value sense is to aswer the question 'are we allowed to continue?'
     fn charge_gas(&mut self, amount: u64) -> bool {
         let prev = self.gas_counter;
-        match prev.checked_add(amount) {
+        match prev.checked_add(Gas::from(amount)) {
             // gas charge overflow protection
             None => false,
             Some(val) if val > self.context.gas_limit => false,
             Some(val) => {
                 self.gas_counter = val;
+                self.state.record(|| JournalEntry::Gas(Gas::from(amount)));
                 true
             }
         }
     }
 
+    /// Gas the current context can still spend before hitting its limit. Gas
+    /// is charged per block of instructions, so this is an approximation.
+    pub fn gas_remaining(&self) -> Gas {
+        self.context.gas_limit.saturating_sub(self.gas_counter)
+    }
+
+    /// Height of the block the deploy is executed in. It's the same every
+    /// time the deploy is executed in that block.
+    pub fn get_block_height(&self) -> u64 {
+        self.context.block_height()
+    }
+
+    /// Number of contract calls the current code is nested in. Session code
+    /// runs at depth 0.
+    pub fn call_stack_depth(&self) -> usize {
+        self.call_depth
+    }
+
     fn gas(&mut self, amount: u64) -> Result<(), Trap> {
         if self.charge_gas(amount) {
             Ok(())
@@ -277,40 +586,83 @@ where
         }
     }
 
-    fn effect(&self) -> ExecutionEffect {
-        self.state.effect()
+    pub fn effect(&self) -> ExecutionEffect {
+        let mut effect = self.state.effect();
+        effect.2 = self.events.clone();
+        effect
+    }
+
+    /// Reads `size` bytes of arguments at `ptr` in the Wasm memory, charging
+    /// the host function being called for each of them.
+    fn bytes_from_mem(&mut self, ptr: u32, size: usize) -> Result<Vec<u8>, Error> {
+        if !self.charge_gas(self.host_function_cost.bytes_cost(size)) {
+            return Err(Error::GasLimit);
+        }
+        self.memory.get(ptr, size).map_err(Into::into)
     }
 
     fn key_from_mem(&mut self, key_ptr: u32, key_size: u32) -> Result<Key, Error> {
-        let bytes = self.memory.get(key_ptr, key_size as usize)?;
+        let bytes = self.bytes_from_mem(key_ptr, key_size as usize)?;
         self.context.deserialize_key(&bytes)
     }
 
+    /// Checks whether the key found in the Wasm memory can be used by the
+    /// current context, i.e. that it isn't a URef which was forged or given
+    /// more rights than were granted.
+    pub fn is_valid(&mut self, key_ptr: u32, key_size: u32) -> Result<bool, Trap> {
+        let bytes = self.bytes_from_mem(key_ptr, key_size as usize)?;
+        let key: Key = deserialize(&bytes).map_err(Error::BytesRepr)?;
+        Ok(self.context.validate_key(&key).is_ok())
+    }
+
+    /// Checks whether the key found in the Wasm memory can be used by the
+    /// current context with the `required` rights: it must not be forged and
+    /// reading, writing or adding to it must be allowed as requested. Returns
+    /// the code of the `AccessError`, or 0 if the key can be used.
+    pub fn validate_key(
+        &mut self,
+        key_ptr: u32,
+        key_size: u32,
+        required: u32,
+    ) -> Result<i32, Trap> {
+        let bytes = self.bytes_from_mem(key_ptr, key_size as usize)?;
+        let key: Key = deserialize(&bytes).map_err(Error::BytesRepr)?;
+        let required = u8::try_from(required)
+            .ok()
+            .and_then(AccessRights::from_bits)
+            .ok_or(Error::InvalidAccessRights(required))?;
+        let result = if self.context.validate_key(&key).is_err() {
+            Err(AccessError::ForgedReference)
+        } else if self.context.validate_access(&key, required).is_err() {
+            Err(AccessError::InsufficientRights)
+        } else {
+            Ok(())
+        };
+        Ok(result.err().map_or(0, i32::from))
+    }
+
     fn value_from_mem(&mut self, value_ptr: u32, value_size: u32) -> Result<Value, Error> {
-        let bytes = self.memory.get(value_ptr, value_size as usize)?;
+        let bytes = self.bytes_from_mem(value_ptr, value_size as usize)?;
         deserialize(&bytes)
             .map_err(Into::into)
+            .and_then(validate_abi_size)
             .and_then(|v| self.context.validate_keys(v))
     }
 
+    fn t_from_mem<T: FromBytes>(&mut self, ptr: u32, size: u32) -> Result<T, Error> {
+        let bytes = self.bytes_from_mem(ptr, size as usize)?;
+        deserialize(&bytes).map_err(Into::into)
+    }
+
     fn string_from_mem(&mut self, ptr: u32, size: u32) -> Result<String, Trap> {
-        let bytes = self
-            .memory
-            .get(ptr, size as usize)
-            .map_err(Error::Interpreter)?;
+        let bytes = self.bytes_from_mem(ptr, size as usize)?;
         deserialize(&bytes).map_err(|e| Error::BytesRepr(e).into())
     }
 
     fn get_function_by_name(&mut self, name_ptr: u32, name_size: u32) -> Result<Vec<u8>, Trap> {
         let name = self.string_from_mem(name_ptr, name_size)?;
 
-        let has_name: bool = self
-            .module
-            .export_section()
-            .and_then(|es| es.entries().iter().find(|e| e.field() == name))
-            .is_some();
-
-        if has_name {
+        if has_export(&self.module, &name) {
             let mut module = self.module.clone();
             // We only want the function exported under `name` to be callable;
             //`optimize` removes all code that is not reachable from the exports
@@ -336,16 +688,62 @@ where
         Ok((key, value))
     }
 
+    /// Reads the value under `key` if the current context can read it.
+    fn read_gs(&mut self, key: Key) -> Result<Option<Value>, Error> {
+        self.context.validate_readable(&key)?;
+        self.state.read(key).map_err(Into::into)
+    }
+
+    /// Reads the value under `key` like `read_gs`, leaving it in the bytes
+    /// it's stored as where it can.
+    fn read_gs_stored(&mut self, key: Key) -> Result<Option<StoredValue<Value>>, Error> {
+        self.context.validate_readable(&key)?;
+        self.state.read_stored(key).map_err(Into::into)
+    }
+
+    /// Writes `value` under `key` if the current context can write to it.
+    fn write_gs(&mut self, key: Key, value: Value) -> Result<(), Error> {
+        self.context.validate_writeable(&key)?;
+        self.state.write(key, value);
+        Ok(())
+    }
+
+    /// Adds `value` to the value under `key` if the current context can add
+    /// to it.
+    fn add_gs(&mut self, key: Key, value: Value) -> Result<(), Error> {
+        self.context.validate_addable(&key)?;
+        match self.state.add(key, value).map_err(Into::into)? {
+            AddResult::Success => Ok(()),
+            AddResult::KeyNotFound(key) => Err(Error::KeyNotFound(key)),
+            AddResult::TypeMismatch(type_mismatch) => Err(Error::TypeMismatch(type_mismatch)),
+            AddResult::Overflow => Err(Error::Overflow),
+        }
+    }
+
     /// Load the i-th argument invoked as part of a `sub_call` into
     /// the runtime buffer so that a subsequent `get_arg` can return it
-    /// to the caller.
-    pub fn load_arg(&mut self, i: usize) -> Result<usize, Trap> {
-        if i < self.args.len() {
-            self.host_buf = self.args[i].clone();
-            Ok(self.host_buf.len())
-        } else {
-            Err(Error::ArgIndexOutOfBounds(i).into())
-        }
+    /// to the caller. Returns the size of the argument, or `None` if fewer
+    /// arguments were passed.
+    pub fn load_arg(&mut self, i: usize) -> Option<usize> {
+        let arg = self.args.get(i)?.clone();
+        self.host_buf = arg;
+        Some(self.host_buf.len())
+    }
+
+    /// Like `load_arg`, for the argument passed under the name found in the
+    /// Wasm memory.
+    pub fn load_named_arg(
+        &mut self,
+        name_ptr: u32,
+        name_size: u32,
+    ) -> Result<Option<usize>, Trap> {
+        let name = self.string_from_mem(name_ptr, name_size)?;
+        let arg = match self.named_args.get(&name) {
+            Some(arg) => arg.to_vec(),
+            None => return Ok(None),
+        };
+        self.host_buf = arg;
+        Ok(Some(self.host_buf.len()))
     }
 
     /// Load the uref known by the given name into the Wasm memory
@@ -386,13 +784,192 @@ where
         self.add_transforms(base_key, Value::NamedKey(name, key))
     }
 
+    /// Removes the key known under `name` from the named keys of the current
+    /// context and from the account or contract the context belongs to. Does
+    /// nothing if there is no such key.
+    pub fn remove_uref(&mut self, name_ptr: u32, name_size: u32) -> Result<(), Trap> {
+        let name = self.string_from_mem(name_ptr, name_size)?;
+        let base_key = self.context.base_key;
+        let value = match self.state.read(base_key).map_err(Into::into)? {
+            Some(Value::Account(mut account)) => {
+                if account.remove_uref(&name).is_none() {
+                    return Ok(());
+                }
+                Value::Account(account)
+            }
+            Some(Value::Contract(mut contract)) => {
+                if contract.remove_uref(&name).is_none() {
+                    return Ok(());
+                }
+                Value::Contract(contract)
+            }
+            Some(other) => {
+                return Err(Error::TypeMismatch(TypeMismatch::new(
+                    "Account or Contract".to_string(),
+                    other.type_string(),
+                ))
+                .into())
+            }
+            None => return Err(Error::KeyNotFound(base_key).into()),
+        };
+        self.context.uref_lookup.remove(&name);
+        self.state.write(base_key, value);
+        Ok(())
+    }
+
+    /// Serializes up to `limit` named keys of the current context, starting
+    /// from the `offset`-th one in the order of their names, into the host
+    /// buffer. At most `MAX_NAMED_KEYS_PAGE_SIZE` keys are returned at once.
+    pub fn list_named_keys(&mut self, offset: u32, limit: u32) -> Result<usize, Trap> {
+        let limit = std::cmp::min(limit as usize, MAX_NAMED_KEYS_PAGE_SIZE);
+        let page: BTreeMap<String, Key> = self
+            .context
+            .uref_lookup
+            .iter()
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(name, key)| (name.clone(), *key))
+            .collect();
+        self.host_buf = page.to_bytes().map_err(Error::BytesRepr)?;
+        Ok(self.host_buf.len())
+    }
+
+    /// Reads the contract which is currently being executed. Fails with
+    /// `TypeMismatch` when running session code, which has no contract of its
+    /// own.
+    fn read_executing_contract(&mut self) -> Result<Contract, Error> {
+        let base_key = self.context.base_key;
+        match self.state.read(base_key).map_err(Into::into)? {
+            Some(Value::Contract(contract)) => Ok(contract),
+            Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+                "Contract".to_string(),
+                other.type_string(),
+            ))),
+            None => Err(Error::KeyNotFound(base_key)),
+        }
+    }
+
+    /// Stores `key` under `name` in the named keys of the executing contract,
+    /// replacing any key previously known under that name. The change is
+    /// written back to the stored contract, so it persists across calls.
+    pub fn put_key(
+        &mut self,
+        name_ptr: u32,
+        name_size: u32,
+        key_ptr: u32,
+        key_size: u32,
+    ) -> Result<(), Trap> {
+        let name = self.string_from_mem(name_ptr, name_size)?;
+        let key = self.key_from_mem(key_ptr, key_size)?;
+        let mut contract = self.read_executing_contract()?;
+        let mut new_urefs: BTreeMap<String, Key> = iter::once((name.clone(), key)).collect();
+        contract.insert_urefs(&mut new_urefs);
+        self.context.insert_named_uref(name, key);
+        let base_key = self.context.base_key;
+        self.state.write(base_key, Value::Contract(contract));
+        Ok(())
+    }
+
+    /// Removes the key known under `name` from the named keys of the executing
+    /// contract. Does nothing if there is no such key.
+    pub fn remove_key(&mut self, name_ptr: u32, name_size: u32) -> Result<(), Trap> {
+        let name = self.string_from_mem(name_ptr, name_size)?;
+        let mut contract = self.read_executing_contract()?;
+        if contract.remove_uref(&name).is_some() {
+            self.context.uref_lookup.remove(&name);
+            let base_key = self.context.base_key;
+            self.state.write(base_key, Value::Contract(contract));
+        }
+        Ok(())
+    }
+
+    /// Looks `name` up in the named keys of the executing contract and writes
+    /// the key found at `dest_ptr` in the Wasm memory. Returns the size of the
+    /// serialized key, or 0 if there is no such key.
+    pub fn get_key(&mut self, name_ptr: u32, name_size: u32, dest_ptr: u32) -> Result<i32, Trap> {
+        let name = self.string_from_mem(name_ptr, name_size)?;
+        let contract = self.read_executing_contract()?;
+        match contract.urefs_lookup().get(&name) {
+            Some(key) => {
+                let key_bytes = key.to_bytes().map_err(Error::BytesRepr)?;
+                self.memory
+                    .set(dest_ptr, &key_bytes)
+                    .map_err(Error::Interpreter)?;
+                Ok(key_bytes.len() as i32)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Writes the phase of the deploy execution into the Wasm memory.
+    pub fn get_phase(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        let phase_bytes = self.context.phase().to_bytes().map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &phase_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Writes the hash of the deploy being executed into the Wasm memory.
+    pub fn get_deploy_hash(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        self.memory
+            .set(dest_ptr, &self.context.deploy_hash())
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Writes the version of the protocol the deploy runs under into the Wasm memory.
+    pub fn get_protocol_version(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        let version_bytes = self
+            .context
+            .protocol_version()
+            .to_bytes()
+            .map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &version_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Stops the execution of the module, discarding the changes made by the deploy.
+    pub fn revert(&mut self, status: u32) -> Trap {
+        Error::Revert(status).into()
+    }
+
+    /// Writes public key of the account which initiated the deploy into the Wasm memory.
+    pub fn get_caller(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        let key_bytes = self
+            .context
+            .account
+            .public_key()
+            .to_bytes()
+            .map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &key_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
     pub fn set_mem_from_buf(&mut self, dest_ptr: u32) -> Result<(), Trap> {
         self.memory
             .set(dest_ptr, &self.host_buf)
             .map_err(|e| Error::Interpreter(e).into())
     }
 
-    /// Return a some bytes from the memory and terminate the current `sub_call`.
+    /// Copies the result of the last host function which returned a size into
+    /// the Wasm memory at `dest_ptr`. Fails instead of writing past `dest_size`
+    /// bytes. Returns the number of bytes copied.
+    pub fn read_host_buffer(&mut self, dest_ptr: u32, dest_size: usize) -> Result<usize, Trap> {
+        if self.host_buf.len() > dest_size {
+            return Err(Error::HostBufferTooSmall {
+                required: self.host_buf.len(),
+                available: dest_size,
+            }
+            .into());
+        }
+        self.set_mem_from_buf(dest_ptr)?;
+        Ok(self.host_buf.len())
+    }
+
+    /// Return a value from the memory and terminate the current `sub_call`.
+    /// The returned bytes have to be exactly one serialized `Value`, in which
+    /// no key is forged.
     /// Note that the return type is `Trap`, indicating that this function will
     /// always kill the current Wasm instance.
     pub fn ret(
@@ -403,11 +980,11 @@ where
         extra_urefs_size: usize,
     ) -> Trap {
         let mem_get = self
-            .memory
-            .get(value_ptr, value_size)
-            .map_err(Error::Interpreter)
+            .bytes_from_mem(value_ptr, value_size)
             .and_then(|x| {
-                let urefs_bytes = self.memory.get(extra_urefs_ptr, extra_urefs_size)?;
+                let value: Value = deserialize(&x)?;
+                self.context.validate_keys(value)?;
+                let urefs_bytes = self.bytes_from_mem(extra_urefs_ptr, extra_urefs_size)?;
                 let urefs = self.context.deserialize_keys(&urefs_bytes)?;
                 Ok((x, urefs))
             });
@@ -432,207 +1009,687 @@ where
         extra_urefs_ptr: u32,
         extra_urefs_size: usize,
     ) -> Result<usize, Error> {
-        let key_bytes = self.memory.get(key_ptr, key_size)?;
-        let args_bytes = self.memory.get(args_ptr, args_size)?;
-        let urefs_bytes = self.memory.get(extra_urefs_ptr, extra_urefs_size)?;
-
-        let key = self.context.deserialize_key(&key_bytes)?;
-        if self.is_readable(&key) {
-            let (args, module, mut refs) = {
-                match self.state.read(key).map_err(Into::into)? {
-                    None => Err(Error::KeyNotFound(key)),
-                    Some(value) => {
-                        if let Value::Contract(contract) = value {
-                            let args: Vec<Vec<u8>> = deserialize(&args_bytes)?;
-                            let module = parity_wasm::deserialize_buffer(contract.bytes())?;
-
-                            Ok((args, module, contract.urefs_lookup().clone()))
-                        } else {
-                            Err(Error::FunctionNotFound(format!(
-                                "Value at {:?} is not a contract",
-                                key
-                            )))
-                        }
-                    }
-                }
-            }?;
-
-            let extra_urefs = self.context.deserialize_keys(&urefs_bytes)?;
-            let result = sub_call(module, args, &mut refs, key, self, extra_urefs)?;
-            self.host_buf = result;
-            Ok(self.host_buf.len())
-        } else {
-            Err(Error::InvalidAccess {
-                required: AccessRights::READ,
-            })
-        }
-    }
-
-    pub fn serialize_function(&mut self, name_ptr: u32, name_size: u32) -> Result<usize, Trap> {
-        let fn_bytes = self.get_function_by_name(name_ptr, name_size)?;
-        self.host_buf = fn_bytes;
-        Ok(self.host_buf.len())
+        self.call_contract_with_selector(
+            key_ptr,
+            key_size,
+            VersionSelector::Latest,
+            args_ptr,
+            args_size,
+            extra_urefs_ptr,
+            extra_urefs_size,
+            None,
+        )
     }
 
-    /// Tries to store a function, located in the Wasm memory, into the GlobalState
-    /// and writes back a function's hash at `hash_ptr` in the Wasm memory.
-    ///
-    /// `name_ptr` and `name_size` tell the host where to look for a function's name.
-    /// Once it knows the name it can search for this exported function in the Wasm module.
-    /// Note that functions that contract wants to store have to be marked with `export` keyword.
-    /// `urefs_ptr` and `urefs_size` describe when the additional unforgable references can be found.
-    pub fn store_function(
+    /// Like `call_contract`, but the callee can spend at most `gas_allowance`
+    /// of the gas left to the deploy. A callee running out of its allowance
+    /// fails with `CalleeGasLimit`, and its changes to the global state and
+    /// the events it emitted are discarded; the gas it used is still charged.
+    #[allow(clippy::too_many_arguments)]
+    pub fn call_contract_with_gas(
         &mut self,
-        name_ptr: u32,
-        name_size: u32,
-        urefs_ptr: u32,
-        urefs_size: u32,
-        hash_ptr: u32,
-    ) -> Result<(), Trap> {
-        let fn_bytes = self.get_function_by_name(name_ptr, name_size)?;
-        let uref_bytes = self
-            .memory
-            .get(urefs_ptr, urefs_size as usize)
-            .map_err(Error::Interpreter)?;
-        let urefs: BTreeMap<String, Key> = deserialize(&uref_bytes).map_err(Error::BytesRepr)?;
-        urefs
-            .iter()
-            .try_for_each(|(_, v)| self.context.validate_key(&v))?;
-        let contract = common::value::Contract::new(fn_bytes, urefs);
-        let new_hash = self.new_function_address()?;
-        self.state
-            .write(Key::Hash(new_hash), Value::Contract(contract));
-        self.function_address(new_hash, hash_ptr)
-    }
-
-    /// Generates new function address.
-    /// Function address is deterministic. It is a hash of public key, nonce and `fn_store_id`,
-    /// which is a counter that is being incremented after every function generation.
-    /// If function address was based only on account's public key and deploy's nonce,
-    /// then all function addresses generated within one deploy would have been the same.
-    fn new_function_address(&mut self) -> Result<[u8; 32], Error> {
-        let mut pre_hash_bytes = Vec::with_capacity(44); //32 byte pk + 8 byte nonce + 4 byte ID
-        pre_hash_bytes.extend_from_slice(self.context.account.pub_key());
-        pre_hash_bytes.append(&mut self.context.account.nonce().to_bytes()?);
-        pre_hash_bytes.append(&mut self.fn_store_id.to_bytes()?);
-
-        self.fn_store_id += 1;
-
-        let mut hasher = VarBlake2b::new(32).unwrap();
-        hasher.input(&pre_hash_bytes);
-        let mut hash_bytes = [0; 32];
-        hasher.variable_result(|hash| hash_bytes.clone_from_slice(hash));
-        Ok(hash_bytes)
-    }
-
-    /// Writes function address (`hash_bytes`) into the Wasm memory (at `dest_ptr` pointer).
-    fn function_address(&mut self, hash_bytes: [u8; 32], dest_ptr: u32) -> Result<(), Trap> {
-        self.memory
-            .set(dest_ptr, &hash_bytes)
-            .map_err(|e| Error::Interpreter(e).into())
+        key_ptr: u32,
+        key_size: usize,
+        args_ptr: u32,
+        args_size: usize,
+        extra_urefs_ptr: u32,
+        extra_urefs_size: usize,
+        gas_allowance: u64,
+    ) -> Result<usize, Error> {
+        self.call_contract_with_selector(
+            key_ptr,
+            key_size,
+            VersionSelector::Latest,
+            args_ptr,
+            args_size,
+            extra_urefs_ptr,
+            extra_urefs_size,
+            Some(gas_allowance),
+        )
     }
 
-    /// Writes value under a key (specified by their pointer and length properties from the Wasm memory).
-    pub fn write(
+    /// Like `call_contract`, but lets the caller pick the version to run
+    /// when the key points at a contract package. The serialized
+    /// `VersionSelector` is read from `selector_ptr` in the Wasm memory.
+    #[allow(clippy::too_many_arguments)]
+    pub fn call_versioned_contract(
         &mut self,
         key_ptr: u32,
-        key_size: u32,
-        value_ptr: u32,
-        value_size: u32,
-    ) -> Result<(), Trap> {
-        self.kv_from_mem(key_ptr, key_size, value_ptr, value_size)
-            .and_then(|(key, value)| {
-                if self.is_writeable(&key) {
-                    self.state.write(key, value);
-                    Ok(())
-                } else {
-                    Err(Error::InvalidAccess {
-                        required: AccessRights::WRITE,
-                    })
-                }
-            })
-            .map_err(Into::into)
+        key_size: usize,
+        selector_ptr: u32,
+        selector_size: usize,
+        args_ptr: u32,
+        args_size: usize,
+        extra_urefs_ptr: u32,
+        extra_urefs_size: usize,
+    ) -> Result<usize, Error> {
+        let selector_bytes = self.bytes_from_mem(selector_ptr, selector_size)?;
+        let selector: VersionSelector = deserialize(&selector_bytes)?;
+        self.call_contract_with_selector(
+            key_ptr,
+            key_size,
+            selector,
+            args_ptr,
+            args_size,
+            extra_urefs_ptr,
+            extra_urefs_size,
+            None,
+        )
     }
 
-    pub fn add(
+    #[allow(clippy::too_many_arguments)]
+    fn call_contract_with_selector(
         &mut self,
         key_ptr: u32,
-        key_size: u32,
-        value_ptr: u32,
-        value_size: u32,
-    ) -> Result<(), Trap> {
-        let (key, value) = self.kv_from_mem(key_ptr, key_size, value_ptr, value_size)?;
-        self.add_transforms(key, value)
-    }
-
-    // Tests whether reading from the `key` is valid.
-    // For Accounts it's valid to read when the operation is done on the current context's key.
-    // For Contracts it's always valid.
-    // For URefs it's valid if the access rights of the URef allow for reading.
-    fn is_readable(&self, key: &Key) -> bool {
-        match key {
-            Key::Account(_) => &self.context.base_key == key,
-            Key::Hash(_) => true,
-            Key::URef(_, rights) => rights.is_readable(),
+        key_size: usize,
+        selector: VersionSelector,
+        args_ptr: u32,
+        args_size: usize,
+        extra_urefs_ptr: u32,
+        extra_urefs_size: usize,
+        gas_allowance: Option<u64>,
+    ) -> Result<usize, Error> {
+        if self.call_depth >= self.max_call_depth {
+            return Err(Error::CallDepthExceeded(self.max_call_depth));
+        }
+        let key_bytes = self.bytes_from_mem(key_ptr, key_size)?;
+        let args_bytes = self.bytes_from_mem(args_ptr, args_size)?;
+        let urefs_bytes = self.bytes_from_mem(extra_urefs_ptr, extra_urefs_size)?;
+
+        let key = self.context.deserialize_key(&key_bytes)?;
+        self.context.validate_readable(&key)?;
+        let extra_urefs = self.context.deserialize_keys(&urefs_bytes)?;
+        let args: Vec<Vec<u8>> = deserialize(&args_bytes)?;
+        let (contract_key, contract) = self.read_contract(key, selector, &args, &extra_urefs)?;
+        if !contract.is_compatible() {
+            return Err(Error::IncompatibleProtocolVersion {
+                expected: PROTOCOL_VERSION,
+                actual: contract.protocol_version(),
+            });
+        }
+        let module = parity_wasm::deserialize_buffer(contract.bytes())?;
+        let mut refs = contract.urefs_lookup().clone();
+        let gas_limit = match gas_allowance {
+            Some(allowance) => match self.gas_counter.checked_add(Gas::from(allowance)) {
+                Some(limit) => std::cmp::min(self.context.gas_limit, limit),
+                None => self.context.gas_limit,
+            },
+            None => self.context.gas_limit,
+        };
+        // Only a callee with an allowance of its own can fail without failing the caller.
+        let checkpoint = gas_allowance.map(|_| (self.state.checkpoint(), self.events.len()));
+        match sub_call(module, args, &mut refs, contract_key, self, extra_urefs, gas_limit) {
+            Ok(result) => {
+                self.host_buf = result;
+                Ok(self.host_buf.len())
+            }
+            Err(Error::GasLimit) if gas_limit < self.context.gas_limit => {
+                if let Some((state_checkpoint, events_count)) = checkpoint {
+                    self.state.rollback(state_checkpoint);
+                    self.events.truncate(events_count);
+                }
+                Err(Error::CalleeGasLimit)
+            }
+            Err(error) => Err(error),
         }
     }
 
-    /// Tests whether addition to `key` is valid.
-    /// Addition to account key is valid iff it is being made from the context of the account.
-    /// Addition to contract key is valid iff it is being made from the context of the contract.
-    /// Additions to unforgeable key is valid as long as key itself is addable
-    fn is_addable(&self, key: &Key) -> bool {
-        match key {
-            Key::Account(_) | Key::Hash(_) => &self.context.base_key == key,
-            Key::URef(_, rights) => rights.is_addable(),
+    /// Reads the contract stored under `key`. If `key` points at a contract
+    /// package, the version picked by `selector` is read instead, provided
+    /// that the caller presents a URef (`presented_urefs`) from one of the
+    /// groups the called entry point of that version is restricted to. A
+    /// plain contract can only be called with `VersionSelector::Latest`.
+    /// Returns the contract together with the key it was actually read from.
+    fn read_contract(
+        &mut self,
+        key: Key,
+        selector: VersionSelector,
+        args: &[Vec<u8>],
+        presented_urefs: &[Key],
+    ) -> Result<(Key, Contract), Error> {
+        let package = match self.state.read(key).map_err(Into::into)? {
+            Some(Value::ContractPackage(package)) => package,
+            Some(Value::Contract(contract)) => {
+                if selector != VersionSelector::Latest {
+                    return Err(Error::ContractVersionNotFound(key, selector));
+                }
+                return Ok((key, contract));
+            }
+            Some(_) => {
+                return Err(Error::FunctionNotFound(format!(
+                    "Value at {:?} is not a contract",
+                    key
+                )))
+            }
+            None => return Err(Error::KeyNotFound(key)),
+        };
+        if let VersionSelector::Exact(version) = selector {
+            if package.is_version_disabled(version) {
+                return Err(Error::DisabledContractVersion(key, version));
+            }
+        }
+        let version_key = match package.resolve(selector) {
+            Some(version_key) => version_key,
+            None => return Err(Error::ContractVersionNotFound(key, selector)),
+        };
+        let contract = match self.state.read(version_key).map_err(Into::into)? {
+            Some(Value::Contract(contract)) => contract,
+            Some(_) => {
+                return Err(Error::FunctionNotFound(format!(
+                    "Value at {:?} is not a contract",
+                    version_key
+                )))
+            }
+            None => return Err(Error::KeyNotFound(version_key)),
+        };
+        let is_authorized = {
+            let groups = contract
+                .abi()
+                .and_then(|abi| called_entry_point(abi, args))
+                .map_or(&[][..], EntryPoint::groups);
+            package.is_callable_with(groups, presented_urefs)
+        };
+        if !is_authorized {
+            return Err(Error::UnauthorizedCall(key));
         }
+        Ok((version_key, contract))
     }
 
-    // Test whether writing to `kay` is valid.
-    // For Accounts and Hashes it's always invalid.
-    // For URefs it depends on the access rights that uref has.
-    fn is_writeable(&self, key: &Key) -> bool {
-        match key {
-            Key::Account(_) | Key::Hash(_) => false,
-            Key::URef(_, rights) => rights.is_writeable(),
+    /// Reads the contract package stored under `package_key`, checking that
+    /// `access_key` grants the right to modify it and that it isn't locked.
+    fn read_contract_package(
+        &mut self,
+        package_key: Key,
+        access_key: Key,
+    ) -> Result<ContractPackage, Error> {
+        let package = match self.state.read(package_key).map_err(Into::into)? {
+            Some(Value::ContractPackage(package)) => package,
+            Some(other) => {
+                return Err(Error::TypeMismatch(TypeMismatch::new(
+                    "ContractPackage".to_string(),
+                    other.type_string(),
+                )))
+            }
+            None => return Err(Error::KeyNotFound(package_key)),
+        };
+        match (package.access_key(), access_key) {
+            (Key::URef(addr, _), Key::URef(given_addr, rights))
+                if addr == given_addr && rights.is_writeable() =>
+            {
+                if package.is_locked() {
+                    Err(Error::LockedContractPackage(package_key))
+                } else {
+                    Ok(package)
+                }
+            }
+            _ => Err(Error::InvalidAccess {
+                required: AccessRights::WRITE,
+            }),
         }
     }
 
-    /// Reads value living under a key (found at `key_ptr` and `key_size` in Wasm memory).
-    /// Fails if `key` is not "readable", i.e. its access rights are weaker than `AccessRights::Read`.
-    fn value_from_key(&mut self, key_ptr: u32, key_size: u32) -> Result<Value, Trap> {
+    /// Locks a contract package, so that no versions or groups can be added
+    /// to or removed from it anymore.
+    pub fn lock_contract_package(
+        &mut self,
+        package_key_ptr: u32,
+        package_key_size: u32,
+        access_key_ptr: u32,
+        access_key_size: u32,
+    ) -> Result<(), Trap> {
+        let package_key = self.key_from_mem(package_key_ptr, package_key_size)?;
+        let access_key = self.key_from_mem(access_key_ptr, access_key_size)?;
+        let mut package = self.read_contract_package(package_key, access_key)?;
+        package.lock();
+        self.state.write(package_key, Value::ContractPackage(package));
+        Ok(())
+    }
+
+    /// Creates an empty contract package under a new hash and a fresh access
+    /// key for it. The hash is written at `package_hash_ptr` and the
+    /// serialized access key at `access_key_ptr` in the Wasm memory.
+    pub fn create_contract_package(
+        &mut self,
+        package_hash_ptr: u32,
+        access_key_ptr: u32,
+    ) -> Result<(), Trap> {
+        let access_key = Key::URef(self.new_address(), AccessRights::READ_ADD_WRITE);
+        self.context.insert_uref(access_key);
+        let package_hash = self.new_address();
+        self.state.write(
+            Key::Hash(package_hash),
+            Value::ContractPackage(ContractPackage::new(access_key)),
+        );
+        self.function_address(package_hash, package_hash_ptr)?;
+        self.memory
+            .set(access_key_ptr, &access_key.to_bytes().map_err(Error::BytesRepr)?)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Adds the contract stored under the given key as the newest version of
+    /// a contract package, released under the `major` version. Returns the
+    /// number of the new version. The contract has to be stored under a hash:
+    /// one stored under a URef could be upgraded in place, changing a version
+    /// of the package even after it's locked.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_contract_version(
+        &mut self,
+        package_key_ptr: u32,
+        package_key_size: u32,
+        access_key_ptr: u32,
+        access_key_size: u32,
+        major: u32,
+        contract_key_ptr: u32,
+        contract_key_size: u32,
+    ) -> Result<u32, Trap> {
+        let package_key = self.key_from_mem(package_key_ptr, package_key_size)?;
+        let access_key = self.key_from_mem(access_key_ptr, access_key_size)?;
+        let contract_key = self.key_from_mem(contract_key_ptr, contract_key_size)?;
+        let mut package = self.read_contract_package(package_key, access_key)?;
+        if let Key::URef(..) = contract_key {
+            return Err(Error::UpgradableContractVersion(contract_key).into());
+        }
+        match self.state.read(contract_key).map_err(Into::into)? {
+            Some(Value::Contract(_)) => (),
+            Some(other) => {
+                return Err(Error::TypeMismatch(TypeMismatch::new(
+                    "Contract".to_string(),
+                    other.type_string(),
+                ))
+                .into())
+            }
+            None => return Err(Error::KeyNotFound(contract_key).into()),
+        }
+        let version = package.add_version(major, contract_key);
+        self.state.write(package_key, Value::ContractPackage(package));
+        Ok(version)
+    }
+
+    /// Disables the given version of a contract package. A disabled version
+    /// can't be called anymore; calls selecting the latest version of the
+    /// package (or of a major version) fall back to the newest enabled one.
+    pub fn disable_contract_version(
+        &mut self,
+        package_key_ptr: u32,
+        package_key_size: u32,
+        access_key_ptr: u32,
+        access_key_size: u32,
+        version: u32,
+    ) -> Result<(), Trap> {
+        let package_key = self.key_from_mem(package_key_ptr, package_key_size)?;
+        let access_key = self.key_from_mem(access_key_ptr, access_key_size)?;
+        let mut package = self.read_contract_package(package_key, access_key)?;
+        if !package.disable_version(version) {
+            return Err(Error::ContractVersionNotFound(
+                package_key,
+                VersionSelector::Exact(version),
+            )
+            .into());
+        }
+        self.state.write(package_key, Value::ContractPackage(package));
+        Ok(())
+    }
+
+    /// Adds a new URef to the user group `group_name` of a contract package,
+    /// creating the group if it doesn't exist yet. The URef is written at
+    /// `dest_ptr` in the Wasm memory so that it can be handed out to callers.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_contract_user_group(
+        &mut self,
+        package_key_ptr: u32,
+        package_key_size: u32,
+        access_key_ptr: u32,
+        access_key_size: u32,
+        group_name_ptr: u32,
+        group_name_size: u32,
+        dest_ptr: u32,
+    ) -> Result<(), Trap> {
+        let package_key = self.key_from_mem(package_key_ptr, package_key_size)?;
+        let access_key = self.key_from_mem(access_key_ptr, access_key_size)?;
+        let group_name = self.string_from_mem(group_name_ptr, group_name_size)?;
+        let mut package = self.read_contract_package(package_key, access_key)?;
+        let group_key = Key::URef(self.new_address(), AccessRights::READ_ADD_WRITE);
+        self.context.insert_uref(group_key);
+        package.add_group_key(group_name, group_key);
+        self.state.write(package_key, Value::ContractPackage(package));
+        self.memory
+            .set(dest_ptr, &group_key.to_bytes().map_err(Error::BytesRepr)?)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Removes the user group `group_name` from a contract package. URefs
+    /// handed out for that group no longer grant access to the package.
+    pub fn remove_contract_user_group(
+        &mut self,
+        package_key_ptr: u32,
+        package_key_size: u32,
+        access_key_ptr: u32,
+        access_key_size: u32,
+        group_name_ptr: u32,
+        group_name_size: u32,
+    ) -> Result<(), Trap> {
+        let package_key = self.key_from_mem(package_key_ptr, package_key_size)?;
+        let access_key = self.key_from_mem(access_key_ptr, access_key_size)?;
+        let group_name = self.string_from_mem(group_name_ptr, group_name_size)?;
+        let mut package = self.read_contract_package(package_key, access_key)?;
+        if !package.remove_group(&group_name) {
+            return Err(Error::GroupNotFound(group_name).into());
+        }
+        self.state.write(package_key, Value::ContractPackage(package));
+        Ok(())
+    }
+
+    pub fn serialize_function(&mut self, name_ptr: u32, name_size: u32) -> Result<usize, Trap> {
+        let fn_bytes = self.get_function_by_name(name_ptr, name_size)?;
+        self.host_buf = fn_bytes;
+        Ok(self.host_buf.len())
+    }
+
+    /// Tries to store a function, located in the Wasm memory, into the GlobalState
+    /// and writes back a function's hash at `hash_ptr` in the Wasm memory.
+    ///
+    /// `name_ptr` and `name_size` tell the host where to look for a function's name.
+    /// Once it knows the name it can search for this exported function in the Wasm module.
+    /// Note that functions that contract wants to store have to be marked with `export` keyword.
+    /// `urefs_ptr` and `urefs_size` describe when the additional unforgable references can be found.
+    pub fn store_function(
+        &mut self,
+        name_ptr: u32,
+        name_size: u32,
+        urefs_ptr: u32,
+        urefs_size: u32,
+        hash_ptr: u32,
+    ) -> Result<(), Trap> {
+        let contract = self.contract_from_mem(name_ptr, name_size, urefs_ptr, urefs_size)?;
+        self.store_contract(contract, hash_ptr)
+    }
+
+    /// Same as `store_function`, but also stores the contract's ABI, which is
+    /// read from `abi_ptr` and `abi_size` in the Wasm memory.
+    #[allow(clippy::too_many_arguments)]
+    pub fn store_function_with_abi(
+        &mut self,
+        name_ptr: u32,
+        name_size: u32,
+        urefs_ptr: u32,
+        urefs_size: u32,
+        abi_ptr: u32,
+        abi_size: u32,
+        hash_ptr: u32,
+    ) -> Result<(), Trap> {
+        if abi_size as usize > MAX_ABI_SIZE {
+            return Err(Error::AbiTooLarge(abi_size as usize).into());
+        }
+        let abi: Abi = self.t_from_mem(abi_ptr, abi_size)?;
+        let contract = self.contract_from_mem(name_ptr, name_size, urefs_ptr, urefs_size)?;
+        self.store_contract(contract.with_abi(abi), hash_ptr)
+    }
+
+    fn contract_from_mem(
+        &mut self,
+        name_ptr: u32,
+        name_size: u32,
+        urefs_ptr: u32,
+        urefs_size: u32,
+    ) -> Result<Contract, Trap> {
+        let fn_bytes = self.get_function_by_name(name_ptr, name_size)?;
+        wasm_prep::validate_contract(&fn_bytes).map_err(Error::InvalidContract)?;
+        let uref_bytes = self.bytes_from_mem(urefs_ptr, urefs_size as usize)?;
+        let urefs: BTreeMap<String, Key> = deserialize(&uref_bytes).map_err(Error::BytesRepr)?;
+        urefs
+            .iter()
+            .try_for_each(|(_, v)| self.context.validate_key(&v))?;
+        Ok(Contract::new(fn_bytes, urefs, PROTOCOL_VERSION))
+    }
+
+    fn store_contract(&mut self, contract: Contract, hash_ptr: u32) -> Result<(), Trap> {
+        let new_hash = self.new_address();
+        self.state
+            .write(Key::Hash(new_hash), Value::Contract(contract));
+        self.function_address(new_hash, hash_ptr)
+    }
+
+    /// Stores the functions exported from the current module under the
+    /// serialized `Vec<String>` of names found at `names_ptr` as a library.
+    /// Other modules link against it by importing these functions from the
+    /// module `lib:<hex encoded hash>`. The hash is written at `hash_ptr`.
+    pub fn store_library(
+        &mut self,
+        names_ptr: u32,
+        names_size: u32,
+        hash_ptr: u32,
+    ) -> Result<(), Trap> {
+        let names: Vec<String> = self.t_from_mem(names_ptr, names_size)?;
+        if let Some(name) = names.iter().find(|name| !has_export(&self.module, name)) {
+            return Err(Error::FunctionNotFound(name.clone()).into());
+        }
+        let mut module = self.module.clone();
+        // Everything not reachable from the library's exports is dropped,
+        // including the `call` entry point of the current module.
+        pwasm_utils::optimize(&mut module, names.iter().map(String::as_str).collect())
+            .map_err(|_| Error::WasmOptimizer)?;
+        let library_bytes = parity_wasm::serialize(module).map_err(Error::ParityWasm)?;
+        wasm_prep::validate_library(&library_bytes).map_err(Error::InvalidContract)?;
+        let library = Contract::new(library_bytes, BTreeMap::new(), PROTOCOL_VERSION);
+        self.store_contract(library, hash_ptr)
+    }
+
+    /// Stores a function under an address derived from the contract's content
+    /// (its code and unforgable references) rather than from the deploy. Storing
+    /// the same contract again always yields the same address.
+    pub fn store_function_at_hash(
+        &mut self,
+        name_ptr: u32,
+        name_size: u32,
+        urefs_ptr: u32,
+        urefs_size: u32,
+        hash_ptr: u32,
+    ) -> Result<(), Trap> {
+        let contract = self.contract_from_mem(name_ptr, name_size, urefs_ptr, urefs_size)?;
+        let contract_hash = blake2b_hash(&contract.to_bytes().map_err(Error::BytesRepr)?);
+        self.state.write(Key::Hash(contract_hash), Value::Contract(contract));
+        self.function_address(contract_hash, hash_ptr)
+    }
+
+    /// Replaces the code of the contract stored under a URef with the exported
+    /// function `name` of the current module. The caller needs write rights on
+    /// the URef; contracts stored under hashes can't be upgraded. The named
+    /// keys of the old contract are kept, its ABI isn't.
+    pub fn upgrade_contract_at_uref(
+        &mut self,
+        name_ptr: u32,
+        name_size: u32,
+        key_ptr: u32,
+        key_size: u32,
+    ) -> Result<(), Trap> {
         let key = self.key_from_mem(key_ptr, key_size)?;
-        if self.is_readable(&key) {
-            err_on_missing_key(key, self.state.read(key)).map_err(Into::into)
-        } else {
-            Err(Error::InvalidAccess {
-                required: AccessRights::READ,
+        self.context.validate_writeable(&key)?;
+        let known_urefs = match self.state.read(key).map_err(Into::into)? {
+            Some(Value::Contract(contract)) => contract.urefs_lookup().clone(),
+            Some(other) => {
+                return Err(Error::TypeMismatch(TypeMismatch::new(
+                    "Contract".to_string(),
+                    other.type_string(),
+                ))
+                .into())
+            }
+            None => return Err(Error::KeyNotFound(key).into()),
+        };
+        let fn_bytes = self.get_function_by_name(name_ptr, name_size)?;
+        wasm_prep::validate_contract(&fn_bytes).map_err(Error::InvalidContract)?;
+        self.state.write(
+            key,
+            Value::Contract(Contract::new(fn_bytes, known_urefs, PROTOCOL_VERSION)),
+        );
+        Ok(())
+    }
+
+    /// Writes function address (`hash_bytes`) into the Wasm memory (at `dest_ptr` pointer).
+    fn function_address(&mut self, hash_bytes: [u8; 32], dest_ptr: u32) -> Result<(), Trap> {
+        self.memory
+            .set(dest_ptr, &hash_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Writes value under a key (specified by their pointer and length properties from the Wasm memory).
+    pub fn write(
+        &mut self,
+        key_ptr: u32,
+        key_size: u32,
+        value_ptr: u32,
+        value_size: u32,
+    ) -> Result<(), Trap> {
+        let (key, value) = self.kv_from_mem(key_ptr, key_size, value_ptr, value_size)?;
+        self.write_gs(key, value).map_err(Into::into)
+    }
+
+    /// Writes a batch of values, serialized as a list of key-value pairs, in
+    /// one go. Nothing is written unless all the keys are writeable.
+    pub fn write_multi(&mut self, entries_ptr: u32, entries_size: u32) -> Result<(), Trap> {
+        let bytes = self.bytes_from_mem(entries_ptr, entries_size as usize)?;
+        let (entries, rem): (Vec<(Key, Value)>, &[u8]) =
+            vec_from_bytes(&bytes).map_err(Error::BytesRepr)?;
+        if !rem.is_empty() {
+            return Err(Error::BytesRepr(BytesReprError::LeftOverBytes).into());
+        }
+        let mut validated = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            self.context.validate_writeable(&key)?;
+            let value = validate_abi_size(value).and_then(|v| self.context.validate_keys(v))?;
+            validated.push((key, value));
+        }
+        for (key, value) in validated {
+            self.state.write(key, value);
+        }
+        Ok(())
+    }
+
+    /// Address of the local storage slot called `key_bytes` in the current
+    /// context. The key of the context is hashed in, so each account and
+    /// contract has local storage of its own.
+    fn local_key(&self, key_bytes: &[u8]) -> Result<Key, Error> {
+        let mut seed = self.context.base_key.to_bytes()?;
+        seed.extend_from_slice(key_bytes);
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&Blake2bHash::new(&seed).to_vec());
+        Ok(Key::Local(hash))
+    }
+
+    /// Address of the item called `key_bytes` in the dictionary seeded by
+    /// the URef `seed`, which has to carry the `required` rights.
+    fn dictionary_item_key(
+        &self,
+        seed: Key,
+        required: AccessRights,
+        key_bytes: &[u8],
+    ) -> Result<Key, Error> {
+        match seed {
+            Key::URef(addr, rights) if rights & required == required => {
+                dictionary_item_key(addr, key_bytes)
             }
-            .into())
+            _ => Err(Error::InvalidAccess { required }),
         }
     }
 
+    /// Reads the item called by the key bytes found in the Wasm memory from
+    /// the dictionary seeded by the given URef into the host buffer. Returns
+    /// the size of the serialized value, or 0 if there is none.
+    pub fn dictionary_get(
+        &mut self,
+        seed_ptr: u32,
+        seed_size: u32,
+        key_ptr: u32,
+        key_size: u32,
+    ) -> Result<usize, Trap> {
+        let seed = self.key_from_mem(seed_ptr, seed_size)?;
+        let key_bytes = self.bytes_from_mem(key_ptr, key_size as usize)?;
+        let key = self.dictionary_item_key(seed, AccessRights::READ, &key_bytes)?;
+        let value_bytes = match self.state.read_stored(key).map_err(Into::into)? {
+            Some(stored) => stored.into_bytes().map_err(Error::BytesRepr)?,
+            None => Vec::new(),
+        };
+        self.host_buf = value_bytes;
+        Ok(self.host_buf.len())
+    }
+
+    /// Writes the value found in the Wasm memory to the dictionary seeded by
+    /// the given URef, under the given key bytes.
+    pub fn dictionary_put(
+        &mut self,
+        seed_ptr: u32,
+        seed_size: u32,
+        key_ptr: u32,
+        key_size: u32,
+        value_ptr: u32,
+        value_size: u32,
+    ) -> Result<(), Trap> {
+        let seed = self.key_from_mem(seed_ptr, seed_size)?;
+        let key_bytes = self.bytes_from_mem(key_ptr, key_size as usize)?;
+        let key = self.dictionary_item_key(seed, AccessRights::WRITE, &key_bytes)?;
+        let value = self.value_from_mem(value_ptr, value_size)?;
+        self.state.write(key, value);
+        Ok(())
+    }
+
+    /// Reads the value stored in the local storage of the current context
+    /// under the key bytes found in the Wasm memory into the host buffer.
+    /// Returns the size of the serialized value, or 0 if there is none.
+    pub fn read_local(&mut self, key_ptr: u32, key_size: u32) -> Result<usize, Trap> {
+        let key_bytes = self.bytes_from_mem(key_ptr, key_size as usize)?;
+        let key = self.local_key(&key_bytes)?;
+        let value_bytes = match self.state.read_stored(key).map_err(Into::into)? {
+            Some(stored) => stored.into_bytes().map_err(Error::BytesRepr)?,
+            None => Vec::new(),
+        };
+        self.host_buf = value_bytes;
+        Ok(self.host_buf.len())
+    }
+
+    /// Writes the value found in the Wasm memory to the local storage of the
+    /// current context, under the given key bytes.
+    pub fn write_local(
+        &mut self,
+        key_ptr: u32,
+        key_size: u32,
+        value_ptr: u32,
+        value_size: u32,
+    ) -> Result<(), Trap> {
+        let key_bytes = self.bytes_from_mem(key_ptr, key_size as usize)?;
+        let key = self.local_key(&key_bytes)?;
+        let value = self.value_from_mem(value_ptr, value_size)?;
+        self.state.write(key, value);
+        Ok(())
+    }
+
+    pub fn add(
+        &mut self,
+        key_ptr: u32,
+        key_size: u32,
+        value_ptr: u32,
+        value_size: u32,
+    ) -> Result<(), Trap> {
+        let (key, value) = self.kv_from_mem(key_ptr, key_size, value_ptr, value_size)?;
+        self.add_transforms(key, value)
+    }
+
+    /// Reads value living under a key (found at `key_ptr` and `key_size` in Wasm memory).
+    /// Fails if `key` is not "readable", i.e. its access rights are weaker than `AccessRights::Read`.
+    fn value_from_key(
+        &mut self,
+        key_ptr: u32,
+        key_size: u32,
+    ) -> Result<StoredValue<Value>, Trap> {
+        let key = self.key_from_mem(key_ptr, key_size)?;
+        let value = self.read_gs_stored(key);
+        err_on_missing_key(key, value).map_err(Into::into)
+    }
+
     /// Adds `value` to the `key`. The premise for being able to `add` value is that
     /// the type of it [value] can be added (is a Monoid). If the values can't be added,
     /// either because they're not a Monoid or if the value stored under `key` has different type,
     /// then `TypeMismatch` errors is returned. Addition can also fail when `key` is not "addable".
     fn add_transforms(&mut self, key: Key, value: Value) -> Result<(), Trap> {
-        if self.is_addable(&key) {
-            match self.state.add(key, value) {
-                Err(storage_error) => Err(storage_error.into().into()),
-                Ok(AddResult::Success) => Ok(()),
-                Ok(AddResult::KeyNotFound(key)) => Err(Error::KeyNotFound(key).into()),
-                Ok(AddResult::TypeMismatch(type_mismatch)) => {
-                    Err(Error::TypeMismatch(type_mismatch).into())
-                }
-                Ok(AddResult::Overflow) => Err(Error::Overflow.into()),
-            }
-        } else {
-            Err(Error::InvalidAccess {
-                required: AccessRights::ADD,
-            }
-            .into())
-        }
+        self.add_gs(key, value).map_err(Into::into)
     }
 
     /// Reads value from the GS living under key specified by `key_ptr` and `key_size`.
@@ -642,24 +1699,683 @@ where
     pub fn read_value(&mut self, key_ptr: u32, key_size: u32) -> Result<usize, Trap> {
         let value_bytes = {
             let value = self.value_from_key(key_ptr, key_size)?;
-            value.to_bytes().map_err(Error::BytesRepr)?
+            value.into_bytes().map_err(Error::BytesRepr)?
         };
         self.host_buf = value_bytes;
         Ok(self.host_buf.len())
     }
 
+    /// Reads the values stored under a batch of keys into the host buffer,
+    /// serialized as a list of options in the order of the keys. Returns the
+    /// size of the list.
+    pub fn read_multi(&mut self, keys_ptr: u32, keys_size: u32) -> Result<usize, Trap> {
+        let bytes = self.bytes_from_mem(keys_ptr, keys_size as usize)?;
+        let keys = self.context.deserialize_keys(&bytes)?;
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.read_gs(key)?);
+        }
+        self.host_buf = vec_to_bytes(&values).map_err(Error::BytesRepr)?;
+        Ok(self.host_buf.len())
+    }
+
+    /// Address of the next URef, purse or contract created by the deploy.
+    fn new_address(&mut self) -> [u8; 32] {
+        self.address_generator.create_address()
+    }
+
     /// Generates new unforgable reference and adds it to the context's known_uref set.
     pub fn new_uref(&mut self, key_ptr: u32, value_ptr: u32, value_size: u32) -> Result<(), Trap> {
-        let value = self.value_from_mem(value_ptr, value_size)?; // read initial value from memory
-        let mut key = [0u8; 32];
-        self.rng.fill_bytes(&mut key);
-        let key = Key::URef(key, AccessRights::READ_ADD_WRITE);
-        self.state.write(key, value); // write initial value to state
+        self.create_uref(key_ptr, value_ptr, value_size, AccessRights::READ_ADD_WRITE)
+    }
+
+    /// Like `new_uref`, but the current context is only granted `rights` to
+    /// the new URef. `rights` are the bits of `AccessRights` and can't be empty.
+    pub fn new_uref_with_rights(
+        &mut self,
+        key_ptr: u32,
+        value_ptr: u32,
+        value_size: u32,
+        rights: u32,
+    ) -> Result<(), Trap> {
+        let access_rights = u8::try_from(rights)
+            .ok()
+            .and_then(AccessRights::from_bits)
+            .filter(|access_rights| !access_rights.is_empty())
+            .ok_or(Error::InvalidAccessRights(rights))?;
+        self.create_uref(key_ptr, value_ptr, value_size, access_rights)
+    }
+
+    fn create_uref(
+        &mut self,
+        key_ptr: u32,
+        value_ptr: u32,
+        value_size: u32,
+        rights: AccessRights,
+    ) -> Result<(), Trap> {
+        let value = self.value_from_mem(value_ptr, value_size)?; // read initial value from memory
+        let key = Key::URef(self.new_address(), rights);
+        self.state.write(key, value); // write initial value to state
         self.context.insert_uref(key);
         self.memory
             .set(key_ptr, &key.to_bytes().map_err(Error::BytesRepr)?)
             .map_err(|e| Error::Interpreter(e).into())
     }
+
+    /// Reads the account the current context runs under. Fails if the
+    /// context is not an account's (i.e. we are in a stored contract).
+    fn read_context_account(&mut self) -> Result<(Key, Account), Error> {
+        let account_key = match self.context.base_key {
+            key @ Key::Account(_) => key,
+            _ => {
+                return Err(Error::InvalidAccess {
+                    required: AccessRights::WRITE,
+                })
+            }
+        };
+        match self.state.read(account_key).map_err(Into::into)? {
+            Some(Value::Account(account)) => Ok((account_key, account)),
+            Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+                "Account".to_string(),
+                other.type_string(),
+            ))),
+            None => Err(Error::KeyNotFound(account_key)),
+        }
+    }
+
+    /// Applies `f` to the account of the current context and stores the result.
+    /// Key management failures are reported to the contract as error codes
+    /// (`0` means success) rather than traps, so that it can react to them.
+    fn manage_account<F>(&mut self, f: F) -> Result<i32, Trap>
+    where
+        F: FnOnce(&mut Account) -> Result<(), KeyManagementError>,
+    {
+        if !self.context.can_manage_keys() {
+            return Ok(KeyManagementError::PermissionDenied.into());
+        }
+        let (account_key, mut account) = self.read_context_account()?;
+        match f(&mut account) {
+            Ok(()) => {
+                self.state.write(account_key, Value::Account(account));
+                Ok(0)
+            }
+            Err(error) => Ok(error.into()),
+        }
+    }
+
+    pub fn add_associated_key(
+        &mut self,
+        public_key_ptr: u32,
+        public_key_size: u32,
+        weight: u32,
+    ) -> Result<i32, Trap> {
+        let public_key: PublicKey = self.t_from_mem(public_key_ptr, public_key_size)?;
+        if weight > u32::from(u8::max_value()) {
+            return Ok(KeyManagementError::WeightOutOfRange.into());
+        }
+        let weight = Weight::new(weight as u8);
+        let limits = self.key_limits;
+        self.manage_account(|account| account.add_associated_key(public_key, weight, &limits))
+    }
+
+    pub fn remove_associated_key(
+        &mut self,
+        public_key_ptr: u32,
+        public_key_size: u32,
+    ) -> Result<i32, Trap> {
+        let public_key: PublicKey = self.t_from_mem(public_key_ptr, public_key_size)?;
+        self.manage_account(|account| account.remove_associated_key(&public_key))
+    }
+
+    pub fn set_action_threshold(&mut self, action_type: u32, threshold: u32) -> Result<i32, Trap> {
+        let action_type = match ActionType::try_from(action_type) {
+            Ok(action_type) => action_type,
+            Err(_) => return Err(Error::InvalidActionType(action_type).into()),
+        };
+        if threshold > u32::from(u8::max_value()) {
+            return Ok(KeyManagementError::ThresholdViolation.into());
+        }
+        let threshold = Weight::new(threshold as u8);
+        self.manage_account(|account| account.set_action_threshold(action_type, threshold))
+    }
+
+    /// Replaces the main purse of the deploying account with a freshly created one,
+    /// moves the whole balance over and writes the new purse into the Wasm memory
+    /// at `dest_ptr`. Requires key management weight and is only allowed from the
+    /// account's context. All changes go through the tracking copy, so they are
+    /// either committed together or not at all.
+    pub fn rotate_main_purse(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        if !self.context.can_manage_keys() {
+            return Err(Error::KeyManagementThreshold.into());
+        }
+        let (account_key, mut account) = self.read_context_account()?;
+        let old_purse = account.purse_id();
+        let new_purse_addr = self.new_address();
+        let new_purse = mint::create_purse(self.state, new_purse_addr);
+        let balance = mint::balance(self.state, old_purse)?;
+        mint::transfer(self.state, old_purse, new_purse, balance)?;
+        account.set_purse_id(new_purse);
+        self.state.write(account_key, Value::Account(account));
+        self.context.insert_uref(new_purse.into());
+        let purse_bytes = new_purse.to_bytes().map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &purse_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Transfers `amount` of tokens from the main purse of the account the
+    /// current context runs under to the main purse of the account owned by
+    /// `target`. The target account is created if it doesn't exist yet.
+    /// The transfer is recorded under `Key::Transfer`. Returns a
+    /// `TransferredTo` code on success and a `TransferError` code otherwise.
+    pub fn transfer_to_account(
+        &mut self,
+        target_ptr: u32,
+        target_size: u32,
+        amount_ptr: u32,
+        amount_size: u32,
+    ) -> Result<i32, Trap> {
+        let target: PublicKey = self.t_from_mem(target_ptr, target_size)?;
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        let source = match self.context.base_key {
+            Key::Account(_) => self.read_context_account()?.1.purse_id(),
+            _ => return Ok(TransferError::InvalidContext.into()),
+        };
+        // Checked up front so that a failed transfer doesn't leave a new account behind.
+        if mint::balance(self.state, source)? < amount {
+            return Ok(TransferError::InsufficientFunds.into());
+        }
+        let target_key = Key::Account(account_address(target));
+        let existing_target = self.state.read(target_key).map_err(Into::<Error>::into)?;
+        let (target_purse, transferred_to) = match existing_target {
+            Some(Value::Account(account)) => (account.purse_id(), TransferredTo::ExistingAccount),
+            Some(other) => {
+                return Err(Error::TypeMismatch(TypeMismatch::new(
+                    "Account".to_string(),
+                    other.type_string(),
+                ))
+                .into())
+            }
+            None => {
+                let purse_addr = self.new_address();
+                let purse = mint::create_purse(self.state, purse_addr);
+                let account = Account::create(target.value(), 0, BTreeMap::new(), purse);
+                self.state.write(target_key, Value::Account(account));
+                (purse, TransferredTo::NewAccount)
+            }
+        };
+        mint::transfer(self.state, source, target_purse, amount)?;
+        let to = Some(account_address(target));
+        self.record_transfer(to, source, target_purse, amount)?;
+        Ok(transferred_to.into())
+    }
+
+    /// Writes the record of a transfer made by the deploy under the next
+    /// transfer address.
+    fn record_transfer(
+        &mut self,
+        to: Option<[u8; 20]>,
+        source: PurseId,
+        target: PurseId,
+        amount: U512,
+    ) -> Result<(), Error> {
+        let addr = self.transfer_address_generator.create_address();
+        let record = Transfer {
+            deploy_hash: self.context.deploy_hash(),
+            from: account_address(self.context.account.public_key()),
+            to,
+            source,
+            target,
+            amount,
+            gas: self.gas_counter,
+        };
+        transfer::write_transfer(self.state, addr, &record)
+    }
+
+    /// Hashes `in_size` bytes found at `in_ptr` with Blake2b-256 and writes the
+    /// digest into the Wasm memory at `out_ptr`.
+    pub fn blake2b(&mut self, in_ptr: u32, in_size: u32, out_ptr: u32) -> Result<(), Trap> {
+        let data = self.bytes_from_mem(in_ptr, in_size as usize)?;
+        let digest = Blake2bHash::new(&data);
+        self.memory
+            .set(out_ptr, &digest.to_vec())
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Checks whether the Ed25519 signature at `signature_ptr` was made over the
+    /// `message_size` bytes at `message_ptr` by the public key at `public_key_ptr`.
+    /// Returns `1` for a valid signature and `0` otherwise, which includes keys and
+    /// signatures that can't be decoded.
+    pub fn verify_ed25519(
+        &mut self,
+        message_ptr: u32,
+        message_size: u32,
+        signature_ptr: u32,
+        public_key_ptr: u32,
+    ) -> Result<i32, Trap> {
+        let message = self.bytes_from_mem(message_ptr, message_size as usize)?;
+        let signature = self.bytes_from_mem(signature_ptr, ED25519_SIGNATURE_LENGTH)?;
+        let public_key = self.bytes_from_mem(public_key_ptr, N32)?;
+        let valid = match (
+            ed25519_dalek::PublicKey::from_bytes(&public_key),
+            ed25519_dalek::Signature::from_bytes(&signature),
+        ) {
+            (Ok(public_key), Ok(signature)) => public_key.verify(&message, &signature).is_ok(),
+            _ => false,
+        };
+        Ok(i32::from(valid))
+    }
+
+    /// Reads the 32 byte digest and the secp256k1 signature passed to the secp256k1
+    /// host functions.
+    fn secp256k1_input(
+        &mut self,
+        digest_ptr: u32,
+        signature_ptr: u32,
+    ) -> Result<(secp256k1::Message, secp256k1::Signature), Error> {
+        let mut digest = [0u8; N32];
+        self.memory.get_into(digest_ptr, &mut digest)?;
+        let mut signature = [0u8; SECP256K1_SIGNATURE_LENGTH];
+        self.memory.get_into(signature_ptr, &mut signature)?;
+        Ok((
+            secp256k1::Message::parse(&digest),
+            secp256k1::Signature::parse(&signature),
+        ))
+    }
+
+    /// Checks whether the secp256k1 signature at `signature_ptr` of the 32 byte
+    /// digest at `digest_ptr` was made by the uncompressed public key at
+    /// `public_key_ptr`. Returns `1` for a valid signature and `0` otherwise.
+    pub fn verify_secp256k1(
+        &mut self,
+        digest_ptr: u32,
+        signature_ptr: u32,
+        public_key_ptr: u32,
+    ) -> Result<i32, Trap> {
+        let (message, signature) = self.secp256k1_input(digest_ptr, signature_ptr)?;
+        let mut public_key = [0u8; SECP256K1_PUBLIC_KEY_LENGTH];
+        self.memory
+            .get_into(public_key_ptr, &mut public_key)
+            .map_err(Error::Interpreter)?;
+        let valid = match secp256k1::PublicKey::parse(&public_key) {
+            Ok(public_key) => secp256k1::verify(&message, &signature, &public_key),
+            Err(_) => false,
+        };
+        Ok(i32::from(valid))
+    }
+
+    /// Recovers the public key which made the secp256k1 signature at
+    /// `signature_ptr` of the 32 byte digest at `digest_ptr` and writes it,
+    /// uncompressed, into the Wasm memory at `dest_ptr`. Returns `1` if a key
+    /// was recovered and `0` otherwise.
+    pub fn recover_secp256k1(
+        &mut self,
+        digest_ptr: u32,
+        signature_ptr: u32,
+        recovery_id: u32,
+        dest_ptr: u32,
+    ) -> Result<i32, Trap> {
+        let (message, signature) = self.secp256k1_input(digest_ptr, signature_ptr)?;
+        let recovery_id = if recovery_id <= u32::from(u8::max_value()) {
+            secp256k1::RecoveryId::parse(recovery_id as u8).ok()
+        } else {
+            None
+        };
+        let public_key = match recovery_id
+            .and_then(|recovery_id| secp256k1::recover(&message, &signature, &recovery_id).ok())
+        {
+            Some(public_key) => public_key,
+            None => return Ok(0),
+        };
+        self.memory
+            .set(dest_ptr, &public_key.serialize())
+            .map_err(Error::Interpreter)?;
+        Ok(1)
+    }
+
+    /// Writes 32 random bytes into the Wasm memory at `dest_ptr`. They are the next
+    /// link of a hash chain seeded by the deploy hash and the phase, so every validator
+    /// gets the same bytes, and payment and session code get different ones. They are
+    /// unbiased but predictable to anyone who knows the deploy, so they must not be
+    /// relied upon to keep secrets.
+    pub fn random_bytes(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        let bytes = self.random_generator.create_address();
+        self.memory
+            .set(dest_ptr, &bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Sets the arguments the deploy passes by name.
+    pub fn with_named_args(mut self, named_args: RuntimeArgs) -> Self {
+        self.named_args = named_args;
+        self
+    }
+
+    /// Sets the keys of the system contracts registered in the engine config.
+    pub fn with_system_contracts(
+        mut self,
+        system_contracts: BTreeMap<SystemContractId, Key>,
+    ) -> Self {
+        self.system_contracts = system_contracts;
+        self
+    }
+
+    /// Sets the costs charged for calls to host functions.
+    pub fn with_host_function_costs(mut self, host_function_costs: HostFunctionCosts) -> Self {
+        self.host_function_costs = host_function_costs;
+        self
+    }
+
+    /// Writes the key of the system contract `id` at `dest_ptr` in the Wasm
+    /// memory and makes it known to the current context. Returns the size of
+    /// the serialized key.
+    pub fn get_system_contract(&mut self, id: u32, dest_ptr: u32) -> Result<usize, Trap> {
+        let key = SystemContractId::try_from(id)
+            .ok()
+            .and_then(|id| self.system_contracts.get(&id).cloned())
+            .ok_or(Error::SystemContractNotFound(id))?;
+        self.context.insert_uref(key);
+        let key_bytes = key.to_bytes().map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &key_bytes)
+            .map_err(Error::Interpreter)?;
+        Ok(key_bytes.len())
+    }
+
+    /// Limits how deeply contract calls can be nested.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Limits the keys accounts associate with themselves.
+    pub fn with_key_limits(mut self, key_limits: KeyLimits) -> Self {
+        self.key_limits = key_limits;
+        self
+    }
+
+    /// Sets how long bonding and unbonding requests wait before they are settled.
+    pub fn with_pos_delays(mut self, pos_delays: pos::QueueDelays) -> Self {
+        self.pos_delays = pos_delays;
+        self
+    }
+
+    /// Makes `print` log the messages of contracts.
+    pub fn with_contract_debug(mut self, contract_debug: bool) -> Self {
+        self.contract_debug = contract_debug;
+        self
+    }
+
+    /// Logs the message found in the Wasm memory if contract debugging is enabled
+    /// in the engine config and does nothing otherwise.
+    pub fn print(&mut self, message_ptr: u32, message_size: u32) -> Result<(), Trap> {
+        if !self.contract_debug {
+            return Ok(());
+        }
+        let message = self.string_from_mem(message_ptr, message_size)?;
+        println!("[contract {:?}] {}", self.context.base_key, message);
+        Ok(())
+    }
+
+    /// Records an event with the topic and the value found in the Wasm memory.
+    /// Events are reported with the effects of the deploy; their number and the
+    /// size of each of them are limited.
+    pub fn emit_event(
+        &mut self,
+        topic_ptr: u32,
+        topic_size: u32,
+        data_ptr: u32,
+        data_size: u32,
+    ) -> Result<(), Trap> {
+        let event_size = topic_size as usize + data_size as usize;
+        if event_size > MAX_EVENT_SIZE {
+            return Err(Error::EventTooLarge(event_size).into());
+        }
+        if self.events.len() >= MAX_EVENTS_PER_DEPLOY {
+            return Err(Error::TooManyEvents.into());
+        }
+        let topic = self.string_from_mem(topic_ptr, topic_size)?;
+        let data = self.value_from_mem(data_ptr, data_size)?;
+        self.events.push(Event { topic, data });
+        Ok(())
+    }
+
+    /// Writes the main purse of the account the current context runs under into
+    /// the Wasm memory at `dest_ptr`. It is read from the global state, so a purse
+    /// replaced by `rotate_main_purse` earlier in the deploy isn't returned.
+    pub fn get_main_purse(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        let purse = self.read_context_account()?.1.purse_id();
+        let purse_bytes = purse.to_bytes().map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &purse_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Creates a new, empty purse and writes the reference to it into the Wasm
+    /// memory at `dest_ptr`. The reference is made known to the current context.
+    pub fn create_purse(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        let purse_addr = self.new_address();
+        let purse = mint::create_purse(self.state, purse_addr);
+        self.context.insert_uref(purse.into());
+        let purse_bytes = purse.to_bytes().map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &purse_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Writes the purse the payment code pays into into the Wasm memory at
+    /// `dest_ptr`. It's only available in the payment phase and is handed
+    /// out with add rights only, so the deploy can't take the payment back.
+    pub fn get_payment_purse(&mut self, dest_ptr: u32) -> Result<(), Trap> {
+        if self.context.phase() != Phase::Payment {
+            return Err(Error::InvalidPhase(self.context.phase()).into());
+        }
+        let purse = mint::payment_purse(self.context.deploy_hash())
+            .with_access_rights(AccessRights::ADD);
+        self.context.insert_uref(purse.into());
+        let purse_bytes = purse.to_bytes().map_err(Error::BytesRepr)?;
+        self.memory
+            .set(dest_ptr, &purse_bytes)
+            .map_err(|e| Error::Interpreter(e).into())
+    }
+
+    /// Reads a purse from the Wasm memory, checking that the reference isn't forged.
+    fn purse_from_mem(&mut self, ptr: u32, size: u32) -> Result<PurseId, Error> {
+        let purse: PurseId = self.t_from_mem(ptr, size)?;
+        self.context.validate_key(&purse.into())?;
+        Ok(purse)
+    }
+
+    /// Transfers `amount` of tokens from the `source` purse to the `target`
+    /// purse. The caller has to hold a reference to `source` with write rights
+    /// and a reference to `target` with add rights. The transfer is recorded
+    /// under `Key::Transfer`. Returns `0` on success and a `TransferError`
+    /// code otherwise.
+    pub fn transfer_from_purse_to_purse(
+        &mut self,
+        source_ptr: u32,
+        source_size: u32,
+        target_ptr: u32,
+        target_size: u32,
+        amount_ptr: u32,
+        amount_size: u32,
+    ) -> Result<i32, Trap> {
+        let source = self.purse_from_mem(source_ptr, source_size)?;
+        let target = self.purse_from_mem(target_ptr, target_size)?;
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        self.context.validate_writeable(&source.into())?;
+        self.context.validate_addable(&target.into())?;
+        match mint::transfer(self.state, source, target, amount) {
+            Ok(()) => {
+                self.record_transfer(None, source, target, amount)?;
+                Ok(0)
+            }
+            Err(Error::InsufficientFunds) => Ok(TransferError::InsufficientFunds.into()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn system_contract(&self, id: SystemContractId) -> Result<Key, Error> {
+        self.system_contracts
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::SystemContractNotFound(id.into()))
+    }
+
+    fn pos_contract(&self) -> Result<Key, Error> {
+        self.system_contract(SystemContractId::ProofOfStake)
+    }
+
+    /// Bonds `amount` of tokens taken from `purse` as the stake of the account
+    /// the deploy runs under. The caller has to hold a reference to `purse`
+    /// with write rights. Returns `0` on success and a `PosError` code otherwise.
+    pub fn bond(
+        &mut self,
+        amount_ptr: u32,
+        amount_size: u32,
+        purse_ptr: u32,
+        purse_size: u32,
+    ) -> Result<i32, Trap> {
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        let purse = self.purse_from_mem(purse_ptr, purse_size)?;
+        self.context.validate_writeable(&purse.into())?;
+        let pos_contract = self.pos_contract()?;
+        let validator = self.context.account.public_key();
+        let height = self.context.block_height();
+        let delays = self.pos_delays;
+        match pos::bond(self.state, pos_contract, validator, purse, amount, height, delays) {
+            Ok(()) => Ok(0),
+            Err(Error::Pos(error)) => Ok(error.into()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Unbonds `amount` of tokens from the stake of the account the deploy
+    /// runs under. Once the unbonding delay passes they are paid out to the
+    /// main purse of the account. Returns `0` on success and a `PosError` code
+    /// otherwise.
+    pub fn unbond(&mut self, amount_ptr: u32, amount_size: u32) -> Result<i32, Trap> {
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        let pos_contract = self.pos_contract()?;
+        let validator = self.context.account.public_key();
+        let target = self.read_context_account()?.1.purse_id();
+        let height = self.context.block_height();
+        let delays = self.pos_delays;
+        match pos::unbond(self.state, pos_contract, validator, target, amount, height, delays) {
+            Ok(()) => Ok(0),
+            Err(Error::Pos(error)) => Ok(error.into()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Delegates `amount` of tokens taken from `purse` to the validator found
+    /// in the Wasm memory, on behalf of the account the deploy runs under. The
+    /// caller has to hold a reference to `purse` with write rights. Returns
+    /// `0` on success and a `PosError` code otherwise.
+    pub fn delegate(
+        &mut self,
+        validator_ptr: u32,
+        validator_size: u32,
+        amount_ptr: u32,
+        amount_size: u32,
+        purse_ptr: u32,
+        purse_size: u32,
+    ) -> Result<i32, Trap> {
+        let validator: PublicKey = self.t_from_mem(validator_ptr, validator_size)?;
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        let purse = self.purse_from_mem(purse_ptr, purse_size)?;
+        self.context.validate_writeable(&purse.into())?;
+        let pos_contract = self.pos_contract()?;
+        let delegator = self.context.account.public_key();
+        let height = self.context.block_height();
+        let delays = self.pos_delays;
+        match pos::delegate(
+            self.state,
+            pos_contract,
+            delegator,
+            validator,
+            purse,
+            amount,
+            height,
+            delays,
+        ) {
+            Ok(()) => Ok(0),
+            Err(Error::Pos(error)) => Ok(error.into()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Undelegates `amount` of the tokens the account the deploy runs under
+    /// delegated to the validator found in the Wasm memory. Once the unbonding
+    /// delay passes they are paid out to the main purse of the account.
+    /// Returns `0` on success and a `PosError` code otherwise.
+    pub fn undelegate(
+        &mut self,
+        validator_ptr: u32,
+        validator_size: u32,
+        amount_ptr: u32,
+        amount_size: u32,
+    ) -> Result<i32, Trap> {
+        let validator: PublicKey = self.t_from_mem(validator_ptr, validator_size)?;
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        let pos_contract = self.pos_contract()?;
+        let delegator = self.context.account.public_key();
+        let target = self.read_context_account()?.1.purse_id();
+        let height = self.context.block_height();
+        let delays = self.pos_delays;
+        match pos::undelegate(
+            self.state,
+            pos_contract,
+            delegator,
+            validator,
+            target,
+            amount,
+            height,
+            delays,
+        ) {
+            Ok(()) => Ok(0),
+            Err(Error::Pos(error)) => Ok(error.into()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Adds `amount` of tokens taken from `purse` to the bid of the account
+    /// the deploy runs under. The caller has to hold a reference to `purse`
+    /// with write rights. Returns `0` on success and an `AuctionError` code
+    /// otherwise.
+    pub fn add_bid(
+        &mut self,
+        amount_ptr: u32,
+        amount_size: u32,
+        purse_ptr: u32,
+        purse_size: u32,
+    ) -> Result<i32, Trap> {
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        let purse = self.purse_from_mem(purse_ptr, purse_size)?;
+        self.context.validate_writeable(&purse.into())?;
+        let auction_contract = self.system_contract(SystemContractId::Auction)?;
+        let bidder = self.context.account.public_key();
+        match auction::add_bid(self.state, auction_contract, bidder, purse, amount) {
+            Ok(()) => Ok(0),
+            Err(Error::Auction(error)) => Ok(error.into()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Withdraws `amount` of tokens from the bid of the account the deploy
+    /// runs under, paying them out to its main purse. Returns `0` on success
+    /// and an `AuctionError` code otherwise.
+    pub fn withdraw_bid(&mut self, amount_ptr: u32, amount_size: u32) -> Result<i32, Trap> {
+        let amount: U512 = self.t_from_mem(amount_ptr, amount_size)?;
+        let auction_contract = self.system_contract(SystemContractId::Auction)?;
+        let bidder = self.context.account.public_key();
+        let target = self.read_context_account()?.1.purse_id();
+        match auction::withdraw_bid(self.state, auction_contract, bidder, target, amount) {
+            Ok(()) => Ok(0),
+            Err(Error::Auction(error)) => Ok(error.into()),
+            Err(error) => Err(error.into()),
+        }
+    }
 }
 
 // Helper function for turning result of lookup into domain values.
@@ -678,6 +2394,38 @@ fn as_usize(u: u32) -> usize {
     u as usize
 }
 
+fn blake2b_hash(bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = VarBlake2b::new(32).unwrap();
+    hasher.input(bytes);
+    let mut hash_bytes = [0; 32];
+    hasher.variable_result(|hash| hash_bytes.clone_from_slice(hash));
+    hash_bytes
+}
+
+// Contracts written directly from Wasm are subject to the same ABI size cap
+// as the ones stored by `store_function_with_abi`.
+fn validate_abi_size(value: Value) -> Result<Value, Error> {
+    if let Value::Contract(ref contract) = value {
+        if let Some(abi) = contract.abi() {
+            let abi_size = abi.to_bytes()?.len();
+            if abi_size > MAX_ABI_SIZE {
+                return Err(Error::AbiTooLarge(abi_size));
+            }
+        }
+    }
+    Ok(value)
+}
+
+/// Finds the entry point of `abi` a call with `args` is made to. By convention
+/// the first argument of a call names the entry point; calls which don't name
+/// one of the entry points are made to the `call` export.
+fn called_entry_point<'a>(abi: &'a Abi, args: &[Vec<u8>]) -> Option<&'a EntryPoint> {
+    args.first()
+        .and_then(|arg| deserialize::<String>(arg).ok())
+        .and_then(|name| abi.entry_point(&name))
+        .or_else(|| abi.entry_point("call"))
+}
+
 const WRITE_FUNC_INDEX: usize = 0;
 const READ_FUNC_INDEX: usize = 1;
 const ADD_FUNC_INDEX: usize = 2;
@@ -695,6 +2443,159 @@ const GAS_FUNC_INDEX: usize = 13;
 const HAS_UREF_FUNC_INDEX: usize = 14;
 const ADD_UREF_FUNC_INDEX: usize = 15;
 const STORE_FN_INDEX: usize = 16;
+const GET_CALLER_INDEX: usize = 17;
+const ROTATE_MAIN_PURSE_INDEX: usize = 18;
+const ADD_ASSOCIATED_KEY_INDEX: usize = 19;
+const REMOVE_ASSOCIATED_KEY_INDEX: usize = 20;
+const SET_ACTION_THRESHOLD_INDEX: usize = 21;
+const CREATE_CONTRACT_PACKAGE_INDEX: usize = 22;
+const ADD_CONTRACT_VERSION_INDEX: usize = 23;
+const CREATE_CONTRACT_USER_GROUP_INDEX: usize = 24;
+const REMOVE_CONTRACT_USER_GROUP_INDEX: usize = 25;
+const STORE_FN_WITH_ABI_INDEX: usize = 26;
+const STORE_FN_AT_HASH_INDEX: usize = 27;
+const LOCK_CONTRACT_PACKAGE_INDEX: usize = 28;
+const CALL_VERSIONED_CONTRACT_INDEX: usize = 29;
+const PUT_KEY_INDEX: usize = 30;
+const REMOVE_KEY_INDEX: usize = 31;
+const GET_KEY_INDEX: usize = 32;
+const STORE_LIBRARY_INDEX: usize = 33;
+const DISABLE_CONTRACT_VERSION_INDEX: usize = 34;
+const GET_PHASE_INDEX: usize = 35;
+const REVERT_INDEX: usize = 36;
+const TRANSFER_TO_ACCOUNT_INDEX: usize = 37;
+const TRANSFER_FROM_PURSE_TO_PURSE_INDEX: usize = 38;
+const CREATE_PURSE_INDEX: usize = 39;
+const GET_MAIN_PURSE_INDEX: usize = 40;
+const BLAKE2B_INDEX: usize = 41;
+const VERIFY_ED25519_INDEX: usize = 42;
+const VERIFY_SECP256K1_INDEX: usize = 43;
+const RECOVER_SECP256K1_INDEX: usize = 44;
+const RANDOM_BYTES_INDEX: usize = 45;
+const EMIT_EVENT_INDEX: usize = 46;
+const PRINT_INDEX: usize = 47;
+const LOAD_NAMED_ARG_INDEX: usize = 48;
+const LIST_NAMED_KEYS_INDEX: usize = 49;
+const REMOVE_UREF_INDEX: usize = 50;
+const IS_VALID_INDEX: usize = 51;
+const READ_LOCAL_INDEX: usize = 52;
+const WRITE_LOCAL_INDEX: usize = 53;
+const GET_SYSTEM_CONTRACT_INDEX: usize = 54;
+const CALL_CONTRACT_WITH_GAS_INDEX: usize = 55;
+const UPGRADE_CONTRACT_AT_UREF_INDEX: usize = 56;
+const GAS_REMAINING_INDEX: usize = 57;
+const READ_MULTI_INDEX: usize = 58;
+const WRITE_MULTI_INDEX: usize = 59;
+const READ_HOST_BUFFER_INDEX: usize = 60;
+const NEW_UREF_WITH_RIGHTS_INDEX: usize = 61;
+const GET_DEPLOY_HASH_INDEX: usize = 62;
+const GET_PROTOCOL_VERSION_INDEX: usize = 63;
+const DICTIONARY_GET_INDEX: usize = 64;
+const DICTIONARY_PUT_INDEX: usize = 65;
+const CALL_STACK_DEPTH_INDEX: usize = 66;
+const VALIDATE_KEY_INDEX: usize = 67;
+const GET_BLOCK_HEIGHT_INDEX: usize = 68;
+const GET_PAYMENT_PURSE_INDEX: usize = 69;
+const BOND_INDEX: usize = 70;
+const UNBOND_INDEX: usize = 71;
+const DELEGATE_INDEX: usize = 72;
+const UNDELEGATE_INDEX: usize = 73;
+const ADD_BID_INDEX: usize = 74;
+const WITHDRAW_BID_INDEX: usize = 75;
+
+/// Names the host functions are imported under, by the index they're invoked
+/// with. Their costs are looked up by these names.
+const HOST_FUNCTION_NAMES: [&str; 76] = [
+    "write",
+    "read_value",
+    "add",
+    "new_uref",
+    "get_read",
+    "serialize_function",
+    "get_function",
+    "load_arg",
+    "get_arg",
+    "ret",
+    "get_call_result",
+    "call_contract",
+    "get_uref",
+    "gas",
+    "has_uref_name",
+    "add_uref",
+    "store_function",
+    "get_caller",
+    "rotate_main_purse",
+    "add_associated_key",
+    "remove_associated_key",
+    "set_action_threshold",
+    "create_contract_package",
+    "add_contract_version",
+    "create_contract_user_group",
+    "remove_contract_user_group",
+    "store_function_with_abi",
+    "store_function_at_hash",
+    "lock_contract_package",
+    "call_versioned_contract",
+    "put_key",
+    "remove_key",
+    "get_key",
+    "store_library",
+    "disable_contract_version",
+    "get_phase",
+    "revert",
+    "transfer_to_account",
+    "transfer_from_purse_to_purse",
+    "create_purse",
+    "get_main_purse",
+    "blake2b",
+    "verify_ed25519",
+    "verify_secp256k1",
+    "recover_secp256k1",
+    "random_bytes",
+    "emit_event",
+    "print",
+    "load_named_arg",
+    "list_named_keys",
+    "remove_uref",
+    "is_valid",
+    "read_local",
+    "write_local",
+    "get_system_contract",
+    "call_contract_with_gas",
+    "upgrade_contract_at_uref",
+    "gas_remaining",
+    "read_multi",
+    "write_multi",
+    "read_host_buffer",
+    "new_uref_with_rights",
+    "get_deploy_hash",
+    "get_protocol_version",
+    "dictionary_get",
+    "dictionary_put",
+    "call_stack_depth",
+    "validate_key",
+    "get_block_height",
+    "get_payment_purse",
+    "bond",
+    "unbond",
+    "delegate",
+    "undelegate",
+    "add_bid",
+    "withdraw_bid",
+];
+
+/// Name of the host function invoked with `index`, as recorded in journals.
+pub fn host_function_name(index: u32) -> Option<&'static str> {
+    HOST_FUNCTION_NAMES.get(index as usize).cloned()
+}
+
+/// Largest number of named keys returned by one `list_named_keys` call.
+pub const MAX_NAMED_KEYS_PAGE_SIZE: usize = 100;
+
+/// Maximal serialized size of the topic and the data of an event.
+pub const MAX_EVENT_SIZE: usize = 1024;
+/// Maximal number of events a deploy can emit.
+pub const MAX_EVENTS_PER_DEPLOY: usize = 100;
 
 impl<'a, R: StateReader<Key, Value>> Externals for Runtime<'a, R>
 where
@@ -705,6 +2606,12 @@ where
         index: usize,
         args: RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Trap> {
+        // Calls to `gas` are the metering itself and aren't charged for.
+        if index != GAS_FUNC_INDEX {
+            self.state.record(|| JournalEntry::HostCall(index as u32));
+            self.host_function_cost = self.host_function_costs.cost(HOST_FUNCTION_NAMES[index]);
+            self.gas(u64::from(self.host_function_cost.base))?;
+        }
         match index {
             READ_FUNC_INDEX => {
                 // args(0) = pointer to key in Wasm memory
@@ -742,129 +2649,791 @@ where
                 Ok(None)
             }
 
-            NEW_FUNC_INDEX => {
+            NEW_FUNC_INDEX => {
+                // args(0) = pointer to key destination in Wasm memory
+                // args(1) = pointer to initial value
+                // args(2) = size of initial value
+                let (key_ptr, value_ptr, value_size) = Args::parse(args)?;
+                self.new_uref(key_ptr, value_ptr, value_size)?;
+                Ok(None)
+            }
+
+            GET_READ_FUNC_INDEX => {
+                // args(0) = pointer to destination in Wasm memory
+                let dest_ptr = Args::parse(args)?;
+                self.set_mem_from_buf(dest_ptr)?;
+                Ok(None)
+            }
+
+            GET_FN_FUNC_INDEX => {
+                // args(0) = pointer to destination in Wasm memory
+                let dest_ptr = Args::parse(args)?;
+                self.set_mem_from_buf(dest_ptr)?;
+                Ok(None)
+            }
+
+            LOAD_ARG_FUNC_INDEX => {
+                // args(0) = index of host runtime arg to load
+                let i = Args::parse(args)?;
+                // A missing argument is reported to the contract as -1.
+                let size = self.load_arg(i).map_or(-1, |size| size as i32);
+                Ok(Some(RuntimeValue::I32(size)))
+            }
+
+            GET_ARG_FUNC_INDEX => {
+                // args(0) = pointer to destination in Wasm memory
+                let dest_ptr = Args::parse(args)?;
+                self.set_mem_from_buf(dest_ptr)?;
+                Ok(None)
+            }
+
+            RET_FUNC_INDEX => {
+                // args(0) = pointer to value
+                // args(1) = size of value
+                // args(2) = pointer to extra returned urefs
+                // args(3) = size of extra urefs
+                let (value_ptr, value_size, extra_urefs_ptr, extra_urefs_size) = Args::parse(args)?;
+
+                Err(self.ret(
+                    value_ptr,
+                    as_usize(value_size),
+                    extra_urefs_ptr,
+                    as_usize(extra_urefs_size),
+                ))
+            }
+
+            CALL_CONTRACT_FUNC_INDEX => {
+                // args(0) = pointer to key where contract is at in global state
+                // args(1) = size of key
+                // args(2) = pointer to function arguments in Wasm memory
+                // args(3) = size of arguments
+                // args(4) = pointer to extra supplied urefs
+                // args(5) = size of extra urefs
+                let (key_ptr, key_size, args_ptr, args_size, extra_urefs_ptr, extra_urefs_size) =
+                    Args::parse(args)?;
+
+                let size = self.call_contract(
+                    key_ptr,
+                    as_usize(key_size),
+                    args_ptr,
+                    as_usize(args_size),
+                    extra_urefs_ptr,
+                    as_usize(extra_urefs_size),
+                )?;
+                Ok(Some(RuntimeValue::I32(size as i32)))
+            }
+
+            CALL_VERSIONED_CONTRACT_INDEX => {
+                // args(0) = pointer to key where contract is at in global state
+                // args(1) = size of key
+                // args(2) = pointer to serialized version selector
+                // args(3) = size of version selector
+                // args(4) = pointer to function arguments in Wasm memory
+                // args(5) = size of arguments
+                // args(6) = pointer to extra supplied urefs
+                // args(7) = size of extra urefs
+                let (
+                    key_ptr,
+                    key_size,
+                    selector_ptr,
+                    selector_size,
+                    args_ptr,
+                    args_size,
+                    extra_urefs_ptr,
+                    extra_urefs_size,
+                ) = Args::parse(args)?;
+
+                let size = self.call_versioned_contract(
+                    key_ptr,
+                    as_usize(key_size),
+                    selector_ptr,
+                    as_usize(selector_size),
+                    args_ptr,
+                    as_usize(args_size),
+                    extra_urefs_ptr,
+                    as_usize(extra_urefs_size),
+                )?;
+                Ok(Some(RuntimeValue::I32(size as i32)))
+            }
+
+            GET_CALL_RESULT_FUNC_INDEX => {
+                // args(0) = pointer to destination in Wasm memory
+                let dest_ptr = Args::parse(args)?;
+                self.set_mem_from_buf(dest_ptr)?;
+                Ok(None)
+            }
+
+            GET_UREF_FUNC_INDEX => {
+                // args(0) = pointer to uref name in Wasm memory
+                // args(1) = size of uref name
+                // args(2) = pointer to destination in Wasm memory
+                let (name_ptr, name_size, dest_ptr) = Args::parse(args)?;
+                self.get_uref(name_ptr, name_size, dest_ptr)?;
+                Ok(None)
+            }
+
+            HAS_UREF_FUNC_INDEX => {
+                // args(0) = pointer to uref name in Wasm memory
+                // args(1) = size of uref name
+                let (name_ptr, name_size) = Args::parse(args)?;
+                let result = self.has_uref(name_ptr, name_size)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            ADD_UREF_FUNC_INDEX => {
+                // args(0) = pointer to uref name in Wasm memory
+                // args(1) = size of uref name
+                // args(2) = pointer to destination in Wasm memory
+                let (name_ptr, name_size, key_ptr, key_size) = Args::parse(args)?;
+                self.add_uref(name_ptr, name_size, key_ptr, key_size)?;
+                Ok(None)
+            }
+
+            GAS_FUNC_INDEX => {
+                let gas: u32 = Args::parse(args)?;
+                self.gas(u64::from(gas))?;
+                Ok(None)
+            }
+
+            STORE_FN_INDEX => {
+                // args(0) = pointer to function name in Wasm memory
+                // args(1) = size of the name
+                // args(2) = pointer to additional unforgable names
+                //           to be saved with the function body
+                // args(3) = size of the additional unforgable names
+                // args(4) = pointer to a Wasm memory where we will save
+                //           hash of the new function
+                let (name_ptr, name_size, urefs_ptr, urefs_size, hash_ptr) = Args::parse(args)?;
+                self.store_function(name_ptr, name_size, urefs_ptr, urefs_size, hash_ptr)?;
+                Ok(None)
+            }
+
+            STORE_FN_AT_HASH_INDEX => {
+                // args(0) = pointer to function name in Wasm memory
+                // args(1) = size of the name
+                // args(2) = pointer to additional unforgable names
+                //           to be saved with the function body
+                // args(3) = size of the additional unforgable names
+                // args(4) = pointer to a Wasm memory where we will save
+                //           hash of the new function
+                let (name_ptr, name_size, urefs_ptr, urefs_size, hash_ptr) = Args::parse(args)?;
+                self.store_function_at_hash(name_ptr, name_size, urefs_ptr, urefs_size, hash_ptr)?;
+                Ok(None)
+            }
+
+            STORE_FN_WITH_ABI_INDEX => {
+                // args(0) = pointer to function name in Wasm memory
+                // args(1) = size of the name
+                // args(2) = pointer to additional unforgable names
+                //           to be saved with the function body
+                // args(3) = size of the additional unforgable names
+                // args(4) = pointer to serialized ABI in Wasm memory
+                // args(5) = size of the ABI
+                // args(6) = pointer to a Wasm memory where we will save
+                //           hash of the new function
+                let (name_ptr, name_size, urefs_ptr, urefs_size, abi_ptr, abi_size, hash_ptr) =
+                    Args::parse(args)?;
+                self.store_function_with_abi(
+                    name_ptr, name_size, urefs_ptr, urefs_size, abi_ptr, abi_size, hash_ptr,
+                )?;
+                Ok(None)
+            }
+
+            GET_CALLER_INDEX => {
+                // args(0) = pointer to Wasm memory where to write the caller's public key
+                let dest_ptr = Args::parse(args)?;
+                self.get_caller(dest_ptr)?;
+                Ok(None)
+            }
+
+            ROTATE_MAIN_PURSE_INDEX => {
+                // args(0) = pointer to Wasm memory where to write the new purse
+                let dest_ptr = Args::parse(args)?;
+                self.rotate_main_purse(dest_ptr)?;
+                Ok(None)
+            }
+
+            ADD_ASSOCIATED_KEY_INDEX => {
+                // args(0) = pointer to public key in Wasm memory
+                // args(1) = size of public key
+                // args(2) = weight of the key
+                let (public_key_ptr, public_key_size, weight) = Args::parse(args)?;
+                let result = self.add_associated_key(public_key_ptr, public_key_size, weight)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            REMOVE_ASSOCIATED_KEY_INDEX => {
+                // args(0) = pointer to public key in Wasm memory
+                // args(1) = size of public key
+                let (public_key_ptr, public_key_size) = Args::parse(args)?;
+                let result = self.remove_associated_key(public_key_ptr, public_key_size)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            SET_ACTION_THRESHOLD_INDEX => {
+                // args(0) = action type
+                // args(1) = new threshold
+                let (action_type, threshold) = Args::parse(args)?;
+                let result = self.set_action_threshold(action_type, threshold)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            CREATE_CONTRACT_PACKAGE_INDEX => {
+                // args(0) = pointer to Wasm memory where to write the package hash
+                // args(1) = pointer to Wasm memory where to write the package's access key
+                let (package_hash_ptr, access_key_ptr) = Args::parse(args)?;
+                self.create_contract_package(package_hash_ptr, access_key_ptr)?;
+                Ok(None)
+            }
+
+            ADD_CONTRACT_VERSION_INDEX => {
+                // args(0) = pointer to package key in Wasm memory
+                // args(1) = size of package key
+                // args(2) = pointer to package's access key in Wasm memory
+                // args(3) = size of access key
+                // args(4) = major version the contract is released under
+                // args(5) = pointer to key of the stored contract in Wasm memory
+                // args(6) = size of contract key
+                let (
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                    major,
+                    contract_key_ptr,
+                    contract_key_size,
+                ) = Args::parse(args)?;
+                let version = self.add_contract_version(
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                    major,
+                    contract_key_ptr,
+                    contract_key_size,
+                )?;
+                Ok(Some(RuntimeValue::I32(version as i32)))
+            }
+
+            LOCK_CONTRACT_PACKAGE_INDEX => {
+                // args(0) = pointer to package key in Wasm memory
+                // args(1) = size of package key
+                // args(2) = pointer to package's access key in Wasm memory
+                // args(3) = size of access key
+                let (package_key_ptr, package_key_size, access_key_ptr, access_key_size) =
+                    Args::parse(args)?;
+                self.lock_contract_package(
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                )?;
+                Ok(None)
+            }
+
+            CREATE_CONTRACT_USER_GROUP_INDEX => {
+                // args(0) = pointer to package key in Wasm memory
+                // args(1) = size of package key
+                // args(2) = pointer to package's access key in Wasm memory
+                // args(3) = size of access key
+                // args(4) = pointer to group name in Wasm memory
+                // args(5) = size of group name
+                // args(6) = pointer to Wasm memory where to write the new URef
+                let (
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                    group_name_ptr,
+                    group_name_size,
+                    dest_ptr,
+                ) = Args::parse(args)?;
+                self.create_contract_user_group(
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                    group_name_ptr,
+                    group_name_size,
+                    dest_ptr,
+                )?;
+                Ok(None)
+            }
+
+            REMOVE_CONTRACT_USER_GROUP_INDEX => {
+                // args(0) = pointer to package key in Wasm memory
+                // args(1) = size of package key
+                // args(2) = pointer to package's access key in Wasm memory
+                // args(3) = size of access key
+                // args(4) = pointer to group name in Wasm memory
+                // args(5) = size of group name
+                let (
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                    group_name_ptr,
+                    group_name_size,
+                ) = Args::parse(args)?;
+                self.remove_contract_user_group(
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                    group_name_ptr,
+                    group_name_size,
+                )?;
+                Ok(None)
+            }
+
+            PUT_KEY_INDEX => {
+                // args(0) = pointer to key name in Wasm memory
+                // args(1) = size of key name
+                // args(2) = pointer to key in Wasm memory
+                // args(3) = size of key
+                let (name_ptr, name_size, key_ptr, key_size) = Args::parse(args)?;
+                self.put_key(name_ptr, name_size, key_ptr, key_size)?;
+                Ok(None)
+            }
+
+            REMOVE_KEY_INDEX => {
+                // args(0) = pointer to key name in Wasm memory
+                // args(1) = size of key name
+                let (name_ptr, name_size) = Args::parse(args)?;
+                self.remove_key(name_ptr, name_size)?;
+                Ok(None)
+            }
+
+            GET_KEY_INDEX => {
+                // args(0) = pointer to key name in Wasm memory
+                // args(1) = size of key name
+                // args(2) = pointer to destination in Wasm memory
+                let (name_ptr, name_size, dest_ptr) = Args::parse(args)?;
+                let size = self.get_key(name_ptr, name_size, dest_ptr)?;
+                Ok(Some(RuntimeValue::I32(size)))
+            }
+
+            STORE_LIBRARY_INDEX => {
+                // args(0) = pointer to serialized names of the library's functions
+                // args(1) = size of the names
+                // args(2) = pointer to destination of the library's hash
+                let (names_ptr, names_size, hash_ptr) = Args::parse(args)?;
+                self.store_library(names_ptr, names_size, hash_ptr)?;
+                Ok(None)
+            }
+
+            DISABLE_CONTRACT_VERSION_INDEX => {
+                // args(0) = pointer to package key in Wasm memory
+                // args(1) = size of package key
+                // args(2) = pointer to package's access key in Wasm memory
+                // args(3) = size of access key
+                // args(4) = number of the version to disable
+                let (package_key_ptr, package_key_size, access_key_ptr, access_key_size, version) =
+                    Args::parse(args)?;
+                self.disable_contract_version(
+                    package_key_ptr,
+                    package_key_size,
+                    access_key_ptr,
+                    access_key_size,
+                    version,
+                )?;
+                Ok(None)
+            }
+
+            GET_PHASE_INDEX => {
+                // args(0) = pointer to destination in Wasm memory
+                let dest_ptr = Args::parse(args)?;
+                self.get_phase(dest_ptr)?;
+                Ok(None)
+            }
+
+            REVERT_INDEX => {
+                // args(0) = status code of the error
+                let status = Args::parse(args)?;
+                Err(self.revert(status))
+            }
+
+            TRANSFER_TO_ACCOUNT_INDEX => {
+                // args(0) = pointer to public key of the target account in Wasm memory
+                // args(1) = size of public key
+                // args(2) = pointer to amount in Wasm memory
+                // args(3) = size of amount
+                let (target_ptr, target_size, amount_ptr, amount_size) = Args::parse(args)?;
+                let result =
+                    self.transfer_to_account(target_ptr, target_size, amount_ptr, amount_size)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            TRANSFER_FROM_PURSE_TO_PURSE_INDEX => {
+                // args(0) = pointer to source purse in Wasm memory
+                // args(1) = size of source purse
+                // args(2) = pointer to target purse in Wasm memory
+                // args(3) = size of target purse
+                // args(4) = pointer to amount in Wasm memory
+                // args(5) = size of amount
+                let (source_ptr, source_size, target_ptr, target_size, amount_ptr, amount_size) =
+                    Args::parse(args)?;
+                let result = self.transfer_from_purse_to_purse(
+                    source_ptr,
+                    source_size,
+                    target_ptr,
+                    target_size,
+                    amount_ptr,
+                    amount_size,
+                )?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            CREATE_PURSE_INDEX => {
+                // args(0) = pointer to Wasm memory where to write the new purse
+                let dest_ptr = Args::parse(args)?;
+                self.create_purse(dest_ptr)?;
+                Ok(None)
+            }
+
+            GET_MAIN_PURSE_INDEX => {
+                // args(0) = pointer to Wasm memory where to write the main purse
+                let dest_ptr = Args::parse(args)?;
+                self.get_main_purse(dest_ptr)?;
+                Ok(None)
+            }
+
+            BLAKE2B_INDEX => {
+                // args(0) = pointer to data in Wasm memory
+                // args(1) = size of data
+                // args(2) = pointer to Wasm memory where to write the 32 byte digest
+                let (in_ptr, in_size, out_ptr) = Args::parse(args)?;
+                self.blake2b(in_ptr, in_size, out_ptr)?;
+                Ok(None)
+            }
+
+            VERIFY_ED25519_INDEX => {
+                // args(0) = pointer to message in Wasm memory
+                // args(1) = size of message
+                // args(2) = pointer to 64 byte signature in Wasm memory
+                // args(3) = pointer to 32 byte public key in Wasm memory
+                let (message_ptr, message_size, signature_ptr, public_key_ptr) =
+                    Args::parse(args)?;
+                let result =
+                    self.verify_ed25519(message_ptr, message_size, signature_ptr, public_key_ptr)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            VERIFY_SECP256K1_INDEX => {
+                // args(0) = pointer to 32 byte digest in Wasm memory
+                // args(1) = pointer to 64 byte signature in Wasm memory
+                // args(2) = pointer to 65 byte public key in Wasm memory
+                let (digest_ptr, signature_ptr, public_key_ptr) = Args::parse(args)?;
+                let result = self.verify_secp256k1(digest_ptr, signature_ptr, public_key_ptr)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            RECOVER_SECP256K1_INDEX => {
+                // args(0) = pointer to 32 byte digest in Wasm memory
+                // args(1) = pointer to 64 byte signature in Wasm memory
+                // args(2) = recovery id
+                // args(3) = pointer to Wasm memory where to write the 65 byte public key
+                let (digest_ptr, signature_ptr, recovery_id, dest_ptr) = Args::parse(args)?;
+                let result =
+                    self.recover_secp256k1(digest_ptr, signature_ptr, recovery_id, dest_ptr)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            RANDOM_BYTES_INDEX => {
+                // args(0) = pointer to Wasm memory where to write the 32 random bytes
+                let dest_ptr = Args::parse(args)?;
+                self.random_bytes(dest_ptr)?;
+                Ok(None)
+            }
+
+            EMIT_EVENT_INDEX => {
+                // args(0) = pointer to topic in Wasm memory
+                // args(1) = size of topic
+                // args(2) = pointer to data in Wasm memory
+                // args(3) = size of data
+                let (topic_ptr, topic_size, data_ptr, data_size) = Args::parse(args)?;
+                self.emit_event(topic_ptr, topic_size, data_ptr, data_size)?;
+                Ok(None)
+            }
+
+            PRINT_INDEX => {
+                // args(0) = pointer to message in Wasm memory
+                // args(1) = size of message
+                let (message_ptr, message_size) = Args::parse(args)?;
+                self.print(message_ptr, message_size)?;
+                Ok(None)
+            }
+
+            LOAD_NAMED_ARG_INDEX => {
+                // args(0) = pointer to name of the argument in Wasm memory
+                // args(1) = size of name
+                let (name_ptr, name_size) = Args::parse(args)?;
+                let size = self.load_named_arg(name_ptr, name_size)?;
+                Ok(Some(RuntimeValue::I32(size.map_or(-1, |size| size as i32))))
+            }
+
+            LIST_NAMED_KEYS_INDEX => {
+                // args(0) = index of the first named key to list
+                // args(1) = maximal number of named keys to list
+                let (offset, limit) = Args::parse(args)?;
+                let size = self.list_named_keys(offset, limit)?;
+                Ok(Some(RuntimeValue::I32(size as i32)))
+            }
+
+            REMOVE_UREF_INDEX => {
+                // args(0) = pointer to name in Wasm memory
+                // args(1) = size of name
+                let (name_ptr, name_size) = Args::parse(args)?;
+                self.remove_uref(name_ptr, name_size)?;
+                Ok(None)
+            }
+
+            IS_VALID_INDEX => {
+                // args(0) = pointer to key in Wasm memory
+                // args(1) = size of key
+                let (key_ptr, key_size) = Args::parse(args)?;
+                let is_valid = self.is_valid(key_ptr, key_size)?;
+                Ok(Some(RuntimeValue::I32(is_valid as i32)))
+            }
+
+            READ_LOCAL_INDEX => {
+                // args(0) = pointer to key bytes in Wasm memory
+                // args(1) = size of key bytes
+                let (key_ptr, key_size) = Args::parse(args)?;
+                let size = self.read_local(key_ptr, key_size)?;
+                Ok(Some(RuntimeValue::I32(size as i32)))
+            }
+
+            WRITE_LOCAL_INDEX => {
+                // args(0) = pointer to key bytes in Wasm memory
+                // args(1) = size of key bytes
+                // args(2) = pointer to value
+                // args(3) = size of value
+                let (key_ptr, key_size, value_ptr, value_size) = Args::parse(args)?;
+                self.write_local(key_ptr, key_size, value_ptr, value_size)?;
+                Ok(None)
+            }
+
+            GET_SYSTEM_CONTRACT_INDEX => {
+                // args(0) = id of the system contract
+                // args(1) = pointer to destination in Wasm memory
+                let (id, dest_ptr) = Args::parse(args)?;
+                let size = self.get_system_contract(id, dest_ptr)?;
+                Ok(Some(RuntimeValue::I32(size as i32)))
+            }
+
+            CALL_CONTRACT_WITH_GAS_INDEX => {
+                // args(0) = pointer to key where contract is at in global state
+                // args(1) = size of key
+                // args(2) = pointer to function arguments in Wasm memory
+                // args(3) = size of arguments
+                // args(4) = pointer to extra supplied urefs
+                // args(5) = size of extra urefs
+                // args(6) = gas the callee can spend at most
+                let (
+                    key_ptr,
+                    key_size,
+                    args_ptr,
+                    args_size,
+                    extra_urefs_ptr,
+                    extra_urefs_size,
+                    gas_allowance,
+                ) = Args::parse(args)?;
+
+                let result = self.call_contract_with_gas(
+                    key_ptr,
+                    as_usize(key_size),
+                    args_ptr,
+                    as_usize(args_size),
+                    extra_urefs_ptr,
+                    as_usize(extra_urefs_size),
+                    gas_allowance,
+                );
+                // Running out of the allowance is reported to the caller as -1.
+                match result {
+                    Ok(size) => Ok(Some(RuntimeValue::I32(size as i32))),
+                    Err(Error::CalleeGasLimit) => Ok(Some(RuntimeValue::I32(-1))),
+                    Err(error) => Err(error.into()),
+                }
+            }
+
+            UPGRADE_CONTRACT_AT_UREF_INDEX => {
+                // args(0) = pointer to name of the function in Wasm memory
+                // args(1) = size of the name
+                // args(2) = pointer to the URef of the contract
+                // args(3) = size of the URef
+                let (name_ptr, name_size, key_ptr, key_size) = Args::parse(args)?;
+                self.upgrade_contract_at_uref(name_ptr, name_size, key_ptr, key_size)?;
+                Ok(None)
+            }
+
+            GAS_REMAINING_INDEX => {
+                Ok(Some(RuntimeValue::I64(self.gas_remaining().as_u64() as i64)))
+            }
+
+            READ_MULTI_INDEX => {
+                // args(0) = pointer to serialized keys in Wasm memory
+                // args(1) = size of the keys
+                let (keys_ptr, keys_size) = Args::parse(args)?;
+                let size = self.read_multi(keys_ptr, keys_size)?;
+                Ok(Some(RuntimeValue::I32(size as i32)))
+            }
+
+            WRITE_MULTI_INDEX => {
+                // args(0) = pointer to serialized key-value pairs in Wasm memory
+                // args(1) = size of the pairs
+                let (entries_ptr, entries_size) = Args::parse(args)?;
+                self.write_multi(entries_ptr, entries_size)?;
+                Ok(None)
+            }
+
+            READ_HOST_BUFFER_INDEX => {
+                // args(0) = pointer to destination in Wasm memory
+                // args(1) = size of the destination
+                let (dest_ptr, dest_size) = Args::parse(args)?;
+                let size = self.read_host_buffer(dest_ptr, as_usize(dest_size))?;
+                Ok(Some(RuntimeValue::I32(size as i32)))
+            }
+
+            NEW_UREF_WITH_RIGHTS_INDEX => {
                 // args(0) = pointer to key destination in Wasm memory
                 // args(1) = pointer to initial value
                 // args(2) = size of initial value
-                let (key_ptr, value_ptr, value_size) = Args::parse(args)?;
-                self.new_uref(key_ptr, value_ptr, value_size)?;
+                // args(3) = bits of the access rights
+                let (key_ptr, value_ptr, value_size, rights) = Args::parse(args)?;
+                self.new_uref_with_rights(key_ptr, value_ptr, value_size, rights)?;
                 Ok(None)
             }
 
-            GET_READ_FUNC_INDEX => {
+            GET_DEPLOY_HASH_INDEX => {
                 // args(0) = pointer to destination in Wasm memory
                 let dest_ptr = Args::parse(args)?;
-                self.set_mem_from_buf(dest_ptr)?;
+                self.get_deploy_hash(dest_ptr)?;
                 Ok(None)
             }
 
-            GET_FN_FUNC_INDEX => {
+            GET_PROTOCOL_VERSION_INDEX => {
                 // args(0) = pointer to destination in Wasm memory
                 let dest_ptr = Args::parse(args)?;
-                self.set_mem_from_buf(dest_ptr)?;
+                self.get_protocol_version(dest_ptr)?;
                 Ok(None)
             }
 
-            LOAD_ARG_FUNC_INDEX => {
-                // args(0) = index of host runtime arg to load
-                let i = Args::parse(args)?;
-                let size = self.load_arg(i)?;
+            DICTIONARY_GET_INDEX => {
+                // args(0) = pointer to the seed URef in Wasm memory
+                // args(1) = size of the seed URef
+                // args(2) = pointer to item key bytes
+                // args(3) = size of item key bytes
+                let (seed_ptr, seed_size, key_ptr, key_size) = Args::parse(args)?;
+                let size = self.dictionary_get(seed_ptr, seed_size, key_ptr, key_size)?;
                 Ok(Some(RuntimeValue::I32(size as i32)))
             }
 
-            GET_ARG_FUNC_INDEX => {
-                // args(0) = pointer to destination in Wasm memory
-                let dest_ptr = Args::parse(args)?;
-                self.set_mem_from_buf(dest_ptr)?;
+            DICTIONARY_PUT_INDEX => {
+                // args(0) = pointer to the seed URef in Wasm memory
+                // args(1) = size of the seed URef
+                // args(2) = pointer to item key bytes
+                // args(3) = size of item key bytes
+                // args(4) = pointer to value
+                // args(5) = size of value
+                let (seed_ptr, seed_size, key_ptr, key_size, value_ptr, value_size) =
+                    Args::parse(args)?;
+                self.dictionary_put(
+                    seed_ptr,
+                    seed_size,
+                    key_ptr,
+                    key_size,
+                    value_ptr,
+                    value_size,
+                )?;
                 Ok(None)
             }
 
-            RET_FUNC_INDEX => {
-                // args(0) = pointer to value
-                // args(1) = size of value
-                // args(2) = pointer to extra returned urefs
-                // args(3) = size of extra urefs
-                let (value_ptr, value_size, extra_urefs_ptr, extra_urefs_size) = Args::parse(args)?;
-
-                Err(self.ret(
-                    value_ptr,
-                    as_usize(value_size),
-                    extra_urefs_ptr,
-                    as_usize(extra_urefs_size),
-                ))
-            }
+            CALL_STACK_DEPTH_INDEX => Ok(Some(RuntimeValue::I32(self.call_stack_depth() as i32))),
 
-            CALL_CONTRACT_FUNC_INDEX => {
-                // args(0) = pointer to key where contract is at in global state
+            VALIDATE_KEY_INDEX => {
+                // args(0) = pointer to key in Wasm memory
                 // args(1) = size of key
-                // args(2) = pointer to function arguments in Wasm memory
-                // args(3) = size of arguments
-                // args(4) = pointer to extra supplied urefs
-                // args(5) = size of extra urefs
-                let (key_ptr, key_size, args_ptr, args_size, extra_urefs_ptr, extra_urefs_size) =
-                    Args::parse(args)?;
-
-                let size = self.call_contract(
-                    key_ptr,
-                    as_usize(key_size),
-                    args_ptr,
-                    as_usize(args_size),
-                    extra_urefs_ptr,
-                    as_usize(extra_urefs_size),
-                )?;
-                Ok(Some(RuntimeValue::I32(size as i32)))
+                // args(2) = bits of the required access rights
+                let (key_ptr, key_size, required) = Args::parse(args)?;
+                let result = self.validate_key(key_ptr, key_size, required)?;
+                Ok(Some(RuntimeValue::I32(result)))
             }
 
-            GET_CALL_RESULT_FUNC_INDEX => {
-                // args(0) = pointer to destination in Wasm memory
+            GET_BLOCK_HEIGHT_INDEX => Ok(Some(RuntimeValue::I64(self.get_block_height() as i64))),
+
+            GET_PAYMENT_PURSE_INDEX => {
+                // args(0) = pointer to Wasm memory where to write the payment purse
                 let dest_ptr = Args::parse(args)?;
-                self.set_mem_from_buf(dest_ptr)?;
+                self.get_payment_purse(dest_ptr)?;
                 Ok(None)
             }
 
-            GET_UREF_FUNC_INDEX => {
-                // args(0) = pointer to uref name in Wasm memory
-                // args(1) = size of uref name
-                // args(2) = pointer to destination in Wasm memory
-                let (name_ptr, name_size, dest_ptr) = Args::parse(args)?;
-                self.get_uref(name_ptr, name_size, dest_ptr)?;
-                Ok(None)
+            BOND_INDEX => {
+                // args(0) = pointer to amount in Wasm memory
+                // args(1) = size of amount
+                // args(2) = pointer to source purse in Wasm memory
+                // args(3) = size of source purse
+                let (amount_ptr, amount_size, purse_ptr, purse_size) = Args::parse(args)?;
+                let result = self.bond(amount_ptr, amount_size, purse_ptr, purse_size)?;
+                Ok(Some(RuntimeValue::I32(result)))
             }
 
-            HAS_UREF_FUNC_INDEX => {
-                // args(0) = pointer to uref name in Wasm memory
-                // args(1) = size of uref name
-                let (name_ptr, name_size) = Args::parse(args)?;
-                let result = self.has_uref(name_ptr, name_size)?;
+            UNBOND_INDEX => {
+                // args(0) = pointer to amount in Wasm memory
+                // args(1) = size of amount
+                let (amount_ptr, amount_size) = Args::parse(args)?;
+                let result = self.unbond(amount_ptr, amount_size)?;
                 Ok(Some(RuntimeValue::I32(result)))
             }
 
-            ADD_UREF_FUNC_INDEX => {
-                // args(0) = pointer to uref name in Wasm memory
-                // args(1) = size of uref name
-                // args(2) = pointer to destination in Wasm memory
-                let (name_ptr, name_size, key_ptr, key_size) = Args::parse(args)?;
-                self.add_uref(name_ptr, name_size, key_ptr, key_size)?;
-                Ok(None)
+            DELEGATE_INDEX => {
+                // args(0) = pointer to validator public key in Wasm memory
+                // args(1) = size of validator public key
+                // args(2) = pointer to amount in Wasm memory
+                // args(3) = size of amount
+                // args(4) = pointer to source purse in Wasm memory
+                // args(5) = size of source purse
+                let (key_ptr, key_size, amount_ptr, amount_size, purse_ptr, purse_size) =
+                    Args::parse(args)?;
+                let result = self.delegate(
+                    key_ptr,
+                    key_size,
+                    amount_ptr,
+                    amount_size,
+                    purse_ptr,
+                    purse_size,
+                )?;
+                Ok(Some(RuntimeValue::I32(result)))
             }
 
-            GAS_FUNC_INDEX => {
-                let gas: u32 = Args::parse(args)?;
-                self.gas(u64::from(gas))?;
-                Ok(None)
+            UNDELEGATE_INDEX => {
+                // args(0) = pointer to validator public key in Wasm memory
+                // args(1) = size of validator public key
+                // args(2) = pointer to amount in Wasm memory
+                // args(3) = size of amount
+                let (validator_ptr, validator_size, amount_ptr, amount_size) = Args::parse(args)?;
+                let result =
+                    self.undelegate(validator_ptr, validator_size, amount_ptr, amount_size)?;
+                Ok(Some(RuntimeValue::I32(result)))
             }
 
-            STORE_FN_INDEX => {
-                // args(0) = pointer to function name in Wasm memory
-                // args(1) = size of the name
-                // args(2) = pointer to additional unforgable names
-                //           to be saved with the function body
-                // args(3) = size of the additional unforgable names
-                // args(4) = pointer to a Wasm memory where we will save
-                //           hash of the new function
-                let (name_ptr, name_size, urefs_ptr, urefs_size, hash_ptr) = Args::parse(args)?;
-                self.store_function(name_ptr, name_size, urefs_ptr, urefs_size, hash_ptr)?;
-                Ok(None)
+            ADD_BID_INDEX => {
+                // args(0) = pointer to amount in Wasm memory
+                // args(1) = size of amount
+                // args(2) = pointer to source purse in Wasm memory
+                // args(3) = size of source purse
+                let (amount_ptr, amount_size, purse_ptr, purse_size) = Args::parse(args)?;
+                let result = self.add_bid(amount_ptr, amount_size, purse_ptr, purse_size)?;
+                Ok(Some(RuntimeValue::I32(result)))
+            }
+
+            WITHDRAW_BID_INDEX => {
+                // args(0) = pointer to amount in Wasm memory
+                // args(1) = size of amount
+                let (amount_ptr, amount_size) = Args::parse(args)?;
+                let result = self.withdraw_bid(amount_ptr, amount_size)?;
+                Ok(Some(RuntimeValue::I32(result)))
             }
 
             _ => panic!("unknown function index"),
@@ -951,6 +3520,10 @@ impl ModuleImportResolver for RuntimeModuleImportResolver {
                 Signature::new(&[ValueType::I32; 6][..], Some(ValueType::I32)),
                 CALL_CONTRACT_FUNC_INDEX,
             ),
+            "call_versioned_contract" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 8][..], Some(ValueType::I32)),
+                CALL_VERSIONED_CONTRACT_INDEX,
+            ),
             "get_call_result" => FuncInstance::alloc_host(
                 Signature::new(&[ValueType::I32; 1][..], None),
                 GET_CALL_RESULT_FUNC_INDEX,
@@ -975,6 +3548,249 @@ impl ModuleImportResolver for RuntimeModuleImportResolver {
                 Signature::new(&[ValueType::I32; 5][..], None),
                 STORE_FN_INDEX,
             ),
+            "store_function_at_hash" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 5][..], None),
+                STORE_FN_AT_HASH_INDEX,
+            ),
+            "store_function_with_abi" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 7][..], None),
+                STORE_FN_WITH_ABI_INDEX,
+            ),
+            "get_caller" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                GET_CALLER_INDEX,
+            ),
+            "rotate_main_purse" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                ROTATE_MAIN_PURSE_INDEX,
+            ),
+            "add_associated_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 3][..], Some(ValueType::I32)),
+                ADD_ASSOCIATED_KEY_INDEX,
+            ),
+            "remove_associated_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                REMOVE_ASSOCIATED_KEY_INDEX,
+            ),
+            "set_action_threshold" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                SET_ACTION_THRESHOLD_INDEX,
+            ),
+            "create_contract_package" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], None),
+                CREATE_CONTRACT_PACKAGE_INDEX,
+            ),
+            "add_contract_version" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 7][..], Some(ValueType::I32)),
+                ADD_CONTRACT_VERSION_INDEX,
+            ),
+            "lock_contract_package" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], None),
+                LOCK_CONTRACT_PACKAGE_INDEX,
+            ),
+            "create_contract_user_group" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 7][..], None),
+                CREATE_CONTRACT_USER_GROUP_INDEX,
+            ),
+            "remove_contract_user_group" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 6][..], None),
+                REMOVE_CONTRACT_USER_GROUP_INDEX,
+            ),
+            "put_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], None),
+                PUT_KEY_INDEX,
+            ),
+            "remove_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], None),
+                REMOVE_KEY_INDEX,
+            ),
+            "get_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 3][..], Some(ValueType::I32)),
+                GET_KEY_INDEX,
+            ),
+            "store_library" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 3][..], None),
+                STORE_LIBRARY_INDEX,
+            ),
+            "disable_contract_version" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 5][..], None),
+                DISABLE_CONTRACT_VERSION_INDEX,
+            ),
+            "get_phase" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                GET_PHASE_INDEX,
+            ),
+            "revert" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                REVERT_INDEX,
+            ),
+            "transfer_to_account" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], Some(ValueType::I32)),
+                TRANSFER_TO_ACCOUNT_INDEX,
+            ),
+            "transfer_from_purse_to_purse" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 6][..], Some(ValueType::I32)),
+                TRANSFER_FROM_PURSE_TO_PURSE_INDEX,
+            ),
+            "create_purse" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                CREATE_PURSE_INDEX,
+            ),
+            "get_main_purse" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                GET_MAIN_PURSE_INDEX,
+            ),
+            "blake2b" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 3][..], None),
+                BLAKE2B_INDEX,
+            ),
+            "verify_ed25519" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], Some(ValueType::I32)),
+                VERIFY_ED25519_INDEX,
+            ),
+            "verify_secp256k1" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 3][..], Some(ValueType::I32)),
+                VERIFY_SECP256K1_INDEX,
+            ),
+            "recover_secp256k1" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], Some(ValueType::I32)),
+                RECOVER_SECP256K1_INDEX,
+            ),
+            "random_bytes" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                RANDOM_BYTES_INDEX,
+            ),
+            "emit_event" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], None),
+                EMIT_EVENT_INDEX,
+            ),
+            "print" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], None),
+                PRINT_INDEX,
+            ),
+            "load_named_arg" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                LOAD_NAMED_ARG_INDEX,
+            ),
+            "list_named_keys" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                LIST_NAMED_KEYS_INDEX,
+            ),
+            "remove_uref" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], None),
+                REMOVE_UREF_INDEX,
+            ),
+            "is_valid" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                IS_VALID_INDEX,
+            ),
+            "read_local" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                READ_LOCAL_INDEX,
+            ),
+            "write_local" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], None),
+                WRITE_LOCAL_INDEX,
+            ),
+            "get_system_contract" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                GET_SYSTEM_CONTRACT_INDEX,
+            ),
+            "call_contract_with_gas" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I64,
+                    ][..],
+                    Some(ValueType::I32),
+                ),
+                CALL_CONTRACT_WITH_GAS_INDEX,
+            ),
+            "upgrade_contract_at_uref" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], None),
+                UPGRADE_CONTRACT_AT_UREF_INDEX,
+            ),
+            "gas_remaining" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I64)),
+                GAS_REMAINING_INDEX,
+            ),
+            "read_multi" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                READ_MULTI_INDEX,
+            ),
+            "write_multi" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], None),
+                WRITE_MULTI_INDEX,
+            ),
+            "read_host_buffer" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                READ_HOST_BUFFER_INDEX,
+            ),
+            "new_uref_with_rights" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], None),
+                NEW_UREF_WITH_RIGHTS_INDEX,
+            ),
+            "get_deploy_hash" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                GET_DEPLOY_HASH_INDEX,
+            ),
+            "get_protocol_version" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                GET_PROTOCOL_VERSION_INDEX,
+            ),
+            "dictionary_get" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], Some(ValueType::I32)),
+                DICTIONARY_GET_INDEX,
+            ),
+            "dictionary_put" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 6][..], None),
+                DICTIONARY_PUT_INDEX,
+            ),
+            "call_stack_depth" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I32)),
+                CALL_STACK_DEPTH_INDEX,
+            ),
+            "validate_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 3][..], Some(ValueType::I32)),
+                VALIDATE_KEY_INDEX,
+            ),
+            "get_block_height" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I64)),
+                GET_BLOCK_HEIGHT_INDEX,
+            ),
+            "get_payment_purse" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 1][..], None),
+                GET_PAYMENT_PURSE_INDEX,
+            ),
+            "bond" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], Some(ValueType::I32)),
+                BOND_INDEX,
+            ),
+            "unbond" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                UNBOND_INDEX,
+            ),
+            "delegate" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 6][..], Some(ValueType::I32)),
+                DELEGATE_INDEX,
+            ),
+            "undelegate" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], Some(ValueType::I32)),
+                UNDELEGATE_INDEX,
+            ),
+            "add_bid" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], Some(ValueType::I32)),
+                ADD_BID_INDEX,
+            ),
+            "withdraw_bid" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 2][..], Some(ValueType::I32)),
+                WITHDRAW_BID_INDEX,
+            ),
             _ => {
                 return Err(InterpreterError::Function(format!(
                     "host module doesn't export function with name {}",
@@ -1013,15 +3829,72 @@ impl ModuleImportResolver for RuntimeModuleImportResolver {
     }
 }
 
-fn instance_and_memory(parity_module: Module) -> Result<(ModuleRef, MemoryRef), Error> {
-    let module = wasmi::Module::from_parity_wasm_module(parity_module)?;
-    let resolver = RuntimeModuleImportResolver::new();
-    let mut imports = ImportsBuilder::new();
-    imports.push_resolver("env", &resolver);
-    let instance = ModuleInstance::new(&module, &imports)?.assert_no_start();
+fn has_export(module: &Module, name: &str) -> bool {
+    module
+        .export_section()
+        .and_then(|es| es.entries().iter().find(|e| e.field() == name))
+        .is_some()
+}
+
+/// Instantiates `parity_module` together with the stored libraries it imports
+/// functions from. Each library gets its own memory. Calls into libraries are
+/// plain Wasm calls, which only keep a weak reference to the library's
+/// instance, so the returned library instances have to be kept alive for as
+/// long as the module is executed.
+fn instance_and_memory<R: StateReader<Key, Value>>(
+    parity_module: Module,
+    state: &mut TrackingCopy<R>,
+) -> Result<(ModuleRef, MemoryRef, Vec<(String, ModuleRef)>), Error>
+where
+    R::Error: Into<Error>,
+{
+    let library_hashes: BTreeMap<String, [u8; 32]> = parity_module
+        .import_section()
+        .map(|imports| {
+            imports
+                .entries()
+                .iter()
+                .filter_map(|entry| {
+                    wasm_prep::library_hash(entry.module())
+                        .map(|hash| (entry.module().to_string(), hash))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut libraries = Vec::with_capacity(library_hashes.len());
+    for (name, hash) in library_hashes {
+        let library_key = Key::Hash(hash);
+        let library = match state.read(library_key).map_err(Into::into)? {
+            Some(Value::Contract(library)) => library,
+            Some(other) => {
+                return Err(Error::TypeMismatch(TypeMismatch::new(
+                    "Contract".to_string(),
+                    other.type_string(),
+                )))
+            }
+            None => return Err(Error::KeyNotFound(library_key)),
+        };
+        let library_module = parity_wasm::deserialize_buffer(library.bytes())?;
+        let library_module = wasmi::Module::from_parity_wasm_module(library_module)?;
+        let resolver = RuntimeModuleImportResolver::new();
+        let mut imports = ImportsBuilder::new();
+        imports.push_resolver("env", &resolver);
+        let instance = ModuleInstance::new(&library_module, &imports)?.assert_no_start();
+        libraries.push((name, instance));
+    }
 
-    let memory = resolver.mem_ref()?;
-    Ok((instance, memory))
+    let (instance, memory) = {
+        let module = wasmi::Module::from_parity_wasm_module(parity_module)?;
+        let resolver = RuntimeModuleImportResolver::new();
+        let mut imports = ImportsBuilder::new();
+        imports.push_resolver("env", &resolver);
+        for (name, library) in &libraries {
+            imports.push_resolver(name.as_str(), library);
+        }
+        let instance = ModuleInstance::new(&module, &imports)?.assert_no_start();
+        (instance, resolver.mem_ref()?)
+    };
+    Ok((instance, memory, libraries))
 }
 
 fn sub_call<R: StateReader<Key, Value>>(
@@ -1033,13 +3906,15 @@ fn sub_call<R: StateReader<Key, Value>>(
     // Unforgable references passed across the call boundary from caller to callee
     //(necessary if the contract takes a uref argument).
     extra_urefs: Vec<Key>,
+    // Gas counter value at which the callee runs out of gas.
+    gas_limit: Gas,
 ) -> Result<Vec<u8>, Error>
 where
     R::Error: Into<Error>,
 {
-    let (instance, memory) = instance_and_memory(parity_module.clone())?;
+    let (instance, memory, _libraries) =
+        instance_and_memory(parity_module.clone(), current_runtime.state)?;
     let known_urefs = vec_key_rights_to_map(refs.values().cloned().chain(extra_urefs));
-    let rng = ChaChaRng::from_rng(&mut current_runtime.rng).map_err(Error::Rng)?;
     let mut runtime = Runtime {
         args,
         memory,
@@ -1047,19 +3922,43 @@ where
         module: parity_module,
         result: Vec::new(),
         host_buf: Vec::new(),
-        fn_store_id: 0,
         gas_counter: current_runtime.gas_counter,
         context: RuntimeContext {
             uref_lookup: refs,
             known_urefs,
             account: current_runtime.context.account,
             base_key: key,
-            gas_limit: current_runtime.context.gas_limit,
+            authorization_keys: current_runtime.context.authorization_keys.clone(),
+            gas_limit,
+            phase: current_runtime.context.phase,
+            deploy_hash: current_runtime.context.deploy_hash,
+            protocol_version: current_runtime.context.protocol_version,
+            block_height: current_runtime.context.block_height,
         },
-        rng,
+        address_generator: current_runtime.address_generator.clone(),
+        transfer_address_generator: current_runtime.transfer_address_generator.clone(),
+        random_generator: current_runtime.random_generator.clone(),
+        events: std::mem::replace(&mut current_runtime.events, Vec::new()),
+        contract_debug: current_runtime.contract_debug,
+        // Named arguments are passed by deploys only.
+        named_args: RuntimeArgs::new(),
+        system_contracts: current_runtime.system_contracts.clone(),
+        host_function_costs: current_runtime.host_function_costs.clone(),
+        host_function_cost: current_runtime.host_function_cost,
+        call_depth: current_runtime.call_depth + 1,
+        max_call_depth: current_runtime.max_call_depth,
+        pos_delays: current_runtime.pos_delays,
+        key_limits: current_runtime.key_limits,
     };
 
     let result = instance.invoke_export("call", &[], &mut runtime);
+    // The gas the callee used is charged to the caller even if the call
+    // failed; otherwise failing calls would compute for free.
+    current_runtime.gas_counter = runtime.gas_counter;
+    current_runtime.random_generator = runtime.random_generator.clone();
+    current_runtime.address_generator = runtime.address_generator.clone();
+    current_runtime.transfer_address_generator = runtime.transfer_address_generator.clone();
+    current_runtime.events = std::mem::replace(&mut runtime.events, Vec::new());
 
     match result {
         Ok(_) => Ok(runtime.result),
@@ -1076,7 +3975,7 @@ where
                     return Ok(runtime.result);
                 }
             }
-            Err(Error::Interpreter(e))
+            Err(e.into())
         }
     }
 }
@@ -1100,15 +3999,26 @@ fn vec_key_rights_to_map<I: IntoIterator<Item = Key>>(
         .collect()
 }
 
-fn create_rng(account_addr: &[u8; 20], timestamp: u64, nonce: u64) -> ChaChaRng {
-    let mut seed: [u8; 32] = [0u8; 32];
-    let mut data: Vec<u8> = Vec::new();
-    let hasher = VarBlake2b::new(32).unwrap();
-    data.extend(account_addr);
-    data.extend_from_slice(&timestamp.to_le_bytes());
-    data.extend_from_slice(&nonce.to_le_bytes());
-    hasher.variable_result(|hash| seed.clone_from_slice(hash));
-    ChaChaRng::from_seed(seed)
+/// Address of the account owned by `public_key`: the first 20 bytes of the
+/// Blake2b-256 hash of the key.
+pub fn account_address(public_key: PublicKey) -> [u8; 20] {
+    let mut addr = [0u8; 20];
+    let mut hasher = VarBlake2b::new(32).unwrap();
+    hasher.input(public_key.value());
+    hasher.variable_result(|hash| addr.copy_from_slice(&hash[..20]));
+    addr
+}
+
+/// Address of the item called `key_bytes` in the dictionary seeded by the
+/// URef with `seed_addr`. Only the address of the seed is hashed in, so every
+/// version of the URef reaches the same items, and as base keys are never
+/// URefs the items can't collide with local storage.
+pub fn dictionary_item_key(seed_addr: [u8; 32], key_bytes: &[u8]) -> Result<Key, Error> {
+    let mut data = Key::URef(seed_addr, AccessRights::READ_ADD_WRITE).to_bytes()?;
+    data.extend_from_slice(key_bytes);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Blake2bHash::new(&data).to_vec());
+    Ok(Key::Local(hash))
 }
 
 #[macro_export]
@@ -1130,12 +4040,16 @@ pub trait Executor<A> {
         &self,
         parity_module: A,
         args: &[u8],
+        named_args: &[u8],
         account_addr: [u8; 20],
-        timestamp: u64,
-        nonce: u64,
-        gas_limit: u64,
+        authorization_keys: BTreeSet<PublicKey>,
+        deploy_hash: [u8; 32],
+        block_height: u64,
+        gas_limit: Gas,
+        phase: Phase,
+        config: &EngineConfig,
         tc: &mut TrackingCopy<R>,
-    ) -> (Result<ExecutionEffect, Error>, u64)
+    ) -> (Result<ExecutionEffect, Error>, Gas)
     where
         R::Error: Into<Error>;
 }
@@ -1147,51 +4061,74 @@ impl Executor<Module> for WasmiExecutor {
         &self,
         parity_module: Module,
         args: &[u8],
+        named_args: &[u8],
         account_addr: [u8; 20],
-        timestamp: u64,
-        nonce: u64,
-        gas_limit: u64,
+        authorization_keys: BTreeSet<PublicKey>,
+        deploy_hash: [u8; 32],
+        block_height: u64,
+        gas_limit: Gas,
+        phase: Phase,
+        config: &EngineConfig,
         tc: &mut TrackingCopy<R>,
-    ) -> (Result<ExecutionEffect, Error>, u64)
+    ) -> (Result<ExecutionEffect, Error>, Gas)
     where
         R::Error: Into<Error>,
     {
-        let (instance, memory) = on_fail_charge!(instance_and_memory(parity_module.clone()), 0);
+        // Only the engine's own flows run in the system context, and none of
+        // them runs Wasm.
+        if is_system_context(account_addr, phase) {
+            return (Err(Error::SystemContext), Gas::default());
+        }
+        let (instance, memory, _libraries) =
+            on_fail_charge!(instance_and_memory(parity_module.clone(), tc), Gas::default());
         let acct_key = Key::Account(account_addr);
         let value = on_fail_charge! {
         match tc.get(&acct_key) {
             Ok(None) => Err(Error::KeyNotFound(acct_key)),
             Err(error) => Err(error.into()),
             Ok(Some(value)) => Ok(value)
-        }, 0 };
+        }, Gas::default() };
         let account = value.as_account();
         let mut uref_lookup_local = account.urefs_lookup().clone();
-        let known_urefs: HashMap<URefAddr, HashSet<AccessRights>> =
-            vec_key_rights_to_map(uref_lookup_local.values().cloned());
+        // Account's main purse is known to the session code.
+        let known_urefs: HashMap<URefAddr, HashSet<AccessRights>> = vec_key_rights_to_map(
+            uref_lookup_local
+                .values()
+                .cloned()
+                .chain(std::iter::once(account.purse_id().into())),
+        );
         let context = RuntimeContext {
             uref_lookup: &mut uref_lookup_local,
             known_urefs,
             account: &account,
             base_key: acct_key,
+            authorization_keys,
             gas_limit,
+            phase,
+            deploy_hash,
+            protocol_version: ProtocolVersion::new(PROTOCOL_VERSION),
+            block_height,
         };
         let arguments: Vec<Vec<u8>> = if args.is_empty() {
             Vec::new()
         } else {
             // TODO: figure out how this works with the cost model
             // https://casperlabs.atlassian.net/browse/EE-239
-            on_fail_charge!(deserialize(args), 0)
+            on_fail_charge!(deserialize(args), Gas::default())
         };
-        let mut runtime = Runtime::new(
-            arguments,
-            memory,
-            tc,
-            parity_module,
-            account_addr,
-            nonce,
-            timestamp,
-            context,
-        );
+        let named_arguments: RuntimeArgs = if named_args.is_empty() {
+            RuntimeArgs::new()
+        } else {
+            on_fail_charge!(deserialize(named_args), Gas::default())
+        };
+        let mut runtime = Runtime::new(arguments, memory, tc, parity_module, context)
+        .with_contract_debug(config.contract_debug())
+        .with_system_contracts(config.system_contracts().clone())
+        .with_host_function_costs(config.host_function_costs().clone())
+        .with_max_call_depth(config.max_call_depth())
+        .with_pos_delays(config.pos_delays())
+        .with_key_limits(config.key_limits())
+        .with_named_args(named_arguments);
         let _ = on_fail_charge!(
             instance.invoke_export("call", &[], &mut runtime),
             runtime.gas_counter
@@ -1211,11 +4148,138 @@ pub fn key_to_tuple(key: Key) -> Option<([u8; 32], AccessRights)> {
         Key::URef(raw_addr, rights) => Some((raw_addr, rights)),
         Key::Account(_) => None,
         Key::Hash(_) => None,
+        Key::Local(_) => None,
+        Key::DeployInfo(_) => None,
+        Key::Transfer(_) => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use common::key::{AccessRights, Key};
+    use common::phase::Phase;
+    use common::value::account::PurseId;
+    use common::value::Account;
+    use shared::gas::Gas;
+
+    use super::{Error, RuntimeContext};
+
+    const ACCOUNT_KEY: Key = Key::Account([1u8; 20]);
+    const UREF_ADDR: [u8; 32] = [2u8; 32];
+
+    const ALL_RIGHTS: [AccessRights; 7] = [
+        AccessRights::READ,
+        AccessRights::WRITE,
+        AccessRights::ADD,
+        AccessRights::READ_ADD,
+        AccessRights::READ_WRITE,
+        AccessRights::ADD_WRITE,
+        AccessRights::READ_ADD_WRITE,
+    ];
+
+    fn account() -> Account {
+        let purse_id = PurseId::new([3u8; 32], AccessRights::READ_ADD_WRITE);
+        Account::create([4u8; 32], 0, BTreeMap::new(), purse_id)
+    }
+
+    fn context<'a>(
+        uref_lookup: &'a mut BTreeMap<String, Key>,
+        account: &'a Account,
+    ) -> RuntimeContext<'a> {
+        RuntimeContext::new(
+            uref_lookup,
+            account,
+            ACCOUNT_KEY,
+            BTreeSet::new(),
+            Gas::default(),
+            Phase::Session,
+        )
+    }
+
+    #[test]
+    fn forged_urefs_are_rejected() {
+        let mut uref_lookup = BTreeMap::new();
+        let account = account();
+        let mut context = context(&mut uref_lookup, &account);
+        context.insert_uref(Key::URef([5u8; 32], AccessRights::READ_ADD_WRITE));
+        for rights in ALL_RIGHTS.iter() {
+            let key = Key::URef(UREF_ADDR, *rights);
+            for required in ALL_RIGHTS.iter() {
+                assert_matches!(
+                    context.validate_access(&key, *required),
+                    Err(Error::ForgedReference(_))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn urefs_can_be_used_with_the_rights_they_were_granted() {
+        let mut uref_lookup = BTreeMap::new();
+        let account = account();
+        let mut context = context(&mut uref_lookup, &account);
+        context.insert_uref(Key::URef(UREF_ADDR, AccessRights::READ_ADD_WRITE));
+        for rights in ALL_RIGHTS.iter() {
+            // Attenuated versions of the URef are known too.
+            let key = Key::URef(UREF_ADDR, *rights);
+            for required in ALL_RIGHTS.iter() {
+                let result = context.validate_access(&key, *required);
+                if *rights & *required == *required {
+                    assert!(result.is_ok());
+                } else {
+                    assert_matches!(result, Err(Error::InvalidAccess { .. }));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn urefs_cant_be_used_with_more_rights_than_granted() {
+        let mut uref_lookup = BTreeMap::new();
+        let account = account();
+        let mut context = context(&mut uref_lookup, &account);
+        context.insert_uref(Key::URef(UREF_ADDR, AccessRights::READ));
+        let read = Key::URef(UREF_ADDR, AccessRights::READ);
+        assert!(context.validate_readable(&read).is_ok());
+        assert_matches!(context.validate_writeable(&read), Err(Error::InvalidAccess { .. }));
+        // Claiming more rights than granted makes the URef a forged one.
+        let escalated = Key::URef(UREF_ADDR, AccessRights::READ_WRITE);
+        assert_matches!(context.validate_writeable(&escalated), Err(Error::ForgedReference(_)));
+        // Rights granted by separate versions of a URef don't add up.
+        context.insert_uref(Key::URef(UREF_ADDR, AccessRights::ADD));
+        assert!(context.validate_addable(&Key::URef(UREF_ADDR, AccessRights::ADD)).is_ok());
+        let combined = Key::URef(UREF_ADDR, AccessRights::READ_ADD);
+        assert_matches!(context.validate_readable(&combined), Err(Error::ForgedReference(_)));
+    }
+
+    #[test]
+    fn other_keys_are_checked_against_the_context() {
+        let mut uref_lookup = BTreeMap::new();
+        let account = account();
+        let context = context(&mut uref_lookup, &account);
+        let other_account = Key::Account([6u8; 20]);
+        let hash = Key::Hash([7u8; 32]);
+        // Only the account of the context can be read and added to.
+        assert!(context.validate_readable(&ACCOUNT_KEY).is_ok());
+        assert!(context.validate_addable(&ACCOUNT_KEY).is_ok());
+        assert!(context.validate_readable(&other_account).is_err());
+        assert!(context.validate_addable(&other_account).is_err());
+        // Contracts can be read by anyone, but only added to by themselves.
+        assert!(context.validate_readable(&hash).is_ok());
+        assert!(context.validate_addable(&hash).is_err());
+        let records = [Key::DeployInfo([9u8; 32]), Key::Transfer([10u8; 32])];
+        for key in [ACCOUNT_KEY, hash, Key::Local([8u8; 32])].iter().chain(&records) {
+            assert_matches!(context.validate_writeable(key), Err(Error::InvalidAccess { .. }));
+        }
+        assert!(context.validate_readable(&Key::Local([8u8; 32])).is_err());
+        for key in &records {
+            assert!(context.validate_readable(key).is_ok());
+            assert!(context.validate_addable(key).is_err());
+        }
+    }
+
     // Need intermediate method b/c when on_fail_charge macro is inlined
     // for the error case it will call return which would exit the test.
     fn indirect_fn(r: Result<u32, String>, f: u32) -> (Result<u32, String>, u32) {
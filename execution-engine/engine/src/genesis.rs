@@ -0,0 +1,208 @@
+//! Settings of the initial global state of a chain.
+//!
+//! The system contracts are implemented natively, so genesis only stores
+//! them under their keys. Every key and purse created by genesis is derived
+//! from the name of the chain, which makes the post-state hash depend on the
+//! genesis config alone. Genesis also records the first protocol data of the
+//! chain.
+
+use std::collections::BTreeSet;
+
+use auction::AuctionSettings;
+use common::key::Key;
+use common::system_contracts::SystemContractId;
+use common::value::account::PublicKey;
+use common::value::contract::PROTOCOL_VERSION;
+use common::value::U512;
+use engine::EngineConfig;
+use shared::newtypes::Blake2bHash;
+use shared::ratio::Ratio;
+use storage::global_state::ExecutionEffect;
+use upgrade::ProtocolData;
+use vm::host_function_costs::HostFunctionCosts;
+use vm::storage_costs::StorageCosts;
+use vm::wasm_costs::WasmCosts;
+
+/// System contracts installed by genesis.
+const SYSTEM_CONTRACTS: [SystemContractId; 4] = [
+    SystemContractId::Mint,
+    SystemContractId::ProofOfStake,
+    SystemContractId::StandardPayment,
+    SystemContractId::Auction,
+];
+
+/// Account created by genesis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenesisAccount {
+    pub public_key: PublicKey,
+    /// Tokens in the main purse of the account.
+    pub balance: U512,
+    /// Tokens bonded as the stake of the account. Accounts with no stake
+    /// aren't validators.
+    pub bonded_amount: U512,
+}
+
+impl GenesisAccount {
+    pub fn new(public_key: PublicKey, balance: U512, bonded_amount: U512) -> GenesisAccount {
+        GenesisAccount {
+            public_key,
+            balance,
+            bonded_amount,
+        }
+    }
+}
+
+/// Initial global state of a chain.
+#[derive(Debug, Clone)]
+pub struct GenesisConfig {
+    name: String,
+    accounts: Vec<GenesisAccount>,
+    wasm_costs: WasmCosts,
+    host_function_costs: HostFunctionCosts,
+    storage_costs: StorageCosts,
+    round_seigniorage_rate: Ratio,
+    auction_settings: AuctionSettings,
+}
+
+impl GenesisConfig {
+    pub fn new(name: &str, accounts: Vec<GenesisAccount>, wasm_costs: WasmCosts) -> GenesisConfig {
+        GenesisConfig {
+            name: name.to_string(),
+            accounts,
+            wasm_costs,
+            host_function_costs: HostFunctionCosts::default(),
+            storage_costs: StorageCosts::default(),
+            round_seigniorage_rate: Ratio::default(),
+            auction_settings: AuctionSettings::default(),
+        }
+    }
+
+    /// Sets the costs of the host functions the chain starts with. Host
+    /// functions are free unless set otherwise.
+    pub fn with_host_function_costs(
+        mut self,
+        host_function_costs: HostFunctionCosts,
+    ) -> GenesisConfig {
+        self.host_function_costs = host_function_costs;
+        self
+    }
+
+    /// Sets the gas charged for every byte deploys add to the state.
+    pub fn with_storage_costs(mut self, storage_costs: StorageCosts) -> GenesisConfig {
+        self.storage_costs = storage_costs;
+        self
+    }
+
+    /// Sets the share of the total supply minted as rewards for every round.
+    /// The chain mints nothing unless set otherwise.
+    pub fn with_round_seigniorage_rate(mut self, round_seigniorage_rate: Ratio) -> GenesisConfig {
+        self.round_seigniorage_rate = round_seigniorage_rate;
+        self
+    }
+
+    /// Sets the number of validators seated in every era.
+    pub fn with_validator_slots(mut self, validator_slots: usize) -> GenesisConfig {
+        self.auction_settings.validator_slots = validator_slots;
+        self
+    }
+
+    /// Sets the number of eras between the one following an auction and the
+    /// one whose validators it picks. The validators of the genesis accounts
+    /// are seated in the eras before the first one picked by an auction.
+    pub fn with_auction_delay(mut self, auction_delay: u64) -> GenesisConfig {
+        self.auction_settings.auction_delay = auction_delay;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn accounts(&self) -> &[GenesisAccount] {
+        &self.accounts
+    }
+
+    pub fn wasm_costs(&self) -> &WasmCosts {
+        &self.wasm_costs
+    }
+
+    pub fn host_function_costs(&self) -> &HostFunctionCosts {
+        &self.host_function_costs
+    }
+
+    pub fn storage_costs(&self) -> StorageCosts {
+        self.storage_costs
+    }
+
+    pub fn round_seigniorage_rate(&self) -> Ratio {
+        self.round_seigniorage_rate
+    }
+
+    pub fn auction_settings(&self) -> AuctionSettings {
+        self.auction_settings
+    }
+
+    /// Public keys of the accounts which are listed more than once.
+    pub fn duplicate_accounts(&self) -> Vec<PublicKey> {
+        let mut seen = BTreeSet::new();
+        self.accounts
+            .iter()
+            .map(|account| account.public_key)
+            .filter(|public_key| !seen.insert(*public_key))
+            .collect()
+    }
+
+    /// Address derived from the name of the chain and `seed`.
+    pub(crate) fn derive_addr(&self, seed: &[u8]) -> [u8; 32] {
+        let mut data = self.name.as_bytes().to_vec();
+        data.extend_from_slice(seed);
+        let mut addr = [0u8; 32];
+        addr.copy_from_slice(&Blake2bHash::new(&data).to_vec());
+        addr
+    }
+
+    /// Key the system contract `id` is installed under.
+    pub fn system_contract_key(&self, id: SystemContractId) -> Key {
+        let mut seed = b"system_contract".to_vec();
+        seed.extend_from_slice(&u32::from(id).to_le_bytes());
+        Key::Hash(self.derive_addr(&seed))
+    }
+
+    /// Protocol data of the chain at its first protocol version.
+    pub fn protocol_data(&self) -> ProtocolData {
+        let system_contracts = SYSTEM_CONTRACTS
+            .iter()
+            .map(|id| (*id, self.system_contract_key(*id)))
+            .collect();
+        ProtocolData {
+            protocol_version: PROTOCOL_VERSION,
+            wasm_costs: self.wasm_costs.clone(),
+            system_contracts,
+            host_function_costs: self.host_function_costs.clone(),
+            storage_costs: self.storage_costs,
+        }
+    }
+
+    /// Configures `config` to run deploys on the chain started by this
+    /// genesis: the system contracts are registered and the Wasm, host
+    /// function and storage costs, the seigniorage rate and the auction
+    /// settings are taken from the genesis config.
+    pub fn engine_config(&self, config: EngineConfig) -> EngineConfig {
+        SYSTEM_CONTRACTS
+            .iter()
+            .fold(config, |config, id| {
+                config.with_system_contract(*id, self.system_contract_key(*id))
+            })
+            .with_wasm_costs(self.wasm_costs.clone())
+            .with_host_function_costs(self.host_function_costs.clone())
+            .with_storage_costs(self.storage_costs)
+            .with_round_seigniorage_rate(self.round_seigniorage_rate)
+            .with_validator_slots(self.auction_settings.validator_slots)
+            .with_auction_delay(self.auction_settings.auction_delay)
+    }
+}
+
+pub struct GenesisResult {
+    pub post_state_hash: Blake2bHash,
+    pub effect: ExecutionEffect,
+}
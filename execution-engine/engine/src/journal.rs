@@ -0,0 +1,325 @@
+//! Journals of what the engine did while executing deploys.
+//!
+//! When journaling is enabled, the tracking copy a deploy runs on records the
+//! values it loaded from the prestate, the reads, writes and additions made
+//! to it, and the runtime records the host functions called and the gas
+//! charged, in the order they happened. Rolled back changes stay in the
+//! journal. The values loaded from the prestate are enough to execute the
+//! deploy again without the global state, so validators which disagree on the
+//! effects of a deploy can replay each other's journals and find where their
+//! executions diverged.
+
+use std::collections::BTreeMap;
+
+use common::bytesrepr::{self, vec_from_bytes, vec_to_bytes, FromBytes, ToBytes};
+use common::key::Key;
+use common::value::{Value, U512};
+use shared::gas::Gas;
+use shared::newtypes::Blake2bHash;
+use storage::global_state::inmem::InMemGS;
+
+const HOST_CALL_TAG: u8 = 0;
+const LOAD_TAG: u8 = 1;
+const READ_TAG: u8 = 2;
+const WRITE_TAG: u8 = 3;
+const ADD_TAG: u8 = 4;
+const GAS_TAG: u8 = 5;
+
+/// Something the engine did while executing a deploy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalEntry {
+    /// Call of the host function with the index, which isn't recorded for
+    /// the calls metering gas.
+    HostCall(u32),
+    /// Value of the key in the prestate, loaded the first time the key was
+    /// accessed.
+    Load(Key, Option<Value>),
+    Read(Key),
+    Write(Key, Value),
+    Add(Key, Value),
+    /// Gas charged by the runtime.
+    Gas(Gas),
+}
+
+impl ToBytes for JournalEntry {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = Vec::new();
+        match self {
+            JournalEntry::HostCall(index) => {
+                result.push(HOST_CALL_TAG);
+                result.append(&mut index.to_bytes()?);
+            }
+            JournalEntry::Load(key, value) => {
+                result.push(LOAD_TAG);
+                result.append(&mut key.to_bytes()?);
+                result.append(&mut value.to_bytes()?);
+            }
+            JournalEntry::Read(key) => {
+                result.push(READ_TAG);
+                result.append(&mut key.to_bytes()?);
+            }
+            JournalEntry::Write(key, value) => {
+                result.push(WRITE_TAG);
+                result.append(&mut key.to_bytes()?);
+                result.append(&mut value.to_bytes()?);
+            }
+            JournalEntry::Add(key, value) => {
+                result.push(ADD_TAG);
+                result.append(&mut key.to_bytes()?);
+                result.append(&mut value.to_bytes()?);
+            }
+            JournalEntry::Gas(gas) => {
+                result.push(GAS_TAG);
+                result.append(&mut gas.value().to_bytes()?);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl FromBytes for JournalEntry {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (tag, rem): (u8, &[u8]) = FromBytes::from_bytes(bytes)?;
+        match tag {
+            HOST_CALL_TAG => {
+                let (index, rem): (u32, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((JournalEntry::HostCall(index), rem))
+            }
+            LOAD_TAG => {
+                let (key, rem): (Key, &[u8]) = FromBytes::from_bytes(rem)?;
+                let (value, rem): (Option<Value>, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((JournalEntry::Load(key, value), rem))
+            }
+            READ_TAG => {
+                let (key, rem): (Key, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((JournalEntry::Read(key), rem))
+            }
+            WRITE_TAG => {
+                let (key, rem): (Key, &[u8]) = FromBytes::from_bytes(rem)?;
+                let (value, rem): (Value, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((JournalEntry::Write(key, value), rem))
+            }
+            ADD_TAG => {
+                let (key, rem): (Key, &[u8]) = FromBytes::from_bytes(rem)?;
+                let (value, rem): (Value, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((JournalEntry::Add(key, value), rem))
+            }
+            GAS_TAG => {
+                let (gas, rem): (U512, &[u8]) = FromBytes::from_bytes(rem)?;
+                Ok((JournalEntry::Gas(Gas::new(gas)), rem))
+            }
+            _ => Err(bytesrepr::Error::FormattingError),
+        }
+    }
+}
+
+/// Journal of the execution of a deploy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeployJournal {
+    pub deploy_hash: [u8; 32],
+    /// State the deploy was executed on.
+    pub prestate_hash: Blake2bHash,
+    pub block_height: u64,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl DeployJournal {
+    /// Values the deploy loaded from its prestate. Keys which had no value
+    /// are left out.
+    pub fn prestate(&self) -> InMemGS<Key, Value> {
+        let mut values = BTreeMap::new();
+        for entry in &self.entries {
+            if let JournalEntry::Load(key, Some(value)) = entry {
+                values.entry(*key).or_insert_with(|| value.clone());
+            }
+        }
+        InMemGS::new(values)
+    }
+}
+
+impl ToBytes for DeployJournal {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.deploy_hash.to_bytes()?;
+        let mut prestate_hash = [0u8; 32];
+        prestate_hash.copy_from_slice(&self.prestate_hash.to_vec());
+        result.append(&mut prestate_hash.to_bytes()?);
+        result.append(&mut self.block_height.to_bytes()?);
+        result.append(&mut vec_to_bytes(&self.entries)?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for DeployJournal {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (deploy_hash, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (prestate_hash, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(rem)?;
+        let (block_height, rem): (u64, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (entries, rem): (Vec<JournalEntry>, &[u8]) = vec_from_bytes(rem)?;
+        Ok((
+            DeployJournal {
+                deploy_hash,
+                prestate_hash: prestate_hash.into(),
+                block_height,
+                entries,
+            },
+            rem,
+        ))
+    }
+}
+
+/// First entry at which a replayed journal differs from the recorded one.
+/// An entry is missing if one of the journals ended before the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    pub recorded: Option<JournalEntry>,
+    pub replayed: Option<JournalEntry>,
+}
+
+/// Outcome of replaying a journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// Journal of the replayed execution.
+    pub journal: DeployJournal,
+    /// Where the replay diverged, if it did.
+    pub divergence: Option<Divergence>,
+    /// Keys which the replay wrote or added to differently, in ascending
+    /// order.
+    pub changed_keys: Vec<Key>,
+}
+
+impl Replay {
+    /// Compares the journal of a replay to the `recorded` one.
+    pub fn new(recorded: &DeployJournal, journal: DeployJournal) -> Replay {
+        let divergence = first_divergence(&recorded.entries, &journal.entries);
+        let changed_keys = effect_diff(&recorded.entries, &journal.entries);
+        Replay {
+            journal,
+            divergence,
+            changed_keys,
+        }
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+pub fn first_divergence(
+    recorded: &[JournalEntry],
+    replayed: &[JournalEntry],
+) -> Option<Divergence> {
+    let len = recorded.len().max(replayed.len());
+    (0..len)
+        .find(|index| recorded.get(*index) != replayed.get(*index))
+        .map(|index| Divergence {
+            index,
+            recorded: recorded.get(index).cloned(),
+            replayed: replayed.get(index).cloned(),
+        })
+}
+
+fn changes(entries: &[JournalEntry]) -> BTreeMap<Key, Vec<&JournalEntry>> {
+    let mut changes: BTreeMap<Key, Vec<&JournalEntry>> = BTreeMap::new();
+    for entry in entries {
+        match entry {
+            JournalEntry::Write(key, _) | JournalEntry::Add(key, _) => {
+                changes.entry(*key).or_default().push(entry)
+            }
+            _ => (),
+        }
+    }
+    changes
+}
+
+/// Keys whose writes and additions aren't the same in both journals.
+pub fn effect_diff(recorded: &[JournalEntry], replayed: &[JournalEntry]) -> Vec<Key> {
+    let recorded = changes(recorded);
+    let replayed = changes(replayed);
+    let mut keys: Vec<Key> = recorded
+        .keys()
+        .chain(replayed.keys())
+        .filter(|key| recorded.get(key) != replayed.get(key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use common::bytesrepr::{deserialize, ToBytes};
+    use common::key::Key;
+    use common::value::{Value, U512};
+    use shared::gas::Gas;
+    use storage::global_state::StateReader;
+
+    use super::{effect_diff, first_divergence, DeployJournal, Divergence, JournalEntry};
+
+    fn entries() -> Vec<JournalEntry> {
+        vec![
+            JournalEntry::Gas(Gas::from(10)),
+            JournalEntry::HostCall(1),
+            JournalEntry::Load(Key::Hash([1u8; 32]), Some(Value::Int32(5))),
+            JournalEntry::Load(Key::Hash([2u8; 32]), None),
+            JournalEntry::Read(Key::Hash([1u8; 32])),
+            JournalEntry::Write(Key::Hash([2u8; 32]), Value::Int32(6)),
+            JournalEntry::Add(Key::Hash([1u8; 32]), Value::UInt512(U512::from(7))),
+        ]
+    }
+
+    #[test]
+    fn journals_roundtrip() {
+        let journal = DeployJournal {
+            deploy_hash: [3u8; 32],
+            prestate_hash: [4u8; 32].into(),
+            block_height: 8,
+            entries: entries(),
+        };
+        let bytes = journal.to_bytes().unwrap();
+        assert_eq!(deserialize::<DeployJournal>(&bytes), Ok(journal));
+    }
+
+    #[test]
+    fn prestate_has_the_loaded_values() {
+        let journal = DeployJournal {
+            deploy_hash: [3u8; 32],
+            prestate_hash: [4u8; 32].into(),
+            block_height: 8,
+            entries: entries(),
+        };
+        let prestate = journal.prestate();
+        let read = |key| prestate.read(&key).expect("In-memory reads should succeed.");
+        assert_eq!(read(Key::Hash([1u8; 32])), Some(Value::Int32(5)));
+        assert_eq!(read(Key::Hash([2u8; 32])), None);
+    }
+
+    #[test]
+    fn divergences_are_found() {
+        let recorded = entries();
+        assert_eq!(first_divergence(&recorded, &recorded), None);
+        assert!(effect_diff(&recorded, &recorded).is_empty());
+
+        let mut replayed = recorded.clone();
+        replayed[5] = JournalEntry::Write(Key::Hash([2u8; 32]), Value::Int32(9));
+        assert_eq!(
+            first_divergence(&recorded, &replayed),
+            Some(Divergence {
+                index: 5,
+                recorded: Some(recorded[5].clone()),
+                replayed: Some(replayed[5].clone()),
+            })
+        );
+        assert_eq!(effect_diff(&recorded, &replayed), vec![Key::Hash([2u8; 32])]);
+
+        // A replay stopping early misses the rest of the entries.
+        let divergence = first_divergence(&recorded, &recorded[..2]).unwrap();
+        assert_eq!(divergence.index, 2);
+        assert_eq!(divergence.replayed, None);
+        assert_eq!(
+            effect_diff(&recorded, &recorded[..2]),
+            vec![Key::Hash([1u8; 32]), Key::Hash([2u8; 32])]
+        );
+    }
+}
@@ -2,23 +2,41 @@
 #![feature(never_type)]
 extern crate common;
 extern crate core;
+extern crate crossbeam_utils;
+extern crate ed25519_dalek;
 extern crate failure;
 extern crate itertools;
 extern crate parity_wasm;
 extern crate parking_lot;
 extern crate pwasm_utils;
-extern crate rand;
-extern crate rand_chacha;
+extern crate secp256k1;
 extern crate shared;
 extern crate storage;
 extern crate vm;
 extern crate wasm_prep;
 extern crate wasmi;
 
+pub mod address_generator;
+pub mod approval;
 pub mod argsparser;
+pub mod auction;
+pub mod deploy_info;
 pub mod engine;
+pub mod error_code;
 pub mod execution;
+pub mod genesis;
+pub mod journal;
+pub mod mint;
+pub mod module_cache;
+pub mod pos;
+pub mod rent;
+pub mod scheduler;
+pub mod standard_payment;
+pub mod step;
+pub mod system;
 pub mod trackingcopy;
+pub mod transfer;
+pub mod upgrade;
 
 mod utils;
 
@@ -11,8 +11,14 @@ use std::iter::Iterator;
 
 use clap::{App, Arg};
 
-use execution_engine::engine::{EngineState, ExecutionResult, RootNotFound};
+use common::value::account::PublicKey;
+use execution_engine::approval::body_hash;
+use execution_engine::deploy_info::DeployPreconditions;
+use execution_engine::engine::{
+    DeployCode, EngineConfig, EngineState, ExecutionResult, RootNotFound, CONV_RATE,
+};
 use execution_engine::execution::WasmiExecutor;
+use shared::gas::Gas;
 use shared::newtypes::Blake2bHash;
 use storage::global_state::inmem::InMemHist;
 use storage::history::CommitResult;
@@ -46,6 +52,11 @@ fn main() {
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("contract-debug")
+                .long("contract-debug")
+                .help("Prints messages passed by contracts to the print host function"),
+        )
         .arg(
             Arg::with_name("wasm")
                 .long("wasm")
@@ -84,35 +95,60 @@ fn main() {
 
     let mut state_hash: Blake2bHash = [0u8; 32].into();
 
-    let gas_limit: u64 = matches
+    let gas_limit: Gas = matches
         .value_of("gas-limit")
         .and_then(|v| v.parse::<u64>().ok())
+        .map(Gas::from)
         .expect("Provided gas limit value is not u64.");
 
     // TODO: move to arg parser
     let timestamp: u64 = 100_000;
     let nonce: u64 = 1;
+    let deploy_hash = [0u8; 32];
+    let block_height: u64 = 0;
+    // Public key of the account created by `mocked_account`.
+    let authorization_keys = [PublicKey::new([48u8; 32])];
 
     // let path = std::path::Path::new("./tmp/");
     // TODO: Better error handling?
     //    let global_state = LmdbGs::new(&path).unwrap();
     let init_state = storage::global_state::mocked_account(account_addr);
     let global_state = InMemHist::new_initialized(&state_hash, init_state);
-    let engine_state = EngineState::new(global_state);
+    let engine_config =
+        EngineConfig::new().with_contract_debug(matches.is_present("contract-debug"));
+    let engine_state = EngineState::with_config(global_state, engine_config);
 
     let wasmi_executor = WasmiExecutor;
     let wasmi_preprocessor = WasmiPreprocessor;
 
     for wasm_bytes in wasm_files.iter() {
         println!("Pre state hash: {:?}", state_hash);
+        let session = DeployCode {
+            module_bytes: &wasm_bytes.bytes,
+            args: &[], // TODO: consume args from CLI
+            named_args: &[],
+        };
+        // Payment code isn't used with the default engine config.
+        let payment = DeployCode {
+            module_bytes: &[],
+            args: &[],
+            named_args: &[],
+        };
         let result = engine_state.run_deploy(
-            &wasm_bytes.bytes,
-            &[], // TODO: consume args from CLI
+            session,
+            payment,
             account_addr,
+            &authorization_keys,
             timestamp,
             nonce,
+            deploy_hash,
+            body_hash(&session, &payment),
+            &[],
+            block_height,
+            DeployPreconditions::default(),
             state_hash,
             gas_limit,
+            CONV_RATE,
             &wasmi_executor,
             &wasmi_preprocessor,
         );
@@ -124,6 +160,7 @@ fn main() {
             Ok(ExecutionResult {
                 result: Ok(effects),
                 cost,
+                ..
             }) => {
                 println!("Cost of executing the contract was: {}", cost);
                 match engine_state.apply_effect(state_hash, effects.1) {
@@ -155,6 +192,7 @@ fn main() {
             Ok(ExecutionResult {
                 result: Err(error),
                 cost,
+                ..
             }) => {
                 println!("Gas used during execution: {:?}", cost);
                 println!("Result for file {}: {:?}", wasm_bytes.path, error)
@@ -0,0 +1,319 @@
+//! Native implementation of the mint.
+//!
+//! A purse is an unforgeable reference and its balance (`Value::UInt512`) is stored
+//! in the global state under that reference. Access rights are checked by the
+//! callers; functions here only operate on balances. The mint also keeps the
+//! total supply of tokens, which changes when tokens are minted or burned, and
+//! a record of every purse it created, so that balances can be listed.
+
+use std::collections::BTreeMap;
+
+use common::bytesrepr;
+use common::key::{AccessRights, Key, LOCAL_ID};
+use common::value::account::PurseId;
+use common::value::contract::PROTOCOL_VERSION;
+use common::value::{Contract, Value, U512};
+use execution::Error;
+use shared::newtypes::Blake2bHash;
+use storage::global_state::StateReader;
+use storage::transform::TypeMismatch;
+use trackingcopy::{AddResult, TrackingCopy};
+
+/// Key under which the balance of `purse_id` is stored.
+/// Balances are always stored under the full-rights version of the reference
+/// so that lookups don't depend on the rights the caller presented.
+pub fn balance_key(purse_id: PurseId) -> Key {
+    Key::URef(purse_id.addr(), AccessRights::READ_ADD_WRITE)
+}
+
+/// Purse holding what the payment code of the deploy with `deploy_hash`
+/// paid until the deploy is finalized. Its address is derived from the
+/// deploy hash, so the engine and the runtime agree on it without storing it.
+pub fn payment_purse(deploy_hash: [u8; 32]) -> PurseId {
+    let mut data = deploy_hash.to_vec();
+    data.extend_from_slice(b"payment");
+    let mut addr = [0u8; 32];
+    addr.copy_from_slice(&Blake2bHash::new(&data).to_vec());
+    PurseId::new(addr, AccessRights::READ_ADD_WRITE)
+}
+
+/// Key under which the total supply of tokens is stored. Like the payment
+/// purses, its address is derived from a fixed seed rather than stored.
+pub fn total_supply_key() -> Key {
+    let mut addr = [0u8; 32];
+    addr.copy_from_slice(&Blake2bHash::new(b"total_supply").to_vec());
+    Key::URef(addr, AccessRights::READ_ADD_WRITE)
+}
+
+/// Key under which the mint records that it created the purse at `addr`.
+/// The record holds the address, so that the purses can be found by listing
+/// the records. Contracts can't write it: their local keys are derived from
+/// their own addresses.
+pub fn purse_record_key(addr: [u8; 32]) -> Key {
+    let mut data = b"purse_record".to_vec();
+    data.extend_from_slice(&addr);
+    let mut record_addr = [0u8; 32];
+    record_addr.copy_from_slice(&Blake2bHash::new(&data).to_vec());
+    Key::Local(record_addr)
+}
+
+/// Stores the mint under `contract_key`, with a total supply of zero. Purses
+/// are handled natively, so the contract has neither code nor named keys.
+pub fn install<R: StateReader<Key, Value>>(state: &mut TrackingCopy<R>, contract_key: Key) {
+    let contract = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION);
+    state.write(contract_key, Value::Contract(contract));
+    state.write(total_supply_key(), Value::UInt512(U512::zero()));
+}
+
+/// Creates a new, empty purse at `addr` and records it.
+pub fn create_purse<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    addr: [u8; 32],
+) -> PurseId {
+    let purse_id = PurseId::new(addr, AccessRights::READ_ADD_WRITE);
+    state.write(balance_key(purse_id), Value::UInt512(U512::zero()));
+    state.write(purse_record_key(addr), Value::ByteArray(addr.to_vec()));
+    purse_id
+}
+
+/// Reads the balance of `purse_id`.
+pub fn balance<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    purse_id: PurseId,
+) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    let key = balance_key(purse_id);
+    match state.read(key).map_err(Into::into)? {
+        None => Err(Error::KeyNotFound(key)),
+        Some(Value::UInt512(balance)) => Ok(balance),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "UInt512".to_string(),
+            other.type_string(),
+        ))),
+    }
+}
+
+/// Reads the total supply of tokens.
+pub fn total_supply<R: StateReader<Key, Value>>(state: &mut TrackingCopy<R>) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    let key = total_supply_key();
+    match state.read(key).map_err(Into::into)? {
+        None => Err(Error::KeyNotFound(key)),
+        Some(Value::UInt512(total_supply)) => Ok(total_supply),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "UInt512".to_string(),
+            other.type_string(),
+        ))),
+    }
+}
+
+/// Balances of the purses the mint created, by the keys they're stored under.
+/// The purses are found through their records, so purses created before the
+/// mint kept records aren't listed. Returns `None` if the state can't list
+/// keys.
+pub fn balances<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+) -> Result<Option<BTreeMap<Key, U512>>, Error>
+where
+    R::Error: Into<Error> + From<bytesrepr::Error>,
+{
+    let pairs = match state.keys_with_prefix(&[LOCAL_ID]).map_err(Into::into)? {
+        Some(pairs) => pairs,
+        None => return Ok(None),
+    };
+    let mut balances = BTreeMap::new();
+    for (key, value) in pairs {
+        // Other local keys may hold 32 bytes too, but not under the record key
+        // of the address they hold.
+        let addr = match value {
+            Value::ByteArray(ref bytes) if bytes.len() == 32 => {
+                let mut addr = [0u8; 32];
+                addr.copy_from_slice(bytes);
+                addr
+            }
+            _ => continue,
+        };
+        if key == purse_record_key(addr) {
+            let purse_id = PurseId::new(addr, AccessRights::READ_ADD_WRITE);
+            balances.insert(balance_key(purse_id), balance(state, purse_id)?);
+        }
+    }
+    Ok(Some(balances))
+}
+
+/// Creates `amount` of new tokens in `purse_id`, adding them to the total
+/// supply.
+pub fn mint<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    purse_id: PurseId,
+    amount: U512,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let total_supply = total_supply(state)?
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    let purse_balance = balance(state, purse_id)?
+        .checked_add(amount)
+        .ok_or(Error::Overflow)?;
+    state.write(balance_key(purse_id), Value::UInt512(purse_balance));
+    state.write(total_supply_key(), Value::UInt512(total_supply));
+    Ok(())
+}
+
+/// Moves `amount` of tokens from `source` to `target`.
+pub fn transfer<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    source: PurseId,
+    target: PurseId,
+    amount: U512,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let source_balance = balance(state, source)?;
+    if source_balance < amount {
+        return Err(Error::InsufficientFunds);
+    }
+    // Make sure target exists before we touch the source.
+    balance(state, target)?;
+    state.write(
+        balance_key(source),
+        Value::UInt512(source_balance - amount),
+    );
+    match state
+        .add(balance_key(target), Value::UInt512(amount))
+        .map_err(Into::into)?
+    {
+        AddResult::Success => Ok(()),
+        AddResult::KeyNotFound(key) => Err(Error::KeyNotFound(key)),
+        AddResult::TypeMismatch(type_mismatch) => Err(Error::TypeMismatch(type_mismatch)),
+        AddResult::Overflow => Err(Error::Overflow),
+    }
+}
+
+/// Takes `amount` of tokens out of `purse_id` and out of circulation.
+/// States created before the total supply was kept have none to reduce.
+pub fn burn<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    purse_id: PurseId,
+    amount: U512,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let purse_balance = balance(state, purse_id)?;
+    if purse_balance < amount {
+        return Err(Error::InsufficientFunds);
+    }
+    state.write(balance_key(purse_id), Value::UInt512(purse_balance - amount));
+    match total_supply(state) {
+        // The supply includes the balance of every purse.
+        Ok(total_supply) => {
+            state.write(total_supply_key(), Value::UInt512(total_supply - amount));
+            Ok(())
+        }
+        Err(Error::KeyNotFound(_)) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use common::key::{AccessRights, Key};
+    use common::value::{Value, U512};
+    use storage::global_state::inmem::InMemGS;
+
+    use super::{balance, balances, burn, create_purse, install, mint, total_supply, transfer};
+    use execution::Error;
+    use trackingcopy::TrackingCopy;
+
+    fn tracking_copy() -> TrackingCopy<InMemGS<Key, Value>> {
+        TrackingCopy::new(InMemGS::new(BTreeMap::new()))
+    }
+
+    #[test]
+    fn transfer_moves_balance() {
+        let mut tc = tracking_copy();
+        let source = create_purse(&mut tc, [1u8; 32]);
+        let target = create_purse(&mut tc, [2u8; 32]);
+        tc.write(super::balance_key(source), Value::UInt512(U512::from(10)));
+
+        transfer(&mut tc, source, target, U512::from(4)).expect("transfer should succeed");
+
+        assert_eq!(balance(&mut tc, source).unwrap(), U512::from(6));
+        assert_eq!(balance(&mut tc, target).unwrap(), U512::from(4));
+    }
+
+    #[test]
+    fn transfer_insufficient_funds() {
+        let mut tc = tracking_copy();
+        let source = create_purse(&mut tc, [1u8; 32]);
+        let target = create_purse(&mut tc, [2u8; 32]);
+
+        let result = transfer(&mut tc, source, target, U512::from(1));
+
+        assert_matches!(result, Err(Error::InsufficientFunds));
+        assert_eq!(balance(&mut tc, target).unwrap(), U512::zero());
+    }
+
+    #[test]
+    fn burn_takes_tokens_out_of_the_purse() {
+        let mut tc = tracking_copy();
+        let purse = create_purse(&mut tc, [1u8; 32]);
+        tc.write(super::balance_key(purse), Value::UInt512(U512::from(10)));
+
+        burn(&mut tc, purse, U512::from(4)).expect("burn should succeed");
+        assert_eq!(balance(&mut tc, purse).unwrap(), U512::from(6));
+        assert_matches!(
+            burn(&mut tc, purse, U512::from(7)),
+            Err(Error::InsufficientFunds)
+        );
+    }
+
+    #[test]
+    fn minting_and_burning_change_the_total_supply() {
+        let mut tc = tracking_copy();
+        install(&mut tc, Key::Hash([3u8; 32]));
+        let purse = create_purse(&mut tc, [1u8; 32]);
+
+        mint(&mut tc, purse, U512::from(10)).expect("mint should succeed");
+        burn(&mut tc, purse, U512::from(4)).expect("burn should succeed");
+        assert_eq!(balance(&mut tc, purse).unwrap(), U512::from(6));
+        assert_eq!(total_supply(&mut tc).unwrap(), U512::from(6));
+        assert_matches!(
+            mint(&mut tc, purse, U512::max_value()),
+            Err(Error::Overflow)
+        );
+    }
+
+    #[test]
+    fn balances_are_listed_from_the_purse_records() {
+        let mut tc = tracking_copy();
+        install(&mut tc, Key::Hash([3u8; 32]));
+        let purse_1 = create_purse(&mut tc, [1u8; 32]);
+        let purse_2 = create_purse(&mut tc, [2u8; 32]);
+        mint(&mut tc, purse_2, U512::from(10)).expect("mint should succeed");
+        // Neither an integer the mint didn't create nor a local key holding an
+        // address is a purse.
+        let uref = Key::URef([4u8; 32], AccessRights::READ_ADD_WRITE);
+        tc.write(uref, Value::UInt512(U512::from(5)));
+        tc.write(Key::Local([5u8; 32]), Value::ByteArray(vec![4u8; 32]));
+
+        let balances = balances(&mut tc).unwrap().expect("the state lists keys");
+        let expected: BTreeMap<Key, U512> = vec![
+            (super::balance_key(purse_1), U512::zero()),
+            (super::balance_key(purse_2), U512::from(10)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(balances, expected);
+    }
+}
@@ -0,0 +1,279 @@
+//! Cache of preprocessed Wasm modules.
+//!
+//! Preprocessing deserializes, validates and instruments the code of a deploy,
+//! which is repeated whenever the same code is sent again. Modules are cached
+//! by the hash of their code, with the least recently used ones evicted once
+//! the cache is full. The instrumentation depends on the Wasm costs, so the
+//! cache is emptied when it's used with other costs than it was filled with.
+
+use std::collections::{BTreeMap, HashMap};
+
+use parking_lot::Mutex;
+use shared::newtypes::Blake2bHash;
+use vm::wasm_costs::WasmCosts;
+use wasm_prep::{PreprocessingError, Preprocessor};
+
+/// Number of modules cached unless configured otherwise.
+pub const DEFAULT_MODULE_CACHE_CAPACITY: usize = 64;
+
+/// Counts of what happened to the lookups of a cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    /// Modules dropped to make room for others.
+    pub evictions: u64,
+    /// Times the cache was emptied because the Wasm costs changed.
+    pub invalidations: u64,
+}
+
+struct CachedModule<A> {
+    module: A,
+    last_used: u64,
+}
+
+struct Modules<A> {
+    wasm_costs: Option<WasmCosts>,
+    modules: HashMap<[u8; 32], CachedModule<A>>,
+    // Code hashes by the time their module was last used.
+    recency: BTreeMap<u64, [u8; 32]>,
+    clock: u64,
+    metrics: CacheMetrics,
+}
+
+impl<A> Modules<A> {
+    fn invalidate_unless(&mut self, wasm_costs: &WasmCosts) {
+        if self.wasm_costs.as_ref() == Some(wasm_costs) {
+            return;
+        }
+        if self.wasm_costs.is_some() {
+            self.metrics.invalidations += 1;
+        }
+        self.wasm_costs = Some(wasm_costs.clone());
+        self.modules.clear();
+        self.recency.clear();
+    }
+
+    fn touch(&mut self, code_hash: [u8; 32]) -> Option<&A> {
+        self.clock += 1;
+        let clock = self.clock;
+        let cached = self.modules.get_mut(&code_hash)?;
+        self.recency.remove(&cached.last_used);
+        self.recency.insert(clock, code_hash);
+        cached.last_used = clock;
+        Some(&cached.module)
+    }
+}
+
+/// Least recently used cache of preprocessed modules, keyed by the hash of
+/// their code. It can be shared by threads preprocessing concurrently.
+pub struct ModuleCache<A> {
+    capacity: usize,
+    modules: Mutex<Modules<A>>,
+}
+
+impl<A: Clone> ModuleCache<A> {
+    /// Cache holding up to `capacity` modules. Nothing is cached if it's zero.
+    pub fn new(capacity: usize) -> ModuleCache<A> {
+        ModuleCache {
+            capacity,
+            modules: Mutex::new(Modules {
+                wasm_costs: None,
+                modules: HashMap::new(),
+                recency: BTreeMap::new(),
+                clock: 0,
+                metrics: CacheMetrics::default(),
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.lock().modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn metrics(&self) -> CacheMetrics {
+        self.modules.lock().metrics
+    }
+
+    /// Module of the code with `code_hash`, if it was preprocessed with
+    /// `wasm_costs`.
+    pub fn get(&self, code_hash: [u8; 32], wasm_costs: &WasmCosts) -> Option<A> {
+        let mut modules = self.modules.lock();
+        modules.invalidate_unless(wasm_costs);
+        let module = modules.touch(code_hash).cloned();
+        match module {
+            Some(_) => modules.metrics.hits += 1,
+            None => modules.metrics.misses += 1,
+        }
+        module
+    }
+
+    /// Caches the module of the code with `code_hash`, preprocessed with
+    /// `wasm_costs`, evicting the least recently used module if it's full.
+    pub fn insert(&self, code_hash: [u8; 32], wasm_costs: &WasmCosts, module: A) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut modules = self.modules.lock();
+        modules.invalidate_unless(wasm_costs);
+        if modules.touch(code_hash).is_some() {
+            return;
+        }
+        if modules.modules.len() >= self.capacity {
+            let oldest = modules.recency.keys().next().cloned();
+            if let Some(last_used) = oldest {
+                let evicted = modules.recency.remove(&last_used);
+                if let Some(evicted) = evicted {
+                    modules.modules.remove(&evicted);
+                    modules.metrics.evictions += 1;
+                }
+            }
+        }
+        let last_used = modules.clock;
+        modules.recency.insert(last_used, code_hash);
+        modules
+            .modules
+            .insert(code_hash, CachedModule { module, last_used });
+    }
+}
+
+/// Preprocessor looking modules up in a cache before preprocessing them with
+/// the preprocessor it wraps.
+pub struct CachingPreprocessor<'a, A: 'a, P> {
+    preprocessor: P,
+    cache: &'a ModuleCache<A>,
+}
+
+impl<'a, A: Clone, P: Preprocessor<A>> CachingPreprocessor<'a, A, P> {
+    pub fn new(preprocessor: P, cache: &'a ModuleCache<A>) -> CachingPreprocessor<'a, A, P> {
+        CachingPreprocessor {
+            preprocessor,
+            cache,
+        }
+    }
+}
+
+impl<'a, A: Clone, P: Preprocessor<A>> Preprocessor<A> for CachingPreprocessor<'a, A, P> {
+    fn preprocess(
+        &self,
+        module_bytes: &[u8],
+        wasm_costs: &WasmCosts,
+    ) -> Result<A, PreprocessingError> {
+        let mut code_hash = [0u8; 32];
+        code_hash.copy_from_slice(&Blake2bHash::new(module_bytes).to_vec());
+        if let Some(module) = self.cache.get(code_hash, wasm_costs) {
+            return Ok(module);
+        }
+        // Modules failing preprocessing aren't cached, they fail again.
+        let module = self.preprocessor.preprocess(module_bytes, wasm_costs)?;
+        self.cache.insert(code_hash, wasm_costs, module.clone());
+        Ok(module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use vm::wasm_costs::WasmCosts;
+    use wasm_prep::{PreprocessingError, Preprocessor};
+
+    use super::{CacheMetrics, CachingPreprocessor, ModuleCache};
+
+    /// Preprocessor counting its calls, whose modules are the code itself.
+    struct CountingPreprocessor<'a>(&'a Cell<usize>);
+
+    impl<'a> Preprocessor<Vec<u8>> for CountingPreprocessor<'a> {
+        fn preprocess(
+            &self,
+            module_bytes: &[u8],
+            _wasm_costs: &WasmCosts,
+        ) -> Result<Vec<u8>, PreprocessingError> {
+            self.0.set(self.0.get() + 1);
+            if module_bytes.is_empty() {
+                return Err(PreprocessingError::NoExportSection);
+            }
+            Ok(module_bytes.to_vec())
+        }
+    }
+
+    #[test]
+    fn modules_are_preprocessed_once() {
+        let calls = Cell::new(0);
+        let cache = ModuleCache::new(2);
+        let preprocessor = CachingPreprocessor::new(CountingPreprocessor(&calls), &cache);
+        let wasm_costs = WasmCosts::default();
+        for _ in 0..3 {
+            assert_eq!(preprocessor.preprocess(&[1], &wasm_costs).unwrap(), vec![1]);
+        }
+        assert_eq!(calls.get(), 1);
+        // Failures aren't cached.
+        assert!(preprocessor.preprocess(&[], &wasm_costs).is_err());
+        assert!(preprocessor.preprocess(&[], &wasm_costs).is_err());
+        assert_eq!(calls.get(), 3);
+        assert_eq!(
+            cache.metrics(),
+            CacheMetrics {
+                hits: 2,
+                misses: 3,
+                evictions: 0,
+                invalidations: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn least_recently_used_modules_are_evicted() {
+        let calls = Cell::new(0);
+        let cache = ModuleCache::new(2);
+        let preprocessor = CachingPreprocessor::new(CountingPreprocessor(&calls), &cache);
+        let wasm_costs = WasmCosts::default();
+        for code in &[[1u8], [2], [1], [3]] {
+            preprocessor.preprocess(code, &wasm_costs).unwrap();
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.metrics().evictions, 1);
+        // The module of [2] was evicted, the one of [1] is still cached.
+        preprocessor.preprocess(&[1], &wasm_costs).unwrap();
+        assert_eq!(calls.get(), 3);
+        preprocessor.preprocess(&[2], &wasm_costs).unwrap();
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn changed_wasm_costs_invalidate_the_cache() {
+        let calls = Cell::new(0);
+        let cache = ModuleCache::new(2);
+        let preprocessor = CachingPreprocessor::new(CountingPreprocessor(&calls), &cache);
+        let wasm_costs = WasmCosts::default();
+        let other_costs = WasmCosts {
+            regular: wasm_costs.regular + 1,
+            ..WasmCosts::default()
+        };
+        preprocessor.preprocess(&[1], &wasm_costs).unwrap();
+        preprocessor.preprocess(&[1], &other_costs).unwrap();
+        assert_eq!(calls.get(), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.metrics().invalidations, 1);
+    }
+
+    #[test]
+    fn nothing_is_cached_without_capacity() {
+        let calls = Cell::new(0);
+        let cache = ModuleCache::new(0);
+        let preprocessor = CachingPreprocessor::new(CountingPreprocessor(&calls), &cache);
+        let wasm_costs = WasmCosts::default();
+        preprocessor.preprocess(&[1], &wasm_costs).unwrap();
+        preprocessor.preprocess(&[1], &wasm_costs).unwrap();
+        assert_eq!(calls.get(), 2);
+        assert!(cache.is_empty());
+    }
+}
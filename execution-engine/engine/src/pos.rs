@@ -0,0 +1,1096 @@
+//! Native implementation of the Proof-of-Stake system contract.
+//!
+//! The contract lives under the key registered for
+//! `SystemContractId::ProofOfStake`. Its named keys point to the purse holding
+//! the bonded tokens, to the queues of bonding and unbonding requests and to
+//! the stake of every bonded validator. Requests wait in the queues for the
+//! configured number of blocks and are settled the next time the contract is
+//! called, or at the end of the era. Validators which equivocate are slashed at
+//! the end of the era and can't bond or unbond again.
+//!
+//! Accounts can also delegate tokens to a bonded validator. Delegations go
+//! through queues of their own and are kept in a dictionary per validator, as
+//! described in `common::pos`. They add to the weight of the validator but
+//! not to its stake, so they aren't slashed with it. Rewards are shared
+//! between the validator and its delegators in proportion to their tokens.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use common::bytesrepr::{self, deserialize, vec_from_bytes, vec_to_bytes, FromBytes, ToBytes};
+use common::key::{AccessRights, Key};
+use common::pos::{
+    delegation_item_name, delegations_key_name, delegator_from_item_name, stake_key_name,
+    validator_from_stake_key_name, PosError, DELEGATED_TOTAL_ITEM, DELEGATORS_ITEM,
+};
+use common::value::account::{PublicKey, PurseId};
+use common::value::contract::PROTOCOL_VERSION;
+use common::value::{Contract, Value, U512};
+use execution::{dictionary_item_key, Error};
+use mint;
+use shared::newtypes::Blake2bHash;
+use storage::global_state::StateReader;
+use storage::transform::TypeMismatch;
+use system::SystemContext;
+use trackingcopy::TrackingCopy;
+
+pub const BONDING_PURSE_KEY: &str = "bonding_purse";
+pub const BONDING_QUEUE_KEY: &str = "bonding_queue";
+pub const UNBONDING_QUEUE_KEY: &str = "unbonding_queue";
+pub const SLASHED_VALIDATORS_KEY: &str = "slashed_validators";
+pub const DELEGATION_QUEUE_KEY: &str = "delegation_queue";
+pub const UNDELEGATION_QUEUE_KEY: &str = "undelegation_queue";
+
+/// Number of blocks requests wait in the queues before they are settled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueDelays {
+    pub bonding: u64,
+    pub unbonding: u64,
+}
+
+/// Request of a validator waiting in one of the queues.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueEntry {
+    pub validator: PublicKey,
+    pub amount: U512,
+    /// Height of the block the request was made in.
+    pub height: u64,
+    /// Purse the tokens are paid out to once an unbonding request is settled.
+    pub purse: PurseId,
+}
+
+impl ToBytes for QueueEntry {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.validator.to_bytes()?;
+        result.append(&mut self.amount.to_bytes()?);
+        result.append(&mut self.height.to_bytes()?);
+        result.append(&mut self.purse.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for QueueEntry {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (validator, rem): (PublicKey, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (amount, rem): (U512, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (height, rem): (u64, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (purse, rem): (PurseId, &[u8]) = FromBytes::from_bytes(rem)?;
+        let entry = QueueEntry {
+            validator,
+            amount,
+            height,
+            purse,
+        };
+        Ok((entry, rem))
+    }
+}
+
+/// Request of a delegator waiting in one of the delegation queues. The
+/// validator of the request is the one the tokens are delegated to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelegationEntry {
+    pub delegator: PublicKey,
+    pub request: QueueEntry,
+}
+
+impl ToBytes for DelegationEntry {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.delegator.to_bytes()?;
+        result.append(&mut self.request.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for DelegationEntry {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (delegator, rem): (PublicKey, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (request, rem): (QueueEntry, &[u8]) = FromBytes::from_bytes(rem)?;
+        Ok((DelegationEntry { delegator, request }, rem))
+    }
+}
+
+fn local_addr(contract_key: Key, name: &str) -> Result<[u8; 32], Error> {
+    let mut seed = contract_key.to_bytes()?;
+    seed.extend_from_slice(name.as_bytes());
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Blake2bHash::new(&seed).to_vec());
+    Ok(hash)
+}
+
+/// Key of the value called `name` in the local storage of the contract.
+pub(crate) fn local_key(contract_key: Key, name: &str) -> Result<Key, Error> {
+    Ok(Key::Local(local_addr(contract_key, name)?))
+}
+
+/// Address of the seed of the dictionary of the delegations to `validator`.
+fn delegations_seed(contract_key: Key, validator: PublicKey) -> Result<[u8; 32], Error> {
+    local_addr(contract_key, &delegations_key_name(validator))
+}
+
+fn empty_queue() -> Result<Value, Error> {
+    Ok(Value::ByteArray(Vec::<Vec<u8>>::new().to_bytes()?))
+}
+
+fn queue_value<T: ToBytes>(entries: &[T]) -> Result<Value, Error> {
+    let entries = entries
+        .iter()
+        .map(ToBytes::to_bytes)
+        .collect::<Result<Vec<Vec<u8>>, _>>()?;
+    Ok(Value::ByteArray(entries.to_bytes()?))
+}
+
+/// Stores a PoS contract without any stakes under `contract_key`, with an
+/// empty bonding purse at `bonding_purse_addr`.
+pub fn install<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    bonding_purse_addr: [u8; 32],
+) -> Result<(), Error> {
+    let bonding_purse = mint::create_purse(state, bonding_purse_addr);
+    let mut named_keys = BTreeMap::new();
+    named_keys.insert(BONDING_PURSE_KEY.to_string(), bonding_purse.into());
+    let queues = [
+        BONDING_QUEUE_KEY,
+        UNBONDING_QUEUE_KEY,
+        DELEGATION_QUEUE_KEY,
+        UNDELEGATION_QUEUE_KEY,
+    ];
+    for name in &queues {
+        let queue_key = local_key(contract_key, name)?;
+        state.write(queue_key, empty_queue()?);
+        named_keys.insert(name.to_string(), queue_key);
+    }
+    let slashed_key = local_key(contract_key, SLASHED_VALIDATORS_KEY)?;
+    state.write(slashed_key, Value::ByteArray(vec_to_bytes::<PublicKey>(&[])?));
+    named_keys.insert(SLASHED_VALIDATORS_KEY.to_string(), slashed_key);
+    let contract = Contract::new(Vec::new(), named_keys, PROTOCOL_VERSION);
+    state.write(contract_key, Value::Contract(contract));
+    Ok(())
+}
+
+pub(crate) fn read_contract<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+) -> Result<Contract, Error>
+where
+    R::Error: Into<Error>,
+{
+    match state.read(contract_key).map_err(Into::into)? {
+        Some(Value::Contract(contract)) => Ok(contract),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "Contract".to_string(),
+            other.type_string(),
+        ))),
+        None => Err(Error::KeyNotFound(contract_key)),
+    }
+}
+
+pub(crate) fn named_key(contract: &Contract, name: &str) -> Result<Key, Error> {
+    contract
+        .urefs_lookup()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::URefNotFound(name.to_string()))
+}
+
+fn bonding_purse(contract: &Contract) -> Result<PurseId, Error> {
+    match named_key(contract, BONDING_PURSE_KEY)? {
+        Key::URef(addr, rights) => Ok(PurseId::new(addr, rights)),
+        _ => Err(Error::URefNotFound(BONDING_PURSE_KEY.to_string())),
+    }
+}
+
+fn read_entries<R: StateReader<Key, Value>, T: FromBytes>(
+    state: &mut TrackingCopy<R>,
+    queue_key: Key,
+) -> Result<Vec<T>, Error>
+where
+    R::Error: Into<Error>,
+{
+    match state.read(queue_key).map_err(Into::into)? {
+        Some(Value::ByteArray(bytes)) => {
+            let entries: Vec<Vec<u8>> = deserialize(&bytes)?;
+            entries
+                .iter()
+                .map(|entry| deserialize(entry).map_err(Into::into))
+                .collect()
+        }
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "ByteArray".to_string(),
+            other.type_string(),
+        ))),
+        None => Err(Error::KeyNotFound(queue_key)),
+    }
+}
+
+fn read_queue<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+    name: &str,
+) -> Result<Vec<QueueEntry>, Error>
+where
+    R::Error: Into<Error>,
+{
+    read_entries(state, named_key(contract, name)?)
+}
+
+fn write_queue<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+    name: &str,
+    entries: &[QueueEntry],
+) -> Result<(), Error> {
+    let queue_key = named_key(contract, name)?;
+    state.write(queue_key, queue_value(entries)?);
+    Ok(())
+}
+
+// Contracts installed before delegation was added have no such queues.
+fn read_delegation_queue<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+    name: &str,
+) -> Result<Vec<DelegationEntry>, Error>
+where
+    R::Error: Into<Error>,
+{
+    match contract.urefs_lookup().get(name) {
+        Some(queue_key) => read_entries(state, *queue_key),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn write_delegation_queue<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    contract: &mut Contract,
+    name: &str,
+    entries: &[DelegationEntry],
+) -> Result<(), Error> {
+    let queue_key = local_key(contract_key, name)?;
+    state.write(queue_key, queue_value(entries)?);
+    let mut named_keys = BTreeMap::new();
+    named_keys.insert(name.to_string(), queue_key);
+    contract.insert_urefs(&mut named_keys);
+    Ok(())
+}
+
+// Contracts installed before slashing was added have no such named key.
+pub(crate) fn slashed_validators<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+) -> Result<BTreeSet<PublicKey>, Error>
+where
+    R::Error: Into<Error>,
+{
+    let slashed_key = match contract.urefs_lookup().get(SLASHED_VALIDATORS_KEY) {
+        Some(key) => *key,
+        None => return Ok(BTreeSet::new()),
+    };
+    match state.read(slashed_key).map_err(Into::into)? {
+        Some(Value::ByteArray(bytes)) => {
+            let (validators, _): (Vec<PublicKey>, _) = vec_from_bytes(&bytes)?;
+            Ok(validators.into_iter().collect())
+        }
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "ByteArray".to_string(),
+            other.type_string(),
+        ))),
+        None => Err(Error::KeyNotFound(slashed_key)),
+    }
+}
+
+fn check_not_slashed<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+    validator: PublicKey,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    if slashed_validators(state, contract)?.contains(&validator) {
+        return Err(Error::Pos(PosError::Slashed));
+    }
+    Ok(())
+}
+
+fn stake<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract: &Contract,
+    validator: PublicKey,
+) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    let stake_key = match contract.urefs_lookup().get(&stake_key_name(validator)) {
+        Some(key) => *key,
+        None => return Ok(U512::zero()),
+    };
+    match state.read(stake_key).map_err(Into::into)? {
+        Some(Value::UInt512(stake)) => Ok(stake),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "UInt512".to_string(),
+            other.type_string(),
+        ))),
+        None => Err(Error::KeyNotFound(stake_key)),
+    }
+}
+
+// Validators whose stake drops to zero are removed from the named keys.
+fn set_stake<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    contract: &mut Contract,
+    validator: PublicKey,
+    stake: U512,
+) -> Result<(), Error> {
+    let name = stake_key_name(validator);
+    let stake_key = local_key(contract_key, &name)?;
+    state.write(stake_key, Value::UInt512(stake));
+    if stake.is_zero() {
+        contract.remove_uref(&name);
+    } else {
+        let mut named_keys = BTreeMap::new();
+        named_keys.insert(name, stake_key);
+        contract.insert_urefs(&mut named_keys);
+    }
+    Ok(())
+}
+
+/// Tokens under `item` in the dictionary of the delegations to `validator`:
+/// those delegated by one delegator, or `DELEGATED_TOTAL_ITEM`.
+fn delegation<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    validator: PublicKey,
+    item: &str,
+) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    let seed = delegations_seed(contract_key, validator)?;
+    let item_key = dictionary_item_key(seed, item.as_bytes())?;
+    match state.read(item_key).map_err(Into::into)? {
+        Some(Value::UInt512(amount)) => Ok(amount),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "UInt512".to_string(),
+            other.type_string(),
+        ))),
+        None => Ok(U512::zero()),
+    }
+}
+
+/// Delegators with tokens delegated to `validator`.
+fn delegators<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    validator: PublicKey,
+) -> Result<Vec<PublicKey>, Error>
+where
+    R::Error: Into<Error>,
+{
+    let seed = delegations_seed(contract_key, validator)?;
+    let item_key = dictionary_item_key(seed, DELEGATORS_ITEM.as_bytes())?;
+    match state.read(item_key).map_err(Into::into)? {
+        Some(Value::ListString(names)) => Ok(names
+            .iter()
+            .filter_map(|name| delegator_from_item_name(name))
+            .collect()),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "ListString".to_string(),
+            other.type_string(),
+        ))),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Replaces the tokens `delegator` delegated to `validator` by what `update`
+/// returns for them, and updates their total and the list of delegators
+/// accordingly. The seed of the dictionary is removed from the named keys
+/// once nothing is delegated to the validator.
+fn update_delegation<R, F>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    contract: &mut Contract,
+    validator: PublicKey,
+    delegator: PublicKey,
+    update: F,
+) -> Result<(), Error>
+where
+    R: StateReader<Key, Value>,
+    R::Error: Into<Error>,
+    F: Fn(U512) -> U512,
+{
+    let seed = delegations_seed(contract_key, validator)?;
+    let item = delegation_item_name(delegator);
+    let current = delegation(state, contract_key, validator, &item)?;
+    let updated = update(current);
+    let total = delegation(state, contract_key, validator, DELEGATED_TOTAL_ITEM)?;
+    // `update` never takes more than the delegator delegated.
+    let total = total - current + updated;
+    state.write(
+        dictionary_item_key(seed, item.as_bytes())?,
+        Value::UInt512(updated),
+    );
+    state.write(
+        dictionary_item_key(seed, DELEGATED_TOTAL_ITEM.as_bytes())?,
+        Value::UInt512(total),
+    );
+
+    let mut delegators = delegators(state, contract_key, validator)?;
+    let listed = delegators.contains(&delegator);
+    if listed == updated.is_zero() {
+        if listed {
+            delegators.retain(|key| *key != delegator);
+        } else {
+            delegators.push(delegator);
+        }
+        let names = delegators.into_iter().map(delegation_item_name).collect();
+        state.write(
+            dictionary_item_key(seed, DELEGATORS_ITEM.as_bytes())?,
+            Value::ListString(names),
+        );
+    }
+
+    let name = delegations_key_name(validator);
+    if total.is_zero() {
+        contract.remove_uref(&name);
+    } else {
+        let mut named_keys = BTreeMap::new();
+        named_keys.insert(name, Key::URef(seed, AccessRights::READ_ADD_WRITE));
+        contract.insert_urefs(&mut named_keys);
+    }
+    Ok(())
+}
+
+/// Settles the requests which have waited long enough at `height`.
+pub fn process_queues<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    height: u64,
+    delays: QueueDelays,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let mut contract = read_contract(state, contract_key)?;
+    let (bonds, waiting_bonds): (Vec<QueueEntry>, Vec<QueueEntry>) =
+        read_queue(state, &contract, BONDING_QUEUE_KEY)?
+            .into_iter()
+            .partition(|entry| entry.height.saturating_add(delays.bonding) <= height);
+    let (unbonds, waiting_unbonds): (Vec<QueueEntry>, Vec<QueueEntry>) =
+        read_queue(state, &contract, UNBONDING_QUEUE_KEY)?
+            .into_iter()
+            .partition(|entry| entry.height.saturating_add(delays.unbonding) <= height);
+    let (delegations, waiting_delegations): (Vec<DelegationEntry>, Vec<DelegationEntry>) =
+        read_delegation_queue(state, &contract, DELEGATION_QUEUE_KEY)?
+            .into_iter()
+            .partition(|entry| entry.request.height.saturating_add(delays.bonding) <= height);
+    let (undelegations, waiting_undelegations): (Vec<DelegationEntry>, Vec<DelegationEntry>) =
+        read_delegation_queue(state, &contract, UNDELEGATION_QUEUE_KEY)?
+            .into_iter()
+            .partition(|entry| entry.request.height.saturating_add(delays.unbonding) <= height);
+    if bonds.is_empty()
+        && unbonds.is_empty()
+        && delegations.is_empty()
+        && undelegations.is_empty()
+    {
+        return Ok(());
+    }
+    for entry in bonds {
+        let new_stake = stake(state, &contract, entry.validator)? + entry.amount;
+        set_stake(state, contract_key, &mut contract, entry.validator, new_stake)?;
+    }
+    let bonding_purse = bonding_purse(&contract)?;
+    for entry in unbonds {
+        // Unbonding requests never exceed the stake which isn't being unbonded yet.
+        let new_stake = stake(state, &contract, entry.validator)? - entry.amount;
+        set_stake(state, contract_key, &mut contract, entry.validator, new_stake)?;
+        mint::transfer(state, bonding_purse, entry.purse, entry.amount)?;
+    }
+    for entry in delegations {
+        let DelegationEntry { delegator, request } = entry;
+        let validator = request.validator;
+        let amount = request.amount;
+        update_delegation(state, contract_key, &mut contract, validator, delegator, |current| {
+            current + amount
+        })?;
+    }
+    for entry in undelegations {
+        // Undelegation requests never exceed what isn't being undelegated yet.
+        let DelegationEntry { delegator, request } = entry;
+        let validator = request.validator;
+        let amount = request.amount;
+        update_delegation(state, contract_key, &mut contract, validator, delegator, |current| {
+            current - amount
+        })?;
+        mint::transfer(state, bonding_purse, request.purse, amount)?;
+    }
+    write_queue(state, &contract, BONDING_QUEUE_KEY, &waiting_bonds)?;
+    write_queue(state, &contract, UNBONDING_QUEUE_KEY, &waiting_unbonds)?;
+    for &(name, waiting) in &[
+        (DELEGATION_QUEUE_KEY, &waiting_delegations),
+        (UNDELEGATION_QUEUE_KEY, &waiting_undelegations),
+    ] {
+        write_delegation_queue(state, contract_key, &mut contract, name, waiting)?;
+    }
+    state.write(contract_key, Value::Contract(contract));
+    Ok(())
+}
+
+/// Moves `amount` of tokens from `source` to the bonding purse and queues
+/// them to be added to the stake of `validator`.
+pub fn bond<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    validator: PublicKey,
+    source: PurseId,
+    amount: U512,
+    height: u64,
+    delays: QueueDelays,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let contract = read_contract(state, contract_key)?;
+    check_not_slashed(state, &contract, validator)?;
+    match mint::transfer(state, source, bonding_purse(&contract)?, amount) {
+        Ok(()) => (),
+        Err(Error::InsufficientFunds) => return Err(Error::Pos(PosError::InsufficientFunds)),
+        Err(error) => return Err(error),
+    }
+    let mut queue = read_queue(state, &contract, BONDING_QUEUE_KEY)?;
+    queue.push(QueueEntry {
+        validator,
+        amount,
+        height,
+        purse: source,
+    });
+    write_queue(state, &contract, BONDING_QUEUE_KEY, &queue)?;
+    process_queues(state, contract_key, height, delays)
+}
+
+/// Queues `amount` of tokens to be taken off the stake of `validator` and
+/// paid out to `target`.
+pub fn unbond<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    validator: PublicKey,
+    target: PurseId,
+    amount: U512,
+    height: u64,
+    delays: QueueDelays,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    process_queues(state, contract_key, height, delays)?;
+    let contract = read_contract(state, contract_key)?;
+    check_not_slashed(state, &contract, validator)?;
+    let stake = stake(state, &contract, validator)?;
+    if stake.is_zero() {
+        return Err(Error::Pos(PosError::NotBonded));
+    }
+    let mut queue = read_queue(state, &contract, UNBONDING_QUEUE_KEY)?;
+    let unbonding = queue
+        .iter()
+        .filter(|entry| entry.validator == validator)
+        .fold(U512::zero(), |total, entry| total + entry.amount);
+    if amount > stake - unbonding {
+        return Err(Error::Pos(PosError::UnbondTooLarge));
+    }
+    queue.push(QueueEntry {
+        validator,
+        amount,
+        height,
+        purse: target,
+    });
+    write_queue(state, &contract, UNBONDING_QUEUE_KEY, &queue)?;
+    process_queues(state, contract_key, height, delays)
+}
+
+/// Moves `amount` of tokens from `source` to the bonding purse and queues
+/// them to be delegated by `delegator` to `validator`, which has to be bonded.
+#[allow(clippy::too_many_arguments)]
+pub fn delegate<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    delegator: PublicKey,
+    validator: PublicKey,
+    source: PurseId,
+    amount: U512,
+    height: u64,
+    delays: QueueDelays,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    let mut contract = read_contract(state, contract_key)?;
+    check_not_slashed(state, &contract, validator)?;
+    if stake(state, &contract, validator)?.is_zero() {
+        return Err(Error::Pos(PosError::NotBonded));
+    }
+    match mint::transfer(state, source, bonding_purse(&contract)?, amount) {
+        Ok(()) => (),
+        Err(Error::InsufficientFunds) => return Err(Error::Pos(PosError::InsufficientFunds)),
+        Err(error) => return Err(error),
+    }
+    let mut queue = read_delegation_queue(state, &contract, DELEGATION_QUEUE_KEY)?;
+    queue.push(DelegationEntry {
+        delegator,
+        request: QueueEntry {
+            validator,
+            amount,
+            height,
+            purse: source,
+        },
+    });
+    write_delegation_queue(state, contract_key, &mut contract, DELEGATION_QUEUE_KEY, &queue)?;
+    state.write(contract_key, Value::Contract(contract));
+    process_queues(state, contract_key, height, delays)
+}
+
+/// Queues `amount` of the tokens `delegator` delegated to `validator` to be
+/// undelegated and paid out to `target`. Tokens delegated to validators
+/// which were slashed or unbonded can still be undelegated.
+#[allow(clippy::too_many_arguments)]
+pub fn undelegate<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    delegator: PublicKey,
+    validator: PublicKey,
+    target: PurseId,
+    amount: U512,
+    height: u64,
+    delays: QueueDelays,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    process_queues(state, contract_key, height, delays)?;
+    let mut contract = read_contract(state, contract_key)?;
+    let item = delegation_item_name(delegator);
+    let delegation = delegation(state, contract_key, validator, &item)?;
+    if delegation.is_zero() {
+        return Err(Error::Pos(PosError::NotDelegated));
+    }
+    let mut queue = read_delegation_queue(state, &contract, UNDELEGATION_QUEUE_KEY)?;
+    let undelegating = queue
+        .iter()
+        .filter(|entry| entry.delegator == delegator && entry.request.validator == validator)
+        .fold(U512::zero(), |total, entry| total + entry.request.amount);
+    if amount > delegation - undelegating {
+        return Err(Error::Pos(PosError::UndelegateTooLarge));
+    }
+    queue.push(DelegationEntry {
+        delegator,
+        request: QueueEntry {
+            validator,
+            amount,
+            height,
+            purse: target,
+        },
+    });
+    write_delegation_queue(state, contract_key, &mut contract, UNDELEGATION_QUEUE_KEY, &queue)?;
+    state.write(contract_key, Value::Contract(contract));
+    process_queues(state, contract_key, height, delays)
+}
+
+/// Takes the stakes and the pending bonds of `validators` and moves the tokens
+/// to `target`, or burns them without one. Their unbonding requests are
+/// dropped and they are recorded as slashed, so they can't get any of the
+/// tokens back. Returns the amount slashed.
+pub fn slash<R: StateReader<Key, Value>>(
+    context: &SystemContext,
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    validators: &BTreeSet<PublicKey>,
+    target: Option<PurseId>,
+) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    if validators.is_empty() {
+        return Ok(U512::zero());
+    }
+    let mut contract = read_contract(state, contract_key)?;
+    let (slashed_bonds, bonds): (Vec<QueueEntry>, Vec<QueueEntry>) =
+        read_queue(state, &contract, BONDING_QUEUE_KEY)?
+            .into_iter()
+            .partition(|entry| validators.contains(&entry.validator));
+    let unbonds: Vec<QueueEntry> = read_queue(state, &contract, UNBONDING_QUEUE_KEY)?
+        .into_iter()
+        .filter(|entry| !validators.contains(&entry.validator))
+        .collect();
+    let mut slashed = slashed_bonds
+        .iter()
+        .fold(U512::zero(), |total, entry| total + entry.amount);
+    for validator in validators {
+        let stake = stake(state, &contract, *validator)?;
+        if !stake.is_zero() {
+            set_stake(state, contract_key, &mut contract, *validator, U512::zero())?;
+            slashed += stake;
+        }
+    }
+    write_queue(state, &contract, BONDING_QUEUE_KEY, &bonds)?;
+    write_queue(state, &contract, UNBONDING_QUEUE_KEY, &unbonds)?;
+
+    let mut recorded = slashed_validators(state, &contract)?;
+    recorded.extend(validators.iter().cloned());
+    let recorded: Vec<PublicKey> = recorded.into_iter().collect();
+    let slashed_key = local_key(contract_key, SLASHED_VALIDATORS_KEY)?;
+    state.write(slashed_key, Value::ByteArray(vec_to_bytes(&recorded)?));
+    let mut named_keys = BTreeMap::new();
+    named_keys.insert(SLASHED_VALIDATORS_KEY.to_string(), slashed_key);
+    contract.insert_urefs(&mut named_keys);
+
+    let bonding_purse = bonding_purse(&contract)?;
+    match target {
+        Some(target) => context.transfer(state, bonding_purse, target, slashed)?,
+        None => context.burn(state, bonding_purse, slashed)?,
+    }
+    state.write(contract_key, Value::Contract(contract));
+    Ok(slashed)
+}
+
+/// Bonds the balance of `rewards_purse` to the validators not listed as
+/// `inactive` and to their delegators. Every validator gets a part in
+/// proportion to its weight, which is shared with its delegators in
+/// proportion to their delegations, the validator getting what's left over
+/// by rounding. What's left over by rounding the parts of the validators
+/// stays in the purse. Returns the amount distributed.
+pub fn distribute_rewards<R: StateReader<Key, Value>>(
+    context: &SystemContext,
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    rewards_purse: PurseId,
+    inactive: &BTreeSet<PublicKey>,
+) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    let rewarded: BTreeMap<PublicKey, U512> = weights(state, contract_key)?
+        .into_iter()
+        .filter(|(validator, _)| !inactive.contains(validator))
+        .collect();
+    let total_weight = rewarded.values().fold(U512::zero(), |total, weight| total + *weight);
+    let balance = mint::balance(state, rewards_purse)?;
+    if total_weight.is_zero() || balance.is_zero() {
+        return Ok(U512::zero());
+    }
+    let mut contract = read_contract(state, contract_key)?;
+    let mut distributed = U512::zero();
+    for (validator, weight) in rewarded {
+        let reward = balance.checked_mul(weight).ok_or(Error::Overflow)? / total_weight;
+        if reward.is_zero() {
+            continue;
+        }
+        let mut validator_reward = reward;
+        for delegator in delegators(state, contract_key, validator)? {
+            let item = delegation_item_name(delegator);
+            let delegation = delegation(state, contract_key, validator, &item)?;
+            let delegator_reward = reward.checked_mul(delegation).ok_or(Error::Overflow)? / weight;
+            update_delegation(state, contract_key, &mut contract, validator, delegator, |current| {
+                current + delegator_reward
+            })?;
+            validator_reward -= delegator_reward;
+        }
+        let stake = stake(state, &contract, validator)?;
+        set_stake(state, contract_key, &mut contract, validator, stake + validator_reward)?;
+        distributed += reward;
+    }
+    context.transfer(state, rewards_purse, bonding_purse(&contract)?, distributed)?;
+    state.write(contract_key, Value::Contract(contract));
+    Ok(distributed)
+}
+
+/// Stakes of the bonded validators, as found in the named keys of the contract.
+pub fn stakes<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+) -> Result<BTreeMap<PublicKey, U512>, Error>
+where
+    R::Error: Into<Error>,
+{
+    let contract = read_contract(state, contract_key)?;
+    let mut stakes = BTreeMap::new();
+    for name in contract.urefs_lookup().keys() {
+        if let Some(validator) = validator_from_stake_key_name(name) {
+            stakes.insert(validator, stake(state, &contract, validator)?);
+        }
+    }
+    Ok(stakes)
+}
+
+/// Weights of the bonded validators: their stakes plus the tokens delegated
+/// to them.
+pub fn weights<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+) -> Result<BTreeMap<PublicKey, U512>, Error>
+where
+    R::Error: Into<Error>,
+{
+    let mut weights = stakes(state, contract_key)?;
+    for (validator, weight) in weights.iter_mut() {
+        *weight += delegation(state, contract_key, *validator, DELEGATED_TOTAL_ITEM)?;
+    }
+    Ok(weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use common::key::Key;
+    use common::pos::{delegations_key_name, PosError};
+    use common::value::account::{PublicKey, PurseId};
+    use common::value::{Value, U512};
+    use storage::global_state::inmem::InMemGS;
+
+    use super::{install, process_queues, read_contract, slash, stakes, weights, QueueDelays};
+    use execution::Error;
+    use mint;
+    use system::{SystemContext, SystemFlow};
+    use trackingcopy::TrackingCopy;
+
+    const CONTRACT_KEY: Key = Key::Hash([7u8; 32]);
+    const VALIDATOR_KEY: [u8; 32] = [1u8; 32];
+    const DELEGATOR_KEY: [u8; 32] = [5u8; 32];
+
+    type TestTrackingCopy = TrackingCopy<InMemGS<Key, Value>>;
+
+    fn setup(balance: u64) -> (TestTrackingCopy, PurseId) {
+        let mut tc = TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        install(&mut tc, CONTRACT_KEY, [2u8; 32]).expect("should install");
+        let purse = mint::create_purse(&mut tc, [3u8; 32]);
+        tc.write(mint::balance_key(purse), Value::UInt512(U512::from(balance)));
+        (tc, purse)
+    }
+
+    fn bond(
+        tc: &mut TestTrackingCopy,
+        purse: PurseId,
+        amount: u64,
+        height: u64,
+        delays: QueueDelays,
+    ) -> Result<(), Error> {
+        let validator = PublicKey::new(VALIDATOR_KEY);
+        super::bond(tc, CONTRACT_KEY, validator, purse, U512::from(amount), height, delays)
+    }
+
+    fn unbond(
+        tc: &mut TestTrackingCopy,
+        purse: PurseId,
+        amount: u64,
+        height: u64,
+        delays: QueueDelays,
+    ) -> Result<(), Error> {
+        let validator = PublicKey::new(VALIDATOR_KEY);
+        super::unbond(tc, CONTRACT_KEY, validator, purse, U512::from(amount), height, delays)
+    }
+
+    fn delegate(
+        tc: &mut TestTrackingCopy,
+        purse: PurseId,
+        validator: [u8; 32],
+        amount: u64,
+        height: u64,
+        delays: QueueDelays,
+    ) -> Result<(), Error> {
+        super::delegate(
+            tc,
+            CONTRACT_KEY,
+            PublicKey::new(DELEGATOR_KEY),
+            PublicKey::new(validator),
+            purse,
+            U512::from(amount),
+            height,
+            delays,
+        )
+    }
+
+    fn undelegate(
+        tc: &mut TestTrackingCopy,
+        purse: PurseId,
+        amount: u64,
+        height: u64,
+        delays: QueueDelays,
+    ) -> Result<(), Error> {
+        super::undelegate(
+            tc,
+            CONTRACT_KEY,
+            PublicKey::new(DELEGATOR_KEY),
+            PublicKey::new(VALIDATOR_KEY),
+            purse,
+            U512::from(amount),
+            height,
+            delays,
+        )
+    }
+
+    fn weight(tc: &mut TestTrackingCopy) -> Option<U512> {
+        let weights = weights(tc, CONTRACT_KEY).expect("should read weights");
+        weights.get(&PublicKey::new(VALIDATOR_KEY)).cloned()
+    }
+
+    fn stake(tc: &mut TestTrackingCopy) -> Option<U512> {
+        let stakes = stakes(tc, CONTRACT_KEY).expect("should read stakes");
+        stakes.get(&PublicKey::new(VALIDATOR_KEY)).cloned()
+    }
+
+    #[test]
+    fn bonds_take_effect_after_the_delay() {
+        let (mut tc, purse) = setup(100);
+        let delays = QueueDelays {
+            bonding: 2,
+            unbonding: 0,
+        };
+        bond(&mut tc, purse, 60, 10, delays).expect("should bond");
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::from(40));
+        assert_eq!(stake(&mut tc), None);
+
+        // Settled by the next call made late enough.
+        bond(&mut tc, purse, 10, 12, delays).expect("should bond");
+        assert_eq!(stake(&mut tc), Some(U512::from(60)));
+    }
+
+    #[test]
+    fn unbonds_are_paid_out_after_the_delay() {
+        let (mut tc, purse) = setup(100);
+        let delays = QueueDelays {
+            bonding: 0,
+            unbonding: 5,
+        };
+        bond(&mut tc, purse, 100, 1, delays).expect("should bond");
+        assert_eq!(stake(&mut tc), Some(U512::from(100)));
+
+        unbond(&mut tc, purse, 70, 2, delays).expect("should unbond");
+        // What's being unbonded can't be unbonded again.
+        assert_matches!(
+            unbond(&mut tc, purse, 40, 3, delays),
+            Err(Error::Pos(PosError::UnbondTooLarge))
+        );
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::zero());
+
+        unbond(&mut tc, purse, 30, 7, delays).expect("should unbond");
+        assert_eq!(stake(&mut tc), Some(U512::from(30)));
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::from(70));
+    }
+
+    #[test]
+    fn fully_unbonded_validators_are_removed() {
+        let (mut tc, purse) = setup(100);
+        let delays = QueueDelays::default();
+        bond(&mut tc, purse, 50, 1, delays).expect("should bond");
+        unbond(&mut tc, purse, 50, 1, delays).expect("should unbond");
+        assert_eq!(stake(&mut tc), None);
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::from(100));
+        assert_matches!(
+            unbond(&mut tc, purse, 1, 1, delays),
+            Err(Error::Pos(PosError::NotBonded))
+        );
+    }
+
+    #[test]
+    fn bonding_more_than_the_purse_holds_fails() {
+        let (mut tc, purse) = setup(10);
+        assert_matches!(
+            bond(&mut tc, purse, 11, 1, QueueDelays::default()),
+            Err(Error::Pos(PosError::InsufficientFunds))
+        );
+    }
+
+    #[test]
+    fn slashed_validators_lose_their_stake_and_requests() {
+        let (mut tc, purse) = setup(100);
+        let delays = QueueDelays {
+            bonding: 5,
+            unbonding: 5,
+        };
+        bond(&mut tc, purse, 50, 1, QueueDelays::default()).expect("should bond");
+        bond(&mut tc, purse, 20, 2, delays).expect("should bond");
+        unbond(&mut tc, purse, 30, 2, delays).expect("should unbond");
+
+        let target = mint::create_purse(&mut tc, [4u8; 32]);
+        let mut validators = BTreeSet::new();
+        validators.insert(PublicKey::new(VALIDATOR_KEY));
+        let context = SystemContext::new(SystemFlow::EraEnd);
+        let slashed = slash(&context, &mut tc, CONTRACT_KEY, &validators, Some(target))
+            .expect("should slash");
+        // The stake and the pending bond, of which the pending unbond was a part.
+        assert_eq!(slashed, U512::from(70));
+        assert_eq!(mint::balance(&mut tc, target).unwrap(), U512::from(70));
+        assert_eq!(stake(&mut tc), None);
+
+        assert_matches!(
+            unbond(&mut tc, purse, 1, 10, delays),
+            Err(Error::Pos(PosError::Slashed))
+        );
+        assert_matches!(
+            bond(&mut tc, purse, 1, 10, delays),
+            Err(Error::Pos(PosError::Slashed))
+        );
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::from(30));
+    }
+
+    #[test]
+    fn delegations_add_to_the_weight_of_the_validator() {
+        let (mut tc, purse) = setup(100);
+        let delays = QueueDelays::default();
+        bond(&mut tc, purse, 50, 1, delays).expect("should bond");
+        let delegator_purse = mint::create_purse(&mut tc, [6u8; 32]);
+        tc.write(mint::balance_key(delegator_purse), Value::UInt512(U512::from(40)));
+
+        delegate(&mut tc, delegator_purse, VALIDATOR_KEY, 30, 2, delays).expect("should delegate");
+        assert_eq!(stake(&mut tc), Some(U512::from(50)));
+        assert_eq!(weight(&mut tc), Some(U512::from(80)));
+        assert_matches!(
+            delegate(&mut tc, delegator_purse, [9u8; 32], 5, 2, delays),
+            Err(Error::Pos(PosError::NotBonded))
+        );
+        assert_matches!(
+            delegate(&mut tc, delegator_purse, VALIDATOR_KEY, 20, 2, delays),
+            Err(Error::Pos(PosError::InsufficientFunds))
+        );
+
+        assert_matches!(
+            undelegate(&mut tc, delegator_purse, 31, 3, delays),
+            Err(Error::Pos(PosError::UndelegateTooLarge))
+        );
+        undelegate(&mut tc, delegator_purse, 30, 3, delays).expect("should undelegate");
+        assert_eq!(weight(&mut tc), Some(U512::from(50)));
+        assert_eq!(mint::balance(&mut tc, delegator_purse).unwrap(), U512::from(40));
+        assert_matches!(
+            undelegate(&mut tc, delegator_purse, 1, 3, delays),
+            Err(Error::Pos(PosError::NotDelegated))
+        );
+    }
+
+    #[test]
+    fn delegations_wait_for_the_delays() {
+        let (mut tc, purse) = setup(100);
+        let delays = QueueDelays {
+            bonding: 2,
+            unbonding: 5,
+        };
+        bond(&mut tc, purse, 50, 0, QueueDelays::default()).expect("should bond");
+        delegate(&mut tc, purse, VALIDATOR_KEY, 20, 1, delays).expect("should delegate");
+        assert_eq!(weight(&mut tc), Some(U512::from(50)));
+
+        process_queues(&mut tc, CONTRACT_KEY, 3, delays).expect("should process queues");
+        assert_eq!(weight(&mut tc), Some(U512::from(70)));
+        // The seed of the dictionary of delegations is found in the named keys.
+        let name = delegations_key_name(PublicKey::new(VALIDATOR_KEY));
+        let contract = read_contract(&mut tc, CONTRACT_KEY).unwrap();
+        assert!(contract.urefs_lookup().contains_key(&name));
+
+        undelegate(&mut tc, purse, 20, 4, delays).expect("should undelegate");
+        process_queues(&mut tc, CONTRACT_KEY, 8, delays).expect("should process queues");
+        assert_eq!(weight(&mut tc), Some(U512::from(70)));
+        process_queues(&mut tc, CONTRACT_KEY, 9, delays).expect("should process queues");
+        assert_eq!(weight(&mut tc), Some(U512::from(50)));
+        assert_eq!(mint::balance(&mut tc, purse).unwrap(), U512::from(50));
+        let contract = read_contract(&mut tc, CONTRACT_KEY).unwrap();
+        assert!(!contract.urefs_lookup().contains_key(&name));
+    }
+}
@@ -0,0 +1,257 @@
+//! Charges for the bytes deploys add to the global state.
+//!
+//! Writes are charged gas when they're made, but what they write is kept for
+//! good, so the session code of a deploy is also charged for the bytes it
+//! adds to the state, net of the bytes it frees, when the deploy is
+//! finalized. Engines can also record a `StorageCredit` for every value a
+//! deploy grows, adding up the gas paid for its bytes, which future pruning
+//! can expire the value by.
+
+use std::collections::BTreeMap;
+
+use common::bytesrepr::{self, deserialize, FromBytes, ToBytes};
+use common::key::Key;
+use common::value::{Value, U512};
+use execution::Error;
+use shared::gas::Gas;
+use shared::newtypes::Blake2bHash;
+use storage::global_state::StateReader;
+use trackingcopy::TrackingCopy;
+use vm::storage_costs::StorageCosts;
+
+fn serialized_size(key: &Key, value: Option<Value>) -> Result<u64, Error> {
+    match value {
+        Some(value) => Ok((key.to_bytes()?.len() + value.to_bytes()?.len()) as u64),
+        None => Ok(0),
+    }
+}
+
+/// Serialized sizes of the keys and values changed in a tracking copy, with
+/// the sizes they had in its prestate.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedSizes(BTreeMap<Key, (u64, u64)>);
+
+impl ChangedSizes {
+    pub fn measure<R: StateReader<Key, Value>>(
+        state: &mut TrackingCopy<R>,
+    ) -> Result<ChangedSizes, Error>
+    where
+        R::Error: Into<Error>,
+    {
+        let keys: Vec<Key> = state.effect().1.keys().cloned().collect();
+        let mut sizes = BTreeMap::new();
+        for key in keys {
+            let prestate_value = state.prestate_value(&key).map_err(Into::into)?;
+            let value = state.get(&key).map_err(Into::into)?;
+            let prestate_size = serialized_size(&key, prestate_value)?;
+            sizes.insert(key, (prestate_size, serialized_size(&key, value)?));
+        }
+        Ok(ChangedSizes(sizes))
+    }
+
+    /// Size of `key` and its value when they were measured, if they had been
+    /// changed.
+    pub fn size(&self, key: &Key) -> Option<u64> {
+        self.0.get(key).map(|(_, size)| *size)
+    }
+
+    /// Bytes added under every key changed since `earlier` was measured on
+    /// the same tracking copy. Keys which shrank have a negative growth.
+    pub fn growth_since(&self, earlier: &ChangedSizes) -> BTreeMap<Key, i64> {
+        self.0
+            .iter()
+            .filter_map(|(key, (prestate_size, size))| {
+                let start = earlier.size(key).unwrap_or(*prestate_size);
+                let growth = *size as i64 - start as i64;
+                if growth != 0 {
+                    Some((*key, growth))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Bytes added to the state in total, net of the ones freed.
+pub fn net_bytes(growth: &BTreeMap<Key, i64>) -> i64 {
+    growth.values().sum()
+}
+
+/// Gas paid for storing a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageCredit {
+    /// Size of the key and the value when the value was last grown.
+    pub bytes: u64,
+    /// Gas paid for the bytes of the value by all the deploys growing it.
+    pub credit: Gas,
+    /// Height of the block the value was first paid for in.
+    pub created_at: u64,
+}
+
+impl StorageCredit {
+    /// Height of the block the credit stops paying for the value at, if
+    /// keeping a byte costs `rent` gas per block. Values which cost nothing
+    /// to keep don't expire.
+    pub fn expires_at(&self, rent: u64) -> u64 {
+        let rent_per_block = U512::from(rent) * U512::from(self.bytes);
+        if rent_per_block.is_zero() {
+            return u64::max_value();
+        }
+        let blocks = self.credit.value() / rent_per_block;
+        if blocks > U512::from(u64::max_value()) {
+            return u64::max_value();
+        }
+        self.created_at.saturating_add(blocks.as_u64())
+    }
+}
+
+impl ToBytes for StorageCredit {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.bytes.to_bytes()?;
+        result.append(&mut self.credit.value().to_bytes()?);
+        result.append(&mut self.created_at.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for StorageCredit {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (bytes, rem): (u64, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (credit, rem): (U512, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (created_at, rem): (u64, &[u8]) = FromBytes::from_bytes(rem)?;
+        let storage_credit = StorageCredit {
+            bytes,
+            credit: Gas::new(credit),
+            created_at,
+        };
+        Ok((storage_credit, rem))
+    }
+}
+
+/// Key the storage credit of the value under `key` is recorded under.
+pub fn credit_key(key: &Key) -> Result<Key, Error> {
+    let mut seed = b"storage_credit".to_vec();
+    seed.append(&mut key.to_bytes()?);
+    let mut addr = [0u8; 32];
+    addr.copy_from_slice(&Blake2bHash::new(&seed).to_vec());
+    Ok(Key::Local(addr))
+}
+
+pub fn read_credit<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    key: &Key,
+) -> Result<Option<StorageCredit>, Error>
+where
+    R::Error: Into<Error>,
+{
+    match state.read(credit_key(key)?).map_err(Into::into)? {
+        Some(Value::ByteArray(bytes)) => Ok(Some(deserialize(&bytes)?)),
+        Some(_) | None => Ok(None),
+    }
+}
+
+/// Adds the gas paid for the bytes every key in `growth` grew by, at
+/// `storage_costs`, to the credit of its value. `sizes` have to be measured
+/// after the growth.
+pub fn add_credits<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    growth: &BTreeMap<Key, i64>,
+    sizes: &ChangedSizes,
+    storage_costs: StorageCosts,
+    block_height: u64,
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    for (key, bytes) in growth.iter().filter(|(_, bytes)| **bytes > 0) {
+        let paid = Gas::from(storage_costs.bytes_cost(*bytes));
+        let (credit, created_at) = match read_credit(state, key)? {
+            Some(storage_credit) => {
+                (storage_credit.credit.saturating_add(paid), storage_credit.created_at)
+            }
+            None => (paid, block_height),
+        };
+        let storage_credit = StorageCredit {
+            bytes: sizes.size(key).unwrap_or_default(),
+            credit,
+            created_at,
+        };
+        state.write(credit_key(key)?, Value::ByteArray(storage_credit.to_bytes()?));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use common::bytesrepr::{deserialize, ToBytes};
+    use common::key::Key;
+    use common::value::Value;
+    use shared::gas::Gas;
+    use storage::global_state::inmem::InMemGS;
+    use vm::storage_costs::StorageCosts;
+
+    use super::{add_credits, net_bytes, read_credit, ChangedSizes, StorageCredit};
+    use trackingcopy::TrackingCopy;
+
+    const GROWN: Key = Key::Hash([1u8; 32]);
+    const SHRUNK: Key = Key::Hash([2u8; 32]);
+    const CREATED: Key = Key::Hash([3u8; 32]);
+
+    fn tracking_copy() -> TrackingCopy<InMemGS<Key, Value>> {
+        let mut prestate = BTreeMap::new();
+        prestate.insert(GROWN, Value::ByteArray(vec![1; 10]));
+        prestate.insert(SHRUNK, Value::ByteArray(vec![2; 10]));
+        TrackingCopy::new(InMemGS::new(prestate))
+    }
+
+    #[test]
+    fn growth_is_measured_against_the_start() {
+        let mut tc = tracking_copy();
+        tc.write(SHRUNK, Value::ByteArray(vec![2; 4]));
+        let start = ChangedSizes::measure(&mut tc).unwrap();
+        tc.write(GROWN, Value::ByteArray(vec![1; 15]));
+        tc.write(SHRUNK, Value::ByteArray(vec![2; 1]));
+        tc.write(CREATED, Value::ByteArray(vec![3; 2]));
+        let sizes = ChangedSizes::measure(&mut tc).unwrap();
+
+        let growth = sizes.growth_since(&start);
+        assert_eq!(growth[&GROWN], 5);
+        assert_eq!(growth[&SHRUNK], -3);
+        // New values are charged for their keys too.
+        let created_size = (CREATED.to_bytes().unwrap().len() + 7) as i64;
+        assert_eq!(growth[&CREATED], created_size);
+        assert_eq!(net_bytes(&growth), 2 + created_size);
+        assert_eq!(net_bytes(&sizes.growth_since(&sizes)), 0);
+    }
+
+    #[test]
+    fn credits_add_up_the_gas_paid_for_values() {
+        let mut tc = tracking_copy();
+        let storage_costs = StorageCosts::new(3);
+        for (block_height, len) in [(4, 12), (9, 13)].iter() {
+            let start = ChangedSizes::measure(&mut tc).unwrap();
+            tc.write(GROWN, Value::ByteArray(vec![1; *len]));
+            let sizes = ChangedSizes::measure(&mut tc).unwrap();
+            let growth = sizes.growth_since(&start);
+            add_credits(&mut tc, &growth, &sizes, storage_costs, *block_height).unwrap();
+        }
+        let storage_credit = read_credit(&mut tc, &GROWN).unwrap().unwrap();
+        let bytes = (GROWN.to_bytes().unwrap().len() + 18) as u64;
+        assert_eq!(
+            storage_credit,
+            StorageCredit {
+                bytes,
+                credit: Gas::from(9),
+                created_at: 4,
+            }
+        );
+        assert_eq!(storage_credit.expires_at(0), u64::max_value());
+        assert_eq!(storage_credit.expires_at(1), 4 + 9 / bytes);
+        let bytes = storage_credit.to_bytes().unwrap();
+        assert_eq!(deserialize::<StorageCredit>(&bytes), Ok(storage_credit));
+        assert_eq!(read_credit(&mut tc, &SHRUNK).unwrap(), None);
+    }
+}
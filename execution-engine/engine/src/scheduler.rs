@@ -0,0 +1,278 @@
+//! Concurrent execution of the deploys of a block.
+//!
+//! The deploys of a block have to end up with the results of running them in
+//! order, each on the state left by the ones before it. They are first all run
+//! concurrently on the prestate of the block, recording the keys each of them
+//! reads from it and the prefixes of the keys it lists. Going through them in
+//! order, a result is kept unless the deploy read a key changed by an earlier
+//! deploy, or listed a prefix an earlier deploy changed a key under; such
+//! deploys are run again
+//! on the state the earlier deploys leave, which is committed for it at a
+//! checkpoint of the global state, so it's gone once the block has been run.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+
+use common::bytesrepr::ToBytes;
+use common::key::Key;
+use common::trie_merkle_proof::TrieMerkleProof;
+use common::value::Value;
+use crossbeam_utils::thread;
+use engine::{EngineState, Error, ExecutionResult, RootNotFound};
+use execution;
+use shared::newtypes::Blake2bHash;
+use storage::global_state::stored_value::StoredValue;
+use storage::global_state::StateReader;
+use storage::history::{CheckpointId, CommitResult, History};
+use storage::transform::Transform;
+
+/// Keys read by a deploy, and the prefixes of the keys it listed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReadSet {
+    pub keys: HashSet<Key>,
+    /// Listing keys depends on all the keys under the prefix, including the
+    /// ones which don't exist yet, so the prefix itself is recorded.
+    pub prefixes: HashSet<Vec<u8>>,
+}
+
+/// Reader recording the keys read through it.
+pub struct RecordingReader<'a, R> {
+    reader: R,
+    read_set: &'a RefCell<ReadSet>,
+}
+
+impl<'a, R> RecordingReader<'a, R> {
+    pub fn new(reader: R, read_set: &'a RefCell<ReadSet>) -> RecordingReader<'a, R> {
+        RecordingReader { reader, read_set }
+    }
+}
+
+impl<'a, R: StateReader<Key, Value>> StateReader<Key, Value> for RecordingReader<'a, R> {
+    type Error = R::Error;
+
+    fn read(&self, key: &Key) -> Result<Option<Value>, Self::Error> {
+        self.read_set.borrow_mut().keys.insert(*key);
+        self.reader.read(key)
+    }
+
+    fn read_with_proof(
+        &self,
+        key: &Key,
+    ) -> Result<Option<TrieMerkleProof<Key, Value>>, Self::Error> {
+        self.read_set.borrow_mut().keys.insert(*key);
+        self.reader.read_with_proof(key)
+    }
+
+    fn read_stored(&self, key: &Key) -> Result<Option<StoredValue<Value>>, Self::Error> {
+        self.read_set.borrow_mut().keys.insert(*key);
+        self.reader.read_stored(key)
+    }
+
+    fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Option<Vec<(Key, Value)>>, Self::Error> {
+        self.read_set.borrow_mut().prefixes.insert(prefix.to_vec());
+        self.reader.keys_with_prefix(prefix)
+    }
+}
+
+/// Calls `f` with every index below `count` on up to `threads` threads and
+/// returns the results in the order of the indices.
+pub fn map_concurrently<T, F>(count: usize, threads: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    let threads = cmp::max(1, cmp::min(threads, count));
+    if threads == 1 {
+        return (0..count).map(f).collect();
+    }
+    let f = &f;
+    let mut results: Vec<Option<T>> = (0..count).map(|_| None).collect();
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|first| {
+                scope.spawn(move |_| {
+                    (first..count)
+                        .step_by(threads)
+                        .map(|index| (index, f(index)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            let thread_results = handle.join().expect("Execution thread panicked.");
+            for (index, result) in thread_results {
+                results[index] = Some(result);
+            }
+        }
+    })
+    .expect("Execution thread panicked.");
+    results
+        .into_iter()
+        .map(|result| result.expect("Every index should have a result."))
+        .collect()
+}
+
+/// State of a block while its deploys are run: the effects of the deploys run
+/// so far, and the last state they have been committed to at the checkpoint
+/// of the block.
+pub struct BlockState {
+    state_hash: Blake2bHash,
+    checkpoint: CheckpointId,
+    changed_keys: HashSet<Key>,
+    uncommitted: Vec<HashMap<Key, Transform>>,
+}
+
+impl BlockState {
+    pub fn new(prestate_hash: Blake2bHash, checkpoint: CheckpointId) -> BlockState {
+        BlockState {
+            state_hash: prestate_hash,
+            checkpoint,
+            changed_keys: HashSet::new(),
+            uncommitted: Vec::new(),
+        }
+    }
+
+    /// Whether a deploy of the block changed any of the keys in `read_set`,
+    /// or any key starting with one of its prefixes.
+    pub fn is_changed(&self, read_set: &ReadSet) -> bool {
+        if !self.changed_keys.is_disjoint(&read_set.keys) {
+            return true;
+        }
+        if read_set.prefixes.is_empty() {
+            return false;
+        }
+        self.changed_keys.iter().any(|key| match key.to_bytes() {
+            Ok(bytes) => read_set.prefixes.iter().any(|prefix| bytes.starts_with(prefix)),
+            // A key which can't be compared is taken for a conflict.
+            Err(_) => true,
+        })
+    }
+
+    /// Records the effects of the next deploy of the block. Failed deploys
+    /// only change the state if they are charged for.
+    pub fn record(&mut self, result: &ExecutionResult) {
+        let effect = match (&result.result, &result.fee_effect) {
+            (Ok(effect), _) | (Err(_), Some(effect)) => effect,
+            (Err(_), None) => return,
+        };
+        self.changed_keys.extend(effect.1.keys().cloned());
+        self.uncommitted.push(effect.1.clone());
+    }
+
+    /// Commits the effects recorded so far and returns the state they leave.
+    pub fn commit<H>(
+        &mut self,
+        engine_state: &EngineState<H>,
+    ) -> Result<Result<Blake2bHash, Error>, RootNotFound>
+    where
+        H: History,
+        H::Error: Into<execution::Error>,
+    {
+        for effects in self.uncommitted.drain(..) {
+            let result = engine_state.apply_effect_at(self.checkpoint, self.state_hash, effects);
+            let error = match result {
+                Ok(CommitResult::Success(state_hash)) => {
+                    self.state_hash = state_hash;
+                    continue;
+                }
+                Ok(CommitResult::RootNotFound) => return Err(RootNotFound(self.state_hash)),
+                Ok(CommitResult::KeyNotFound(key)) => execution::Error::KeyNotFound(key),
+                Ok(CommitResult::TypeMismatch(type_mismatch)) => {
+                    execution::Error::TypeMismatch(type_mismatch)
+                }
+                Ok(CommitResult::Overflow) => execution::Error::Overflow,
+                Err(error) => error.into(),
+            };
+            return Ok(Err(error.into()));
+        }
+        Ok(Ok(self.state_hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    use common::bytesrepr::ToBytes;
+    use common::key::Key;
+    use common::value::Value;
+    use engine::ExecutionResult;
+    use shared::gas::Gas;
+    use shared::newtypes::Blake2bHash;
+    use storage::global_state::inmem::{InMemGS, InMemHist};
+    use storage::global_state::ExecutionEffect;
+    use storage::history::History;
+    use storage::op::Op;
+    use storage::transform::Transform;
+
+    use super::{map_concurrently, BlockState, ReadSet, RecordingReader};
+    use trackingcopy::TrackingCopy;
+
+    #[test]
+    fn results_are_in_the_order_of_the_indices() {
+        let squares: Vec<usize> = (0..10).map(|index| index * index).collect();
+        for threads in 1..12 {
+            assert_eq!(map_concurrently(10, threads, |index| index * index), squares);
+        }
+        assert!(map_concurrently(0, 4, |index| index).is_empty());
+    }
+
+    #[test]
+    fn reads_of_missing_keys_are_recorded() {
+        let present = Key::Hash([1u8; 32]);
+        let missing = Key::Hash([2u8; 32]);
+        let written = Key::Hash([3u8; 32]);
+        let mut state = BTreeMap::new();
+        state.insert(present, Value::Int32(1));
+        let read_set = RefCell::new(ReadSet::default());
+        {
+            let reader = RecordingReader::new(InMemGS::new(state), &read_set);
+            let mut tc = TrackingCopy::new(reader);
+            tc.write(written, Value::Int32(2));
+            assert!(tc.read(present).unwrap().is_some());
+            assert!(tc.read(missing).unwrap().is_none());
+            assert!(tc.read(written).unwrap().is_some());
+        }
+        let expected: HashSet<Key> = vec![present, missing].into_iter().collect();
+        assert_eq!(read_set.into_inner().keys, expected);
+    }
+
+    #[test]
+    fn keys_added_under_a_listed_prefix_are_conflicts() {
+        let listed = Key::Hash([1u8; 32]);
+        let added = Key::Hash([2u8; 32]);
+        let elsewhere = Key::Account([2u8; 20]);
+        let prefix = listed.to_bytes().unwrap()[..1].to_vec();
+        let mut state = BTreeMap::new();
+        state.insert(listed, Value::Int32(1));
+
+        // Deploy 1 lists the prefix on the prestate, where only `listed` is.
+        let read_set = RefCell::new(ReadSet::default());
+        {
+            let reader = RecordingReader::new(InMemGS::new(state), &read_set);
+            let mut tc = TrackingCopy::new(reader);
+            let pairs = tc.keys_with_prefix(&prefix).unwrap().unwrap();
+            assert_eq!(pairs, vec![(listed, Value::Int32(1))]);
+        }
+        let read_set = read_set.into_inner();
+
+        let root_hash: Blake2bHash = [0u8; 32].into();
+        let mut hist = InMemHist::new_initialized(&root_hash, BTreeMap::new());
+        let mut block_state = BlockState::new(root_hash, hist.checkpoint());
+        let deploy_effect = |key: Key| {
+            let mut ops = HashMap::new();
+            ops.insert(key, Op::Write);
+            let mut transforms = HashMap::new();
+            transforms.insert(key, Transform::Write(Value::Int32(2)));
+            ExecutionResult::success(ExecutionEffect(ops, transforms, Vec::new()), Gas::default())
+        };
+        // A key changed under another prefix isn't a conflict.
+        block_state.record(&deploy_effect(elsewhere));
+        assert!(!block_state.is_changed(&read_set));
+        // Deploy 0 adds a key under the prefix deploy 1 listed.
+        block_state.record(&deploy_effect(added));
+        assert!(block_state.is_changed(&read_set));
+    }
+}
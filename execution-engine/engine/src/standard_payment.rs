@@ -0,0 +1,126 @@
+//! Native implementation of the standard payment contract.
+//!
+//! Deploys which only want to pay a fixed amount for their gas reference the
+//! standard payment instead of shipping payment code: their payment code is
+//! left empty and its first argument is the amount of motes to pay. The
+//! amount is moved from the main purse of the deploying account into the
+//! payment purse of the deploy. Failures are reported the way a contract
+//! doing the same would report them, by reverting with an `ApiError`.
+
+use std::collections::BTreeMap;
+
+use common::bytesrepr::deserialize;
+use common::contract_api::error::ApiError;
+use common::key::Key;
+use common::value::account::PurseId;
+use common::value::contract::PROTOCOL_VERSION;
+use common::value::{Contract, Value, U512};
+use execution::Error;
+use mint;
+use storage::global_state::StateReader;
+use storage::transform::TypeMismatch;
+use trackingcopy::TrackingCopy;
+
+/// Gas charged for running the standard payment.
+pub const STANDARD_PAYMENT_COST: u64 = 10_000;
+
+/// Stores the standard payment contract under `contract_key`. It has no code
+/// and no named keys; it's there so that the engine can tell it's installed.
+pub fn install<R: StateReader<Key, Value>>(state: &mut TrackingCopy<R>, contract_key: Key) {
+    let contract = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION);
+    state.write(contract_key, Value::Contract(contract));
+}
+
+/// Pays the amount found in `args` from `source` into `payment_purse`,
+/// provided the contract is installed under `contract_key`.
+pub fn pay<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    contract_key: Key,
+    source: PurseId,
+    payment_purse: PurseId,
+    args: &[u8],
+) -> Result<(), Error>
+where
+    R::Error: Into<Error>,
+{
+    match state.read(contract_key).map_err(Into::into)? {
+        Some(Value::Contract(_)) => (),
+        Some(other) => {
+            return Err(Error::TypeMismatch(TypeMismatch::new(
+                "Contract".to_string(),
+                other.type_string(),
+            )))
+        }
+        None => return Err(Error::KeyNotFound(contract_key)),
+    }
+    let amount = amount_from_args(args).map_err(|error| Error::Revert(error.into()))?;
+    match mint::transfer(state, source, payment_purse, amount) {
+        Err(Error::InsufficientFunds) => Err(Error::Revert(ApiError::Transfer.into())),
+        result => result,
+    }
+}
+
+fn amount_from_args(args: &[u8]) -> Result<U512, ApiError> {
+    let args: Vec<Vec<u8>> = if args.is_empty() {
+        Vec::new()
+    } else {
+        deserialize(args).map_err(|_| ApiError::InvalidArgument)?
+    };
+    match args.first() {
+        Some(amount) => deserialize(amount).map_err(|_| ApiError::InvalidArgument),
+        None => Err(ApiError::MissingArgument),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use common::bytesrepr::ToBytes;
+    use common::contract_api::error::ApiError;
+    use common::key::Key;
+    use common::value::{Value, U512};
+    use storage::global_state::inmem::InMemGS;
+
+    use super::{install, pay};
+    use execution::Error;
+    use mint;
+    use trackingcopy::TrackingCopy;
+
+    const CONTRACT_KEY: Key = Key::Hash([5u8; 32]);
+
+    fn args(amount: u64) -> Vec<u8> {
+        let amount = U512::from(amount).to_bytes().unwrap();
+        vec![amount].to_bytes().unwrap()
+    }
+
+    fn pay_from_purse_with(balance: u64, args: &[u8]) -> (Result<(), Error>, U512, U512) {
+        let mut tc = TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        install(&mut tc, CONTRACT_KEY);
+        let source = mint::create_purse(&mut tc, [1u8; 32]);
+        let payment_purse = mint::create_purse(&mut tc, [2u8; 32]);
+        tc.write(mint::balance_key(source), Value::UInt512(U512::from(balance)));
+        let result = pay(&mut tc, CONTRACT_KEY, source, payment_purse, args);
+        let source_balance = mint::balance(&mut tc, source).unwrap();
+        let payment_balance = mint::balance(&mut tc, payment_purse).unwrap();
+        (result, source_balance, payment_balance)
+    }
+
+    #[test]
+    fn pays_the_amount_into_the_payment_purse() {
+        let (result, source_balance, payment_balance) = pay_from_purse_with(100, &args(30));
+        assert!(result.is_ok());
+        assert_eq!(source_balance, U512::from(70));
+        assert_eq!(payment_balance, U512::from(30));
+    }
+
+    #[test]
+    fn failures_revert_like_a_contract() {
+        let missing_argument = u32::from(ApiError::MissingArgument);
+        let (result, _, _) = pay_from_purse_with(100, &[]);
+        assert_matches!(result, Err(Error::Revert(code)) if code == missing_argument);
+        let (result, source_balance, _) = pay_from_purse_with(10, &args(30));
+        assert_matches!(result, Err(Error::Revert(code)) if code == u32::from(ApiError::Transfer));
+        assert_eq!(source_balance, U512::from(10));
+    }
+}
@@ -0,0 +1,484 @@
+//! The step the engine runs at the end of every era.
+//!
+//! Rewards aren't paid out deploy by deploy. Fees collect in the rewards purse
+//! during an era, and once it ends the node asks the engine for a step, which
+//! bonds them to the validators which took part in the era and to their
+//! delegators. Before that, the validators which equivocated are slashed:
+//! their stakes go to the rewards purse, or are burned if there's none. The
+//! seigniorage of the era is minted into the rewards purse as well, at the
+//! rate set for every round. The step also settles the requests waiting in
+//! the queues of the PoS contract, so that unbonded tokens are paid out even
+//! if nobody calls the contract. Then the auction picks the validators of an
+//! upcoming era, once the weights of the era which ended are settled. Last,
+//! the balances of the purses are checked against the total supply, so that a
+//! step which created or lost tokens isn't committed.
+
+use std::collections::BTreeSet;
+
+use auction;
+use common::bytesrepr;
+use common::key::Key;
+use common::system_contracts::SystemContractId;
+use common::value::account::{PublicKey, PurseId};
+use common::value::{Value, U512};
+use engine::EngineConfig;
+use execution::Error;
+use mint;
+use pos;
+use shared::newtypes::Blake2bHash;
+use shared::ratio::Ratio;
+use storage::global_state::{ExecutionEffect, StateReader};
+use system::SystemContext;
+use trackingcopy::TrackingCopy;
+
+/// What the node knows about an era once it ended.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EraEndData {
+    /// Id of the era which ended.
+    pub era_id: u64,
+    /// Height of the last block of the era.
+    pub height: u64,
+    /// Number of rounds the era lasted, for which seigniorage is minted.
+    pub rounds: u64,
+    /// Validators which didn't take part in the era and get no rewards.
+    pub inactive_validators: BTreeSet<PublicKey>,
+    /// Validators which equivocated during the era and are slashed.
+    pub equivocators: BTreeSet<PublicKey>,
+}
+
+/// Tokens moved by the step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepTotals {
+    /// Stakes and pending bonds taken from the equivocators.
+    pub slashed: U512,
+    /// Tokens minted as the seigniorage of the era.
+    pub seigniorage: U512,
+    /// Rewards bonded to the validators and their delegators.
+    pub rewards: U512,
+}
+
+/// Mints the seigniorage of `rounds` rounds at `rate` into `rewards_purse`.
+/// The rate applies to the total supply, compounded from round to round.
+/// Returns the amount minted.
+pub fn mint_rewards<R: StateReader<Key, Value>>(
+    context: &SystemContext,
+    state: &mut TrackingCopy<R>,
+    rewards_purse: PurseId,
+    rate: Ratio,
+    rounds: u64,
+) -> Result<U512, Error>
+where
+    R::Error: Into<Error>,
+{
+    if rate.is_zero() || rounds == 0 {
+        return Ok(U512::zero());
+    }
+    let total_supply = mint::total_supply(state)?;
+    let mut supply = total_supply;
+    for _ in 0..rounds {
+        let minted = rate.of(supply).ok_or(Error::Overflow)?;
+        supply = supply.checked_add(minted).ok_or(Error::Overflow)?;
+    }
+    let seigniorage = supply - total_supply;
+    context.mint(state, rewards_purse, seigniorage)?;
+    Ok(seigniorage)
+}
+
+/// Checks that the balances of the purses the mint created add up to the
+/// total supply. States without a total supply, or which can't list keys,
+/// aren't checked.
+pub fn audit_supply<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+) -> Result<(), StepError>
+where
+    R::Error: Into<Error> + From<bytesrepr::Error>,
+{
+    let total_supply = match mint::total_supply(state) {
+        Ok(total_supply) => total_supply,
+        Err(Error::KeyNotFound(_)) => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    let balances = match mint::balances(state)? {
+        Some(balances) => balances,
+        None => return Ok(()),
+    };
+    let mut total = U512::zero();
+    for balance in balances.values() {
+        total = total.checked_add(*balance).ok_or(Error::Overflow)?;
+    }
+    if total != total_supply {
+        return Err(StepError::SupplyMismatch {
+            total_supply,
+            balances: total,
+        });
+    }
+    Ok(())
+}
+
+impl EraEndData {
+    /// Slashes the equivocators, mints the seigniorage of the era into the
+    /// rewards purse of `config` and distributes its balance, if there is
+    /// one, and settles the queues of the PoS contract at `pos_contract`.
+    /// Then runs the auction, if `config` has an auction contract, and audits
+    /// the total supply.
+    pub fn apply<R: StateReader<Key, Value>>(
+        &self,
+        context: &SystemContext,
+        state: &mut TrackingCopy<R>,
+        pos_contract: Key,
+        config: &EngineConfig,
+    ) -> Result<StepTotals, StepError>
+    where
+        R::Error: Into<Error> + From<bytesrepr::Error>,
+    {
+        let rewards_purse = config.rewards_purse();
+        let slashed = pos::slash(
+            context,
+            state,
+            pos_contract,
+            &self.equivocators,
+            rewards_purse,
+        )?;
+        let (seigniorage, rewards) = match rewards_purse {
+            Some(purse) => {
+                let rate = config.round_seigniorage_rate();
+                let seigniorage = mint_rewards(context, state, purse, rate, self.rounds)?;
+                let rewards = pos::distribute_rewards(
+                    context,
+                    state,
+                    pos_contract,
+                    purse,
+                    &self.inactive_validators,
+                )?;
+                (seigniorage, rewards)
+            }
+            None => (U512::zero(), U512::zero()),
+        };
+        pos::process_queues(state, pos_contract, self.height, config.pos_delays())?;
+        if let Some(auction_contract) = config.system_contracts().get(&SystemContractId::Auction) {
+            auction::run_auction(
+                state,
+                *auction_contract,
+                pos_contract,
+                self.era_id,
+                self.height,
+                config.auction_settings(),
+                config.pos_delays(),
+            )?;
+        }
+        audit_supply(state)?;
+        Ok(StepTotals {
+            slashed,
+            seigniorage,
+            rewards,
+        })
+    }
+}
+
+/// Reasons why the step can't be run.
+#[derive(Debug)]
+pub enum StepError {
+    RootNotFound(Blake2bHash),
+    /// The effects of the step couldn't be committed.
+    CommitFailed,
+    /// The balances of the purses don't add up to the total supply.
+    SupplyMismatch { total_supply: U512, balances: U512 },
+    Exec(Error),
+}
+
+impl From<Error> for StepError {
+    fn from(error: Error) -> StepError {
+        StepError::Exec(error)
+    }
+}
+
+pub struct StepResult {
+    pub post_state_hash: Blake2bHash,
+    pub totals: StepTotals,
+    pub effect: ExecutionEffect,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use auction;
+    use common::key::{AccessRights, Key};
+    use common::system_contracts::SystemContractId;
+    use common::value::account::{PublicKey, PurseId};
+    use common::value::{Value, U512};
+    use shared::ratio::Ratio;
+    use storage::global_state::inmem::InMemGS;
+
+    use super::{EraEndData, StepError, StepTotals};
+    use engine::EngineConfig;
+    use mint;
+    use pos::{self, QueueDelays};
+    use system::{SystemContext, SystemFlow};
+    use trackingcopy::TrackingCopy;
+
+    const MINT_KEY: Key = Key::Hash([6u8; 32]);
+    const POS_KEY: Key = Key::Hash([7u8; 32]);
+    const AUCTION_KEY: Key = Key::Hash([8u8; 32]);
+    const BONDING_PURSE_ADDR: [u8; 32] = [2u8; 32];
+
+    type TestTrackingCopy = TrackingCopy<InMemGS<Key, Value>>;
+
+    fn purse(tc: &mut TestTrackingCopy, addr: [u8; 32], balance: u64) -> PurseId {
+        let purse = mint::create_purse(tc, addr);
+        mint::mint(tc, purse, U512::from(balance)).expect("should mint");
+        purse
+    }
+
+    /// PoS contract with validators 1 and 2 bonded with the stakes 100 and
+    /// 300, and a rewards purse holding `rewards`.
+    fn setup(rewards: u64) -> (TestTrackingCopy, PurseId) {
+        let mut tc = TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        mint::install(&mut tc, MINT_KEY);
+        pos::install(&mut tc, POS_KEY, BONDING_PURSE_ADDR).expect("should install");
+        for &(validator, stake) in &[(1u8, 100u64), (2, 300)] {
+            let source = purse(&mut tc, [10 + validator; 32], stake);
+            let validator = PublicKey::new([validator; 32]);
+            let amount = U512::from(stake);
+            let delays = QueueDelays::default();
+            pos::bond(&mut tc, POS_KEY, validator, source, amount, 0, delays)
+                .expect("should bond");
+        }
+        let rewards_purse = purse(&mut tc, [3u8; 32], rewards);
+        (tc, rewards_purse)
+    }
+
+    fn step_at_rate(
+        tc: &mut TestTrackingCopy,
+        rewards_purse: PurseId,
+        data: &EraEndData,
+        rate: Ratio,
+    ) -> StepTotals {
+        let context = SystemContext::new(SystemFlow::EraEnd);
+        let config = EngineConfig::default()
+            .with_rewards_purse(rewards_purse)
+            .with_unbonding_delay(10)
+            .with_round_seigniorage_rate(rate);
+        data.apply(&context, tc, POS_KEY, &config)
+            .expect("should step")
+    }
+
+    fn step(tc: &mut TestTrackingCopy, rewards_purse: PurseId, data: &EraEndData) -> StepTotals {
+        step_at_rate(tc, rewards_purse, data, Ratio::default())
+    }
+
+    fn stakes(tc: &mut TestTrackingCopy) -> Vec<U512> {
+        let stakes = pos::stakes(tc, POS_KEY).expect("should read stakes");
+        stakes.values().cloned().collect()
+    }
+
+    fn weights(tc: &mut TestTrackingCopy) -> Vec<U512> {
+        let weights = pos::weights(tc, POS_KEY).expect("should read weights");
+        weights.values().cloned().collect()
+    }
+
+    fn delegate(tc: &mut TestTrackingCopy, validator: u8, amount: u64) -> PurseId {
+        let source = purse(tc, [20 + validator; 32], amount);
+        pos::delegate(
+            tc,
+            POS_KEY,
+            PublicKey::new([5u8; 32]),
+            PublicKey::new([validator; 32]),
+            source,
+            U512::from(amount),
+            0,
+            QueueDelays::default(),
+        )
+        .expect("should delegate");
+        source
+    }
+
+    #[test]
+    fn rewards_are_bonded_in_proportion_to_stakes() {
+        let (mut tc, rewards_purse) = setup(41);
+        let totals = step(&mut tc, rewards_purse, &EraEndData::default());
+        assert_eq!(totals.rewards, U512::from(40));
+        assert_eq!(stakes(&mut tc), vec![U512::from(110), U512::from(330)]);
+        // What's left over by rounding is distributed in a later era.
+        assert_eq!(mint::balance(&mut tc, rewards_purse).unwrap(), U512::from(1));
+    }
+
+    #[test]
+    fn inactive_validators_get_no_rewards() {
+        let (mut tc, rewards_purse) = setup(40);
+        let mut inactive_validators = BTreeSet::new();
+        inactive_validators.insert(PublicKey::new([2u8; 32]));
+        let data = EraEndData {
+            height: 1,
+            inactive_validators,
+            ..Default::default()
+        };
+        assert_eq!(step(&mut tc, rewards_purse, &data).rewards, U512::from(40));
+        assert_eq!(stakes(&mut tc), vec![U512::from(140), U512::from(300)]);
+    }
+
+    #[test]
+    fn unbonding_requests_are_settled_at_the_end_of_the_era() {
+        let (mut tc, rewards_purse) = setup(0);
+        let target = purse(&mut tc, [4u8; 32], 0);
+        let validator = PublicKey::new([1u8; 32]);
+        let delays = QueueDelays {
+            bonding: 0,
+            unbonding: 10,
+        };
+        pos::unbond(&mut tc, POS_KEY, validator, target, U512::from(60), 5, delays)
+            .expect("should unbond");
+        let data = |height| EraEndData {
+            height,
+            ..Default::default()
+        };
+        step(&mut tc, rewards_purse, &data(14));
+        assert_eq!(mint::balance(&mut tc, target).unwrap(), U512::zero());
+        step(&mut tc, rewards_purse, &data(15));
+        assert_eq!(mint::balance(&mut tc, target).unwrap(), U512::from(60));
+        assert_eq!(stakes(&mut tc), vec![U512::from(40), U512::from(300)]);
+    }
+
+    #[test]
+    fn slashed_stakes_are_rewarded_to_the_other_validators() {
+        let (mut tc, rewards_purse) = setup(0);
+        let mut equivocators = BTreeSet::new();
+        equivocators.insert(PublicKey::new([1u8; 32]));
+        let data = EraEndData {
+            height: 1,
+            equivocators,
+            ..Default::default()
+        };
+        let totals = step(&mut tc, rewards_purse, &data);
+        assert_eq!(
+            totals,
+            StepTotals {
+                slashed: U512::from(100),
+                seigniorage: U512::zero(),
+                rewards: U512::from(100),
+            }
+        );
+        assert_eq!(stakes(&mut tc), vec![U512::from(400)]);
+    }
+
+    #[test]
+    fn seigniorage_is_compounded_over_the_rounds() {
+        let (mut tc, rewards_purse) = setup(0);
+        let data = EraEndData {
+            height: 1,
+            rounds: 2,
+            ..Default::default()
+        };
+        let rate = Ratio::new(1, 10).expect("rate should be valid");
+        let totals = step_at_rate(&mut tc, rewards_purse, &data, rate);
+        // 400 grows to 440 in the first round and to 484 in the second.
+        assert_eq!(totals.seigniorage, U512::from(84));
+        assert_eq!(totals.rewards, U512::from(84));
+        assert_eq!(mint::total_supply(&mut tc).unwrap(), U512::from(484));
+        assert_eq!(stakes(&mut tc), vec![U512::from(121), U512::from(363)]);
+    }
+
+    #[test]
+    fn rewards_are_shared_with_delegators() {
+        let (mut tc, rewards_purse) = setup(50);
+        delegate(&mut tc, 1, 100);
+        assert_eq!(weights(&mut tc), vec![U512::from(200), U512::from(300)]);
+        let totals = step(&mut tc, rewards_purse, &EraEndData::default());
+        assert_eq!(totals.rewards, U512::from(50));
+        // Validator 1 gets 20, half of which goes to its delegator.
+        assert_eq!(stakes(&mut tc), vec![U512::from(110), U512::from(330)]);
+        assert_eq!(weights(&mut tc), vec![U512::from(220), U512::from(330)]);
+    }
+
+    #[test]
+    fn steps_conserve_the_total_supply() {
+        let (mut tc, rewards_purse) = setup(7);
+        let delegator_purse = delegate(&mut tc, 2, 50);
+        let bonding_purse = PurseId::new(BONDING_PURSE_ADDR, AccessRights::READ_ADD_WRITE);
+        let purses = [
+            bonding_purse,
+            rewards_purse,
+            delegator_purse,
+            PurseId::new([11u8; 32], AccessRights::READ_ADD_WRITE),
+            PurseId::new([12u8; 32], AccessRights::READ_ADD_WRITE),
+        ];
+        let rate = Ratio::new(1, 100).expect("rate should be valid");
+        let mut supply = mint::total_supply(&mut tc).unwrap();
+        assert_eq!(supply, U512::from(457));
+        for height in 1..4 {
+            let mut equivocators = BTreeSet::new();
+            if height == 2 {
+                equivocators.insert(PublicKey::new([1u8; 32]));
+            }
+            let data = EraEndData {
+                height,
+                rounds: 5,
+                equivocators,
+                ..Default::default()
+            };
+            let totals = step_at_rate(&mut tc, rewards_purse, &data, rate);
+            supply += totals.seigniorage;
+            assert_eq!(mint::total_supply(&mut tc).unwrap(), supply);
+            let balances = purses.iter().fold(U512::zero(), |total, purse| {
+                total + mint::balance(&mut tc, *purse).unwrap()
+            });
+            assert_eq!(balances, supply);
+            // No other purse holds tokens.
+            let listed = mint::balances(&mut tc).unwrap().expect("the state lists keys");
+            let listed = listed
+                .values()
+                .fold(U512::zero(), |total, balance| total + *balance);
+            assert_eq!(listed, supply);
+            // Everything in the bonding purse is bonded or delegated.
+            let weights = weights(&mut tc)
+                .into_iter()
+                .fold(U512::zero(), |total, weight| total + weight);
+            assert_eq!(mint::balance(&mut tc, bonding_purse).unwrap(), weights);
+        }
+    }
+
+    #[test]
+    fn steps_are_rejected_if_the_balances_miss_the_total_supply() {
+        let (mut tc, rewards_purse) = setup(0);
+        // Tokens the mint didn't create.
+        let purse = mint::create_purse(&mut tc, [30u8; 32]);
+        tc.write(mint::balance_key(purse), Value::UInt512(U512::from(5)));
+        let context = SystemContext::new(SystemFlow::EraEnd);
+        let config = EngineConfig::default().with_rewards_purse(rewards_purse);
+        assert_matches!(
+            EraEndData::default().apply(&context, &mut tc, POS_KEY, &config),
+            Err(StepError::SupplyMismatch { total_supply, balances })
+                if total_supply == U512::from(400) && balances == U512::from(405)
+        );
+    }
+
+    #[test]
+    fn auctions_pick_the_validators_of_upcoming_eras() {
+        let (mut tc, rewards_purse) = setup(0);
+        auction::install(&mut tc, AUCTION_KEY, [4u8; 32]).expect("should install auction");
+        let source = purse(&mut tc, [30u8; 32], 200);
+        let bidder = PublicKey::new([3u8; 32]);
+        auction::add_bid(&mut tc, AUCTION_KEY, bidder, source, U512::from(200))
+            .expect("should bid");
+        let context = SystemContext::new(SystemFlow::EraEnd);
+        let config = EngineConfig::default()
+            .with_rewards_purse(rewards_purse)
+            .with_system_contract(SystemContractId::Auction, AUCTION_KEY)
+            .with_validator_slots(2);
+        let data = EraEndData {
+            era_id: 4,
+            height: 1,
+            ..Default::default()
+        };
+        data.apply(&context, &mut tc, POS_KEY, &config)
+            .expect("should step");
+        let era_validators = auction::era_validators(&mut tc, AUCTION_KEY).unwrap();
+        let validators: Vec<U512> = era_validators[&6].values().cloned().collect();
+        assert_eq!(validators, vec![U512::from(300), U512::from(200)]);
+        // The winning bid is bonded, and validator 1 lost its seat.
+        assert_eq!(
+            stakes(&mut tc),
+            vec![U512::from(100), U512::from(300), U512::from(200)]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! The system account and the context the engine's own flows run in.
+//!
+//! Genesis, upgrades, the settlement of payments and the end of eras change
+//! the state in ways no deploy may: they install contracts, rewrite the
+//! protocol data and move tokens out of purses they were never handed. These flows are native and
+//! run in the system context, as the system account in the `System` or
+//! `FinalizePayment` phase. Deploys never do: the engine rejects deploys sent
+//! from the system account, and executors refuse to run code in the system
+//! context.
+
+use common::key::Key;
+use common::phase::Phase;
+use common::value::account::PurseId;
+use common::value::{Value, U512};
+use execution::Error;
+use mint;
+use storage::global_state::StateReader;
+use trackingcopy::TrackingCopy;
+
+pub use common::value::account::SYSTEM_ACCOUNT;
+
+/// Flow of the engine running in the system context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemFlow {
+    Genesis,
+    Upgrade,
+    FinalizePayment,
+    EraEnd,
+}
+
+/// Context of a flow run by the engine on its own behalf. Only the engine
+/// creates these, so functions taking one can't be reached from a deploy.
+#[derive(Debug)]
+pub struct SystemContext {
+    flow: SystemFlow,
+}
+
+impl SystemContext {
+    pub(crate) fn new(flow: SystemFlow) -> SystemContext {
+        SystemContext { flow }
+    }
+
+    pub fn flow(&self) -> SystemFlow {
+        self.flow
+    }
+
+    pub fn phase(&self) -> Phase {
+        match self.flow {
+            SystemFlow::Genesis | SystemFlow::Upgrade | SystemFlow::EraEnd => Phase::System,
+            SystemFlow::FinalizePayment => Phase::FinalizePayment,
+        }
+    }
+
+    /// Key of the account the flow acts as.
+    pub fn account(&self) -> Key {
+        Key::Account(SYSTEM_ACCOUNT)
+    }
+
+    /// Moves `amount` of tokens from `source` to `target` without the rights
+    /// to either purse, which deploys would need.
+    pub fn transfer<R: StateReader<Key, Value>>(
+        &self,
+        state: &mut TrackingCopy<R>,
+        source: PurseId,
+        target: PurseId,
+        amount: U512,
+    ) -> Result<(), Error>
+    where
+        R::Error: Into<Error>,
+    {
+        mint::transfer(state, source, target, amount)
+    }
+
+    /// Creates `amount` of new tokens in `purse`.
+    pub fn mint<R: StateReader<Key, Value>>(
+        &self,
+        state: &mut TrackingCopy<R>,
+        purse: PurseId,
+        amount: U512,
+    ) -> Result<(), Error>
+    where
+        R::Error: Into<Error>,
+    {
+        mint::mint(state, purse, amount)
+    }
+
+    /// Takes `amount` of tokens out of `purse` and out of circulation.
+    pub fn burn<R: StateReader<Key, Value>>(
+        &self,
+        state: &mut TrackingCopy<R>,
+        purse: PurseId,
+        amount: U512,
+    ) -> Result<(), Error>
+    where
+        R::Error: Into<Error>,
+    {
+        mint::burn(state, purse, amount)
+    }
+}
+
+/// Whether code run under `address` in `phase` would run in the system
+/// context.
+pub fn is_system_context(address: [u8; 20], phase: Phase) -> bool {
+    match phase {
+        Phase::System | Phase::FinalizePayment => true,
+        Phase::Payment | Phase::Session => address == SYSTEM_ACCOUNT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::phase::Phase;
+
+    use super::{is_system_context, SystemContext, SystemFlow, SYSTEM_ACCOUNT};
+
+    #[test]
+    fn system_flows_run_in_the_system_context() {
+        let flows = [
+            SystemFlow::Genesis,
+            SystemFlow::Upgrade,
+            SystemFlow::FinalizePayment,
+            SystemFlow::EraEnd,
+        ];
+        for flow in &flows {
+            let context = SystemContext::new(*flow);
+            assert!(is_system_context(SYSTEM_ACCOUNT, context.phase()));
+        }
+        assert_eq!(SystemContext::new(SystemFlow::Upgrade).phase(), Phase::System);
+    }
+
+    #[test]
+    fn deploys_run_outside_the_system_context() {
+        let address = [1u8; 20];
+        assert!(!is_system_context(address, Phase::Payment));
+        assert!(!is_system_context(address, Phase::Session));
+        // Neither the phase nor the account of a deploy can be the system's.
+        assert!(is_system_context(address, Phase::System));
+        assert!(is_system_context(address, Phase::FinalizePayment));
+        assert!(is_system_context(SYSTEM_ACCOUNT, Phase::Session));
+    }
+}
@@ -1,7 +1,12 @@
 use std::collections::{BTreeMap, HashMap};
+use std::mem;
 
+use common::bytesrepr::{self, ToBytes};
 use common::key::Key;
+use common::trie_merkle_proof::TrieMerkleProof;
 use common::value::Value;
+use journal::JournalEntry;
+use storage::global_state::stored_value::StoredValue;
 use storage::global_state::{ExecutionEffect, StateReader};
 use storage::op::Op;
 use storage::transform::{self, Transform, TypeMismatch};
@@ -15,11 +20,34 @@ pub enum QueryResult {
 
 pub struct TrackingCopy<R: StateReader<Key, Value>> {
     reader: R,
-    cache: HashMap<Key, Value>,
+    /// Current values of the keys accessed so far. Keys missing from the
+    /// state are cached as `None`, so the reader is only asked about them once.
+    cache: HashMap<Key, Option<Value>>,
+    /// Bytes of the values read without decoding them, as the reader stored
+    /// them. They're values of the prestate, so rollbacks keep them.
+    raw_cache: HashMap<Key, Vec<u8>>,
     ops: HashMap<Key, Op>,
     fns: HashMap<Key, Transform>,
+    /// Entries recorded since journaling was enabled, which rollbacks don't
+    /// discard.
+    journal: Option<Vec<JournalEntry>>,
+    /// Entries the accesses made since the first checkpoint replaced, which
+    /// rollbacks put back, latest first.
+    undo_log: Option<Vec<Undo>>,
 }
 
+/// Entries of a key as they were before it was accessed.
+enum Undo {
+    /// The key was read, which only changed its op.
+    Read(Key, Option<Op>),
+    /// The key was written or added to.
+    Write(Key, Option<Option<Value>>, Option<Op>, Option<Transform>),
+}
+
+/// State of a `TrackingCopy` which it can be rolled back to: the length of
+/// its undo log when the checkpoint was taken.
+pub struct Checkpoint(usize);
+
 #[derive(Debug)]
 pub enum AddResult {
     Success,
@@ -33,34 +61,132 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
         TrackingCopy {
             reader,
             cache: HashMap::new(),
+            raw_cache: HashMap::new(),
             ops: HashMap::new(),
             fns: HashMap::new(),
+            journal: None,
+            undo_log: None,
+        }
+    }
+
+    /// Starts recording a journal of the accesses to the state.
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Vec::new());
+        }
+    }
+
+    /// Records the entry made by `entry` if journaling is enabled.
+    pub fn record<F: FnOnce() -> JournalEntry>(&mut self, entry: F) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(entry());
+        }
+    }
+
+    /// Entries recorded so far, leaving the journal empty. There are none if
+    /// journaling isn't enabled.
+    pub fn take_journal(&mut self) -> Vec<JournalEntry> {
+        match self.journal.as_mut() {
+            Some(journal) => mem::replace(journal, Vec::new()),
+            None => Vec::new(),
         }
     }
 
     pub fn get(&mut self, k: &Key) -> Result<Option<Value>, R::Error> {
         if let Some(value) = self.cache.get(k) {
-            return Ok(Some(value.clone()));
-        }
-        if let Some(value) = self.reader.read(k)? {
-            self.cache.insert(*k, value.clone());
-            Ok(Some(value))
-        } else {
-            Ok(None)
+            return Ok(value.clone());
         }
+        let value = self.reader.read(k)?;
+        self.record(|| JournalEntry::Load(*k, value.clone()));
+        self.cache.insert(*k, value.clone());
+        Ok(value)
+    }
+
+    /// Value of `k` in the state the tracking copy was checked out from,
+    /// whatever was written to it since.
+    pub fn prestate_value(&mut self, k: &Key) -> Result<Option<Value>, R::Error> {
+        let value = self.reader.read(k)?;
+        self.record(|| JournalEntry::Load(*k, value.clone()));
+        Ok(value)
     }
 
+    /// Reads of missing keys are tracked as well, since what the deploy does
+    /// next depends on the key staying missing.
     pub fn read(&mut self, k: Key) -> Result<Option<Value>, R::Error> {
-        if let Some(value) = self.get(&k)? {
-            add(&mut self.ops, k, Op::Read);
-            Ok(Some(value))
-        } else {
-            Ok(None)
+        self.record(|| JournalEntry::Read(k));
+        let value = self.get(&k)?;
+        self.save_op(k);
+        add(&mut self.ops, k, Op::Read);
+        Ok(value)
+    }
+
+    /// Reads `k` like `read`, without decoding its value if it's only in the
+    /// prestate. Values written or added to are in the cache, decoded; so are
+    /// the values loaded while journaling, since loads are journaled with
+    /// their values.
+    pub fn read_stored(&mut self, k: Key) -> Result<Option<StoredValue<Value>>, R::Error> {
+        if self.journal.is_some() || self.cache.contains_key(&k) {
+            return Ok(self.read(k)?.map(StoredValue::Decoded));
+        }
+        self.save_op(k);
+        add(&mut self.ops, k, Op::Read);
+        if let Some(bytes) = self.raw_cache.get(&k) {
+            return Ok(Some(StoredValue::Raw(bytes.clone())));
+        }
+        let stored = self.reader.read_stored(&k)?;
+        match stored {
+            Some(StoredValue::Raw(ref bytes)) => {
+                self.raw_cache.insert(k, bytes.clone());
+            }
+            Some(StoredValue::Decoded(ref value)) => {
+                self.cache.insert(k, Some(value.clone()));
+            }
+            None => {
+                self.cache.insert(k, None);
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Keys whose bytes start with `prefix`, with their current values, in
+    /// the order of their bytes. Keys written since the tracking copy was
+    /// made are listed too. Returns `None` if the reader can't list keys.
+    pub fn keys_with_prefix(&mut self, prefix: &[u8]) -> Result<Option<Vec<(Key, Value)>>, R::Error>
+    where
+        R::Error: From<bytesrepr::Error>,
+    {
+        let listed = match self.reader.keys_with_prefix(prefix)? {
+            Some(listed) => listed,
+            None => return Ok(None),
+        };
+        for (key, value) in listed {
+            if !self.cache.contains_key(&key) {
+                self.record(|| JournalEntry::Load(key, Some(value.clone())));
+                self.cache.insert(key, Some(value));
+            }
+        }
+        // Every key listed is cached now, along with the keys written.
+        let mut pairs = BTreeMap::new();
+        for (key, value) in &self.cache {
+            if let Some(value) = value {
+                let bytes = key.to_bytes()?;
+                if bytes.starts_with(prefix) {
+                    pairs.insert(bytes, (*key, value.clone()));
+                }
+            }
+        }
+        for (key, _) in pairs.values() {
+            self.record(|| JournalEntry::Read(*key));
+            self.save_op(*key);
+            add(&mut self.ops, *key, Op::Read);
         }
+        Ok(Some(pairs.into_iter().map(|(_, pair)| pair).collect()))
     }
 
     pub fn write(&mut self, k: Key, v: Value) {
-        let _ = self.cache.insert(k, v.clone());
+        self.record(|| JournalEntry::Write(k, v.clone()));
+        self.save(k);
+        let _ = self.cache.insert(k, Some(v.clone()));
         add(&mut self.ops, k, Op::Write);
         add(&mut self.fns, k, Transform::Write(v));
     }
@@ -72,6 +198,9 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
         match self.get(&k)? {
             None => Ok(AddResult::KeyNotFound(k)),
             Some(curr) => {
+                // The value is moved into the transform, so its entry is made
+                // beforehand.
+                let entry = self.journal.as_ref().map(|_| JournalEntry::Add(k, v.clone()));
                 let t = match v {
                     Value::Int32(i) => Transform::AddInt32(i),
                     Value::UInt128(i) => Transform::AddUInt128(i),
@@ -91,7 +220,11 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
                 };
                 match t.clone().apply(curr) {
                     Ok(new_value) => {
-                        let _ = self.cache.insert(k, new_value);
+                        if let (Some(journal), Some(entry)) = (self.journal.as_mut(), entry) {
+                            journal.push(entry);
+                        }
+                        self.save(k);
+                        let _ = self.cache.insert(k, Some(new_value));
                         add(&mut self.ops, k, Op::Add);
                         add(&mut self.fns, k, t);
                         Ok(AddResult::Success)
@@ -105,8 +238,58 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
         }
     }
 
+    /// Marks the changes tracked so far, so that the ones made afterwards
+    /// can be discarded with `rollback`. From the first checkpoint on, the
+    /// entries replaced by each access are kept in the undo log.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        let undo_log = self.undo_log.get_or_insert_with(Vec::new);
+        Checkpoint(undo_log.len())
+    }
+
+    /// Discards all the changes made since `checkpoint` was taken, putting
+    /// back the entries they replaced. Values loaded from the prestate in the
+    /// meantime stay cached.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        let undo_log = match self.undo_log.as_mut() {
+            Some(undo_log) => undo_log,
+            None => return,
+        };
+        while undo_log.len() > checkpoint.0 {
+            match undo_log.pop() {
+                Some(Undo::Read(k, op)) => restore(&mut self.ops, k, op),
+                Some(Undo::Write(k, value, op, transform)) => {
+                    restore(&mut self.cache, k, value);
+                    restore(&mut self.ops, k, op);
+                    restore(&mut self.fns, k, transform);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Saves the op of `k` before it's read, if a checkpoint was taken.
+    fn save_op(&mut self, k: Key) {
+        if let Some(undo_log) = self.undo_log.as_mut() {
+            undo_log.push(Undo::Read(k, self.ops.get(&k).cloned()));
+        }
+    }
+
+    /// Saves the entries of `k` before it's written or added to, if a
+    /// checkpoint was taken.
+    fn save(&mut self, k: Key) {
+        if let Some(undo_log) = self.undo_log.as_mut() {
+            undo_log.push(Undo::Write(
+                k,
+                self.cache.get(&k).cloned(),
+                self.ops.get(&k).cloned(),
+                self.fns.get(&k).cloned(),
+            ));
+        }
+    }
+
     pub fn effect(&self) -> ExecutionEffect {
-        ExecutionEffect(self.ops.clone(), self.fns.clone())
+        // Events are collected by the runtime.
+        ExecutionEffect(self.ops.clone(), self.fns.clone(), Vec::new())
     }
 
     pub fn query(&mut self, base_key: Key, path: &[String]) -> Result<QueryResult, R::Error> {
@@ -129,7 +312,7 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
                         match curr_value {
                             Value::Account(account) => {
                                 if let Some(key) = account.urefs_lookup().get(name) {
-                                    self.read_key_or_stop(*key, i)
+                                    self.read_key_or_stop(*key, name, i)
                                 } else {
                                     Err(Ok((i, format!("Name {} not found in Account at path:", name))))
                                 }
@@ -137,7 +320,7 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
 
                             Value::Contract(contract) => {
                                 if let Some(key) = contract.urefs_lookup().get(name) {
-                                    self.read_key_or_stop(*key, i)
+                                    self.read_key_or_stop(*key, name, i)
                                 } else {
                                     Err(Ok((i, format!("Name {} not found in Contract at path:", name))))
                                 }
@@ -161,16 +344,46 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
         }
     }
 
+    /// Like `query`, with proofs of the values the query loaded from the
+    /// state, in the order they were loaded. Values the tracking copy had
+    /// already loaded aren't proved, nor are any if the reader can't prove
+    /// values.
+    pub fn query_with_proofs(
+        &mut self,
+        base_key: Key,
+        path: &[String],
+    ) -> Result<(QueryResult, Vec<TrieMerkleProof<Key, Value>>), R::Error> {
+        // The loads are recorded in a journal of their own, whether or not
+        // journaling is enabled.
+        let journal = mem::replace(&mut self.journal, Some(Vec::new()));
+        let result = self.query(base_key, path);
+        let entries = mem::replace(&mut self.journal, journal).unwrap_or_default();
+        let result = result?;
+        let mut proofs = Vec::new();
+        for entry in entries {
+            if let JournalEntry::Load(key, Some(_)) = entry {
+                if let Some(proof) = self.reader.read_with_proof(&key)? {
+                    proofs.push(proof);
+                }
+            }
+        }
+        Ok((result, proofs))
+    }
+
     fn read_key_or_stop(
         &mut self,
         key: Key,
+        name: &str,
         i: usize,
     ) -> Result<Value, Result<(usize, String), R::Error>> {
         match self.read(key) {
             // continue recursing
             Ok(Some(value)) => Ok(value),
             // key not found in the global state; stop recursing
-            Ok(None) => Err(Ok((i, format!("Name {:?} not found: ", key)))),
+            Ok(None) => Err(Ok((
+                i,
+                format!("Value of name {} at {:?} not found at path:", name, key),
+            ))),
             // global state access error; stop recursing
             Err(error) => Err(Err(error)),
         }
@@ -193,6 +406,19 @@ impl<R: StateReader<Key, Value>> TrackingCopy<R> {
     }
 }
 
+/// Puts `entry` back as the entry of `k` in `map`, `None` meaning there was
+/// none.
+fn restore<V>(map: &mut HashMap<Key, V>, k: Key, entry: Option<V>) {
+    match entry {
+        Some(v) => {
+            let _ = map.insert(k, v);
+        }
+        None => {
+            let _ = map.remove(&k);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::cell::Cell;
@@ -203,15 +429,24 @@ mod tests {
     use proptest::collection::vec;
     use proptest::prelude::*;
 
+    use common::bytesrepr::ToBytes;
     use common::gens::*;
     use common::key::{AccessRights, Key};
+    use common::trie_merkle_proof::validate_proof;
+    use common::value::account::PurseId;
+    use common::value::contract::PROTOCOL_VERSION;
     use common::value::{Account, Contract, Value};
+    use shared::newtypes::Blake2bHash;
+    use storage::global_state::in_memory::InMemoryGlobalState;
     use storage::global_state::inmem::InMemGS;
+    use storage::global_state::stored_value::StoredValue;
     use storage::global_state::StateReader;
+    use storage::history::History;
     use storage::op::Op;
     use storage::transform::Transform;
 
     use super::{AddResult, QueryResult, TrackingCopy};
+    use journal::JournalEntry;
 
     struct CountingDb {
         count: Rc<Cell<i32>>,
@@ -258,6 +493,37 @@ mod tests {
         assert_eq!(tc.fns.is_empty(), true);
     }
 
+    #[test]
+    fn journal_records_accesses_in_order() {
+        let db = CountingDb::new(Rc::new(Cell::new(0)));
+        let mut tc = TrackingCopy::new(db);
+        let k = Key::Hash([0u8; 32]);
+        // Nothing is recorded before journaling is enabled.
+        tc.read(k).unwrap();
+        tc.enable_journal();
+        let checkpoint = tc.checkpoint();
+        tc.add(k, Value::Int32(2)).unwrap();
+        tc.rollback(checkpoint);
+        tc.write(k, Value::Int32(3));
+        assert_eq!(
+            tc.take_journal(),
+            vec![
+                JournalEntry::Add(k, Value::Int32(2)),
+                JournalEntry::Write(k, Value::Int32(3)),
+            ]
+        );
+
+        let other = Key::Hash([1u8; 32]);
+        tc.read(other).unwrap();
+        assert_eq!(
+            tc.take_journal(),
+            vec![
+                JournalEntry::Read(other),
+                JournalEntry::Load(other, Some(Value::Int32(1))),
+            ]
+        );
+    }
+
     #[test]
     fn tracking_copy_caching() {
         let counter = Rc::new(Cell::new(0));
@@ -278,6 +544,34 @@ mod tests {
         assert_eq!(db_value, 1);
     }
 
+    /// Reader of an empty state, counting the reads.
+    struct EmptyDb(Rc<Cell<i32>>);
+
+    impl StateReader<Key, Value> for EmptyDb {
+        type Error = !;
+        fn read(&self, _key: &Key) -> Result<Option<Value>, Self::Error> {
+            self.0.set(self.0.get() + 1);
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn tracking_copy_caches_missing_keys() {
+        let counter = Rc::new(Cell::new(0));
+        let mut tc = TrackingCopy::new(EmptyDb(Rc::clone(&counter)));
+        let k = Key::Hash([0u8; 32]);
+
+        assert_eq!(tc.read(k).unwrap(), None);
+        assert_eq!(tc.read(k).unwrap(), None);
+        assert_matches!(tc.add(k, Value::Int32(1)), Ok(AddResult::KeyNotFound(_)));
+        assert_eq!(counter.get(), 1);
+
+        // Written keys aren't missing anymore.
+        tc.write(k, Value::Int32(2));
+        assert_eq!(tc.read(k).unwrap(), Some(Value::Int32(2)));
+        assert_eq!(counter.get(), 1);
+    }
+
     #[test]
     fn tracking_copy_read() {
         let counter = Rc::new(Cell::new(0));
@@ -296,6 +590,48 @@ mod tests {
         assert_eq!(tc.ops.get(&k), Some(&Op::Read));
     }
 
+    #[test]
+    fn tracking_copy_rollback() {
+        let counter = Rc::new(Cell::new(0));
+        let db = CountingDb::new(Rc::clone(&counter));
+        let mut tc = TrackingCopy::new(db);
+        let k = Key::Hash([0u8; 32]);
+        let other_k = Key::Hash([1u8; 32]);
+
+        tc.write(k, Value::Int32(1));
+        let checkpoint = tc.checkpoint();
+        tc.write(k, Value::Int32(2));
+        let nested = tc.checkpoint();
+        tc.add(other_k, Value::Int32(3)).unwrap();
+        tc.rollback(nested);
+        assert_eq!(tc.fns.get(&other_k), None);
+        assert_eq!(tc.ops.get(&other_k), None);
+        assert_eq!(tc.fns.get(&k), Some(&Transform::Write(Value::Int32(2))));
+        tc.write(other_k, Value::Int32(3));
+        tc.rollback(checkpoint);
+
+        assert_eq!(tc.read(k).unwrap(), Some(Value::Int32(1)));
+        assert_eq!(tc.fns.len(), 1);
+        assert_eq!(tc.fns.get(&k), Some(&Transform::Write(Value::Int32(1))));
+        assert_eq!(tc.ops.get(&other_k), None);
+        // The value of `other_k` loaded by the addition is still cached.
+        assert_eq!(tc.read(other_k).unwrap(), Some(Value::Int32(0)));
+        assert_eq!(counter.get(), 1);
+    }
+
+    #[test]
+    fn reads_of_missing_keys_are_tracked() {
+        let mut tc = TrackingCopy::new(EmptyDb(Rc::new(Cell::new(0))));
+        let k = Key::Hash([0u8; 32]);
+
+        assert_eq!(tc.read(k).unwrap(), None);
+        assert_eq!(tc.fns.is_empty(), true);
+        assert_eq!(tc.ops.get(&k), Some(&Op::Read));
+        // Writing the key afterwards depends on the read.
+        tc.write(k, Value::Int32(1));
+        assert_eq!(tc.ops.get(&k), Some(&Op::Write));
+    }
+
     #[test]
     fn tracking_copy_write() {
         let counter = Rc::new(Cell::new(0));
@@ -360,7 +696,8 @@ mod tests {
     #[test]
     fn tracking_copy_add_named_key() {
         // DB now holds an `Account` so that we can test adding a `NamedKey`
-        let account = common::value::Account::new([0u8; 32], 0u64, BTreeMap::new());
+        let purse_id = PurseId::new([0u8; 32], AccessRights::READ_ADD_WRITE);
+        let account = common::value::Account::create([0u8; 32], 0u64, BTreeMap::new(), purse_id);
         let db = CountingDb::new_init(Value::Account(account));
         let mut tc = TrackingCopy::new(db);
         let k = Key::Hash([0u8; 32]);
@@ -457,6 +794,94 @@ mod tests {
         assert_eq!(tc.ops.get(&k), Some(&Op::Write));
     }
 
+    #[test]
+    fn query_with_proofs_proves_the_values_on_the_path() {
+        let state_key = Key::Hash([1u8; 32]);
+        let contract_key = Key::Hash([2u8; 32]);
+        let known_urefs = iter::once(("state".to_owned(), state_key)).collect();
+        let contract = Contract::new(vec![0u8], known_urefs, PROTOCOL_VERSION);
+        let pairs = [
+            (state_key, Value::Int32(1)),
+            (contract_key, contract.into()),
+        ];
+        let (global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let reader = global_state.checkout(root_hash).unwrap().unwrap();
+        let mut tc = TrackingCopy::new(reader);
+
+        let path = vec!["state".to_owned()];
+        let (result, proofs) = tc.query_with_proofs(contract_key, &path).unwrap();
+        assert_matches!(result, QueryResult::Success(Value::Int32(1)));
+        let proved_keys: Vec<Key> = proofs.iter().map(|proof| *proof.key()).collect();
+        assert_eq!(proved_keys, vec![contract_key, state_key]);
+        let hash = |bytes: &[u8]| Blake2bHash::new(bytes).value();
+        for proof in &proofs {
+            assert_eq!(
+                validate_proof(&root_hash.value(), proof.key(), proof, hash),
+                Ok(())
+            );
+        }
+        // The values are loaded already.
+        let (_, proofs) = tc.query_with_proofs(contract_key, &path).unwrap();
+        assert!(proofs.is_empty());
+    }
+
+    #[test]
+    fn values_read_stored_are_the_values_read() {
+        let k = Key::Hash([1u8; 32]);
+        let missing = Key::Hash([2u8; 32]);
+        let pairs = [(k, Value::String("stored".to_owned()))];
+        let (global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let reader = global_state.checkout(root_hash).unwrap().unwrap();
+        let mut tc = TrackingCopy::new(reader);
+
+        let stored = tc.read_stored(k).unwrap().unwrap();
+        assert_matches!(stored, StoredValue::Raw(_));
+        assert_eq!(stored.into_value().unwrap(), Value::String("stored".to_owned()));
+        assert_eq!(tc.ops.get(&k), Some(&Op::Read));
+        assert!(tc.read_stored(missing).unwrap().is_none());
+        assert_eq!(tc.cache.get(&missing), Some(&None));
+        // Values written are read decoded.
+        tc.write(k, Value::Int32(3));
+        assert_matches!(
+            tc.read_stored(k).unwrap(),
+            Some(StoredValue::Decoded(Value::Int32(3)))
+        );
+    }
+
+    #[test]
+    fn keys_with_prefix_lists_the_current_values() {
+        let hash_1 = Key::Hash([1u8; 32]);
+        let hash_2 = Key::Hash([2u8; 32]);
+        let hash_3 = Key::Hash([3u8; 32]);
+        let account = Key::Account([1u8; 20]);
+        let pairs = [
+            (hash_1, Value::Int32(1)),
+            (hash_2, Value::Int32(2)),
+            (account, Value::Int32(3)),
+        ];
+        let (global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let reader = global_state.checkout(root_hash).unwrap().unwrap();
+        let mut tc = TrackingCopy::new(reader);
+        tc.write(hash_1, Value::Int32(4));
+        tc.write(hash_3, Value::Int32(5));
+
+        let hash_prefix = &hash_1.to_bytes().unwrap()[..1];
+        let listed = tc.keys_with_prefix(hash_prefix).unwrap();
+        let expected = vec![
+            (hash_1, Value::Int32(4)),
+            (hash_2, Value::Int32(2)),
+            (hash_3, Value::Int32(5)),
+        ];
+        assert_eq!(listed, Some(expected));
+        assert_eq!(tc.ops.get(&hash_2), Some(&Op::Read));
+        assert_eq!(tc.ops.get(&account), None);
+        // Keys written are listed before they're in the state.
+        let mut tc = TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        tc.write(hash_1, Value::Int32(4));
+        let listed = tc.keys_with_prefix(hash_prefix).unwrap();
+        assert_eq!(listed, Some(vec![(hash_1, Value::Int32(4))]));
+    }
+
     proptest! {
         #[test]
         fn query_empty_path(k in key_arb(), missing_key in key_arb(), v in value_arb()) {
@@ -489,7 +914,7 @@ mod tests {
 
             let mut known_urefs = BTreeMap::new();
             known_urefs.insert(name.clone(), k);
-            let contract: Value = Contract::new(body, known_urefs).into();
+            let contract: Value = Contract::new(body, known_urefs, PROTOCOL_VERSION).into();
             let contract_key = Key::Hash(hash);
             map.insert(contract_key, contract);
 
@@ -518,15 +943,17 @@ mod tests {
             pk in u8_slice_32(), // account public key
             nonce in any::<u64>(), // account nonce
             address in u8_slice_20(), // address for account key
+            purse_id in purse_id_arb(), // main purse of the account
         ) {
             let mut map = BTreeMap::new();
             map.insert(k, v.clone());
 
             let known_urefs = iter::once((name.clone(), k)).collect();
-            let account = Account::new(
+            let account = Account::create(
                 pk,
                 nonce,
                 known_urefs,
+                purse_id,
             );
             let account_key = Key::Account(address);
             map.insert(account_key, Value::Account(account));
@@ -557,6 +984,7 @@ mod tests {
             address in u8_slice_20(), // address for account key
             body in vec(any::<u8>(), 1..1000), //contract body
             hash in u8_slice_32(), // hash for contract key
+            purse_id in purse_id_arb(), // main purse of the account
         ) {
             let mut map = BTreeMap::new();
             map.insert(k, v.clone());
@@ -564,17 +992,18 @@ mod tests {
             // create contract which knows about value
             let mut contract_known_urefs = BTreeMap::new();
             contract_known_urefs.insert(state_name.clone(), k);
-            let contract: Value = Contract::new(body, contract_known_urefs).into();
+            let contract: Value = Contract::new(body, contract_known_urefs, PROTOCOL_VERSION).into();
             let contract_key = Key::Hash(hash);
             map.insert(contract_key, contract);
 
             // create account which knows about contract
             let mut account_known_urefs = BTreeMap::new();
             account_known_urefs.insert(contract_name.clone(), contract_key);
-            let account = Account::new(
+            let account = Account::create(
                 pk,
                 nonce,
                 account_known_urefs,
+                purse_id,
             );
             let account_key = Key::Account(address);
             map.insert(account_key, Value::Account(account));
@@ -0,0 +1,170 @@
+//! Records of the native transfers made by deploys.
+//!
+//! Every transfer a deploy makes through the transfer host functions leaves a
+//! `Transfer` under `Key::Transfer`, at an address drawn from a chain seeded
+//! by the deploy hash and the phase, so that clients can prove and list
+//! transfers without replaying deploys. The execution result of a deploy
+//! lists the addresses of the records it wrote.
+
+use common::bytesrepr::{self, deserialize, FromBytes, ToBytes};
+use common::key::Key;
+use common::value::account::PurseId;
+use common::value::{Value, U512};
+use execution::Error;
+use shared::gas::Gas;
+use storage::global_state::{ExecutionEffect, StateReader};
+use trackingcopy::TrackingCopy;
+
+/// Address of a transfer record, under `Key::Transfer`.
+pub type TransferAddr = [u8; 32];
+
+/// Record of a native transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// Hash of the deploy which made the transfer.
+    pub deploy_hash: [u8; 32],
+    /// Account the deploy was executed under.
+    pub from: [u8; 20],
+    /// Account the tokens were sent to, unless they were sent to a purse.
+    pub to: Option<[u8; 20]>,
+    pub source: PurseId,
+    pub target: PurseId,
+    pub amount: U512,
+    /// Gas the phase of the deploy had used when the transfer was made.
+    pub gas: Gas,
+}
+
+/// Parses the 20 bytes of an account address serialized as a `Vec<u8>`.
+pub(crate) fn account_addr(bytes: &[u8]) -> Result<[u8; 20], bytesrepr::Error> {
+    if bytes.len() != 20 {
+        return Err(bytesrepr::Error::FormattingError);
+    }
+    let mut addr = [0u8; 20];
+    addr.copy_from_slice(bytes);
+    Ok(addr)
+}
+
+impl ToBytes for Transfer {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.deploy_hash.to_bytes()?;
+        result.append(&mut self.from.to_vec().to_bytes()?);
+        result.append(&mut self.to.map(|to| to.to_vec()).to_bytes()?);
+        result.append(&mut self.source.to_bytes()?);
+        result.append(&mut self.target.to_bytes()?);
+        result.append(&mut self.amount.to_bytes()?);
+        result.append(&mut self.gas.value().to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for Transfer {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (deploy_hash, rem): ([u8; 32], &[u8]) = FromBytes::from_bytes(bytes)?;
+        let (from, rem): (Vec<u8>, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (to, rem): (Option<Vec<u8>>, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (source, rem): (PurseId, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (target, rem): (PurseId, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (amount, rem): (U512, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (gas, rem): (U512, &[u8]) = FromBytes::from_bytes(rem)?;
+        let to = match to {
+            Some(to) => Some(account_addr(&to)?),
+            None => None,
+        };
+        Ok((
+            Transfer {
+                deploy_hash,
+                from: account_addr(&from)?,
+                to,
+                source,
+                target,
+                amount,
+                gas: Gas::new(gas),
+            },
+            rem,
+        ))
+    }
+}
+
+/// Reads the transfer record at `addr`, if there is one.
+pub fn read_transfer<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    addr: TransferAddr,
+) -> Result<Option<Transfer>, Error>
+where
+    R::Error: Into<Error>,
+{
+    match state.read(Key::Transfer(addr)).map_err(Into::into)? {
+        Some(Value::ByteArray(bytes)) => Ok(Some(deserialize(&bytes)?)),
+        Some(_) | None => Ok(None),
+    }
+}
+
+pub fn write_transfer<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+    addr: TransferAddr,
+    transfer: &Transfer,
+) -> Result<(), Error> {
+    state.write(Key::Transfer(addr), Value::ByteArray(transfer.to_bytes()?));
+    Ok(())
+}
+
+/// Addresses of the transfer records written by `effect`, in ascending order.
+pub fn transfer_addrs(effect: &ExecutionEffect) -> Vec<TransferAddr> {
+    let mut addrs: Vec<TransferAddr> = effect
+        .1
+        .keys()
+        .filter_map(|key| match key {
+            Key::Transfer(addr) => Some(*addr),
+            _ => None,
+        })
+        .collect();
+    addrs.sort();
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use common::bytesrepr::{deserialize, ToBytes};
+    use common::key::{AccessRights, Key};
+    use common::value::account::PurseId;
+    use common::value::{Value, U512};
+    use shared::gas::Gas;
+    use storage::global_state::inmem::InMemGS;
+
+    use super::{read_transfer, transfer_addrs, write_transfer, Transfer};
+    use trackingcopy::TrackingCopy;
+
+    fn transfer(to: Option<[u8; 20]>) -> Transfer {
+        Transfer {
+            deploy_hash: [1u8; 32],
+            from: [2u8; 20],
+            to,
+            source: PurseId::new([3u8; 32], AccessRights::READ_ADD_WRITE),
+            target: PurseId::new([4u8; 32], AccessRights::ADD),
+            amount: U512::from(50),
+            gas: Gas::from(7),
+        }
+    }
+
+    #[test]
+    fn transfers_roundtrip() {
+        for transfer in &[transfer(Some([5u8; 20])), transfer(None)] {
+            let bytes = transfer.to_bytes().unwrap();
+            assert_eq!(deserialize::<Transfer>(&bytes).as_ref(), Ok(transfer));
+        }
+    }
+
+    #[test]
+    fn written_transfers_are_listed_by_the_effect() {
+        let mut tc: TrackingCopy<InMemGS<Key, Value>> =
+            TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        write_transfer(&mut tc, [9u8; 32], &transfer(None)).unwrap();
+        write_transfer(&mut tc, [8u8; 32], &transfer(Some([5u8; 20]))).unwrap();
+        tc.write(Key::Hash([7u8; 32]), Value::UInt512(U512::one()));
+        assert_eq!(transfer_addrs(&tc.effect()), vec![[8u8; 32], [9u8; 32]]);
+        assert_eq!(read_transfer(&mut tc, [9u8; 32]).unwrap(), Some(transfer(None)));
+        assert_eq!(read_transfer(&mut tc, [6u8; 32]).unwrap(), None);
+    }
+}
@@ -0,0 +1,415 @@
+//! Protocol data and the upgrades changing it.
+//!
+//! The protocol data of a chain is recorded in its global state under
+//! `protocol_data_key()`. Genesis writes the first version and every upgrade
+//! replaces it with the data of the protocol version it activates.
+
+use std::collections::BTreeMap;
+
+use common::bytesrepr::{self, deserialize, FromBytes, ToBytes};
+use common::key::Key;
+use common::system_contracts::SystemContractId;
+use common::value::{Contract, Value};
+use execution::Error;
+use shared::newtypes::Blake2bHash;
+use std::convert::TryFrom;
+use storage::global_state::{ExecutionEffect, StateReader};
+use storage::transform::TypeMismatch;
+use system::SystemContext;
+use trackingcopy::TrackingCopy;
+use vm::host_function_costs::{HostFunctionCost, HostFunctionCosts};
+use vm::storage_costs::StorageCosts;
+use vm::wasm_costs::WasmCosts;
+
+/// Key the protocol data is stored under.
+pub fn protocol_data_key() -> Key {
+    let mut addr = [0u8; 32];
+    addr.copy_from_slice(&Blake2bHash::new(b"protocol_data").to_vec());
+    Key::Hash(addr)
+}
+
+/// Settings of the protocol a chain runs at a given protocol version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolData {
+    pub protocol_version: u64,
+    pub wasm_costs: WasmCosts,
+    pub system_contracts: BTreeMap<SystemContractId, Key>,
+    pub host_function_costs: HostFunctionCosts,
+    pub storage_costs: StorageCosts,
+}
+
+fn wasm_costs_fields(wasm_costs: &WasmCosts) -> [u32; 12] {
+    [
+        wasm_costs.regular,
+        wasm_costs.div,
+        wasm_costs.mul,
+        wasm_costs.mem,
+        wasm_costs.initial_mem,
+        wasm_costs.grow_mem,
+        wasm_costs.memcpy,
+        wasm_costs.max_stack_height,
+        wasm_costs.max_initial_pages,
+        wasm_costs.max_grow_pages,
+        wasm_costs.opcodes_mul,
+        wasm_costs.opcodes_div,
+    ]
+}
+
+impl ToBytes for ProtocolData {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = self.protocol_version.to_bytes()?;
+        for field in wasm_costs_fields(&self.wasm_costs).iter() {
+            result.append(&mut field.to_bytes()?);
+        }
+        let system_contracts: BTreeMap<u32, Key> = self
+            .system_contracts
+            .iter()
+            .map(|(id, key)| (u32::from(*id), *key))
+            .collect();
+        result.append(&mut system_contracts.to_bytes()?);
+        let default_cost = self.host_function_costs.default_cost();
+        result.append(&mut (default_cost.base, default_cost.per_byte).to_bytes()?);
+        let costs: BTreeMap<String, (u32, u32)> = self
+            .host_function_costs
+            .costs()
+            .iter()
+            .map(|(name, cost)| (name.clone(), (cost.base, cost.per_byte)))
+            .collect();
+        result.append(&mut costs.to_bytes()?);
+        result.append(&mut self.storage_costs.gas_per_byte.to_bytes()?);
+        Ok(result)
+    }
+}
+
+impl FromBytes for ProtocolData {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (protocol_version, mut rem): (u64, &[u8]) = FromBytes::from_bytes(bytes)?;
+        let mut fields = [0u32; 12];
+        for field in fields.iter_mut() {
+            let (value, next): (u32, &[u8]) = FromBytes::from_bytes(rem)?;
+            *field = value;
+            rem = next;
+        }
+        let wasm_costs = WasmCosts {
+            regular: fields[0],
+            div: fields[1],
+            mul: fields[2],
+            mem: fields[3],
+            initial_mem: fields[4],
+            grow_mem: fields[5],
+            memcpy: fields[6],
+            max_stack_height: fields[7],
+            max_initial_pages: fields[8],
+            max_grow_pages: fields[9],
+            opcodes_mul: fields[10],
+            opcodes_div: fields[11],
+        };
+        let (ids, rem): (BTreeMap<u32, Key>, &[u8]) = FromBytes::from_bytes(rem)?;
+        let system_contracts = ids
+            .into_iter()
+            .map(|(id, key)| {
+                SystemContractId::try_from(id)
+                    .map(|id| (id, key))
+                    .map_err(|_| bytesrepr::Error::FormattingError)
+            })
+            .collect::<Result<_, _>>()?;
+        let ((base, per_byte), rem): ((u32, u32), &[u8]) = FromBytes::from_bytes(rem)?;
+        let (costs, rem): (BTreeMap<String, (u32, u32)>, &[u8]) = FromBytes::from_bytes(rem)?;
+        let (gas_per_byte, rem): (u32, &[u8]) = FromBytes::from_bytes(rem)?;
+        let host_function_costs = costs.into_iter().fold(
+            HostFunctionCosts::new(HostFunctionCost::new(base, per_byte)),
+            |host_function_costs, (name, (base, per_byte))| {
+                host_function_costs.with_cost(&name, HostFunctionCost::new(base, per_byte))
+            },
+        );
+        let protocol_data = ProtocolData {
+            protocol_version,
+            wasm_costs,
+            system_contracts,
+            host_function_costs,
+            storage_costs: StorageCosts::new(gas_per_byte),
+        };
+        Ok((protocol_data, rem))
+    }
+}
+
+/// Reads the protocol data recorded in the global state, if there's any.
+pub fn read_protocol_data<R: StateReader<Key, Value>>(
+    state: &mut TrackingCopy<R>,
+) -> Result<Option<ProtocolData>, Error>
+where
+    R::Error: Into<Error>,
+{
+    match state.read(protocol_data_key()).map_err(Into::into)? {
+        Some(Value::ByteArray(bytes)) => Ok(Some(deserialize(&bytes)?)),
+        Some(other) => Err(Error::TypeMismatch(TypeMismatch::new(
+            "ByteArray".to_string(),
+            other.type_string(),
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Records `protocol_data` in the global state, which only genesis and
+/// upgrades do.
+pub fn write_protocol_data<R: StateReader<Key, Value>>(
+    _context: &SystemContext,
+    state: &mut TrackingCopy<R>,
+    protocol_data: &ProtocolData,
+) -> Result<(), Error> {
+    state.write(protocol_data_key(), Value::ByteArray(protocol_data.to_bytes()?));
+    Ok(())
+}
+
+/// Changes made to the protocol once it reaches an activation version.
+#[derive(Debug, Clone)]
+pub struct UpgradeConfig {
+    activation_protocol_version: u64,
+    wasm_costs: Option<WasmCosts>,
+    host_function_costs: Option<HostFunctionCosts>,
+    storage_costs: Option<StorageCosts>,
+    system_contract_code: BTreeMap<SystemContractId, Vec<u8>>,
+    migrations: Vec<(Key, Value)>,
+}
+
+impl UpgradeConfig {
+    /// Upgrade activating `activation_protocol_version`, which has to be
+    /// higher than the version the chain runs.
+    pub fn new(activation_protocol_version: u64) -> UpgradeConfig {
+        UpgradeConfig {
+            activation_protocol_version,
+            wasm_costs: None,
+            host_function_costs: None,
+            storage_costs: None,
+            system_contract_code: BTreeMap::new(),
+            migrations: Vec::new(),
+        }
+    }
+
+    /// Replaces the Wasm costs of the protocol.
+    pub fn with_wasm_costs(mut self, wasm_costs: WasmCosts) -> UpgradeConfig {
+        self.wasm_costs = Some(wasm_costs);
+        self
+    }
+
+    /// Replaces the costs of the host functions of the protocol.
+    pub fn with_host_function_costs(
+        mut self,
+        host_function_costs: HostFunctionCosts,
+    ) -> UpgradeConfig {
+        self.host_function_costs = Some(host_function_costs);
+        self
+    }
+
+    /// Replaces the costs of the bytes deploys store.
+    pub fn with_storage_costs(mut self, storage_costs: StorageCosts) -> UpgradeConfig {
+        self.storage_costs = Some(storage_costs);
+        self
+    }
+
+    /// Installs `bytes` as the new code of the system contract `id`. The
+    /// contract keeps its key and its named keys.
+    pub fn with_system_contract_code(
+        mut self,
+        id: SystemContractId,
+        bytes: Vec<u8>,
+    ) -> UpgradeConfig {
+        self.system_contract_code.insert(id, bytes);
+        self
+    }
+
+    /// Writes `value` under `key` as part of the upgrade. Migrations are
+    /// applied in the order they are added.
+    pub fn with_migration(mut self, key: Key, value: Value) -> UpgradeConfig {
+        self.migrations.push((key, value));
+        self
+    }
+
+    pub fn activation_protocol_version(&self) -> u64 {
+        self.activation_protocol_version
+    }
+
+    /// Applies the upgrade to the protocol data recorded in `state` and to
+    /// the system contracts it lists.
+    pub fn apply<R: StateReader<Key, Value>>(
+        &self,
+        context: &SystemContext,
+        state: &mut TrackingCopy<R>,
+    ) -> Result<ProtocolData, UpgradeError>
+    where
+        R::Error: Into<Error>,
+    {
+        let mut protocol_data = read_protocol_data(state)?.ok_or(UpgradeError::NoProtocolData)?;
+        if self.activation_protocol_version <= protocol_data.protocol_version {
+            return Err(UpgradeError::InvalidActivationVersion {
+                current: protocol_data.protocol_version,
+                activation: self.activation_protocol_version,
+            });
+        }
+        for (id, bytes) in &self.system_contract_code {
+            let key = *protocol_data
+                .system_contracts
+                .get(id)
+                .ok_or(UpgradeError::UnknownSystemContract(*id))?;
+            let contract = match state.read(key).map_err(Into::into)? {
+                Some(Value::Contract(contract)) => contract,
+                _ => return Err(UpgradeError::UnknownSystemContract(*id)),
+            };
+            let (_, named_keys, protocol_version, abi) = contract.destructure();
+            let mut upgraded = Contract::new(bytes.clone(), named_keys, protocol_version);
+            if let Some(abi) = abi {
+                upgraded = upgraded.with_abi(abi);
+            }
+            state.write(key, Value::Contract(upgraded));
+        }
+        for (key, value) in &self.migrations {
+            state.write(*key, value.clone());
+        }
+        protocol_data.protocol_version = self.activation_protocol_version;
+        if let Some(wasm_costs) = &self.wasm_costs {
+            protocol_data.wasm_costs = wasm_costs.clone();
+        }
+        if let Some(host_function_costs) = &self.host_function_costs {
+            protocol_data.host_function_costs = host_function_costs.clone();
+        }
+        if let Some(storage_costs) = self.storage_costs {
+            protocol_data.storage_costs = storage_costs;
+        }
+        write_protocol_data(context, state, &protocol_data)?;
+        Ok(protocol_data)
+    }
+}
+
+/// Reasons why an upgrade can't be applied.
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// The state wasn't created by genesis, so there's nothing to upgrade.
+    NoProtocolData,
+    /// The upgrade doesn't activate a version higher than the current one.
+    InvalidActivationVersion { current: u64, activation: u64 },
+    /// The protocol has no system contract with the id.
+    UnknownSystemContract(SystemContractId),
+    RootNotFound(Blake2bHash),
+    /// The effects of the upgrade couldn't be committed.
+    CommitFailed,
+    Exec(Error),
+}
+
+impl From<Error> for UpgradeError {
+    fn from(error: Error) -> UpgradeError {
+        UpgradeError::Exec(error)
+    }
+}
+
+impl From<bytesrepr::Error> for UpgradeError {
+    fn from(error: bytesrepr::Error) -> UpgradeError {
+        UpgradeError::Exec(error.into())
+    }
+}
+
+pub struct UpgradeResult {
+    pub post_state_hash: Blake2bHash,
+    pub protocol_data: ProtocolData,
+    pub effect: ExecutionEffect,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use common::bytesrepr::{deserialize, ToBytes};
+    use common::key::Key;
+    use common::system_contracts::SystemContractId;
+    use common::value::contract::PROTOCOL_VERSION;
+    use common::value::{Contract, Value};
+    use storage::global_state::inmem::InMemGS;
+    use vm::host_function_costs::{HostFunctionCost, HostFunctionCosts};
+    use vm::storage_costs::StorageCosts;
+    use vm::wasm_costs::WasmCosts;
+
+    use super::{
+        read_protocol_data, write_protocol_data, ProtocolData, UpgradeConfig, UpgradeError,
+    };
+    use system::{SystemContext, SystemFlow};
+    use trackingcopy::TrackingCopy;
+
+    const MINT_KEY: Key = Key::Hash([1u8; 32]);
+
+    fn context() -> SystemContext {
+        SystemContext::new(SystemFlow::Upgrade)
+    }
+
+    fn protocol_data() -> ProtocolData {
+        let mut system_contracts = BTreeMap::new();
+        system_contracts.insert(SystemContractId::Mint, MINT_KEY);
+        ProtocolData {
+            protocol_version: 1,
+            wasm_costs: WasmCosts::default(),
+            system_contracts,
+            host_function_costs: HostFunctionCosts::new(HostFunctionCost::new(1, 0))
+                .with_cost("blake2b", HostFunctionCost::new(10, 2)),
+            storage_costs: StorageCosts::new(3),
+        }
+    }
+
+    #[test]
+    fn protocol_data_roundtrips() {
+        let protocol_data = protocol_data();
+        let bytes = protocol_data.to_bytes().expect("should serialize");
+        assert_eq!(deserialize::<ProtocolData>(&bytes), Ok(protocol_data));
+    }
+
+    #[test]
+    fn upgrade_replaces_costs_and_code() {
+        let mut tc = TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        write_protocol_data(&context(), &mut tc, &protocol_data()).expect("should write");
+        let mut named_keys = BTreeMap::new();
+        named_keys.insert("purse".to_string(), Key::Hash([2u8; 32]));
+        let mint = Contract::new(Vec::new(), named_keys.clone(), PROTOCOL_VERSION);
+        tc.write(MINT_KEY, Value::Contract(mint));
+        let wasm_costs = WasmCosts {
+            regular: 2,
+            ..WasmCosts::default()
+        };
+        let host_function_costs = HostFunctionCosts::new(HostFunctionCost::new(2, 1));
+
+        let upgrade = UpgradeConfig::new(2)
+            .with_wasm_costs(wasm_costs.clone())
+            .with_host_function_costs(host_function_costs.clone())
+            .with_storage_costs(StorageCosts::new(5))
+            .with_system_contract_code(SystemContractId::Mint, vec![1, 2, 3])
+            .with_migration(Key::Hash([3u8; 32]), Value::Int32(7));
+        let upgraded = upgrade.apply(&context(), &mut tc).expect("should upgrade");
+        assert_eq!(upgraded.protocol_version, 2);
+        assert_eq!(upgraded.wasm_costs, wasm_costs);
+        assert_eq!(upgraded.host_function_costs, host_function_costs);
+        assert_eq!(upgraded.storage_costs, StorageCosts::new(5));
+        assert_eq!(read_protocol_data(&mut tc).unwrap(), Some(upgraded));
+        match tc.read(MINT_KEY) {
+            Ok(Some(Value::Contract(contract))) => {
+                assert_eq!(contract.bytes(), &[1, 2, 3]);
+                assert_eq!(contract.urefs_lookup(), &named_keys);
+            }
+            other => panic!("Expected a contract, got {:?}", other),
+        }
+        assert_matches!(tc.read(Key::Hash([3u8; 32])), Ok(Some(Value::Int32(7))));
+
+        // The same version can't be activated twice.
+        assert_matches!(
+            upgrade.apply(&context(), &mut tc),
+            Err(UpgradeError::InvalidActivationVersion {
+                current: 2,
+                activation: 2
+            })
+        );
+    }
+
+    #[test]
+    fn upgrade_requires_protocol_data() {
+        let mut tc = TrackingCopy::new(InMemGS::new(BTreeMap::new()));
+        assert_matches!(
+            UpgradeConfig::new(2).apply(&context(), &mut tc),
+            Err(UpgradeError::NoProtocolData)
+        );
+    }
+}
@@ -1,34 +1,74 @@
 extern crate common;
+extern crate ed25519_dalek;
 extern crate execution_engine;
 extern crate failure;
 extern crate parity_wasm;
 extern crate rand;
 extern crate rand_chacha;
+extern crate secp256k1;
 extern crate shared;
 extern crate storage;
+extern crate vm;
 extern crate wabt;
 extern crate wasm_prep;
 extern crate wasmi;
 
-use common::bytesrepr::{deserialize, FromBytes, ToBytes};
-use common::key::{AccessRights, Key, UREF_SIZE};
-use common::value::{self, Account, Contract, Value};
-use execution_engine::execution::{Runtime, RuntimeContext};
+use common::auction::AuctionError;
+use common::bytesrepr::{deserialize, vec_from_bytes, vec_to_bytes, FromBytes, ToBytes};
+use common::contract_api::error::ApiError;
+use common::key::{AccessError, AccessRights, Key, UREF_SIZE};
+use common::phase::{Phase, PHASE_SIZE};
+use common::pos::PosError;
+use common::protocol_version::{ProtocolVersion, PROTOCOL_VERSION_SIZE};
+use common::runtime_args::RuntimeArgs;
+use common::system_contracts::SystemContractId;
+use common::value::account::{
+    ActionThresholds, AssociatedKeys, KeyLimits, KeyManagementError, PublicKey, PurseId,
+    TransferError, TransferredTo, Weight, PUBLIC_KEY_SIZE,
+};
+use common::value::abi::{Abi, AbiType, Arg, EntryPoint, MAX_ABI_SIZE};
+use common::value::contract::PROTOCOL_VERSION;
+use common::value::contract_package::{ContractVersion, VersionSelector};
+use common::value::{self, Account, Contract, ContractPackage, Value, U512};
+use execution_engine::approval::{body_hash, deploy_hash, Approval, ApprovalError};
+use execution_engine::auction;
+use execution_engine::execution::{
+    Executor, Runtime, RuntimeContext, WasmiExecutor, MAX_EVENTS_PER_DEPLOY, MAX_EVENT_SIZE,
+    MAX_NAMED_KEYS_PAGE_SIZE,
+};
+use execution_engine::deploy_info::{DeployPreconditions, PreconditionError};
+use execution_engine::error_code::ErrorCode;
+use execution_engine::engine::{
+    Deploy, DeployCode, EngineConfig, EngineState, Error as EngineError, ExecutionResult,
+    RefundRatio, CONV_RATE,
+};
+use execution_engine::journal::JournalEntry;
+use execution_engine::mint;
+use execution_engine::module_cache::CachingPreprocessor;
+use execution_engine::pos;
+use execution_engine::standard_payment::STANDARD_PAYMENT_COST;
+use execution_engine::system::SYSTEM_ACCOUNT;
 use execution_engine::trackingcopy::TrackingCopy;
+use execution_engine::transfer::{self, Transfer};
 use failure::Error;
 use parity_wasm::builder::module;
-use parity_wasm::elements::Module;
+use parity_wasm::elements::{External, Module};
 use rand::RngCore;
+use shared::gas::Gas;
 use shared::newtypes::Blake2bHash;
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::iter::once;
 use std::iter::IntoIterator;
 use std::rc::Rc;
-use storage::global_state::{inmem::*, StateReader};
+use storage::global_state::{inmem::*, Event, StateReader};
 use storage::history::*;
 use storage::transform::Transform;
-use wasm_prep::MAX_MEM_PAGES;
+use vm::host_function_costs::{HostFunctionCost, HostFunctionCosts};
+use vm::storage_costs::StorageCosts;
+use vm::wasm_costs::WasmCosts;
+use wasm_prep::{Preprocessor, PreprocessingError, WasmiPreprocessor, MAX_MEM_PAGES};
 use wasmi::memory_units::Pages;
 use wasmi::{MemoryInstance, MemoryRef};
 
@@ -65,9 +105,6 @@ impl MockEnv {
     pub fn runtime<'a>(
         &'a mut self,
         tc: &'a mut TrackingCopy<InMemGS<Key, Value>>,
-        address: [u8; 20],
-        timestamp: u64,
-        nonce: u64,
         module: Module,
     ) -> Runtime<'a, InMemGS<Key, Value>> {
         let context = mock_context(
@@ -76,16 +113,7 @@ impl MockEnv {
             &self.deploy_account,
             self.base_key,
         );
-        Runtime::new(
-            Vec::new(),
-            self.memory.clone(),
-            tc,
-            module,
-            address,
-            nonce,
-            timestamp,
-            context,
-        )
+        Runtime::new(Vec::new(), self.memory.clone(), tc, module, context)
     }
 
     pub fn memory_manager(&self) -> WasmMemoryManager {
@@ -190,8 +218,12 @@ impl WasmMemoryManager {
     }
 }
 
+fn mock_purse_id() -> PurseId {
+    PurseId::new([255u8; 32], AccessRights::READ_ADD_WRITE)
+}
+
 fn mock_account(addr: [u8; 20]) -> (Key, value::Account) {
-    let account = value::Account::new([0u8; 32], 0, BTreeMap::new());
+    let account = value::Account::create([0u8; 32], 0, BTreeMap::new(), mock_purse_id());
     let key = Key::Account(addr);
 
     (key, account)
@@ -199,7 +231,7 @@ fn mock_account(addr: [u8; 20]) -> (Key, value::Account) {
 
 fn mock_tc(init_key: Key, init_account: &value::Account) -> TrackingCopy<InMemGS<Key, Value>> {
     let root_hash: Blake2bHash = [0u8; 32].into();
-    let mut hist = InMemHist::new(&root_hash);
+    let hist = InMemHist::new(&root_hash);
     let transform = Transform::Write(value::Value::Account(init_account.clone()));
 
     let mut m = HashMap::new();
@@ -221,8 +253,16 @@ fn mock_context<'a>(
     account: &'a value::Account,
     base_key: Key,
 ) -> RuntimeContext<'a> {
-    let gas_limit = 1000u64;
-    let mut context = RuntimeContext::new(uref_lookup, account, base_key, gas_limit);
+    let gas_limit = Gas::from(1000);
+    let authorization_keys = once(account.public_key()).collect();
+    let mut context = RuntimeContext::new(
+        uref_lookup,
+        account,
+        base_key,
+        authorization_keys,
+        gas_limit,
+        Phase::Session,
+    );
     known_urefs
         .iter()
         .for_each(|key| context.insert_uref(key.clone()));
@@ -296,8 +336,6 @@ fn wasm_write<T: ToBytes>(wasm_memory: &mut WasmMemoryManager, value: T) -> (u32
 
 struct TestFixture {
     addr: [u8; 20],
-    timestamp: u64,
-    nonce: u64,
     env: MockEnv,
     memory: WasmMemoryManager,
     tc: Rc<RefCell<TrackingCopy<InMemGS<Key, Value>>>>,
@@ -306,16 +344,12 @@ struct TestFixture {
 impl TestFixture {
     fn new(
         addr: [u8; 20],
-        timestamp: u64,
-        nonce: u64,
         env: MockEnv,
         memory: WasmMemoryManager,
         tc: Rc<RefCell<TrackingCopy<InMemGS<Key, Value>>>>,
     ) -> TestFixture {
         TestFixture {
             addr,
-            timestamp,
-            nonce,
             env,
             memory,
             tc,
@@ -326,15 +360,13 @@ impl TestFixture {
 impl Default for TestFixture {
     fn default() -> Self {
         let addr = [0u8; 20];
-        let timestamp: u64 = 1000;
-        let nonce: u64 = 1;
         let (key, account) = mock_account(addr);
         let tc = Rc::new(RefCell::new(mock_tc(key, &account)));
         let uref_lookup: BTreeMap<String, Key> = BTreeMap::new();
         let known_urefs: HashSet<Key> = HashSet::new();
         let env = MockEnv::new(key, uref_lookup, known_urefs, account, 0);
         let memory = env.memory_manager();
-        TestFixture::new(addr, timestamp, nonce, env, memory, tc)
+        TestFixture::new(addr, env, memory, tc)
     }
 }
 
@@ -343,13 +375,7 @@ fn valid_uref() {
     // Test fixture
     let mut test_fixture: TestFixture = Default::default();
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        mock_module(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
 
     // write arbitrary value to wasm memory to allow call to write
     let init_value = wasm_write(&mut test_fixture.memory, value::Value::Int32(42));
@@ -372,13 +398,7 @@ fn forged_uref() {
     // Test fixture
     let mut test_fixture: TestFixture = Default::default();
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        mock_module(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
 
     let mut rng = rand::thread_rng();
     // create a forged uref
@@ -407,7 +427,11 @@ fn contract_bytes_from_wat(mut test_module: TestModule, urefs: BTreeMap<String,
     rename_export_to_call(&mut test_module.module, test_module.func_name);
     let contract_bytes =
         parity_wasm::serialize(test_module.module).expect("Failed to serialize Wasm module.");
-    Value::Contract(common::value::Contract::new(contract_bytes, urefs))
+    Value::Contract(common::value::Contract::new(
+        contract_bytes,
+        urefs,
+        PROTOCOL_VERSION,
+    ))
 }
 
 fn read_contract_hash(wasm_memory: &WasmMemoryManager, hash_ptr: u32) -> Key {
@@ -467,13 +491,7 @@ fn store_contract_hash() {
     // and we can borrow it again when we call `effect()`.
     let hash = {
         let mut tc_borrowed = test_fixture.tc.borrow_mut();
-        let mut runtime = test_fixture.env.runtime(
-            &mut tc_borrowed,
-            test_fixture.addr,
-            test_fixture.timestamp,
-            test_fixture.nonce,
-            wasm_module.module,
-        );
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module);
 
         let store_result = test_fixture
             .memory
@@ -500,6 +518,103 @@ fn store_contract_hash() {
     assert_eq!(effect, &Transform::Write(contract));
 }
 
+#[test]
+fn store_contract_with_unsupported_import() {
+    let wat = r#"
+            (module
+                (import "env" "unsupported" (func $unsupported))
+                (func (export "add")
+                    call $unsupported
+                )
+            )
+            "#;
+    let module: Module = {
+        let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+        parity_wasm::deserialize_buffer(&wasm_binary)
+            .expect("Failed to deserialize bytes to Wasm module.")
+    };
+    let mut test_fixture: TestFixture = Default::default();
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, module);
+    let store_result = test_fixture
+        .memory
+        .store_contract("add", urefs_map(std::iter::empty()));
+    let result = runtime.store_function(
+        store_result.contract_ptr,
+        store_result.contract_len as u32,
+        store_result.urefs_ptr,
+        store_result.urefs_len as u32,
+        store_result.hash_ptr,
+    );
+    assert_error_contains(result, "InvalidContract(InvalidImportsError");
+}
+
+// Calls `store_library` on a module exporting the pure function `double` and
+// the function `caller`, which calls a host function.
+fn store_library(names: Vec<String>) -> (Result<(), wasmi::Trap>, TestFixture, u32) {
+    let wat = r#"
+            (module
+                (import "env" "get_caller" (func $get_caller (param i32)))
+                (func (export "double") (param i32) (result i32)
+                    get_local 0
+                    i32.const 2
+                    i32.mul
+                )
+                (func (export "caller") (param i32)
+                    get_local 0
+                    call $get_caller
+                )
+            )
+            "#;
+    let module: Module = {
+        let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+        parity_wasm::deserialize_buffer(&wasm_binary)
+            .expect("Failed to deserialize bytes to Wasm module.")
+    };
+    let mut test_fixture: TestFixture = Default::default();
+    let names = wasm_write(&mut test_fixture.memory, names);
+    let hash_ptr = test_fixture.memory.alloc(32);
+    let result = {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, module);
+        runtime.store_library(names.0, names.1 as u32, hash_ptr)
+    };
+    (result, test_fixture, hash_ptr)
+}
+
+#[test]
+fn store_library_keeps_only_its_functions() {
+    let (result, test_fixture, hash_ptr) = store_library(vec!["double".to_string()]);
+    result.expect("store_library should succeed");
+    let hash = read_contract_hash(&test_fixture.memory, hash_ptr);
+    let library = match test_fixture.tc.borrow_mut().get(&hash).unwrap() {
+        Some(Value::Contract(library)) => library,
+        other => panic!("Expected contract, got {:?}", other),
+    };
+    let module: Module =
+        parity_wasm::deserialize_buffer(library.bytes()).expect("Library should deserialize");
+    let exports: Vec<&str> = module
+        .export_section()
+        .expect("Library should have exports")
+        .entries()
+        .iter()
+        .map(|export| export.field())
+        .collect();
+    assert_eq!(exports, vec!["double"]);
+}
+
+#[test]
+fn store_library_rejects_host_functions() {
+    let (result, _, _) = store_library(vec!["caller".to_string()]);
+    assert_error_contains(result, "InvalidContract(InvalidImportsError");
+}
+
+#[test]
+fn store_library_with_unknown_function() {
+    let (result, _, _) = store_library(vec!["double".to_string(), "triple".to_string()]);
+    assert_error_contains(result, "FunctionNotFound");
+}
+
 fn assert_invalid_access<T>(result: Result<T, wasmi::Trap>) {
     assert_error_contains(result, "InvalidAccess")
 }
@@ -527,13 +642,7 @@ fn store_contract_hash_illegal_urefs() {
     let urefs = urefs_map(vec![("ForgedURef".to_owned(), uref)]);
 
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module,
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module);
 
     let store_result = test_fixture
         .memory
@@ -563,13 +672,7 @@ fn store_contract_hash_legal_urefs() {
     // and we can borrow it again when we call `effect()`.
     let (hash, contract) = {
         let mut tc_borrowed = test_fixture.tc.borrow_mut();
-        let mut runtime = test_fixture.env.runtime(
-            &mut tc_borrowed,
-            test_fixture.addr,
-            test_fixture.timestamp,
-            test_fixture.nonce,
-            wasm_module.module.clone(),
-        );
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
         // Initial value of the uref the in the contract's
         // known_urefs map.
@@ -644,13 +747,11 @@ fn store_contract_uref_known_key() {
     let known_urefs: HashSet<Key> = once(contract_uref).chain(once(known_uref)).collect();
     let mut test_fixture: TestFixture = {
         let addr = [0u8; 20];
-        let timestamp = 1u64;
-        let nonce = 1u64;
         let (key, account) = mock_account(addr);
         let tc = Rc::new(RefCell::new(mock_tc(key, &account)));
         let env = MockEnv::new(key, urefs.clone(), known_urefs, account, 0);
         let memory = env.memory_manager();
-        TestFixture::new(addr, timestamp, nonce, env, memory, tc)
+        TestFixture::new(addr, env, memory, tc)
     };
 
     let wasm_module = create_wasm_module();
@@ -660,13 +761,7 @@ fn store_contract_uref_known_key() {
     // and we can borrow it again when we call `effect()`.
     let contract = {
         let mut tc_borrowed = test_fixture.tc.borrow_mut();
-        let mut runtime = test_fixture.env.runtime(
-            &mut tc_borrowed,
-            test_fixture.addr,
-            test_fixture.timestamp,
-            test_fixture.nonce,
-            wasm_module.module.clone(),
-        );
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
         let wasm_contract_uref = wasm_write(&mut test_fixture.memory, contract_uref);
 
@@ -701,13 +796,11 @@ fn store_contract_uref_forged_key() {
 
     let mut test_fixture: TestFixture = {
         let addr = [0u8; 20];
-        let timestamp = 1u64;
-        let nonce = 1u64;
         let (key, account) = mock_account(addr);
         let tc = Rc::new(RefCell::new(mock_tc(key, &account)));
         let env = MockEnv::new(key, urefs.clone(), known_urefs, account, 0);
         let memory = env.memory_manager();
-        TestFixture::new(addr, timestamp, nonce, env, memory, tc)
+        TestFixture::new(addr, env, memory, tc)
     };
 
     let wasm_module = create_wasm_module();
@@ -716,13 +809,7 @@ fn store_contract_uref_forged_key() {
     // We need this braces so that the `tc_borrowed` gets dropped
     // and we can borrow it again when we call `effect()`.
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module.clone(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
     let wasm_contract_uref = wasm_write(&mut test_fixture.memory, forged_contract_uref);
 
@@ -748,13 +835,7 @@ fn account_key_writeable() {
     let wasm_value = wasm_write(&mut test_fixture.memory, Value::Int32(1));
 
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module.clone(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
     let result = gs_write(&mut runtime, wasm_key, wasm_value);
     assert_invalid_access(result);
@@ -786,13 +867,7 @@ fn test_account_key_readable(
         Key::Account(test_fixture.addr)
     };
     tc_borrowed.write(account_key, init_value);
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module.clone(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
     let wasm_account_key = wasm_write(&mut test_fixture.memory, account_key);
 
     gs_read(&mut test_fixture.memory, &mut runtime, wasm_account_key)
@@ -829,7 +904,7 @@ fn account_key_addable_valid() {
         "PublicHash".to_owned(),
         random_contract_key(&mut rng),
     )]);
-    let account = Account::new([1u8; 32], 1, known_urefs.clone());
+    let account = Account::create([1u8; 32], 1, known_urefs.clone(), mock_purse_id());
     let account_key = Key::Account(test_fixture.addr);
     let wasm_account_key = wasm_write(&mut test_fixture.memory, account_key);
     // This is the key we will want to add to an account
@@ -844,13 +919,7 @@ fn account_key_addable_valid() {
             Value::Account(account.clone()),
         );
 
-        let mut runtime = test_fixture.env.runtime(
-            &mut tc_borrowed,
-            test_fixture.addr,
-            test_fixture.timestamp,
-            test_fixture.nonce,
-            wasm_module.module.clone(),
-        );
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
         // Add key to current context's account.
         runtime
@@ -867,7 +936,12 @@ fn account_key_addable_valid() {
         let additional_key_map = urefs_map(
             once((additional_key.0.clone(), additional_key.1)).chain(known_urefs.clone()),
         );
-        Account::new([1u8; 32], account.nonce(), additional_key_map)
+        Account::create(
+            [1u8; 32],
+            account.nonce(),
+            additional_key_map,
+            mock_purse_id(),
+        )
     };
     let tc_account: Value = tc
         .get(&Key::Account(test_fixture.addr))
@@ -890,7 +964,7 @@ fn account_key_addable_invalid() {
         "PublicHash".to_owned(),
         random_contract_key(&mut rng),
     )]);
-    let account = Account::new([1u8; 32], 1, known_urefs.clone());
+    let account = Account::create([1u8; 32], 1, known_urefs.clone(), mock_purse_id());
     // This is the key we will want to add to an account
     let additional_key = ("PublichHash#2".to_owned(), random_contract_key(&mut rng));
     let named_key = Value::NamedKey(additional_key.0, additional_key.1);
@@ -903,13 +977,7 @@ fn account_key_addable_invalid() {
     // Write an account under current context's key
     tc_borrowed.write(some_other_account, Value::Account(account.clone()));
 
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module.clone(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
     // Add key to some account.
     // We cannot use add_uref as in the other test because
@@ -943,13 +1011,7 @@ fn contract_key_writeable() {
     let wasm_contract = wasm_write(&mut test_fixture.memory, contract);
 
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module.clone(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
     let result = gs_write(&mut runtime, wasm_key, wasm_contract);
     assert_invalid_access(result);
@@ -972,13 +1034,7 @@ fn contract_key_readable() {
     let wasm_urefs = wasm_write(&mut test_fixture.memory, empty_urefs);
 
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module.clone(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
     let result = runtime.call_contract(
         wasm_key.0,
@@ -1000,13 +1056,58 @@ fn contract_key_readable() {
     }
 }
 
+#[test]
+fn call_contract_rejects_incompatible_protocol_version() {
+    let mut test_fixture: TestFixture = Default::default();
+    let mut rng = rand::thread_rng();
+    let wasm_module = create_wasm_module();
+
+    let contract_key = random_contract_key(&mut rng);
+    let newer_contract = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION + 1);
+    test_fixture
+        .tc
+        .borrow_mut()
+        .write(contract_key, Value::Contract(newer_contract));
+    let wasm_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let empty_vec: Vec<u8> = Vec::new();
+    let wasm_args = wasm_write(&mut test_fixture.memory, empty_vec);
+    let empty_urefs: Vec<Key> = Vec::new();
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, empty_urefs);
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
+
+    let result = runtime.call_contract(
+        wasm_key.0,
+        wasm_key.1,
+        wasm_args.0,
+        wasm_args.1,
+        wasm_urefs.0,
+        wasm_urefs.1,
+    );
+
+    match result {
+        Err(execution_engine::execution::Error::IncompatibleProtocolVersion {
+            expected,
+            actual,
+        }) => {
+            assert_eq!(expected, PROTOCOL_VERSION);
+            assert_eq!(actual, PROTOCOL_VERSION + 1);
+        }
+        Err(error) => panic!("Test failed with unexpected error {:?}", error),
+        Ok(_) => panic!("Test should have failed but didn't"),
+    }
+}
+
 fn test_contract_key_addable(base_key: Key, add_to_key: Key) -> Result<(), wasmi::Trap> {
-    let init_contract = Contract::new(Vec::new(), urefs_map(std::iter::empty()));
+    let init_contract = Contract::new(
+        Vec::new(),
+        urefs_map(std::iter::empty()),
+        PROTOCOL_VERSION,
+    );
     // We're setting up the test fixture so that the current context is pointing at `base_key`.
     let mut test_fixture: TestFixture = {
         let addr = [0u8; 20];
-        let nonce = 1u64;
-        let timestamp = 1u64;
         let gas_limit = 0u64;
         let (acc_key, account) = mock_account(addr);
         let uref_lookup = urefs_map(std::iter::empty());
@@ -1019,14 +1120,7 @@ fn test_contract_key_addable(base_key: Key, add_to_key: Key) -> Result<(), wasmi
         tc.write(base_key, Value::Contract(init_contract.clone()));
 
         let memory = env.memory_manager();
-        TestFixture::new(
-            addr,
-            timestamp,
-            nonce,
-            env,
-            memory,
-            Rc::new(RefCell::new(tc)),
-        )
+        TestFixture::new(addr, env, memory, Rc::new(RefCell::new(tc)))
     };
     let wasm_module = create_wasm_module();
     // This is the key we will want to add to a contract
@@ -1041,13 +1135,7 @@ fn test_contract_key_addable(base_key: Key, add_to_key: Key) -> Result<(), wasmi
 
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
 
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        wasm_module.module.clone(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
 
     // We're trying to add to `add_to_key` (which may be different than `base_key`).
     // This way we simulate addition to current (or not) context's base key.
@@ -1098,23 +1186,10 @@ fn test_uref_key_readable(init_value: Value, rights: AccessRights) -> Result<Val
         // We're putting some data under uref so that we can read it later.
         init_tc.write(uref, init_value.clone());
         let tc = Rc::new(RefCell::new(init_tc));
-        TestFixture::new(
-            default.addr,
-            default.timestamp,
-            default.nonce,
-            env,
-            memory,
-            tc,
-        )
+        TestFixture::new(default.addr, env, memory, tc)
     };
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        mock_module(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
     let wasm_uref = wasm_write(&mut test_fixture.memory, uref);
     gs_read(&mut test_fixture.memory, &mut runtime, wasm_uref)
 }
@@ -1157,23 +1232,10 @@ fn test_uref_key_writeable(rights: AccessRights) -> Result<(), wasmi::Trap> {
         let mut init_tc = mock_tc(key, &account);
         init_tc.write(uref, init_value.clone());
         let tc = Rc::new(RefCell::new(init_tc));
-        TestFixture::new(
-            default.addr,
-            default.timestamp,
-            default.nonce,
-            env,
-            memory,
-            tc,
-        )
+        TestFixture::new(default.addr, env, memory, tc)
     };
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        mock_module(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
     let wasm_uref = wasm_write(&mut test_fixture.memory, uref);
     let new_value = Value::Int32(2);
     let wasm_new_value = wasm_write(&mut test_fixture.memory, new_value);
@@ -1210,23 +1272,10 @@ fn test_uref_key_addable(rights: AccessRights) -> Result<(), wasmi::Trap> {
         let mut init_tc = mock_tc(key, &account);
         init_tc.write(uref, init_value.clone());
         let tc = Rc::new(RefCell::new(init_tc));
-        TestFixture::new(
-            default.addr,
-            default.timestamp,
-            default.nonce,
-            env,
-            memory,
-            tc,
-        )
+        TestFixture::new(default.addr, env, memory, tc)
     };
     let mut tc_borrowed = test_fixture.tc.borrow_mut();
-    let mut runtime = test_fixture.env.runtime(
-        &mut tc_borrowed,
-        test_fixture.addr,
-        test_fixture.timestamp,
-        test_fixture.nonce,
-        mock_module(),
-    );
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
     let wasm_uref = wasm_write(&mut test_fixture.memory, uref);
     let new_value = Value::Int32(2);
     let wasm_new_value = wasm_write(&mut test_fixture.memory, new_value);
@@ -1250,3 +1299,3375 @@ fn uref_key_addable_invalid() {
     let result = test_uref_key_addable(AccessRights::READ);
     assert_invalid_access(result);
 }
+
+#[test]
+fn get_caller_returns_deploy_account_key() {
+    // Tests that `get_caller` writes public key of the deploying account into the Wasm memory.
+    let mut test_fixture: TestFixture = Default::default();
+    let wasm_module = create_wasm_module();
+    let dest_ptr = test_fixture.memory.alloc(PUBLIC_KEY_SIZE);
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module);
+
+    runtime
+        .get_caller(dest_ptr)
+        .expect("get_caller should succeed");
+
+    let bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, PUBLIC_KEY_SIZE)
+        .expect("Reading from WasmMemory should work");
+    let caller: PublicKey = deserialize(&bytes).expect("Deserializing should work");
+    // `mock_account` creates accounts with all-zeros public key.
+    assert_eq!(caller, PublicKey::new([0u8; 32]));
+}
+
+#[test]
+fn get_phase_returns_session_phase() {
+    let mut test_fixture: TestFixture = Default::default();
+    let dest_ptr = test_fixture.memory.alloc(PHASE_SIZE);
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    runtime.get_phase(dest_ptr).expect("get_phase should succeed");
+
+    let bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, PHASE_SIZE)
+        .expect("Reading from WasmMemory should work");
+    let phase: Phase = deserialize(&bytes).expect("Deserializing should work");
+    assert_eq!(phase, Phase::Session);
+}
+
+#[test]
+fn deploy_hash_and_protocol_version_come_from_the_context() {
+    let mut test_fixture: TestFixture = Default::default();
+    let hash_ptr = test_fixture.memory.alloc(32);
+    let version_ptr = test_fixture.memory.alloc(PROTOCOL_VERSION_SIZE);
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let env = &mut test_fixture.env;
+    let context = mock_context(
+        &mut env.uref_lookup,
+        &mut env.known_urefs,
+        &env.deploy_account,
+        env.base_key,
+    )
+    .with_deploy_hash([3u8; 32]);
+    let mut runtime = Runtime::new(
+        Vec::new(),
+        env.memory.clone(),
+        &mut tc_borrowed,
+        mock_module(),
+        context,
+    );
+    runtime
+        .get_deploy_hash(hash_ptr)
+        .expect("get_deploy_hash should succeed");
+    runtime
+        .get_protocol_version(version_ptr)
+        .expect("get_protocol_version should succeed");
+
+    let hash = test_fixture
+        .memory
+        .read_bytes(hash_ptr, 32)
+        .expect("Reading from WasmMemory should work");
+    assert_eq!(hash, vec![3u8; 32]);
+    let version_bytes = test_fixture
+        .memory
+        .read_bytes(version_ptr, PROTOCOL_VERSION_SIZE)
+        .expect("Reading from WasmMemory should work");
+    let version: ProtocolVersion = deserialize(&version_bytes).expect("Deserializing should work");
+    assert_eq!(version, ProtocolVersion::new(PROTOCOL_VERSION));
+}
+
+#[test]
+fn revert_status_is_reported_through_calls() {
+    let wat = r#"
+            (module
+                (import "env" "memory" (memory 1))
+                (import "env" "revert" (func $revert (param i32)))
+                (func (export "call")
+                    i32.const 65538
+                    call $revert
+                )
+            )
+            "#;
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    test_fixture.tc.borrow_mut().write(
+        contract_key,
+        Value::Contract(Contract::new(wasm_binary, BTreeMap::new(), PROTOCOL_VERSION)),
+    );
+    let wasm_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<Vec<u8>>::new());
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    match runtime.call_contract(
+        wasm_key.0,
+        wasm_key.1,
+        wasm_args.0,
+        wasm_args.1,
+        wasm_urefs.0,
+        wasm_urefs.1,
+    ) {
+        Err(execution_engine::execution::Error::Revert(status)) => assert_eq!(status, 65538),
+        other => panic!("Expected revert, got {:?}", other),
+    }
+}
+
+// Sets up a fixture whose account's main purse holds `balance` and
+// whose key management threshold is `key_management_threshold`.
+fn purse_fixture(balance: u64, key_management_threshold: u8) -> TestFixture {
+    let default: TestFixture = Default::default();
+    let account = Account::new(
+        [0u8; 32],
+        0,
+        BTreeMap::new(),
+        mock_purse_id(),
+        AssociatedKeys::new(PublicKey::new([0u8; 32]), Weight::new(1)),
+        ActionThresholds::new(Weight::new(1), Weight::new(key_management_threshold)),
+    );
+    let key = Key::Account(default.addr);
+    let mut tc = mock_tc(key, &account);
+    tc.write(
+        mint::balance_key(mock_purse_id()),
+        Value::UInt512(U512::from(balance)),
+    );
+    let env = MockEnv::new(key, BTreeMap::new(), HashSet::new(), account, 0);
+    let memory = env.memory_manager();
+    TestFixture::new(default.addr, env, memory, Rc::new(RefCell::new(tc)))
+}
+
+#[test]
+fn rotate_main_purse_moves_balance() {
+    let mut test_fixture = purse_fixture(10, 1);
+    let dest_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        runtime
+            .rotate_main_purse(dest_ptr)
+            .expect("rotate_main_purse should succeed");
+    }
+    let bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, UREF_SIZE)
+        .expect("Reading from WasmMemory should work");
+    let new_purse: PurseId = deserialize(&bytes).expect("Deserializing should work");
+    assert_ne!(new_purse, mock_purse_id());
+
+    let mut tc = test_fixture.tc.borrow_mut();
+    let account = match tc.get(&Key::Account(test_fixture.addr)).unwrap() {
+        Some(Value::Account(account)) => account,
+        other => panic!("Expected account, got {:?}", other),
+    };
+    assert_eq!(account.purse_id(), new_purse);
+    assert_eq!(mint::balance(&mut *tc, new_purse).unwrap(), U512::from(10));
+    assert_eq!(
+        mint::balance(&mut *tc, mock_purse_id()).unwrap(),
+        U512::zero()
+    );
+}
+
+// Transfer records written by the runtimes of the fixture.
+fn transfer_records(test_fixture: &TestFixture) -> Vec<Transfer> {
+    let mut tc = test_fixture.tc.borrow_mut();
+    let addrs = transfer::transfer_addrs(&tc.effect());
+    addrs
+        .into_iter()
+        .map(|addr| {
+            transfer::read_transfer(&mut *tc, addr)
+                .expect("Transfer should be read.")
+                .expect("Transfer should be recorded.")
+        })
+        .collect()
+}
+
+// Makes two transfers of `amount` from a purse holding `balance` to the
+// account owned by `target` and returns their result codes.
+fn transfer_twice_to_account(
+    balance: u64,
+    target: PublicKey,
+    amount: u64,
+) -> (i32, i32, TestFixture) {
+    let mut test_fixture = purse_fixture(balance, 1);
+    let (target_ptr, target_size) = test_fixture.memory.write(target);
+    let (amount_ptr, amount_size) = test_fixture.memory.write(U512::from(amount));
+    let results = {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        let mut transfer = || {
+            runtime
+                .transfer_to_account(
+                    target_ptr,
+                    target_size as u32,
+                    amount_ptr,
+                    amount_size as u32,
+                )
+                .expect("transfer_to_account should not trap")
+        };
+        (transfer(), transfer())
+    };
+    (results.0, results.1, test_fixture)
+}
+
+#[test]
+fn transfer_to_account_creates_target_account() {
+    let target = PublicKey::new([3u8; 32]);
+    let (first, second, test_fixture) = transfer_twice_to_account(10, target, 4);
+    assert_eq!(first, i32::from(TransferredTo::NewAccount));
+    assert_eq!(second, i32::from(TransferredTo::ExistingAccount));
+
+    let mut tc = test_fixture.tc.borrow_mut();
+    let target_addr = execution_engine::execution::account_address(target);
+    let target_key = Key::Account(target_addr);
+    let account = match tc.get(&target_key).unwrap() {
+        Some(Value::Account(account)) => account,
+        other => panic!("Expected account, got {:?}", other),
+    };
+    assert_eq!(account.public_key(), target);
+    assert_eq!(
+        mint::balance(&mut *tc, account.purse_id()).unwrap(),
+        U512::from(8)
+    );
+    assert_eq!(
+        mint::balance(&mut *tc, mock_purse_id()).unwrap(),
+        U512::from(2)
+    );
+    drop(tc);
+
+    let records = transfer_records(&test_fixture);
+    assert_eq!(records.len(), 2);
+    for record in &records {
+        assert_eq!(record.to, Some(target_addr));
+        assert_eq!(record.target.addr(), account.purse_id().addr());
+        assert_eq!(record.amount, U512::from(4));
+    }
+}
+
+#[test]
+fn transfer_to_account_with_insufficient_funds() {
+    let target = PublicKey::new([3u8; 32]);
+    let (first, second, test_fixture) = transfer_twice_to_account(6, target, 4);
+    assert_eq!(first, i32::from(TransferredTo::NewAccount));
+    assert_eq!(second, i32::from(TransferError::InsufficientFunds));
+    assert_eq!(
+        mint::balance(&mut *test_fixture.tc.borrow_mut(), mock_purse_id()).unwrap(),
+        U512::from(2)
+    );
+}
+
+// Transfers `amount` from the account's main purse holding 10 tokens to
+// an empty purse, presenting the purses with the given rights.
+fn transfer_between_purses(
+    source_rights: AccessRights,
+    target_rights: AccessRights,
+    amount: u64,
+) -> (Result<i32, wasmi::Trap>, TestFixture) {
+    let mut test_fixture = purse_fixture(10, 1);
+    let target_purse = mint::create_purse(&mut test_fixture.tc.borrow_mut(), [7u8; 32]);
+    test_fixture.env.known_urefs.insert(mock_purse_id().into());
+    test_fixture.env.known_urefs.insert(target_purse.into());
+    let source = PurseId::new(mock_purse_id().addr(), source_rights);
+    let target = PurseId::new(target_purse.addr(), target_rights);
+    let (source_ptr, source_size) = test_fixture.memory.write(source);
+    let (target_ptr, target_size) = test_fixture.memory.write(target);
+    let (amount_ptr, amount_size) = test_fixture.memory.write(U512::from(amount));
+    let result = {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        runtime.transfer_from_purse_to_purse(
+            source_ptr,
+            source_size as u32,
+            target_ptr,
+            target_size as u32,
+            amount_ptr,
+            amount_size as u32,
+        )
+    };
+    (result, test_fixture)
+}
+
+#[test]
+fn transfer_from_purse_to_purse_moves_tokens() {
+    let (result, test_fixture) =
+        transfer_between_purses(AccessRights::READ_ADD_WRITE, AccessRights::ADD, 4);
+    assert_eq!(result.expect("transfer should not trap"), 0);
+    let mut tc = test_fixture.tc.borrow_mut();
+    let target_purse = PurseId::new([7u8; 32], AccessRights::READ_ADD_WRITE);
+    assert_eq!(
+        mint::balance(&mut *tc, mock_purse_id()).unwrap(),
+        U512::from(6)
+    );
+    assert_eq!(mint::balance(&mut *tc, target_purse).unwrap(), U512::from(4));
+    drop(tc);
+
+    let records = transfer_records(&test_fixture);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].to, None);
+    assert_eq!(records[0].source, mock_purse_id());
+    assert_eq!(records[0].target.addr(), [7u8; 32]);
+    assert_eq!(records[0].amount, U512::from(4));
+}
+
+#[test]
+fn transfer_from_purse_to_purse_with_insufficient_funds() {
+    let (result, test_fixture) =
+        transfer_between_purses(AccessRights::READ_ADD_WRITE, AccessRights::ADD, 11);
+    assert_eq!(
+        result.expect("transfer should not trap"),
+        i32::from(TransferError::InsufficientFunds)
+    );
+    assert!(transfer_records(&test_fixture).is_empty());
+}
+
+#[test]
+fn transfer_from_purse_to_purse_checks_rights() {
+    let (result, _) = transfer_between_purses(AccessRights::READ_ADD, AccessRights::ADD, 4);
+    assert_error_contains(result, "InvalidAccess { required: WRITE }");
+    let (result, _) = transfer_between_purses(AccessRights::READ_ADD_WRITE, AccessRights::READ, 4);
+    assert_error_contains(result, "InvalidAccess { required: ADD }");
+}
+
+fn blake2b(data: &[u8]) -> (Result<(), wasmi::Trap>, Vec<u8>) {
+    let mut test_fixture: TestFixture = Default::default();
+    let (in_ptr, _) = test_fixture
+        .memory
+        .write_raw(data.to_vec())
+        .expect("Writing to Wasm memory should work.");
+    let out_ptr = test_fixture.memory.alloc(32);
+    let result = {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        runtime.blake2b(in_ptr, data.len() as u32, out_ptr)
+    };
+    let digest = test_fixture
+        .memory
+        .read_bytes(out_ptr, 32)
+        .expect("Reading from WasmMemory should work");
+    (result, digest)
+}
+
+#[test]
+fn blake2b_writes_digest() {
+    let data = b"casperlabs";
+    let (result, digest) = blake2b(data);
+    result.expect("blake2b should succeed");
+    assert_eq!(digest, Blake2bHash::new(data).to_vec());
+}
+
+#[test]
+fn blake2b_is_charged_per_byte() {
+    let hash = |size: u32| {
+        let wat = format!(
+            r#"
+            (module
+                (import "env" "blake2b" (func $blake2b (param i32 i32 i32)))
+                (memory 1)
+                (func (export "call")
+                    (call $blake2b (i32.const 0) (i32.const {}) (i32.const 1024))
+                )
+            )
+            "#,
+            size
+        );
+        run_wat_deploy(&wat, EngineConfig::new()).result
+    };
+    assert!(hash(7).is_ok());
+    // Deploys are run with 1000 units of gas, which the default cost of
+    // hashing 500 bytes exceeds.
+    let result = hash(500);
+    assert!(format!("{:?}", result).contains("GasLimit"));
+}
+
+fn verify_ed25519(message: &[u8], signature: [u8; 64], public_key: [u8; 32]) -> i32 {
+    let mut test_fixture: TestFixture = Default::default();
+    let (message_ptr, _) = test_fixture
+        .memory
+        .write_raw(message.to_vec())
+        .expect("Writing to Wasm memory should work.");
+    let (signature_ptr, _) = test_fixture
+        .memory
+        .write_raw(signature.to_vec())
+        .expect("Writing to Wasm memory should work.");
+    let (public_key_ptr, _) = test_fixture
+        .memory
+        .write_raw(public_key.to_vec())
+        .expect("Writing to Wasm memory should work.");
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    runtime
+        .verify_ed25519(message_ptr, message.len() as u32, signature_ptr, public_key_ptr)
+        .expect("verify_ed25519 should not trap")
+}
+
+#[test]
+fn verify_ed25519_checks_signature() {
+    let secret = ed25519_dalek::SecretKey::from_bytes(&[7u8; 32]).expect("valid secret key");
+    let public: ed25519_dalek::PublicKey = (&secret).into();
+    let keypair = ed25519_dalek::Keypair { secret, public };
+    let message = b"transfer 10 motes";
+    let signature = keypair.sign(message).to_bytes();
+    let public_key = public.to_bytes();
+
+    assert_eq!(verify_ed25519(message, signature, public_key), 1);
+    assert_eq!(verify_ed25519(b"transfer 99 motes", signature, public_key), 0);
+    assert_eq!(verify_ed25519(message, [0u8; 64], public_key), 0);
+}
+
+// Runs `f` with a runtime of its own, so that gas used by earlier calls
+// doesn't count against the limit of the mocked context.
+fn with_fresh_runtime<T, F>(test_fixture: &mut TestFixture, f: F) -> T
+where
+    F: FnOnce(&mut Runtime<InMemGS<Key, Value>>) -> T,
+{
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    f(&mut runtime)
+}
+
+#[test]
+fn secp256k1_verify_and_recover() {
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&Blake2bHash::new(b"bridge 10 motes").to_vec());
+    let secret_key = secp256k1::SecretKey::parse(&[7u8; 32]).expect("valid secret key");
+    let public_key = secp256k1::PublicKey::from_secret_key(&secret_key).serialize();
+    let (signature, recovery_id) =
+        secp256k1::sign(&secp256k1::Message::parse(&digest), &secret_key);
+
+    let mut test_fixture: TestFixture = Default::default();
+    let (digest_ptr, signature_ptr, public_key_ptr, other_public_key_ptr) = {
+        let mut write = |bytes: &[u8]| {
+            test_fixture
+                .memory
+                .write_raw(bytes.to_vec())
+                .expect("Writing to Wasm memory should work.")
+                .0
+        };
+        (
+            write(&digest),
+            write(&signature.serialize()),
+            write(&public_key),
+            write(&[4u8; 65]),
+        )
+    };
+    let dest_ptr = test_fixture.memory.alloc(65);
+
+    for (key_ptr, expected) in vec![(public_key_ptr, 1), (other_public_key_ptr, 0)] {
+        let result = with_fresh_runtime(&mut test_fixture, |runtime| {
+            runtime.verify_secp256k1(digest_ptr, signature_ptr, key_ptr)
+        });
+        assert_eq!(result.expect("verify_secp256k1 should not trap"), expected);
+    }
+    let recovery_id = u32::from(recovery_id.serialize());
+    let result = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime.recover_secp256k1(digest_ptr, signature_ptr, recovery_id, dest_ptr)
+    });
+    assert_eq!(result.expect("recover_secp256k1 should not trap"), 1);
+    let recovered = test_fixture
+        .memory
+        .read_bytes(dest_ptr, 65)
+        .expect("Reading from WasmMemory should work");
+    assert_eq!(recovered, public_key.to_vec());
+}
+
+#[test]
+fn print_depends_on_contract_debug() {
+    let mut test_fixture: TestFixture = Default::default();
+    let message = wasm_write(&mut test_fixture.memory, String::from("checkpoint"));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    // Without contract debugging the message isn't even read.
+    runtime
+        .print(u32::max_value(), 10)
+        .expect("print should be a no-op");
+
+    let mut runtime = runtime.with_contract_debug(true);
+    runtime
+        .print(message.0, message.1 as u32)
+        .expect("print should succeed");
+    assert!(runtime.print(u32::max_value(), 10).is_err());
+}
+
+#[test]
+fn named_args_are_loaded_by_name() {
+    let mut test_fixture: TestFixture = Default::default();
+    let amount = wasm_write(&mut test_fixture.memory, String::from("amount"));
+    let target = wasm_write(&mut test_fixture.memory, String::from("target"));
+    let mut named_args = RuntimeArgs::new();
+    named_args
+        .insert("amount", &U512::from(10))
+        .expect("should serialize");
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture
+        .env
+        .runtime(&mut tc_borrowed, mock_module())
+        .with_named_args(named_args);
+    let expected_size = U512::from(10).to_bytes().expect("should serialize").len();
+    assert_eq!(
+        runtime
+            .load_named_arg(amount.0, amount.1 as u32)
+            .expect("load_named_arg should succeed"),
+        Some(expected_size)
+    );
+    assert_eq!(
+        runtime
+            .load_named_arg(target.0, target.1 as u32)
+            .expect("load_named_arg should succeed"),
+        None
+    );
+    // Named arguments don't take up positions.
+    assert_eq!(runtime.load_arg(0), None);
+}
+
+#[test]
+fn emitted_events_are_part_of_effects() {
+    let mut test_fixture: TestFixture = Default::default();
+    let topic = wasm_write(&mut test_fixture.memory, String::from("transfer"));
+    let data = wasm_write(&mut test_fixture.memory, Value::Int32(10));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    for _ in 0..MAX_EVENTS_PER_DEPLOY {
+        runtime
+            .emit_event(topic.0, topic.1 as u32, data.0, data.1 as u32)
+            .expect("emit_event should succeed");
+    }
+    let events = runtime.effect().2;
+    assert_eq!(events.len(), MAX_EVENTS_PER_DEPLOY);
+    assert_eq!(
+        events[0],
+        Event {
+            topic: String::from("transfer"),
+            data: Value::Int32(10)
+        }
+    );
+
+    let result = runtime.emit_event(topic.0, topic.1 as u32, data.0, data.1 as u32);
+    assert_error_contains(result, "TooManyEvents");
+}
+
+#[test]
+fn emit_event_rejects_large_events() {
+    let mut test_fixture: TestFixture = Default::default();
+    let topic = wasm_write(&mut test_fixture.memory, String::from("blob"));
+    let data = wasm_write(
+        &mut test_fixture.memory,
+        Value::ByteArray(vec![0u8; MAX_EVENT_SIZE]),
+    );
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    let result = runtime.emit_event(topic.0, topic.1 as u32, data.0, data.1 as u32);
+    assert_error_contains(result, "EventTooLarge");
+}
+
+// Calls `random_bytes` twice in the `phase` of the deploy with `deploy_hash`.
+fn random_bytes(deploy_hash: [u8; 32], phase: Phase) -> (Vec<u8>, Vec<u8>) {
+    let mut test_fixture: TestFixture = Default::default();
+    let first_ptr = test_fixture.memory.alloc(32);
+    let second_ptr = test_fixture.memory.alloc(32);
+    {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let env = &mut test_fixture.env;
+        let authorization_keys = once(env.deploy_account.public_key()).collect();
+        let context = RuntimeContext::new(
+            &mut env.uref_lookup,
+            &env.deploy_account,
+            env.base_key,
+            authorization_keys,
+            Gas::from(1000),
+            phase,
+        )
+        .with_deploy_hash(deploy_hash);
+        let mut runtime = Runtime::new(
+            Vec::new(),
+            env.memory.clone(),
+            &mut tc_borrowed,
+            mock_module(),
+            context,
+        );
+        runtime
+            .random_bytes(first_ptr)
+            .expect("random_bytes should succeed");
+        runtime
+            .random_bytes(second_ptr)
+            .expect("random_bytes should succeed");
+    }
+    let read = |ptr| {
+        test_fixture
+            .memory
+            .read_bytes(ptr, 32)
+            .expect("Reading from WasmMemory should work")
+    };
+    (read(first_ptr), read(second_ptr))
+}
+
+fn block_height_seen(block_height: u64) -> u64 {
+    let mut test_fixture: TestFixture = Default::default();
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let env = &mut test_fixture.env;
+    let context = mock_context(
+        &mut env.uref_lookup,
+        &mut env.known_urefs,
+        &env.deploy_account,
+        env.base_key,
+    )
+    .with_block_height(block_height);
+    let runtime = Runtime::new(
+        Vec::new(),
+        env.memory.clone(),
+        &mut tc_borrowed,
+        mock_module(),
+        context,
+    );
+    runtime.get_block_height()
+}
+
+#[test]
+fn block_height_is_the_same_across_executions() {
+    assert_eq!(block_height_seen(42), 42);
+    assert_eq!(block_height_seen(42), block_height_seen(42));
+}
+
+#[test]
+fn random_bytes_are_deterministic() {
+    assert_eq!(
+        random_bytes([1u8; 32], Phase::Session),
+        random_bytes([1u8; 32], Phase::Session)
+    );
+}
+
+#[test]
+fn random_bytes_differ_between_calls_deploys_and_phases() {
+    let (first, second) = random_bytes([1u8; 32], Phase::Session);
+    let (other_deploy_first, _) = random_bytes([2u8; 32], Phase::Session);
+    let (payment_first, _) = random_bytes([1u8; 32], Phase::Payment);
+    assert_ne!(first, second);
+    assert_ne!(first, other_deploy_first);
+    assert_ne!(first, payment_first);
+}
+
+#[test]
+fn get_main_purse_follows_rotation() {
+    let mut test_fixture = purse_fixture(10, 1);
+    let before_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let rotated_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let after_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        runtime
+            .get_main_purse(before_ptr)
+            .expect("get_main_purse should succeed");
+        runtime
+            .rotate_main_purse(rotated_ptr)
+            .expect("rotate_main_purse should succeed");
+        runtime
+            .get_main_purse(after_ptr)
+            .expect("get_main_purse should succeed");
+    }
+    let read_purse = |ptr| {
+        let bytes = test_fixture
+            .memory
+            .read_bytes(ptr, UREF_SIZE)
+            .expect("Reading from WasmMemory should work");
+        deserialize::<PurseId>(&bytes).expect("Deserializing should work")
+    };
+    assert_eq!(read_purse(before_ptr), mock_purse_id());
+    assert_eq!(read_purse(after_ptr), read_purse(rotated_ptr));
+}
+
+#[test]
+fn stored_contracts_cannot_get_main_purse() {
+    let mut test_fixture: TestFixture = Default::default();
+    let dest_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    test_fixture.env.base_key = Key::Hash([1u8; 32]);
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    assert_error_contains(runtime.get_main_purse(dest_ptr), "InvalidAccess");
+}
+
+#[test]
+fn create_purse_makes_empty_purse_known_to_context() {
+    let mut test_fixture = purse_fixture(10, 1);
+    test_fixture.env.known_urefs.insert(mock_purse_id().into());
+    let dest_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let (source_ptr, source_size) = test_fixture.memory.write(mock_purse_id());
+    let (amount_ptr, amount_size) = test_fixture.memory.write(U512::from(3));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let purse = {
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        runtime
+            .create_purse(dest_ptr)
+            .expect("create_purse should succeed");
+        // The new purse can be used right away.
+        let result = runtime.transfer_from_purse_to_purse(
+            source_ptr,
+            source_size as u32,
+            dest_ptr,
+            UREF_SIZE as u32,
+            amount_ptr,
+            amount_size as u32,
+        );
+        assert_eq!(result.expect("transfer should not trap"), 0);
+        let purse_bytes = test_fixture
+            .memory
+            .read_bytes(dest_ptr, UREF_SIZE)
+            .expect("Reading from WasmMemory should work");
+        deserialize::<PurseId>(&purse_bytes).expect("Deserializing should work")
+    };
+    assert_eq!(purse.access_rights(), AccessRights::READ_ADD_WRITE);
+    assert_eq!(
+        mint::balance(&mut *tc_borrowed, purse).unwrap(),
+        U512::from(3)
+    );
+}
+
+#[test]
+fn rotate_main_purse_requires_key_management_weight() {
+    let mut test_fixture = purse_fixture(10, 2);
+    let dest_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    let result = runtime.rotate_main_purse(dest_ptr);
+    assert_error_contains(result, "KeyManagementThreshold");
+}
+
+#[test]
+fn add_associated_key_requires_key_management_weight() {
+    let mut test_fixture = purse_fixture(0, 2);
+    let (key_ptr, key_size) = test_fixture.memory.write(PublicKey::new([1u8; 32]));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    let result = runtime
+        .add_associated_key(key_ptr, key_size as u32, 1)
+        .expect("add_associated_key should not trap");
+    assert_eq!(result, i32::from(KeyManagementError::PermissionDenied));
+}
+
+#[test]
+fn add_associated_key_rejects_duplicates() {
+    let mut test_fixture = purse_fixture(0, 1);
+    let (key_ptr, key_size) = test_fixture.memory.write(PublicKey::new([0u8; 32]));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    let result = runtime
+        .add_associated_key(key_ptr, key_size as u32, 1)
+        .expect("add_associated_key should not trap");
+    assert_eq!(result, i32::from(KeyManagementError::DuplicateKey));
+}
+
+#[test]
+fn add_associated_key_respects_the_configured_limit() {
+    let mut test_fixture = purse_fixture(0, 1);
+    let (key_ptr, key_size) = test_fixture.memory.write(PublicKey::new([1u8; 32]));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture
+        .env
+        .runtime(&mut tc_borrowed, mock_module())
+        .with_key_limits(KeyLimits::default().with_max_keys(1));
+    let result = runtime
+        .add_associated_key(key_ptr, key_size as u32, 1)
+        .expect("add_associated_key should not trap");
+    assert_eq!(result, i32::from(KeyManagementError::MaxKeysLimit));
+}
+
+// Creates a contract package and adds a stored contract as its version,
+// presenting the package's access key with `access_rights`.
+// Returns the result of `add_contract_version` and the package's key.
+fn add_contract_version_with_rights(
+    access_rights: AccessRights,
+) -> (Result<u32, wasmi::Trap>, Key, TestFixture) {
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    let contract = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION);
+    test_fixture
+        .tc
+        .borrow_mut()
+        .write(contract_key, Value::Contract(contract));
+    let package_hash_ptr = test_fixture.memory.alloc(32);
+    let access_key_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let (result, package_key) = {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        runtime
+            .create_contract_package(package_hash_ptr, access_key_ptr)
+            .expect("create_contract_package should succeed");
+
+        let package_key = read_contract_hash(&test_fixture.memory, package_hash_ptr);
+        let access_key_bytes = test_fixture
+            .memory
+            .read_bytes(access_key_ptr, UREF_SIZE)
+            .expect("Reading from WasmMemory should work");
+        let access_key = match deserialize(&access_key_bytes).expect("Deserializing should work") {
+            Key::URef(addr, _) => Key::URef(addr, access_rights),
+            other => panic!("Access key should be a URef, got {:?}", other),
+        };
+        let package = wasm_write(&mut test_fixture.memory, package_key);
+        let access = wasm_write(&mut test_fixture.memory, access_key);
+        let contract = wasm_write(&mut test_fixture.memory, contract_key);
+        let result = runtime.add_contract_version(
+            package.0,
+            package.1 as u32,
+            access.0,
+            access.1 as u32,
+            1,
+            contract.0,
+            contract.1 as u32,
+        );
+        (result, package_key)
+    };
+    (result, package_key, test_fixture)
+}
+
+#[test]
+fn add_contract_version_appends_to_package() {
+    let (result, package_key, test_fixture) =
+        add_contract_version_with_rights(AccessRights::READ_ADD_WRITE);
+    assert_eq!(result.expect("add_contract_version should succeed"), 1);
+
+    let package = match test_fixture.tc.borrow_mut().get(&package_key).unwrap() {
+        Some(Value::ContractPackage(package)) => package,
+        other => panic!("Expected contract package, got {:?}", other),
+    };
+    assert_eq!(package.versions(), &[ContractVersion::new(1, Key::Hash([7u8; 32]))]);
+    assert_eq!(package.latest(), Some(Key::Hash([7u8; 32])));
+}
+
+#[test]
+fn add_contract_version_requires_writeable_access_key() {
+    let (result, _, _) = add_contract_version_with_rights(AccessRights::READ);
+    assert_invalid_access(result);
+}
+
+#[test]
+fn call_contract_package_without_versions() {
+    let mut test_fixture: TestFixture = Default::default();
+    let package_key = Key::Hash([8u8; 32]);
+    let access_key = Key::URef([9u8; 32], AccessRights::READ_ADD_WRITE);
+    test_fixture.tc.borrow_mut().write(
+        package_key,
+        Value::ContractPackage(ContractPackage::new(access_key)),
+    );
+    let wasm_key = wasm_write(&mut test_fixture.memory, package_key);
+    let empty_vec: Vec<u8> = Vec::new();
+    let wasm_args = wasm_write(&mut test_fixture.memory, empty_vec);
+    let empty_urefs: Vec<Key> = Vec::new();
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, empty_urefs);
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    let result = runtime.call_contract(
+        wasm_key.0,
+        wasm_key.1,
+        wasm_args.0,
+        wasm_args.1,
+        wasm_urefs.0,
+        wasm_urefs.1,
+    );
+    match result {
+        Err(execution_engine::execution::Error::ContractVersionNotFound(key, selector)) => {
+            assert_eq!(key, package_key);
+            assert_eq!(selector, VersionSelector::Latest);
+        }
+        Err(error) => panic!("Test failed with unexpected error {:?}", error),
+        Ok(_) => panic!("Test should have failed but didn't"),
+    }
+}
+
+// Calls a package with three versions (two under major 1, one under major 2)
+// whose contracts are missing from the global state, so the key reported by
+// `KeyNotFound` tells which version `selector` resolved to.
+fn call_versioned_contract_package(
+    selector: VersionSelector,
+) -> Result<usize, execution_engine::execution::Error> {
+    let mut test_fixture: TestFixture = Default::default();
+    let package_key = Key::Hash([8u8; 32]);
+    let mut package = ContractPackage::new(Key::URef([9u8; 32], AccessRights::READ_ADD_WRITE));
+    package.add_version(1, Key::Hash([1u8; 32]));
+    package.add_version(1, Key::Hash([2u8; 32]));
+    package.add_version(2, Key::Hash([3u8; 32]));
+    test_fixture
+        .tc
+        .borrow_mut()
+        .write(package_key, Value::ContractPackage(package));
+    let wasm_key = wasm_write(&mut test_fixture.memory, package_key);
+    let wasm_selector = wasm_write(&mut test_fixture.memory, selector);
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<u8>::new());
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    runtime.call_versioned_contract(
+        wasm_key.0,
+        wasm_key.1,
+        wasm_selector.0,
+        wasm_selector.1,
+        wasm_args.0,
+        wasm_args.1,
+        wasm_urefs.0,
+        wasm_urefs.1,
+    )
+}
+
+#[test]
+fn call_versioned_contract_resolves_selector() {
+    let expected = vec![
+        (VersionSelector::Latest, Key::Hash([3u8; 32])),
+        (VersionSelector::Exact(1), Key::Hash([1u8; 32])),
+        (VersionSelector::Major(1), Key::Hash([2u8; 32])),
+        (VersionSelector::Major(2), Key::Hash([3u8; 32])),
+    ];
+    for (selector, contract_key) in expected {
+        match call_versioned_contract_package(selector) {
+            Err(execution_engine::execution::Error::KeyNotFound(key)) => {
+                assert_eq!(key, contract_key)
+            }
+            Err(error) => panic!("Test failed with unexpected error {:?}", error),
+            Ok(_) => panic!("Test should have failed but didn't"),
+        }
+    }
+}
+
+#[test]
+fn call_versioned_contract_with_unknown_version() {
+    for selector in vec![VersionSelector::Exact(4), VersionSelector::Major(3)] {
+        match call_versioned_contract_package(selector) {
+            Err(execution_engine::execution::Error::ContractVersionNotFound(key, found)) => {
+                assert_eq!(key, Key::Hash([8u8; 32]));
+                assert_eq!(found, selector);
+            }
+            Err(error) => panic!("Test failed with unexpected error {:?}", error),
+            Ok(_) => panic!("Test should have failed but didn't"),
+        }
+    }
+}
+
+#[test]
+fn contract_user_groups_restrict_callers() {
+    let mut test_fixture: TestFixture = Default::default();
+    // The contract isn't valid Wasm, so getting past the group check is as far
+    // as a call can go.
+    let contract_key = Key::Hash([1u8; 32]);
+    let abi = Abi::new(vec![
+        EntryPoint::new("mint".to_string(), vec![], AbiType::Unit)
+            .with_groups(vec!["minters".to_string()]),
+        EntryPoint::new("balance".to_string(), vec![], AbiType::UInt512),
+    ]);
+    let contract = Contract::new(vec![], BTreeMap::new(), PROTOCOL_VERSION).with_abi(abi);
+    test_fixture
+        .tc
+        .borrow_mut()
+        .write(contract_key, Value::Contract(contract));
+    let package_hash_ptr = test_fixture.memory.alloc(32);
+    let access_key_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let group_key_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let group_name = wasm_write(&mut test_fixture.memory, "minters");
+    let version_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let mint_args = wasm_write(&mut test_fixture.memory, vec!["mint".to_bytes().unwrap()]);
+    let balance_args = wasm_write(&mut test_fixture.memory, vec!["balance".to_bytes().unwrap()]);
+    let no_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    runtime
+        .create_contract_package(package_hash_ptr, access_key_ptr)
+        .expect("create_contract_package should succeed");
+    let package_key = read_contract_hash(&test_fixture.memory, package_hash_ptr);
+    let package = wasm_write(&mut test_fixture.memory, package_key);
+    let access_key_bytes = test_fixture
+        .memory
+        .read_bytes(access_key_ptr, UREF_SIZE)
+        .expect("Reading from WasmMemory should work");
+    runtime
+        .add_contract_version(
+            package.0,
+            package.1 as u32,
+            access_key_ptr,
+            access_key_bytes.len() as u32,
+            1,
+            version_key.0,
+            version_key.1 as u32,
+        )
+        .expect("add_contract_version should succeed");
+    runtime
+        .create_contract_user_group(
+            package.0,
+            package.1 as u32,
+            access_key_ptr,
+            access_key_bytes.len() as u32,
+            group_name.0,
+            group_name.1 as u32,
+            group_key_ptr,
+        )
+        .expect("create_contract_user_group should succeed");
+
+    let result = runtime.call_contract(
+        package.0,
+        package.1,
+        mint_args.0,
+        mint_args.1,
+        no_urefs.0,
+        no_urefs.1,
+    );
+    match result {
+        Err(execution_engine::execution::Error::UnauthorizedCall(key)) => {
+            assert_eq!(key, package_key)
+        }
+        Err(error) => panic!("Test failed with unexpected error {:?}", error),
+        Ok(_) => panic!("Test should have failed but didn't"),
+    }
+
+    // Entry points without groups can be called by anyone.
+    let result = runtime.call_contract(
+        package.0,
+        package.1,
+        balance_args.0,
+        balance_args.1,
+        no_urefs.0,
+        no_urefs.1,
+    );
+    match result {
+        Err(execution_engine::execution::Error::ParityWasm(_)) => (),
+        Err(error) => panic!("Test failed with unexpected error {:?}", error),
+        Ok(_) => panic!("Test should have failed but didn't"),
+    }
+
+    let group_key_bytes = test_fixture
+        .memory
+        .read_bytes(group_key_ptr, UREF_SIZE)
+        .expect("Reading from WasmMemory should work");
+    let group_key: Key = deserialize(&group_key_bytes).expect("Deserializing should work");
+    let group_urefs = wasm_write(&mut test_fixture.memory, vec![group_key]);
+    let result = runtime.call_contract(
+        package.0,
+        package.1,
+        mint_args.0,
+        mint_args.1,
+        group_urefs.0,
+        group_urefs.1,
+    );
+    match result {
+        Err(execution_engine::execution::Error::ParityWasm(_)) => (),
+        Err(error) => panic!("Test failed with unexpected error {:?}", error),
+        Ok(_) => panic!("Test should have failed but didn't"),
+    }
+}
+
+#[test]
+fn store_contract_with_abi() {
+    let mut test_fixture: TestFixture = Default::default();
+    let wasm_module = create_wasm_module();
+    let urefs = urefs_map(std::iter::empty());
+    let abi = Abi::new(vec![EntryPoint::new(
+        "call".to_string(),
+        vec![Arg::new("amount".to_string(), AbiType::UInt512)],
+        AbiType::Unit,
+    )]);
+    let expected_contract = match contract_bytes_from_wat(wasm_module.clone(), urefs.clone()) {
+        Value::Contract(contract) => contract.with_abi(abi.clone()),
+        other => panic!("Expected contract, got {:?}", other),
+    };
+
+    let hash = {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module);
+        let store_result = test_fixture
+            .memory
+            .store_contract(&wasm_module.func_name, urefs);
+        let wasm_abi = wasm_write(&mut test_fixture.memory, abi);
+        runtime
+            .store_function_with_abi(
+                store_result.contract_ptr,
+                store_result.contract_len as u32,
+                store_result.urefs_ptr,
+                store_result.urefs_len as u32,
+                wasm_abi.0,
+                wasm_abi.1 as u32,
+                store_result.hash_ptr,
+            )
+            .expect("store_function_with_abi should succeed");
+        read_contract_hash(&test_fixture.memory, store_result.hash_ptr)
+    };
+
+    let transforms = test_fixture.tc.borrow().effect().1;
+    let effect = transforms.get(&hash).unwrap();
+    assert_eq!(effect, &Transform::Write(Value::Contract(expected_contract)));
+}
+
+#[test]
+fn store_contract_with_too_large_abi() {
+    let mut test_fixture: TestFixture = Default::default();
+    let wasm_module = create_wasm_module();
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module);
+    let store_result = test_fixture
+        .memory
+        .store_contract(&wasm_module.func_name, urefs_map(std::iter::empty()));
+    let result = runtime.store_function_with_abi(
+        store_result.contract_ptr,
+        store_result.contract_len as u32,
+        store_result.urefs_ptr,
+        store_result.urefs_len as u32,
+        0,
+        (MAX_ABI_SIZE + 1) as u32,
+        store_result.hash_ptr,
+    );
+    assert_error_contains(result, "AbiTooLarge");
+}
+
+#[test]
+fn store_contract_at_hash_is_content_addressed() {
+    let mut test_fixture: TestFixture = Default::default();
+    let wasm_module = create_wasm_module();
+    let urefs = urefs_map(std::iter::empty());
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module.clone());
+
+    let mut hashes = Vec::new();
+    for _ in 0..2 {
+        let store_result = test_fixture
+            .memory
+            .store_contract(&wasm_module.func_name, urefs.clone());
+        runtime
+            .store_function_at_hash(
+                store_result.contract_ptr,
+                store_result.contract_len as u32,
+                store_result.urefs_ptr,
+                store_result.urefs_len as u32,
+                store_result.hash_ptr,
+            )
+            .expect("store_function_at_hash should succeed");
+        hashes.push(read_contract_hash(&test_fixture.memory, store_result.hash_ptr));
+    }
+    assert_eq!(hashes[0], hashes[1]);
+
+    let store_result = test_fixture
+        .memory
+        .store_contract(&wasm_module.func_name, urefs);
+    runtime
+        .store_function(
+            store_result.contract_ptr,
+            store_result.contract_len as u32,
+            store_result.urefs_ptr,
+            store_result.urefs_len as u32,
+            store_result.hash_ptr,
+        )
+        .expect("store_function should succeed");
+    let deploy_derived = read_contract_hash(&test_fixture.memory, store_result.hash_ptr);
+    assert_ne!(hashes[0], deploy_derived);
+}
+
+#[test]
+fn locked_contract_package_cannot_be_upgraded() {
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    let contract = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION);
+    test_fixture
+        .tc
+        .borrow_mut()
+        .write(contract_key, Value::Contract(contract));
+    let package_hash_ptr = test_fixture.memory.alloc(32);
+    let access_key_ptr = test_fixture.memory.alloc(UREF_SIZE);
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    runtime
+        .create_contract_package(package_hash_ptr, access_key_ptr)
+        .expect("create_contract_package should succeed");
+    let package_key = read_contract_hash(&test_fixture.memory, package_hash_ptr);
+    let package = wasm_write(&mut test_fixture.memory, package_key);
+    let contract = wasm_write(&mut test_fixture.memory, contract_key);
+
+    runtime
+        .lock_contract_package(package.0, package.1 as u32, access_key_ptr, UREF_SIZE as u32)
+        .expect("lock_contract_package should succeed");
+
+    let result = runtime.add_contract_version(
+        package.0,
+        package.1 as u32,
+        access_key_ptr,
+        UREF_SIZE as u32,
+        1,
+        contract.0,
+        contract.1 as u32,
+    );
+    assert_error_contains(result, "LockedContractPackage");
+
+    let result = runtime.lock_contract_package(
+        package.0,
+        package.1 as u32,
+        access_key_ptr,
+        UREF_SIZE as u32,
+    );
+    assert_error_contains(result, "LockedContractPackage");
+}
+
+#[test]
+fn disabled_contract_version_cannot_be_called() {
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    let contract = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION);
+    test_fixture
+        .tc
+        .borrow_mut()
+        .write(contract_key, Value::Contract(contract));
+    let package_hash_ptr = test_fixture.memory.alloc(32);
+    let access_key_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let selector = wasm_write(&mut test_fixture.memory, VersionSelector::Exact(1));
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<u8>::new());
+    let no_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    runtime
+        .create_contract_package(package_hash_ptr, access_key_ptr)
+        .expect("create_contract_package should succeed");
+    let package_key = read_contract_hash(&test_fixture.memory, package_hash_ptr);
+    let package = wasm_write(&mut test_fixture.memory, package_key);
+    let contract = wasm_write(&mut test_fixture.memory, contract_key);
+    runtime
+        .add_contract_version(
+            package.0,
+            package.1 as u32,
+            access_key_ptr,
+            UREF_SIZE as u32,
+            1,
+            contract.0,
+            contract.1 as u32,
+        )
+        .expect("add_contract_version should succeed");
+
+    let result = runtime.disable_contract_version(
+        package.0,
+        package.1 as u32,
+        access_key_ptr,
+        UREF_SIZE as u32,
+        2,
+    );
+    assert_error_contains(result, "ContractVersionNotFound");
+    runtime
+        .disable_contract_version(package.0, package.1 as u32, access_key_ptr, UREF_SIZE as u32, 1)
+        .expect("disable_contract_version should succeed");
+
+    let result = runtime.call_versioned_contract(
+        package.0,
+        package.1,
+        selector.0,
+        selector.1,
+        wasm_args.0,
+        wasm_args.1,
+        no_urefs.0,
+        no_urefs.1,
+    );
+    match result {
+        Err(execution_engine::execution::Error::DisabledContractVersion(key, version)) => {
+            assert_eq!(key, package_key);
+            assert_eq!(version, 1);
+        }
+        Err(error) => panic!("Test failed with unexpected error {:?}", error),
+        Ok(_) => panic!("Test should have failed but didn't"),
+    }
+    // With its only version disabled, the package has nothing to run.
+    let result = runtime.call_contract(
+        package.0,
+        package.1,
+        wasm_args.0,
+        wasm_args.1,
+        no_urefs.0,
+        no_urefs.1,
+    );
+    match result {
+        Err(execution_engine::execution::Error::ContractVersionNotFound(..)) => (),
+        Err(error) => panic!("Test failed with unexpected error {:?}", error),
+        Ok(_) => panic!("Test should have failed but didn't"),
+    }
+}
+
+// Sets up a fixture in which the current context is the contract stored
+// under `contract_key`, as if it had been called.
+fn contract_context_fixture(contract_key: Key) -> TestFixture {
+    let addr = [0u8; 20];
+    let (acc_key, account) = mock_account(addr);
+    let mut tc = mock_tc(acc_key, &account);
+    let contract = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION);
+    tc.write(contract_key, Value::Contract(contract));
+    let env = MockEnv::new(contract_key, BTreeMap::new(), HashSet::new(), account, 0);
+    let memory = env.memory_manager();
+    TestFixture::new(addr, env, memory, Rc::new(RefCell::new(tc)))
+}
+
+fn stored_urefs(test_fixture: &TestFixture, contract_key: Key) -> BTreeMap<String, Key> {
+    match test_fixture.tc.borrow_mut().get(&contract_key).unwrap() {
+        Some(Value::Contract(contract)) => contract.urefs_lookup().clone(),
+        other => panic!("Expected contract, got {:?}", other),
+    }
+}
+
+#[test]
+fn contract_named_keys_are_written_back() {
+    let contract_key = Key::Hash([5u8; 32]);
+    let named_key = Key::Hash([6u8; 32]);
+    let mut test_fixture = contract_context_fixture(contract_key);
+    let name = wasm_write(&mut test_fixture.memory, "counter");
+    let key = wasm_write(&mut test_fixture.memory, named_key);
+    let dest_ptr = test_fixture.memory.alloc(UREF_SIZE);
+
+    {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        let size = runtime
+            .get_key(name.0, name.1 as u32, dest_ptr)
+            .expect("get_key should succeed");
+        assert_eq!(size, 0);
+        runtime
+            .put_key(name.0, name.1 as u32, key.0, key.1 as u32)
+            .expect("put_key should succeed");
+        let size = runtime
+            .get_key(name.0, name.1 as u32, dest_ptr)
+            .expect("get_key should succeed");
+        let key_bytes = test_fixture
+            .memory
+            .read_bytes(dest_ptr, size as usize)
+            .expect("Reading from WasmMemory should work");
+        let found: Key = deserialize(&key_bytes).expect("Deserializing should work");
+        assert_eq!(found, named_key);
+    }
+    assert_eq!(stored_urefs(&test_fixture, contract_key).get("counter"), Some(&named_key));
+
+    {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        runtime
+            .remove_key(name.0, name.1 as u32)
+            .expect("remove_key should succeed");
+    }
+    assert!(stored_urefs(&test_fixture, contract_key).is_empty());
+}
+
+#[test]
+fn session_code_has_no_contract_named_keys() {
+    let mut test_fixture: TestFixture = Default::default();
+    let name = wasm_write(&mut test_fixture.memory, "counter");
+    let key = wasm_write(&mut test_fixture.memory, Key::Hash([6u8; 32]));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    let result = runtime.put_key(name.0, name.1 as u32, key.0, key.1 as u32);
+    assert_error_contains(result, "TypeMismatch");
+}
+
+#[test]
+fn missing_arg_does_not_trap() {
+    let mut test_fixture: TestFixture = Default::default();
+    let size = with_fresh_runtime(&mut test_fixture, |runtime| runtime.load_arg(0));
+    assert_eq!(size, None);
+}
+
+fn list_named_keys_page(
+    test_fixture: &mut TestFixture,
+    offset: u32,
+    limit: u32,
+) -> BTreeMap<String, Key> {
+    let dest_ptr = test_fixture.memory.alloc(8192);
+    let size = {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+        let size = runtime
+            .list_named_keys(offset, limit)
+            .expect("list_named_keys should succeed");
+        runtime
+            .set_mem_from_buf(dest_ptr)
+            .expect("Writing to Wasm memory should work.");
+        size
+    };
+    let page_bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, size)
+        .expect("Reading from Wasm memory should work.");
+    deserialize(&page_bytes).expect("Page should deserialize.")
+}
+
+#[test]
+fn named_keys_are_listed_in_pages() {
+    let mut test_fixture: TestFixture = Default::default();
+    for i in 0..150u8 {
+        test_fixture
+            .env
+            .uref_lookup
+            .insert(format!("key-{:03}", i), Key::Hash([i; 32]));
+    }
+    let first_page = list_named_keys_page(&mut test_fixture, 0, 1000);
+    assert_eq!(first_page.len(), MAX_NAMED_KEYS_PAGE_SIZE);
+    assert_eq!(first_page.get("key-000"), Some(&Key::Hash([0; 32])));
+
+    let last_page = list_named_keys_page(&mut test_fixture, 140, 20);
+    let names: Vec<&String> = last_page.keys().collect();
+    assert_eq!(names.len(), 10);
+    assert_eq!(names[0], "key-140");
+
+    assert!(list_named_keys_page(&mut test_fixture, 150, 20).is_empty());
+}
+
+#[test]
+fn remove_uref_is_written_back_to_account() {
+    let mut test_fixture: TestFixture = Default::default();
+    let name = wasm_write(&mut test_fixture.memory, "counter");
+    let key = wasm_write(&mut test_fixture.memory, Key::Hash([6u8; 32]));
+    let account_key = Key::Account(test_fixture.addr);
+    with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime
+            .add_uref(name.0, name.1 as u32, key.0, key.1 as u32)
+            .expect("add_uref should succeed");
+        runtime
+            .remove_uref(name.0, name.1 as u32)
+            .expect("remove_uref should succeed");
+        // Removing a name which is not known does nothing.
+        runtime
+            .remove_uref(name.0, name.1 as u32)
+            .expect("remove_uref should succeed");
+        let has_uref = runtime
+            .has_uref(name.0, name.1 as u32)
+            .expect("has_uref should succeed");
+        assert_eq!(has_uref, 1);
+    });
+    match test_fixture.tc.borrow_mut().get(&account_key).unwrap() {
+        Some(Value::Account(account)) => assert!(!account.urefs_lookup().contains_key("counter")),
+        other => panic!("Expected account, got {:?}", other),
+    }
+}
+
+#[test]
+fn is_valid_rejects_forged_urefs() {
+    let mut test_fixture: TestFixture = Default::default();
+    test_fixture
+        .env
+        .known_urefs
+        .insert(Key::URef([7u8; 32], AccessRights::READ));
+    let granted = wasm_write(&mut test_fixture.memory, Key::URef([7u8; 32], AccessRights::READ));
+    let escalated = wasm_write(
+        &mut test_fixture.memory,
+        Key::URef([7u8; 32], AccessRights::READ_WRITE),
+    );
+    let unknown = wasm_write(&mut test_fixture.memory, Key::URef([8u8; 32], AccessRights::READ));
+    let hash = wasm_write(&mut test_fixture.memory, Key::Hash([8u8; 32]));
+    with_fresh_runtime(&mut test_fixture, |runtime| {
+        let mut is_valid = |key: (u32, usize)| {
+            runtime
+                .is_valid(key.0, key.1 as u32)
+                .expect("is_valid should succeed")
+        };
+        assert!(is_valid(granted));
+        assert!(!is_valid(escalated));
+        assert!(!is_valid(unknown));
+        assert!(is_valid(hash));
+    });
+}
+
+#[test]
+fn validate_key_checks_provenance_and_rights() {
+    let mut test_fixture: TestFixture = Default::default();
+    test_fixture
+        .env
+        .known_urefs
+        .insert(Key::URef([7u8; 32], AccessRights::READ_ADD));
+    let granted = wasm_write(
+        &mut test_fixture.memory,
+        Key::URef([7u8; 32], AccessRights::READ_ADD),
+    );
+    let read_only = wasm_write(&mut test_fixture.memory, Key::URef([7u8; 32], AccessRights::READ));
+    let unknown = wasm_write(&mut test_fixture.memory, Key::URef([8u8; 32], AccessRights::READ));
+    let hash = wasm_write(&mut test_fixture.memory, Key::Hash([8u8; 32]));
+    with_fresh_runtime(&mut test_fixture, |runtime| {
+        let mut validate = |key: (u32, usize), required: AccessRights| {
+            let code = runtime
+                .validate_key(key.0, key.1 as u32, required.bits().into())
+                .expect("validate_key should succeed");
+            if code == 0 {
+                Ok(())
+            } else {
+                Err(AccessError::try_from(code).expect("should be a known code"))
+            }
+        };
+        assert_eq!(validate(granted, AccessRights::READ_ADD), Ok(()));
+        assert_eq!(validate(read_only, AccessRights::READ), Ok(()));
+        assert_eq!(
+            validate(read_only, AccessRights::ADD),
+            Err(AccessError::InsufficientRights)
+        );
+        assert_eq!(
+            validate(granted, AccessRights::WRITE),
+            Err(AccessError::InsufficientRights)
+        );
+        assert_eq!(
+            validate(unknown, AccessRights::READ),
+            Err(AccessError::ForgedReference)
+        );
+        // Contracts can be read by anyone, but only written by the host.
+        assert_eq!(validate(hash, AccessRights::READ), Ok(()));
+        assert_eq!(
+            validate(hash, AccessRights::WRITE),
+            Err(AccessError::InsufficientRights)
+        );
+    });
+    let result = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime.validate_key(hash.0, hash.1 as u32, 8)
+    });
+    assert_error_contains(result, "InvalidAccessRights");
+}
+
+#[test]
+fn local_storage_is_private_to_context() {
+    let mut test_fixture: TestFixture = Default::default();
+    let local_key = test_fixture
+        .memory
+        .write_raw(b"counter".to_vec())
+        .expect("Writing to Wasm memory should work.");
+    let value = wasm_write(&mut test_fixture.memory, Value::Int32(7));
+    let dest_ptr = test_fixture.memory.alloc(64);
+    let size = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime
+            .write_local(local_key.0, local_key.1 as u32, value.0, value.1 as u32)
+            .expect("write_local should succeed");
+        let size = runtime
+            .read_local(local_key.0, local_key.1 as u32)
+            .expect("read_local should succeed");
+        runtime
+            .set_mem_from_buf(dest_ptr)
+            .expect("Writing to Wasm memory should work.");
+        size
+    });
+    let value_bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, size)
+        .expect("Reading from Wasm memory should work.");
+    let found: Value = deserialize(&value_bytes).expect("Value should deserialize.");
+    assert_eq!(found, Value::Int32(7));
+
+    // A contract using the same key bytes gets a local key of its own.
+    test_fixture.env.base_key = Key::Hash([5u8; 32]);
+    let size = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime
+            .read_local(local_key.0, local_key.1 as u32)
+            .expect("read_local should succeed")
+    });
+    assert_eq!(size, 0);
+}
+
+#[test]
+fn dictionary_items_are_reached_through_the_seed() {
+    let seed_addr = [6u8; 32];
+    let mut test_fixture: TestFixture = Default::default();
+    test_fixture
+        .env
+        .known_urefs
+        .insert(Key::URef(seed_addr, AccessRights::READ_WRITE));
+    let seed = wasm_write(
+        &mut test_fixture.memory,
+        Key::URef(seed_addr, AccessRights::READ_WRITE),
+    );
+    let read_only_seed = wasm_write(
+        &mut test_fixture.memory,
+        Key::URef(seed_addr, AccessRights::READ),
+    );
+    let item_key = test_fixture
+        .memory
+        .write_raw(b"alice".to_vec())
+        .expect("Writing to Wasm memory should work.");
+    let value = wasm_write(&mut test_fixture.memory, Value::Int32(7));
+    let dest_ptr = test_fixture.memory.alloc(64);
+    with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime
+            .dictionary_put(
+                seed.0,
+                seed.1 as u32,
+                item_key.0,
+                item_key.1 as u32,
+                value.0,
+                value.1 as u32,
+            )
+            .expect("dictionary_put should succeed")
+    });
+
+    // Another contract given a read-only version of the seed can read the
+    // item, but it can't put items.
+    test_fixture.env.base_key = Key::Hash([5u8; 32]);
+    test_fixture.env.known_urefs.clear();
+    test_fixture
+        .env
+        .known_urefs
+        .insert(Key::URef(seed_addr, AccessRights::READ));
+    let (size, put_result) = with_fresh_runtime(&mut test_fixture, |runtime| {
+        let size = runtime
+            .dictionary_get(
+                read_only_seed.0,
+                read_only_seed.1 as u32,
+                item_key.0,
+                item_key.1 as u32,
+            )
+            .expect("dictionary_get should succeed");
+        runtime
+            .set_mem_from_buf(dest_ptr)
+            .expect("Writing to Wasm memory should work.");
+        let put_result = runtime.dictionary_put(
+            read_only_seed.0,
+            read_only_seed.1 as u32,
+            item_key.0,
+            item_key.1 as u32,
+            value.0,
+            value.1 as u32,
+        );
+        (size, put_result)
+    });
+    let value_bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, size)
+        .expect("Reading from Wasm memory should work.");
+    let found: Value = deserialize(&value_bytes).expect("Value should deserialize.");
+    assert_eq!(found, Value::Int32(7));
+    assert_error_contains(put_result, "InvalidAccess");
+
+    // Without the seed the items can't be reached at all.
+    test_fixture.env.known_urefs.clear();
+    let result = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime.dictionary_get(seed.0, seed.1 as u32, item_key.0, item_key.1 as u32)
+    });
+    assert_error_contains(result, "ForgedReference");
+}
+
+#[test]
+fn system_contracts_are_found_by_id() {
+    let mint_key = Key::URef([9u8; 32], AccessRights::READ);
+    let mut test_fixture: TestFixture = Default::default();
+    let dest_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let mint = wasm_write(&mut test_fixture.memory, mint_key);
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture
+        .env
+        .runtime(&mut tc_borrowed, mock_module())
+        .with_system_contracts(once((SystemContractId::Mint, mint_key)).collect());
+    assert!(!runtime
+        .is_valid(mint.0, mint.1 as u32)
+        .expect("is_valid should succeed"));
+
+    let size = runtime
+        .get_system_contract(SystemContractId::Mint.into(), dest_ptr)
+        .expect("get_system_contract should succeed");
+    let key_bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, size)
+        .expect("Reading from Wasm memory should work.");
+    let found: Key = deserialize(&key_bytes).expect("Key should deserialize.");
+    assert_eq!(found, mint_key);
+    // The URef of the system contract can be used by the contract from now on.
+    assert!(runtime
+        .is_valid(mint.0, mint.1 as u32)
+        .expect("is_valid should succeed"));
+
+    let result = runtime.get_system_contract(SystemContractId::ProofOfStake.into(), dest_ptr);
+    assert_error_contains(result, "SystemContractNotFound");
+}
+
+#[test]
+fn bond_and_unbond_move_tokens_through_pos() {
+    let pos_key = Key::Hash([8u8; 32]);
+    let mut test_fixture = purse_fixture(10, 1);
+    test_fixture.env.known_urefs.insert(mock_purse_id().into());
+    let (purse_ptr, purse_size) = test_fixture.memory.write(mock_purse_id());
+    let (bond_ptr, bond_size) = test_fixture.memory.write(U512::from(6));
+    let (unbond_ptr, unbond_size) = test_fixture.memory.write(U512::from(4));
+    let (too_much_ptr, too_much_size) = test_fixture.memory.write(U512::from(3));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    pos::install(&mut *tc_borrowed, pos_key, [9u8; 32]).expect("PoS should be installed");
+    {
+        let mut runtime = test_fixture
+            .env
+            .runtime(&mut tc_borrowed, mock_module())
+            .with_system_contracts(once((SystemContractId::ProofOfStake, pos_key)).collect());
+        let result = runtime.bond(bond_ptr, bond_size as u32, purse_ptr, purse_size as u32);
+        assert_eq!(result.expect("bond should not trap"), 0);
+        let result = runtime.unbond(unbond_ptr, unbond_size as u32);
+        assert_eq!(result.expect("unbond should not trap"), 0);
+        let result = runtime.unbond(too_much_ptr, too_much_size as u32);
+        assert_eq!(
+            result.expect("unbond should not trap"),
+            i32::from(PosError::UnbondTooLarge)
+        );
+    }
+    let stakes = pos::stakes(&mut *tc_borrowed, pos_key).expect("stakes should be read");
+    assert_eq!(stakes.values().collect::<Vec<_>>(), vec![&U512::from(2)]);
+    // Unbonded tokens are paid out to the main purse of the account.
+    assert_eq!(
+        mint::balance(&mut *tc_borrowed, mock_purse_id()).unwrap(),
+        U512::from(8)
+    );
+}
+
+#[test]
+fn delegate_and_undelegate_move_tokens_through_pos() {
+    let pos_key = Key::Hash([8u8; 32]);
+    let validator = PublicKey::new([3u8; 32]);
+    let mut test_fixture = purse_fixture(10, 1);
+    test_fixture.env.known_urefs.insert(mock_purse_id().into());
+    let (validator_ptr, validator_size) = test_fixture.memory.write(validator);
+    let (unbonded_ptr, unbonded_size) = test_fixture.memory.write(PublicKey::new([4u8; 32]));
+    let (purse_ptr, purse_size) = test_fixture.memory.write(mock_purse_id());
+    let (delegate_ptr, delegate_size) = test_fixture.memory.write(U512::from(3));
+    let (undelegate_ptr, undelegate_size) = test_fixture.memory.write(U512::from(2));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    pos::install(&mut *tc_borrowed, pos_key, [9u8; 32]).expect("PoS should be installed");
+    let (stake, delays) = (U512::from(5), pos::QueueDelays::default());
+    pos::bond(&mut *tc_borrowed, pos_key, validator, mock_purse_id(), stake, 0, delays)
+        .expect("validator should bond");
+    {
+        let mut runtime = test_fixture
+            .env
+            .runtime(&mut tc_borrowed, mock_module())
+            .with_system_contracts(once((SystemContractId::ProofOfStake, pos_key)).collect());
+        let result = runtime.delegate(
+            validator_ptr,
+            validator_size as u32,
+            delegate_ptr,
+            delegate_size as u32,
+            purse_ptr,
+            purse_size as u32,
+        );
+        assert_eq!(result.expect("delegate should not trap"), 0);
+        let result = runtime.delegate(
+            unbonded_ptr,
+            unbonded_size as u32,
+            delegate_ptr,
+            delegate_size as u32,
+            purse_ptr,
+            purse_size as u32,
+        );
+        assert_eq!(
+            result.expect("delegate should not trap"),
+            i32::from(PosError::NotBonded)
+        );
+        let result = runtime.undelegate(
+            validator_ptr,
+            validator_size as u32,
+            undelegate_ptr,
+            undelegate_size as u32,
+        );
+        assert_eq!(result.expect("undelegate should not trap"), 0);
+    }
+    let weights = pos::weights(&mut *tc_borrowed, pos_key).expect("weights should be read");
+    assert_eq!(weights.get(&validator), Some(&U512::from(6)));
+    // Undelegated tokens are paid out to the main purse of the account.
+    assert_eq!(
+        mint::balance(&mut *tc_borrowed, mock_purse_id()).unwrap(),
+        U512::from(4)
+    );
+}
+
+#[test]
+fn bids_are_added_and_withdrawn_through_the_auction() {
+    let auction_key = Key::Hash([8u8; 32]);
+    let mut test_fixture = purse_fixture(10, 1);
+    test_fixture.env.known_urefs.insert(mock_purse_id().into());
+    let (purse_ptr, purse_size) = test_fixture.memory.write(mock_purse_id());
+    let (bid_ptr, bid_size) = test_fixture.memory.write(U512::from(6));
+    let (too_large_ptr, too_large_size) = test_fixture.memory.write(U512::from(7));
+    let (withdraw_ptr, withdraw_size) = test_fixture.memory.write(U512::from(4));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    auction::install(&mut *tc_borrowed, auction_key, [9u8; 32]).expect("auction should install");
+    {
+        let mut runtime = test_fixture
+            .env
+            .runtime(&mut tc_borrowed, mock_module())
+            .with_system_contracts(once((SystemContractId::Auction, auction_key)).collect());
+        let result = runtime.add_bid(bid_ptr, bid_size as u32, purse_ptr, purse_size as u32);
+        assert_eq!(result.expect("add_bid should not trap"), 0);
+        let result = runtime.withdraw_bid(too_large_ptr, too_large_size as u32);
+        assert_eq!(
+            result.expect("withdraw_bid should not trap"),
+            i32::from(AuctionError::WithdrawTooLarge)
+        );
+        let result = runtime.withdraw_bid(withdraw_ptr, withdraw_size as u32);
+        assert_eq!(result.expect("withdraw_bid should not trap"), 0);
+    }
+    let bids = auction::bids(&mut *tc_borrowed, auction_key).expect("bids should be read");
+    assert_eq!(bids.get(&PublicKey::new([0u8; 32])), Some(&U512::from(2)));
+    // Withdrawn tokens are paid out to the main purse of the account.
+    assert_eq!(
+        mint::balance(&mut *tc_borrowed, mock_purse_id()).unwrap(),
+        U512::from(8)
+    );
+}
+
+#[test]
+fn callee_gas_allowance_is_enforced() {
+    // Adds a named key to itself and then burns gas until it runs out.
+    let wat = format!(
+        r#"
+            (module
+                (import "env" "memory" (memory 1))
+                (import "env" "add_uref" (func $add_uref (param i32 i32 i32 i32)))
+                (import "env" "gas" (func $gas (param i32)))
+                (data (i32.const 0) "\01\00\00\00x")
+                (data (i32.const 8) "\01{}")
+                (func (export "call")
+                    i32.const 0
+                    i32.const 5
+                    i32.const 8
+                    i32.const 33
+                    call $add_uref
+                    (loop $burn
+                        i32.const 100
+                        call $gas
+                        br $burn
+                    )
+                )
+            )
+            "#,
+        "\\00".repeat(32)
+    );
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    test_fixture.tc.borrow_mut().write(
+        contract_key,
+        Value::Contract(Contract::new(wasm_binary, BTreeMap::new(), PROTOCOL_VERSION)),
+    );
+    let wasm_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<Vec<u8>>::new());
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let result = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime.call_contract_with_gas(
+            wasm_key.0,
+            wasm_key.1,
+            wasm_args.0,
+            wasm_args.1,
+            wasm_urefs.0,
+            wasm_urefs.1,
+            300,
+        )
+    });
+    match result {
+        Err(execution_engine::execution::Error::CalleeGasLimit) => (),
+        other => panic!("Expected CalleeGasLimit, got {:?}", other),
+    }
+    // The named key added by the callee is discarded.
+    assert!(stored_urefs(&test_fixture, contract_key).is_empty());
+
+    // Without an allowance the callee uses up the gas of the whole deploy.
+    let result = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime.call_contract(
+            wasm_key.0,
+            wasm_key.1,
+            wasm_args.0,
+            wasm_args.1,
+            wasm_urefs.0,
+            wasm_urefs.1,
+        )
+    });
+    match result {
+        Err(execution_engine::execution::Error::GasLimit) => (),
+        other => panic!("Expected GasLimit, got {:?}", other),
+    }
+}
+
+// Stores a contract which returns `returned_bytes` with `ret` and calls it.
+fn call_returning_contract(returned_bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let data: String = returned_bytes
+        .iter()
+        .map(|byte| format!("\\{:02x}", byte))
+        .collect();
+    let wat = format!(
+        r#"
+            (module
+                (import "env" "memory" (memory 1))
+                (import "env" "ret" (func $ret (param i32 i32 i32 i32)))
+                (data (i32.const 0) "{}")
+                (data (i32.const 256) "\00\00\00\00")
+                (func (export "call")
+                    i32.const 0
+                    i32.const {}
+                    i32.const 256
+                    i32.const 4
+                    call $ret
+                )
+            )
+            "#,
+        data,
+        returned_bytes.len()
+    );
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    test_fixture.tc.borrow_mut().write(
+        contract_key,
+        Value::Contract(Contract::new(wasm_binary, BTreeMap::new(), PROTOCOL_VERSION)),
+    );
+    let wasm_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<Vec<u8>>::new());
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+    let dest_ptr = test_fixture.memory.alloc(256);
+    let size = with_fresh_runtime(&mut test_fixture, |runtime| {
+        let size = runtime
+            .call_contract(
+                wasm_key.0,
+                wasm_key.1,
+                wasm_args.0,
+                wasm_args.1,
+                wasm_urefs.0,
+                wasm_urefs.1,
+            )
+            .map_err(|error| format!("{:?}", error))?;
+        runtime
+            .set_mem_from_buf(dest_ptr)
+            .expect("Writing to Wasm memory should work.");
+        Ok(size)
+    })?;
+    Ok(test_fixture
+        .memory
+        .read_bytes(dest_ptr, size)
+        .expect("Reading from Wasm memory should work."))
+}
+
+#[test]
+fn contracts_return_values() {
+    let value_bytes = Value::Int32(5).to_bytes().expect("should serialize");
+    assert_eq!(call_returning_contract(&value_bytes), Ok(value_bytes.clone()));
+
+    let mut too_long = value_bytes.clone();
+    too_long.push(0);
+    let error = call_returning_contract(&too_long).expect_err("left-over bytes should fail");
+    assert!(error.contains("LeftOverBytes"));
+
+    let forged = Value::NamedKey(String::from("forged"), Key::URef([1u8; 32], AccessRights::READ))
+        .to_bytes()
+        .expect("should serialize");
+    let error = call_returning_contract(&forged).expect_err("forged URefs should fail");
+    assert!(error.contains("ForgedReference"));
+}
+
+#[test]
+fn upgrade_contract_at_uref_keeps_named_keys() {
+    let mut test_fixture: TestFixture = Default::default();
+    let wasm_module = create_wasm_module();
+    let contract_uref = Key::URef([9u8; 32], AccessRights::READ_ADD_WRITE);
+    let named_keys = urefs_map(vec![("counter".to_owned(), Key::Hash([6u8; 32]))]);
+    test_fixture.env.known_urefs.insert(contract_uref);
+    test_fixture.tc.borrow_mut().write(
+        contract_uref,
+        Value::Contract(Contract::new(vec![1, 2, 3], named_keys.clone(), PROTOCOL_VERSION)),
+    );
+    let name = wasm_write(&mut test_fixture.memory, wasm_module.func_name.clone());
+    let writeable = wasm_write(&mut test_fixture.memory, contract_uref);
+    let read_only = wasm_write(&mut test_fixture.memory, Key::URef([9u8; 32], AccessRights::READ));
+    {
+        let mut tc_borrowed = test_fixture.tc.borrow_mut();
+        let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module);
+        let result = runtime.upgrade_contract_at_uref(
+            name.0,
+            name.1 as u32,
+            read_only.0,
+            read_only.1 as u32,
+        );
+        assert_error_contains(result, "InvalidAccess");
+        runtime
+            .upgrade_contract_at_uref(name.0, name.1 as u32, writeable.0, writeable.1 as u32)
+            .expect("upgrade_contract_at_uref should succeed");
+    }
+    match test_fixture.tc.borrow_mut().get(&contract_uref).unwrap() {
+        Some(Value::Contract(contract)) => {
+            assert_ne!(contract.bytes(), &[1u8, 2, 3][..]);
+            assert_eq!(contract.urefs_lookup(), &named_keys);
+        }
+        other => panic!("Expected contract, got {:?}", other),
+    }
+}
+
+#[test]
+fn locked_contract_package_versions_cannot_be_upgraded() {
+    let mut test_fixture: TestFixture = Default::default();
+    let wasm_module = create_wasm_module();
+    let contract_hash = Key::Hash([7u8; 32]);
+    let contract_uref = Key::URef([9u8; 32], AccessRights::READ_ADD_WRITE);
+    test_fixture.env.known_urefs.insert(contract_uref);
+    for key in &[contract_hash, contract_uref] {
+        test_fixture.tc.borrow_mut().write(
+            *key,
+            Value::Contract(Contract::new(vec![1, 2, 3], BTreeMap::new(), PROTOCOL_VERSION)),
+        );
+    }
+    let package_hash_ptr = test_fixture.memory.alloc(32);
+    let access_key_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let name = wasm_write(&mut test_fixture.memory, wasm_module.func_name.clone());
+    let hash = wasm_write(&mut test_fixture.memory, contract_hash);
+    let uref = wasm_write(&mut test_fixture.memory, contract_uref);
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, wasm_module.module);
+    runtime
+        .create_contract_package(package_hash_ptr, access_key_ptr)
+        .expect("create_contract_package should succeed");
+    let package_key = read_contract_hash(&test_fixture.memory, package_hash_ptr);
+    let package = wasm_write(&mut test_fixture.memory, package_key);
+
+    let result = runtime.add_contract_version(
+        package.0,
+        package.1 as u32,
+        access_key_ptr,
+        UREF_SIZE as u32,
+        1,
+        uref.0,
+        uref.1 as u32,
+    );
+    assert_error_contains(result, "UpgradableContractVersion");
+    runtime
+        .add_contract_version(
+            package.0,
+            package.1 as u32,
+            access_key_ptr,
+            UREF_SIZE as u32,
+            1,
+            hash.0,
+            hash.1 as u32,
+        )
+        .expect("add_contract_version should succeed");
+    runtime
+        .lock_contract_package(package.0, package.1 as u32, access_key_ptr, UREF_SIZE as u32)
+        .expect("lock_contract_package should succeed");
+
+    let result = runtime.upgrade_contract_at_uref(name.0, name.1 as u32, hash.0, hash.1 as u32);
+    assert_invalid_access(result);
+}
+
+#[test]
+fn gas_remaining_reflects_the_callee_allowance() {
+    // Spends some gas and reverts with the gas it has left.
+    let wat = r#"
+            (module
+                (import "env" "gas" (func $gas (param i32)))
+                (import "env" "gas_remaining" (func $gas_remaining (result i64)))
+                (import "env" "revert" (func $revert (param i32)))
+                (func (export "call")
+                    i32.const 100
+                    call $gas
+                    call $gas_remaining
+                    i32.wrap/i64
+                    call $revert
+                )
+            )
+            "#;
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    test_fixture.tc.borrow_mut().write(
+        contract_key,
+        Value::Contract(Contract::new(wasm_binary, BTreeMap::new(), PROTOCOL_VERSION)),
+    );
+    let wasm_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<Vec<u8>>::new());
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let result = with_fresh_runtime(&mut test_fixture, |runtime| {
+        assert_eq!(runtime.gas_remaining(), Gas::from(1000));
+        runtime.call_contract_with_gas(
+            wasm_key.0,
+            wasm_key.1,
+            wasm_args.0,
+            wasm_args.1,
+            wasm_urefs.0,
+            wasm_urefs.1,
+            300,
+        )
+    });
+    match result {
+        Err(execution_engine::execution::Error::Revert(remaining)) => assert_eq!(remaining, 200),
+        other => panic!("Expected Revert, got {:?}", other),
+    }
+}
+
+#[test]
+fn failing_callees_charge_their_gas_to_the_caller() {
+    // Spends some gas and reverts.
+    let wat = r#"
+            (module
+                (import "env" "gas" (func $gas (param i32)))
+                (import "env" "revert" (func $revert (param i32)))
+                (func (export "call")
+                    i32.const 100
+                    call $gas
+                    i32.const 1
+                    call $revert
+                )
+            )
+            "#;
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    test_fixture.tc.borrow_mut().write(
+        contract_key,
+        Value::Contract(Contract::new(wasm_binary, BTreeMap::new(), PROTOCOL_VERSION)),
+    );
+    let wasm_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<Vec<u8>>::new());
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let (result, remaining) = with_fresh_runtime(&mut test_fixture, |runtime| {
+        let result = runtime.call_contract(
+            wasm_key.0,
+            wasm_key.1,
+            wasm_args.0,
+            wasm_args.1,
+            wasm_urefs.0,
+            wasm_urefs.1,
+        );
+        (result, runtime.gas_remaining())
+    });
+    match result {
+        Err(execution_engine::execution::Error::Revert(1)) => (),
+        other => panic!("Expected Revert, got {:?}", other),
+    }
+    assert_eq!(remaining, Gas::from(900));
+}
+
+#[test]
+fn call_stack_depth_is_limited() {
+    // Reverts with its depth in the call stack.
+    let wat = r#"
+            (module
+                (import "env" "call_stack_depth" (func $call_stack_depth (result i32)))
+                (import "env" "revert" (func $revert (param i32)))
+                (func (export "call")
+                    call $call_stack_depth
+                    call $revert
+                )
+            )
+            "#;
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let mut test_fixture: TestFixture = Default::default();
+    let contract_key = Key::Hash([7u8; 32]);
+    test_fixture.tc.borrow_mut().write(
+        contract_key,
+        Value::Contract(Contract::new(wasm_binary, BTreeMap::new(), PROTOCOL_VERSION)),
+    );
+    let wasm_key = wasm_write(&mut test_fixture.memory, contract_key);
+    let wasm_args = wasm_write(&mut test_fixture.memory, Vec::<Vec<u8>>::new());
+    let wasm_urefs = wasm_write(&mut test_fixture.memory, Vec::<Key>::new());
+
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    assert_eq!(runtime.call_stack_depth(), 0);
+    match runtime.call_contract(
+        wasm_key.0,
+        wasm_key.1,
+        wasm_args.0,
+        wasm_args.1,
+        wasm_urefs.0,
+        wasm_urefs.1,
+    ) {
+        Err(execution_engine::execution::Error::Revert(depth)) => assert_eq!(depth, 1),
+        other => panic!("Expected Revert, got {:?}", other),
+    }
+
+    // Session code may not call contracts at all with a limit of 0.
+    let mut runtime = runtime.with_max_call_depth(0);
+    match runtime.call_contract(
+        wasm_key.0,
+        wasm_key.1,
+        wasm_args.0,
+        wasm_args.1,
+        wasm_urefs.0,
+        wasm_urefs.1,
+    ) {
+        Err(execution_engine::execution::Error::CallDepthExceeded(limit)) => assert_eq!(limit, 0),
+        other => panic!("Expected CallDepthExceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn batched_reads_and_writes() {
+    let mut test_fixture: TestFixture = Default::default();
+    let first = Key::URef([1u8; 32], AccessRights::READ_ADD_WRITE);
+    let second = Key::URef([2u8; 32], AccessRights::READ_ADD_WRITE);
+    let missing = Key::Hash([3u8; 32]);
+    test_fixture.env.known_urefs.insert(first);
+    test_fixture.env.known_urefs.insert(second);
+    let entries = vec![(first, Value::Int32(1)), (second, Value::String(String::from("two")))];
+    let entries = test_fixture
+        .memory
+        .write_raw(vec_to_bytes(&entries).expect("should serialize"))
+        .expect("Writing to Wasm memory should work.");
+    // The hash isn't writeable, so none of the batch is written.
+    let rejected = vec![(first, Value::Int32(5)), (missing, Value::Int32(6))];
+    let rejected = test_fixture
+        .memory
+        .write_raw(vec_to_bytes(&rejected).expect("should serialize"))
+        .expect("Writing to Wasm memory should work.");
+    let keys = wasm_write(&mut test_fixture.memory, vec![first, second, missing]);
+    let dest_ptr = test_fixture.memory.alloc(256);
+
+    let size = with_fresh_runtime(&mut test_fixture, |runtime| {
+        runtime
+            .write_multi(entries.0, entries.1 as u32)
+            .expect("write_multi should succeed");
+        let result = runtime.write_multi(rejected.0, rejected.1 as u32);
+        assert_error_contains(result, "InvalidAccess");
+        let size = runtime
+            .read_multi(keys.0, keys.1 as u32)
+            .expect("read_multi should succeed");
+        runtime
+            .set_mem_from_buf(dest_ptr)
+            .expect("Writing to Wasm memory should work.");
+        size
+    });
+    let bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, size)
+        .expect("Reading from Wasm memory should work.");
+    let (values, rem): (Vec<Option<Value>>, &[u8]) =
+        vec_from_bytes(&bytes).expect("should deserialize");
+    assert!(rem.is_empty());
+    assert_eq!(
+        values,
+        vec![Some(Value::Int32(1)), Some(Value::String(String::from("two"))), None]
+    );
+}
+
+#[test]
+fn host_buffer_is_read_in_two_steps() {
+    let mut test_fixture: TestFixture = Default::default();
+    let key = Key::Hash([5u8; 32]);
+    test_fixture.tc.borrow_mut().write(key, Value::Int32(7));
+    let wasm_key = wasm_write(&mut test_fixture.memory, key);
+    let dest_ptr = test_fixture.memory.alloc(256);
+
+    let size = with_fresh_runtime(&mut test_fixture, |runtime| {
+        let size = runtime
+            .read_value(wasm_key.0, wasm_key.1 as u32)
+            .expect("read_value should succeed");
+        let result = runtime.read_host_buffer(dest_ptr, size - 1);
+        assert_error_contains(result, "HostBufferTooSmall");
+        let copied = runtime
+            .read_host_buffer(dest_ptr, 256)
+            .expect("read_host_buffer should succeed");
+        assert_eq!(copied, size);
+        size
+    });
+    let bytes = test_fixture
+        .memory
+        .read_bytes(dest_ptr, size)
+        .expect("Reading from Wasm memory should work.");
+    assert_eq!(deserialize::<Value>(&bytes).expect("should deserialize"), Value::Int32(7));
+}
+
+#[test]
+fn new_uref_with_rights_grants_only_the_given_rights() {
+    let mut test_fixture: TestFixture = Default::default();
+    let key_ptr = test_fixture.memory.alloc(UREF_SIZE);
+    let value = wasm_write(&mut test_fixture.memory, Value::Int32(1));
+    let new_value = wasm_write(&mut test_fixture.memory, Value::Int32(2));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+
+    let result = runtime.new_uref_with_rights(key_ptr, value.0, value.1 as u32, 0);
+    assert_error_contains(result, "InvalidAccessRights(0)");
+    let result = runtime.new_uref_with_rights(key_ptr, value.0, value.1 as u32, 8);
+    assert_error_contains(result, "InvalidAccessRights(8)");
+    let read_bits = u32::from(AccessRights::READ.bits());
+    runtime
+        .new_uref_with_rights(key_ptr, value.0, value.1 as u32, read_bits)
+        .expect("new_uref_with_rights should succeed");
+
+    let key_bytes = test_fixture
+        .memory
+        .read_bytes(key_ptr, UREF_SIZE)
+        .expect("Reading from Wasm memory should work.");
+    let uref: Key = deserialize(&key_bytes).expect("should deserialize");
+    match uref {
+        Key::URef(_, AccessRights::READ) => (),
+        other => panic!("Expected a read-only URef, got {:?}", other),
+    }
+    let read_only = wasm_write(&mut test_fixture.memory, uref);
+    let result = runtime.write(read_only.0, read_only.1 as u32, new_value.0, new_value.1 as u32);
+    assert_error_contains(result, "InvalidAccess");
+}
+
+#[test]
+fn urefs_cannot_be_presented_with_more_rights_than_granted() {
+    let mut test_fixture: TestFixture = Default::default();
+    let read_only = Key::URef([5u8; 32], AccessRights::READ);
+    test_fixture.env.known_urefs.insert(read_only);
+    test_fixture.tc.borrow_mut().write(read_only, Value::Int32(1));
+    let escalated = wasm_write(
+        &mut test_fixture.memory,
+        Key::URef([5u8; 32], AccessRights::READ_WRITE),
+    );
+    let new_value = wasm_write(&mut test_fixture.memory, Value::Int32(2));
+    let mut tc_borrowed = test_fixture.tc.borrow_mut();
+    let mut runtime = test_fixture.env.runtime(&mut tc_borrowed, mock_module());
+    let result = runtime.write(escalated.0, escalated.1 as u32, new_value.0, new_value.1 as u32);
+    assert_forged_reference(result);
+}
+
+/// Runs the deploy made of `wat` on an engine with the given config, from the
+/// account created by `mocked_account`.
+fn run_wat_deploy(wat: &str, config: EngineConfig) -> ExecutionResult {
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let address = [1u8; 20];
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account(address);
+    let hist = InMemHist::new_initialized(&root_hash, init_state);
+    let engine_state = EngineState::with_config(hist, config);
+    let session = DeployCode {
+        module_bytes: &wasm_binary,
+        args: &[],
+        named_args: &[],
+    };
+    engine_state
+        .run_deploy(
+            session,
+            session,
+            address,
+            // Public key of the account created by `mocked_account`.
+            &[PublicKey::new([48u8; 32])],
+            1000,
+            1,
+            [0u8; 32],
+            body_hash(&session, &session),
+            &[],
+            0,
+            DeployPreconditions::default(),
+            root_hash,
+            Gas::from(1_000_000),
+            CONV_RATE,
+            &WasmiExecutor,
+            &WasmiPreprocessor,
+        )
+        .expect("Root hash should exist.")
+}
+
+/// Cost of running a deploy which only does some arithmetic on an engine
+/// using the given Wasm costs.
+fn arithmetic_deploy_cost(wasm_costs: WasmCosts) -> u64 {
+    let wat = r#"
+            (module
+                (memory 1)
+                (func (export "call")
+                    i32.const 1
+                    i32.const 2
+                    i32.add
+                    drop
+                )
+            )
+            "#;
+    let result = run_wat_deploy(wat, EngineConfig::new().with_wasm_costs(wasm_costs));
+    assert!(result.result.is_ok());
+    result.cost.as_u64()
+}
+
+#[test]
+fn wasm_costs_are_taken_from_the_engine_config() {
+    let default_cost = arithmetic_deploy_cost(WasmCosts::default());
+    let doubled_cost = arithmetic_deploy_cost(WasmCosts {
+        regular: 2,
+        ..WasmCosts::default()
+    });
+    assert!(default_cost > 0);
+    assert_eq!(doubled_cost, 2 * default_cost);
+}
+
+#[test]
+fn sessions_are_charged_for_the_bytes_they_store() {
+    let wat = r#"
+            (module
+                (import "env" "create_purse" (func $create_purse (param i32)))
+                (memory 1)
+                (func (export "call")
+                    (call $create_purse (i32.const 0))))
+            "#;
+    let cost = |storage_costs: StorageCosts| {
+        let result = run_wat_deploy(wat, EngineConfig::new().with_storage_costs(storage_costs));
+        assert!(result.result.is_ok());
+        result.cost.as_u64()
+    };
+    let free = cost(StorageCosts::default());
+    let per_byte = cost(StorageCosts::new(1)) - free;
+    assert!(per_byte > 0);
+    assert_eq!(cost(StorageCosts::new(3)), free + 3 * per_byte);
+}
+
+#[test]
+fn deploys_are_charged_for_their_instructions_before_running_them() {
+    // Two constants, an addition and a drop at the regular cost.
+    assert_eq!(arithmetic_deploy_cost(WasmCosts::default()), 4);
+
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account([1u8; 20]);
+    let engine_state = EngineState::new(InMemHist::new_initialized(&root_hash, init_state));
+    let run = |gas_limit: u64| {
+        let deploy = Deploy {
+            gas_limit: Gas::from(gas_limit),
+            ..block_deploy(&wasm_binary, &authorization_keys, [1u8; 32], &[])
+        };
+        engine_state
+            .speculative_exec(root_hash, &deploy, 0, &WasmiExecutor, &WasmiPreprocessor)
+            .expect("Root hash should exist.")
+    };
+    assert!(run(4).result.is_ok());
+    // The block is charged as a whole, so none of it runs on less gas.
+    let result = run(3);
+    match result.result {
+        Err(EngineError::ExecError(execution_engine::execution::Error::GasLimit)) => (),
+        other => panic!("Expected GasLimit, got {:?}", other),
+    }
+    assert_eq!(result.cost, Gas::default());
+}
+
+#[test]
+fn host_functions_are_charged_from_the_cost_table() {
+    // Hashes 7 bytes with `blake2b`, then checks `gas_remaining`.
+    let wat = r#"
+            (module
+                (import "env" "blake2b" (func $blake2b (param i32 i32 i32)))
+                (import "env" "gas_remaining" (func $gas_remaining (result i64)))
+                (memory 1)
+                (func (export "call")
+                    (call $blake2b (i32.const 0) (i32.const 7) (i32.const 32))
+                    (drop (call $gas_remaining))
+                )
+            )
+            "#;
+    let cost = |host_function_costs: HostFunctionCosts| {
+        let config = EngineConfig::new().with_host_function_costs(host_function_costs);
+        let result = run_wat_deploy(wat, config);
+        assert!(result.result.is_ok());
+        result.cost.as_u64()
+    };
+    let free = cost(HostFunctionCosts::new(HostFunctionCost::default()));
+    assert_eq!(cost(HostFunctionCosts::default()), free + 100 + 2 * 7);
+    let blake2b = HostFunctionCosts::default().with_cost("blake2b", HostFunctionCost::new(100, 3));
+    assert_eq!(cost(blake2b), free + 100 + 3 * 7);
+    // Functions without a cost of their own are charged the default cost.
+    let default = HostFunctionCosts::new(HostFunctionCost::new(5, 1));
+    assert_eq!(cost(default), free + 2 * 5 + 7);
+}
+
+#[test]
+fn floating_point_modules_are_rejected() {
+    let preprocess = |body: &str| {
+        let wat = format!(r#"(module (memory 1) (func (export "call") {}))"#, body);
+        let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+        WasmiPreprocessor.preprocess(&wasm_binary, &WasmCosts::default())
+    };
+    assert!(preprocess("i32.const 1 drop").is_ok());
+    for body in &[
+        "f32.const 1 drop",
+        "(local f64)",
+        "i32.const 1 f32.convert_s/i32 i32.reinterpret/f32 drop",
+        "(block (result f64) f64.const 0) drop",
+    ] {
+        match preprocess(body) {
+            Err(PreprocessingError::FloatingPoint) => (),
+            other => panic!("Expected FloatingPoint for {}, got {:?}", body, other),
+        }
+    }
+}
+
+#[test]
+fn memory_page_limits_are_enforced() {
+    let preprocess = |memory: &str, wasm_costs: &WasmCosts| {
+        let wat = format!(r#"(module {} (func (export "call")))"#, memory);
+        let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+        WasmiPreprocessor.preprocess(&wasm_binary, wasm_costs)
+    };
+    let default_costs = WasmCosts::default();
+    match preprocess("(memory 200)", &default_costs) {
+        Err(PreprocessingError::InitialMemoryTooLarge { pages: 200, limit: 128 }) => (),
+        other => panic!("Expected InitialMemoryTooLarge, got {:?}", other),
+    }
+    match preprocess("(memory 1 1000)", &default_costs) {
+        Err(PreprocessingError::MaximumMemoryTooLarge { pages: 1000, limit: 256 }) => (),
+        other => panic!("Expected MaximumMemoryTooLarge, got {:?}", other),
+    }
+
+    // A module without a maximum gets the default one, unless the configured
+    // limit is lower.
+    let maximum_pages = |wasm_costs: &WasmCosts| {
+        let module = preprocess("(memory 1)", wasm_costs).expect("should preprocess");
+        module
+            .import_section()
+            .and_then(|section| {
+                section.entries().iter().find_map(|entry| match *entry.external() {
+                    External::Memory(memory_type) => memory_type.limits().maximum(),
+                    _ => None,
+                })
+            })
+            .expect("should import memory with a maximum")
+    };
+    assert_eq!(maximum_pages(&default_costs), 128);
+
+    let stricter_costs = WasmCosts {
+        max_initial_pages: 2,
+        max_grow_pages: 4,
+        ..WasmCosts::default()
+    };
+    assert!(preprocess("(memory 2 4)", &stricter_costs).is_ok());
+    match preprocess("(memory 3 4)", &stricter_costs) {
+        Err(PreprocessingError::InitialMemoryTooLarge { pages: 3, limit: 2 }) => (),
+        other => panic!("Expected InitialMemoryTooLarge, got {:?}", other),
+    }
+    match preprocess("(memory 1 5)", &stricter_costs) {
+        Err(PreprocessingError::MaximumMemoryTooLarge { pages: 5, limit: 4 }) => (),
+        other => panic!("Expected MaximumMemoryTooLarge, got {:?}", other),
+    }
+    assert_eq!(maximum_pages(&stricter_costs), 4);
+}
+
+#[test]
+fn deploys_exceeding_configured_memory_fail() {
+    let wat = r#"(module (memory 16) (func (export "call")))"#;
+    assert!(run_wat_deploy(wat, EngineConfig::new()).result.is_ok());
+    let result = run_wat_deploy(wat, EngineConfig::new().with_memory_limits(8, 16));
+    assert!(result.result.is_err());
+}
+
+#[test]
+fn deep_recursion_hits_the_stack_height_limit() {
+    // Recurses 100 times before returning.
+    let wat = r#"
+            (module
+                (memory 1)
+                (func $recurse (param i32)
+                    (if (i32.gt_u (get_local 0) (i32.const 0))
+                        (then (call $recurse (i32.sub (get_local 0) (i32.const 1))))
+                    )
+                )
+                (func (export "call")
+                    (call $recurse (i32.const 100))
+                )
+            )
+            "#;
+    assert!(run_wat_deploy(wat, EngineConfig::new()).result.is_ok());
+    let result = run_wat_deploy(wat, EngineConfig::new().with_max_stack_height(100));
+    assert!(result.result.is_err());
+    // The deploy still pays for the gas it used before being stopped.
+    assert!(result.cost > Gas::default());
+}
+
+#[test]
+fn unbounded_recursion_fails_the_deploy() {
+    let wat = r#"
+            (module
+                (memory 1)
+                (func $recurse
+                    (call $recurse)
+                )
+                (func (export "call")
+                    (call $recurse)
+                )
+            )
+            "#;
+    assert!(run_wat_deploy(wat, EngineConfig::new()).result.is_err());
+}
+
+// Balance of the main purse of the account created by `mocked_account`
+// in the tests of deploys with payment code.
+const PAYER_BALANCE: u64 = 1_000_000;
+
+fn rewards_purse() -> PurseId {
+    PurseId::new([50u8; 32], AccessRights::READ_ADD_WRITE)
+}
+
+// Key the standard payment contract is installed under in the tests of deploys
+// with payment code.
+const STANDARD_PAYMENT_KEY: Key = Key::Hash([51u8; 32]);
+
+/// Runs `session_wat` after payment code which pays `payment_amount` out of
+/// the main purse of the deploying account. Fees go to `rewards_purse`.
+/// Returns the result and the balances of the main purse of the account and
+/// of the rewards purse once the effects of the deploy are committed.
+fn run_paid_deploy(payment_amount: u64, session_wat: &str) -> (ExecutionResult, U512, U512) {
+    run_priced_deploy(payment_amount, session_wat, CONV_RATE, RefundRatio::default())
+}
+
+/// Like `run_paid_deploy`, buying gas at `gas_price` on an engine refunding
+/// `refund_ratio` of the unused payment.
+fn run_priced_deploy(
+    payment_amount: u64,
+    session_wat: &str,
+    gas_price: u64,
+    refund_ratio: RefundRatio,
+) -> (ExecutionResult, U512, U512) {
+    let amount_bytes = U512::from(payment_amount).to_bytes().unwrap();
+    let amount_data: String = amount_bytes.iter().map(|b| format!("\\{:02x}", b)).collect();
+    let payment_wat = format!(
+        r#"
+            (module
+                (import "env" "get_main_purse" (func $get_main_purse (param i32)))
+                (import "env" "get_payment_purse" (func $get_payment_purse (param i32)))
+                (import "env" "transfer_from_purse_to_purse"
+                    (func $transfer (param i32 i32 i32 i32 i32 i32) (result i32)))
+                (memory 1)
+                (data (i32.const 0) "{}")
+                (func (export "call")
+                    (call $get_main_purse (i32.const 100))
+                    (call $get_payment_purse (i32.const 200))
+                    (drop (call $transfer
+                        (i32.const 100) (i32.const {uref_size})
+                        (i32.const 200) (i32.const {uref_size})
+                        (i32.const 0) (i32.const {})))
+                )
+            )
+            "#,
+        amount_data,
+        amount_bytes.len(),
+        uref_size = UREF_SIZE
+    );
+    let payment_binary = wabt::wat2wasm(payment_wat).expect("failed to parse wat");
+    let payment = DeployCode {
+        module_bytes: &payment_binary,
+        args: &[],
+        named_args: &[],
+    };
+    run_deploy_paid_with_config(payment, session_wat, gas_price, refund_ratio)
+}
+
+/// Like `run_paid_deploy`, with the given payment code.
+fn run_deploy_paid_with(payment: DeployCode, session_wat: &str) -> (ExecutionResult, U512, U512) {
+    run_deploy_paid_with_config(payment, session_wat, CONV_RATE, RefundRatio::default())
+}
+
+fn run_deploy_paid_with_config(
+    payment: DeployCode,
+    session_wat: &str,
+    gas_price: u64,
+    refund_ratio: RefundRatio,
+) -> (ExecutionResult, U512, U512) {
+    let session_binary = wabt::wat2wasm(session_wat).expect("failed to parse wat");
+
+    let address = [1u8; 20];
+    let account_purse = PurseId::new([49u8; 32], AccessRights::READ_ADD_WRITE);
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let mut init_state = storage::global_state::mocked_account(address);
+    init_state.insert(
+        mint::balance_key(account_purse),
+        Value::UInt512(U512::from(PAYER_BALANCE)),
+    );
+    init_state.insert(mint::balance_key(rewards_purse()), Value::UInt512(U512::zero()));
+    let standard_payment = Contract::new(Vec::new(), BTreeMap::new(), PROTOCOL_VERSION);
+    init_state.insert(STANDARD_PAYMENT_KEY, Value::Contract(standard_payment));
+    let hist = InMemHist::new_initialized(&root_hash, init_state);
+    let config = EngineConfig::new()
+        .with_use_payment_code(true)
+        .with_rewards_purse(rewards_purse())
+        .with_refund_ratio(refund_ratio)
+        .with_system_contract(SystemContractId::StandardPayment, STANDARD_PAYMENT_KEY);
+    let engine_state = EngineState::with_config(hist, config);
+    let session = DeployCode {
+        module_bytes: &session_binary,
+        args: &[],
+        named_args: &[],
+    };
+    let result = engine_state
+        .run_deploy(
+            session,
+            payment,
+            address,
+            &[PublicKey::new([48u8; 32])],
+            1000,
+            1,
+            [0u8; 32],
+            body_hash(&session, &payment),
+            &[],
+            0,
+            DeployPreconditions::default(),
+            root_hash,
+            Gas::from(1_000_000),
+            gas_price,
+            &WasmiExecutor,
+            &WasmiPreprocessor,
+        )
+        .expect("Root hash should exist.");
+
+    let effect = match (&result.result, &result.fee_effect) {
+        (Ok(effect), _) | (Err(_), Some(effect)) => effect.1.clone(),
+        // Nothing is charged for deploys failing to pay.
+        (Err(_), None) => HashMap::new(),
+    };
+    let post_state_hash = match engine_state.apply_effect(root_hash, effect) {
+        Ok(CommitResult::Success(post_state_hash)) => post_state_hash,
+        _ => panic!("Effects should be committed"),
+    };
+    // Charged deploys are recorded along with their cost.
+    let deploy_info = engine_state
+        .deploy_info(post_state_hash, [0u8; 32])
+        .expect("Deploy info should be read.");
+    match deploy_info {
+        Some(deploy_info) => {
+            assert_eq!(deploy_info.from, address);
+            assert_eq!(deploy_info.source_purse.addr(), account_purse.addr());
+            assert_eq!(deploy_info.gas, result.cost);
+            assert!(deploy_info.transfers.is_empty());
+        }
+        None => assert!(result.result.is_err() && result.fee_effect.is_none()),
+    }
+    let mut tc = engine_state
+        .tracking_copy(post_state_hash)
+        .expect("Checkout should not fail.")
+        .expect("Post state hash should exist.");
+    let account_balance = mint::balance(&mut tc, account_purse).unwrap();
+    let rewards_balance = mint::balance(&mut tc, rewards_purse()).unwrap();
+    (result, account_balance, rewards_balance)
+}
+
+const ARITHMETIC_SESSION: &str = r#"
+            (module
+                (memory 1)
+                (func (export "call")
+                    i32.const 1
+                    i32.const 2
+                    i32.add
+                    drop
+                )
+            )
+            "#;
+
+#[test]
+fn payment_code_buys_gas_for_the_session() {
+    let (result, account_balance, rewards_balance) = run_paid_deploy(100_000, ARITHMETIC_SESSION);
+    assert!(result.result.is_ok());
+    assert!(result.payment_cost > Gas::default());
+    assert!(result.cost > result.payment_cost);
+    // The fee is collected and the rest of the payment is refunded.
+    assert_eq!(rewards_balance, result.cost.value());
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE) - result.cost.value());
+}
+
+#[test]
+fn failed_session_still_pays_fees() {
+    let session = r#"(module (memory 1) (func (export "call") unreachable))"#;
+    let (result, account_balance, rewards_balance) = run_paid_deploy(100_000, session);
+    assert!(result.result.is_err());
+    assert!(result.fee_effect.is_some());
+    assert_eq!(rewards_balance, result.cost.value());
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE) - result.cost.value());
+}
+
+#[test]
+fn session_is_limited_by_purchased_gas() {
+    let session = r#"(module (memory 1) (func (export "call") (loop br 0)))"#;
+    let (result, account_balance, rewards_balance) = run_paid_deploy(1_000, session);
+    match result.result {
+        Err(EngineError::ExecError(execution_engine::execution::Error::GasLimit)) => (),
+        other => panic!("Expected GasLimit, got {:?}", other),
+    }
+    assert!(result.cost <= Gas::from(1_000));
+    assert_eq!(rewards_balance, result.cost.value());
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE) - result.cost.value());
+}
+
+#[test]
+fn unused_payment_is_refunded_at_the_gas_price() {
+    let gas_price = 3;
+    let (result, account_balance, rewards_balance) =
+        run_priced_deploy(100_000, ARITHMETIC_SESSION, gas_price, RefundRatio::default());
+    assert!(result.result.is_ok());
+    let fee = result.cost.as_u64() * gas_price;
+    assert_eq!(rewards_balance, U512::from(fee));
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE - fee));
+}
+
+#[test]
+fn refund_ratio_splits_the_unused_payment() {
+    let payment = 100_000;
+    let gas_price = 2;
+    let quarter = RefundRatio::new(1, 4).unwrap();
+    let (result, account_balance, rewards_balance) =
+        run_priced_deploy(payment, ARITHMETIC_SESSION, gas_price, quarter);
+    assert!(result.result.is_ok());
+    let unused = payment - result.cost.as_u64() * gas_price;
+    let refund = unused / 4;
+    assert!(refund > 0);
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE - payment + refund));
+    assert_eq!(rewards_balance, U512::from(payment - refund));
+
+    // Without refunds the whole payment goes to the rewards purse.
+    let nothing = RefundRatio::new(0, 1).unwrap();
+    let (_, account_balance, rewards_balance) =
+        run_priced_deploy(payment, ARITHMETIC_SESSION, gas_price, nothing);
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE - payment));
+    assert_eq!(rewards_balance, U512::from(payment));
+}
+
+#[test]
+fn failed_session_gets_the_refund_of_its_unused_payment() {
+    let session = r#"(module (memory 1) (func (export "call") unreachable))"#;
+    let payment = 100_000;
+    let half = RefundRatio::new(1, 2).unwrap();
+    let (result, account_balance, rewards_balance) =
+        run_priced_deploy(payment, session, CONV_RATE, half);
+    assert!(result.result.is_err());
+    let refund = (payment - result.cost.as_u64()) / 2;
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE - payment + refund));
+    assert_eq!(rewards_balance, U512::from(payment - refund));
+}
+
+#[test]
+fn deploys_paying_nothing_are_not_executed() {
+    let (result, account_balance, rewards_balance) = run_paid_deploy(0, ARITHMETIC_SESSION);
+    match result.result {
+        Err(EngineError::InsufficientPayment) => (),
+        other => panic!("Expected InsufficientPayment, got {:?}", other),
+    }
+    assert_eq!(result.cost, result.payment_cost);
+    assert!(result.fee_effect.is_none());
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE));
+    assert_eq!(rewards_balance, U512::zero());
+}
+
+#[test]
+fn standard_payment_buys_gas_for_the_session() {
+    let args = vec![U512::from(100_000).to_bytes().unwrap()].to_bytes().unwrap();
+    let payment = DeployCode::standard_payment(&args);
+    let (result, account_balance, rewards_balance) =
+        run_deploy_paid_with(payment, ARITHMETIC_SESSION);
+    assert!(result.result.is_ok());
+    assert_eq!(result.payment_cost, Gas::from(STANDARD_PAYMENT_COST));
+    assert_eq!(rewards_balance, result.cost.value());
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE) - result.cost.value());
+}
+
+#[test]
+fn standard_payment_without_amount_fails() {
+    let payment = DeployCode::standard_payment(&[]);
+    let (result, account_balance, _) = run_deploy_paid_with(payment, ARITHMETIC_SESSION);
+    match result.result {
+        Err(EngineError::PaymentError(error)) => match *error {
+            EngineError::ExecError(execution_engine::execution::Error::Revert(code)) => {
+                assert_eq!(code, u32::from(ApiError::MissingArgument))
+            }
+            other => panic!("Expected a revert, got {:?}", other),
+        },
+        other => panic!("Expected PaymentError, got {:?}", other),
+    }
+    assert_eq!(account_balance, U512::from(PAYER_BALANCE));
+}
+
+#[test]
+fn payment_purse_is_only_available_to_payment_code() {
+    let wat = r#"
+            (module
+                (import "env" "get_payment_purse" (func $get_payment_purse (param i32)))
+                (memory 1)
+                (func (export "call")
+                    (call $get_payment_purse (i32.const 0))
+                )
+            )
+            "#;
+    assert!(run_wat_deploy(wat, EngineConfig::new()).result.is_err());
+}
+
+#[test]
+fn executed_deploys_are_recorded_and_checked() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let address = [1u8; 20];
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account(address);
+    let hist = InMemHist::new_initialized(&root_hash, init_state);
+    let engine_state = EngineState::new(hist);
+    let run = |deploy_hash: [u8; 32], preconditions: DeployPreconditions, root_hash: Blake2bHash| {
+        let session = DeployCode {
+            module_bytes: &wasm_binary,
+            args: &[],
+            named_args: &[],
+        };
+        engine_state
+            .run_deploy(
+                session,
+                session,
+                address,
+                &[PublicKey::new([48u8; 32])],
+                1000,
+                1,
+                deploy_hash,
+                body_hash(&session, &session),
+                &[],
+                0,
+                preconditions,
+                root_hash,
+                Gas::from(1_000_000),
+                CONV_RATE,
+                &WasmiExecutor,
+                &WasmiPreprocessor,
+            )
+            .expect("Root hash should exist.")
+            .result
+    };
+    let first_hash = [1u8; 32];
+    let second_hash = [2u8; 32];
+    let dependencies = [first_hash];
+    let depends_on_first = DeployPreconditions {
+        dependencies: &dependencies,
+        ..Default::default()
+    };
+    match run(second_hash, depends_on_first, root_hash) {
+        Err(EngineError::Precondition(PreconditionError::MissingDependency(hash))) => {
+            assert_eq!(hash, first_hash)
+        }
+        other => panic!("Expected a missing dependency, got {:?}", other),
+    }
+    let expired = DeployPreconditions {
+        ttl_millis: 100,
+        block_time: 2000,
+        ..Default::default()
+    };
+    match run(first_hash, expired, root_hash) {
+        Err(EngineError::Precondition(PreconditionError::Expired { expiry, .. })) => {
+            assert_eq!(expiry, 1100)
+        }
+        other => panic!("Expected an expired deploy, got {:?}", other),
+    }
+
+    let effect = run(first_hash, DeployPreconditions::default(), root_hash)
+        .expect("Deploy should be executed.");
+    let post_state_hash = match engine_state.apply_effect(root_hash, effect.1) {
+        Ok(CommitResult::Success(post_state_hash)) => post_state_hash,
+        _ => panic!("Effects should be committed"),
+    };
+    match run(first_hash, DeployPreconditions::default(), post_state_hash) {
+        Err(EngineError::Precondition(PreconditionError::AlreadyExecuted(hash))) => {
+            assert_eq!(hash, first_hash)
+        }
+        other => panic!("Expected a replayed deploy, got {:?}", other),
+    }
+    assert!(run(second_hash, depends_on_first, post_state_hash).is_ok());
+}
+
+fn block_deploy<'a>(
+    module_bytes: &'a [u8],
+    authorization_keys: &'a [PublicKey],
+    deploy_hash: [u8; 32],
+    dependencies: &'a [[u8; 32]],
+) -> Deploy<'a> {
+    let code = DeployCode {
+        module_bytes,
+        args: &[],
+        named_args: &[],
+    };
+    Deploy {
+        session: code,
+        payment: code,
+        address: [1u8; 20],
+        authorization_keys,
+        timestamp: 1000,
+        nonce: 1,
+        deploy_hash,
+        body_hash: body_hash(&code, &code),
+        approvals: &[],
+        preconditions: DeployPreconditions {
+            dependencies,
+            ..Default::default()
+        },
+        gas_limit: Gas::from(1_000_000),
+        gas_price: CONV_RATE,
+    }
+}
+
+#[test]
+fn deploys_of_a_block_see_the_effects_of_earlier_deploys() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    // Public key of the account created by `mocked_account`.
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let first_hash = [1u8; 32];
+    let dependencies = [first_hash];
+    let deploys = [
+        block_deploy(&wasm_binary, &authorization_keys, first_hash, &[]),
+        block_deploy(&wasm_binary, &authorization_keys, [2u8; 32], &dependencies),
+        block_deploy(&wasm_binary, &authorization_keys, first_hash, &[]),
+        block_deploy(&wasm_binary, &authorization_keys, [3u8; 32], &[]),
+    ];
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    // Running them concurrently doesn't change the results.
+    for threads in &[1, 4] {
+        let init_state = storage::global_state::mocked_account([1u8; 20]);
+        let hist = InMemHist::new_initialized(&root_hash, init_state);
+        let config = EngineConfig::new().with_execution_threads(*threads);
+        let engine_state = EngineState::with_config(hist, config);
+        let results = engine_state
+            .run_deploys(&deploys, 0, root_hash, &WasmiExecutor, &WasmiPreprocessor)
+            .expect("Root hash should exist.");
+        assert_eq!(results.len(), deploys.len());
+        assert!(results[0].result.is_ok());
+        // The dependency was executed by the first deploy of the block.
+        assert!(results[1].result.is_ok());
+        match &results[2].result {
+            Err(EngineError::Precondition(PreconditionError::AlreadyExecuted(hash))) => {
+                assert_eq!(*hash, first_hash)
+            }
+            other => panic!("Expected a replayed deploy, got {:?}", other),
+        }
+        assert!(results[3].result.is_ok());
+        // The state the second deploy was run again on isn't kept.
+        let effects = match &results[0].result {
+            Ok(effect) => effect.1.clone(),
+            Err(error) => panic!("Expected the first deploy to succeed, got {:?}", error),
+        };
+        let init_state = storage::global_state::mocked_account([1u8; 20]);
+        let hist = InMemHist::new_initialized(&root_hash, init_state);
+        let block_state_hash = match hist.commit(root_hash, effects) {
+            Ok(CommitResult::Success(state_hash)) => state_hash,
+            _ => panic!("Effects should be committed"),
+        };
+        assert!(engine_state
+            .tracking_copy(block_state_hash)
+            .expect("Checkout should not fail.")
+            .is_none());
+    }
+}
+
+#[test]
+fn oversized_deploys_are_rejected_without_charge() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let deploy = block_deploy(&wasm_binary, &authorization_keys, [1u8; 32], &[]);
+    // The session and the payment code are both counted.
+    assert_eq!(deploy.size(), 2 * wasm_binary.len());
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let run = |max_deploy_size| {
+        let init_state = storage::global_state::mocked_account([1u8; 20]);
+        let hist = InMemHist::new_initialized(&root_hash, init_state);
+        let config = EngineConfig::new().with_max_deploy_size(max_deploy_size);
+        EngineState::with_config(hist, config)
+            .speculative_exec(root_hash, &deploy, 0, &WasmiExecutor, &WasmiPreprocessor)
+            .expect("Root hash should exist.")
+    };
+    assert!(run(deploy.size()).result.is_ok());
+    let result = run(deploy.size() - 1);
+    match result.result {
+        Err(EngineError::Precondition(PreconditionError::DeployTooLarge { size, max_size })) => {
+            assert_eq!((size, max_size), (deploy.size(), deploy.size() - 1))
+        }
+        other => panic!("Expected an oversized deploy, got {:?}", other),
+    }
+    assert_eq!(result.cost, Gas::default());
+}
+
+#[test]
+fn blocks_stop_taking_deploys_at_their_gas_limit() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let (module_bytes, authorization_keys) = (&wasm_binary[..], &authorization_keys[..]);
+    let deploy = move |deploy_hash, gas_limit: u64| Deploy {
+        gas_limit: Gas::from(gas_limit),
+        ..block_deploy(module_bytes, authorization_keys, deploy_hash, &[])
+    };
+    let deploys = [
+        deploy([1u8; 32], 1_000_000),
+        deploy([2u8; 32], 1_000_000),
+        deploy([3u8; 32], 1_000_000),
+        deploy([4u8; 32], 10),
+    ];
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account([1u8; 20]);
+    let hist = InMemHist::new_initialized(&root_hash, init_state);
+    // Enough for the first two deploys, which use 4 gas each.
+    let config = EngineConfig::new().with_block_gas_limit(1_000_004);
+    let engine_state = EngineState::with_config(hist, config);
+    let results = engine_state
+        .run_deploys(&deploys, 0, root_hash, &WasmiExecutor, &WasmiPreprocessor)
+        .expect("Root hash should exist.");
+    assert!(results[0].result.is_ok());
+    assert!(results[1].result.is_ok());
+    match &results[2].result {
+        Err(EngineError::Precondition(PreconditionError::BlockGasLimitExceeded {
+            max_cost,
+            remaining,
+        })) => assert_eq!((*max_cost, *remaining), (Gas::from(1_000_000), Gas::from(999_996))),
+        other => panic!("Expected the block to be full, got {:?}", other),
+    }
+    assert_eq!(results[2].cost, Gas::default());
+    // Smaller deploys still fit in what's left.
+    assert!(results[3].result.is_ok());
+}
+
+#[test]
+fn journaled_deploys_replay_without_the_global_state() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let deploy = block_deploy(&wasm_binary, &authorization_keys, [1u8; 32], &[]);
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account([1u8; 20]);
+    let engine_state = EngineState::with_config(
+        InMemHist::new_initialized(&root_hash, init_state),
+        EngineConfig::new().with_journaling(true),
+    );
+    let result = engine_state
+        .speculative_exec(root_hash, &deploy, 0, &WasmiExecutor, &WasmiPreprocessor)
+        .expect("Root hash should exist.");
+    assert!(result.result.is_ok());
+    let journal = result.journal.expect("Deploy should be journaled.");
+    assert_eq!(journal.deploy_hash, [1u8; 32]);
+    // Everything the deploy paid for was charged by the runtime.
+    let charged = journal
+        .entries
+        .iter()
+        .filter_map(|entry| match entry {
+            JournalEntry::Gas(gas) => Some(*gas),
+            _ => None,
+        })
+        .fold(Gas::default(), |sum, gas| sum.saturating_add(gas));
+    assert_eq!(charged, result.cost);
+
+    // An engine without the prestate replays the deploy from its journal.
+    let engine_state = EngineState::new(InMemHist::new(&root_hash));
+    let replay = engine_state.replay(&deploy, &journal, &WasmiExecutor, &WasmiPreprocessor);
+    assert!(replay.is_identical());
+    assert!(replay.changed_keys.is_empty());
+    assert_eq!(replay.journal, journal);
+
+    // A journal missing its last write diverges where it ends.
+    let mut truncated = journal.clone();
+    let last = truncated.entries.pop();
+    let replay = engine_state.replay(&deploy, &truncated, &WasmiExecutor, &WasmiPreprocessor);
+    let divergence = replay.divergence.expect("Replay should diverge.");
+    assert_eq!(divergence.index, truncated.entries.len());
+    assert_eq!((divergence.recorded, divergence.replayed), (None, last));
+}
+
+#[test]
+fn speculative_execution_commits_nothing() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let deploy_hash = [1u8; 32];
+    let deploy = block_deploy(&wasm_binary, &authorization_keys, deploy_hash, &[]);
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account([1u8; 20]);
+    let engine_state = EngineState::new(InMemHist::new_initialized(&root_hash, init_state));
+    let speculative_exec = || {
+        engine_state
+            .speculative_exec(root_hash, &deploy, 0, &WasmiExecutor, &WasmiPreprocessor)
+            .expect("Root hash should exist.")
+    };
+    let first = speculative_exec();
+    let effect = first.result.expect("Deploy should be executed.");
+    assert!(first.cost > Gas::default());
+    // The deploy would be recorded if its effects were committed.
+    assert!(effect.1.contains_key(&Key::DeployInfo(deploy_hash)));
+
+    // Nothing was recorded, so the deploy can be run again.
+    let second = speculative_exec();
+    assert!(second.result.is_ok());
+    assert_eq!(second.cost, first.cost);
+    let mut tc = engine_state
+        .tracking_copy(root_hash)
+        .expect("Checkout should not fail.")
+        .expect("Root hash should exist.");
+    assert_eq!(tc.get(&Key::DeployInfo(deploy_hash)).unwrap(), None);
+}
+
+#[test]
+fn created_addresses_depend_only_on_the_deploy() {
+    let wat = r#"
+            (module
+                (import "env" "create_purse" (func $create_purse (param i32)))
+                (memory 1)
+                (func (export "call")
+                    (call $create_purse (i32.const 0))
+                    (call $create_purse (i32.const 100))))
+            "#;
+    let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account([1u8; 20]);
+    let engine_state = EngineState::new(InMemHist::new_initialized(&root_hash, init_state));
+    let written_keys = |deploy_hash| {
+        let deploy = block_deploy(&wasm_binary, &authorization_keys, deploy_hash, &[]);
+        let effect = engine_state
+            .speculative_exec(root_hash, &deploy, 0, &WasmiExecutor, &WasmiPreprocessor)
+            .expect("Root hash should exist.")
+            .result
+            .expect("Deploy should be executed.");
+        effect
+            .1
+            .keys()
+            .filter(|key| key != &&Key::DeployInfo(deploy_hash))
+            .cloned()
+            .collect::<HashSet<Key>>()
+    };
+    let keys = written_keys([1u8; 32]);
+    assert_eq!(written_keys([1u8; 32]), keys);
+    // The purses of another deploy are created at other addresses.
+    let other_keys = written_keys([2u8; 32]);
+    assert_eq!(other_keys.len(), keys.len());
+    assert!(keys.iter().any(|key| !other_keys.contains(key)));
+}
+
+#[test]
+fn repeated_code_is_preprocessed_once() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account([1u8; 20]);
+    let engine_state = EngineState::new(InMemHist::new_initialized(&root_hash, init_state));
+    let preprocessor = CachingPreprocessor::new(WasmiPreprocessor, engine_state.module_cache());
+    let costs: Vec<Gas> = [[1u8; 32], [2u8; 32]]
+        .iter()
+        .map(|deploy_hash| {
+            let deploy = block_deploy(&wasm_binary, &authorization_keys, *deploy_hash, &[]);
+            let result = engine_state
+                .speculative_exec(root_hash, &deploy, 0, &WasmiExecutor, &preprocessor)
+                .expect("Root hash should exist.");
+            assert!(result.result.is_ok());
+            result.cost
+        })
+        .collect();
+    // The cached module is instrumented the same way.
+    assert_eq!(costs[0], costs[1]);
+    let metrics = engine_state.module_cache().metrics();
+    assert_eq!((metrics.hits, metrics.misses), (1, 1));
+    assert_eq!(engine_state.module_cache().len(), 1);
+}
+
+#[test]
+fn failed_deploys_report_the_kind_of_their_error() {
+    let error_code = |wat: &str| match run_wat_deploy(wat, EngineConfig::new()).result {
+        Err(error) => error.code(),
+        Ok(_) => panic!("Deploy should fail"),
+    };
+    let trap = r#"(module (memory 1) (func (export "call") unreachable))"#;
+    assert_eq!(error_code(trap), ErrorCode::Trap);
+    // Host functions failing inside the trap are what's reported.
+    let host_error = r#"
+            (module
+                (import "env" "get_payment_purse" (func $get_payment_purse (param i32)))
+                (memory 1)
+                (func (export "call")
+                    (call $get_payment_purse (i32.const 0))
+                )
+            )
+            "#;
+    assert_eq!(error_code(host_error), ErrorCode::Execution);
+}
+
+#[test]
+fn deploys_from_the_system_account_are_rejected_without_charge() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let deploy = Deploy {
+        address: SYSTEM_ACCOUNT,
+        ..block_deploy(&wasm_binary, &authorization_keys, [1u8; 32], &[])
+    };
+    let root_hash: Blake2bHash = [0u8; 32].into();
+    let init_state = storage::global_state::mocked_account(SYSTEM_ACCOUNT);
+    let engine_state = EngineState::new(InMemHist::new_initialized(&root_hash, init_state));
+    let result = engine_state
+        .run_deploys(&[deploy], 0, root_hash, &WasmiExecutor, &WasmiPreprocessor)
+        .expect("Root hash should exist.")
+        .remove(0);
+    match &result.result {
+        Err(EngineError::ExecError(execution_engine::execution::Error::SystemContext)) => (),
+        other => panic!("Expected the deploy to be rejected, got {:?}", other),
+    }
+    assert_eq!(result.cost, Gas::default());
+    assert!(result.fee_effect.is_none());
+}
+
+#[test]
+fn approvals_are_verified_if_the_config_asks_for_it() {
+    let wasm_binary = wabt::wat2wasm(ARITHMETIC_SESSION).expect("failed to parse wat");
+    let authorization_keys = [PublicKey::new([48u8; 32])];
+    let unapproved = block_deploy(&wasm_binary, &authorization_keys, [1u8; 32], &[]);
+    let unapproved = Deploy {
+        deploy_hash: deploy_hash(&unapproved, &unapproved.body_hash),
+        ..unapproved
+    };
+    let forged = [Approval {
+        public_key: authorization_keys[0],
+        signature: vec![0u8; 64],
+    }];
+    let run = |deploy: Deploy, verify_approvals: bool| {
+        let root_hash: Blake2bHash = [0u8; 32].into();
+        let init_state = storage::global_state::mocked_account([1u8; 20]);
+        let hist = InMemHist::new_initialized(&root_hash, init_state);
+        let config = EngineConfig::new().with_verify_approvals(verify_approvals);
+        EngineState::with_config(hist, config)
+            .run_deploys(&[deploy], 0, root_hash, &WasmiExecutor, &WasmiPreprocessor)
+            .expect("Root hash should exist.")
+            .remove(0)
+    };
+    // The node verifies approvals, so the engine doesn't by default.
+    assert!(run(unapproved, false).result.is_ok());
+
+    let result = run(unapproved, true);
+    match &result.result {
+        Err(EngineError::Approval(ApprovalError::MissingApproval(key))) => {
+            assert_eq!(*key, authorization_keys[0])
+        }
+        other => panic!("Expected a missing approval, got {:?}", other),
+    }
+    assert_eq!(result.cost, Gas::default());
+    let code = |result: ExecutionResult| match result.result {
+        Err(error) => error.code(),
+        Ok(_) => panic!("Deploy should be rejected"),
+    };
+    assert_eq!(code(result), ErrorCode::InvalidSignature);
+
+    let deploy = Deploy {
+        approvals: &forged,
+        ..unapproved
+    };
+    assert_eq!(code(run(deploy, true)), ErrorCode::InvalidSignature);
+    let deploy = Deploy {
+        body_hash: [0u8; 32],
+        ..unapproved
+    };
+    assert_eq!(code(run(deploy, true)), ErrorCode::InvalidDeployHash);
+    let deploy = Deploy {
+        deploy_hash: [1u8; 32],
+        ..unapproved
+    };
+    assert_eq!(code(run(deploy, true)), ErrorCode::InvalidDeployHash);
+}
+
+#[test]
+fn executor_refuses_to_run_in_the_system_context() {
+    let exec = |address: [u8; 20], phase: Phase| {
+        let (key, account) = mock_account(address);
+        let mut tc = mock_tc(key, &account);
+        let (result, cost) = WasmiExecutor.exec(
+            mock_module(),
+            &[],
+            &[],
+            address,
+            BTreeSet::new(),
+            [1u8; 32],
+            0,
+            Gas::from(1_000_000),
+            phase,
+            &EngineConfig::new(),
+            &mut tc,
+        );
+        (result.err(), cost)
+    };
+    for &(address, phase) in &[
+        ([1u8; 20], Phase::System),
+        ([1u8; 20], Phase::FinalizePayment),
+        (SYSTEM_ACCOUNT, Phase::Session),
+    ] {
+        match exec(address, phase) {
+            (Some(execution_engine::execution::Error::SystemContext), cost)
+                if cost == Gas::default() => {}
+            other => panic!("Expected {:?} to be refused, got {:?}", phase, other),
+        }
+    }
+    // The module has no `call` export, so deploys fail later on.
+    match exec([1u8; 20], Phase::Session) {
+        (Some(execution_engine::execution::Error::SystemContext), _) | (None, _) => {
+            panic!("Session code should get past the system context check")
+        }
+        _ => (),
+    }
+}
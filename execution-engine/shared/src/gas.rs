@@ -0,0 +1,95 @@
+//! Gas, the unit deploys are charged in.
+
+use std::fmt;
+
+use common::value::U512;
+
+use crate::motes::Motes;
+
+/// Amount of gas. Deploys buy it with motes at the gas price they set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Gas(U512);
+
+impl Gas {
+    pub fn new(value: U512) -> Self {
+        Gas(value)
+    }
+
+    pub fn value(&self) -> U512 {
+        self.0
+    }
+
+    /// Gas bought by `motes` at `gas_price` motes per unit of gas, rounded
+    /// down. There's none at a price of zero.
+    pub fn from_motes(motes: Motes, gas_price: u64) -> Option<Self> {
+        motes.value().checked_div(U512::from(gas_price)).map(Gas)
+    }
+
+    pub fn checked_add(&self, rhs: Gas) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Gas)
+    }
+
+    pub fn saturating_add(&self, rhs: Gas) -> Self {
+        Gas(self.0.saturating_add(rhs.0))
+    }
+
+    pub fn saturating_sub(&self, rhs: Gas) -> Self {
+        Gas(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Value capped at what a `u64` holds, which is how gas is reported to
+    /// contracts and clients.
+    pub fn as_u64(&self) -> u64 {
+        if self.0 > U512::from(u64::max_value()) {
+            u64::max_value()
+        } else {
+            self.0.as_u64()
+        }
+    }
+}
+
+impl From<u64> for Gas {
+    fn from(value: u64) -> Self {
+        Gas(U512::from(value))
+    }
+}
+
+impl fmt::Display for Gas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::value::U512;
+
+    use super::Gas;
+    use crate::motes::Motes;
+
+    #[test]
+    fn motes_buy_gas_at_the_gas_price() {
+        let motes = Motes::new(U512::from(10));
+        assert_eq!(Gas::from_motes(motes, 1), Some(Gas::from(10)));
+        assert_eq!(Gas::from_motes(motes, 3), Some(Gas::from(3)));
+        assert_eq!(Gas::from_motes(motes, 11), Some(Gas::from(0)));
+        assert_eq!(Gas::from_motes(motes, 0), None);
+    }
+
+    #[test]
+    fn gas_is_priced_in_motes() {
+        assert_eq!(Motes::from_gas(Gas::from(10), 3), Some(Motes::new(U512::from(30))));
+        assert_eq!(Motes::from_gas(Gas::from(10), 0), Some(Motes::default()));
+        assert_eq!(Motes::from_gas(Gas::new(U512::max_value()), 2), None);
+    }
+
+    #[test]
+    fn gas_is_reported_as_u64() {
+        assert_eq!(Gas::from(7).as_u64(), 7);
+        assert_eq!(Gas::new(U512::max_value()).as_u64(), u64::max_value());
+        assert_eq!(Gas::from(7).saturating_sub(Gas::from(9)), Gas::default());
+        let max = Gas::new(U512::max_value());
+        assert_eq!(max.checked_add(Gas::from(1)), None);
+        assert_eq!(max.saturating_add(Gas::from(1)), max);
+    }
+}
@@ -1,5 +1,8 @@
 extern crate blake2;
 extern crate common;
 
+pub mod gas;
+pub mod motes;
 pub mod newtypes;
+pub mod ratio;
 pub mod test_utils;
@@ -0,0 +1,41 @@
+//! Motes, the smallest unit of the tokens held in purses.
+
+use std::fmt;
+
+use common::value::U512;
+
+use crate::gas::Gas;
+
+/// Amount of motes, such as what a deploy pays for its gas.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Motes(U512);
+
+impl Motes {
+    pub fn new(value: U512) -> Self {
+        Motes(value)
+    }
+
+    pub fn value(&self) -> U512 {
+        self.0
+    }
+
+    /// Price of `gas` at `gas_price` motes per unit of gas, unless it
+    /// overflows.
+    pub fn from_gas(gas: Gas, gas_price: u64) -> Option<Self> {
+        gas.value().checked_mul(U512::from(gas_price)).map(Motes)
+    }
+
+    pub fn checked_add(&self, rhs: Motes) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Motes)
+    }
+
+    pub fn checked_sub(&self, rhs: Motes) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Motes)
+    }
+}
+
+impl fmt::Display for Motes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
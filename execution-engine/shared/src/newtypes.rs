@@ -27,6 +27,11 @@ impl Blake2bHash {
     pub fn to_vec(&self) -> Vec<u8> {
         self.0.to_vec()
     }
+
+    /// Returns the underlying BLAKE2b hash digest array
+    pub fn value(&self) -> [u8; BLAKE2B_DIGEST_LENGTH] {
+        self.0
+    }
 }
 
 impl From<[u8; BLAKE2B_DIGEST_LENGTH]> for Blake2bHash {
@@ -0,0 +1,82 @@
+//! Ratios of token amounts, such as rates of seigniorage.
+
+use std::fmt;
+
+use common::value::U512;
+
+/// Ratio of two integers, applied to amounts of tokens with the result
+/// rounded down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ratio {
+    numerator: u64,
+    denominator: u64,
+}
+
+impl Ratio {
+    /// Ratio of `numerator` to `denominator`, unless the denominator is zero.
+    pub fn new(numerator: u64, denominator: u64) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        Some(Ratio {
+            numerator,
+            denominator,
+        })
+    }
+
+    pub fn numerator(&self) -> u64 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> u64 {
+        self.denominator
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    /// Part of `amount` given by the ratio, rounded down, unless computing it
+    /// overflows.
+    pub fn of(&self, amount: U512) -> Option<U512> {
+        amount
+            .checked_mul(U512::from(self.numerator))
+            .map(|product| product / U512::from(self.denominator))
+    }
+}
+
+/// The ratio of zero.
+impl Default for Ratio {
+    fn default() -> Self {
+        Ratio {
+            numerator: 0,
+            denominator: 1,
+        }
+    }
+}
+
+impl fmt::Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::value::U512;
+
+    use super::Ratio;
+
+    #[test]
+    fn ratios_round_down() {
+        let ratio = Ratio::new(1, 3).expect("ratio should be valid");
+        assert_eq!(ratio.of(U512::from(10)), Some(U512::from(3)));
+        assert_eq!(ratio.of(U512::zero()), Some(U512::zero()));
+        assert_eq!(Ratio::new(1, 0), None);
+        assert!(Ratio::default().is_zero());
+        assert_eq!(Ratio::default().of(U512::from(10)), Some(U512::zero()));
+        let above_one = Ratio::new(3, 2).expect("ratio should be valid");
+        assert_eq!(above_one.of(U512::from(10)), Some(U512::from(15)));
+        assert_eq!(above_one.of(U512::max_value()), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Time spent compressing and decompressing tries, against the space saved.
+//!
+//! Run with `cargo bench --features compression`. The throughput is of the
+//! raw tries, and the size each trie is stored at is printed once per
+//! benchmark, on stderr.
+
+#![feature(test)]
+
+extern crate common;
+extern crate shared;
+extern crate storage;
+extern crate test;
+
+use std::sync::{Once, ONCE_INIT};
+
+use common::bytesrepr::ToBytes;
+use shared::newtypes::Blake2bHash;
+use storage::history::trie::{Pointer, PointerBlock, Trie};
+use storage::history::trie_store::compression::{self, Compression};
+use test::Bencher;
+
+/// Slots of a node.
+const RADIX: usize = 256;
+
+/// Node pointing to `count` tries, every `RADIX / count` slots.
+fn node(count: usize) -> Vec<u8> {
+    let mut pointer_block = PointerBlock::new();
+    for index in (0..RADIX).step_by(RADIX / count) {
+        let hash = Blake2bHash::new(&[index as u8]);
+        pointer_block[index] = Some(Pointer::NodePointer(hash));
+    }
+    let node: Trie<Vec<u8>, Vec<u8>> = Trie::Node {
+        pointer_block: Box::new(pointer_block),
+    };
+    node.to_bytes().unwrap()
+}
+
+fn leaf() -> Vec<u8> {
+    let leaf: Trie<Vec<u8>, Vec<u8>> = Trie::Leaf {
+        key: vec![7u8; 33],
+        value: vec![1u8; 64],
+    };
+    leaf.to_bytes().unwrap()
+}
+
+fn report(name: &str, trie_bytes: &[u8], compression: &Compression) {
+    let stored = compression::encode(trie_bytes.to_vec(), Some(compression)).unwrap();
+    eprintln!(
+        "{}: {} bytes stored at {} bytes with level {}",
+        name,
+        trie_bytes.len(),
+        stored.len(),
+        compression.level()
+    );
+}
+
+fn bench_encode(b: &mut Bencher, once: &Once, name: &str, trie_bytes: Vec<u8>, level: i32) {
+    let compression = Compression::zstd().with_level(level).with_threshold(0);
+    once.call_once(|| report(name, &trie_bytes, &compression));
+    b.bytes = trie_bytes.len() as u64;
+    b.iter(|| compression::encode(trie_bytes.clone(), Some(&compression)).unwrap());
+}
+
+fn bench_decode(b: &mut Bencher, trie_bytes: Vec<u8>, level: i32) {
+    let compression = Compression::zstd().with_level(level).with_threshold(0);
+    let stored = compression::encode(trie_bytes.clone(), Some(&compression)).unwrap();
+    b.bytes = trie_bytes.len() as u64;
+    b.iter(|| compression::decode(&stored).unwrap().len());
+}
+
+#[bench]
+fn encode_sparse_node(b: &mut Bencher) {
+    static ONCE: Once = ONCE_INIT;
+    bench_encode(b, &ONCE, "sparse node", node(2), compression::DEFAULT_LEVEL);
+}
+
+#[bench]
+fn encode_half_full_node(b: &mut Bencher) {
+    static ONCE: Once = ONCE_INIT;
+    bench_encode(b, &ONCE, "half full node", node(RADIX / 2), compression::DEFAULT_LEVEL);
+}
+
+#[bench]
+fn encode_full_node(b: &mut Bencher) {
+    static ONCE: Once = ONCE_INIT;
+    bench_encode(b, &ONCE, "full node", node(RADIX), compression::DEFAULT_LEVEL);
+}
+
+#[bench]
+fn encode_full_node_at_level_19(b: &mut Bencher) {
+    static ONCE: Once = ONCE_INIT;
+    bench_encode(b, &ONCE, "full node", node(RADIX), 19);
+}
+
+#[bench]
+fn encode_leaf(b: &mut Bencher) {
+    static ONCE: Once = ONCE_INIT;
+    bench_encode(b, &ONCE, "leaf", leaf(), compression::DEFAULT_LEVEL);
+}
+
+#[bench]
+fn decode_sparse_node(b: &mut Bencher) {
+    bench_decode(b, node(2), compression::DEFAULT_LEVEL);
+}
+
+#[bench]
+fn decode_full_node(b: &mut Bencher) {
+    bench_decode(b, node(RADIX), compression::DEFAULT_LEVEL);
+}
+
+#[bench]
+fn decode_raw_full_node(b: &mut Bencher) {
+    let trie_bytes = node(RADIX);
+    b.bytes = trie_bytes.len() as u64;
+    b.iter(|| compression::decode(&trie_bytes).unwrap().len());
+}
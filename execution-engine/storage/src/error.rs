@@ -1,5 +1,9 @@
 use common::bytesrepr;
+use history::trie_store::compression::CompressionError;
+use history::trie_store::in_memory;
 use lmdb;
+use rocksdb;
+use shared::newtypes::Blake2bHash;
 use wasmi;
 
 #[derive(Debug, Fail, PartialEq, Eq)]
@@ -7,11 +11,26 @@ pub enum Error {
     #[fail(display = "{}", _0)]
     Lmdb(#[fail(cause)] lmdb::Error),
 
+    /// The message of an error of RocksDB.
+    #[fail(display = "{}", _0)]
+    RocksDb(String),
+
     #[fail(display = "{}", _0)]
     BytesRepr(#[fail(cause)] bytesrepr::Error),
 
     #[fail(display = "Another thread panicked while holding a lock")]
     PoisonError,
+
+    #[fail(display = "{}", _0)]
+    Compression(#[fail(cause)] CompressionError),
+
+    /// A record didn't match the key it's stored at, or its checksum.
+    #[fail(display = "Corruption detected in the record at {:?}", key)]
+    CorruptionDetected { key: Vec<u8> },
+
+    /// Some tries of the states with the given root hashes aren't in the store.
+    #[fail(display = "Tries of {:?} are missing", roots)]
+    MissingTries { roots: Vec<Blake2bHash> },
 }
 
 impl wasmi::HostError for Error {}
@@ -22,12 +41,33 @@ impl From<lmdb::Error> for Error {
     }
 }
 
+impl From<rocksdb::Error> for Error {
+    fn from(e: rocksdb::Error) -> Self {
+        Error::RocksDb(e.into_string())
+    }
+}
+
 impl From<bytesrepr::Error> for Error {
     fn from(e: bytesrepr::Error) -> Self {
         Error::BytesRepr(e)
     }
 }
 
+impl From<CompressionError> for Error {
+    fn from(e: CompressionError) -> Self {
+        Error::Compression(e)
+    }
+}
+
+impl From<in_memory::Error> for Error {
+    fn from(e: in_memory::Error) -> Self {
+        match e {
+            in_memory::Error::BytesRepr(e) => Error::BytesRepr(e),
+            in_memory::Error::PoisonError => Error::PoisonError,
+        }
+    }
+}
+
 impl<T> From<std::sync::PoisonError<T>> for Error {
     fn from(_e: std::sync::PoisonError<T>) -> Self {
         Error::PoisonError
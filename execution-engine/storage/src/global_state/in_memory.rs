@@ -0,0 +1,436 @@
+//! Global state kept in memory, for tests which shouldn't need a database
+//! on disk.
+//!
+//! The states are kept as tries in an
+//! [`InMemoryEnvironment`](crate::history::trie_store::in_memory::InMemoryEnvironment),
+//! the same way [`LmdbGlobalState`](crate::global_state::lmdb::LmdbGlobalState)
+//! keeps them, so their root hashes are the same in both, and the states
+//! committed at a checkpoint are held apart from them the same way too.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use common::key::Key;
+use common::trie_merkle_proof::TrieMerkleProof;
+use common::value::Value;
+use error;
+use global_state::pending::{self, Checkpoints};
+use global_state::snapshot::{self, SnapshotError};
+use global_state::stored_value::StoredValue;
+use global_state::{self, StateReader};
+use history::trie_store::in_memory::{InMemoryEnvironment, InMemoryTrieStore};
+use history::trie_store::operations::{self, ReadResult};
+use history::trie_store::overlay::{OverlayTrieStore, TrieBatch};
+use history::trie_store::{Transaction, TransactionSource};
+use history::{CheckpointId, CommitResult, History, PruneResult};
+use metrics::{OperationTimer, StoreMetrics, StoreOperation};
+use shared::newtypes::Blake2bHash;
+use transform::Transform;
+
+/// Global state kept in an [`InMemoryEnvironment`].
+pub struct InMemoryGlobalState {
+    environment: Arc<InMemoryEnvironment>,
+    trie_store: InMemoryTrieStore,
+    /// Behind a lock, so that commits can add their roots through a shared
+    /// reference.
+    roots: RwLock<HashSet<Blake2bHash>>,
+    empty_root_hash: Blake2bHash,
+    checkpoints: Checkpoints,
+}
+
+impl InMemoryGlobalState {
+    /// Global state with only the empty state.
+    pub fn empty() -> Result<InMemoryGlobalState, error::Error> {
+        let environment = Arc::new(InMemoryEnvironment::new());
+        let trie_store = InMemoryTrieStore::new(&environment);
+        let empty_root_hash = {
+            let mut txn = environment.create_read_write_txn()?;
+            let empty_trie = operations::empty_trie::<Key, Value>();
+            let empty_root_hash = operations::put_trie(&mut txn, &trie_store, &empty_trie)?;
+            txn.commit()?;
+            empty_root_hash
+        };
+        let mut roots = HashSet::new();
+        roots.insert(empty_root_hash);
+        Ok(InMemoryGlobalState {
+            environment,
+            trie_store,
+            roots: RwLock::new(roots),
+            empty_root_hash,
+            checkpoints: Checkpoints::new(),
+        })
+    }
+
+    /// Global state with the state holding `pairs` committed on top of the
+    /// empty state, whose root hash is returned with it.
+    pub fn from_pairs(
+        pairs: &[(Key, Value)],
+    ) -> Result<(InMemoryGlobalState, Blake2bHash), error::Error> {
+        let global_state = InMemoryGlobalState::empty()?;
+        let effects: HashMap<Key, Transform> = pairs
+            .iter()
+            .map(|(key, value)| (*key, Transform::Write(value.clone())))
+            .collect();
+        let empty_root_hash = global_state.empty_root_hash;
+        match global_state.commit(empty_root_hash, effects)? {
+            CommitResult::Success(root_hash) => Ok((global_state, root_hash)),
+            // Writes to the empty state can't fail.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Root hash of the state without any values.
+    pub fn empty_root_hash(&self) -> Blake2bHash {
+        self.empty_root_hash
+    }
+
+    /// The global state, reporting how long its operations take and the
+    /// tries it reads and writes to `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn StoreMetrics>) -> InMemoryGlobalState {
+        self.trie_store = self.trie_store.with_metrics(metrics);
+        self
+    }
+
+    /// Writes a snapshot of the state at `root_hash` to `writer`, with at
+    /// most `chunk_size` tries per chunk.
+    pub fn export_snapshot<W: Write>(
+        &self,
+        root_hash: Blake2bHash,
+        chunk_size: usize,
+        writer: &mut W,
+    ) -> Result<(), SnapshotError> {
+        let txn = self.environment.create_read_txn()?;
+        snapshot::export(&txn, &self.trie_store, &root_hash, chunk_size, writer)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Imports the state of the snapshot read from `reader`, which can be
+    /// checked out once its root hash is returned.
+    pub fn import_snapshot<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Blake2bHash, SnapshotError> {
+        let root_hash = snapshot::import(&*self.environment, &self.trie_store, reader)?;
+        self.roots.get_mut().insert(root_hash);
+        Ok(root_hash)
+    }
+
+    /// Tries of the state at `root_hash` which a checkpoint holds, none if
+    /// the state is committed. Returns `None` if there's no such state.
+    fn find_state(&self, root_hash: &Blake2bHash) -> Option<Arc<TrieBatch<Key, Value>>> {
+        match self.checkpoints.find(root_hash) {
+            Some(pending) => Some(Arc::clone(pending.tries())),
+            None if self.roots.read().contains(root_hash) => Some(Arc::default()),
+            None => None,
+        }
+    }
+}
+
+/// A state in an [`InMemoryGlobalState`].
+#[derive(Clone)]
+pub struct InMemoryGlobalStateView {
+    environment: Arc<InMemoryEnvironment>,
+    trie_store: InMemoryTrieStore,
+    root_hash: Blake2bHash,
+    /// Tries of the state held by a checkpoint.
+    pending_tries: Arc<TrieBatch<Key, Value>>,
+}
+
+impl InMemoryGlobalStateView {
+    pub fn root_hash(&self) -> Blake2bHash {
+        self.root_hash
+    }
+
+    fn missing_tries(&self) -> error::Error {
+        error::Error::MissingTries {
+            roots: vec![self.root_hash],
+        }
+    }
+}
+
+impl StateReader<Key, Value> for InMemoryGlobalStateView {
+    type Error = error::Error;
+
+    fn read(&self, key: &Key) -> Result<Option<Value>, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Read);
+        let txn = self.environment.create_read_txn()?;
+        let trie_store = OverlayTrieStore::new(&self.trie_store, &self.pending_tries);
+        let result = operations::read(&txn, &trie_store, &self.root_hash, key)?;
+        txn.commit()?;
+        match result {
+            ReadResult::Found(value) => Ok(Some(value)),
+            ReadResult::NotFound => Ok(None),
+            // The tries of forgotten states are kept until they're pruned,
+            // so views of them can still be read.
+            ReadResult::RootNotFound => Err(self.missing_tries()),
+        }
+    }
+
+    fn read_with_proof(
+        &self,
+        key: &Key,
+    ) -> Result<Option<TrieMerkleProof<Key, Value>>, Self::Error> {
+        let _timer =
+            OperationTimer::start(self.trie_store.metrics(), StoreOperation::ReadWithProof);
+        let txn = self.environment.create_read_txn()?;
+        let trie_store = OverlayTrieStore::new(&self.trie_store, &self.pending_tries);
+        let result = operations::prove(&txn, &trie_store, &self.root_hash, key)?;
+        txn.commit()?;
+        match result {
+            ReadResult::Found(proof) => Ok(Some(proof)),
+            ReadResult::NotFound => Ok(None),
+            ReadResult::RootNotFound => Err(self.missing_tries()),
+        }
+    }
+
+    fn read_stored(&self, key: &Key) -> Result<Option<StoredValue<Value>>, Self::Error> {
+        if !self.pending_tries.is_empty() {
+            // The tries a checkpoint holds are kept decoded.
+            return Ok(self.read(key)?.map(StoredValue::Decoded));
+        }
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Read);
+        let txn = self.environment.create_read_txn()?;
+        let result: ReadResult<StoredValue<Value>> =
+            operations::read(&txn, &self.trie_store, &self.root_hash, key)?;
+        txn.commit()?;
+        match result {
+            ReadResult::Found(value) => Ok(Some(value)),
+            ReadResult::NotFound => Ok(None),
+            ReadResult::RootNotFound => Err(self.missing_tries()),
+        }
+    }
+
+    fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Option<Vec<(Key, Value)>>, Self::Error> {
+        let _timer =
+            OperationTimer::start(self.trie_store.metrics(), StoreOperation::KeysWithPrefix);
+        let txn = self.environment.create_read_txn()?;
+        let trie_store = OverlayTrieStore::new(&self.trie_store, &self.pending_tries);
+        let pairs = global_state::keys_with_prefix(&txn, &trie_store, &self.root_hash, prefix)?;
+        txn.commit()?;
+        match pairs {
+            Some(pairs) => Ok(Some(pairs)),
+            None => Err(self.missing_tries()),
+        }
+    }
+}
+
+impl History for InMemoryGlobalState {
+    type Error = error::Error;
+    type Reader = InMemoryGlobalStateView;
+
+    fn checkout(&self, prestate_hash: Blake2bHash) -> Result<Option<Self::Reader>, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Checkout);
+        let pending_tries = match self.find_state(&prestate_hash) {
+            Some(pending_tries) => pending_tries,
+            None => return Ok(None),
+        };
+        Ok(Some(InMemoryGlobalStateView {
+            environment: Arc::clone(&self.environment),
+            trie_store: self.trie_store.clone(),
+            root_hash: prestate_hash,
+            pending_tries,
+        }))
+    }
+
+    fn commit(
+        &self,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Commit);
+        if !self.roots.read().contains(&prestate_hash) {
+            return Ok(CommitResult::RootNotFound);
+        }
+        let mut txn = self.environment.create_read_write_txn()?;
+        let result = global_state::commit(&mut txn, &self.trie_store, prestate_hash, effects)?;
+        if let CommitResult::Success(root_hash) = &result {
+            txn.commit()?;
+            self.roots.write().insert(*root_hash);
+        }
+        Ok(result)
+    }
+
+    fn checkpoint(&mut self) -> CheckpointId {
+        self.checkpoints.open()
+    }
+
+    fn commit_at(
+        &mut self,
+        checkpoint: CheckpointId,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::CommitAt);
+        let is_pending = self
+            .checkpoints
+            .get(checkpoint)
+            .map_or(false, |pending| pending.roots().contains(&prestate_hash));
+        if !is_pending && !self.roots.read().contains(&prestate_hash) {
+            return Ok(CommitResult::RootNotFound);
+        }
+        let mut txn = self.environment.create_read_write_txn()?;
+        let result = self.checkpoints.commit(
+            &mut txn,
+            &self.trie_store,
+            checkpoint,
+            prestate_hash,
+            effects,
+        )?;
+        Ok(result)
+    }
+
+    fn rollback(&mut self, checkpoint: CheckpointId) {
+        self.checkpoints.close(checkpoint);
+    }
+
+    fn commit_checkpoint(&mut self, checkpoint: CheckpointId) -> Result<(), Self::Error> {
+        let _timer =
+            OperationTimer::start(self.trie_store.metrics(), StoreOperation::CommitCheckpoint);
+        {
+            let pending = match self.checkpoints.get(checkpoint) {
+                Some(pending) => pending,
+                None => return Ok(()),
+            };
+            let mut txn = self.environment.create_read_write_txn()?;
+            pending::put_tries(&mut txn, &self.trie_store, pending)?;
+            txn.commit()?;
+            self.roots.get_mut().extend(pending.roots());
+        }
+        self.checkpoints.close(checkpoint);
+        Ok(())
+    }
+
+    fn diff(
+        &self,
+        from: Blake2bHash,
+        to: Blake2bHash,
+    ) -> Result<Option<Vec<(Key, Option<Value>, Option<Value>)>>, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Diff);
+        let (from_tries, to_tries) = match (self.find_state(&from), self.find_state(&to)) {
+            (Some(from_tries), Some(to_tries)) => (from_tries, to_tries),
+            _ => return Ok(None),
+        };
+        let txn = self.environment.create_read_txn()?;
+        let changes = {
+            let from_store = OverlayTrieStore::new(&self.trie_store, &from_tries);
+            let trie_store = OverlayTrieStore::new(&from_store, &to_tries);
+            global_state::diff(&txn, &trie_store, &from, &to)?
+        };
+        txn.commit()?;
+        match changes {
+            Some(changes) => Ok(Some(changes)),
+            None => Err(error::Error::MissingTries {
+                roots: vec![from, to],
+            }),
+        }
+    }
+
+    fn prune(
+        &mut self,
+        retained_roots: &[Blake2bHash],
+        batch_size: usize,
+    ) -> Result<PruneResult, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Prune);
+        if let Some(root) = retained_roots
+            .iter()
+            .find(|root| !self.roots.read().contains(*root))
+        {
+            return Ok(PruneResult::RootNotFound(*root));
+        }
+        self.checkpoints.clear();
+        *self.roots.get_mut() = retained_roots.iter().cloned().collect();
+        match global_state::prune_tries(
+            &*self.environment,
+            &self.trie_store,
+            retained_roots,
+            batch_size,
+        )? {
+            Some(deleted) => Ok(PruneResult::Pruned(deleted)),
+            None => Err(error::Error::MissingTries {
+                roots: retained_roots.to_vec(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use common::key::Key;
+    use common::value::Value;
+    use error;
+    use global_state::StateReader;
+    use history::{CommitResult, History, PruneResult};
+    use transform::Transform;
+
+    use super::{InMemoryGlobalState, InMemoryGlobalStateView};
+
+    const KEY1: Key = Key::Account([1u8; 20]);
+    const KEY2: Key = Key::Account([2u8; 20]);
+
+    #[test]
+    fn rollbacks_forget_the_states_committed_at_the_checkpoint() {
+        let pairs = [(KEY1, Value::Int32(1))];
+        let (mut global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let checkpoint = global_state.checkpoint();
+        let mut effects = HashMap::new();
+        effects.insert(KEY2, Transform::Write(Value::Int32(2)));
+        let new_root_hash = match global_state
+            .commit_at(checkpoint, root_hash, effects)
+            .unwrap()
+        {
+            CommitResult::Success(new_root_hash) => new_root_hash,
+            _ => panic!("Test commit failed."),
+        };
+        let view = global_state.checkout(new_root_hash).unwrap().unwrap();
+        assert_eq!(view.read(&KEY2).unwrap(), Some(Value::Int32(2)));
+
+        global_state.rollback(checkpoint);
+        assert!(global_state.checkout(new_root_hash).unwrap().is_none());
+        let view = global_state.checkout(root_hash).unwrap().unwrap();
+        assert_eq!(view.read(&KEY1).unwrap(), Some(Value::Int32(1)));
+        assert_eq!(view.read(&KEY2).unwrap(), None);
+    }
+
+    #[test]
+    fn reading_a_state_without_tries_fails() {
+        let global_state = InMemoryGlobalState::empty().unwrap();
+        let root_hash = [1u8; 32].into();
+        let view = InMemoryGlobalStateView {
+            environment: Arc::clone(&global_state.environment),
+            trie_store: global_state.trie_store.clone(),
+            root_hash,
+            pending_tries: Arc::default(),
+        };
+        match view.read(&KEY1) {
+            Err(error::Error::MissingTries { roots }) => assert_eq!(roots, vec![root_hash]),
+            other => panic!("Expected missing tries, got {:?}", other),
+        }
+        match view.keys_with_prefix(&[]) {
+            Err(error::Error::MissingTries { roots }) => assert_eq!(roots, vec![root_hash]),
+            other => panic!("Expected missing tries, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reading_a_pruned_state_fails() {
+        let pairs = [(KEY1, Value::Int32(1))];
+        let (mut global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs).unwrap();
+        let view = global_state.checkout(root_hash).unwrap().unwrap();
+        let empty_root_hash = global_state.empty_root_hash();
+        match global_state.prune(&[empty_root_hash], 10).unwrap() {
+            PruneResult::Pruned(_) => (),
+            other => panic!("Expected pruned tries, got {:?}", other),
+        }
+        match view.read_stored(&KEY1) {
+            Err(error::Error::MissingTries { roots }) => assert_eq!(roots, vec![root_hash]),
+            other => panic!("Expected missing tries, got {:?}", other),
+        }
+    }
+}
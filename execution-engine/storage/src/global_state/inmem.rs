@@ -5,8 +5,10 @@ use common::key::Key;
 use common::value::Value;
 use global_state::*;
 use history::*;
+use metrics::{self, OperationTimer, StoreMetrics, StoreOperation};
+use parking_lot::RwLock;
 use shared::newtypes::Blake2bHash;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 
 pub struct InMemGS<K, V>(Arc<BTreeMap<K, V>>);
@@ -27,13 +29,37 @@ impl StateReader<Key, Value> for InMemGS<Key, Value> {
     fn read(&self, k: &Key) -> Result<Option<Value>, Self::Error> {
         Ok(self.0.get(k).map(Clone::clone))
     }
+
+    fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Option<Vec<(Key, Value)>>, Self::Error> {
+        let mut pairs = Vec::new();
+        for (key, value) in self.0.iter() {
+            let bytes = key.to_bytes()?;
+            if bytes.starts_with(prefix) {
+                pairs.push((bytes, *key, value.clone()));
+            }
+        }
+        // Keys are ordered by their bytes, like in tries.
+        pairs.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+        Ok(Some(
+            pairs
+                .into_iter()
+                .map(|(_, key, value)| (key, value))
+                .collect(),
+        ))
+    }
 }
 
 /// In memory representation of the versioned global state
 /// store - stores a snapshot of the global state at the specific block
 /// history - stores all the snapshots of the global state
 pub struct InMemHist<K, V> {
-    history: HashMap<Blake2bHash, InMemGS<K, V>>,
+    /// Behind a lock, so that commits can add their snapshots through a
+    /// shared reference.
+    history: RwLock<HashMap<Blake2bHash, InMemGS<K, V>>>,
+    /// The snapshots committed at each checkpoint.
+    checkpoints: HashMap<CheckpointId, HashMap<Blake2bHash, InMemGS<K, V>>>,
+    next_checkpoint_id: u64,
+    metrics: Arc<dyn StoreMetrics>,
 }
 
 impl<K: Ord, V> InMemHist<K, V> {
@@ -47,7 +73,30 @@ impl<K: Ord, V> InMemHist<K, V> {
     ) -> InMemHist<K, V> {
         let mut history = HashMap::new();
         history.insert(empty_root_hash.clone(), InMemGS(Arc::new(init_state)));
-        InMemHist { history }
+        InMemHist {
+            history: RwLock::new(history),
+            checkpoints: HashMap::new(),
+            next_checkpoint_id: 0,
+            metrics: metrics::no_metrics(),
+        }
+    }
+
+    /// The history, reporting how long its operations take to `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn StoreMetrics>) -> InMemHist<K, V> {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The snapshot at `root_hash`, either committed or held by a checkpoint.
+    fn find(&self, root_hash: &Blake2bHash) -> Option<InMemGS<K, V>> {
+        if let Some(snapshot) = self.history.read().get(root_hash) {
+            return Some(snapshot.clone());
+        }
+        self.checkpoints
+            .values()
+            .filter_map(|states| states.get(root_hash))
+            .next()
+            .cloned()
     }
 
     // TODO(mateusz.gorski): I know this is not efficient and we should be caching these values
@@ -66,61 +115,166 @@ impl<K: Ord, V> InMemHist<K, V> {
     }
 }
 
+impl InMemHist<Key, Value> {
+    /// Applies `effects` to the snapshot `base`, returning the new snapshot
+    /// with its hash, or the result of the failed commit.
+    fn apply(
+        mut base: BTreeMap<Key, Value>,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<Result<(Blake2bHash, InMemGS<Key, Value>), CommitResult>, StorageError> {
+        for (k, t) in effects.into_iter() {
+            let maybe_curr = base.remove(&k);
+            match maybe_curr {
+                None => match t {
+                    Transform::Write(v) => {
+                        base.insert(k, v);
+                    }
+                    _ => return Ok(Err(CommitResult::KeyNotFound(k))),
+                },
+                Some(curr) => match t.apply(curr) {
+                    Ok(new_value) => {
+                        base.insert(k, new_value);
+                    }
+                    Err(transform::Error::TypeMismatch(type_mismatch)) => {
+                        return Ok(Err(CommitResult::TypeMismatch(type_mismatch)))
+                    }
+                    Err(transform::Error::Overflow) => return Ok(Err(CommitResult::Overflow)),
+                },
+            }
+        }
+        let hash = InMemHist::get_root_hash(&base)?;
+        Ok(Ok((hash, InMemGS(Arc::new(base)))))
+    }
+}
+
 impl History for InMemHist<Key, Value> {
     type Error = StorageError;
     type Reader = InMemGS<Key, Value>;
 
     fn checkout(&self, prestate_hash: Blake2bHash) -> Result<Option<Self::Reader>, Self::Error> {
-        match self.history.get(&prestate_hash) {
-            None => Ok(None),
-            Some(gs) => Ok(Some(gs.clone())),
-        }
+        let _timer = OperationTimer::start(&self.metrics, StoreOperation::Checkout);
+        Ok(self.find(&prestate_hash))
     }
 
     fn commit(
-        &mut self,
+        &self,
         prestate_hash: Blake2bHash,
         effects: HashMap<Key, Transform>,
     ) -> Result<CommitResult, Self::Error> {
+        let _timer = OperationTimer::start(&self.metrics, StoreOperation::Commit);
         let base_result = self
             .history
+            .read()
             .get(&prestate_hash)
             .map(|gs| BTreeMap::clone(&gs.0));
         match base_result {
-            Some(mut base) => {
-                for (k, t) in effects.into_iter() {
-                    let maybe_curr = base.remove(&k);
-                    match maybe_curr {
-                        None => match t {
-                            Transform::Write(v) => {
-                                base.insert(k, v);
-                            }
-                            _ => return Ok(CommitResult::KeyNotFound(k)),
-                        },
-                        Some(curr) => match t.apply(curr) {
-                            Ok(new_value) => {
-                                base.insert(k, new_value);
-                            }
-                            Err(transform::Error::TypeMismatch(type_mismatch)) => {
-                                return Ok(CommitResult::TypeMismatch(type_mismatch))
-                            }
-                            Err(transform::Error::Overflow) => return Ok(CommitResult::Overflow),
-                        },
+            Some(base) => match InMemHist::apply(base, effects)? {
+                Ok((hash, gs)) => {
+                    self.history.write().insert(hash, gs);
+                    Ok(CommitResult::Success(hash))
+                }
+                Err(result) => Ok(result),
+            },
+            None => Ok(CommitResult::RootNotFound),
+        }
+    }
+
+    fn checkpoint(&mut self) -> CheckpointId {
+        let checkpoint = CheckpointId(self.next_checkpoint_id);
+        self.next_checkpoint_id += 1;
+        self.checkpoints.insert(checkpoint, HashMap::new());
+        checkpoint
+    }
+
+    fn commit_at(
+        &mut self,
+        checkpoint: CheckpointId,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, Self::Error> {
+        let _timer = OperationTimer::start(&self.metrics, StoreOperation::CommitAt);
+        let base_result = match self.checkpoints.get(&checkpoint) {
+            Some(states) => match states.get(&prestate_hash) {
+                Some(gs) => Some(BTreeMap::clone(&gs.0)),
+                None => self
+                    .history
+                    .read()
+                    .get(&prestate_hash)
+                    .map(|gs| BTreeMap::clone(&gs.0)),
+            },
+            None => None,
+        };
+        match base_result {
+            Some(base) => match InMemHist::apply(base, effects)? {
+                Ok((hash, gs)) => {
+                    if let Some(states) = self.checkpoints.get_mut(&checkpoint) {
+                        states.insert(hash, gs);
                     }
+                    Ok(CommitResult::Success(hash))
                 }
-                let hash = InMemHist::get_root_hash(&base)?;
-                self.history.insert(hash, InMemGS(Arc::new(base)));
-                Ok(CommitResult::Success(hash))
-            }
+                Err(result) => Ok(result),
+            },
             None => Ok(CommitResult::RootNotFound),
         }
     }
+
+    fn rollback(&mut self, checkpoint: CheckpointId) {
+        self.checkpoints.remove(&checkpoint);
+    }
+
+    fn commit_checkpoint(&mut self, checkpoint: CheckpointId) -> Result<(), Self::Error> {
+        let _timer = OperationTimer::start(&self.metrics, StoreOperation::CommitCheckpoint);
+        if let Some(states) = self.checkpoints.remove(&checkpoint) {
+            self.history.get_mut().extend(states);
+        }
+        Ok(())
+    }
+
+    fn diff(
+        &self,
+        from: Blake2bHash,
+        to: Blake2bHash,
+    ) -> Result<Option<Vec<(Key, Option<Value>, Option<Value>)>>, Self::Error> {
+        let _timer = OperationTimer::start(&self.metrics, StoreOperation::Diff);
+        let (from, to) = match (self.find(&from), self.find(&to)) {
+            (Some(from), Some(to)) => (from.0, to.0),
+            _ => return Ok(None),
+        };
+        let keys: BTreeSet<&Key> = from.keys().chain(to.keys()).collect();
+        let changes = keys
+            .into_iter()
+            .filter_map(|key| match (from.get(key), to.get(key)) {
+                (a, b) if a == b => None,
+                (a, b) => Some((*key, a.cloned(), b.cloned())),
+            })
+            .collect();
+        Ok(Some(changes))
+    }
+
+    /// There are no tries, so the entries deleted are the forgotten states.
+    fn prune(
+        &mut self,
+        retained_roots: &[Blake2bHash],
+        _batch_size: usize,
+    ) -> Result<PruneResult, Self::Error> {
+        let _timer = OperationTimer::start(&self.metrics, StoreOperation::Prune);
+        let history = self.history.get_mut();
+        if let Some(root) = retained_roots
+            .iter()
+            .find(|root| !history.contains_key(*root))
+        {
+            return Ok(PruneResult::RootNotFound(*root));
+        }
+        self.checkpoints.clear();
+        let count = history.len();
+        history.retain(|root, _| retained_roots.contains(root));
+        Ok(PruneResult::Pruned(count - history.len()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use global_state::inmem::*;
-    use std::sync::Arc;
     use transform::Transform;
 
     const KEY1: Key = Key::Account([1u8; 20]);
@@ -133,9 +287,7 @@ mod tests {
         let mut map = BTreeMap::new();
         map.insert(KEY1, VALUE1.clone());
         map.insert(KEY2, VALUE2.clone());
-        let mut history = HashMap::new();
-        history.insert(empty_root_hash, InMemGS(Arc::new(map)));
-        InMemHist { history }
+        InMemHist::new_initialized(&empty_root_hash, map)
     }
 
     fn checkout<H>(hist: &H, hash: Blake2bHash) -> H::Reader
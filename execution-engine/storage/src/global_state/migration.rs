@@ -0,0 +1,579 @@
+//! Migrations of the persistent stores from one storage format to the next.
+//!
+//! A store records the version of the format its tries are stored in. Every
+//! change of the format bumps [`FORMAT_VERSION`] and registers the migration
+//! rewriting the tries of the previous version, so a store written by any
+//! earlier build can be brought up to date. Migrations are run when the
+//! server starts, before anything is read; since they ship with the build,
+//! a protocol upgrade needing a new format migrates the stores when the
+//! nodes restart to activate it.
+//!
+//! Migrations rewrite the bytes tries are stored as, like the envelopes of
+//! their values, and leave them at their hashes: the hashes are of the
+//! serialized tries, which the roots of the states depend on. Each trie is
+//! taken through all the migrations it needs at once. The tries are migrated
+//! in batches, a transaction each, recording how far the migration went in
+//! the same transaction, so an interrupted migration is resumed where it
+//! stopped and no trie is migrated twice.
+//!
+//! The format version and the progress are records with checksums, as
+//! [`integrity`](../../history/trie_store/integrity/index.html) writes them, so a
+//! corrupted record stops the migration rather than misleading it.
+
+use std::cmp;
+use std::sync::Arc;
+
+use common::bytesrepr::{self, ToBytes};
+use error;
+use history::trie_store::integrity;
+use history::trie_store::{Readable, Transaction, TransactionSource, Writable};
+use lmdb;
+use metrics::StoreMetrics;
+use shared::newtypes::Blake2bHash;
+
+/// Version of the format this build stores tries in.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Version of the format of the stores written before the versions were
+/// recorded.
+pub const UNVERSIONED_FORMAT: u32 = 1;
+
+/// Tries migrated per transaction unless configured otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+const FORMAT_VERSION_KEY: &[u8] = b"format_version";
+const PROGRESS_KEY: &[u8] = b"migration_progress";
+
+#[derive(Debug, Fail)]
+pub enum MigrationError {
+    #[fail(display = "{}", _0)]
+    Storage(#[fail(cause)] error::Error),
+
+    #[fail(display = "Store is at format version {}, newer than version {}", _0, _1)]
+    NewerFormat(u32, u32),
+
+    #[fail(display = "No migration from format version {}", _0)]
+    MissingMigration(u32),
+
+    /// A migration to another version than the one configured was
+    /// interrupted. It has to be finished by the build which started it.
+    #[fail(display = "Migration to format version {} was interrupted", _0)]
+    Interrupted(u32),
+
+    #[fail(display = "Migration to format version {} failed on trie {:?}: {}", _0, _1, _2)]
+    Failed(u32, Blake2bHash, #[fail(cause)] error::Error),
+}
+
+impl From<error::Error> for MigrationError {
+    fn from(error: error::Error) -> Self {
+        MigrationError::Storage(error)
+    }
+}
+
+impl From<lmdb::Error> for MigrationError {
+    fn from(error: lmdb::Error) -> Self {
+        MigrationError::Storage(error.into())
+    }
+}
+
+impl From<bytesrepr::Error> for MigrationError {
+    fn from(error: bytesrepr::Error) -> Self {
+        MigrationError::Storage(error.into())
+    }
+}
+
+/// A change of the format tries are stored in.
+pub trait Migration: Send + Sync {
+    /// Version of the format the migration brings the stores to, from the
+    /// version before it.
+    fn version(&self) -> u32;
+
+    /// What the migration changes, for the reports.
+    fn description(&self) -> &str;
+
+    /// The bytes the trie at `hash` is stored as in the format of the
+    /// migration, from the bytes `stored` it's stored as in the format before
+    /// it. Returns `None` if they're the same.
+    fn migrate_trie(
+        &self,
+        hash: &Blake2bHash,
+        stored: &[u8],
+    ) -> Result<Option<Vec<u8>>, error::Error>;
+}
+
+/// Migrations of this build, to each format version after the first, in
+/// order.
+fn registered_migrations() -> Vec<Box<dyn Migration>> {
+    Vec::new()
+}
+
+/// How far a migration went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub tries_total: usize,
+    /// Tries taken through the migrations so far, whether they changed or
+    /// not.
+    pub tries_migrated: usize,
+    pub tries_rewritten: usize,
+    /// Whether nothing was written.
+    pub dry_run: bool,
+}
+
+/// Runs the migrations a store needs.
+pub struct Migrator {
+    migrations: Vec<Box<dyn Migration>>,
+    target_version: u32,
+    dry_run: bool,
+    batch_size: usize,
+    progress: Option<Box<dyn Fn(&MigrationReport)>>,
+}
+
+impl Migrator {
+    /// Migrator bringing stores to [`FORMAT_VERSION`] with the migrations of
+    /// this build.
+    pub fn new() -> Migrator {
+        Migrator::with_migrations(registered_migrations(), FORMAT_VERSION)
+    }
+
+    /// Migrator bringing stores to `target_version` with `migrations`.
+    pub fn with_migrations(migrations: Vec<Box<dyn Migration>>, target_version: u32) -> Migrator {
+        Migrator {
+            migrations,
+            target_version,
+            dry_run: false,
+            batch_size: DEFAULT_BATCH_SIZE,
+            progress: None,
+        }
+    }
+
+    /// The migrator, only counting the tries it would rewrite if `dry_run`.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Migrator {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The migrator, migrating `batch_size` tries per transaction.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Migrator {
+        self.batch_size = cmp::max(batch_size, 1);
+        self
+    }
+
+    /// The migrator, calling `progress` after every batch.
+    pub fn with_progress(mut self, progress: Box<dyn Fn(&MigrationReport)>) -> Migrator {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn target_version(&self) -> u32 {
+        self.target_version
+    }
+
+    /// The migrations from `from_version` to the target version, in order.
+    fn path(&self, from_version: u32) -> Result<Vec<&dyn Migration>, MigrationError> {
+        if from_version > self.target_version {
+            return Err(MigrationError::NewerFormat(from_version, self.target_version));
+        }
+        let mut path = Vec::new();
+        for version in from_version + 1..=self.target_version {
+            match self.migrations.iter().find(|m| m.version() == version) {
+                Some(migration) => path.push(migration.as_ref()),
+                None => return Err(MigrationError::MissingMigration(version - 1)),
+            }
+        }
+        Ok(path)
+    }
+
+    fn report(&self, report: &MigrationReport) {
+        if let Some(progress) = &self.progress {
+            progress(report);
+        }
+    }
+
+    /// Migrates the tries kept in `tries`, recording the format version and
+    /// the progress in `meta`. Corrupted records are reported to `metrics`.
+    pub fn run<'a, E>(
+        &self,
+        environment: &'a E,
+        tries: E::Handle,
+        meta: E::Handle,
+        metrics: &Arc<dyn StoreMetrics>,
+    ) -> Result<MigrationReport, MigrationError>
+    where
+        E: TransactionSource<'a>,
+        E::Handle: Copy,
+        error::Error: From<E::Error>,
+        MigrationError: From<E::Error>,
+    {
+        let txn = environment.create_read_txn()?;
+        let from_version = format_version(&txn, meta, metrics)?;
+        let progress = read_progress(&txn, meta, metrics)?;
+        let mut keys = txn.keys(tries)?;
+        txn.commit()?;
+
+        let path = self.path(from_version)?;
+        keys.sort();
+        let mut report = MigrationReport {
+            from_version,
+            to_version: self.target_version,
+            tries_total: keys.len(),
+            tries_migrated: 0,
+            tries_rewritten: 0,
+            dry_run: self.dry_run,
+        };
+        if path.is_empty() {
+            return Ok(report);
+        }
+        // The tries up to the last one of the interrupted migration are
+        // migrated already.
+        let resume_after = match progress {
+            Some((version, _)) if version != self.target_version => {
+                return Err(MigrationError::Interrupted(version));
+            }
+            Some((_, last_key)) => Some(last_key),
+            None => None,
+        };
+        if let Some(last_key) = &resume_after {
+            report.tries_migrated = keys.iter().take_while(|key| *key <= last_key).count();
+        }
+
+        for batch in keys[report.tries_migrated..].chunks(self.batch_size) {
+            let mut txn = environment.create_read_write_txn()?;
+            for key in batch {
+                let hash: Blake2bHash = bytesrepr::deserialize(key)?;
+                let stored = match txn.read(tries, key)? {
+                    Some(stored) => stored,
+                    None => continue,
+                };
+                if let Some(migrated) = migrate_stored(&path, &hash, stored)? {
+                    report.tries_rewritten += 1;
+                    if !self.dry_run {
+                        txn.write(tries, key, &migrated)?;
+                    }
+                }
+            }
+            report.tries_migrated += batch.len();
+            // A dry run drops its transaction, writing nothing.
+            if !self.dry_run {
+                let last_key = batch[batch.len() - 1].clone();
+                let progress = (self.target_version, last_key).to_bytes()?;
+                integrity::write_record(&mut txn, meta, PROGRESS_KEY, &progress)?;
+                txn.commit()?;
+            }
+            self.report(&report);
+        }
+
+        if !self.dry_run {
+            let mut txn = environment.create_read_write_txn()?;
+            let version = self.target_version.to_bytes()?;
+            integrity::write_record(&mut txn, meta, FORMAT_VERSION_KEY, &version)?;
+            txn.delete(meta, PROGRESS_KEY)?;
+            txn.commit()?;
+        }
+        Ok(report)
+    }
+}
+
+impl Default for Migrator {
+    fn default() -> Self {
+        Migrator::new()
+    }
+}
+
+/// The bytes the trie at `hash` is stored as once taken through `path`, or
+/// `None` if no migration changed them.
+fn migrate_stored(
+    path: &[&dyn Migration],
+    hash: &Blake2bHash,
+    stored: Vec<u8>,
+) -> Result<Option<Vec<u8>>, MigrationError> {
+    let mut migrated = None;
+    for migration in path {
+        let result = {
+            let current = migrated.as_ref().unwrap_or(&stored);
+            migration.migrate_trie(hash, current)
+        };
+        match result {
+            Ok(Some(bytes)) => migrated = Some(bytes),
+            Ok(None) => (),
+            Err(error) => return Err(MigrationError::Failed(migration.version(), *hash, error)),
+        }
+    }
+    Ok(migrated)
+}
+
+/// The format version recorded in `meta`, or [`UNVERSIONED_FORMAT`] if
+/// there's none.
+pub fn format_version<T>(
+    txn: &T,
+    meta: T::Handle,
+    metrics: &Arc<dyn StoreMetrics>,
+) -> Result<u32, error::Error>
+where
+    T: Readable,
+    error::Error: From<T::Error>,
+{
+    match integrity::read_record(txn, meta, FORMAT_VERSION_KEY, metrics)? {
+        Some(bytes) => Ok(bytesrepr::deserialize(&bytes)?),
+        None => Ok(UNVERSIONED_FORMAT),
+    }
+}
+
+/// Records the format version of a store being opened if it isn't yet:
+/// stores created by this build are in [`FORMAT_VERSION`], and existing ones
+/// are in [`UNVERSIONED_FORMAT`].
+pub fn record_format_version<T>(
+    txn: &mut T,
+    meta: T::Handle,
+    created: bool,
+) -> Result<(), error::Error>
+where
+    T: Readable + Writable,
+    error::Error: From<T::Error>,
+{
+    if txn.read(meta, FORMAT_VERSION_KEY)?.is_some() {
+        return Ok(());
+    }
+    let version = if created {
+        FORMAT_VERSION
+    } else {
+        UNVERSIONED_FORMAT
+    };
+    integrity::write_record(txn, meta, FORMAT_VERSION_KEY, &version.to_bytes()?)
+}
+
+/// Target version and last key of the interrupted migration, if any.
+fn read_progress<T>(
+    txn: &T,
+    meta: T::Handle,
+    metrics: &Arc<dyn StoreMetrics>,
+) -> Result<Option<(u32, Vec<u8>)>, error::Error>
+where
+    T: Readable,
+    error::Error: From<T::Error>,
+{
+    match integrity::read_record(txn, meta, PROGRESS_KEY, metrics)? {
+        Some(bytes) => Ok(Some(bytesrepr::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use lmdb::{Database, DatabaseFlags};
+    use tempfile::{tempdir, TempDir};
+
+    use common::bytesrepr::{self, ToBytes};
+    use error;
+    use history::trie_store::lmdb::LmdbEnvironment;
+    use history::trie_store::{Readable, Transaction, TransactionSource, Writable};
+    use metrics::no_metrics;
+    use shared::newtypes::Blake2bHash;
+
+    use super::{format_version, Migration, MigrationError, MigrationReport, Migrator};
+
+    /// Appends `byte` to every trie but `fails_on`.
+    struct Append {
+        version: u32,
+        byte: u8,
+        fails_on: Option<Blake2bHash>,
+    }
+
+    impl Append {
+        fn new(version: u32, byte: u8) -> Box<dyn Migration> {
+            Box::new(Append {
+                version,
+                byte,
+                fails_on: None,
+            })
+        }
+    }
+
+    impl Migration for Append {
+        fn version(&self) -> u32 {
+            self.version
+        }
+
+        fn description(&self) -> &str {
+            "Appends a byte to the tries"
+        }
+
+        fn migrate_trie(
+            &self,
+            hash: &Blake2bHash,
+            stored: &[u8],
+        ) -> Result<Option<Vec<u8>>, error::Error> {
+            if self.fails_on == Some(*hash) {
+                return Err(bytesrepr::Error::FormattingError.into());
+            }
+            let mut migrated = stored.to_vec();
+            migrated.push(self.byte);
+            Ok(Some(migrated))
+        }
+    }
+
+    /// Leaves the tries as they are.
+    struct Keep(u32);
+
+    impl Migration for Keep {
+        fn version(&self) -> u32 {
+            self.0
+        }
+
+        fn description(&self) -> &str {
+            "Changes nothing"
+        }
+
+        fn migrate_trie(&self, _: &Blake2bHash, _: &[u8]) -> Result<Option<Vec<u8>>, error::Error> {
+            Ok(None)
+        }
+    }
+
+    /// Environment with five tries stored in an unversioned format.
+    fn store() -> (LmdbEnvironment, Database, Database, TempDir) {
+        let dir = tempdir().unwrap();
+        let environment = LmdbEnvironment::new(&dir.path().to_path_buf()).unwrap();
+        let tries = environment
+            .env()
+            .create_db(Some("tries"), DatabaseFlags::empty())
+            .unwrap();
+        let meta = environment
+            .env()
+            .create_db(Some("meta"), DatabaseFlags::empty())
+            .unwrap();
+        let mut txn = environment.create_read_write_txn().unwrap();
+        for i in 0u8..5 {
+            let key = Blake2bHash::new(&[i]).to_bytes().unwrap();
+            txn.write(tries, &key, &[i; 4]).unwrap();
+        }
+        txn.commit().unwrap();
+        (environment, tries, meta, dir)
+    }
+
+    /// Hashes and stored bytes of the tries, in the order of the hashes.
+    fn stored_tries(environment: &LmdbEnvironment, tries: Database) -> Vec<(Blake2bHash, Vec<u8>)> {
+        let txn = environment.create_read_txn().unwrap();
+        let stored = txn
+            .keys(tries)
+            .unwrap()
+            .into_iter()
+            .map(|key| {
+                let bytes = txn.read(tries, &key).unwrap().unwrap();
+                (bytesrepr::deserialize(&key).unwrap(), bytes)
+            })
+            .collect();
+        txn.commit().unwrap();
+        stored
+    }
+
+    fn version(environment: &LmdbEnvironment, meta: Database) -> u32 {
+        let txn = environment.create_read_txn().unwrap();
+        let version = format_version(&txn, meta, &no_metrics()).unwrap();
+        txn.commit().unwrap();
+        version
+    }
+
+    /// `stored` with `suffix` appended to every trie.
+    fn appended(stored: &[(Blake2bHash, Vec<u8>)], suffix: &[u8]) -> Vec<(Blake2bHash, Vec<u8>)> {
+        stored
+            .iter()
+            .map(|(hash, bytes)| (*hash, [&bytes[..], suffix].concat()))
+            .collect()
+    }
+
+    #[test]
+    fn migrations_bring_the_tries_to_the_target_version() {
+        let (environment, tries, meta, _dir) = store();
+        let metrics = no_metrics();
+        let before = stored_tries(&environment, tries);
+        assert_eq!(version(&environment, meta), 1);
+
+        let progress = Rc::new(RefCell::new(Vec::new()));
+        let reported = Rc::clone(&progress);
+        let migrations = vec![Append::new(2, 0xaa), Box::new(Keep(3)) as Box<dyn Migration>];
+        let migrator = Migrator::with_migrations(migrations, 3)
+            .with_batch_size(2)
+            .with_progress(Box::new(move |report: &MigrationReport| {
+                reported.borrow_mut().push(report.tries_migrated);
+            }));
+        let report = migrator.run(&environment, tries, meta, &metrics).unwrap();
+        assert_eq!((report.from_version, report.to_version), (1, 3));
+        assert_eq!(report.tries_total, 5);
+        assert_eq!((report.tries_migrated, report.tries_rewritten), (5, 5));
+        assert_eq!(*progress.borrow(), vec![2, 4, 5]);
+        assert_eq!(stored_tries(&environment, tries), appended(&before, &[0xaa]));
+        assert_eq!(version(&environment, meta), 3);
+
+        // Migrated stores are left alone.
+        let report = migrator.run(&environment, tries, meta, &metrics).unwrap();
+        assert_eq!((report.from_version, report.tries_rewritten), (3, 0));
+        assert_eq!(stored_tries(&environment, tries), appended(&before, &[0xaa]));
+    }
+
+    #[test]
+    fn dry_runs_write_nothing() {
+        let (environment, tries, meta, _dir) = store();
+        let metrics = no_metrics();
+        let before = stored_tries(&environment, tries);
+        let migrator = Migrator::with_migrations(vec![Append::new(2, 0xaa)], 2).with_dry_run(true);
+        let report = migrator.run(&environment, tries, meta, &metrics).unwrap();
+        assert!(report.dry_run);
+        assert_eq!((report.tries_migrated, report.tries_rewritten), (5, 5));
+        assert_eq!(stored_tries(&environment, tries), before);
+        assert_eq!(version(&environment, meta), 1);
+    }
+
+    #[test]
+    fn interrupted_migrations_are_resumed() {
+        let (environment, tries, meta, _dir) = store();
+        let metrics = no_metrics();
+        let before = stored_tries(&environment, tries);
+        let failing: Box<dyn Migration> = Box::new(Append {
+            version: 2,
+            byte: 0xaa,
+            fails_on: Some(before[2].0),
+        });
+        let migrator = Migrator::with_migrations(vec![failing], 2).with_batch_size(1);
+        match migrator.run(&environment, tries, meta, &metrics) {
+            Err(MigrationError::Failed(2, hash, _)) => assert_eq!(hash, before[2].0),
+            other => panic!("Expected the migration to fail, got {:?}", other),
+        }
+        assert_eq!(version(&environment, meta), 1);
+
+        // The migration has to be finished before another one is started.
+        let migrations = vec![Append::new(2, 0xaa), Append::new(3, 0xbb)];
+        let migrator = Migrator::with_migrations(migrations, 3);
+        match migrator.run(&environment, tries, meta, &metrics) {
+            Err(MigrationError::Interrupted(2)) => (),
+            other => panic!("Expected an interrupted migration, got {:?}", other),
+        }
+
+        let migrator = Migrator::with_migrations(vec![Append::new(2, 0xaa)], 2);
+        let report = migrator.run(&environment, tries, meta, &metrics).unwrap();
+        assert_eq!((report.tries_migrated, report.tries_rewritten), (5, 3));
+        // The tries migrated before the failure aren't migrated again.
+        assert_eq!(stored_tries(&environment, tries), appended(&before, &[0xaa]));
+        assert_eq!(version(&environment, meta), 2);
+    }
+
+    #[test]
+    fn stores_are_only_migrated_forward_and_without_gaps() {
+        let (environment, tries, meta, _dir) = store();
+        let metrics = no_metrics();
+        let migrator = Migrator::with_migrations(vec![Append::new(3, 0xaa)], 3);
+        match migrator.run(&environment, tries, meta, &metrics) {
+            Err(MigrationError::MissingMigration(1)) => (),
+            other => panic!("Expected a missing migration, got {:?}", other),
+        }
+        let migrator = Migrator::with_migrations(vec![Append::new(2, 0xaa)], 2);
+        migrator.run(&environment, tries, meta, &metrics).unwrap();
+        match Migrator::new().run(&environment, tries, meta, &metrics) {
+            Err(MigrationError::NewerFormat(2, 1)) => (),
+            other => panic!("Expected a newer format, got {:?}", other),
+        }
+    }
+}
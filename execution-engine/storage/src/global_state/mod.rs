@@ -1,13 +1,57 @@
+use super::history::trie_store::operations::{self, ReadResult, WriteResult};
+use super::history::trie_store::{Readable, Transaction, TransactionSource, TrieStore, Writable};
+use super::history::CommitResult;
 use super::op::Op;
-use super::transform::Transform;
-use crate::common::key::Key;
-use crate::common::value::{Account, Value};
+use super::transform::{self, Transform};
+use self::stored_value::StoredValue;
+use crate::common::bytesrepr;
+use crate::common::key::{AccessRights, Key};
+use crate::common::trie_merkle_proof::TrieMerkleProof;
+use crate::common::value::account::PurseId;
+use crate::common::value::{Account, Value, U512};
+use crate::shared::newtypes::Blake2bHash;
+use std::cmp;
 use std::collections::{BTreeMap, HashMap};
 
+pub mod in_memory;
 pub mod inmem;
+pub mod lmdb;
+pub mod migration;
+pub mod pending;
+pub mod rocksdb;
+pub mod snapshot;
+pub mod stored_value;
+
+/// Notification emitted by a contract for off-chain consumers. Events are
+/// reported together with the effects of a deploy but never stored in the
+/// global state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub topic: String,
+    pub data: Value,
+}
 
 #[derive(Debug)]
-pub struct ExecutionEffect(pub HashMap<Key, Op>, pub HashMap<Key, Transform>);
+pub struct ExecutionEffect(
+    pub HashMap<Key, Op>,
+    pub HashMap<Key, Transform>,
+    pub Vec<Event>,
+);
+
+impl ExecutionEffect {
+    /// Whether this effect and `other` can be committed in either order with
+    /// the same outcome, which is what allows merging the blocks of deploys
+    /// run on the same prestate. Every key both of them touch has to be
+    /// touched by operations which commute.
+    pub fn commutes_with(&self, other: &ExecutionEffect) -> bool {
+        self.0.iter().all(|(key, op)| {
+            other
+                .0
+                .get(key)
+                .map_or(true, |other_op| op.commutes_with(other_op))
+        })
+    }
+}
 
 /// A reader of state
 pub trait StateReader<K, V> {
@@ -16,12 +60,169 @@ pub trait StateReader<K, V> {
 
     /// Returns the state value from the corresponding key
     fn read(&self, key: &K) -> Result<Option<V>, Self::Error>;
+
+    /// Returns a proof of the state value under the corresponding key, or
+    /// `None` if there is no value or the reader can't prove values.
+    fn read_with_proof(&self, _key: &K) -> Result<Option<TrieMerkleProof<K, V>>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the state value from the corresponding key without decoding
+    /// it, for readers which only pass its bytes on. Readers which can't read
+    /// values raw return them decoded.
+    fn read_stored(&self, key: &K) -> Result<Option<StoredValue<V>>, Self::Error> {
+        Ok(self.read(key)?.map(StoredValue::Decoded))
+    }
+
+    /// Returns the keys whose bytes start with `prefix`, with their values, in
+    /// the order of their bytes, or `None` if the reader can't list keys.
+    fn keys_with_prefix(&self, _prefix: &[u8]) -> Result<Option<Vec<(K, V)>>, Self::Error> {
+        Ok(None)
+    }
+}
+
+/// Deletes the tries in `store` which aren't reachable from `retained_roots`,
+/// at most `batch_size` of them per transaction, and returns how many were
+/// deleted. Returns `None`, deleting nothing, if a trie under the roots is
+/// missing. The tries to delete are found before deleting any, so nothing may
+/// be committed to the store meanwhile.
+pub fn prune_tries<'a, E, S>(
+    environment: &'a E,
+    store: &S,
+    retained_roots: &[Blake2bHash],
+    batch_size: usize,
+) -> Result<Option<usize>, S::Error>
+where
+    E: TransactionSource<'a, Handle = S::Handle>,
+    S: TrieStore<Key, Value>,
+    S::Error: From<E::Error>,
+{
+    let unreachable: Vec<Blake2bHash> = {
+        let txn = environment.create_read_txn()?;
+        let reachable = match operations::reachable(&txn, store, retained_roots)? {
+            Some(reachable) => reachable,
+            None => return Ok(None),
+        };
+        let hashes = store.hashes(&txn)?;
+        txn.commit()?;
+        hashes
+            .into_iter()
+            .filter(|hash| !reachable.contains(hash))
+            .collect()
+    };
+    for batch in unreachable.chunks(cmp::max(batch_size, 1)) {
+        let mut txn = environment.create_read_write_txn()?;
+        for hash in batch {
+            store.delete(&mut txn, hash)?;
+        }
+        txn.commit()?;
+    }
+    Ok(Some(unreachable.len()))
+}
+
+/// The values changed from the state at `from` to the state at `to`, both
+/// kept in `store`, in the order of the bytes of their keys. Returns `None` if
+/// a trie of the states is missing.
+pub fn diff<T, S>(
+    txn: &T,
+    store: &S,
+    from: &Blake2bHash,
+    to: &Blake2bHash,
+) -> Result<Option<Vec<(Key, Option<Value>, Option<Value>)>>, S::Error>
+where
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<Key, Value>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    operations::diff(txn, store, from, to).collect()
+}
+
+/// The keys starting with the bytes `prefix` in the state at `root` kept in
+/// `store`, with their values, in the order of their bytes. Returns `None` if
+/// a trie of the state is missing.
+pub fn keys_with_prefix<T, S>(
+    txn: &T,
+    store: &S,
+    root: &Blake2bHash,
+    prefix: &[u8],
+) -> Result<Option<Vec<(Key, Value)>>, S::Error>
+where
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<Key, Value>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    operations::keys_with_prefix(txn, store, root, prefix).collect()
+}
+
+/// Applies `effects` to the state at `prestate_hash` kept in `store`, putting
+/// the tries of the new state in it.
+pub fn commit<T, S>(
+    txn: &mut T,
+    store: &S,
+    prestate_hash: Blake2bHash,
+    effects: HashMap<Key, Transform>,
+) -> Result<CommitResult, S::Error>
+where
+    T: Readable<Handle = S::Handle> + Writable<Handle = S::Handle>,
+    S: TrieStore<Key, Value>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    let mut root_hash = prestate_hash;
+    for (key, transform) in effects.into_iter() {
+        let value = match operations::read(txn, store, &root_hash, &key)? {
+            ReadResult::Found(current) => match transform.apply(current) {
+                Ok(value) => value,
+                Err(transform::Error::TypeMismatch(type_mismatch)) => {
+                    return Ok(CommitResult::TypeMismatch(type_mismatch))
+                }
+                Err(transform::Error::Overflow) => return Ok(CommitResult::Overflow),
+            },
+            ReadResult::NotFound => match transform {
+                Transform::Write(value) => value,
+                _ => return Ok(CommitResult::KeyNotFound(key)),
+            },
+            ReadResult::RootNotFound => return Ok(CommitResult::RootNotFound),
+        };
+        match operations::write(txn, store, &root_hash, &key, &value)? {
+            WriteResult::Written(new_root_hash) => root_hash = new_root_hash,
+            WriteResult::RootNotFound => return Ok(CommitResult::RootNotFound),
+        }
+    }
+    Ok(CommitResult::Success(root_hash))
 }
 
 pub fn mocked_account(account_addr: [u8; 20]) -> BTreeMap<Key, Value> {
-    let account = Account::new([48u8; 32], 0, BTreeMap::new());
+    let purse_id = PurseId::new([49u8; 32], AccessRights::READ_ADD_WRITE);
+    let account = Account::create([48u8; 32], 0, BTreeMap::new(), purse_id);
     let mut map = BTreeMap::new();
     map.insert(Key::Account(account_addr), Value::Account(account));
+    map.insert(purse_id.into(), Value::UInt512(U512::zero()));
 
     map
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::ExecutionEffect;
+    use crate::common::key::Key;
+    use crate::op::Op;
+
+    fn effect(ops: &[(Key, Op)]) -> ExecutionEffect {
+        let ops: HashMap<Key, Op> = ops.iter().cloned().collect();
+        ExecutionEffect(ops, HashMap::new(), Vec::new())
+    }
+
+    #[test]
+    fn effects_commute_unless_they_conflict_on_a_key() {
+        let a = Key::Hash([1u8; 32]);
+        let b = Key::Hash([2u8; 32]);
+        let reads = effect(&[(a, Op::Read), (b, Op::Add)]);
+        assert!(reads.commutes_with(&effect(&[(a, Op::Read), (b, Op::Add)])));
+        assert!(reads.commutes_with(&effect(&[(Key::Hash([3u8; 32]), Op::Write)])));
+        assert!(!reads.commutes_with(&effect(&[(a, Op::Write)])));
+        assert!(!reads.commutes_with(&effect(&[(b, Op::Read)])));
+        assert!(!effect(&[(b, Op::Read)]).commutes_with(&reads));
+    }
+}
@@ -0,0 +1,116 @@
+//! The states held by the checkpoints of a global state kept as tries,
+//! whose tries stay in memory until their checkpoint is committed.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use common::bytesrepr;
+use common::key::Key;
+use common::value::Value;
+use global_state;
+use history::trie_store::overlay::{OverlayTrieStore, TrieBatch};
+use history::trie_store::{Readable, TrieStore, Writable};
+use history::{CheckpointId, CommitResult};
+use shared::newtypes::Blake2bHash;
+use transform::Transform;
+
+/// The states committed at a checkpoint. Their tries which aren't in the
+/// store are shared with the views of the states.
+#[derive(Debug, Clone, Default)]
+pub struct PendingStates {
+    tries: Arc<TrieBatch<Key, Value>>,
+    roots: HashSet<Blake2bHash>,
+}
+
+impl PendingStates {
+    pub fn tries(&self) -> &Arc<TrieBatch<Key, Value>> {
+        &self.tries
+    }
+
+    pub fn roots(&self) -> &HashSet<Blake2bHash> {
+        &self.roots
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Checkpoints {
+    pending: HashMap<CheckpointId, PendingStates>,
+    next_id: u64,
+}
+
+impl Checkpoints {
+    pub fn new() -> Checkpoints {
+        Default::default()
+    }
+
+    pub fn open(&mut self) -> CheckpointId {
+        let checkpoint = CheckpointId(self.next_id);
+        self.next_id += 1;
+        self.pending.insert(checkpoint, PendingStates::default());
+        checkpoint
+    }
+
+    pub fn get(&self, checkpoint: CheckpointId) -> Option<&PendingStates> {
+        self.pending.get(&checkpoint)
+    }
+
+    /// The states of a checkpoint holding the state `root_hash`.
+    pub fn find(&self, root_hash: &Blake2bHash) -> Option<&PendingStates> {
+        self.pending
+            .values()
+            .find(|pending| pending.roots.contains(root_hash))
+    }
+
+    pub fn close(&mut self, checkpoint: CheckpointId) -> Option<PendingStates> {
+        self.pending.remove(&checkpoint)
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Applies `effects` to the state at `prestate_hash`, which is either in
+    /// `store` or held by the checkpoint, holding the tries of the new state
+    /// in the checkpoint. Nothing is written to the store.
+    pub fn commit<T, S>(
+        &mut self,
+        txn: &mut T,
+        store: &S,
+        checkpoint: CheckpointId,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, S::Error>
+    where
+        T: Readable<Handle = S::Handle> + Writable<Handle = S::Handle>,
+        S: TrieStore<Key, Value>,
+        S::Error: From<T::Error> + From<bytesrepr::Error>,
+    {
+        let pending = match self.pending.get_mut(&checkpoint) {
+            Some(pending) => pending,
+            None => return Ok(CommitResult::RootNotFound),
+        };
+        let (result, writes) = {
+            let overlay = OverlayTrieStore::new(store, &pending.tries);
+            let result = global_state::commit(txn, &overlay, prestate_hash, effects)?;
+            (result, overlay.into_writes())
+        };
+        if let CommitResult::Success(root_hash) = &result {
+            Arc::make_mut(&mut pending.tries).extend(writes);
+            pending.roots.insert(*root_hash);
+        }
+        Ok(result)
+    }
+}
+
+/// Puts the tries of `pending` in `store`.
+pub fn put_tries<T, S>(txn: &mut T, store: &S, pending: &PendingStates) -> Result<(), S::Error>
+where
+    T: Writable<Handle = S::Handle>,
+    S: TrieStore<Key, Value>,
+    S::Error: From<T::Error>,
+{
+    for (hash, trie) in pending.tries.iter() {
+        store.put(txn, hash, trie)?;
+    }
+    Ok(())
+}
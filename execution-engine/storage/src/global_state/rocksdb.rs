@@ -0,0 +1,408 @@
+//! Global state persisted in RocksDB, kept like the
+//! [LMDB-backed](../lmdb/index.html) one.
+//!
+//! The tries and the root hashes of the states committed are kept in column
+//! families of their own. The writes of a transaction are one batch, so a
+//! failed commit leaves nothing behind either. The format version of the
+//! store has a column family of its own too.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use common::bytesrepr::ToBytes;
+use common::key::Key;
+use common::trie_merkle_proof::TrieMerkleProof;
+use common::value::Value;
+use error;
+use global_state::migration::{self, MigrationError, MigrationReport, Migrator};
+use global_state::pending::{self, Checkpoints};
+use global_state::snapshot::{self, SnapshotError};
+use global_state::stored_value::StoredValue;
+use global_state::{self, StateReader};
+use history::trie_store::compression::Compression;
+use history::trie_store::integrity;
+use history::trie_store::operations::{self, ReadResult};
+use history::trie_store::overlay::{OverlayTrieStore, TrieBatch};
+use history::trie_store::rocksdb::{RocksDbEnvironment, RocksDbTrieStore};
+use history::trie_store::{Readable, Transaction, TransactionSource, Writable};
+use history::{CheckpointId, CommitResult, History, PruneResult};
+use metrics::{OperationTimer, StoreMetrics, StoreOperation};
+use shared::newtypes::Blake2bHash;
+use transform::Transform;
+
+const TRIES_CF: &str = "tries";
+const ROOTS_CF: &str = "roots";
+/// Format version of the store, and the progress of its migration.
+const META_CF: &str = "meta";
+
+fn missing_tries(root_hash: &Blake2bHash) -> error::Error {
+    error::Error::RocksDb(format!("Tries of {:?} are missing", root_hash))
+}
+
+/// Global state persisted in a [`RocksDbEnvironment`].
+pub struct RocksDbGlobalState {
+    environment: Arc<RocksDbEnvironment>,
+    trie_store: RocksDbTrieStore,
+    empty_root_hash: Blake2bHash,
+    checkpoints: Checkpoints,
+}
+
+impl RocksDbGlobalState {
+    /// Opens the global state kept in the database in the directory at
+    /// `path`, creating the database and the empty state if they don't exist
+    /// yet.
+    pub fn open(path: &PathBuf) -> Result<RocksDbGlobalState, error::Error> {
+        let column_families = [TRIES_CF, ROOTS_CF, META_CF];
+        let environment = Arc::new(RocksDbEnvironment::new(path, &column_families)?);
+        let trie_store = RocksDbTrieStore::new(&environment, TRIES_CF)?;
+        let empty_root_hash = {
+            let mut txn = environment.create_read_write_txn()?;
+            let empty_trie = operations::empty_trie::<Key, Value>();
+            let empty_root_hash = operations::put_trie(&mut txn, &trie_store, &empty_trie)?;
+            let root_key = empty_root_hash.to_bytes()?;
+            // Every store opened before has the empty state.
+            let created = txn.read(ROOTS_CF, &root_key)?.is_none();
+            migration::record_format_version(&mut txn, META_CF, created)?;
+            integrity::write_record(&mut txn, ROOTS_CF, &root_key, &[])?;
+            txn.commit()?;
+            empty_root_hash
+        };
+        Ok(RocksDbGlobalState {
+            environment,
+            trie_store,
+            empty_root_hash,
+            checkpoints: Checkpoints::new(),
+        })
+    }
+
+    /// Root hash of the state without any values.
+    pub fn empty_root_hash(&self) -> Blake2bHash {
+        self.empty_root_hash
+    }
+
+    pub fn environment(&self) -> &RocksDbEnvironment {
+        &self.environment
+    }
+
+    /// The global state, reporting how long its operations take and the
+    /// tries it reads and writes to `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn StoreMetrics>) -> RocksDbGlobalState {
+        self.trie_store = self.trie_store.with_metrics(metrics);
+        self
+    }
+
+    /// The global state, compressing the tries it writes from now on with
+    /// `compression`. The tries written before are read all the same.
+    pub fn with_compression(mut self, compression: Compression) -> RocksDbGlobalState {
+        self.trie_store = self.trie_store.with_compression(compression);
+        self
+    }
+
+    /// Version of the format the tries are stored in.
+    pub fn format_version(&self) -> Result<u32, error::Error> {
+        let txn = self.environment.create_read_txn()?;
+        let version = migration::format_version(&txn, META_CF, self.trie_store.metrics())?;
+        txn.commit()?;
+        Ok(version)
+    }
+
+    /// Migrates the tries to the format version of `migrator`, before the
+    /// global state is used.
+    pub fn migrate(&mut self, migrator: &Migrator) -> Result<MigrationReport, MigrationError> {
+        migrator.run(&*self.environment, TRIES_CF, META_CF, self.trie_store.metrics())
+    }
+
+    /// Whether the state at the root hash `root_bytes` was committed,
+    /// checking the record of its root.
+    fn has_root<T>(&self, txn: &T, root_bytes: &[u8]) -> Result<bool, error::Error>
+    where
+        T: Readable<Handle = &'static str>,
+        error::Error: From<T::Error>,
+    {
+        let root = integrity::read_record(txn, ROOTS_CF, root_bytes, self.trie_store.metrics())?;
+        Ok(root.is_some())
+    }
+
+    /// Tries of the state at `root_hash` which a checkpoint holds, none if
+    /// the state is committed. Returns `None` if there's no such state.
+    fn find_state(
+        &self,
+        root_hash: &Blake2bHash,
+    ) -> Result<Option<Arc<TrieBatch<Key, Value>>>, error::Error> {
+        if let Some(pending) = self.checkpoints.find(root_hash) {
+            return Ok(Some(Arc::clone(pending.tries())));
+        }
+        let txn = self.environment.create_read_txn()?;
+        let found = self.has_root(&txn, &root_hash.to_bytes()?)?;
+        txn.commit()?;
+        Ok(if found { Some(Arc::default()) } else { None })
+    }
+
+    /// Writes a snapshot of the state at `root_hash` to `writer`, with at
+    /// most `chunk_size` tries per chunk. The tries are all read in one
+    /// transaction.
+    pub fn export_snapshot<W: Write>(
+        &self,
+        root_hash: Blake2bHash,
+        chunk_size: usize,
+        writer: &mut W,
+    ) -> Result<(), SnapshotError> {
+        let txn = self.environment.create_read_txn()?;
+        snapshot::export(&txn, &self.trie_store, &root_hash, chunk_size, writer)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Imports the state of the snapshot read from `reader`, which can be
+    /// checked out once its root hash is returned.
+    pub fn import_snapshot<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Blake2bHash, SnapshotError> {
+        let root_hash = snapshot::import(&*self.environment, &self.trie_store, reader)?;
+        let mut txn = self.environment.create_read_write_txn()?;
+        integrity::write_record(&mut txn, ROOTS_CF, &root_hash.to_bytes()?, &[])?;
+        txn.commit()?;
+        Ok(root_hash)
+    }
+}
+
+/// A state in a [`RocksDbGlobalState`]. Every read is a transaction of its
+/// own.
+#[derive(Clone)]
+pub struct RocksDbGlobalStateView {
+    environment: Arc<RocksDbEnvironment>,
+    trie_store: RocksDbTrieStore,
+    root_hash: Blake2bHash,
+    /// Tries of the state held by a checkpoint.
+    pending_tries: Arc<TrieBatch<Key, Value>>,
+}
+
+impl RocksDbGlobalStateView {
+    pub fn root_hash(&self) -> Blake2bHash {
+        self.root_hash
+    }
+}
+
+impl StateReader<Key, Value> for RocksDbGlobalStateView {
+    type Error = error::Error;
+
+    fn read(&self, key: &Key) -> Result<Option<Value>, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Read);
+        let txn = self.environment.create_read_txn()?;
+        let trie_store = OverlayTrieStore::new(&self.trie_store, &self.pending_tries);
+        let result = operations::read(&txn, &trie_store, &self.root_hash, key)?;
+        txn.commit()?;
+        match result {
+            ReadResult::Found(value) => Ok(Some(value)),
+            ReadResult::NotFound => Ok(None),
+            // Views are only made of known roots, whose tries are only
+            // removed by pruning.
+            ReadResult::RootNotFound => Err(missing_tries(&self.root_hash)),
+        }
+    }
+
+    fn read_with_proof(
+        &self,
+        key: &Key,
+    ) -> Result<Option<TrieMerkleProof<Key, Value>>, Self::Error> {
+        let _timer =
+            OperationTimer::start(self.trie_store.metrics(), StoreOperation::ReadWithProof);
+        let txn = self.environment.create_read_txn()?;
+        let trie_store = OverlayTrieStore::new(&self.trie_store, &self.pending_tries);
+        let result = operations::prove(&txn, &trie_store, &self.root_hash, key)?;
+        txn.commit()?;
+        match result {
+            ReadResult::Found(proof) => Ok(Some(proof)),
+            ReadResult::NotFound => Ok(None),
+            ReadResult::RootNotFound => Err(missing_tries(&self.root_hash)),
+        }
+    }
+
+    fn read_stored(&self, key: &Key) -> Result<Option<StoredValue<Value>>, Self::Error> {
+        if !self.pending_tries.is_empty() {
+            // The tries a checkpoint holds are kept decoded.
+            return Ok(self.read(key)?.map(StoredValue::Decoded));
+        }
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Read);
+        let txn = self.environment.create_read_txn()?;
+        let result: ReadResult<StoredValue<Value>> =
+            operations::read(&txn, &self.trie_store, &self.root_hash, key)?;
+        txn.commit()?;
+        match result {
+            ReadResult::Found(value) => Ok(Some(value)),
+            ReadResult::NotFound => Ok(None),
+            ReadResult::RootNotFound => Err(missing_tries(&self.root_hash)),
+        }
+    }
+
+    fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Option<Vec<(Key, Value)>>, Self::Error> {
+        let _timer =
+            OperationTimer::start(self.trie_store.metrics(), StoreOperation::KeysWithPrefix);
+        let txn = self.environment.create_read_txn()?;
+        let trie_store = OverlayTrieStore::new(&self.trie_store, &self.pending_tries);
+        let pairs = global_state::keys_with_prefix(&txn, &trie_store, &self.root_hash, prefix)?;
+        txn.commit()?;
+        match pairs {
+            Some(pairs) => Ok(Some(pairs)),
+            None => Err(missing_tries(&self.root_hash)),
+        }
+    }
+}
+
+impl History for RocksDbGlobalState {
+    type Error = error::Error;
+    type Reader = RocksDbGlobalStateView;
+
+    fn checkout(&self, prestate_hash: Blake2bHash) -> Result<Option<Self::Reader>, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Checkout);
+        let pending_tries = match self.find_state(&prestate_hash)? {
+            Some(pending_tries) => pending_tries,
+            None => return Ok(None),
+        };
+        Ok(Some(RocksDbGlobalStateView {
+            environment: Arc::clone(&self.environment),
+            trie_store: self.trie_store.clone(),
+            root_hash: prestate_hash,
+            pending_tries,
+        }))
+    }
+
+    fn commit(
+        &self,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Commit);
+        // Returning early drops the transaction, which aborts it.
+        let mut txn = self.environment.create_read_write_txn()?;
+        if !self.has_root(&txn, &prestate_hash.to_bytes()?)? {
+            return Ok(CommitResult::RootNotFound);
+        }
+        let result = global_state::commit(&mut txn, &self.trie_store, prestate_hash, effects)?;
+        if let CommitResult::Success(root_hash) = &result {
+            integrity::write_record(&mut txn, ROOTS_CF, &root_hash.to_bytes()?, &[])?;
+            txn.commit()?;
+        }
+        Ok(result)
+    }
+
+    fn checkpoint(&mut self) -> CheckpointId {
+        self.checkpoints.open()
+    }
+
+    fn commit_at(
+        &mut self,
+        checkpoint: CheckpointId,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::CommitAt);
+        // Nothing is written in the transaction, which is aborted.
+        let mut txn = self.environment.create_read_write_txn()?;
+        let is_pending = self
+            .checkpoints
+            .get(checkpoint)
+            .map_or(false, |pending| pending.roots().contains(&prestate_hash));
+        if !is_pending && !self.has_root(&txn, &prestate_hash.to_bytes()?)? {
+            return Ok(CommitResult::RootNotFound);
+        }
+        self.checkpoints.commit(
+            &mut txn,
+            &self.trie_store,
+            checkpoint,
+            prestate_hash,
+            effects,
+        )
+    }
+
+    fn rollback(&mut self, checkpoint: CheckpointId) {
+        self.checkpoints.close(checkpoint);
+    }
+
+    fn commit_checkpoint(&mut self, checkpoint: CheckpointId) -> Result<(), Self::Error> {
+        let _timer =
+            OperationTimer::start(self.trie_store.metrics(), StoreOperation::CommitCheckpoint);
+        {
+            let pending = match self.checkpoints.get(checkpoint) {
+                Some(pending) => pending,
+                None => return Ok(()),
+            };
+            let mut txn = self.environment.create_read_write_txn()?;
+            pending::put_tries(&mut txn, &self.trie_store, pending)?;
+            for root_hash in pending.roots() {
+                integrity::write_record(&mut txn, ROOTS_CF, &root_hash.to_bytes()?, &[])?;
+            }
+            txn.commit()?;
+        }
+        self.checkpoints.close(checkpoint);
+        Ok(())
+    }
+
+    fn diff(
+        &self,
+        from: Blake2bHash,
+        to: Blake2bHash,
+    ) -> Result<Option<Vec<(Key, Option<Value>, Option<Value>)>>, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Diff);
+        let (from_tries, to_tries) = match (self.find_state(&from)?, self.find_state(&to)?) {
+            (Some(from_tries), Some(to_tries)) => (from_tries, to_tries),
+            _ => return Ok(None),
+        };
+        let txn = self.environment.create_read_txn()?;
+        let changes = {
+            let from_store = OverlayTrieStore::new(&self.trie_store, &from_tries);
+            let trie_store = OverlayTrieStore::new(&from_store, &to_tries);
+            global_state::diff(&txn, &trie_store, &from, &to)?
+        };
+        txn.commit()?;
+        match changes {
+            Some(changes) => Ok(Some(changes)),
+            // Known states have all their tries until they're pruned.
+            None => Err(error::Error::RocksDb(format!(
+                "Tries of {:?} or {:?} are missing",
+                from, to
+            ))),
+        }
+    }
+
+    fn prune(
+        &mut self,
+        retained_roots: &[Blake2bHash],
+        batch_size: usize,
+    ) -> Result<PruneResult, Self::Error> {
+        let _timer = OperationTimer::start(self.trie_store.metrics(), StoreOperation::Prune);
+        // The other roots are forgotten before any trie is deleted, so no
+        // state missing some of its tries can be checked out.
+        let mut txn = self.environment.create_read_write_txn()?;
+        let mut retained = HashSet::new();
+        for root in retained_roots {
+            let root_bytes = root.to_bytes()?;
+            if !self.has_root(&txn, &root_bytes)? {
+                return Ok(PruneResult::RootNotFound(*root));
+            }
+            retained.insert(root_bytes);
+        }
+        self.checkpoints.clear();
+        for root_bytes in txn.keys(ROOTS_CF)? {
+            if !retained.contains(&root_bytes) {
+                txn.delete(ROOTS_CF, &root_bytes)?;
+            }
+        }
+        txn.commit()?;
+        match global_state::prune_tries(
+            &*self.environment,
+            &self.trie_store,
+            retained_roots,
+            batch_size,
+        )? {
+            Some(deleted) => Ok(PruneResult::Pruned(deleted)),
+            // Committed roots keep all their tries.
+            None => Err(error::Error::RocksDb(
+                "Tries of the retained states are missing".to_owned(),
+            )),
+        }
+    }
+}
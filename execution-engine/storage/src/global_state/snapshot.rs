@@ -0,0 +1,344 @@
+//! Snapshots of a state kept as tries, to bootstrap a global state with it
+//! without committing every state before it.
+//!
+//! A snapshot starts with a header naming the root hash of the state. Chunks
+//! of the tries of the state follow, every trie after the ones it points to,
+//! and an empty chunk ends it. Each chunk carries a checksum of its tries, and
+//! the tries are only put in the store once the ones they point to are there,
+//! so a root found in the store after an import has all of its tries.
+//!
+//! Imports commit a transaction per chunk. An interrupted import is resumed
+//! by importing the same snapshot again: the tries already there are skipped.
+
+use std::cmp;
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::mem;
+
+use common::bytesrepr::{self, ToBytes, U32_SIZE};
+use common::key::Key;
+use common::value::Value;
+use error;
+use history::trie::Trie;
+use history::trie_store::in_memory;
+use history::trie_store::{Readable, Transaction, TransactionSource, TrieStore};
+use lmdb;
+use shared::newtypes::Blake2bHash;
+
+const SNAPSHOT_MAGIC: &[u8] = b"CLSNAPSH";
+const SNAPSHOT_VERSION: u32 = 1;
+const HASH_LENGTH: usize = 32;
+/// Longest chunk read, so a corrupted length can't exhaust the memory.
+const MAX_CHUNK_LENGTH: u32 = 256 * 1024 * 1024;
+
+#[derive(Debug, Fail)]
+pub enum SnapshotError {
+    #[fail(display = "{}", _0)]
+    Io(#[fail(cause)] io::Error),
+
+    #[fail(display = "{}", _0)]
+    Storage(#[fail(cause)] error::Error),
+
+    #[fail(display = "Not a snapshot, or one of an unknown version")]
+    InvalidHeader,
+
+    #[fail(display = "Trie {:?} isn't in the store", _0)]
+    MissingTrie(Blake2bHash),
+
+    #[fail(display = "Chunk {} is too long", _0)]
+    ChunkTooLong(usize),
+
+    #[fail(display = "Checksum of chunk {} doesn't match its tries", _0)]
+    ChecksumMismatch(usize),
+
+    #[fail(display = "Trie {:?} comes before the tries it points to", _0)]
+    MissingChildren(Blake2bHash),
+
+    #[fail(display = "Snapshot ended without the tries of its root {:?}", _0)]
+    MissingRoot(Blake2bHash),
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<error::Error> for SnapshotError {
+    fn from(error: error::Error) -> Self {
+        SnapshotError::Storage(error)
+    }
+}
+
+impl From<lmdb::Error> for SnapshotError {
+    fn from(error: lmdb::Error) -> Self {
+        SnapshotError::Storage(error.into())
+    }
+}
+
+impl From<in_memory::Error> for SnapshotError {
+    fn from(error: in_memory::Error) -> Self {
+        SnapshotError::Storage(error.into())
+    }
+}
+
+impl From<bytesrepr::Error> for SnapshotError {
+    fn from(error: bytesrepr::Error) -> Self {
+        SnapshotError::Storage(error.into())
+    }
+}
+
+/// Hashes of the tries `trie` points to.
+fn children(trie: &Trie<Key, Value>) -> Vec<Blake2bHash> {
+    match trie {
+        Trie::Leaf { .. } => Vec::new(),
+        Trie::Node { pointer_block } => (0..256)
+            .filter_map(|index| pointer_block[index].map(|pointer| *pointer.hash()))
+            .collect(),
+        Trie::Extension { pointer, .. } => vec![*pointer.hash()],
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, tries: Vec<Vec<u8>>) -> Result<(), SnapshotError> {
+    let payload = tries.to_bytes()?;
+    writer.write_all(&(payload.len() as u32).to_bytes()?)?;
+    writer.write_all(&payload)?;
+    writer.write_all(&Blake2bHash::new(&payload).value())?;
+    Ok(())
+}
+
+/// Tries of the chunk at `index`, or `None` at the end of the snapshot.
+fn read_chunk<R: Read>(
+    reader: &mut R,
+    index: usize,
+) -> Result<Option<Vec<Vec<u8>>>, SnapshotError> {
+    let mut length_bytes = [0u8; U32_SIZE];
+    reader.read_exact(&mut length_bytes)?;
+    let length: u32 = bytesrepr::deserialize(&length_bytes)?;
+    if length == 0 {
+        return Ok(None);
+    }
+    if length > MAX_CHUNK_LENGTH {
+        return Err(SnapshotError::ChunkTooLong(index));
+    }
+    let mut payload = vec![0u8; length as usize];
+    reader.read_exact(&mut payload)?;
+    let mut checksum = [0u8; HASH_LENGTH];
+    reader.read_exact(&mut checksum)?;
+    if Blake2bHash::new(&payload).value() != checksum {
+        return Err(SnapshotError::ChecksumMismatch(index));
+    }
+    Ok(Some(bytesrepr::deserialize(&payload)?))
+}
+
+/// Writes a snapshot of the state at `root` in `store` to `writer`, with at
+/// most `chunk_size` tries per chunk. Tries shared within the state are
+/// written once.
+pub fn export<T, S, W>(
+    txn: &T,
+    store: &S,
+    root: &Blake2bHash,
+    chunk_size: usize,
+    writer: &mut W,
+) -> Result<(), SnapshotError>
+where
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<Key, Value>,
+    S::Error: From<T::Error>,
+    SnapshotError: From<S::Error>,
+    W: Write,
+{
+    enum Visit {
+        Enter(Blake2bHash),
+        Leave(Vec<u8>),
+    }
+
+    writer.write_all(SNAPSHOT_MAGIC)?;
+    writer.write_all(&SNAPSHOT_VERSION.to_bytes()?)?;
+    writer.write_all(&root.value())?;
+
+    let chunk_size = cmp::max(chunk_size, 1);
+    let mut chunk = Vec::with_capacity(chunk_size);
+    let mut visited = HashSet::new();
+    let mut pending = vec![Visit::Enter(*root)];
+    while let Some(visit) = pending.pop() {
+        match visit {
+            Visit::Enter(hash) => {
+                if !visited.insert(hash) {
+                    continue;
+                }
+                let trie = match store.get(txn, &hash)? {
+                    Some(trie) => trie,
+                    None => return Err(SnapshotError::MissingTrie(hash)),
+                };
+                // The trie is left once the tries it points to have been.
+                pending.push(Visit::Leave(trie.to_bytes()?));
+                for child in children(&trie).into_iter().rev() {
+                    if !visited.contains(&child) {
+                        pending.push(Visit::Enter(child));
+                    }
+                }
+            }
+            Visit::Leave(trie_bytes) => {
+                chunk.push(trie_bytes);
+                if chunk.len() == chunk_size {
+                    let full_chunk = mem::replace(&mut chunk, Vec::with_capacity(chunk_size));
+                    write_chunk(writer, full_chunk)?;
+                }
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        write_chunk(writer, chunk)?;
+    }
+    writer.write_all(&0u32.to_bytes()?)?;
+    Ok(())
+}
+
+/// Reads a snapshot from `reader`, putting its tries in `store` a chunk per
+/// transaction, and returns the root hash of its state.
+pub fn import<'a, E, S, R>(
+    environment: &'a E,
+    store: &S,
+    reader: &mut R,
+) -> Result<Blake2bHash, SnapshotError>
+where
+    E: TransactionSource<'a, Handle = S::Handle>,
+    S: TrieStore<Key, Value>,
+    S::Error: From<E::Error>,
+    SnapshotError: From<S::Error> + From<E::Error>,
+    R: Read,
+{
+    let mut header = vec![0u8; SNAPSHOT_MAGIC.len() + U32_SIZE + HASH_LENGTH];
+    reader.read_exact(&mut header)?;
+    let (magic, rest) = header.split_at(SNAPSHOT_MAGIC.len());
+    let (version, root_bytes) = rest.split_at(U32_SIZE);
+    if magic != SNAPSHOT_MAGIC || bytesrepr::deserialize::<u32>(version)? != SNAPSHOT_VERSION {
+        return Err(SnapshotError::InvalidHeader);
+    }
+    let mut root = [0u8; HASH_LENGTH];
+    root.copy_from_slice(root_bytes);
+    let root = Blake2bHash::from(root);
+
+    let mut index = 0;
+    while let Some(tries) = read_chunk(reader, index)? {
+        let mut txn = environment.create_read_write_txn()?;
+        for trie_bytes in tries {
+            let hash = Blake2bHash::new(&trie_bytes);
+            if store.get(&txn, &hash)?.is_some() {
+                continue;
+            }
+            let trie: Trie<Key, Value> = bytesrepr::deserialize(&trie_bytes)?;
+            for child in children(&trie) {
+                if store.get(&txn, &child)?.is_none() {
+                    return Err(SnapshotError::MissingChildren(hash));
+                }
+            }
+            store.put(&mut txn, &hash, &trie)?;
+        }
+        txn.commit()?;
+        index += 1;
+    }
+
+    let txn = environment.create_read_txn()?;
+    let root_trie = store.get(&txn, &root)?;
+    txn.commit()?;
+    match root_trie {
+        Some(_) => Ok(root),
+        None => Err(SnapshotError::MissingRoot(root)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::tempdir;
+
+    use common::key::Key;
+    use common::value::Value;
+    use global_state::in_memory::InMemoryGlobalState;
+    use global_state::lmdb::LmdbGlobalState;
+    use global_state::StateReader;
+    use history::trie_store::lmdb::LmdbEnvironment;
+    use history::History;
+    use shared::newtypes::Blake2bHash;
+
+    use super::SnapshotError;
+
+    fn pairs() -> Vec<(Key, Value)> {
+        (0u8..20)
+            .map(|i| (Key::Hash([i; 32]), Value::Int32(i.into())))
+            .collect()
+    }
+
+    fn snapshot(chunk_size: usize) -> (Vec<u8>, Blake2bHash) {
+        let (global_state, root_hash) = InMemoryGlobalState::from_pairs(&pairs()).unwrap();
+        let mut snapshot = Vec::new();
+        global_state
+            .export_snapshot(root_hash, chunk_size, &mut snapshot)
+            .unwrap();
+        (snapshot, root_hash)
+    }
+
+    #[test]
+    fn imported_states_are_the_exported_ones() {
+        let (snapshot, root_hash) = snapshot(4);
+        let dir = tempdir().unwrap();
+        let environment = LmdbEnvironment::new(&dir.path().to_path_buf()).unwrap();
+        let mut global_state = LmdbGlobalState::new(Arc::new(environment)).unwrap();
+        assert!(global_state.checkout(root_hash).unwrap().is_none());
+
+        let imported_root_hash = global_state.import_snapshot(&mut &snapshot[..]).unwrap();
+        assert_eq!(imported_root_hash, root_hash);
+        let reader = global_state.checkout(root_hash).unwrap().unwrap();
+        for (key, value) in pairs() {
+            assert_eq!(reader.read(&key).unwrap(), Some(value));
+        }
+        // Snapshots of the same state are the same.
+        let mut exported = Vec::new();
+        global_state
+            .export_snapshot(root_hash, 4, &mut exported)
+            .unwrap();
+        assert_eq!(exported, snapshot);
+    }
+
+    #[test]
+    fn interrupted_imports_are_resumed() {
+        let (snapshot, root_hash) = snapshot(1);
+        let mut global_state = InMemoryGlobalState::empty().unwrap();
+        let truncated = &snapshot[..snapshot.len() / 2];
+        match global_state.import_snapshot(&mut &truncated[..]) {
+            Err(SnapshotError::Io(_)) => (),
+            other => panic!("Expected the snapshot to be cut short, got {:?}", other),
+        }
+        assert!(global_state.checkout(root_hash).unwrap().is_none());
+
+        let imported_root_hash = global_state.import_snapshot(&mut &snapshot[..]).unwrap();
+        assert_eq!(imported_root_hash, root_hash);
+        assert!(global_state.checkout(root_hash).unwrap().is_some());
+    }
+
+    #[test]
+    fn corrupted_snapshots_are_rejected() {
+        let (snapshot, root_hash) = snapshot(4);
+        let mut global_state = InMemoryGlobalState::empty().unwrap();
+
+        let mut corrupted = snapshot.clone();
+        corrupted[0] ^= 1;
+        match global_state.import_snapshot(&mut &corrupted[..]) {
+            Err(SnapshotError::InvalidHeader) => (),
+            other => panic!("Expected an invalid header, got {:?}", other),
+        }
+
+        // A byte of the checksum of the last chunk, before the empty one.
+        let mut corrupted = snapshot.clone();
+        let index = corrupted.len() - 10;
+        corrupted[index] ^= 1;
+        match global_state.import_snapshot(&mut &corrupted[..]) {
+            Err(SnapshotError::ChecksumMismatch(_)) => (),
+            other => panic!("Expected a checksum mismatch, got {:?}", other),
+        }
+        assert!(global_state.checkout(root_hash).unwrap().is_none());
+    }
+}
@@ -0,0 +1,116 @@
+//! Values of the global state which are only decoded when they're needed.
+//!
+//! Reading a value out of a trie decodes it, which is wasted when all the
+//! reader does with it is passing its bytes on, as contracts reading values
+//! get them. A [`StoredValue`] holds the bytes the value was stored as until
+//! it's decoded, and gives them back as they are.
+
+use common::bytesrepr::{self, FromBytes, ToBytes};
+
+/// A value, either still in the bytes it's stored as or decoded.
+#[derive(Debug, Clone)]
+pub enum StoredValue<V> {
+    /// Bytes of the value, not decoded yet.
+    Raw(Vec<u8>),
+    Decoded(V),
+}
+
+impl<V: FromBytes> StoredValue<V> {
+    /// The value, decoding it if it wasn't yet.
+    pub fn into_value(self) -> Result<V, bytesrepr::Error> {
+        match self {
+            StoredValue::Raw(bytes) => bytesrepr::deserialize(&bytes),
+            StoredValue::Decoded(value) => Ok(value),
+        }
+    }
+
+    /// The value, decoding it in place if it wasn't yet, so that it's only
+    /// decoded once.
+    pub fn decode(&mut self) -> Result<&V, bytesrepr::Error> {
+        let decoded = match self {
+            StoredValue::Raw(bytes) => Some(bytesrepr::deserialize(bytes)?),
+            StoredValue::Decoded(_) => None,
+        };
+        if let Some(value) = decoded {
+            *self = StoredValue::Decoded(value);
+        }
+        match self {
+            StoredValue::Decoded(value) => Ok(value),
+            StoredValue::Raw(_) => unreachable!(),
+        }
+    }
+}
+
+impl<V: ToBytes> StoredValue<V> {
+    /// Bytes of the value, which are the ones it was stored as unless it was
+    /// decoded.
+    pub fn into_bytes(self) -> Result<Vec<u8>, bytesrepr::Error> {
+        match self {
+            StoredValue::Raw(bytes) => Ok(bytes),
+            StoredValue::Decoded(value) => value.to_bytes(),
+        }
+    }
+}
+
+impl<V> From<V> for StoredValue<V> {
+    fn from(value: V) -> Self {
+        StoredValue::Decoded(value)
+    }
+}
+
+impl<V: ToBytes> ToBytes for StoredValue<V> {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        match self {
+            StoredValue::Raw(bytes) => Ok(bytes.clone()),
+            StoredValue::Decoded(value) => value.to_bytes(),
+        }
+    }
+}
+
+/// Values aren't prefixed with their length, so a stored value takes all the
+/// bytes left. It can only be the last thing decoded from some bytes, like
+/// the value of a leaf of a trie.
+impl<V> FromBytes for StoredValue<V> {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        Ok((StoredValue::Raw(bytes.to_vec()), &[]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::bytesrepr::{self, ToBytes};
+    use common::gens::value_arb;
+    use common::key::Key;
+    use common::value::Value;
+    use history::trie::Trie;
+    use proptest::prelude::*;
+
+    use super::StoredValue;
+
+    proptest! {
+        #[test]
+        fn raw_values_decode_to_the_values(value in value_arb()) {
+            let bytes = value.to_bytes().unwrap();
+            let mut stored: StoredValue<Value> = StoredValue::Raw(bytes.clone());
+            assert_eq!(stored.clone().into_value().unwrap(), value);
+            assert_eq!(stored.clone().into_bytes().unwrap(), bytes);
+            assert_eq!(stored.decode().unwrap(), &value);
+            assert_eq!(stored.into_bytes().unwrap(), bytes);
+        }
+
+        #[test]
+        fn leaves_read_raw_are_the_leaves(value in value_arb()) {
+            let leaf = Trie::Leaf { key: Key::Hash([7u8; 32]), value };
+            let bytes = leaf.to_bytes().unwrap();
+            let raw: Trie<Key, StoredValue<Value>> = bytesrepr::deserialize(&bytes).unwrap();
+            assert_eq!(raw.to_bytes().unwrap(), bytes);
+            match (raw, leaf) {
+                (Trie::Leaf { key: raw_key, value: raw_value }, Trie::Leaf { key, value }) => {
+                    assert_eq!(raw_key, key);
+                    assert_eq!(raw_value.into_value().unwrap(), value);
+                }
+                _ => panic!("Leaves should stay leaves."),
+            }
+        }
+    }
+}
@@ -9,6 +9,9 @@ use transform::{Transform, TypeMismatch};
 pub mod trie;
 pub mod trie_store;
 
+#[cfg(test)]
+mod tests;
+
 pub enum CommitResult {
     RootNotFound,
     Success(Blake2bHash),
@@ -17,6 +20,18 @@ pub enum CommitResult {
     Overflow,
 }
 
+/// A batch of states held in memory. See [`History::checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CheckpointId(pub(crate) u64);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PruneResult {
+    /// One of the states to retain isn't there. Nothing was pruned.
+    RootNotFound(Blake2bHash),
+    /// Number of entries deleted from the store.
+    Pruned(usize),
+}
+
 pub trait History {
     type Error;
     type Reader: StateReader<Key, Value, Error = Self::Error>;
@@ -26,9 +41,56 @@ pub trait History {
 
     /// Applies changes and returns a new post state hash.
     /// block_hash is used for computing a deterministic and unique keys.
+    /// Commits only need a shared reference, so that the states already
+    /// committed can be checked out and read while they run.
     fn commit(
+        &self,
+        prestate_hash: Blake2bHash,
+        effects: HashMap<Key, Transform>,
+    ) -> Result<CommitResult, Self::Error>;
+
+    /// Starts a batch of states held in memory, which are only committed
+    /// with [`commit_at`](History::commit_at) and are kept apart from the
+    /// states committed otherwise until the checkpoint is committed. They can
+    /// be checked out meanwhile.
+    fn checkpoint(&mut self) -> CheckpointId;
+
+    /// Applies changes like [`commit`](History::commit), holding the new
+    /// state in the batch of the checkpoint. The prestate is either committed
+    /// or in the batch. Checkpoints which were already committed or rolled
+    /// back have no batch, so committing at them gives
+    /// [`CommitResult::RootNotFound`].
+    fn commit_at(
         &mut self,
+        checkpoint: CheckpointId,
         prestate_hash: Blake2bHash,
         effects: HashMap<Key, Transform>,
     ) -> Result<CommitResult, Self::Error>;
+
+    /// Forgets the states held by the checkpoint. Checkpoints which were
+    /// already committed or rolled back are ignored.
+    fn rollback(&mut self, checkpoint: CheckpointId);
+
+    /// Commits the states held by the checkpoint at once. Checkpoints which
+    /// were already committed or rolled back are ignored.
+    fn commit_checkpoint(&mut self, checkpoint: CheckpointId) -> Result<(), Self::Error>;
+
+    /// Keys whose values differ between the states at `from` and `to`, with
+    /// their values in both, if they have any. States held by checkpoints can
+    /// be compared too. Returns `None` if one of the states isn't there.
+    fn diff(
+        &self,
+        from: Blake2bHash,
+        to: Blake2bHash,
+    ) -> Result<Option<Vec<(Key, Option<Value>, Option<Value>)>>, Self::Error>;
+
+    /// Forgets every state but `retained_roots` and deletes what only the
+    /// forgotten states were kept in, at most `batch_size` entries per
+    /// transaction. Readers of the forgotten states can't be used anymore,
+    /// and the checkpoints are rolled back.
+    fn prune(
+        &mut self,
+        retained_roots: &[Blake2bHash],
+        batch_size: usize,
+    ) -> Result<PruneResult, Self::Error>;
 }
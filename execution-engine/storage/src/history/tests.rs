@@ -0,0 +1,1001 @@
+//! Tests run on every implementation of [`History`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use common::bytesrepr::ToBytes;
+use common::key::Key;
+use common::value::{Value, U512};
+use error;
+use global_state::in_memory::InMemoryGlobalState;
+use global_state::inmem::InMemHist;
+use global_state::lmdb::LmdbGlobalState;
+use global_state::migration::{self, Migration, Migrator};
+use global_state::rocksdb::RocksDbGlobalState;
+use global_state::StateReader;
+#[cfg(feature = "compression")]
+use history::trie_store::compression::Compression;
+use history::trie_store::lmdb::LmdbEnvironment;
+use history::{CheckpointId, CommitResult, History, PruneResult};
+use metrics::{StoreCounters, StoreCounts, StoreOperation};
+use shared::newtypes::Blake2bHash;
+use tempfile::{tempdir, TempDir};
+use transform::Transform;
+
+const KEY1: Key = Key::Account([1u8; 20]);
+const KEY2: Key = Key::Account([2u8; 20]);
+const KEY3: Key = Key::Hash([1u8; 32]);
+
+fn initial_state() -> BTreeMap<Key, Value> {
+    let mut state = BTreeMap::new();
+    state.insert(KEY1, Value::Int32(1));
+    state.insert(KEY2, Value::Int32(2));
+    state
+}
+
+fn writes(values: &[(Key, Value)]) -> HashMap<Key, Transform> {
+    values
+        .iter()
+        .map(|(key, value)| (*key, Transform::Write(value.clone())))
+        .collect()
+}
+
+fn commit<H>(hist: &mut H, hash: Blake2bHash, effects: HashMap<Key, Transform>) -> Blake2bHash
+where
+    H: History,
+    H::Error: Debug,
+{
+    match hist.commit(hash, effects).unwrap() {
+        CommitResult::Success(new_hash) => new_hash,
+        _ => panic!("Test commit failed."),
+    }
+}
+
+fn read<H>(hist: &H, hash: Blake2bHash, key: Key) -> Option<Value>
+where
+    H: History,
+    H::Error: Debug,
+{
+    let reader = hist.checkout(hash).unwrap().expect("Root should exist.");
+    reader.read(&key).unwrap()
+}
+
+fn in_mem_hist() -> (InMemHist<Key, Value>, Blake2bHash) {
+    let root_hash = [0u8; 32].into();
+    (InMemHist::new_initialized(&root_hash, initial_state()), root_hash)
+}
+
+fn in_memory() -> (InMemoryGlobalState, Blake2bHash) {
+    let state: Vec<(Key, Value)> = initial_state().into_iter().collect();
+    InMemoryGlobalState::from_pairs(&state).unwrap()
+}
+
+/// The LMDB-backed global state with the initial state committed on top of
+/// the empty one. The directory of the environment has to outlive it.
+fn lmdb() -> (LmdbGlobalState, Blake2bHash, TempDir) {
+    let dir = tempdir().unwrap();
+    let environment = LmdbEnvironment::new(&dir.path().to_path_buf()).unwrap();
+    let mut global_state = LmdbGlobalState::new(Arc::new(environment)).unwrap();
+    let empty_root_hash = global_state.empty_root_hash();
+    let state: Vec<(Key, Value)> = initial_state().into_iter().collect();
+    let root_hash = commit(&mut global_state, empty_root_hash, writes(&state));
+    (global_state, root_hash, dir)
+}
+
+/// Like [`lmdb`], with the global state persisted in RocksDB.
+fn rocksdb() -> (RocksDbGlobalState, Blake2bHash, TempDir) {
+    let dir = tempdir().unwrap();
+    let mut global_state = RocksDbGlobalState::open(&dir.path().to_path_buf()).unwrap();
+    let empty_root_hash = global_state.empty_root_hash();
+    let state: Vec<(Key, Value)> = initial_state().into_iter().collect();
+    let root_hash = commit(&mut global_state, empty_root_hash, writes(&state));
+    (global_state, root_hash, dir)
+}
+
+fn checkout_reads_the_state<H>(hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    assert_eq!(read(&hist, root_hash, KEY1), Some(Value::Int32(1)));
+    assert_eq!(read(&hist, root_hash, KEY2), Some(Value::Int32(2)));
+    assert_eq!(read(&hist, root_hash, KEY3), None);
+    assert!(hist.checkout([9u8; 32].into()).unwrap().is_none());
+}
+
+#[test]
+fn in_mem_hist_checkout_reads_the_state() {
+    let (hist, root_hash) = in_mem_hist();
+    checkout_reads_the_state(hist, root_hash);
+}
+
+#[test]
+fn in_memory_checkout_reads_the_state() {
+    let (hist, root_hash) = in_memory();
+    checkout_reads_the_state(hist, root_hash);
+}
+
+#[test]
+fn lmdb_checkout_reads_the_state() {
+    let (global_state, root_hash, _dir) = lmdb();
+    checkout_reads_the_state(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_checkout_reads_the_state() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    checkout_reads_the_state(global_state, root_hash);
+}
+
+fn commits_leave_earlier_states_alone<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let effects = {
+        let mut effects = writes(&[(KEY3, Value::String("three".to_owned()))]);
+        effects.insert(KEY1, Transform::AddInt32(10));
+        effects
+    };
+    let new_root_hash = commit(&mut hist, root_hash, effects);
+    assert_ne!(new_root_hash, root_hash);
+    assert_eq!(read(&hist, new_root_hash, KEY1), Some(Value::Int32(11)));
+    assert_eq!(read(&hist, new_root_hash, KEY2), Some(Value::Int32(2)));
+    assert_eq!(
+        read(&hist, new_root_hash, KEY3),
+        Some(Value::String("three".to_owned()))
+    );
+
+    assert_eq!(read(&hist, root_hash, KEY1), Some(Value::Int32(1)));
+    assert_eq!(read(&hist, root_hash, KEY3), None);
+}
+
+#[test]
+fn in_mem_hist_commits_leave_earlier_states_alone() {
+    let (hist, root_hash) = in_mem_hist();
+    commits_leave_earlier_states_alone(hist, root_hash);
+}
+
+#[test]
+fn in_memory_commits_leave_earlier_states_alone() {
+    let (hist, root_hash) = in_memory();
+    commits_leave_earlier_states_alone(hist, root_hash);
+}
+
+#[test]
+fn lmdb_commits_leave_earlier_states_alone() {
+    let (global_state, root_hash, _dir) = lmdb();
+    commits_leave_earlier_states_alone(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_commits_leave_earlier_states_alone() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    commits_leave_earlier_states_alone(global_state, root_hash);
+}
+
+fn failed_commits_are_reported<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let mut effects = HashMap::new();
+    effects.insert(KEY3, Transform::AddInt32(1));
+    match hist.commit(root_hash, effects).unwrap() {
+        CommitResult::KeyNotFound(key) => assert_eq!(key, KEY3),
+        _ => panic!("Adding to a missing key should fail."),
+    }
+
+    let mut effects = HashMap::new();
+    effects.insert(KEY1, Transform::AddUInt512(U512::from(1)));
+    match hist.commit(root_hash, effects).unwrap() {
+        CommitResult::TypeMismatch(_) => (),
+        _ => panic!("Adding a U512 to an i32 should fail."),
+    }
+
+    let effects = writes(&[(KEY3, Value::Int32(3))]);
+    match hist.commit([9u8; 32].into(), effects).unwrap() {
+        CommitResult::RootNotFound => (),
+        _ => panic!("Committing to a missing root should fail."),
+    }
+}
+
+#[test]
+fn in_mem_hist_failed_commits_are_reported() {
+    let (hist, root_hash) = in_mem_hist();
+    failed_commits_are_reported(hist, root_hash);
+}
+
+#[test]
+fn in_memory_failed_commits_are_reported() {
+    let (hist, root_hash) = in_memory();
+    failed_commits_are_reported(hist, root_hash);
+}
+
+#[test]
+fn lmdb_failed_commits_are_reported() {
+    let (global_state, root_hash, _dir) = lmdb();
+    failed_commits_are_reported(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_failed_commits_are_reported() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    failed_commits_are_reported(global_state, root_hash);
+}
+
+fn root_hashes_depend_only_on_the_state<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let one = writes(&[(KEY3, Value::Int32(3))]);
+    let other = writes(&[(KEY3, Value::Int32(4))]);
+    let first = commit(&mut hist, root_hash, one.clone());
+    assert_eq!(commit(&mut hist, root_hash, one), first);
+    assert_ne!(commit(&mut hist, root_hash, other), first);
+}
+
+#[test]
+fn in_mem_hist_root_hashes_depend_only_on_the_state() {
+    let (hist, root_hash) = in_mem_hist();
+    root_hashes_depend_only_on_the_state(hist, root_hash);
+}
+
+#[test]
+fn in_memory_root_hashes_depend_only_on_the_state() {
+    let (hist, root_hash) = in_memory();
+    root_hashes_depend_only_on_the_state(hist, root_hash);
+}
+
+#[test]
+fn lmdb_root_hashes_depend_only_on_the_state() {
+    let (global_state, root_hash, _dir) = lmdb();
+    root_hashes_depend_only_on_the_state(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_root_hashes_depend_only_on_the_state() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    root_hashes_depend_only_on_the_state(global_state, root_hash);
+}
+
+fn keys_sharing_bytes_are_kept_apart<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    // Hashes sharing all but their last byte, then only their first one,
+    // with addresses of other lengths in between.
+    let mut values = Vec::new();
+    for i in 0..8u8 {
+        let mut long_prefix = [7u8; 32];
+        long_prefix[31] = i;
+        let mut short_prefix = [i; 32];
+        short_prefix[0] = 9;
+        values.push((Key::Hash(long_prefix), Value::Int32(i32::from(i))));
+        values.push((Key::Hash(short_prefix), Value::Int32(100 + i32::from(i))));
+        values.push((Key::Account([i; 20]), Value::Int32(200 + i32::from(i))));
+    }
+    let mut new_root_hash = root_hash;
+    for chunk in values.chunks(5) {
+        new_root_hash = commit(&mut hist, new_root_hash, writes(chunk));
+    }
+    for (key, value) in values {
+        assert_eq!(read(&hist, new_root_hash, key), Some(value));
+    }
+    assert_eq!(read(&hist, new_root_hash, Key::Hash([8u8; 32])), None);
+}
+
+#[test]
+fn in_mem_hist_keys_sharing_bytes_are_kept_apart() {
+    let (hist, root_hash) = in_mem_hist();
+    keys_sharing_bytes_are_kept_apart(hist, root_hash);
+}
+
+#[test]
+fn in_memory_keys_sharing_bytes_are_kept_apart() {
+    let (hist, root_hash) = in_memory();
+    keys_sharing_bytes_are_kept_apart(hist, root_hash);
+}
+
+#[test]
+fn lmdb_keys_sharing_bytes_are_kept_apart() {
+    let (global_state, root_hash, _dir) = lmdb();
+    keys_sharing_bytes_are_kept_apart(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_keys_sharing_bytes_are_kept_apart() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    keys_sharing_bytes_are_kept_apart(global_state, root_hash);
+}
+
+fn pruning_keeps_only_the_retained_states<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let first = commit(&mut hist, root_hash, writes(&[(KEY3, Value::Int32(3))]));
+    let second = commit(&mut hist, first, writes(&[(KEY3, Value::Int32(4))]));
+    let missing: Blake2bHash = [9u8; 32].into();
+    assert_eq!(
+        hist.prune(&[second, missing], 1).unwrap(),
+        PruneResult::RootNotFound(missing)
+    );
+    assert_eq!(read(&hist, first, KEY3), Some(Value::Int32(3)));
+
+    match hist.prune(&[root_hash, second], 1).unwrap() {
+        PruneResult::Pruned(deleted) => assert!(deleted > 0),
+        result => panic!("Pruning failed: {:?}", result),
+    }
+    assert!(hist.checkout(first).unwrap().is_none());
+    assert_eq!(read(&hist, root_hash, KEY1), Some(Value::Int32(1)));
+    assert_eq!(read(&hist, root_hash, KEY3), None);
+    assert_eq!(read(&hist, second, KEY1), Some(Value::Int32(1)));
+    assert_eq!(read(&hist, second, KEY2), Some(Value::Int32(2)));
+    assert_eq!(read(&hist, second, KEY3), Some(Value::Int32(4)));
+    // The retained states can still be committed to.
+    let third = commit(&mut hist, second, writes(&[(KEY3, Value::Int32(5))]));
+    assert_eq!(read(&hist, third, KEY2), Some(Value::Int32(2)));
+
+    assert_eq!(
+        hist.prune(&[root_hash, second, third], 10).unwrap(),
+        PruneResult::Pruned(0)
+    );
+}
+
+#[test]
+fn in_mem_hist_pruning_keeps_only_the_retained_states() {
+    let (hist, root_hash) = in_mem_hist();
+    pruning_keeps_only_the_retained_states(hist, root_hash);
+}
+
+#[test]
+fn in_memory_pruning_keeps_only_the_retained_states() {
+    let (hist, root_hash) = in_memory();
+    pruning_keeps_only_the_retained_states(hist, root_hash);
+}
+
+#[test]
+fn lmdb_pruning_keeps_only_the_retained_states() {
+    let (global_state, root_hash, _dir) = lmdb();
+    pruning_keeps_only_the_retained_states(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_pruning_keeps_only_the_retained_states() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    pruning_keeps_only_the_retained_states(global_state, root_hash);
+}
+
+fn commit_at<H>(
+    hist: &mut H,
+    checkpoint: CheckpointId,
+    hash: Blake2bHash,
+    effects: HashMap<Key, Transform>,
+) -> Blake2bHash
+where
+    H: History,
+    H::Error: Debug,
+{
+    match hist.commit_at(checkpoint, hash, effects).unwrap() {
+        CommitResult::Success(new_hash) => new_hash,
+        _ => panic!("Test commit failed."),
+    }
+}
+
+fn checkpoints_hold_states_until_committed<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let checkpoint = hist.checkpoint();
+    let first = commit_at(
+        &mut hist,
+        checkpoint,
+        root_hash,
+        writes(&[(KEY3, Value::Int32(3))]),
+    );
+    // States held by the checkpoint can be committed to within it only.
+    let second = commit_at(
+        &mut hist,
+        checkpoint,
+        first,
+        writes(&[(KEY1, Value::Int32(4))]),
+    );
+    let effects = writes(&[(KEY2, Value::Int32(5))]);
+    match hist.commit(first, effects).unwrap() {
+        CommitResult::RootNotFound => (),
+        _ => panic!("States held by a checkpoint shouldn't be committed."),
+    }
+    let other = hist.checkpoint();
+    let effects = writes(&[(KEY2, Value::Int32(5))]);
+    match hist.commit_at(other, first, effects).unwrap() {
+        CommitResult::RootNotFound => (),
+        _ => panic!("States held by another checkpoint shouldn't be seen."),
+    }
+    assert_eq!(read(&hist, first, KEY3), Some(Value::Int32(3)));
+    assert_eq!(read(&hist, second, KEY1), Some(Value::Int32(4)));
+    assert_eq!(read(&hist, second, KEY3), Some(Value::Int32(3)));
+
+    hist.commit_checkpoint(checkpoint).unwrap();
+    assert_eq!(read(&hist, second, KEY1), Some(Value::Int32(4)));
+    assert_eq!(read(&hist, root_hash, KEY1), Some(Value::Int32(1)));
+    // The committed states are like any other now.
+    let third = commit(&mut hist, second, writes(&[(KEY2, Value::Int32(5))]));
+    assert_eq!(read(&hist, third, KEY3), Some(Value::Int32(3)));
+    let effects = writes(&[(KEY2, Value::Int32(6))]);
+    match hist.commit_at(checkpoint, second, effects).unwrap() {
+        CommitResult::RootNotFound => (),
+        _ => panic!("A committed checkpoint should be closed."),
+    }
+}
+
+#[test]
+fn in_mem_hist_checkpoints_hold_states_until_committed() {
+    let (hist, root_hash) = in_mem_hist();
+    checkpoints_hold_states_until_committed(hist, root_hash);
+}
+
+#[test]
+fn in_memory_checkpoints_hold_states_until_committed() {
+    let (hist, root_hash) = in_memory();
+    checkpoints_hold_states_until_committed(hist, root_hash);
+}
+
+#[test]
+fn lmdb_checkpoints_hold_states_until_committed() {
+    let (global_state, root_hash, _dir) = lmdb();
+    checkpoints_hold_states_until_committed(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_checkpoints_hold_states_until_committed() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    checkpoints_hold_states_until_committed(global_state, root_hash);
+}
+
+fn rollbacks_forget_the_states_of_the_checkpoint<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let checkpoint = hist.checkpoint();
+    let other = hist.checkpoint();
+    let first = commit_at(
+        &mut hist,
+        checkpoint,
+        root_hash,
+        writes(&[(KEY3, Value::Int32(3))]),
+    );
+    let kept = commit_at(
+        &mut hist,
+        other,
+        root_hash,
+        writes(&[(KEY3, Value::Int32(4))]),
+    );
+
+    hist.rollback(checkpoint);
+    assert!(hist.checkout(first).unwrap().is_none());
+    assert_eq!(read(&hist, kept, KEY3), Some(Value::Int32(4)));
+    assert_eq!(read(&hist, root_hash, KEY3), None);
+    // Committing a checkpoint which was rolled back does nothing.
+    hist.commit_checkpoint(checkpoint).unwrap();
+    assert!(hist.checkout(first).unwrap().is_none());
+}
+
+#[test]
+fn in_mem_hist_rollbacks_forget_the_states_of_the_checkpoint() {
+    let (hist, root_hash) = in_mem_hist();
+    rollbacks_forget_the_states_of_the_checkpoint(hist, root_hash);
+}
+
+#[test]
+fn in_memory_rollbacks_forget_the_states_of_the_checkpoint() {
+    let (hist, root_hash) = in_memory();
+    rollbacks_forget_the_states_of_the_checkpoint(hist, root_hash);
+}
+
+#[test]
+fn lmdb_rollbacks_forget_the_states_of_the_checkpoint() {
+    let (global_state, root_hash, _dir) = lmdb();
+    rollbacks_forget_the_states_of_the_checkpoint(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_rollbacks_forget_the_states_of_the_checkpoint() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    rollbacks_forget_the_states_of_the_checkpoint(global_state, root_hash);
+}
+
+fn diffs_list_the_changed_values<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let effects = writes(&[(KEY1, Value::Int32(4)), (KEY3, Value::Int32(3))]);
+    let post = commit(&mut hist, root_hash, effects);
+    {
+        let diff = |from, to| {
+            let mut changes = hist
+                .diff(from, to)
+                .unwrap()
+                .expect("Both states should exist.");
+            changes.sort_by_key(|(key, _, _)| *key);
+            changes
+        };
+        let mut expected = vec![
+            (KEY1, Some(Value::Int32(1)), Some(Value::Int32(4))),
+            (KEY3, None, Some(Value::Int32(3))),
+        ];
+        expected.sort_by_key(|(key, _, _)| *key);
+        assert_eq!(diff(root_hash, post), expected);
+        let mut reversed = vec![
+            (KEY1, Some(Value::Int32(4)), Some(Value::Int32(1))),
+            (KEY3, Some(Value::Int32(3)), None),
+        ];
+        reversed.sort_by_key(|(key, _, _)| *key);
+        assert_eq!(diff(post, root_hash), reversed);
+        assert!(diff(post, post).is_empty());
+    }
+
+    // States held by checkpoints can be compared with committed ones.
+    let checkpoint = hist.checkpoint();
+    let pending = commit_at(
+        &mut hist,
+        checkpoint,
+        post,
+        writes(&[(KEY2, Value::Int32(5))]),
+    );
+    let changes = hist.diff(post, pending).unwrap();
+    let expected = vec![(KEY2, Some(Value::Int32(2)), Some(Value::Int32(5)))];
+    assert_eq!(changes, Some(expected));
+    let missing = [9u8; 32].into();
+    assert_eq!(hist.diff(root_hash, missing).unwrap(), None);
+}
+
+#[test]
+fn in_mem_hist_diffs_list_the_changed_values() {
+    let (hist, root_hash) = in_mem_hist();
+    diffs_list_the_changed_values(hist, root_hash);
+}
+
+#[test]
+fn in_memory_diffs_list_the_changed_values() {
+    let (hist, root_hash) = in_memory();
+    diffs_list_the_changed_values(hist, root_hash);
+}
+
+#[test]
+fn lmdb_diffs_list_the_changed_values() {
+    let (global_state, root_hash, _dir) = lmdb();
+    diffs_list_the_changed_values(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_diffs_list_the_changed_values() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    diffs_list_the_changed_values(global_state, root_hash);
+}
+
+fn keys_with_prefix_are_listed<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let post = commit(&mut hist, root_hash, writes(&[(KEY3, Value::Int32(3))]));
+    let reader = hist.checkout(post).unwrap().expect("Root should exist.");
+    let list = |prefix: &[u8]| {
+        reader
+            .keys_with_prefix(prefix)
+            .unwrap()
+            .expect("Every backend can list keys.")
+    };
+    let account_prefix = &KEY1.to_bytes().unwrap()[..1];
+    assert_eq!(
+        list(account_prefix),
+        vec![(KEY1, Value::Int32(1)), (KEY2, Value::Int32(2))]
+    );
+    assert_eq!(list(&KEY3.to_bytes().unwrap()), vec![(KEY3, Value::Int32(3))]);
+    assert_eq!(list(&[]).len(), 3);
+    assert!(list(&[255u8; 3]).is_empty());
+}
+
+#[test]
+fn in_mem_hist_keys_with_prefix_are_listed() {
+    let (hist, root_hash) = in_mem_hist();
+    keys_with_prefix_are_listed(hist, root_hash);
+}
+
+#[test]
+fn in_memory_keys_with_prefix_are_listed() {
+    let (hist, root_hash) = in_memory();
+    keys_with_prefix_are_listed(hist, root_hash);
+}
+
+#[test]
+fn lmdb_keys_with_prefix_are_listed() {
+    let (global_state, root_hash, _dir) = lmdb();
+    keys_with_prefix_are_listed(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_keys_with_prefix_are_listed() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    keys_with_prefix_are_listed(global_state, root_hash);
+}
+
+/// Reads `key` from the state at `hash` without decoding it where the
+/// backend can, decoding it afterwards.
+fn read_stored<H>(hist: &H, hash: Blake2bHash, key: Key) -> Option<Value>
+where
+    H: History,
+    H::Error: Debug,
+{
+    let reader = hist.checkout(hash).unwrap().expect("Root should exist.");
+    let stored = reader.read_stored(&key).unwrap();
+    stored.map(|stored| stored.into_value().unwrap())
+}
+
+fn values_read_stored_are_the_values_read<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let value = Value::String("stored".to_owned());
+    let post = commit(&mut hist, root_hash, writes(&[(KEY3, value.clone())]));
+    // States held by a checkpoint are read from memory.
+    let checkpoint = hist.checkpoint();
+    let held = commit_at(
+        &mut hist,
+        checkpoint,
+        post,
+        writes(&[(KEY1, Value::Int32(4))]),
+    );
+    for &hash in &[root_hash, post, held] {
+        for &key in &[KEY1, KEY2, KEY3] {
+            assert_eq!(read_stored(&hist, hash, key), read(&hist, hash, key));
+        }
+    }
+    assert_eq!(read_stored(&hist, post, KEY3), Some(value));
+    assert_eq!(read_stored(&hist, held, KEY1), Some(Value::Int32(4)));
+    assert_eq!(read_stored(&hist, root_hash, KEY3), None);
+}
+
+#[test]
+fn in_mem_hist_values_read_stored_are_the_values_read() {
+    let (hist, root_hash) = in_mem_hist();
+    values_read_stored_are_the_values_read(hist, root_hash);
+}
+
+#[test]
+fn in_memory_values_read_stored_are_the_values_read() {
+    let (hist, root_hash) = in_memory();
+    values_read_stored_are_the_values_read(hist, root_hash);
+}
+
+#[test]
+fn lmdb_values_read_stored_are_the_values_read() {
+    let (global_state, root_hash, _dir) = lmdb();
+    values_read_stored_are_the_values_read(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_values_read_stored_are_the_values_read() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    values_read_stored_are_the_values_read(global_state, root_hash);
+}
+
+/// Rewrites every trie as it is.
+struct Rewrite;
+
+impl Migration for Rewrite {
+    fn version(&self) -> u32 {
+        migration::FORMAT_VERSION + 1
+    }
+
+    fn description(&self) -> &str {
+        "Rewrites the tries"
+    }
+
+    fn migrate_trie(
+        &self,
+        _: &Blake2bHash,
+        stored: &[u8],
+    ) -> Result<Option<Vec<u8>>, error::Error> {
+        Ok(Some(stored.to_vec()))
+    }
+}
+
+fn rewriting_migrator() -> Migrator {
+    Migrator::with_migrations(vec![Box::new(Rewrite)], migration::FORMAT_VERSION + 1)
+}
+
+#[test]
+fn lmdb_migrations_keep_the_states() {
+    let (mut global_state, root_hash, _dir) = lmdb();
+    assert_eq!(global_state.format_version(), Ok(migration::FORMAT_VERSION));
+    let post = commit(&mut global_state, root_hash, writes(&[(KEY3, Value::Int32(3))]));
+
+    let report = global_state.migrate(&rewriting_migrator()).unwrap();
+    assert_eq!(report.tries_rewritten, report.tries_total);
+    assert_eq!(global_state.format_version(), Ok(migration::FORMAT_VERSION + 1));
+    assert_eq!(read(&global_state, root_hash, KEY1), Some(Value::Int32(1)));
+    assert_eq!(read(&global_state, post, KEY3), Some(Value::Int32(3)));
+}
+
+#[test]
+fn rocksdb_migrations_keep_the_states() {
+    let (mut global_state, root_hash, dir) = rocksdb();
+    assert_eq!(global_state.format_version(), Ok(migration::FORMAT_VERSION));
+    let post = commit(&mut global_state, root_hash, writes(&[(KEY3, Value::Int32(3))]));
+
+    let report = global_state.migrate(&rewriting_migrator()).unwrap();
+    assert_eq!(report.tries_rewritten, report.tries_total);
+    assert_eq!(global_state.format_version(), Ok(migration::FORMAT_VERSION + 1));
+    assert_eq!(read(&global_state, root_hash, KEY1), Some(Value::Int32(1)));
+    assert_eq!(read(&global_state, post, KEY3), Some(Value::Int32(3)));
+    // Reopened stores keep their version.
+    drop(global_state);
+    let global_state = RocksDbGlobalState::open(&dir.path().to_path_buf()).unwrap();
+    assert_eq!(global_state.format_version(), Ok(migration::FORMAT_VERSION + 1));
+}
+
+/// Commits a chain of states on one thread while other threads check out
+/// and read the states committed so far. Both keys have the same value in
+/// every state, so a reader seeing a commit half done would find them apart.
+fn reads_are_isolated_from_commits<H>(hist: H, root_hash: Blake2bHash)
+where
+    H: History + Send + Sync + 'static,
+    H::Error: Debug,
+{
+    const COMMITS: i32 = 50;
+    const READERS: usize = 4;
+    let same_values =
+        |value: i32| writes(&[(KEY1, Value::Int32(value)), (KEY2, Value::Int32(value))]);
+    let first = match hist.commit(root_hash, same_values(0)).unwrap() {
+        CommitResult::Success(first) => first,
+        _ => panic!("Test commit failed."),
+    };
+    let hist = Arc::new(hist);
+    let committed = Arc::new(Mutex::new(vec![(first, 0)]));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            let hist = Arc::clone(&hist);
+            let committed = Arc::clone(&committed);
+            let done = Arc::clone(&done);
+            thread::spawn(move || loop {
+                // The last round reads every state committed.
+                let last_round = done.load(Ordering::SeqCst);
+                let states = committed.lock().unwrap().clone();
+                for (state_hash, value) in states {
+                    let reader = hist
+                        .checkout(state_hash)
+                        .unwrap()
+                        .expect("Committed states should exist.");
+                    assert_eq!(reader.read(&KEY1).unwrap(), Some(Value::Int32(value)));
+                    assert_eq!(reader.read(&KEY2).unwrap(), Some(Value::Int32(value)));
+                }
+                if last_round {
+                    break;
+                }
+            })
+        })
+        .collect();
+
+    let mut state_hash = first;
+    for value in 1..=COMMITS {
+        state_hash = match hist.commit(state_hash, same_values(value)).unwrap() {
+            CommitResult::Success(state_hash) => state_hash,
+            _ => panic!("Test commit failed."),
+        };
+        committed.lock().unwrap().push((state_hash, value));
+    }
+    done.store(true, Ordering::SeqCst);
+    for reader in readers {
+        reader.join().expect("Readers should see whole states.");
+    }
+}
+
+#[test]
+fn in_mem_hist_reads_are_isolated_from_commits() {
+    let (hist, root_hash) = in_mem_hist();
+    reads_are_isolated_from_commits(hist, root_hash);
+}
+
+#[test]
+fn in_memory_reads_are_isolated_from_commits() {
+    let (hist, root_hash) = in_memory();
+    reads_are_isolated_from_commits(hist, root_hash);
+}
+
+#[test]
+fn lmdb_reads_are_isolated_from_commits() {
+    let (global_state, root_hash, _dir) = lmdb();
+    reads_are_isolated_from_commits(global_state, root_hash);
+}
+
+#[test]
+fn rocksdb_reads_are_isolated_from_commits() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    reads_are_isolated_from_commits(global_state, root_hash);
+}
+
+/// Commits a state and reads it back, returning what `counters`, which
+/// `hist` reports to, counted meanwhile.
+fn metered_commit_and_read<H>(
+    mut hist: H,
+    root_hash: Blake2bHash,
+    counters: &StoreCounters,
+) -> StoreCounts
+where
+    H: History,
+    H::Error: Debug,
+{
+    let state_hash = commit(&mut hist, root_hash, writes(&[(KEY1, Value::Int32(1))]));
+    assert_eq!(read(&hist, state_hash, KEY1), Some(Value::Int32(1)));
+    let counts = counters.counts();
+    let count = |operation: StoreOperation| {
+        counts
+            .operations
+            .get(&operation)
+            .map(|counts| counts.count)
+    };
+    assert_eq!(count(StoreOperation::Commit), Some(1));
+    assert_eq!(count(StoreOperation::Checkout), Some(1));
+    counts
+}
+
+/// Checks the tries counted by a global state keeping its states as tries.
+fn assert_tries_counted(counts: &StoreCounts) {
+    assert!(counts.tries_written > 0);
+    assert!(counts.trie_bytes_written >= counts.tries_written);
+    assert!(counts.tries_read > 0);
+    assert!(counts.trie_bytes_read >= counts.tries_read);
+    let reads = counts.operations.get(&StoreOperation::Read);
+    assert_eq!(reads.map(|counts| counts.count), Some(1));
+}
+
+#[test]
+fn in_mem_hist_reports_its_metrics() {
+    let counters = Arc::new(StoreCounters::new());
+    let (hist, root_hash) = in_mem_hist();
+    let hist = hist.with_metrics(counters.clone());
+    let counts = metered_commit_and_read(hist, root_hash, &counters);
+    // There are no tries, and the snapshots are read directly.
+    assert_eq!((counts.tries_read, counts.tries_written), (0, 0));
+}
+
+#[test]
+fn in_memory_reports_its_metrics() {
+    let counters = Arc::new(StoreCounters::new());
+    let (hist, root_hash) = in_memory();
+    let hist = hist.with_metrics(counters.clone());
+    assert_tries_counted(&metered_commit_and_read(hist, root_hash, &counters));
+}
+
+#[test]
+fn lmdb_reports_its_metrics() {
+    let counters = Arc::new(StoreCounters::new());
+    let (global_state, root_hash, _dir) = lmdb();
+    let global_state = global_state.with_metrics(counters.clone());
+    assert_tries_counted(&metered_commit_and_read(global_state, root_hash, &counters));
+}
+
+#[test]
+fn rocksdb_reports_its_metrics() {
+    let counters = Arc::new(StoreCounters::new());
+    let (global_state, root_hash, _dir) = rocksdb();
+    let global_state = global_state.with_metrics(counters.clone());
+    assert_tries_counted(&metered_commit_and_read(global_state, root_hash, &counters));
+}
+
+/// Commits with `hist` compressing the tries it writes, on top of a state
+/// whose tries were written raw.
+#[cfg(feature = "compression")]
+fn compression_keeps_the_root_hashes<H>(mut hist: H, root_hash: Blake2bHash)
+where
+    H: History,
+    H::Error: Debug,
+{
+    let (mut raw, raw_root_hash) = in_memory();
+    assert_eq!(root_hash, raw_root_hash);
+    let effects = writes(&[(KEY3, Value::String("three".to_owned()))]);
+    let state_hash = commit(&mut hist, root_hash, effects.clone());
+    assert_eq!(commit(&mut raw, raw_root_hash, effects), state_hash);
+    assert_eq!(read(&hist, state_hash, KEY1), Some(Value::Int32(1)));
+    assert_eq!(
+        read(&hist, state_hash, KEY3),
+        Some(Value::String("three".to_owned()))
+    );
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn lmdb_compression_keeps_the_root_hashes() {
+    let (global_state, root_hash, _dir) = lmdb();
+    let compression = Compression::zstd().with_threshold(0);
+    compression_keeps_the_root_hashes(global_state.with_compression(compression), root_hash);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn rocksdb_compression_keeps_the_root_hashes() {
+    let (global_state, root_hash, _dir) = rocksdb();
+    let compression = Compression::zstd().with_threshold(0);
+    compression_keeps_the_root_hashes(global_state.with_compression(compression), root_hash);
+}
+
+#[test]
+fn lmdb_states_outlive_the_environment() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_path_buf();
+    let root_hash = {
+        let environment = LmdbEnvironment::with_map_size(&path, 1 << 20).unwrap();
+        assert_eq!(environment.map_size(), 1 << 20);
+        let mut global_state = LmdbGlobalState::new(Arc::new(environment)).unwrap();
+        let empty_root_hash = global_state.empty_root_hash();
+        commit(&mut global_state, empty_root_hash, writes(&[(KEY3, Value::Int32(3))]))
+    };
+    let environment = LmdbEnvironment::with_map_size(&path, 1 << 20).unwrap();
+    let global_state = LmdbGlobalState::new(Arc::new(environment)).unwrap();
+    assert_eq!(read(&global_state, root_hash, KEY3), Some(Value::Int32(3)));
+}
+
+#[test]
+fn lmdb_pruned_states_stay_pruned_after_reopening() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_path_buf();
+    let (first, second) = {
+        let environment = LmdbEnvironment::new(&path).unwrap();
+        let mut global_state = LmdbGlobalState::new(Arc::new(environment)).unwrap();
+        let empty_root_hash = global_state.empty_root_hash();
+        let first = commit(&mut global_state, empty_root_hash, writes(&[(KEY3, Value::Int32(3))]));
+        let second = commit(&mut global_state, first, writes(&[(KEY1, Value::Int32(1))]));
+        global_state.prune(&[second], 2).unwrap();
+        (first, second)
+    };
+    let environment = LmdbEnvironment::new(&path).unwrap();
+    let global_state = LmdbGlobalState::new(Arc::new(environment)).unwrap();
+    assert!(global_state.checkout(first).unwrap().is_none());
+    assert_eq!(read(&global_state, second, KEY3), Some(Value::Int32(3)));
+    assert_eq!(read(&global_state, second, KEY1), Some(Value::Int32(1)));
+}
+
+#[test]
+fn in_memory_and_lmdb_states_have_the_same_root_hashes() {
+    let (_, in_memory_root_hash) = in_memory();
+    let (_, lmdb_root_hash, _dir) = lmdb();
+    assert_eq!(in_memory_root_hash, lmdb_root_hash);
+}
+
+#[test]
+fn rocksdb_states_outlive_the_database() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_path_buf();
+    let (first, second) = {
+        let mut global_state = RocksDbGlobalState::open(&path).unwrap();
+        let empty_root_hash = global_state.empty_root_hash();
+        let first = commit(&mut global_state, empty_root_hash, writes(&[(KEY3, Value::Int32(3))]));
+        let second = commit(&mut global_state, first, writes(&[(KEY1, Value::Int32(1))]));
+        global_state.prune(&[second], 2).unwrap();
+        (first, second)
+    };
+    let global_state = RocksDbGlobalState::open(&path).unwrap();
+    assert!(global_state.checkout(first).unwrap().is_none());
+    assert_eq!(read(&global_state, second, KEY3), Some(Value::Int32(3)));
+    assert_eq!(read(&global_state, second, KEY1), Some(Value::Int32(1)));
+}
+
+#[test]
+fn rocksdb_and_lmdb_states_have_the_same_root_hashes() {
+    let (_, rocksdb_root_hash, _rocksdb_dir) = rocksdb();
+    let (_, lmdb_root_hash, _lmdb_dir) = lmdb();
+    assert_eq!(rocksdb_root_hash, lmdb_root_hash);
+}
@@ -1,6 +1,7 @@
 //! Core types for a Merkle Trie
 
 use common::bytesrepr::{self, FromBytes, ToBytes};
+use common::trie_merkle_proof;
 use shared::newtypes::Blake2bHash;
 use std::mem::size_of;
 use std::ops::Deref;
@@ -23,7 +24,7 @@ pub enum Pointer {
 }
 
 impl Pointer {
-    fn hash(&self) -> &Blake2bHash {
+    pub fn hash(&self) -> &Blake2bHash {
         match self {
             Pointer::LeafPointer(hash) => hash,
             Pointer::NodePointer(hash) => hash,
@@ -38,6 +39,15 @@ impl Pointer {
     }
 }
 
+impl From<Pointer> for trie_merkle_proof::Pointer {
+    fn from(pointer: Pointer) -> Self {
+        match pointer {
+            Pointer::LeafPointer(hash) => trie_merkle_proof::Pointer::LeafPointer(hash.value()),
+            Pointer::NodePointer(hash) => trie_merkle_proof::Pointer::NodePointer(hash.value()),
+        }
+    }
+}
+
 impl ToBytes for Pointer {
     fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
         let mut hash_bytes = self.hash().to_bytes()?;
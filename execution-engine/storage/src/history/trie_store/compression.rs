@@ -0,0 +1,180 @@
+//! Compression of the tries kept by the persistent trie stores.
+//!
+//! Tries are stored one by one, each compressed on its own. A compressed
+//! trie is framed by a leading [`COMPRESSED`] byte, which no serialized trie
+//! starts with since their tags are small numbers, so raw tries are stored
+//! as they always were, and stores written before compression was configured
+//! can still be read. Tries smaller than the threshold, or which don't shrink,
+//! are stored raw. Sparse nodes, whose empty slots are runs of zeros, shrink
+//! the most; leaves are usually below the threshold.
+//!
+//! Compressing needs the `compression` feature, which builds zstd in. Stores
+//! built without it fail to read compressed tries.
+
+use std::borrow::Cow;
+
+#[cfg(feature = "compression")]
+use zstd;
+
+/// First byte of the compressed tries.
+pub const COMPRESSED: u8 = 0xff;
+
+/// Level tries are compressed at unless configured otherwise.
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Size in bytes below which tries are stored raw unless configured otherwise.
+pub const DEFAULT_THRESHOLD: usize = 128;
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum CompressionError {
+    #[fail(display = "Compressed trie can't be read without the compression feature")]
+    Unsupported,
+
+    /// The message of an error of zstd.
+    #[fail(display = "{}", _0)]
+    Zstd(String),
+}
+
+/// How a store compresses the tries it writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    level: i32,
+    threshold: usize,
+}
+
+impl Compression {
+    /// Compression with zstd at the default level and threshold.
+    #[cfg(feature = "compression")]
+    pub fn zstd() -> Compression {
+        Compression {
+            level: DEFAULT_LEVEL,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Compression at `level`, from 1 to 21, higher levels trading time for
+    /// smaller tries.
+    pub fn with_level(mut self, level: i32) -> Compression {
+        self.level = level;
+        self
+    }
+
+    /// Compression of the tries of at least `threshold` bytes only.
+    pub fn with_threshold(mut self, threshold: usize) -> Compression {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+}
+
+/// The bytes `trie_bytes` of a trie are stored as, compressed by
+/// `compression` if there's any and it makes them smaller.
+pub fn encode(
+    trie_bytes: Vec<u8>,
+    compression: Option<&Compression>,
+) -> Result<Vec<u8>, CompressionError> {
+    let compression = match compression {
+        Some(compression) if trie_bytes.len() >= compression.threshold => compression,
+        _ => return Ok(trie_bytes),
+    };
+    let compressed = compress(&trie_bytes, compression.level)?;
+    if compressed.len() + 1 >= trie_bytes.len() {
+        return Ok(trie_bytes);
+    }
+    let mut stored = Vec::with_capacity(compressed.len() + 1);
+    stored.push(COMPRESSED);
+    stored.extend(compressed);
+    Ok(stored)
+}
+
+/// The bytes of the trie stored as `stored`.
+pub fn decode(stored: &[u8]) -> Result<Cow<[u8]>, CompressionError> {
+    match stored.split_first() {
+        Some((&COMPRESSED, compressed)) => Ok(Cow::Owned(decompress(compressed)?)),
+        _ => Ok(Cow::Borrowed(stored)),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn compress(bytes: &[u8], level: i32) -> Result<Vec<u8>, CompressionError> {
+    zstd::encode_all(bytes, level).map_err(|error| CompressionError::Zstd(error.to_string()))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    zstd::decode_all(bytes).map_err(|error| CompressionError::Zstd(error.to_string()))
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_bytes: &[u8], _level: i32) -> Result<Vec<u8>, CompressionError> {
+    // There's no way to configure compression without the feature.
+    unreachable!()
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_bytes: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    Err(CompressionError::Unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::bytesrepr::ToBytes;
+    use history::trie::{Pointer, PointerBlock, Trie};
+    use shared::newtypes::Blake2bHash;
+
+    use super::{decode, encode, COMPRESSED};
+
+    fn sparse_node() -> Vec<u8> {
+        let mut pointer_block = PointerBlock::new();
+        pointer_block[3] = Some(Pointer::LeafPointer(Blake2bHash::new(b"leaf")));
+        pointer_block[200] = Some(Pointer::NodePointer(Blake2bHash::new(b"node")));
+        let node: Trie<Vec<u8>, Vec<u8>> = Trie::Node {
+            pointer_block: Box::new(pointer_block),
+        };
+        node.to_bytes().unwrap()
+    }
+
+    #[test]
+    fn tries_are_stored_raw_without_compression() {
+        let trie_bytes = sparse_node();
+        assert_eq!(encode(trie_bytes.clone(), None).unwrap(), trie_bytes);
+        assert_eq!(decode(&trie_bytes).unwrap(), &trie_bytes[..]);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn sparse_nodes_shrink() {
+        use super::Compression;
+
+        let trie_bytes = sparse_node();
+        let compression = Compression::zstd();
+        let stored = encode(trie_bytes.clone(), Some(&compression)).unwrap();
+        assert_eq!(stored[0], COMPRESSED);
+        assert!(stored.len() * 4 < trie_bytes.len());
+        assert_eq!(decode(&stored).unwrap(), &trie_bytes[..]);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn tries_below_the_threshold_are_stored_raw() {
+        use super::Compression;
+
+        let trie_bytes = sparse_node();
+        let compression = Compression::zstd().with_threshold(trie_bytes.len() + 1);
+        assert_eq!(encode(trie_bytes.clone(), Some(&compression)).unwrap(), trie_bytes);
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn compressed_tries_are_refused_without_the_feature() {
+        let stored = vec![COMPRESSED, 1, 2, 3];
+        assert!(decode(&stored).is_err());
+    }
+}
@@ -17,8 +17,8 @@
 //! let leaf_2 = Trie::Leaf { key: vec![1u8, 0, 0], value: b"val_2".to_vec() };
 //!
 //! // Get their hashes
-//! let leaf_1_hash = Blake2bHash::new(&leaf_1.to_bytes());
-//! let leaf_2_hash = Blake2bHash::new(&leaf_2.to_bytes());
+//! let leaf_1_hash = Blake2bHash::new(&leaf_1.to_bytes().unwrap());
+//! let leaf_2_hash = Blake2bHash::new(&leaf_2.to_bytes().unwrap());
 //!
 //! // Create a node
 //! let node: Trie<Vec<u8>, Vec<u8>> = {
@@ -30,7 +30,7 @@
 //! };
 //!
 //! // Get its hash
-//! let node_hash = Blake2bHash::new(&node.to_bytes());
+//! let node_hash = Blake2bHash::new(&node.to_bytes().unwrap());
 //!
 //! // Create the environment and the store. For both the in-memory and
 //! // LMDB-backed implementations, the environment is the source of
@@ -100,6 +100,7 @@
 
 use super::*;
 use common::bytesrepr::{self, deserialize, FromBytes, ToBytes};
+use metrics::{self, StoreMetrics};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, MutexGuard};
 
@@ -132,17 +133,17 @@ impl<T> From<std::sync::PoisonError<T>> for Error {
     }
 }
 
-/// A read transaction for the in-memory trie store.
+/// A read transaction for the in-memory trie store. It shares the data
+/// committed before it began instead of copying it.
 pub struct InMemoryReadTransaction {
-    view: BytesMap,
+    view: Arc<BytesMap>,
 }
 
 impl InMemoryReadTransaction {
     pub fn new(store: &InMemoryEnvironment) -> Result<InMemoryReadTransaction, Error> {
         let view = {
-            let arc = store.data.clone();
-            let lock = arc.lock()?;
-            lock.to_owned()
+            let lock = store.data.lock()?;
+            Arc::clone(&lock)
         };
         Ok(InMemoryReadTransaction { view })
     }
@@ -162,12 +163,16 @@ impl Readable for InMemoryReadTransaction {
     fn read(&self, _handle: Self::Handle, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
         Ok(self.view.get(&key.to_vec()).map(ToOwned::to_owned))
     }
+
+    fn keys(&self, _handle: Self::Handle) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(self.view.keys().cloned().collect())
+    }
 }
 
 /// A read-write transaction for the in-memory trie store.
 pub struct InMemoryReadWriteTransaction<'a> {
     view: BytesMap,
-    store_ref: Arc<Mutex<BytesMap>>,
+    store_ref: Arc<Mutex<Arc<BytesMap>>>,
     _write_lock: WriteLock<'a>,
 }
 
@@ -177,7 +182,7 @@ impl<'a> InMemoryReadWriteTransaction<'a> {
         let store_ref = store.data.clone();
         let view = {
             let view_lock = store_ref.lock()?;
-            view_lock.to_owned()
+            BytesMap::clone(&view_lock)
         };
         Ok(InMemoryReadWriteTransaction {
             _write_lock,
@@ -193,8 +198,10 @@ impl<'a> Transaction for InMemoryReadWriteTransaction<'a> {
     type Handle = ();
 
     fn commit(self) -> Result<(), Self::Error> {
+        // Nothing else was committed since the view was copied, as the
+        // transaction holds the write lock.
         let mut store_ref_lock = self.store_ref.lock()?;
-        store_ref_lock.extend(self.view);
+        *store_ref_lock = Arc::new(self.view);
         Ok(())
     }
 }
@@ -203,6 +210,10 @@ impl<'a> Readable for InMemoryReadWriteTransaction<'a> {
     fn read(&self, _handle: Self::Handle, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
         Ok(self.view.get(&key.to_vec()).map(ToOwned::to_owned))
     }
+
+    fn keys(&self, _handle: Self::Handle) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(self.view.keys().cloned().collect())
+    }
 }
 
 impl<'a> Writable for InMemoryReadWriteTransaction<'a> {
@@ -215,17 +226,22 @@ impl<'a> Writable for InMemoryReadWriteTransaction<'a> {
         self.view.insert(key.to_vec(), value.to_vec());
         Ok(())
     }
+
+    fn delete(&mut self, _handle: Self::Handle, key: &[u8]) -> Result<(), Self::Error> {
+        self.view.remove(key);
+        Ok(())
+    }
 }
 
 /// An environment for the in-memory trie store.
 pub struct InMemoryEnvironment {
-    data: Arc<Mutex<BytesMap>>,
+    data: Arc<Mutex<Arc<BytesMap>>>,
     write_mutex: Arc<Mutex<WriteCapability>>,
 }
 
 impl Default for InMemoryEnvironment {
     fn default() -> Self {
-        let data = Arc::new(Mutex::new(HashMap::new()));
+        let data = Arc::new(Mutex::new(Arc::new(HashMap::new())));
         let write_mutex = Arc::new(Mutex::new(WriteCapability));
         InMemoryEnvironment { data, write_mutex }
     }
@@ -256,11 +272,25 @@ impl<'a> TransactionSource<'a> for InMemoryEnvironment {
 }
 
 /// An in-memory trie store.
-pub struct InMemoryTrieStore;
+#[derive(Debug, Clone)]
+pub struct InMemoryTrieStore {
+    metrics: Arc<dyn StoreMetrics>,
+}
 
 impl InMemoryTrieStore {
     pub fn new(_env: &InMemoryEnvironment) -> Self {
-        InMemoryTrieStore
+        InMemoryTrieStore {
+            metrics: metrics::no_metrics(),
+        }
+    }
+
+    /// The store, reporting the tries it reads and writes to `metrics`.
+    pub fn with_metrics(self, metrics: Arc<dyn StoreMetrics>) -> Self {
+        InMemoryTrieStore { metrics, ..self }
+    }
+
+    pub fn metrics(&self) -> &Arc<dyn StoreMetrics> {
+        &self.metrics
     }
 }
 
@@ -277,6 +307,7 @@ impl<K: ToBytes + FromBytes, V: ToBytes + FromBytes> TrieStore<K, V> for InMemor
         match txn.read((), &key.to_bytes()?)? {
             None => Ok(None),
             Some(bytes) => {
+                self.metrics.trie_read(bytes.len());
                 let trie = deserialize(&bytes)?;
                 Ok(Some(trie))
             }
@@ -288,7 +319,28 @@ impl<K: ToBytes + FromBytes, V: ToBytes + FromBytes> TrieStore<K, V> for InMemor
         T: Writable<Handle = Self::Handle>,
         Self::Error: From<T::Error>,
     {
-        txn.write((), &key.to_bytes()?, &value.to_bytes()?)
-            .map_err(Into::into)
+        let bytes = value.to_bytes()?;
+        self.metrics.trie_written(bytes.len());
+        txn.write((), &key.to_bytes()?, &bytes).map_err(Into::into)
+    }
+
+    fn hashes<T>(&self, txn: &T) -> Result<Vec<Blake2bHash>, Self::Error>
+    where
+        T: Readable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        let mut hashes = Vec::new();
+        for bytes in txn.keys(())? {
+            hashes.push(deserialize(&bytes)?);
+        }
+        Ok(hashes)
+    }
+
+    fn delete<T>(&self, txn: &mut T, key: &Blake2bHash) -> Result<(), Self::Error>
+    where
+        T: Writable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        txn.delete((), &key.to_bytes()?).map_err(Into::into)
     }
 }
@@ -0,0 +1,174 @@
+//! Detection of the records of the persistent stores corrupted on disk.
+//!
+//! Tries are stored at the hash of their bytes, so a trie read is checked by
+//! hashing it again. The other records, like the roots of the states and the
+//! format version of a store, are stored with a checksum of their key and
+//! value. Either way, a mismatch is reported as
+//! [`CorruptionDetected`](error::Error::CorruptionDetected) rather than as
+//! whatever decoding the bytes would have failed with, and counted in the
+//! metrics of the store.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use common::bytesrepr::ToBytes;
+use error;
+use history::trie_store::compression::{self, CompressionError};
+use history::trie_store::{Readable, Writable};
+use metrics::StoreMetrics;
+use shared::newtypes::Blake2bHash;
+
+/// Length in bytes of the checksums of the records.
+pub const CHECKSUM_LENGTH: usize = 8;
+
+fn corrupted(key: &[u8], metrics: &Arc<dyn StoreMetrics>) -> error::Error {
+    metrics.corruption_detected();
+    error::Error::CorruptionDetected { key: key.to_vec() }
+}
+
+/// The bytes of the trie stored as `stored` at `hash`, once they're checked
+/// against it. Compressed tries which don't decompress are corrupted too.
+pub fn open_trie<'a>(
+    hash: &Blake2bHash,
+    stored: &'a [u8],
+    metrics: &Arc<dyn StoreMetrics>,
+) -> Result<Cow<'a, [u8]>, error::Error> {
+    let trie_bytes = match compression::decode(stored) {
+        Ok(trie_bytes) => trie_bytes,
+        Err(CompressionError::Zstd(_)) => return Err(corrupted(&hash.to_vec(), metrics)),
+        Err(error) => return Err(error.into()),
+    };
+    if Blake2bHash::new(&trie_bytes) != *hash {
+        return Err(corrupted(&hash.to_vec(), metrics));
+    }
+    Ok(trie_bytes)
+}
+
+/// Checksum of `value` stored under `key`, so that a value moved to another
+/// key doesn't match either.
+fn checksum(key: &[u8], value: &[u8]) -> Result<Vec<u8>, error::Error> {
+    let mut bytes = key.to_vec().to_bytes()?;
+    bytes.extend_from_slice(value);
+    Ok(Blake2bHash::new(&bytes).value()[..CHECKSUM_LENGTH].to_vec())
+}
+
+/// The bytes `value` is stored as under `key`: the value followed by its
+/// checksum.
+pub fn seal(key: &[u8], value: &[u8]) -> Result<Vec<u8>, error::Error> {
+    let mut stored = value.to_vec();
+    stored.extend(checksum(key, value)?);
+    Ok(stored)
+}
+
+/// The value stored as `stored` under `key`, once it's checked against its
+/// checksum. Records written before they had checksums are empty, and are
+/// taken as they are.
+pub fn open<'a>(
+    key: &[u8],
+    stored: &'a [u8],
+    metrics: &Arc<dyn StoreMetrics>,
+) -> Result<&'a [u8], error::Error> {
+    if stored.is_empty() {
+        return Ok(stored);
+    }
+    if stored.len() < CHECKSUM_LENGTH {
+        return Err(corrupted(key, metrics));
+    }
+    let (value, stored_checksum) = stored.split_at(stored.len() - CHECKSUM_LENGTH);
+    if checksum(key, value)? != stored_checksum {
+        return Err(corrupted(key, metrics));
+    }
+    Ok(value)
+}
+
+/// Reads the record under `key` in `handle`, checking it.
+pub fn read_record<T>(
+    txn: &T,
+    handle: T::Handle,
+    key: &[u8],
+    metrics: &Arc<dyn StoreMetrics>,
+) -> Result<Option<Vec<u8>>, error::Error>
+where
+    T: Readable,
+    error::Error: From<T::Error>,
+{
+    match txn.read(handle, key)? {
+        Some(stored) => Ok(Some(open(key, &stored, metrics)?.to_vec())),
+        None => Ok(None),
+    }
+}
+
+/// Writes the record `value` under `key` in `handle`, with its checksum.
+pub fn write_record<T>(
+    txn: &mut T,
+    handle: T::Handle,
+    key: &[u8],
+    value: &[u8],
+) -> Result<(), error::Error>
+where
+    T: Writable,
+    error::Error: From<T::Error>,
+{
+    txn.write(handle, key, &seal(key, value)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use common::bytesrepr::ToBytes;
+    use error;
+    use history::trie::Trie;
+    use metrics::{StoreCounters, StoreMetrics};
+    use shared::newtypes::Blake2bHash;
+
+    use super::{open, open_trie, seal};
+
+    fn counters() -> (Arc<StoreCounters>, Arc<dyn StoreMetrics>) {
+        let counters = Arc::new(StoreCounters::new());
+        let metrics: Arc<dyn StoreMetrics> = counters.clone();
+        (counters, metrics)
+    }
+
+    #[test]
+    fn flipped_bits_of_tries_are_detected() {
+        let (counters, metrics) = counters();
+        let leaf: Trie<Vec<u8>, Vec<u8>> = Trie::Leaf {
+            key: vec![1u8, 2, 3],
+            value: b"value".to_vec(),
+        };
+        let trie_bytes = leaf.to_bytes().unwrap();
+        let hash = Blake2bHash::new(&trie_bytes);
+        assert_eq!(open_trie(&hash, &trie_bytes, &metrics).unwrap(), &trie_bytes[..]);
+
+        let mut flipped = trie_bytes.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 1;
+        match open_trie(&hash, &flipped, &metrics) {
+            Err(error::Error::CorruptionDetected { key }) => assert_eq!(key, hash.to_vec()),
+            other => panic!("Expected a corrupted trie, got {:?}", other),
+        }
+        assert_eq!(counters.counts().corruptions_detected, 1);
+    }
+
+    #[test]
+    fn flipped_bits_of_records_are_detected() {
+        let (counters, metrics) = counters();
+        let stored = seal(b"key", b"value").unwrap();
+        assert_eq!(open(b"key", &stored, &metrics), Ok(&b"value"[..]));
+        // The same value under another key doesn't match its checksum.
+        assert!(open(b"other key", &stored, &metrics).is_err());
+        for index in 0..stored.len() {
+            let mut flipped = stored.clone();
+            flipped[index] ^= 0x80;
+            match open(b"key", &flipped, &metrics) {
+                Err(error::Error::CorruptionDetected { key }) => assert_eq!(key, b"key"),
+                other => panic!("Expected a corrupted record, got {:?}", other),
+            }
+        }
+        assert_eq!(counters.counts().corruptions_detected, stored.len() as u64 + 1);
+        // Records from before the checksums are empty.
+        assert_eq!(open(b"key", &[], &metrics), Ok(&[][..]));
+    }
+}
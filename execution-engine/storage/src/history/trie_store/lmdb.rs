@@ -108,8 +108,14 @@
 use super::*;
 use common::bytesrepr::{deserialize, FromBytes, ToBytes};
 use error;
-use lmdb::{self, Database, DatabaseFlags, Environment, RoTransaction, RwTransaction, WriteFlags};
+use history::trie_store::compression::{self, Compression};
+use history::trie_store::integrity;
+use lmdb::{
+    self, Cursor, Database, DatabaseFlags, Environment, RoTransaction, RwTransaction, WriteFlags,
+};
+use metrics::{self, StoreMetrics};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 impl<'a> Transaction for RoTransaction<'a> {
     type Error = lmdb::Error;
@@ -129,6 +135,12 @@ impl<'a> Readable for RoTransaction<'a> {
             Err(e) => Err(e),
         }
     }
+
+    fn keys(&self, handle: Self::Handle) -> Result<Vec<Vec<u8>>, Self::Error> {
+        let mut cursor = lmdb::Transaction::open_ro_cursor(self, handle)?;
+        let keys = cursor.iter_start().map(|(key, _)| key.to_vec()).collect();
+        Ok(keys)
+    }
 }
 
 impl<'a> Transaction for RwTransaction<'a> {
@@ -149,6 +161,12 @@ impl<'a> Readable for RwTransaction<'a> {
             Err(e) => Err(e),
         }
     }
+
+    fn keys(&self, handle: Self::Handle) -> Result<Vec<Vec<u8>>, Self::Error> {
+        let mut cursor = lmdb::Transaction::open_ro_cursor(self, handle)?;
+        let keys = cursor.iter_start().map(|(key, _)| key.to_vec()).collect();
+        Ok(keys)
+    }
 }
 
 impl<'a> Writable for RwTransaction<'a> {
@@ -156,27 +174,63 @@ impl<'a> Writable for RwTransaction<'a> {
         self.put(handle, &key, &value, WriteFlags::empty())
             .map_err(Into::into)
     }
+
+    fn delete(&mut self, handle: Self::Handle, key: &[u8]) -> Result<(), Self::Error> {
+        match self.del(handle, &key, None) {
+            Ok(()) | Err(lmdb::Error::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
 }
 
+/// Size the memory map of an environment can grow to, in bytes, unless set
+/// otherwise. It bounds the size of the databases in the environment.
+pub const DEFAULT_MAP_SIZE: usize = 1 << 30;
+
+/// Number of named databases an environment can have.
+pub const MAX_DBS: u32 = 4;
+
 /// The environment for an LMDB-backed trie store.
 ///
 /// Wraps [`lmdb::Environment`].
 #[derive(Debug)]
 pub struct LmdbEnvironment {
     path: PathBuf,
+    map_size: usize,
     env: Environment,
 }
 
 impl LmdbEnvironment {
     pub fn new(path: &PathBuf) -> Result<Self, error::Error> {
-        let env = Environment::new().open(path)?;
+        LmdbEnvironment::with_map_size(path, DEFAULT_MAP_SIZE)
+    }
+
+    /// Opens the environment in the directory at `path`, whose databases can
+    /// take up `map_size` bytes.
+    pub fn with_map_size(path: &PathBuf, map_size: usize) -> Result<Self, error::Error> {
+        let env = Environment::new()
+            .set_map_size(map_size)
+            .set_max_dbs(MAX_DBS)
+            .open(path)?;
         let path = path.to_owned();
-        Ok(LmdbEnvironment { path, env })
+        Ok(LmdbEnvironment {
+            path,
+            map_size,
+            env,
+        })
     }
 
     pub fn path(&self) -> &PathBuf {
         &self.path
     }
+
+    pub fn map_size(&self) -> usize {
+        self.map_size
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
 }
 
 impl<'a> TransactionSource<'a> for LmdbEnvironment {
@@ -203,6 +257,8 @@ impl<'a> TransactionSource<'a> for LmdbEnvironment {
 #[derive(Debug, Clone)]
 pub struct LmdbTrieStore {
     db: Database,
+    metrics: Arc<dyn StoreMetrics>,
+    compression: Option<Compression>,
 }
 
 impl LmdbTrieStore {
@@ -212,12 +268,42 @@ impl LmdbTrieStore {
         flags: DatabaseFlags,
     ) -> Result<Self, error::Error> {
         let db = env.env.create_db(name, flags)?;
-        Ok(LmdbTrieStore { db })
+        Ok(LmdbTrieStore {
+            db,
+            metrics: metrics::no_metrics(),
+            compression: None,
+        })
     }
 
     pub fn open(env: &LmdbEnvironment, name: Option<&str>) -> Result<Self, error::Error> {
         let db = env.env.open_db(name)?;
-        Ok(LmdbTrieStore { db })
+        Ok(LmdbTrieStore {
+            db,
+            metrics: metrics::no_metrics(),
+            compression: None,
+        })
+    }
+
+    /// The database the tries are kept in.
+    pub fn db(&self) -> Database {
+        self.db
+    }
+
+    /// The store, compressing the tries it writes with `compression`.
+    pub fn with_compression(self, compression: Compression) -> Self {
+        LmdbTrieStore {
+            compression: Some(compression),
+            ..self
+        }
+    }
+
+    /// The store, reporting the tries it reads and writes to `metrics`.
+    pub fn with_metrics(self, metrics: Arc<dyn StoreMetrics>) -> Self {
+        LmdbTrieStore { metrics, ..self }
+    }
+
+    pub fn metrics(&self) -> &Arc<dyn StoreMetrics> {
+        &self.metrics
     }
 }
 
@@ -238,7 +324,8 @@ impl<K: ToBytes + FromBytes, V: ToBytes + FromBytes> TrieStore<K, V> for LmdbTri
         match txn.read(self.db, &key.to_bytes()?)? {
             None => Ok(None),
             Some(bytes) => {
-                let trie = deserialize(&bytes)?;
+                self.metrics.trie_read(bytes.len());
+                let trie = deserialize(&integrity::open_trie(key, &bytes, &self.metrics)?)?;
                 Ok(Some(trie))
             }
         }
@@ -254,7 +341,29 @@ impl<K: ToBytes + FromBytes, V: ToBytes + FromBytes> TrieStore<K, V> for LmdbTri
         T: Writable<Handle = Self::Handle>,
         Self::Error: From<T::Error>,
     {
-        txn.write(self.db, &key.to_bytes()?, &value.to_bytes()?)
+        let bytes = compression::encode(value.to_bytes()?, self.compression.as_ref())?;
+        self.metrics.trie_written(bytes.len());
+        txn.write(self.db, &key.to_bytes()?, &bytes)
             .map_err(Into::into)
     }
+
+    fn hashes<T: Readable>(&self, txn: &T) -> Result<Vec<Blake2bHash>, Self::Error>
+    where
+        T: Readable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        let mut hashes = Vec::new();
+        for bytes in txn.keys(self.db)? {
+            hashes.push(deserialize(&bytes)?);
+        }
+        Ok(hashes)
+    }
+
+    fn delete<T: Writable>(&self, txn: &mut T, key: &Blake2bHash) -> Result<(), Self::Error>
+    where
+        T: Writable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        txn.delete(self.db, &key.to_bytes()?).map_err(Into::into)
+    }
 }
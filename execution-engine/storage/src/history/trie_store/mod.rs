@@ -1,15 +1,19 @@
 //! A store for persisting [`Trie`](crate::history::trie::Trie) values at their hashes.
 //!
 //! See the [in_memory](in_memory/index.html#usage) and
-//! [lmdb](lmdb/index.html#usage) modules for usage examples.
+//! [lmdb](lmdb/index.html#usage) modules for usage examples. The
+//! [rocksdb](rocksdb/index.html) module is used like the lmdb one.
 
 use history::trie::Trie;
 use shared::newtypes::Blake2bHash;
 
+pub mod compression;
+pub mod in_memory;
+pub mod integrity;
 pub mod lmdb;
-
-#[cfg(test)]
-mod in_memory;
+pub mod operations;
+pub mod overlay;
+pub mod rocksdb;
 
 #[cfg(test)]
 mod tests;
@@ -38,12 +42,19 @@ pub trait Transaction: Sized {
 pub trait Readable: Transaction {
     /// Returns the value from the corresponding key from a given [`Transaction::Handle`].
     fn read(&self, handle: Self::Handle, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Returns all the keys in a given [`Transaction::Handle`].
+    fn keys(&self, handle: Self::Handle) -> Result<Vec<Vec<u8>>, Self::Error>;
 }
 
 /// A transaction with the capability to write to a given [`Handle`](Transaction::Handle).
 pub trait Writable: Transaction {
     /// Inserts a key-value pair into a given [`Transaction::Handle`].
     fn write(&mut self, handle: Self::Handle, key: &[u8], value: &[u8]) -> Result<(), Self::Error>;
+
+    /// Removes a key and its value from a given [`Transaction::Handle`], if
+    /// the key is there.
+    fn delete(&mut self, handle: Self::Handle, key: &[u8]) -> Result<(), Self::Error>;
 }
 
 /// A source of transactions e.g. values that implement [`Readable`]
@@ -90,4 +101,16 @@ pub trait TrieStore<K, V> {
     where
         T: Writable<Handle = Self::Handle>,
         Self::Error: From<T::Error>;
+
+    /// Returns the hashes of all the [`Trie`] values.
+    fn hashes<T>(&self, txn: &T) -> Result<Vec<Blake2bHash>, Self::Error>
+    where
+        T: Readable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>;
+
+    /// Removes the [`Trie`] value at a given hash, if there is one.
+    fn delete<T>(&self, txn: &mut T, key: &Blake2bHash) -> Result<(), Self::Error>
+    where
+        T: Writable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>;
 }
@@ -0,0 +1,941 @@
+//! Reading and writing the values of a [`Trie`](crate::history::trie::Trie)
+//! kept in a [`TrieStore`].
+//!
+//! Values are found by the bytes of their keys, one byte per level of
+//! [`Node`](Trie::Node)s, with [`Extension`](Trie::Extension)s skipping the
+//! bytes the keys under them share. The bytes of keys are assumed to be
+//! prefix-free, which `bytesrepr` encodings are. Writes never change the
+//! tries in the store, they put the tries on the path to the written leaf
+//! anew, so every root hash keeps referring to the same values.
+
+use std::cmp;
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use common::bytesrepr::{self, FromBytes, ToBytes};
+use common::trie_merkle_proof::{TrieMerkleProof, TrieMerkleProofStep};
+use history::trie::{Pointer, PointerBlock, Trie};
+use history::trie_store::{Readable, TrieStore, Writable};
+use shared::newtypes::Blake2bHash;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadResult<V> {
+    Found(V),
+    NotFound,
+    /// The root, or a trie under it, isn't in the store.
+    RootNotFound,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WriteResult {
+    /// Hash of the root of the trie with the written value.
+    Written(Blake2bHash),
+    /// The root, or a trie under it, isn't in the store.
+    RootNotFound,
+}
+
+/// The trie with no values, whose hash is the root hash of the empty state.
+pub fn empty_trie<K, V>() -> Trie<K, V> {
+    Trie::Node {
+        pointer_block: Box::new(PointerBlock::new()),
+    }
+}
+
+/// Puts `trie` in the store at its hash, which is returned.
+pub fn put_trie<K, V, T, S>(
+    txn: &mut T,
+    store: &S,
+    trie: &Trie<K, V>,
+) -> Result<Blake2bHash, S::Error>
+where
+    K: ToBytes,
+    V: ToBytes,
+    T: Writable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    let hash = Blake2bHash::new(&trie.to_bytes()?);
+    store.put(txn, &hash, trie)?;
+    Ok(hash)
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// The tries on the path of a key from a root.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TrieScan<K, V> {
+    /// The trie the path ends at: a leaf, which may be of another key, or a
+    /// node or an extension the rest of the path doesn't lead through.
+    pub tip: Trie<K, V>,
+    /// The tries leading to the tip, starting at the root, with the number of
+    /// bytes of the path their parents consumed.
+    pub parents: Vec<(usize, Trie<K, V>)>,
+}
+
+/// Follows the path of `key` from `root` as far as it goes. Returns `None` if
+/// one of the tries on the path isn't in the store.
+pub fn scan<K, V, T, S>(
+    txn: &T,
+    store: &S,
+    root: &Blake2bHash,
+    key: &K,
+) -> Result<Option<TrieScan<K, V>>, S::Error>
+where
+    K: ToBytes + FromBytes,
+    V: ToBytes + FromBytes,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    let path = key.to_bytes()?;
+    let mut parents = Vec::new();
+    let mut depth = 0;
+    let mut hash = *root;
+    loop {
+        let trie = match store.get(txn, &hash)? {
+            Some(trie) => trie,
+            None => return Ok(None),
+        };
+        let next = match &trie {
+            Trie::Leaf { .. } => None,
+            Trie::Node { pointer_block } => path
+                .get(depth)
+                .and_then(|index| pointer_block[*index as usize])
+                .map(|pointer| (pointer, 1)),
+            Trie::Extension { affix, pointer } if path[depth..].starts_with(affix) => {
+                Some((*pointer, affix.len()))
+            }
+            Trie::Extension { .. } => None,
+        };
+        match next {
+            Some((pointer, consumed)) => {
+                parents.push((depth, trie));
+                hash = *pointer.hash();
+                depth += consumed;
+            }
+            None => return Ok(Some(TrieScan { tip: trie, parents })),
+        }
+    }
+}
+
+pub fn read<K, V, T, S>(
+    txn: &T,
+    store: &S,
+    root: &Blake2bHash,
+    key: &K,
+) -> Result<ReadResult<V>, S::Error>
+where
+    K: ToBytes + FromBytes,
+    V: ToBytes + FromBytes,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    match scan(txn, store, root, key)? {
+        Some(TrieScan {
+            tip: Trie::Leaf {
+                key: leaf_key,
+                value,
+            },
+            ..
+        }) => {
+            if leaf_key.to_bytes()? == key.to_bytes()? {
+                Ok(ReadResult::Found(value))
+            } else {
+                Ok(ReadResult::NotFound)
+            }
+        }
+        Some(_) => Ok(ReadResult::NotFound),
+        None => Ok(ReadResult::RootNotFound),
+    }
+}
+
+/// Proves the value under `key` in the trie at `root` with the tries on the
+/// path to its leaf.
+pub fn prove<K, V, T, S>(
+    txn: &T,
+    store: &S,
+    root: &Blake2bHash,
+    key: &K,
+) -> Result<ReadResult<TrieMerkleProof<K, V>>, S::Error>
+where
+    K: ToBytes + FromBytes,
+    V: ToBytes + FromBytes,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    let path = key.to_bytes()?;
+    let TrieScan { tip, parents } = match scan(txn, store, root, key)? {
+        Some(trie_scan) => trie_scan,
+        None => return Ok(ReadResult::RootNotFound),
+    };
+    let (leaf_key, value) = match tip {
+        Trie::Leaf { key, value } => (key, value),
+        _ => return Ok(ReadResult::NotFound),
+    };
+    if leaf_key.to_bytes()? != path {
+        return Ok(ReadResult::NotFound);
+    }
+    let proof_steps = parents
+        .into_iter()
+        .rev()
+        .map(|(depth, trie)| match trie {
+            Trie::Node { pointer_block } => {
+                let hole_index = path[depth];
+                let indexed_pointers_with_hole = (0..=255u8)
+                    .filter(|index| *index != hole_index)
+                    .filter_map(|index| {
+                        pointer_block[index as usize].map(|pointer| (index, pointer.into()))
+                    })
+                    .collect();
+                TrieMerkleProofStep::Node {
+                    hole_index,
+                    indexed_pointers_with_hole,
+                }
+            }
+            Trie::Extension { affix, .. } => TrieMerkleProofStep::Extension { affix },
+            // Scans never lead through leaves.
+            Trie::Leaf { .. } => unreachable!(),
+        })
+        .collect();
+    let proof = TrieMerkleProof::new(leaf_key, value, proof_steps);
+    Ok(ReadResult::Found(proof))
+}
+
+/// Hashes of the tries reachable from `roots`, leaves included. Returns `None`
+/// if one of the nodes or extensions isn't in the store.
+pub fn reachable<K, V, T, S>(
+    txn: &T,
+    store: &S,
+    roots: &[Blake2bHash],
+) -> Result<Option<HashSet<Blake2bHash>>, S::Error>
+where
+    K: ToBytes + FromBytes,
+    V: ToBytes + FromBytes,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error>,
+{
+    let mut reachable = HashSet::new();
+    let mut pending: Vec<Pointer> = roots
+        .iter()
+        .map(|root| Pointer::NodePointer(*root))
+        .collect();
+    while let Some(pointer) = pending.pop() {
+        if !reachable.insert(*pointer.hash()) {
+            continue;
+        }
+        // Leaves don't point anywhere, so they aren't read.
+        if let Pointer::LeafPointer(_) = pointer {
+            continue;
+        }
+        match store.get(txn, pointer.hash())? {
+            Some(Trie::Leaf { .. }) => (),
+            Some(Trie::Node { pointer_block }) => {
+                pending.extend((0..256).filter_map(|index| pointer_block[index]))
+            }
+            Some(Trie::Extension { pointer, .. }) => pending.push(pointer),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(reachable))
+}
+
+/// A value changed from one state to another: its key, with its value in
+/// the first state and in the second one, if it has any there.
+pub type Change<K, V> = (K, Option<V>, Option<V>);
+
+/// What is left to compare of a trie once its parents consumed some bytes of
+/// the paths: a trie of the store, or the rest of an extension or a leaf.
+#[derive(Debug, PartialEq)]
+enum Subtrie<K, V> {
+    Stored(Pointer),
+    Node(Box<PointerBlock>),
+    Extension { affix: Vec<u8>, pointer: Pointer },
+    Leaf { path: Vec<u8>, key: K, value: V },
+}
+
+impl<K, V> Subtrie<K, V> {
+    /// The subtries under the next byte of the paths, at `depth`, by byte.
+    /// Stored tries have to be read first.
+    fn children(self, depth: usize) -> Vec<(u8, Subtrie<K, V>)> {
+        match self {
+            Subtrie::Node(pointer_block) => (0..256)
+                .filter_map(|index| {
+                    pointer_block[index].map(|pointer| (index as u8, Subtrie::Stored(pointer)))
+                })
+                .collect(),
+            Subtrie::Extension { affix, pointer } => {
+                let rest = if affix.len() == 1 {
+                    Subtrie::Stored(pointer)
+                } else {
+                    Subtrie::Extension {
+                        affix: affix[1..].to_vec(),
+                        pointer,
+                    }
+                };
+                vec![(affix[0], rest)]
+            }
+            Subtrie::Leaf { path, key, value } => {
+                let index = path[depth];
+                vec![(index, Subtrie::Leaf { path, key, value })]
+            }
+            // Stored tries are read before they're expanded.
+            Subtrie::Stored(_) => unreachable!(),
+        }
+    }
+}
+
+enum Step<K, V> {
+    Changed(Change<K, V>),
+    TrieNotFound,
+    Done,
+}
+
+/// The changes from the state at one root to the state at another, in the
+/// order of the bytes of their keys. See [`diff`].
+pub struct TrieDiff<'a, K, V, T, S> {
+    txn: &'a T,
+    store: &'a S,
+    /// Subtries left to compare, at the number of bytes of the path their
+    /// parents consumed, the next one last.
+    pending: Vec<(usize, Option<Subtrie<K, V>>, Option<Subtrie<K, V>>)>,
+}
+
+/// Walks the tries at `from` and `to` together, without reading the
+/// subtries they share, which have the same hashes. Every change is an
+/// item, after which an item of `None` means that a trie of one of the
+/// states isn't in the store, and ends the walk.
+pub fn diff<'a, K, V, T, S>(
+    txn: &'a T,
+    store: &'a S,
+    from: &Blake2bHash,
+    to: &Blake2bHash,
+) -> TrieDiff<'a, K, V, T, S> {
+    let from = Subtrie::Stored(Pointer::NodePointer(*from));
+    let to = Subtrie::Stored(Pointer::NodePointer(*to));
+    TrieDiff {
+        txn,
+        store,
+        pending: vec![(0, Some(from), Some(to))],
+    }
+}
+
+impl<'a, K, V, T, S> TrieDiff<'a, K, V, T, S>
+where
+    K: ToBytes + FromBytes + PartialEq,
+    V: ToBytes + FromBytes + PartialEq,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    /// Reads `subtrie` from the store if it's stored. Returns `None` if it
+    /// isn't in the store.
+    fn load(
+        &self,
+        subtrie: Option<Subtrie<K, V>>,
+    ) -> Result<Option<Option<Subtrie<K, V>>>, S::Error> {
+        let pointer = match subtrie {
+            Some(Subtrie::Stored(pointer)) => pointer,
+            other => return Ok(Some(other)),
+        };
+        let loaded = match self.store.get(self.txn, pointer.hash())? {
+            Some(Trie::Leaf { key, value }) => {
+                let path = key.to_bytes()?;
+                Subtrie::Leaf { path, key, value }
+            }
+            Some(Trie::Node { pointer_block }) => Subtrie::Node(pointer_block),
+            Some(Trie::Extension { affix, pointer }) => Subtrie::Extension { affix, pointer },
+            None => return Ok(None),
+        };
+        Ok(Some(Some(loaded)))
+    }
+
+    /// Compares the children of `a` and `b` next, in the order of their
+    /// bytes.
+    fn expand(&mut self, depth: usize, a: Option<Subtrie<K, V>>, b: Option<Subtrie<K, V>>) {
+        let mut children: Vec<(Option<Subtrie<K, V>>, Option<Subtrie<K, V>>)> =
+            (0..256).map(|_| (None, None)).collect();
+        for (index, child) in a.map_or_else(Vec::new, |a| a.children(depth)) {
+            children[index as usize].0 = Some(child);
+        }
+        for (index, child) in b.map_or_else(Vec::new, |b| b.children(depth)) {
+            children[index as usize].1 = Some(child);
+        }
+        for (a, b) in children.into_iter().rev() {
+            if a.is_some() || b.is_some() {
+                self.pending.push((depth + 1, a, b));
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<Step<K, V>, S::Error> {
+        while let Some((depth, a, b)) = self.pending.pop() {
+            if a == b {
+                continue;
+            }
+            let (a, b) = match (self.load(a)?, self.load(b)?) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return Ok(Step::TrieNotFound),
+            };
+            match (a, b) {
+                (Some(Subtrie::Leaf { key, value, .. }), None) => {
+                    return Ok(Step::Changed((key, Some(value), None)));
+                }
+                (None, Some(Subtrie::Leaf { key, value, .. })) => {
+                    return Ok(Step::Changed((key, None, Some(value))));
+                }
+                (
+                    Some(Subtrie::Leaf {
+                        path: path_a,
+                        key: key_a,
+                        value: value_a,
+                    }),
+                    Some(Subtrie::Leaf {
+                        path: path_b,
+                        key: key_b,
+                        value: value_b,
+                    }),
+                ) => {
+                    if path_a == path_b {
+                        return Ok(Step::Changed((key_a, Some(value_a), Some(value_b))));
+                    }
+                    // Leaves of different keys are apart further down.
+                    let a = Subtrie::Leaf {
+                        path: path_a,
+                        key: key_a,
+                        value: value_a,
+                    };
+                    let b = Subtrie::Leaf {
+                        path: path_b,
+                        key: key_b,
+                        value: value_b,
+                    };
+                    self.expand(depth, Some(a), Some(b));
+                }
+                (a, b) => self.expand(depth, a, b),
+            }
+        }
+        Ok(Step::Done)
+    }
+}
+
+impl<'a, K, V, T, S> Iterator for TrieDiff<'a, K, V, T, S>
+where
+    K: ToBytes + FromBytes + PartialEq,
+    V: ToBytes + FromBytes + PartialEq,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    type Item = Result<Option<Change<K, V>>, S::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.step() {
+            Ok(Step::Changed(change)) => Some(Ok(Some(change))),
+            Ok(Step::Done) => None,
+            Ok(Step::TrieNotFound) => {
+                self.pending.clear();
+                Some(Ok(None))
+            }
+            Err(error) => {
+                self.pending.clear();
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+enum Found<K, V> {
+    Value(K, V),
+    TrieNotFound,
+    Done,
+}
+
+/// The keys starting with some bytes, with their values, in the order of
+/// their bytes. See [`keys_with_prefix`].
+pub struct KeysWithPrefix<'a, K, V, T, S> {
+    txn: &'a T,
+    store: &'a S,
+    prefix: Vec<u8>,
+    /// Tries left to visit, at the number of bytes of the path their parents
+    /// consumed, the next one last.
+    pending: Vec<(usize, Pointer)>,
+    _marker: PhantomData<(K, V)>,
+}
+
+/// Walks the trie at `root` down the path of `prefix`, then through every
+/// trie under it. Every key starting with `prefix` is an item, with its
+/// value, after which an item of `None` means that a trie under the root
+/// isn't in the store, and ends the walk.
+pub fn keys_with_prefix<'a, K, V, T, S>(
+    txn: &'a T,
+    store: &'a S,
+    root: &Blake2bHash,
+    prefix: &[u8],
+) -> KeysWithPrefix<'a, K, V, T, S> {
+    KeysWithPrefix {
+        txn,
+        store,
+        prefix: prefix.to_vec(),
+        pending: vec![(0, Pointer::NodePointer(*root))],
+        _marker: PhantomData,
+    }
+}
+
+impl<'a, K, V, T, S> KeysWithPrefix<'a, K, V, T, S>
+where
+    K: ToBytes + FromBytes,
+    V: ToBytes + FromBytes,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    fn step(&mut self) -> Result<Found<K, V>, S::Error> {
+        while let Some((depth, pointer)) = self.pending.pop() {
+            // The bytes of the prefix the tries at `depth` still have to match.
+            let rest = self.prefix.get(depth..).unwrap_or(&[]);
+            match self.store.get(self.txn, pointer.hash())? {
+                // Leaves can be above the end of the prefix, so their whole
+                // key is matched.
+                Some(Trie::Leaf { key, value }) => {
+                    if key.to_bytes()?.starts_with(&self.prefix) {
+                        return Ok(Found::Value(key, value));
+                    }
+                }
+                Some(Trie::Node { pointer_block }) => {
+                    let indices = match rest.first() {
+                        Some(index) => *index as usize..*index as usize + 1,
+                        None => 0..256,
+                    };
+                    for index in indices.rev() {
+                        if let Some(pointer) = pointer_block[index] {
+                            self.pending.push((depth + 1, pointer));
+                        }
+                    }
+                }
+                Some(Trie::Extension { affix, pointer }) => {
+                    let len = cmp::min(affix.len(), rest.len());
+                    if affix[..len] == rest[..len] {
+                        self.pending.push((depth + affix.len(), pointer));
+                    }
+                }
+                None => return Ok(Found::TrieNotFound),
+            }
+        }
+        Ok(Found::Done)
+    }
+}
+
+impl<'a, K, V, T, S> Iterator for KeysWithPrefix<'a, K, V, T, S>
+where
+    K: ToBytes + FromBytes,
+    V: ToBytes + FromBytes,
+    T: Readable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    type Item = Result<Option<(K, V)>, S::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.step() {
+            Ok(Found::Value(key, value)) => Some(Ok(Some((key, value)))),
+            Ok(Found::Done) => None,
+            Ok(Found::TrieNotFound) => {
+                self.pending.clear();
+                Some(Ok(None))
+            }
+            Err(error) => {
+                self.pending.clear();
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Puts a node branching to `a` and `b` at the bytes after the ones their
+/// paths share from `depth`, under an extension with the shared bytes if
+/// there are any.
+fn branch<K, V, T, S>(
+    txn: &mut T,
+    store: &S,
+    depth: usize,
+    (path_a, a): (&[u8], Pointer),
+    (path_b, b): (&[u8], Pointer),
+) -> Result<Pointer, S::Error>
+where
+    K: ToBytes,
+    V: ToBytes,
+    T: Writable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    let shared = common_prefix_len(&path_a[depth..], &path_b[depth..]);
+    let mut pointer_block = PointerBlock::new();
+    pointer_block[path_a[depth + shared] as usize] = Some(a);
+    pointer_block[path_b[depth + shared] as usize] = Some(b);
+    let node = Trie::Node {
+        pointer_block: Box::new(pointer_block),
+    };
+    let node_pointer = Pointer::NodePointer(put_trie::<K, V, T, S>(txn, store, &node)?);
+    if shared == 0 {
+        return Ok(node_pointer);
+    }
+    let extension = Trie::Extension {
+        affix: path_a[depth..depth + shared].to_vec(),
+        pointer: node_pointer,
+    };
+    Ok(Pointer::NodePointer(put_trie::<K, V, T, S>(txn, store, &extension)?))
+}
+
+/// Writes `leaf` at `path` in the trie at `hash`, whose parents consumed
+/// `depth` bytes of the path. Returns `None` if a trie is missing.
+fn write_at<K, V, T, S>(
+    txn: &mut T,
+    store: &S,
+    hash: &Blake2bHash,
+    depth: usize,
+    path: &[u8],
+    leaf: Pointer,
+) -> Result<Option<Pointer>, S::Error>
+where
+    K: ToBytes + FromBytes,
+    V: ToBytes + FromBytes,
+    T: Readable<Handle = S::Handle> + Writable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    let trie: Trie<K, V> = match store.get(txn, hash)? {
+        Some(trie) => trie,
+        None => return Ok(None),
+    };
+    let pointer = match trie {
+        Trie::Leaf { key, .. } => {
+            let leaf_path = key.to_bytes()?;
+            if leaf_path == path {
+                leaf
+            } else {
+                let existing = Pointer::LeafPointer(*hash);
+                branch::<K, V, T, S>(txn, store, depth, (path, leaf), (&leaf_path, existing))?
+            }
+        }
+        Trie::Node { mut pointer_block } => {
+            let index = path[depth] as usize;
+            let child = match pointer_block[index] {
+                Some(child) => match write_at::<K, V, T, S>(
+                    txn,
+                    store,
+                    child.hash(),
+                    depth + 1,
+                    path,
+                    leaf,
+                )? {
+                    Some(child) => child,
+                    None => return Ok(None),
+                },
+                None => leaf,
+            };
+            pointer_block[index] = Some(child);
+            let node = Trie::Node { pointer_block };
+            Pointer::NodePointer(put_trie::<K, V, T, S>(txn, store, &node)?)
+        }
+        Trie::Extension { affix, pointer } => {
+            let shared = common_prefix_len(&affix, &path[depth..]);
+            if shared == affix.len() {
+                let depth = depth + affix.len();
+                let pointer = match write_at::<K, V, T, S>(
+                    txn,
+                    store,
+                    pointer.hash(),
+                    depth,
+                    path,
+                    leaf,
+                )? {
+                    Some(pointer) => pointer,
+                    None => return Ok(None),
+                };
+                let extension = Trie::Extension { affix, pointer };
+                Pointer::NodePointer(put_trie::<K, V, T, S>(txn, store, &extension)?)
+            } else {
+                // The extension is split at the first byte the path doesn't
+                // share, keeping the bytes after it in an extension of its
+                // own if there are any.
+                let rest = if shared + 1 == affix.len() {
+                    pointer
+                } else {
+                    let extension: Trie<K, V> = Trie::Extension {
+                        affix: affix[shared + 1..].to_vec(),
+                        pointer,
+                    };
+                    Pointer::NodePointer(put_trie(txn, store, &extension)?)
+                };
+                let mut extension_path = path[..depth].to_vec();
+                extension_path.extend_from_slice(&affix);
+                branch::<K, V, T, S>(txn, store, depth, (path, leaf), (&extension_path, rest))?
+            }
+        }
+    };
+    Ok(Some(pointer))
+}
+
+/// Writes `value` under `key` in the trie at `root`.
+pub fn write<K, V, T, S>(
+    txn: &mut T,
+    store: &S,
+    root: &Blake2bHash,
+    key: &K,
+    value: &V,
+) -> Result<WriteResult, S::Error>
+where
+    K: ToBytes + FromBytes + Clone,
+    V: ToBytes + FromBytes + Clone,
+    T: Readable<Handle = S::Handle> + Writable<Handle = S::Handle>,
+    S: TrieStore<K, V>,
+    S::Error: From<T::Error> + From<bytesrepr::Error>,
+{
+    let path = key.to_bytes()?;
+    let leaf = Trie::Leaf {
+        key: key.clone(),
+        value: value.clone(),
+    };
+    let leaf = Pointer::LeafPointer(put_trie(txn, store, &leaf)?);
+    match write_at::<K, V, T, S>(txn, store, root, 0, &path, leaf)? {
+        Some(pointer) => Ok(WriteResult::Written(*pointer.hash())),
+        None => Ok(WriteResult::RootNotFound),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use common::bytesrepr::ToBytes;
+    use common::gens::{key_arb, value_arb};
+    use common::key::Key;
+    use common::trie_merkle_proof::validate_proof;
+    use common::value::Value;
+    use history::trie::Trie;
+    use history::trie_store::in_memory::{InMemoryEnvironment, InMemoryTrieStore};
+    use history::trie_store::{Transaction, TransactionSource};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use shared::newtypes::Blake2bHash;
+
+    use super::{
+        diff, empty_trie, keys_with_prefix, prove, put_trie, read, scan, write, ReadResult,
+        WriteResult,
+    };
+
+    /// Hashes made of few distinct bytes, whose paths share long prefixes.
+    fn hash_key_arb() -> impl Strategy<Value = Key> {
+        vec(0u8..3, 32).prop_map(|bytes| {
+            let mut hash = [0u8; 32];
+            hash.copy_from_slice(&bytes);
+            Key::Hash(hash)
+        })
+    }
+
+    fn pairs_arb() -> impl Strategy<Value = Vec<(Key, Value)>> {
+        let key = prop_oneof![key_arb(), hash_key_arb()];
+        vec((key, any::<i32>().prop_map(Value::Int32)), 1..50)
+    }
+
+    /// Writes `pairs` in order on top of the empty trie, returning the root
+    /// hash of every trie written.
+    fn write_all(
+        env: &InMemoryEnvironment,
+        store: &InMemoryTrieStore,
+        pairs: &[(Key, Value)],
+    ) -> Vec<Blake2bHash> {
+        let mut txn = env.create_read_write_txn().unwrap();
+        let mut root = put_trie(&mut txn, store, &empty_trie::<Key, Value>()).unwrap();
+        let mut roots = vec![root];
+        for (key, value) in pairs {
+            root = match write(&mut txn, store, &root, key, value).unwrap() {
+                WriteResult::Written(root) => root,
+                WriteResult::RootNotFound => panic!("The root was just written."),
+            };
+            roots.push(root);
+        }
+        txn.commit().unwrap();
+        roots
+    }
+
+    fn root_of(pairs: &[(Key, Value)]) -> Blake2bHash {
+        let env = InMemoryEnvironment::new();
+        let store = InMemoryTrieStore::new(&env);
+        *write_all(&env, &store, pairs).last().unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn values_are_read_where_they_were_written(pairs in pairs_arb()) {
+            let env = InMemoryEnvironment::new();
+            let store = InMemoryTrieStore::new(&env);
+            let roots = write_all(&env, &store, &pairs);
+            let txn = env.create_read_txn().unwrap();
+            let mut expected = BTreeMap::new();
+            for ((key, value), root) in pairs.iter().zip(&roots[1..]) {
+                expected.insert(*key, value.clone());
+                // Every root keeps the values written before it.
+                for (key, value) in &expected {
+                    let found = read::<Key, Value, _, _>(&txn, &store, root, key).unwrap();
+                    prop_assert_eq!(found, ReadResult::Found(value.clone()));
+                }
+            }
+            let empty_root = &roots[0];
+            for (key, _) in &pairs {
+                let found = read::<Key, Value, _, _>(&txn, &store, empty_root, key).unwrap();
+                prop_assert_eq!(found, ReadResult::NotFound);
+            }
+        }
+
+        #[test]
+        fn roots_do_not_depend_on_the_order_of_writes(
+            (pairs, shuffled) in pairs_arb()
+                .prop_map(|pairs| {
+                    // Writes of distinct keys don't depend on each other.
+                    let distinct: BTreeMap<Key, Value> = pairs.into_iter().collect();
+                    distinct.into_iter().collect::<Vec<(Key, Value)>>()
+                })
+                .prop_flat_map(|pairs| (Just(pairs.clone()), Just(pairs).prop_shuffle()))
+        ) {
+            prop_assert_eq!(root_of(&pairs), root_of(&shuffled));
+        }
+
+        #[test]
+        fn roots_depend_only_on_the_last_values_written(
+            pairs in pairs_arb(),
+            value in value_arb()
+        ) {
+            let (key, _) = pairs[0].clone();
+            let mut rewritten = pairs.clone();
+            rewritten.push((key, value.clone()));
+            rewritten.push((key, pairs[0].1.clone()));
+            let mut once = pairs.clone();
+            once.push((key, pairs[0].1.clone()));
+            prop_assert_eq!(root_of(&rewritten), root_of(&once));
+        }
+
+        #[test]
+        fn scans_lead_from_the_root_to_the_leaf(pairs in pairs_arb()) {
+            let env = InMemoryEnvironment::new();
+            let store = InMemoryTrieStore::new(&env);
+            let root = *write_all(&env, &store, &pairs).last().unwrap();
+            let txn = env.create_read_txn().unwrap();
+            for (key, _) in &pairs {
+                let trie_scan = scan::<Key, Value, _, _>(&txn, &store, &root, key)
+                    .unwrap()
+                    .unwrap();
+                match &trie_scan.tip {
+                    Trie::Leaf { key: leaf_key, .. } => prop_assert_eq!(leaf_key, key),
+                    tip => panic!("Expected a leaf, got {:?}", tip),
+                }
+                let (depth, first) = &trie_scan.parents[0];
+                prop_assert_eq!(*depth, 0);
+                prop_assert_eq!(Blake2bHash::new(&first.to_bytes().unwrap()), root);
+                prop_assert!(trie_scan.parents.iter().all(|(_, trie)| match trie {
+                    Trie::Leaf { .. } => false,
+                    _ => true,
+                }));
+            }
+        }
+
+        #[test]
+        fn proofs_of_the_values_written_are_valid(pairs in pairs_arb()) {
+            let env = InMemoryEnvironment::new();
+            let store = InMemoryTrieStore::new(&env);
+            let roots = write_all(&env, &store, &pairs);
+            let root = roots.last().unwrap();
+            let txn = env.create_read_txn().unwrap();
+            let hash = |bytes: &[u8]| Blake2bHash::new(bytes).value();
+            let expected: BTreeMap<Key, Value> = pairs.into_iter().collect();
+            for (key, value) in &expected {
+                let proof = match prove::<Key, Value, _, _>(&txn, &store, root, key).unwrap() {
+                    ReadResult::Found(proof) => proof,
+                    result => panic!("Expected a proof, got {:?}", result),
+                };
+                prop_assert_eq!(proof.value(), value);
+                prop_assert_eq!(validate_proof(&root.value(), key, &proof, hash), Ok(()));
+                // Proofs are only valid for the state they were made of.
+                prop_assert!(validate_proof(&roots[0].value(), key, &proof, hash).is_err());
+            }
+            let empty_root = &roots[0];
+            for key in expected.keys() {
+                let result = prove::<Key, Value, _, _>(&txn, &store, empty_root, key).unwrap();
+                prop_assert_eq!(result, ReadResult::NotFound);
+            }
+        }
+
+        #[test]
+        fn diffs_are_the_values_changed_between_states(
+            pairs_a in pairs_arb(),
+            pairs_b in pairs_arb()
+        ) {
+            let env = InMemoryEnvironment::new();
+            let store = InMemoryTrieStore::new(&env);
+            let root_a = *write_all(&env, &store, &pairs_a).last().unwrap();
+            let root_b = *write_all(&env, &store, &pairs_b).last().unwrap();
+            let values_a: BTreeMap<Key, Value> = pairs_a.into_iter().collect();
+            let values_b: BTreeMap<Key, Value> = pairs_b.into_iter().collect();
+            let mut expected = BTreeMap::new();
+            for key in values_a.keys().chain(values_b.keys()) {
+                let a = values_a.get(key).cloned();
+                let b = values_b.get(key).cloned();
+                if a != b {
+                    expected.insert(*key, (a, b));
+                }
+            }
+            let txn = env.create_read_txn().unwrap();
+            let changes: Vec<(Key, Option<Value>, Option<Value>)> =
+                diff::<Key, Value, _, _>(&txn, &store, &root_a, &root_b)
+                    .map(|change| change.unwrap().expect("Every trie was written."))
+                    .collect();
+            // Changes come in the order of the bytes of their keys.
+            let paths: Vec<Vec<u8>> = changes
+                .iter()
+                .map(|(key, _, _)| key.to_bytes().unwrap())
+                .collect();
+            prop_assert!(paths.windows(2).all(|pair| pair[0] < pair[1]));
+            let changes: BTreeMap<Key, (Option<Value>, Option<Value>)> = changes
+                .into_iter()
+                .map(|(key, a, b)| (key, (a, b)))
+                .collect();
+            prop_assert_eq!(changes, expected);
+            prop_assert_eq!(diff::<Key, Value, _, _>(&txn, &store, &root_a, &root_a).count(), 0);
+        }
+
+        #[test]
+        fn keys_with_prefix_are_the_keys_starting_with_it(
+            pairs in pairs_arb(),
+            prefix_len in 0usize..40
+        ) {
+            let env = InMemoryEnvironment::new();
+            let store = InMemoryTrieStore::new(&env);
+            let root = *write_all(&env, &store, &pairs).last().unwrap();
+            let path = pairs[0].0.to_bytes().unwrap();
+            let prefix = &path[..prefix_len.min(path.len())];
+            let values: BTreeMap<Key, Value> = pairs.into_iter().collect();
+            let mut expected: Vec<(Vec<u8>, Key, Value)> = values
+                .into_iter()
+                .map(|(key, value)| (key.to_bytes().unwrap(), key, value))
+                .filter(|(path, _, _)| path.starts_with(prefix))
+                .collect();
+            // Keys come in the order of their bytes.
+            expected.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+            let expected: Vec<(Key, Value)> = expected
+                .into_iter()
+                .map(|(_, key, value)| (key, value))
+                .collect();
+            let txn = env.create_read_txn().unwrap();
+            let found: Vec<(Key, Value)> =
+                keys_with_prefix::<Key, Value, _, _>(&txn, &store, &root, prefix)
+                    .map(|pair| pair.unwrap().expect("Every trie was written."))
+                    .collect();
+            prop_assert_eq!(found, expected);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! A trie store holding the tries put in it in memory, on top of another trie
+//! store which is only read from.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use history::trie::Trie;
+use history::trie_store::{Readable, TrieStore, Writable};
+use shared::newtypes::Blake2bHash;
+
+/// Tries held in memory, at their hashes.
+pub type TrieBatch<K, V> = HashMap<Blake2bHash, Trie<K, V>>;
+
+/// Reads the tries put in it, then the tries of `base`, then the ones of
+/// `store`. Only the tries put in it are removed by deletes.
+pub struct OverlayTrieStore<'a, K, V, S> {
+    store: &'a S,
+    base: &'a TrieBatch<K, V>,
+    writes: RefCell<TrieBatch<K, V>>,
+}
+
+impl<'a, K, V, S> OverlayTrieStore<'a, K, V, S> {
+    pub fn new(store: &'a S, base: &'a TrieBatch<K, V>) -> Self {
+        OverlayTrieStore {
+            store,
+            base,
+            writes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The tries put in the overlay.
+    pub fn into_writes(self) -> TrieBatch<K, V> {
+        self.writes.into_inner()
+    }
+}
+
+impl<'a, K, V, S> TrieStore<K, V> for OverlayTrieStore<'a, K, V, S>
+where
+    K: Clone,
+    V: Clone,
+    S: TrieStore<K, V>,
+{
+    type Error = S::Error;
+
+    type Handle = S::Handle;
+
+    fn get<T>(&self, txn: &T, key: &Blake2bHash) -> Result<Option<Trie<K, V>>, Self::Error>
+    where
+        T: Readable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        if let Some(trie) = self.writes.borrow().get(key) {
+            return Ok(Some(trie.clone()));
+        }
+        if let Some(trie) = self.base.get(key) {
+            return Ok(Some(trie.clone()));
+        }
+        self.store.get(txn, key)
+    }
+
+    fn put<T>(&self, _txn: &mut T, key: &Blake2bHash, value: &Trie<K, V>) -> Result<(), Self::Error>
+    where
+        T: Writable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        self.writes.borrow_mut().insert(*key, value.clone());
+        Ok(())
+    }
+
+    fn hashes<T>(&self, txn: &T) -> Result<Vec<Blake2bHash>, Self::Error>
+    where
+        T: Readable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        let mut hashes: HashSet<Blake2bHash> = self.store.hashes(txn)?.into_iter().collect();
+        hashes.extend(self.base.keys());
+        hashes.extend(self.writes.borrow().keys());
+        Ok(hashes.into_iter().collect())
+    }
+
+    fn delete<T>(&self, _txn: &mut T, key: &Blake2bHash) -> Result<(), Self::Error>
+    where
+        T: Writable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        self.writes.borrow_mut().remove(key);
+        Ok(())
+    }
+}
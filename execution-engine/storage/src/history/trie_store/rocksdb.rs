@@ -0,0 +1,283 @@
+//! A RocksDB-backed trie store, with the same transactions as the
+//! [lmdb](../lmdb/index.html) one.
+//!
+//! Stores are kept in column families of a database. Read transactions read
+//! a snapshot of the database taken when they begin. Read-write transactions
+//! hold their writes in memory, in front of a snapshot of their own, and
+//! write them in one batch when they're committed. Only one of them can run
+//! at a time, like in LMDB.
+
+use super::*;
+use common::bytesrepr::{deserialize, FromBytes, ToBytes};
+use error;
+use history::trie_store::compression::{self, Compression};
+use history::trie_store::integrity;
+use metrics::{self, StoreMetrics};
+use parking_lot::{Mutex, MutexGuard};
+use rocksdb::{ColumnFamily, IteratorMode, Options, Snapshot, WriteBatch, DB};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub use rocksdb::DEFAULT_COLUMN_FAMILY_NAME;
+
+fn column_family<'a>(db: &'a DB, name: &str) -> Result<&'a ColumnFamily, error::Error> {
+    db.cf_handle(name)
+        .ok_or_else(|| error::Error::RocksDb(format!("Column family {} isn't open", name)))
+}
+
+fn keys(db: &DB, snapshot: &Snapshot, name: &str) -> Result<Vec<Vec<u8>>, error::Error> {
+    let mut iter = snapshot.iterator_cf(column_family(db, name)?, IteratorMode::Start);
+    let keys = iter.by_ref().map(|(key, _)| key.into_vec()).collect();
+    iter.status()?;
+    Ok(keys)
+}
+
+/// A read transaction of a [`RocksDbEnvironment`]. Column families are its
+/// handles, by name.
+pub struct RocksDbReadTransaction<'a> {
+    db: &'a DB,
+    snapshot: Snapshot<'a>,
+}
+
+impl<'a> Transaction for RocksDbReadTransaction<'a> {
+    type Error = error::Error;
+
+    type Handle = &'static str;
+
+    fn commit(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> Readable for RocksDbReadTransaction<'a> {
+    fn read(&self, handle: Self::Handle, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        let value = self.snapshot.get_cf(column_family(self.db, handle)?, key)?;
+        Ok(value)
+    }
+
+    fn keys(&self, handle: Self::Handle) -> Result<Vec<Vec<u8>>, Self::Error> {
+        keys(self.db, &self.snapshot, handle)
+    }
+}
+
+/// A read-write transaction of a [`RocksDbEnvironment`]. Its writes and
+/// deletes are only seen by itself until it's committed.
+pub struct RocksDbReadWriteTransaction<'a> {
+    db: &'a DB,
+    snapshot: Snapshot<'a>,
+    /// Values written, or `None` for the deleted ones, by column family and
+    /// key.
+    changes: BTreeMap<(&'static str, Vec<u8>), Option<Vec<u8>>>,
+    _write_lock: MutexGuard<'a, ()>,
+}
+
+impl<'a> Transaction for RocksDbReadWriteTransaction<'a> {
+    type Error = error::Error;
+
+    type Handle = &'static str;
+
+    fn commit(self) -> Result<(), Self::Error> {
+        let mut batch = WriteBatch::default();
+        for ((handle, key), value) in self.changes {
+            let cf = column_family(self.db, handle)?;
+            match value {
+                Some(value) => batch.put_cf(cf, key, value),
+                None => batch.delete_cf(cf, key),
+            }
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+}
+
+impl<'a> Readable for RocksDbReadWriteTransaction<'a> {
+    fn read(&self, handle: Self::Handle, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        if let Some(value) = self.changes.get(&(handle, key.to_vec())) {
+            return Ok(value.clone());
+        }
+        let value = self.snapshot.get_cf(column_family(self.db, handle)?, key)?;
+        Ok(value)
+    }
+
+    fn keys(&self, handle: Self::Handle) -> Result<Vec<Vec<u8>>, Self::Error> {
+        let mut keys: BTreeSet<Vec<u8>> = keys(self.db, &self.snapshot, handle)?
+            .into_iter()
+            .collect();
+        for ((change_handle, key), value) in &self.changes {
+            if *change_handle != handle {
+                continue;
+            }
+            match value {
+                Some(_) => keys.insert(key.clone()),
+                None => keys.remove(key),
+            };
+        }
+        Ok(keys.into_iter().collect())
+    }
+}
+
+impl<'a> Writable for RocksDbReadWriteTransaction<'a> {
+    fn write(&mut self, handle: Self::Handle, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.changes.insert((handle, key.to_vec()), Some(value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, handle: Self::Handle, key: &[u8]) -> Result<(), Self::Error> {
+        self.changes.insert((handle, key.to_vec()), None);
+        Ok(())
+    }
+}
+
+/// The environment for a RocksDB-backed trie store.
+///
+/// Wraps a [`rocksdb::DB`].
+pub struct RocksDbEnvironment {
+    path: PathBuf,
+    db: DB,
+    write_mutex: Mutex<()>,
+}
+
+impl RocksDbEnvironment {
+    /// Opens the database in the directory at `path` with `column_families`,
+    /// creating the database and the column families if they don't exist yet.
+    /// Every column family of an existing database has to be opened.
+    pub fn new(path: &PathBuf, column_families: &[&str]) -> Result<Self, error::Error> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = DB::open_cf(&options, path, column_families)?;
+        Ok(RocksDbEnvironment {
+            path: path.to_owned(),
+            db,
+            write_mutex: Mutex::new(()),
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+}
+
+impl<'a> TransactionSource<'a> for RocksDbEnvironment {
+    type Error = error::Error;
+
+    type Handle = &'static str;
+
+    type ReadTransaction = RocksDbReadTransaction<'a>;
+
+    type ReadWriteTransaction = RocksDbReadWriteTransaction<'a>;
+
+    fn create_read_txn(&'a self) -> Result<RocksDbReadTransaction<'a>, Self::Error> {
+        Ok(RocksDbReadTransaction {
+            db: &self.db,
+            snapshot: self.db.snapshot(),
+        })
+    }
+
+    fn create_read_write_txn(&'a self) -> Result<RocksDbReadWriteTransaction<'a>, Self::Error> {
+        // The snapshot is taken once the other read-write transactions are
+        // done, so it has all their writes.
+        let _write_lock = self.write_mutex.lock();
+        Ok(RocksDbReadWriteTransaction {
+            db: &self.db,
+            snapshot: self.db.snapshot(),
+            changes: BTreeMap::new(),
+            _write_lock,
+        })
+    }
+}
+
+/// A RocksDB-backed trie store, keeping the tries in a column family.
+#[derive(Debug, Clone)]
+pub struct RocksDbTrieStore {
+    column_family: &'static str,
+    metrics: Arc<dyn StoreMetrics>,
+    compression: Option<Compression>,
+}
+
+impl RocksDbTrieStore {
+    /// The store in the column family `name`, which has to be open in `env`.
+    pub fn new(env: &RocksDbEnvironment, name: &'static str) -> Result<Self, error::Error> {
+        column_family(&env.db, name)?;
+        Ok(RocksDbTrieStore {
+            column_family: name,
+            metrics: metrics::no_metrics(),
+            compression: None,
+        })
+    }
+
+    /// The store, compressing the tries it writes with `compression`.
+    pub fn with_compression(self, compression: Compression) -> Self {
+        RocksDbTrieStore {
+            compression: Some(compression),
+            ..self
+        }
+    }
+
+    /// The store, reporting the tries it reads and writes to `metrics`.
+    pub fn with_metrics(self, metrics: Arc<dyn StoreMetrics>) -> Self {
+        RocksDbTrieStore { metrics, ..self }
+    }
+
+    pub fn metrics(&self) -> &Arc<dyn StoreMetrics> {
+        &self.metrics
+    }
+}
+
+impl<K: ToBytes + FromBytes, V: ToBytes + FromBytes> TrieStore<K, V> for RocksDbTrieStore {
+    type Error = error::Error;
+
+    type Handle = &'static str;
+
+    fn get<T>(&self, txn: &T, key: &Blake2bHash) -> Result<Option<Trie<K, V>>, Self::Error>
+    where
+        T: Readable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        match txn.read(self.column_family, &key.to_bytes()?)? {
+            None => Ok(None),
+            Some(bytes) => {
+                self.metrics.trie_read(bytes.len());
+                let trie = deserialize(&integrity::open_trie(key, &bytes, &self.metrics)?)?;
+                Ok(Some(trie))
+            }
+        }
+    }
+
+    fn put<T>(&self, txn: &mut T, key: &Blake2bHash, value: &Trie<K, V>) -> Result<(), Self::Error>
+    where
+        T: Writable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        let bytes = compression::encode(value.to_bytes()?, self.compression.as_ref())?;
+        self.metrics.trie_written(bytes.len());
+        txn.write(self.column_family, &key.to_bytes()?, &bytes)
+            .map_err(Into::into)
+    }
+
+    fn hashes<T>(&self, txn: &T) -> Result<Vec<Blake2bHash>, Self::Error>
+    where
+        T: Readable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        let mut hashes = Vec::new();
+        for bytes in txn.keys(self.column_family)? {
+            hashes.push(deserialize(&bytes)?);
+        }
+        Ok(hashes)
+    }
+
+    fn delete<T>(&self, txn: &mut T, key: &Blake2bHash) -> Result<(), Self::Error>
+    where
+        T: Writable<Handle = Self::Handle>,
+        Self::Error: From<T::Error>,
+    {
+        txn.delete(self.column_family, &key.to_bytes()?)
+            .map_err(Into::into)
+    }
+}
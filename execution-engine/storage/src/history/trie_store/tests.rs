@@ -107,9 +107,14 @@ mod simple {
     use history::trie::Trie;
     use history::trie_store::in_memory::{self, InMemoryEnvironment, InMemoryTrieStore};
     use history::trie_store::lmdb::{LmdbEnvironment, LmdbTrieStore};
-    use history::trie_store::{Transaction, TransactionSource, TrieStore};
+    use history::trie_store::rocksdb::{
+        RocksDbEnvironment, RocksDbTrieStore, DEFAULT_COLUMN_FAMILY_NAME,
+    };
+    use history::trie_store::{Transaction, TransactionSource, TrieStore, Writable};
     use lmdb::DatabaseFlags;
+    use metrics::StoreCounters;
     use shared::newtypes::Blake2bHash;
+    use std::sync::Arc;
     use tempfile::tempdir;
 
     fn put_succeeds<'a, K, V, S, X, E>(
@@ -164,6 +169,23 @@ mod simple {
         tmp_dir.close().unwrap();
     }
 
+    #[test]
+    fn rocksdb_put_succeeds() {
+        let tmp_dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&tmp_dir.path().to_path_buf(), &[]).unwrap();
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+        let data = &super::create_data()[0..1];
+
+        assert!(put_succeeds::<
+            Vec<u8>,
+            Vec<u8>,
+            RocksDbTrieStore,
+            RocksDbEnvironment,
+            error::Error,
+        >(&store, &env, data)
+        .is_ok());
+    }
+
     fn put_get_succeeds<'a, K, V, S, X, E>(
         store: &S,
         transaction_source: &'a X,
@@ -234,6 +256,32 @@ mod simple {
         tmp_dir.close().unwrap();
     }
 
+    #[test]
+    fn rocksdb_put_get_succeeds() {
+        let tmp_dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&tmp_dir.path().to_path_buf(), &[]).unwrap();
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+        let data = &super::create_data()[0..1];
+
+        let expected: Vec<Trie<Vec<u8>, Vec<u8>>> =
+            data.to_vec().into_iter().map(|TestData(_, v)| v).collect();
+
+        assert_eq!(
+            expected,
+            put_get_succeeds::<
+                Vec<u8>,
+                Vec<u8>,
+                RocksDbTrieStore,
+                RocksDbEnvironment,
+                error::Error,
+            >(&store, &env, data)
+            .expect("put_get_succeeds failed")
+            .into_iter()
+            .collect::<Option<Vec<Trie<Vec<u8>, Vec<u8>>>>>()
+            .expect("one of the outputs was empty")
+        );
+    }
+
     #[test]
     fn in_memory_put_get_many_succeeds() {
         let env = InMemoryEnvironment::new();
@@ -283,6 +331,32 @@ mod simple {
         tmp_dir.close().unwrap();
     }
 
+    #[test]
+    fn rocksdb_put_get_many_succeeds() {
+        let tmp_dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&tmp_dir.path().to_path_buf(), &[]).unwrap();
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+        let data = super::create_data();
+
+        let expected: Vec<Trie<Vec<u8>, Vec<u8>>> =
+            data.to_vec().into_iter().map(|TestData(_, v)| v).collect();
+
+        assert_eq!(
+            expected,
+            put_get_succeeds::<
+                Vec<u8>,
+                Vec<u8>,
+                RocksDbTrieStore,
+                RocksDbEnvironment,
+                error::Error,
+            >(&store, &env, &data)
+            .expect("put_get failed")
+            .into_iter()
+            .collect::<Option<Vec<Trie<Vec<u8>, Vec<u8>>>>>()
+            .expect("one of the outputs was empty")
+        );
+    }
+
     fn uncommitted_read_write_txn_does_not_persist<'a, K, V, S, X, E>(
         store: &S,
         transaction_source: &'a X,
@@ -354,6 +428,28 @@ mod simple {
         tmp_dir.close().unwrap();
     }
 
+    #[test]
+    fn rocksdb_uncommitted_read_write_txn_does_not_persist() {
+        let tmp_dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&tmp_dir.path().to_path_buf(), &[]).unwrap();
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+        let data = super::create_data();
+
+        assert_eq!(
+            None,
+            uncommitted_read_write_txn_does_not_persist::<
+                Vec<u8>,
+                Vec<u8>,
+                RocksDbTrieStore,
+                RocksDbEnvironment,
+                error::Error,
+            >(&store, &env, &data)
+            .expect("uncommitted_read_write_txn_does_not_persist failed")
+            .into_iter()
+            .collect::<Option<Vec<Trie<Vec<u8>, Vec<u8>>>>>()
+        );
+    }
+
     fn read_write_transaction_does_not_block_read_transaction<'a, X, E>(
         transaction_source: &'a X,
     ) -> Result<(), E>
@@ -389,6 +485,17 @@ mod simple {
         assert!(result.is_ok())
     }
 
+    #[test]
+    fn rocksdb_read_write_transaction_does_not_block_read_transaction() {
+        let dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&dir.path().to_path_buf(), &[]).unwrap();
+
+        let result: Result<(), error::Error> =
+            read_write_transaction_does_not_block_read_transaction(&env);
+
+        assert!(result.is_ok())
+    }
+
     fn reads_are_isolated<'a, S, X, E>(store: &S, env: &'a X) -> Result<(), E>
     where
         S: TrieStore<Vec<u8>, Vec<u8>>,
@@ -445,6 +552,17 @@ mod simple {
         assert!(result.is_ok())
     }
 
+    #[test]
+    fn rocksdb_reads_are_isolated() {
+        let dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&dir.path().to_path_buf(), &[]).unwrap();
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+
+        let result: Result<(), error::Error> = reads_are_isolated(&store, &env);
+
+        assert!(result.is_ok())
+    }
+
     fn reads_are_isolated_2<'a, S, X, E>(store: &S, env: &'a X) -> Result<(), E>
     where
         S: TrieStore<Vec<u8>, Vec<u8>>,
@@ -504,6 +622,73 @@ mod simple {
 
         assert!(result.is_ok())
     }
+
+    #[test]
+    fn rocksdb_reads_are_isolated_2() {
+        let dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&dir.path().to_path_buf(), &[]).unwrap();
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+
+        let result: Result<(), error::Error> = reads_are_isolated_2(&store, &env);
+
+        assert!(result.is_ok())
+    }
+
+    /// Flips a bit of a trie stored in `handle`, which the store has to
+    /// refuse to read.
+    fn corrupted_tries_are_detected<'a, S, X>(
+        store: &S,
+        transaction_source: &'a X,
+        handle: S::Handle,
+        counters: &StoreCounters,
+    ) -> Result<(), error::Error>
+    where
+        S: TrieStore<Vec<u8>, Vec<u8>, Error = error::Error>,
+        X: TransactionSource<'a, Handle = S::Handle>,
+        error::Error: From<X::Error>,
+    {
+        let TestData(hash, trie) = super::create_data().remove(0);
+        let mut txn = transaction_source.create_read_write_txn()?;
+        store.put(&mut txn, &hash, &trie)?;
+        let mut corrupted = trie.to_bytes()?;
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        txn.write(handle, &hash.to_bytes()?, &corrupted)?;
+        txn.commit()?;
+
+        let txn = transaction_source.create_read_txn()?;
+        match store.get(&txn, &hash) {
+            Err(error::Error::CorruptionDetected { key }) => assert_eq!(key, hash.to_vec()),
+            other => panic!("Expected a corrupted trie, got {:?}", other),
+        }
+        txn.commit()?;
+        assert_eq!(counters.counts().corruptions_detected, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn lmdb_corrupted_tries_are_detected() {
+        let dir = tempdir().unwrap();
+        let env = LmdbEnvironment::new(&dir.path().to_path_buf()).unwrap();
+        let counters = Arc::new(StoreCounters::new());
+        let store = LmdbTrieStore::new(&env, None, DatabaseFlags::empty())
+            .unwrap()
+            .with_metrics(counters.clone());
+
+        corrupted_tries_are_detected(&store, &env, store.db(), &counters).unwrap();
+    }
+
+    #[test]
+    fn rocksdb_corrupted_tries_are_detected() {
+        let dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&dir.path().to_path_buf(), &[]).unwrap();
+        let counters = Arc::new(StoreCounters::new());
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME)
+            .unwrap()
+            .with_metrics(counters.clone());
+
+        corrupted_tries_are_detected(&store, &env, DEFAULT_COLUMN_FAMILY_NAME, &counters).unwrap();
+    }
 }
 
 mod concurrent {
@@ -511,6 +696,9 @@ mod concurrent {
     use history::trie::Trie;
     use history::trie_store::in_memory::{InMemoryEnvironment, InMemoryTrieStore};
     use history::trie_store::lmdb::{LmdbEnvironment, LmdbTrieStore};
+    use history::trie_store::rocksdb::{
+        RocksDbEnvironment, RocksDbTrieStore, DEFAULT_COLUMN_FAMILY_NAME,
+    };
     use history::trie_store::{Transaction, TransactionSource, TrieStore};
     use std::sync::{Arc, Barrier};
     use std::thread;
@@ -619,6 +807,58 @@ mod concurrent {
 
         assert!(handles.into_iter().all(|b| b.join().unwrap()))
     }
+
+    #[test]
+    fn rocksdb_writer_mutex_does_not_collide_with_readers() {
+        let dir = tempdir().unwrap();
+        let env = Arc::new(RocksDbEnvironment::new(&dir.path().to_path_buf(), &[]).unwrap());
+        let store = Arc::new(RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap());
+        let num_threads = 10;
+        let barrier = Arc::new(Barrier::new(num_threads + 1));
+        let mut handles = Vec::new();
+        let TestData(ref leaf_1_hash, ref leaf_1) = &super::create_data()[0..1][0];
+
+        for _ in 0..num_threads {
+            let reader_env = env.clone();
+            let reader_store = store.clone();
+            let reader_barrier = barrier.clone();
+            let leaf_1_hash = *leaf_1_hash;
+            #[allow(clippy::clone_on_copy)]
+            let leaf_1 = leaf_1.clone();
+
+            handles.push(thread::spawn(move || {
+                {
+                    let txn = reader_env.create_read_txn().unwrap();
+                    let result: Option<Trie<Vec<u8>, Vec<u8>>> =
+                        reader_store.get(&txn, &leaf_1_hash).unwrap();
+                    assert_eq!(result, None);
+                    txn.commit().unwrap();
+                }
+                // wait for other reader threads to read and the main thread to
+                // take a read-write transaction
+                reader_barrier.wait();
+                // wait for main thread to put and commit
+                reader_barrier.wait();
+                {
+                    let txn = reader_env.create_read_txn().unwrap();
+                    let result: Option<Trie<Vec<u8>, Vec<u8>>> =
+                        reader_store.get(&txn, &leaf_1_hash).unwrap();
+                    txn.commit().unwrap();
+                    result.unwrap() == leaf_1
+                }
+            }));
+        }
+
+        let mut txn = env.create_read_write_txn().unwrap();
+        // wait for reader threads to read
+        barrier.wait();
+        store.put(&mut txn, &leaf_1_hash, &leaf_1).unwrap();
+        txn.commit().unwrap();
+        // sync with reader threads
+        barrier.wait();
+
+        assert!(handles.into_iter().all(|b| b.join().unwrap()))
+    }
 }
 
 mod proptests {
@@ -723,6 +963,23 @@ mod proptests {
         ret
     }
 
+    fn rocksdb_roundtrip_succeeds(inputs: Vec<Trie<Key, Value>>) -> bool {
+        use error;
+        use history::trie_store::rocksdb::{
+            RocksDbEnvironment, RocksDbTrieStore, DEFAULT_COLUMN_FAMILY_NAME,
+        };
+
+        let tmp_dir = tempdir().unwrap();
+        let env = RocksDbEnvironment::new(&tmp_dir.path().to_path_buf(), &[]).unwrap();
+        let store = RocksDbTrieStore::new(&env, DEFAULT_COLUMN_FAMILY_NAME).unwrap();
+
+        let ret = roundtrip_succeeds::<RocksDbTrieStore, RocksDbEnvironment, error::Error>(
+            &store, &env, inputs,
+        );
+        tmp_dir.close().unwrap();
+        ret
+    }
+
     proptest! {
         #[test]
         fn prop_in_memory_roundtrip_succeeds(v in vec(trie_arb(), get_range())) {
@@ -733,5 +990,10 @@ mod proptests {
         fn prop_lmdb_roundtrip_succeeds(v in vec(trie_arb(), get_range())) {
             assert!(lmdb_roundtrip_succeeds(v))
         }
+
+        #[test]
+        fn prop_rocksdb_roundtrip_succeeds(v in vec(trie_arb(), get_range())) {
+            assert!(rocksdb_roundtrip_succeeds(v))
+        }
     }
 }
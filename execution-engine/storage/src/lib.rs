@@ -7,8 +7,11 @@ extern crate common;
 extern crate lmdb;
 extern crate num;
 extern crate parking_lot;
+extern crate rocksdb;
 extern crate shared;
 extern crate wasmi;
+#[cfg(feature = "compression")]
+extern crate zstd;
 
 #[cfg(test)]
 extern crate proptest;
@@ -19,5 +22,6 @@ extern crate tempfile;
 pub mod error;
 pub mod global_state;
 pub mod history;
+pub mod metrics;
 pub mod op;
 pub mod transform;
@@ -0,0 +1,205 @@
+//! Metrics of the stores of the global state.
+//!
+//! The trie stores report every trie they read or write with its size in
+//! bytes, and the global states report how long their operations take, to
+//! the [`StoreMetrics`] they are given. Both report the records they find
+//! corrupted. Nothing is recorded by default; [`StoreCounters`] keeps running
+//! totals, which the server reports.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Operations of a global state whose latency is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StoreOperation {
+    Checkout,
+    Read,
+    ReadWithProof,
+    KeysWithPrefix,
+    Commit,
+    CommitAt,
+    CommitCheckpoint,
+    Diff,
+    Prune,
+}
+
+impl StoreOperation {
+    /// Name of the operation in reports, in snake case.
+    pub fn name(self) -> &'static str {
+        match self {
+            StoreOperation::Checkout => "checkout",
+            StoreOperation::Read => "read",
+            StoreOperation::ReadWithProof => "read_with_proof",
+            StoreOperation::KeysWithPrefix => "keys_with_prefix",
+            StoreOperation::Commit => "commit",
+            StoreOperation::CommitAt => "commit_at",
+            StoreOperation::CommitCheckpoint => "commit_checkpoint",
+            StoreOperation::Diff => "diff",
+            StoreOperation::Prune => "prune",
+        }
+    }
+}
+
+/// Receives the metrics of a store. It's shared by the threads using the
+/// store, and every method does nothing unless it's overridden.
+pub trait StoreMetrics: Send + Sync {
+    /// A trie of `size` bytes was read from the store.
+    fn trie_read(&self, _size: usize) {}
+
+    /// A trie of `size` bytes was written to the store.
+    fn trie_written(&self, _size: usize) {}
+
+    /// `operation` took `duration`, whether it succeeded or not.
+    fn operation(&self, _operation: StoreOperation, _duration: Duration) {}
+
+    /// A record read didn't match its key or checksum.
+    fn corruption_detected(&self) {}
+}
+
+impl fmt::Debug for dyn StoreMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("StoreMetrics")
+    }
+}
+
+/// Metrics which aren't recorded anywhere.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoMetrics;
+
+impl StoreMetrics for NoMetrics {}
+
+/// What the stores record unless they're given other metrics.
+pub fn no_metrics() -> Arc<dyn StoreMetrics> {
+    Arc::new(NoMetrics)
+}
+
+/// Records the time from its start until it's dropped as the latency of an
+/// operation, so that operations returning early are timed too.
+pub struct OperationTimer {
+    metrics: Arc<dyn StoreMetrics>,
+    operation: StoreOperation,
+    started: Instant,
+}
+
+impl OperationTimer {
+    pub fn start(metrics: &Arc<dyn StoreMetrics>, operation: StoreOperation) -> OperationTimer {
+        OperationTimer {
+            metrics: Arc::clone(metrics),
+            operation,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for OperationTimer {
+    fn drop(&mut self) {
+        self.metrics.operation(self.operation, self.started.elapsed());
+    }
+}
+
+/// How many times an operation ran, and how long it took altogether.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OperationCounts {
+    pub count: u64,
+    pub total_duration: Duration,
+}
+
+/// Totals of the metrics of a store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreCounts {
+    pub tries_read: u64,
+    pub trie_bytes_read: u64,
+    pub tries_written: u64,
+    pub trie_bytes_written: u64,
+    pub corruptions_detected: u64,
+    /// Only the operations which ran are there.
+    pub operations: BTreeMap<StoreOperation, OperationCounts>,
+}
+
+/// Metrics kept as running totals.
+#[derive(Debug, Default)]
+pub struct StoreCounters {
+    counts: Mutex<StoreCounts>,
+}
+
+impl StoreCounters {
+    pub fn new() -> StoreCounters {
+        StoreCounters::default()
+    }
+
+    pub fn counts(&self) -> StoreCounts {
+        self.counts.lock().clone()
+    }
+}
+
+impl StoreMetrics for StoreCounters {
+    fn trie_read(&self, size: usize) {
+        let mut counts = self.counts.lock();
+        counts.tries_read += 1;
+        counts.trie_bytes_read += size as u64;
+    }
+
+    fn trie_written(&self, size: usize) {
+        let mut counts = self.counts.lock();
+        counts.tries_written += 1;
+        counts.trie_bytes_written += size as u64;
+    }
+
+    fn operation(&self, operation: StoreOperation, duration: Duration) {
+        let mut counts = self.counts.lock();
+        let operation_counts = counts.operations.entry(operation).or_default();
+        operation_counts.count += 1;
+        operation_counts.total_duration += duration;
+    }
+
+    fn corruption_detected(&self) {
+        self.counts.lock().corruptions_detected += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{OperationCounts, OperationTimer, StoreCounters, StoreMetrics, StoreOperation};
+
+    #[test]
+    fn counters_add_up_the_metrics() {
+        let counters = StoreCounters::new();
+        counters.trie_read(10);
+        counters.trie_read(20);
+        counters.trie_written(5);
+        counters.corruption_detected();
+        counters.operation(StoreOperation::Commit, Duration::from_millis(2));
+        counters.operation(StoreOperation::Commit, Duration::from_millis(3));
+        let counts = counters.counts();
+        assert_eq!((counts.tries_read, counts.trie_bytes_read), (2, 30));
+        assert_eq!((counts.tries_written, counts.trie_bytes_written), (1, 5));
+        assert_eq!(counts.corruptions_detected, 1);
+        assert_eq!(
+            counts.operations.get(&StoreOperation::Commit),
+            Some(&OperationCounts {
+                count: 2,
+                total_duration: Duration::from_millis(5),
+            })
+        );
+        assert_eq!(counts.operations.get(&StoreOperation::Read), None);
+    }
+
+    #[test]
+    fn timers_record_their_operation_when_dropped() {
+        let counters = Arc::new(StoreCounters::new());
+        {
+            let metrics: Arc<dyn StoreMetrics> = counters.clone();
+            let _timer = OperationTimer::start(&metrics, StoreOperation::Diff);
+            assert!(counters.counts().operations.is_empty());
+        }
+        let operations = counters.counts().operations;
+        assert_eq!(operations.get(&StoreOperation::Diff).map(|op| op.count), Some(1));
+    }
+}
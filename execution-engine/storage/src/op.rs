@@ -11,6 +11,18 @@ pub enum Op {
 
 use self::Op::*;
 
+impl Op {
+    /// Whether operations of two deploys on the same key can be applied in
+    /// either order with the same outcome: reads with reads and additions
+    /// with additions.
+    pub fn commutes_with(&self, other: &Op) -> bool {
+        match (self, other) {
+            (NoOp, _) | (_, NoOp) | (Read, Read) | (Add, Add) => true,
+            _ => false,
+        }
+    }
+}
+
 impl Add for Op {
     type Output = Op;
 
@@ -30,3 +42,24 @@ impl fmt::Display for Op {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Op;
+
+    #[test]
+    fn only_reads_and_additions_commute() {
+        let ops = [Op::Read, Op::Write, Op::Add, Op::NoOp];
+        for a in &ops {
+            assert!(a.commutes_with(&Op::NoOp));
+            for b in &ops {
+                assert_eq!(a.commutes_with(b), b.commutes_with(a));
+            }
+        }
+        assert!(Op::Read.commutes_with(&Op::Read));
+        assert!(Op::Add.commutes_with(&Op::Add));
+        assert!(!Op::Read.commutes_with(&Op::Add));
+        assert!(!Op::Write.commutes_with(&Op::Write));
+        assert!(!Op::Write.commutes_with(&Op::Read));
+    }
+}
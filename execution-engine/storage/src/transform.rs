@@ -197,6 +197,7 @@ impl Add for Transform {
             }
             (AddInt32(i), b) => match b {
                 AddInt32(j) => i.checked_add(j).map_or(Failure(Error::Overflow), AddInt32),
+                AddUInt128(j) => i32_checked_addition(j, i).map_or_else(Failure, AddUInt128),
                 AddUInt256(j) => i32_checked_addition(j, i).map_or_else(Failure, AddUInt256),
                 AddUInt512(j) => i32_checked_addition(j, i).map_or_else(Failure, AddUInt512),
                 other => Failure(
@@ -290,6 +291,30 @@ mod tests {
         assert_eq!(transform_underflow, Transform::Failure(Error::Overflow));
     }
 
+    #[test]
+    fn int32_additions_compose_with_every_uint() {
+        assert_eq!(
+            Transform::AddInt32(1) + Transform::AddUInt128(U128::from(2)),
+            Transform::AddUInt128(U128::from(3))
+        );
+        assert_eq!(
+            Transform::AddInt32(-1) + Transform::AddUInt256(U256::from(2)),
+            Transform::AddUInt256(U256::from(1))
+        );
+        assert_eq!(
+            Transform::AddUInt512(U512::from(2)) + Transform::AddInt32(1),
+            Transform::AddUInt512(U512::from(3))
+        );
+        match Transform::AddInt32(1) + Transform::Write(Value::Int32(2)) {
+            Transform::Write(Value::Int32(2)) => (),
+            other => panic!("Expected the write to win, got {:?}", other),
+        }
+        match Transform::Write(Value::String("a".to_string())) + Transform::AddInt32(1) {
+            Transform::Failure(Error::TypeMismatch(_)) => (),
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+    }
+
     #[test]
     fn u128_overflow() {
         uint_overflow_test::<U128>();
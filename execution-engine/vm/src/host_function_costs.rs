@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+
+/// Gas charged for a call to a host function: a base cost plus a cost per
+/// byte of the arguments the function reads from the Wasm memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostFunctionCost {
+    pub base: u32,
+    pub per_byte: u32,
+}
+
+impl HostFunctionCost {
+    pub fn new(base: u32, per_byte: u32) -> HostFunctionCost {
+        HostFunctionCost { base, per_byte }
+    }
+
+    /// Gas charged for `bytes` bytes of arguments.
+    pub fn bytes_cost(&self, bytes: usize) -> u64 {
+        u64::from(self.per_byte).saturating_mul(bytes as u64)
+    }
+}
+
+/// Costs of the host functions by the name they're imported under. Functions
+/// without a cost of their own are charged the default cost. The default table
+/// prices the hashing, signature and batch functions and leaves the rest free.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostFunctionCosts {
+    default_cost: HostFunctionCost,
+    costs: BTreeMap<String, HostFunctionCost>,
+}
+
+impl Default for HostFunctionCosts {
+    fn default() -> HostFunctionCosts {
+        HostFunctionCosts::new(HostFunctionCost::default())
+            .with_cost("blake2b", HostFunctionCost::new(100, 2))
+            // The message gets hashed, unlike the digests passed to secp256k1.
+            .with_cost("verify_ed25519", HostFunctionCost::new(500, 1))
+            .with_cost("verify_secp256k1", HostFunctionCost::new(600, 0))
+            .with_cost("recover_secp256k1", HostFunctionCost::new(700, 0))
+            .with_cost("read_multi", HostFunctionCost::new(0, 1))
+            .with_cost("write_multi", HostFunctionCost::new(0, 1))
+    }
+}
+
+impl HostFunctionCosts {
+    /// A table charging every host function `default_cost`.
+    pub fn new(default_cost: HostFunctionCost) -> HostFunctionCosts {
+        HostFunctionCosts {
+            default_cost,
+            costs: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the cost of the host function imported as `name`.
+    pub fn with_cost(mut self, name: &str, cost: HostFunctionCost) -> HostFunctionCosts {
+        self.costs.insert(name.to_string(), cost);
+        self
+    }
+
+    pub fn default_cost(&self) -> HostFunctionCost {
+        self.default_cost
+    }
+
+    /// Costs set for single host functions.
+    pub fn costs(&self) -> &BTreeMap<String, HostFunctionCost> {
+        &self.costs
+    }
+
+    pub fn cost(&self, name: &str) -> HostFunctionCost {
+        self.costs.get(name).cloned().unwrap_or(self.default_cost)
+    }
+}
@@ -1 +1,3 @@
+pub mod host_function_costs;
+pub mod storage_costs;
 pub mod wasm_costs;
@@ -0,0 +1,22 @@
+/// Gas charged for the bytes deploys add to the global state, on top of the
+/// gas charged for the code writing them. Storage is free unless set
+/// otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageCosts {
+    pub gas_per_byte: u32,
+}
+
+impl StorageCosts {
+    pub fn new(gas_per_byte: u32) -> StorageCosts {
+        StorageCosts { gas_per_byte }
+    }
+
+    /// Gas charged for adding `bytes` bytes. Nothing is given back for the
+    /// bytes freed.
+    pub fn bytes_cost(&self, bytes: i64) -> u64 {
+        if bytes <= 0 {
+            return 0;
+        }
+        u64::from(self.gas_per_byte).saturating_mul(bytes as u64)
+    }
+}
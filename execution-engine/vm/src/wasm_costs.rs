@@ -1,5 +1,5 @@
 // Taken (partially) from parity-ethereum
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WasmCosts {
     /// Default opcode cost
     pub regular: u32,
@@ -17,6 +17,12 @@ pub struct WasmCosts {
     pub memcpy: u32,
     /// Max stack height (native WebAssembly stack limiter)
     pub max_stack_height: u32,
+    /// Max number of memory pages (64kb) a module can start with.
+    pub max_initial_pages: u32,
+    /// Max number of memory pages (64kb) a module can grow its memory to. Modules which
+    /// don't declare a maximum get 128 pages, or this limit if it's lower. The runtime
+    /// provides at most 256.
+    pub max_grow_pages: u32,
     /// Cost of wasm opcode is calculated as TABLE_ENTRY_COST * `opcodes_mul` / `opcodes_div`
     pub opcodes_mul: u32,
     /// Cost of wasm opcode is calculated as TABLE_ENTRY_COST * `opcodes_mul` / `opcodes_div`
@@ -40,6 +46,8 @@ impl Default for WasmCosts {
             grow_mem: 8192,
             memcpy: 1,
             max_stack_height: 64 * 1024,
+            max_initial_pages: 128,
+            max_grow_pages: 256,
             opcodes_mul: 3,
             opcodes_div: 8,
         }
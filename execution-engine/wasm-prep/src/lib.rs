@@ -1,9 +1,15 @@
 extern crate parity_wasm;
 extern crate pwasm_utils;
 extern crate vm;
+#[cfg(test)]
+extern crate wabt;
 
-use parity_wasm::elements::{self, deserialize_buffer, Error as ParityWasmError, Module};
+use parity_wasm::elements::{
+    self, deserialize_buffer, BlockType, Error as ParityWasmError, ImportEntry, Instruction,
+    Module, Type, ValueType,
+};
 use pwasm_utils::{externalize_mem, inject_gas_counter, rules};
+use std::cmp;
 use std::error::Error;
 use std::iter::Iterator;
 use vm::wasm_costs::WasmCosts;
@@ -21,14 +27,82 @@ const ALLOWED_IMPORTS: &[&str] = &[
     "get_arg",
     "ret",
     "call_contract",
+    "call_versioned_contract",
     "get_call_result",
     "get_uref",
     "has_uref_name",
     "add_uref",
+    "get_caller",
+    "rotate_main_purse",
+    "add_associated_key",
+    "remove_associated_key",
+    "set_action_threshold",
+    "create_contract_package",
+    "add_contract_version",
+    "create_contract_user_group",
+    "remove_contract_user_group",
+    "store_function_with_abi",
+    "store_function_at_hash",
+    "lock_contract_package",
+    "put_key",
+    "remove_key",
+    "get_key",
+    "store_library",
+    "disable_contract_version",
+    "get_phase",
+    "revert",
+    "transfer_to_account",
+    "transfer_from_purse_to_purse",
+    "create_purse",
+    "get_main_purse",
+    "blake2b",
+    "verify_ed25519",
+    "verify_secp256k1",
+    "recover_secp256k1",
+    "random_bytes",
+    "emit_event",
+    "print",
+    "load_named_arg",
+    "list_named_keys",
+    "remove_uref",
+    "is_valid",
+    "read_local",
+    "write_local",
+    "get_system_contract",
+    "call_contract_with_gas",
+    "upgrade_contract_at_uref",
+    "gas_remaining",
+    "read_multi",
+    "write_multi",
+    "read_host_buffer",
+    "new_uref_with_rights",
+    "get_deploy_hash",
+    "get_protocol_version",
+    "dictionary_get",
+    "dictionary_put",
+    "call_stack_depth",
+    "validate_key",
+    "get_block_height",
+    "get_payment_purse",
+    "bond",
+    "unbond",
+    "delegate",
+    "undelegate",
+    "add_bid",
+    "withdraw_bid",
 ];
 
+// Imported by the gas counter which is injected during preprocessing, so it
+// appears in contracts stored by already preprocessed modules.
+const GAS_IMPORT: &str = "gas";
+
+/// Modules link against a stored library by importing its functions from a
+/// module named after the library's hash, e.g. `lib:0a1b...` (64 hex digits).
+pub const LIBRARY_MODULE_PREFIX: &str = "lib:";
+
 const MEM_PAGES: u32 = 128;
 pub const MAX_MEM_PAGES: u32 = 305; // 10mb
+pub const MAX_MODULE_SIZE: usize = 1024 * 1024; // 1mb
 
 #[derive(Debug)]
 pub enum PreprocessingError {
@@ -38,6 +112,11 @@ pub enum PreprocessingError {
     DeserializeError(String),
     OperationForbiddenByGasRules,
     StackLimiterError,
+    ModuleTooLarge(usize),
+    StartSectionPresent,
+    FloatingPoint,
+    InitialMemoryTooLarge { pages: u32, limit: u32 },
+    MaximumMemoryTooLarge { pages: u32, limit: u32 },
 }
 
 use PreprocessingError::*;
@@ -59,12 +138,16 @@ impl Preprocessor<Module> for WasmiPreprocessor {
         module_bytes: &[u8],
         wasm_costs: &WasmCosts,
     ) -> Result<Module, PreprocessingError> {
-        // type annotation in closure needed
-        let from_parity_err = |err: ParityWasmError| DeserializeError(err.description().to_owned());
-        let deserialized_module = deserialize_buffer(module_bytes).map_err(from_parity_err)?;
-        let mut ext_mod = externalize_mem(deserialized_module, None, MEM_PAGES);
+        let deserialized_module = deserialize_module(module_bytes)?;
+        validate_start_section(&deserialized_module)?;
+        validate_no_floats(&deserialized_module)?;
+        // Modules which don't declare a maximum get the default one, unless the
+        // configured limit is lower.
+        let default_max_pages = cmp::min(MEM_PAGES, wasm_costs.max_grow_pages);
+        let mut ext_mod = externalize_mem(deserialized_module, None, default_max_pages);
         remove_memory_export(&mut ext_mod)?;
         validate_imports(&ext_mod)?;
+        validate_memory_limits(&ext_mod, wasm_costs)?;
         let gas_mod = inject_gas_counters(ext_mod, wasm_costs)?;
         let module =
             pwasm_utils::stack_height::inject_limiter(gas_mod, wasm_costs.max_stack_height)
@@ -73,6 +156,154 @@ impl Preprocessor<Module> for WasmiPreprocessor {
     }
 }
 
+/// Validates a module which is about to be stored as a contract. Such a module
+/// comes from an already preprocessed one, so it may import the gas counter
+/// and it doesn't have to import memory if it doesn't use it.
+pub fn validate_contract(module_bytes: &[u8]) -> Result<(), PreprocessingError> {
+    let module = deserialize_module(module_bytes)?;
+    validate_start_section(&module)?;
+    validate_no_floats(&module)?;
+    match module.import_section() {
+        Some(imports) => imports
+            .entries()
+            .iter()
+            .try_for_each(|entry| validate_import(entry, true).map(|_| ())),
+        None => Ok(()),
+    }
+}
+
+/// Validates a module which is about to be stored as a library. A library is
+/// instantiated with memory of its own, so it can't call host functions, as
+/// those would access the memory of the calling module instead. The only
+/// imports allowed are memory and the gas counter.
+pub fn validate_library(module_bytes: &[u8]) -> Result<(), PreprocessingError> {
+    let module = deserialize_module(module_bytes)?;
+    validate_start_section(&module)?;
+    validate_no_floats(&module)?;
+    match module.import_section() {
+        Some(imports) => imports.entries().iter().try_for_each(|entry| {
+            let is_function = match *entry.external() {
+                elements::External::Function(_) => true,
+                _ => false,
+            };
+            if is_function && (entry.module() != "env" || entry.field() != GAS_IMPORT) {
+                invalid_imports_error(format!(
+                    "Libraries can't import '{}' from '{}'",
+                    entry.field(),
+                    entry.module()
+                ))
+            } else {
+                validate_import(entry, true).map(|_| ())
+            }
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the hash of the library which is linked against by importing from
+/// `module_name`, or `None` if `module_name` doesn't name a library.
+pub fn library_hash(module_name: &str) -> Option<[u8; 32]> {
+    if !module_name.starts_with(LIBRARY_MODULE_PREFIX) {
+        return None;
+    }
+    let hex = &module_name[LIBRARY_MODULE_PREFIX.len()..];
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut hash = [0u8; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+fn deserialize_module(module_bytes: &[u8]) -> Result<Module, PreprocessingError> {
+    if module_bytes.len() > MAX_MODULE_SIZE {
+        return Err(ModuleTooLarge(module_bytes.len()));
+    }
+    // type annotation in closure needed
+    let from_parity_err = |err: ParityWasmError| DeserializeError(err.description().to_owned());
+    deserialize_buffer(module_bytes).map_err(from_parity_err)
+}
+
+// Code run by the start function would be executed as soon as the module is
+// instantiated, before the host gets to call its entry point.
+fn validate_start_section(module: &Module) -> Result<(), PreprocessingError> {
+    if module.start_section().is_some() {
+        Err(StartSectionPresent)
+    } else {
+        Ok(())
+    }
+}
+
+// Results of floating-point operations, NaNs in particular, may differ between
+// platforms, so validators could disagree on the outcome of a deploy.
+fn validate_no_floats(module: &Module) -> Result<(), PreprocessingError> {
+    let in_types = module.type_section().map_or(false, |section| {
+        section.types().iter().any(|ty| match ty {
+            Type::Function(function_type) => {
+                function_type.params().iter().cloned().any(is_float)
+                    || function_type.return_type().map_or(false, is_float)
+            }
+        })
+    });
+    let in_imports = module.import_section().map_or(false, |section| {
+        section.entries().iter().any(|entry| match *entry.external() {
+            elements::External::Global(global_type) => is_float(global_type.content_type()),
+            _ => false,
+        })
+    });
+    let in_globals = module.global_section().map_or(false, |section| {
+        section.entries().iter().any(|entry| {
+            is_float(entry.global_type().content_type())
+                || entry.init_expr().code().iter().any(is_float_instruction)
+        })
+    });
+    let in_code = module.code_section().map_or(false, |section| {
+        section.bodies().iter().any(|body| {
+            body.locals().iter().any(|local| is_float(local.value_type()))
+                || body.code().elements().iter().any(is_float_instruction)
+        })
+    });
+    if in_types || in_imports || in_globals || in_code {
+        Err(FloatingPoint)
+    } else {
+        Ok(())
+    }
+}
+
+fn is_float(value_type: ValueType) -> bool {
+    match value_type {
+        ValueType::F32 | ValueType::F64 => true,
+        _ => false,
+    }
+}
+
+fn is_float_instruction(instruction: &Instruction) -> bool {
+    use parity_wasm::elements::Instruction::*;
+    match *instruction {
+        Block(BlockType::Value(value_type))
+        | Loop(BlockType::Value(value_type))
+        | If(BlockType::Value(value_type)) => is_float(value_type),
+        F32Load(..) | F64Load(..) | F32Store(..) | F64Store(..) | F32Const(_) | F64Const(_) => {
+            true
+        }
+        F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge | F64Eq | F64Ne | F64Lt | F64Gt | F64Le
+        | F64Ge => true,
+        F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt | F32Add
+        | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign => true,
+        F64Abs | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt | F64Add
+        | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => true,
+        I32TruncSF32 | I32TruncUF32 | I32TruncSF64 | I32TruncUF64 | I64TruncSF32
+        | I64TruncUF32 | I64TruncSF64 | I64TruncUF64 => true,
+        F32ConvertSI32 | F32ConvertUI32 | F32ConvertSI64 | F32ConvertUI64 | F32DemoteF64
+        | F64ConvertSI32 | F64ConvertUI32 | F64ConvertSI64 | F64ConvertUI64
+        | F64PromoteF32 => true,
+        I32ReinterpretF32 | I64ReinterpretF64 | F32ReinterpretI32 | F64ReinterpretI64 => true,
+        _ => false,
+    }
+}
+
 fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
     rules::Set::new(wasm_costs.regular, {
         let mut vals = ::std::collections::BTreeMap::new();
@@ -98,6 +329,11 @@ fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
     .with_forbidden_floats()
 }
 
+// Charges every metered block of code, i.e. a run of instructions which is
+// either executed as a whole or not at all, for the costs of its instructions
+// by calling the gas host function with their sum before it. Metering doesn't
+// depend on the interpreter running the module, and it traps as soon as the
+// gas limit is exceeded.
 fn inject_gas_counters(
     module: Module,
     wasm_costs: &WasmCosts,
@@ -119,41 +355,9 @@ fn validate_imports(module: &Module) -> Result<(), PreprocessingError> {
         .ok_or(NoImportSection)?
         .entries()
         .iter()
-        .try_fold(false, |has_imported_memory_properly_named, ref entry| {
-            if entry.module() != "env" {
-                return invalid_imports_error("All imports should be from env");
-            }
-            match *entry.external() {
-                elements::External::Function(_) => {
-                    if !ALLOWED_IMPORTS.contains(&entry.field()) {
-                        invalid_imports_error::<bool, String>(format!(
-                            "'{}' is not supported by the runtime",
-                            entry.field()
-                        ))
-                    } else {
-                        Ok(has_imported_memory_properly_named)
-                    }
-                }
-                elements::External::Memory(m) => {
-                    let max = m.limits().maximum().ok_or_else(|| {
-                        invalid_imports(
-                            "There is a limit to Wasm memory. This program does not limit memory",
-                        )
-                    })?;
-                    if max > MAX_MEM_PAGES {
-                        return invalid_imports_error::<bool, String>(format!(
-                            "Wasm runtime has 10Mb limit (305 pages each 64KiB) on \
-                             max contract memory. This program specific {}",
-                            max
-                        ));
-                    }
-                    Ok(entry.field() == "memory") // memory properly imported
-                }
-                elements::External::Global(_) => {
-                    invalid_imports_error::<bool, &str>("No globals are provided with the runtime.")
-                }
-                elements::External::Table(_) => Ok(has_imported_memory_properly_named),
-            }
+        .try_fold(false, |has_imported_memory_properly_named, entry| {
+            validate_import(entry, false)
+                .map(|is_memory| has_imported_memory_properly_named || is_memory)
         })
         .and_then(|had_imported_memory_properly_named| {
             if had_imported_memory_properly_named {
@@ -164,6 +368,95 @@ fn validate_imports(module: &Module) -> Result<(), PreprocessingError> {
         })
 }
 
+// Holds the imported memory to the limits of the engine config, which may be
+// stricter than what the runtime is able to provide.
+fn validate_memory_limits(
+    module: &Module,
+    wasm_costs: &WasmCosts,
+) -> Result<(), PreprocessingError> {
+    let memory_types = module
+        .import_section()
+        .into_iter()
+        .flat_map(|section| section.entries())
+        .filter_map(|entry| match *entry.external() {
+            elements::External::Memory(memory_type) => Some(memory_type),
+            _ => None,
+        });
+    for memory_type in memory_types {
+        let limits = memory_type.limits();
+        if limits.initial() > wasm_costs.max_initial_pages {
+            return Err(InitialMemoryTooLarge {
+                pages: limits.initial(),
+                limit: wasm_costs.max_initial_pages,
+            });
+        }
+        match limits.maximum() {
+            Some(maximum) if maximum > wasm_costs.max_grow_pages => {
+                return Err(MaximumMemoryTooLarge {
+                    pages: maximum,
+                    limit: wasm_costs.max_grow_pages,
+                })
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Checks a single import. Returns whether it's the memory imported properly,
+/// i.e. as `env::memory`. The gas counter import is only accepted when
+/// `allow_gas` is set.
+fn validate_import(entry: &ImportEntry, allow_gas: bool) -> Result<bool, PreprocessingError> {
+    if library_hash(entry.module()).is_some() {
+        return match *entry.external() {
+            elements::External::Function(_) => Ok(false),
+            _ => invalid_imports_error("Only functions can be imported from libraries"),
+        };
+    }
+    if entry.module() != "env" {
+        return invalid_imports_error("All imports should be from env or from stored libraries");
+    }
+    match *entry.external() {
+        elements::External::Function(_) => {
+            let field = entry.field();
+            if ALLOWED_IMPORTS.contains(&field) || (allow_gas && field == GAS_IMPORT) {
+                Ok(false)
+            } else {
+                invalid_imports_error::<bool, String>(format!(
+                    "'{}' is not supported by the runtime",
+                    field
+                ))
+            }
+        }
+        elements::External::Memory(m) => {
+            let max = m.limits().maximum().ok_or_else(|| {
+                invalid_imports(
+                    "There is a limit to Wasm memory. This program does not limit memory",
+                )
+            })?;
+            if max > MAX_MEM_PAGES {
+                return invalid_imports_error::<bool, String>(format!(
+                    "Wasm runtime has 10Mb limit (305 pages each 64KiB) on \
+                     max contract memory. This program specific {}",
+                    max
+                ));
+            }
+            if m.limits().initial() > max {
+                return invalid_imports_error::<bool, String>(format!(
+                    "Initial memory size ({} pages) exceeds the maximum of {} pages",
+                    m.limits().initial(),
+                    max
+                ));
+            }
+            Ok(entry.field() == "memory") // memory properly imported
+        }
+        elements::External::Global(_) => {
+            invalid_imports_error::<bool, &str>("No globals are provided with the runtime.")
+        }
+        elements::External::Table(_) => Ok(false),
+    }
+}
+
 fn remove_memory_export(module: &mut Module) -> Result<(), PreprocessingError> {
     let exports = module.export_section_mut().ok_or(NoExportSection)?;
     let entries = exports.entries_mut();
@@ -176,3 +469,87 @@ fn remove_memory_export(module: &mut Module) -> Result<(), PreprocessingError> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use parity_wasm::elements::{External, Instruction, Module};
+    use vm::wasm_costs::WasmCosts;
+
+    use super::{Preprocessor, WasmiPreprocessor, GAS_IMPORT};
+
+    /// Amounts of gas charged by the injected calls to the gas counter, in
+    /// the order they appear in the code of `body` once preprocessed.
+    fn gas_charges(body: &str, wasm_costs: &WasmCosts) -> Vec<u32> {
+        let wat = format!(r#"(module (memory 1) (func (export "call") {}))"#, body);
+        let wasm_binary = wabt::wat2wasm(wat).expect("failed to parse wat");
+        let module: Module = WasmiPreprocessor
+            .preprocess(&wasm_binary, wasm_costs)
+            .expect("Module should be preprocessed.");
+        let gas_index = module
+            .import_section()
+            .expect("Module should have imports.")
+            .entries()
+            .iter()
+            .filter(|entry| match *entry.external() {
+                External::Function(_) => true,
+                _ => false,
+            })
+            .position(|entry| entry.module() == "env" && entry.field() == GAS_IMPORT)
+            .expect("Gas counter should be imported.") as u32;
+        let mut charges = Vec::new();
+        for body in module.code_section().expect("Module should have code.").bodies() {
+            for pair in body.code().elements().windows(2) {
+                if let (Instruction::I32Const(amount), Instruction::Call(index)) =
+                    (&pair[0], &pair[1])
+                {
+                    if *index == gas_index {
+                        charges.push(*amount as u32);
+                    }
+                }
+            }
+        }
+        charges
+    }
+
+    #[test]
+    fn straight_code_is_charged_once() {
+        let body = "i32.const 1 i32.const 2 i32.add drop";
+        assert_eq!(gas_charges(body, &WasmCosts::default()), vec![4]);
+        let wasm_costs = WasmCosts {
+            regular: 3,
+            ..WasmCosts::default()
+        };
+        assert_eq!(gas_charges(body, &wasm_costs), vec![12]);
+    }
+
+    #[test]
+    fn instructions_are_priced_by_the_wasm_costs() {
+        let wasm_costs = WasmCosts::default();
+        // A load costs `mem`, the constant and the drop are regular.
+        let load = "i32.const 0 i32.load drop";
+        assert_eq!(gas_charges(load, &wasm_costs), vec![2 + wasm_costs.mem]);
+        let store = "i32.const 0 i32.const 1 i32.store";
+        assert_eq!(gas_charges(store, &wasm_costs), vec![2 + wasm_costs.mem]);
+        let mul = "i32.const 2 i32.const 3 i32.mul drop";
+        assert_eq!(gas_charges(mul, &wasm_costs), vec![3 + wasm_costs.mul]);
+        let div = "i32.const 6 i32.const 3 i32.div_u drop";
+        assert_eq!(gas_charges(div, &wasm_costs), vec![3 + wasm_costs.div]);
+    }
+
+    #[test]
+    fn branches_are_charged_separately() {
+        let wasm_costs = WasmCosts::default();
+        let if_else = r#"
+            i32.const 1
+            if
+                i32.const 2 drop
+            else
+                i32.const 3 i32.const 4 i32.add drop
+            end"#;
+        // The condition and the `if`, then each of the branches.
+        assert_eq!(gas_charges(if_else, &wasm_costs), vec![2, 2, 4]);
+        // The `loop`, then its body, which is charged on every iteration.
+        let loop_ = "loop i32.const 0 br_if 0 end";
+        assert_eq!(gas_charges(loop_, &wasm_costs), vec![1, 2]);
+    }
+}